@@ -0,0 +1,96 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Describes a presentation without playing it.
+
+use anyhow::Error;
+use retina::client::{Described, Session};
+use retina::codec::Parameters;
+use std::io::Write;
+
+#[derive(structopt::StructOpt)]
+pub struct Opts {
+    #[structopt(flatten)]
+    src: super::Source,
+}
+
+pub async fn run(opts: Opts) -> Result<(), Error> {
+    let creds = super::creds(opts.src.username, opts.src.password);
+    let session = Session::describe(
+        opts.src.url,
+        retina::client::SessionOptions::default()
+            .creds(creds)
+            .user_agent("Retina info example".to_owned()),
+    )
+    .await?;
+    write_info(&mut std::io::stdout().lock(), &session)?;
+    Ok(())
+}
+
+/// Writes a summary of each of `session`'s streams to `out`.
+fn write_info(out: &mut dyn Write, session: &Session<Described>) -> Result<(), Error> {
+    writeln!(out, "{} streams", session.streams().len())?;
+    for (i, s) in session.streams().iter().enumerate() {
+        writeln!(
+            out,
+            "stream {}: {}/{} (payload type {}), clock rate {} Hz",
+            i, s.media, s.encoding_name, s.rtp_payload_type, s.clock_rate
+        )?;
+        match s.parameters() {
+            Some(Parameters::Video(v)) => {
+                let (width, height) = v.pixel_dimensions();
+                write!(out, "    video: {} {}x{}", v.rfc6381_codec(), width, height)?;
+                if let Some((num, denom)) = v.frame_rate() {
+                    write!(out, ", {}/{} fps", denom, num)?;
+                }
+                writeln!(out)?;
+            }
+            Some(Parameters::Audio(a)) => {
+                write!(
+                    out,
+                    "    audio: {}",
+                    a.rfc6381_codec().unwrap_or("(no rfc6381 codec)")
+                )?;
+                if let Some(rate) = a.sample_rate() {
+                    write!(out, ", {} Hz", rate)?;
+                }
+                if let Some(channels) = a.channels() {
+                    write!(out, ", {} channels", channels)?;
+                }
+                writeln!(out)?;
+            }
+            Some(Parameters::Message(_)) => writeln!(out, "    message stream")?,
+            None => writeln!(out, "    (parameters unknown)")?,
+        }
+        for (name, value) in s.sdp_attributes() {
+            match value {
+                Some(v) => writeln!(out, "    a={}:{}", name, v)?,
+                None => writeln!(out, "    a={}", name)?,
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock;
+
+    #[tokio::test]
+    async fn info() {
+        let (url, server) = mock::start(mock::reolink_sdp().to_owned(), Vec::new()).await;
+        let session =
+            retina::client::Session::describe(url, retina::client::SessionOptions::default())
+                .await
+                .unwrap();
+        let mut out = Vec::new();
+        super::write_info(&mut out, &session).unwrap();
+        drop(session);
+        server.await.unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("2 streams\n"), "{}", out);
+        assert!(out.contains("stream 0: video/h264"), "{}", out);
+        assert!(out.contains("stream 1: audio/"), "{}", out);
+        assert!(out.contains("    a=control:"), "{}", out);
+    }
+}
@@ -3,11 +3,16 @@
 
 //! RTSP client examples.
 
+mod info;
 mod metadata;
+#[cfg(test)]
+mod mock;
 mod mp4;
 
 use anyhow::Error;
 use log::{error, info};
+use retina::client::{LossPolicy, SessionOptions, Transport, TransportPolicy};
+use std::num::NonZeroU64;
 use std::str::FromStr;
 use structopt::StructOpt;
 
@@ -24,10 +29,139 @@ struct Source {
     /// Password; requires username.
     #[structopt(long, requires = "username")]
     password: Option<String>,
+
+    /// The transport to use: `tcp`, `udp` (experimental), `udp-then-tcp`
+    /// (experimental), or `udp-multicast` (experimental).
+    ///
+    /// With `udp-then-tcp`, falls back to TCP if no packets arrive over UDP.
+    #[structopt(default_value, long)]
+    transport: TransportArg,
+
+    /// What to do on RTP packet loss: `tolerate`, `abort`, or
+    /// `request-keyframe`.
+    ///
+    /// `tolerate` logs a warning and continues; video may be corrupt until the
+    /// next key frame. `abort` fails immediately, which suits bug repros that
+    /// must be complete but ends the session on any hiccup. `request-keyframe`
+    /// continues and asks the server for a key frame, which recovers video
+    /// faster but works only if the server advertises PLI or FIR support.
+    #[structopt(default_value, long)]
+    loss_policy: LossPolicy,
+
+    /// Shorthand for `--loss-policy=abort`.
+    #[structopt(long)]
+    strict: bool,
+}
+
+impl Source {
+    /// Returns the effective `--loss-policy`, considering `--strict`.
+    fn loss_policy(&self) -> LossPolicy {
+        if self.strict {
+            LossPolicy::Abort
+        } else {
+            self.loss_policy
+        }
+    }
+}
+
+/// Limits after which to stop recording automatically.
+#[derive(StructOpt)]
+struct Limits {
+    /// Duration after which to exit automatically, in seconds.
+    #[structopt(long, name = "secs")]
+    duration: Option<u64>,
+
+    /// Number of frames after which to exit automatically.
+    #[structopt(long)]
+    frames: Option<NonZeroU64>,
+}
+
+impl Limits {
+    /// Returns a future which completes when the `--duration` limit is reached.
+    fn sleep(&self) -> futures::future::Either<tokio::time::Sleep, futures::future::Pending<()>> {
+        match self.duration {
+            Some(secs) => futures::future::Either::Left(tokio::time::sleep(
+                std::time::Duration::from_secs(secs),
+            )),
+            None => futures::future::Either::Right(futures::future::pending()),
+        }
+    }
+
+    /// Returns true if `frames` frames reaches the `--frames` limit.
+    fn frame_limit_reached(&self, frames: u64) -> bool {
+        matches!(self.frames, Some(f) if frames >= f.get())
+    }
+}
+
+/// The value of [Source]'s `--transport` flag.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TransportArg {
+    Tcp,
+    Udp,
+    UdpThenTcp,
+    UdpMulticast,
+}
+
+impl TransportArg {
+    /// Returns the policy to pass to [SessionOptions::transport_policy], or
+    /// `None` for multicast, which isn't expressible as a policy.
+    fn policy(self) -> Option<TransportPolicy> {
+        match self {
+            TransportArg::Tcp => Some(TransportPolicy::TcpOnly),
+            TransportArg::Udp => Some(TransportPolicy::UdpOnly),
+            TransportArg::UdpThenTcp => Some(TransportPolicy::UdpThenTcp),
+            TransportArg::UdpMulticast => None,
+        }
+    }
+
+    /// Applies this transport choice to `options`.
+    fn apply(self, options: SessionOptions) -> SessionOptions {
+        match self.policy() {
+            Some(p) => options.transport_policy(p),
+            None => options.transport(Transport::UdpMulticast),
+        }
+    }
+}
+
+impl Default for TransportArg {
+    fn default() -> Self {
+        TransportArg::Tcp
+    }
+}
+
+impl std::fmt::Display for TransportArg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            TransportArg::Tcp => "tcp",
+            TransportArg::Udp => "udp",
+            TransportArg::UdpThenTcp => "udp-then-tcp",
+            TransportArg::UdpMulticast => "udp-multicast",
+        })
+    }
+}
+
+impl FromStr for TransportArg {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "tcp" => TransportArg::Tcp,
+            "udp" => TransportArg::Udp,
+            "udp-then-tcp" => TransportArg::UdpThenTcp,
+            "udp-multicast" => TransportArg::UdpMulticast,
+            _ => anyhow::bail!(
+                "bad transport {}; expected tcp, udp, udp-then-tcp, or udp-multicast",
+                s
+            ),
+        })
+    }
 }
 
 #[derive(StructOpt)]
 enum Cmd {
+    /// Describes the presentation's streams without playing them.
+    #[structopt(alias = "describe")]
+    Info(info::Opts),
     Mp4(mp4::Opts),
     Metadata(metadata::Opts),
 }
@@ -51,7 +185,7 @@ async fn main() {
     let mut h = init_logging();
     if let Err(e) = {
         let _a = h.async_scope();
-        main_inner().await
+        main_inner(Cmd::from_args()).await
     } {
         error!("Fatal: {}", itertools::join(e.chain(), "\ncaused by: "));
         std::process::exit(1);
@@ -74,10 +208,90 @@ fn creds(
     }
 }
 
-async fn main_inner() -> Result<(), Error> {
-    let cmd = Cmd::from_args();
+async fn main_inner(cmd: Cmd) -> Result<(), Error> {
     match cmd {
+        Cmd::Info(opts) => info::run(opts).await,
         Cmd::Mp4(opts) => mp4::run(opts).await,
         Cmd::Metadata(opts) => metadata::run(opts).await,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Cmd, Source, TransportArg};
+    use crate::mock;
+    use retina::client::{LossPolicy, TransportPolicy};
+    use structopt::StructOpt;
+
+    fn parse(args: &[&str]) -> Result<Source, structopt::clap::Error> {
+        let mut full = vec!["client", "--url", "rtsp://192.168.5.206/"];
+        full.extend_from_slice(args);
+        Source::from_iter_safe(full)
+    }
+
+    #[test]
+    fn transport_flag() {
+        assert_eq!(parse(&[]).unwrap().transport, TransportArg::Tcp);
+        for (arg, policy) in [
+            ("tcp", Some(TransportPolicy::TcpOnly)),
+            ("udp", Some(TransportPolicy::UdpOnly)),
+            ("udp-then-tcp", Some(TransportPolicy::UdpThenTcp)),
+            ("udp-multicast", None),
+        ] {
+            let src = parse(&["--transport", arg]).unwrap();
+            assert_eq!(src.transport.policy(), policy, "{}", arg);
+            assert_eq!(src.transport.to_string(), arg);
+        }
+        assert!(parse(&["--transport", "carrier-pigeon"]).is_err());
+    }
+
+    #[test]
+    fn loss_policy_flags() {
+        assert_eq!(parse(&[]).unwrap().loss_policy(), LossPolicy::Tolerate);
+        assert_eq!(
+            parse(&["--strict"]).unwrap().loss_policy(),
+            LossPolicy::Abort
+        );
+        assert_eq!(
+            parse(&["--loss-policy", "request-keyframe"])
+                .unwrap()
+                .loss_policy(),
+            LossPolicy::RequestKeyframe
+        );
+    }
+
+    /// Runs `metadata` against a mock server which drops packets 2 through 4.
+    async fn run_lossy_metadata(args: &[&str]) -> Result<(), anyhow::Error> {
+        let sdp = "v=0\r\n\
+                   o=- 0 0 IN IP4 127.0.0.1\r\n\
+                   s=Metadata\r\n\
+                   t=0 0\r\n\
+                   m=application 0 RTP/AVP 107\r\n\
+                   a=control:trackID=1\r\n\
+                   a=rtpmap:107 vnd.onvif.metadata/90000\r\n";
+        let packets = [1u16, 5]
+            .iter()
+            .map(|&seq| {
+                let mut p = vec![0x80, 0xeb]; // version 2; marker; payload type 107.
+                p.extend_from_slice(&seq.to_be_bytes());
+                p.extend_from_slice(&u32::from(seq).to_be_bytes()); // timestamp
+                p.extend_from_slice(b"\x00\x00\x00\x01"); // ssrc
+                p.extend_from_slice(b"<a/>");
+                p
+            })
+            .collect();
+        let (url, server) = mock::start(sdp.to_owned(), packets).await;
+        let mut full = vec!["client", "metadata", "--url", url.as_str()];
+        full.extend_from_slice(args);
+        let r = super::main_inner(Cmd::from_iter_safe(full).unwrap()).await;
+        server.await.unwrap();
+        r
+    }
+
+    #[tokio::test]
+    async fn strict_aborts_on_loss() {
+        let e = run_lossy_metadata(&["--strict"]).await.unwrap_err();
+        assert!(e.to_string().contains("Lost 3 RTP packets"), "{}", e);
+        run_lossy_metadata(&["--frames", "2"]).await.unwrap();
+    }
+}
@@ -4,7 +4,7 @@
 use anyhow::{anyhow, Error};
 use futures::StreamExt;
 use log::{error, info};
-use retina::client::SessionGroup;
+use retina::client::{rtp::SenderReport, SessionGroup};
 use retina::codec::CodecItem;
 use std::sync::Arc;
 
@@ -12,6 +12,9 @@ use std::sync::Arc;
 pub struct Opts {
     #[structopt(flatten)]
     src: super::Source,
+
+    #[structopt(flatten)]
+    limits: super::Limits,
 }
 
 pub async fn run(opts: Opts) -> Result<(), Error> {
@@ -26,13 +29,17 @@ pub async fn run(opts: Opts) -> Result<(), Error> {
 async fn run_inner(opts: Opts, session_group: Arc<SessionGroup>) -> Result<(), Error> {
     let stop = tokio::signal::ctrl_c();
 
+    let loss_policy = opts.src.loss_policy();
     let creds = super::creds(opts.src.username, opts.src.password);
     let mut session = retina::client::Session::describe(
         opts.src.url,
-        retina::client::SessionOptions::default()
-            .creds(creds)
-            .user_agent("Retina metadata example".to_owned())
-            .session_group(session_group),
+        opts.src.transport.apply(
+            retina::client::SessionOptions::default()
+                .creds(creds)
+                .user_agent("Retina metadata example".to_owned())
+                .session_group(session_group)
+                .loss_policy(loss_policy),
+        ),
     )
     .await?;
     let onvif_stream_i = session
@@ -43,16 +50,39 @@ async fn run_inner(opts: Opts, session_group: Arc<SessionGroup>) -> Result<(), E
     session.setup(onvif_stream_i).await?;
     let mut session = session
         .play(retina::client::PlayOptions::default().ignore_zero_seq(true))
-        .await?
-        .demuxed()?;
+        .await?;
+    info!("Using transport {}", session.active_transport());
+    let mut session = session.demuxed()?;
 
+    let sleep = opts.limits.sleep();
     tokio::pin!(stop);
+    tokio::pin!(sleep);
+    let mut latest_sr = None;
+    let mut frames = 0;
     loop {
         tokio::select! {
             item = session.next() => {
                 match item.ok_or_else(|| anyhow!("EOF"))?? {
+                    CodecItem::SenderReport(sr) if sr.stream_id == onvif_stream_i => {
+                        latest_sr = Some(sr);
+                    },
                     CodecItem::MessageFrame(m) => {
-                        info!("{}: {}\n", &m.timestamp, std::str::from_utf8(&m.data[..]).unwrap());
+                        let wallclock = match &latest_sr {
+                            Some(sr) => wallclock(sr, m.timestamp).to_string(),
+                            None => "unknown".to_owned(),
+                        };
+                        info!(
+                            "{} (npt {:.3}, wallclock {}): {}\n",
+                            &m.timestamp,
+                            m.timestamp.elapsed_secs(),
+                            wallclock,
+                            std::str::from_utf8(&m.data[..]).unwrap(),
+                        );
+                        frames += 1;
+                        if opts.limits.frame_limit_reached(frames) {
+                            info!("Stopping after {} messages", frames);
+                            break;
+                        }
                     },
                     _ => continue,
                 };
@@ -60,7 +90,22 @@ async fn run_inner(opts: Opts, session_group: Arc<SessionGroup>) -> Result<(), E
             _ = &mut stop => {
                 break;
             },
+            _ = &mut sleep => {
+                info!("Stopping after {} seconds", opts.limits.duration.unwrap());
+                break;
+            },
         }
     }
     Ok(())
 }
+
+/// Estimates the wallclock time of `timestamp` from a sender report on the
+/// same stream.
+fn wallclock(sr: &SenderReport, timestamp: retina::Timestamp) -> retina::NtpTimestamp {
+    let delta = i128::from(timestamp.timestamp() - sr.timestamp.timestamp());
+    let clock_rate = i128::from(timestamp.clock_rate().get());
+
+    // NTP timestamps are 32.32 fixed-point seconds.
+    let ntp = i128::from(sr.ntp_timestamp.0) + (delta << 32) / clock_rate;
+    retina::NtpTimestamp(ntp as u64)
+}
@@ -0,0 +1,105 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! A minimal mock RTSP server for the examples' tests.
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+/// The Reolink test presentation's SDP.
+pub fn reolink_sdp() -> &'static str {
+    let raw = include_str!("../../src/client/testdata/reolink_describe.txt");
+    &raw[raw.find("\r\n\r\n").unwrap() + 4..]
+}
+
+/// Starts a server on a local port which serves one RTSP connection until the
+/// client disconnects. Returns its URL and a handle to await its completion.
+///
+/// Answers `DESCRIBE` with `sdp`, `SETUP` with interleaved channels 0-1, and
+/// anything else with a bare `200 OK`. After answering `PLAY`, sends each of
+/// `packets` as interleaved data on channel 0.
+pub async fn start(sdp: String, packets: Vec<Vec<u8>>) -> (url::Url, tokio::task::JoinHandle<()>) {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = url::Url::parse(&format!(
+        "rtsp://{}/h264Preview_01_main",
+        listener.local_addr().unwrap()
+    ))
+    .unwrap();
+    let server = tokio::spawn(async move {
+        let (tcp, _) = listener.accept().await.unwrap();
+        serve(tcp, &sdp, &packets).await;
+    });
+    (url, server)
+}
+
+async fn serve(mut tcp: tokio::net::TcpStream, sdp: &str, packets: &[Vec<u8>]) {
+    let mut buf = Vec::new();
+    loop {
+        if buf.first() == Some(&b'$') {
+            // Skip interleaved data from the client, such as RTCP receiver reports.
+            if buf.len() >= 4 {
+                let len = 4 + usize::from(u16::from_be_bytes([buf[2], buf[3]]));
+                if buf.len() >= len {
+                    buf.drain(..len);
+                    continue;
+                }
+            }
+        } else if let Some(end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let req: Vec<u8> = buf.drain(..end + 4).collect();
+            if !respond(&mut tcp, std::str::from_utf8(&req).unwrap(), sdp, packets).await {
+                return;
+            }
+            continue;
+        }
+        let mut chunk = [0u8; 1024];
+        match tcp.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+        }
+    }
+}
+
+/// Responds to a single request, returning false if the client has gone away.
+async fn respond(
+    tcp: &mut tokio::net::TcpStream,
+    req: &str,
+    sdp: &str,
+    packets: &[Vec<u8>],
+) -> bool {
+    let mut words = req.split_whitespace();
+    let method = words.next().expect("request has a method");
+    let uri = words.next().expect("request has a URI");
+    let cseq = req
+        .lines()
+        .find_map(|l| l.strip_prefix("CSeq: "))
+        .expect("request has a CSeq");
+    let head = format!("RTSP/1.0 200 OK\r\nCSeq: {}\r\n", cseq);
+    let response = match method {
+        "DESCRIBE" => format!(
+            "{}Content-Base: {}/\r\nContent-Type: application/sdp\r\nContent-Length: {}\r\n\r\n{}",
+            head,
+            uri,
+            sdp.len(),
+            sdp
+        ),
+        "SETUP" => format!(
+            "{}Session: F8F8E425\r\nTransport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n\r\n",
+            head
+        ),
+        "PLAY" => format!("{}Session: F8F8E425\r\nRange: npt=0.000-\r\n\r\n", head),
+        _ => format!("{}\r\n", head),
+    };
+    if tcp.write_all(response.as_bytes()).await.is_err() {
+        return false;
+    }
+    if method == "PLAY" {
+        for p in packets {
+            let mut data = vec![b'$', 0];
+            data.extend_from_slice(&(p.len() as u16).to_be_bytes());
+            data.extend_from_slice(p);
+            if tcp.write_all(&data).await.is_err() {
+                return false;
+            }
+        }
+    }
+    true
+}
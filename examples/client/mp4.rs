@@ -8,6 +8,13 @@
 //! (`mdat`) first or reserved a fixed size for the `moov`, but it will slow
 //! playback, particularly when serving `.mp4` files remotely.
 //!
+//! Tracks which start at different times (as measured by NPT) are aligned
+//! via edit lists.
+//!
+//! With `--fragmented`, it instead writes the `moov` up front, followed by a
+//! `moof`/`mdat` fragment starting at each video key frame, so that the file
+//! remains playable if recording is interrupted.
+//!
 //! For a more high-quality implementation, see [Moonfire NVR](https://github.com/scottlamb/moonfire-nvr).
 //! It's better tested, places the `moov` atom at the start, can do HTTP range
 //! serving for arbitrary time ranges, and supports standard and fragmented
@@ -18,13 +25,10 @@
 //! https://standards.iso.org/ittf/PubliclyAvailableStandards/c068960_ISO_IEC_14496-12_2015.zip
 
 use anyhow::{anyhow, bail, Context, Error};
-use bytes::{Buf, BufMut, BytesMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use futures::{Future, StreamExt};
 use log::{info, warn};
-use retina::{
-    client::Transport,
-    codec::{AudioParameters, CodecItem, VideoParameters},
-};
+use retina::codec::{AudioParameters, CodecItem, VideoParameters};
 
 use std::num::NonZeroU32;
 use std::path::PathBuf;
@@ -53,7 +57,10 @@ pub struct Opts {
     #[structopt(long)]
     no_audio: bool,
 
-    /// Allow lost packets mid-stream without aborting.
+    /// Allow lost packets mid-stream without aborting the `.mp4`.
+    ///
+    /// This applies after `--loss-policy`; without it, any loss the session
+    /// tolerates still aborts the recording.
     #[structopt(long)]
     allow_loss: bool,
 
@@ -61,15 +68,12 @@ pub struct Opts {
     #[structopt(default_value, long)]
     teardown: retina::client::TeardownPolicy,
 
-    /// Duration after which to exit automatically, in seconds.
-    #[structopt(long, name = "secs")]
-    duration: Option<u64>,
+    #[structopt(flatten)]
+    limits: super::Limits,
 
-    /// The transport to use: `tcp` or `udp` (experimental).
-    ///
-    /// Note: `--allow-loss` is strongly recommended with `udp`.
-    #[structopt(default_value, long)]
-    transport: retina::client::Transport,
+    /// Write a fragmented `.mp4`, which remains playable if interrupted.
+    #[structopt(long)]
+    fragmented: bool,
 
     /// Path to `.mp4` file to write.
     #[structopt(parse(try_from_str))]
@@ -118,9 +122,143 @@ pub struct Mp4Writer<W: AsyncWrite + AsyncSeek + Send + Unpin> {
 
     video_trak: TrakTracker,
     audio_trak: TrakTracker,
+
+    /// The NPT which is movie time 0, in the movie timescale (90 kHz), once
+    /// fixed by writing a fragmented `.mp4`'s first fragment. Otherwise it's
+    /// the earliest of the tracks' starts.
+    origin: Option<i64>,
+
+    /// The NPT of each track's first sample, in the movie timescale, so
+    /// tracks which start at different NPTs stay in sync.
+    video_start: Option<i64>,
+    audio_start: Option<i64>,
+
+    /// State for writing a fragmented `.mp4`, if requested.
+    fragments: Option<FragmentState>,
     inner: W,
 }
 
+/// State for writing a fragmented `.mp4` (BMFF section 8.8).
+#[derive(Default)]
+struct FragmentState {
+    /// True once the (sample-less) `moov` has been written. This is deferred
+    /// until the first fragment so the first video frame can supply
+    /// parameters.
+    wrote_moov: bool,
+
+    /// The sequence number of the last `moof` written.
+    sequence_number: u32,
+
+    video: FragmentTrack,
+    audio: FragmentTrack,
+}
+
+/// Samples of one track buffered for the next fragment.
+#[derive(Default)]
+struct FragmentTrack {
+    /// The decode time of the first buffered sample, in the track's timescale.
+    base_decode_time: u64,
+    samples: Vec<FragmentSample>,
+    started: bool,
+}
+
+struct FragmentSample {
+    pts: i64,
+    is_sync: bool,
+    data: Bytes,
+}
+
+impl FragmentTrack {
+    fn push(
+        &mut self,
+        timestamp: retina::Timestamp,
+        is_sync: bool,
+        data: Bytes,
+        loss: u16,
+        allow_loss: bool,
+    ) -> Result<(), Error> {
+        if self.started && loss > 0 && !allow_loss {
+            bail!("Lost {} RTP packets mid-stream", loss);
+        }
+        self.started = true;
+        self.samples.push(FragmentSample {
+            pts: timestamp.timestamp(),
+            is_sync,
+            data,
+        });
+        Ok(())
+    }
+
+    /// Returns the span of buffered samples, in the track's timescale.
+    fn buffered_duration(&self) -> i64 {
+        match (self.samples.first(), self.samples.last()) {
+            (Some(first), Some(last)) => last.pts - first.pts,
+            _ => 0,
+        }
+    }
+
+    /// Takes buffered samples to write, returning their base decode time and
+    /// each with its duration.
+    ///
+    /// The last sample lasts until `end` if supplied. Otherwise its duration
+    /// isn't known yet, so it stays buffered for the next fragment unless
+    /// `finish` is set, in which case it's given duration 0.
+    fn take(
+        &mut self,
+        end: Option<i64>,
+        finish: bool,
+    ) -> Result<(u64, Vec<(u32, FragmentSample)>), Error> {
+        let keep = if end.is_none() && !finish && !self.samples.is_empty() {
+            self.samples.len() - 1
+        } else {
+            self.samples.len()
+        };
+        let rest = self.samples.split_off(keep);
+        let samples = std::mem::replace(&mut self.samples, rest);
+        let base_decode_time = self.base_decode_time;
+        let mut out = Vec::with_capacity(samples.len());
+        let mut iter = samples.into_iter().peekable();
+        while let Some(sample) = iter.next() {
+            let next_pts = match iter.peek() {
+                Some(next) => Some(next.pts),
+                None => end.or_else(|| self.samples.first().map(|s| s.pts)),
+            };
+            let duration = match next_pts {
+                Some(n) => u32::try_from(
+                    n.checked_sub(sample.pts)
+                        .ok_or_else(|| anyhow!("timestamp overflow after {}", sample.pts))?,
+                )?,
+                None => 0,
+            };
+            self.base_decode_time += u64::from(duration);
+            out.push((duration, sample));
+        }
+        Ok((base_decode_time, out))
+    }
+}
+
+/// Returns true if `parameters` describe AAC, which needs `roll` sample groups.
+fn is_aac(parameters: &AudioParameters) -> bool {
+    parameters
+        .rfc6381_codec()
+        .map(|c| c.starts_with("mp4a."))
+        .unwrap_or(false)
+}
+
+/// Writes a `sbgp` assigning `samples` samples to the `roll` group described
+/// by the track's `sgpd`.
+fn write_roll_sbgp(buf: &mut BytesMut, samples: u32) -> Result<(), Error> {
+    write_box!(buf, b"sbgp", {
+        // BMFF section 8.9.2: SampleToGroupBox
+        buf.put_u32(0); // version
+        buf.extend_from_slice(b"roll"); // grouping type
+        buf.put_u32(1); // entry_count
+        buf.put_u32(samples);
+        buf.put_u32(1); // group_description_index
+    });
+    Ok(())
+}
+
 /// Tracks the parts of a `trak` atom which are common between video and audio samples.
 #[derive(Default)]
 struct TrakTracker {
@@ -182,7 +320,6 @@ impl TrakTracker {
     }
 
     fn write_common_stbl_parts(&self, buf: &mut BytesMut) -> Result<(), Error> {
-        // TODO: add an edit list so the video and audio tracks are in sync.
         write_box!(buf, b"stts", {
             buf.put_u32(0);
             buf.put_u32(u32::try_from(self.durations.len())?);
@@ -233,6 +370,7 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
         video_params: Option<Box<VideoParameters>>,
         audio_params: Option<Box<AudioParameters>>,
         allow_loss: bool,
+        fragmented: bool,
         mut inner: W,
     ) -> Result<Self, Error> {
         let mut buf = BytesMut::new();
@@ -243,7 +381,9 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
                 b'i', b's', b'o', b'm', // compatible_brands[0]
             ]);
         });
-        buf.extend_from_slice(&b"\0\0\0\0mdat"[..]);
+        if !fragmented {
+            buf.extend_from_slice(&b"\0\0\0\0mdat"[..]);
+        }
         let mdat_start = u32::try_from(buf.len())?;
         write_all_buf(&mut inner, &mut buf).await?;
         Ok(Mp4Writer {
@@ -254,12 +394,25 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
             video_trak: TrakTracker::default(),
             audio_trak: TrakTracker::default(),
             video_sync_sample_nums: Vec::new(),
+            origin: None,
+            video_start: None,
+            audio_start: None,
+            fragments: if fragmented {
+                Some(FragmentState::default())
+            } else {
+                None
+            },
             mdat_start,
             mdat_pos: mdat_start,
         })
     }
 
     pub async fn finish(mut self) -> Result<(), Error> {
+        if self.fragments.is_some() {
+            // Everything written so far is already playable; just flush the
+            // remaining samples.
+            return self.write_fragment(None, true).await;
+        }
         self.video_trak.finish();
         self.audio_trak.finish();
         let mut buf = BytesMut::with_capacity(
@@ -267,7 +420,20 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
                 + self.audio_trak.size_estimate()
                 + 4 * self.video_sync_sample_nums.len(),
         );
-        write_box!(&mut buf, b"moov", {
+        self.write_moov(&mut buf)?;
+        write_all_buf(&mut self.inner, &mut buf.freeze()).await?;
+        self.inner
+            .seek(SeekFrom::Start(u64::from(self.mdat_start - 8)))
+            .await?;
+        self.inner
+            .write_all(&u32::try_from(self.mdat_pos + 8 - self.mdat_start)?.to_be_bytes()[..])
+            .await?;
+        Ok(())
+    }
+
+    /// Writes the `moov` atom, which in fragmented mode describes no samples.
+    fn write_moov(&self, buf: &mut BytesMut) -> Result<(), Error> {
+        write_box!(buf, b"moov", {
             write_box!(&mut buf, b"mvhd", {
                 buf.put_u32(1 << 24); // version
                 buf.put_u64(0); // creation_time
@@ -287,19 +453,132 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
                 buf.put_u32(2); // next_track_id
             });
             if let Some(p) = self.video_params.as_ref() {
-                self.write_video_trak(&mut buf, p)?;
+                self.write_video_trak(buf, p)?;
             }
             if let Some(p) = self.audio_params.as_ref() {
-                self.write_audio_trak(&mut buf, p)?;
+                self.write_audio_trak(buf, p)?;
+            }
+            if self.fragments.is_some() {
+                write_box!(buf, b"mvex", {
+                    for (track_id, present) in [
+                        (1, self.video_params.is_some()),
+                        (2, self.audio_params.is_some()),
+                    ] {
+                        if !present {
+                            continue;
+                        }
+                        write_box!(buf, b"trex", {
+                            buf.put_u32(0); // version, flags
+                            buf.put_u32(track_id);
+                            buf.put_u32(1); // default_sample_description_index
+                            buf.put_u32(0); // default_sample_duration
+                            buf.put_u32(0); // default_sample_size
+                            buf.put_u32(0); // default_sample_flags
+                        });
+                    }
+                });
+            }
+        });
+        Ok(())
+    }
+
+    /// Writes a `moof`/`mdat` pair holding the buffered samples, preceded by
+    /// the `moov` if this is the first fragment.
+    ///
+    /// `video_end` is the timestamp of the video frame which will start the
+    /// next fragment, if any. `finish` flushes all samples, including ones
+    /// with unknown durations.
+    async fn write_fragment(&mut self, video_end: Option<i64>, finish: bool) -> Result<(), Error> {
+        let wrote_moov = self
+            .fragments
+            .as_ref()
+            .expect("write_fragment requires fragmented mode")
+            .wrote_moov;
+        if !wrote_moov {
+            // Fix movie time 0 now that samples are going out.
+            self.origin = Some(self.origin());
+            let mut buf = BytesMut::with_capacity(1024);
+            self.write_moov(&mut buf)?;
+            write_all_buf(&mut self.inner, &mut buf.freeze()).await?;
+        }
+        let audio_is_aac = self.audio_params.as_deref().map(is_aac).unwrap_or(false);
+        let frag = self.fragments.as_mut().unwrap();
+        frag.wrote_moov = true;
+        let tracks = [
+            (1, frag.video.take(video_end, finish)?),
+            (2, frag.audio.take(None, finish)?),
+        ];
+        if tracks.iter().all(|(_, (_, samples))| samples.is_empty()) {
+            return Ok(());
+        }
+        frag.sequence_number += 1;
+
+        // BMFF section 8.8.4: MovieFragmentBox.
+        let mut buf = BytesMut::with_capacity(1024);
+        let mut data_offsets = Vec::new(); // (position in buf, offset within mdat)
+        let mut mdat_len = 0u32;
+        write_box!(&mut buf, b"moof", {
+            write_box!(&mut buf, b"mfhd", {
+                buf.put_u32(0); // version, flags
+                buf.put_u32(frag.sequence_number);
+            });
+            for (track_id, (base_decode_time, samples)) in &tracks {
+                if samples.is_empty() {
+                    continue;
+                }
+                write_box!(&mut buf, b"traf", {
+                    write_box!(&mut buf, b"tfhd", {
+                        buf.put_u32(0x02_0000); // version, flags=default-base-is-moof
+                        buf.put_u32(*track_id);
+                    });
+                    write_box!(&mut buf, b"tfdt", {
+                        buf.put_u32(1 << 24); // version
+                        buf.put_u64(*base_decode_time);
+                    });
+                    write_box!(&mut buf, b"trun", {
+                        // version, flags=data-offset, sample-duration,
+                        // sample-size, sample-flags present.
+                        buf.put_u32(0x00_0701);
+                        buf.put_u32(u32::try_from(samples.len())?);
+                        data_offsets.push((buf.len(), mdat_len));
+                        buf.put_u32(0); // data_offset, filled in below.
+                        for (duration, sample) in samples {
+                            let size = u32::try_from(sample.data.len())?;
+                            buf.put_u32(*duration);
+                            buf.put_u32(size);
+                            buf.put_u32(if sample.is_sync {
+                                0x0200_0000 // sample_depends_on=2 (independent)
+                            } else {
+                                0x0101_0000 // sample_depends_on=1, sample_is_non_sync_sample
+                            });
+                            mdat_len = mdat_len
+                                .checked_add(size)
+                                .ok_or_else(|| anyhow!("mdat overflow"))?;
+                        }
+                    });
+                    if *track_id == 2 && audio_is_aac {
+                        write_roll_sbgp(&mut buf, u32::try_from(samples.len())?)?;
+                    }
+                });
             }
         });
+        let moof_len = u32::try_from(buf.len())?;
+        for (pos, offset) in data_offsets {
+            let data_offset = moof_len + 8 + offset;
+            buf[pos..pos + 4].copy_from_slice(&data_offset.to_be_bytes()[..]);
+        }
+        buf.put_u32(
+            mdat_len
+                .checked_add(8)
+                .ok_or_else(|| anyhow!("mdat overflow"))?,
+        );
+        buf.extend_from_slice(b"mdat");
         write_all_buf(&mut self.inner, &mut buf.freeze()).await?;
-        self.inner
-            .seek(SeekFrom::Start(u64::from(self.mdat_start - 8)))
-            .await?;
-        self.inner
-            .write_all(&u32::try_from(self.mdat_pos + 8 - self.mdat_start)?.to_be_bytes()[..])
-            .await?;
+        for (_, (_, samples)) in tracks {
+            for (_, mut sample) in samples {
+                write_all_buf(&mut self.inner, &mut sample.data).await?;
+            }
+        }
         Ok(())
     }
 
@@ -334,6 +613,11 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
                 buf.put_u32(width);
                 buf.put_u32(height);
             });
+            self.write_edts(
+                buf,
+                self.movie_start(self.video_start),
+                self.video_trak.tot_duration,
+            )?;
             write_box!(buf, b"mdia", {
                 write_box!(buf, b"mdhd", {
                     buf.put_u32(1 << 24); // version
@@ -372,16 +656,22 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
                         write_box!(buf, b"stsd", {
                             buf.put_u32(0); // version
                             buf.put_u32(1); // entry_count
-                            self.write_video_sample_entry(buf, parameters)?;
+                            buf.extend_from_slice(
+                                &parameters
+                                    .sample_entry()
+                                    .expect("all added streams have sample entries")[..],
+                            );
                         });
                         self.video_trak.write_common_stbl_parts(buf)?;
-                        write_box!(buf, b"stss", {
-                            buf.put_u32(0); // version
-                            buf.put_u32(u32::try_from(self.video_sync_sample_nums.len())?);
-                            for n in &self.video_sync_sample_nums {
-                                buf.put_u32(*n);
-                            }
-                        });
+                        if self.fragments.is_none() {
+                            write_box!(buf, b"stss", {
+                                buf.put_u32(0); // version
+                                buf.put_u32(u32::try_from(self.video_sync_sample_nums.len())?);
+                                for n in &self.video_sync_sample_nums {
+                                    buf.put_u32(*n);
+                                }
+                            });
+                        }
                     });
                 });
             });
@@ -389,6 +679,82 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
         Ok(())
     }
 
+    /// Writes an edit list which delays a track starting at movie time
+    /// `start`, if it's non-zero. `duration` is the track's duration in the
+    /// movie timescale.
+    ///
+    /// In fragmented mode, the track's initial decode time is offset instead.
+    fn write_edts(
+        &self,
+        buf: &mut BytesMut,
+        start: Option<u64>,
+        duration: u64,
+    ) -> Result<(), Error> {
+        let start = match start {
+            Some(s) if s > 0 && self.fragments.is_none() => s,
+            _ => return Ok(()),
+        };
+        write_box!(buf, b"edts", {
+            write_box!(buf, b"elst", {
+                buf.put_u32(1 << 24); // version
+                buf.put_u32(2); // entry_count
+
+                // An empty edit, then the full media.
+                buf.put_u64(start); // segment_duration
+                buf.put_i64(-1); // media_time
+                buf.put_u32(0x00010000); // media_rate
+                buf.put_u64(duration); // segment_duration
+                buf.put_i64(0); // media_time
+                buf.put_u32(0x00010000); // media_rate
+            });
+        });
+        Ok(())
+    }
+
+    /// Returns the NPT of `timestamp` in the movie timescale.
+    fn npt(timestamp: retina::Timestamp) -> i64 {
+        timestamp.elapsed() * 90_000 / i64::from(timestamp.clock_rate().get())
+    }
+
+    /// Returns the NPT which is movie time 0: the earliest track start.
+    fn origin(&self) -> i64 {
+        self.origin
+            .unwrap_or_else(|| match (self.video_start, self.audio_start) {
+                (Some(v), Some(a)) => v.min(a),
+                (v, a) => v.or(a).unwrap_or(0),
+            })
+    }
+
+    /// Returns the movie time at which a track starting at NPT `start` begins.
+    ///
+    /// A track can only start before the origin in a fragmented `.mp4`, if
+    /// its first sample arrives after the first fragment has been written.
+    /// Such a track is clamped to movie time 0.
+    fn movie_start(&self, start: Option<i64>) -> Option<u64> {
+        start.map(|s| u64::try_from(s - self.origin()).unwrap_or(0))
+    }
+
+    /// Sets the fragments' base decode times from the tracks' starts.
+    ///
+    /// Until the origin is fixed, a new track start may move it, so this
+    /// updates every track. Afterward, it only sets tracks with no samples.
+    fn set_base_decode_times(&mut self) {
+        let fixed = self.origin.is_some();
+        let video = self.movie_start(self.video_start);
+        let audio = self.movie_start(self.audio_start);
+        let audio_clock_rate = self.audio_params.as_ref().map(|p| p.clock_rate());
+        let f = match self.fragments.as_mut() {
+            Some(f) => f,
+            None => return,
+        };
+        if let (Some(v), false) = (video, fixed && f.video.started) {
+            f.video.base_decode_time = v;
+        }
+        if let (Some(a), Some(r), false) = (audio, audio_clock_rate, fixed && f.audio.started) {
+            f.audio.base_decode_time = a * u64::from(r) / 90_000;
+        }
+    }
+
     fn write_audio_trak(
         &self,
         buf: &mut BytesMut,
@@ -413,6 +779,11 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
                 buf.put_u32(0); // width
                 buf.put_u32(0); // height
             });
+            self.write_edts(
+                buf,
+                self.movie_start(self.audio_start),
+                self.audio_trak.tot_duration * 90_000 / u64::from(parameters.clock_rate()),
+            )?;
             write_box!(buf, b"mdia", {
                 write_box!(buf, b"mdhd", {
                     buf.put_u32(1 << 24); // version
@@ -465,22 +836,21 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
                         // AAC requires two samples (really, each is a set of 960 or 1024 samples)
                         // to decode accurately. See
                         // https://developer.apple.com/library/archive/documentation/QuickTime/QTFF/QTFFAppenG/QTFFAppenG.html .
-                        write_box!(buf, b"sgpd", {
-                            // BMFF section 8.9.3: SampleGroupDescriptionBox
-                            buf.put_u32(0); // version
-                            buf.extend_from_slice(b"roll"); // grouping type
-                            buf.put_u32(1); // entry_count
-                                            // BMFF section 10.1: AudioRollRecoveryEntry
-                            buf.put_i16(-1); // roll_distance
-                        });
-                        write_box!(buf, b"sbgp", {
-                            // BMFF section 8.9.2: SampleToGroupBox
-                            buf.put_u32(0); // version
-                            buf.extend_from_slice(b"roll"); // grouping type
-                            buf.put_u32(1); // entry_count
-                            buf.put_u32(self.audio_trak.samples);
-                            buf.put_u32(1); // group_description_index
-                        });
+                        // In fragmented mode, each `traf` has its own `sbgp`
+                        // referring to this `sgpd`.
+                        if is_aac(parameters) {
+                            write_box!(buf, b"sgpd", {
+                                // BMFF section 8.9.3: SampleGroupDescriptionBox
+                                buf.put_u32(0); // version
+                                buf.extend_from_slice(b"roll"); // grouping type
+                                buf.put_u32(1); // entry_count
+                                                // BMFF section 10.1: AudioRollRecoveryEntry
+                                buf.put_i16(-1); // roll_distance
+                            });
+                        }
+                        if is_aac(parameters) && self.fragments.is_none() {
+                            write_roll_sbgp(buf, self.audio_trak.samples)?;
+                        }
                     });
                 });
             });
@@ -488,40 +858,6 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
         Ok(())
     }
 
-    fn write_video_sample_entry(
-        &self,
-        buf: &mut BytesMut,
-        parameters: &VideoParameters,
-    ) -> Result<(), Error> {
-        // TODO: this should move to client::VideoParameters::sample_entry() or some such.
-        write_box!(buf, b"avc1", {
-            buf.put_u32(0);
-            buf.put_u32(1); // data_reference_index = 1
-            buf.extend_from_slice(&[0; 16]);
-            buf.put_u16(u16::try_from(parameters.pixel_dimensions().0)?);
-            buf.put_u16(u16::try_from(parameters.pixel_dimensions().1)?);
-            buf.extend_from_slice(&[
-                0x00, 0x48, 0x00, 0x00, // horizresolution
-                0x00, 0x48, 0x00, 0x00, // vertresolution
-                0x00, 0x00, 0x00, 0x00, // reserved
-                0x00, 0x01, // frame count
-                0x00, 0x00, 0x00, 0x00, // compressorname
-                0x00, 0x00, 0x00, 0x00, //
-                0x00, 0x00, 0x00, 0x00, //
-                0x00, 0x00, 0x00, 0x00, //
-                0x00, 0x00, 0x00, 0x00, //
-                0x00, 0x00, 0x00, 0x00, //
-                0x00, 0x00, 0x00, 0x00, //
-                0x00, 0x00, 0x00, 0x00, //
-                0x00, 0x18, 0xff, 0xff, // depth + pre_defined
-            ]);
-            write_box!(buf, b"avcC", {
-                buf.extend_from_slice(parameters.extra_data());
-            });
-        });
-        Ok(())
-    }
-
     async fn video(&mut self, mut frame: retina::codec::VideoFrame) -> Result<(), Error> {
         println!(
             "{}: {}-byte video frame",
@@ -529,51 +865,98 @@ impl<W: AsyncWrite + AsyncSeek + Send + Unpin> Mp4Writer<W> {
             frame.data().remaining(),
         );
         if let Some(p) = frame.new_parameters.take() {
-            if self.video_trak.samples > 0 {
+            let started = match &self.fragments {
+                Some(f) => f.wrote_moov || !f.video.samples.is_empty(),
+                None => self.video_trak.samples > 0,
+            };
+            if started {
                 bail!("parameters change unimplemented. new parameters: {:#?}", p);
             }
             self.video_params = Some(p);
         }
-        let size = u32::try_from(frame.data().remaining())?;
-        self.video_trak.add_sample(
-            self.mdat_pos,
-            size,
-            frame.timestamp,
-            frame.loss,
-            self.allow_loss,
-        )?;
+        let timestamp = frame.timestamp;
+        let loss = frame.loss;
+        let is_random_access_point = frame.is_random_access_point;
+        self.video_sample(timestamp, loss, is_random_access_point, frame.into_data())
+            .await
+    }
+
+    async fn video_sample(
+        &mut self,
+        timestamp: retina::Timestamp,
+        loss: u16,
+        is_random_access_point: bool,
+        mut data: Bytes,
+    ) -> Result<(), Error> {
+        if self.video_start.is_none() {
+            self.video_start = Some(Self::npt(timestamp));
+            self.set_base_decode_times();
+        }
+        if let Some(f) = self.fragments.as_mut() {
+            if is_random_access_point && !f.video.samples.is_empty() {
+                self.write_fragment(Some(timestamp.timestamp()), false)
+                    .await?;
+            }
+            let allow_loss = self.allow_loss;
+            let f = self.fragments.as_mut().unwrap();
+            return f
+                .video
+                .push(timestamp, is_random_access_point, data, loss, allow_loss);
+        }
+        let size = u32::try_from(data.remaining())?;
+        self.video_trak
+            .add_sample(self.mdat_pos, size, timestamp, loss, self.allow_loss)?;
         self.mdat_pos = self
             .mdat_pos
             .checked_add(size)
             .ok_or_else(|| anyhow!("mdat_pos overflow"))?;
-        if frame.is_random_access_point {
+        if is_random_access_point {
             self.video_sync_sample_nums
                 .push(u32::try_from(self.video_trak.samples)?);
         }
-        let mut data = frame.into_data();
         write_all_buf(&mut self.inner, &mut data).await?;
         Ok(())
     }
 
-    async fn audio(&mut self, mut frame: retina::codec::AudioFrame) -> Result<(), Error> {
+    async fn audio(&mut self, frame: retina::codec::AudioFrame) -> Result<(), Error> {
         println!(
             "{}: {}-byte audio frame",
             &frame.timestamp,
             frame.data.remaining()
         );
-        let size = u32::try_from(frame.data.remaining())?;
-        self.audio_trak.add_sample(
-            self.mdat_pos,
-            size,
-            frame.timestamp,
-            frame.loss,
-            self.allow_loss,
-        )?;
+        self.audio_sample(frame.timestamp, frame.loss, frame.data)
+            .await
+    }
+
+    async fn audio_sample(
+        &mut self,
+        timestamp: retina::Timestamp,
+        loss: u16,
+        mut data: Bytes,
+    ) -> Result<(), Error> {
+        let clock_rate = timestamp.clock_rate().get();
+        if self.audio_start.is_none() {
+            self.audio_start = Some(Self::npt(timestamp));
+            self.set_base_decode_times();
+        }
+        if self.fragments.is_some() {
+            // Without video, start a fragment roughly every second.
+            let audio = &self.fragments.as_ref().unwrap().audio;
+            if self.video_params.is_none() && audio.buffered_duration() >= i64::from(clock_rate) {
+                self.write_fragment(None, false).await?;
+            }
+            let allow_loss = self.allow_loss;
+            let f = self.fragments.as_mut().unwrap();
+            return f.audio.push(timestamp, true, data, loss, allow_loss);
+        }
+        let size = u32::try_from(data.remaining())?;
+        self.audio_trak
+            .add_sample(self.mdat_pos, size, timestamp, loss, self.allow_loss)?;
         self.mdat_pos = self
             .mdat_pos
             .checked_add(size)
             .ok_or_else(|| anyhow!("mdat_pos overflow"))?;
-        write_all_buf(&mut self.inner, &mut frame.data).await?;
+        write_all_buf(&mut self.inner, &mut data).await?;
         Ok(())
     }
 }
@@ -585,12 +968,10 @@ async fn copy<'a>(
     stop_signal: Pin<Box<dyn Future<Output = Result<(), std::io::Error>>>>,
     mp4: &'a mut Mp4Writer<File>,
 ) -> Result<(), Error> {
-    let sleep = match opts.duration {
-        Some(secs) => {
-            futures::future::Either::Left(tokio::time::sleep(std::time::Duration::from_secs(secs)))
-        }
-        None => futures::future::Either::Right(futures::future::pending()),
-    };
+    // `--frames` counts video frames, or audio frames if there's no video.
+    let count_video = mp4.video_params.is_some();
+    let mut frames = 0;
+    let sleep = opts.limits.sleep();
     tokio::pin!(stop_signal);
     tokio::pin!(sleep);
     loop {
@@ -601,24 +982,34 @@ async fn copy<'a>(
                         let start_ctx = f.start_ctx();
                         mp4.video(f).await.with_context(
                             || format!("Error processing video frame starting with {}", start_ctx))?;
+                        if count_video {
+                            frames += 1;
+                        }
                     },
                     CodecItem::AudioFrame(f) => {
                         let ctx = f.ctx;
                         mp4.audio(f).await.with_context(
                             || format!("Error processing audio frame, {}", ctx))?;
+                        if !count_video {
+                            frames += 1;
+                        }
                     },
                     CodecItem::SenderReport(sr) => {
                         println!("{}: SR ts={}", sr.timestamp, sr.ntp_timestamp);
                     },
                     _ => continue,
                 };
+                if opts.limits.frame_limit_reached(frames) {
+                    info!("Stopping after {} frames", frames);
+                    break;
+                }
             },
             _ = &mut stop_signal => {
                 info!("Stopping due to signal");
                 break;
             },
             _ = &mut sleep => {
-                info!("Stopping after {} seconds", opts.duration.unwrap());
+                info!("Stopping after {} seconds", opts.limits.duration.unwrap());
                 break;
             },
         }
@@ -640,8 +1031,9 @@ async fn write_mp4<'a>(
                 .initial_timestamp(opts.initial_timestamp)
                 .enforce_timestamps_with_max_jump_secs(NonZeroU32::new(10).unwrap()),
         )
-        .await?
-        .demuxed()?;
+        .await?;
+    info!("Using transport {}", session.active_transport());
+    let mut session = session.demuxed()?;
 
     // Append into a filename suffixed with ".partial", then try to either rename it into
     // place if it's complete or delete it otherwise.
@@ -654,6 +1046,7 @@ async fn write_mp4<'a>(
         video_stream.map(|(_, p)| Box::new(p)),
         audio_stream.map(|(_, p)| Box::new(p)),
         opts.allow_loss,
+        opts.fragmented,
         out,
     )
     .await?;
@@ -688,8 +1081,11 @@ async fn write_mp4<'a>(
 }
 
 pub async fn run(opts: Opts) -> Result<(), Error> {
-    if matches!(opts.transport, Transport::Udp) && !opts.allow_loss {
-        warn!("Using --transport=udp without strongly recommended --allow-loss!");
+    if opts.src.transport != super::TransportArg::Tcp && !opts.allow_loss {
+        warn!(
+            "Using --transport={} without strongly recommended --allow-loss!",
+            opts.src.transport
+        );
     }
 
     let creds = super::creds(opts.src.username.clone(), opts.src.password.clone());
@@ -697,12 +1093,14 @@ pub async fn run(opts: Opts) -> Result<(), Error> {
     let session_group = Arc::new(retina::client::SessionGroup::default());
     let mut session = retina::client::Session::describe(
         opts.src.url.clone(),
-        retina::client::SessionOptions::default()
-            .creds(creds)
-            .session_group(session_group.clone())
-            .user_agent("Retina mp4 example".to_owned())
-            .transport(opts.transport)
-            .teardown(opts.teardown),
+        opts.src.transport.apply(
+            retina::client::SessionOptions::default()
+                .creds(creds)
+                .session_group(session_group.clone())
+                .user_agent("Retina mp4 example".to_owned())
+                .teardown(opts.teardown)
+                .loss_policy(opts.src.loss_policy()),
+        ),
     )
     .await?;
     let video_stream = if !opts.no_video {
@@ -711,7 +1109,11 @@ pub async fn run(opts: Opts) -> Result<(), Error> {
             .iter()
             .enumerate()
             .find_map(|(i, s)| match s.parameters() {
-                Some(retina::codec::Parameters::Video(v)) => Some((i, v.clone())),
+                // Only consider video streams that can produce a .mp4 sample
+                // entry.
+                Some(retina::codec::Parameters::Video(v)) if v.sample_entry().is_some() => {
+                    Some((i, v.clone()))
+                }
                 _ => None,
             })
     } else {
@@ -748,3 +1150,253 @@ pub async fn run(opts: Opts) -> Result<(), Error> {
     }
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+    use std::num::NonZeroU32;
+
+    /// Splits `data` into its boxes' fourccs and bodies.
+    fn boxes(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+        let mut out = Vec::new();
+        while !data.is_empty() {
+            let len = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+            assert!(len >= 8 && len <= data.len(), "bad box length {}", len);
+            out.push((&data[4..8], &data[8..len]));
+            data = &data[len..];
+        }
+        out
+    }
+
+    fn find<'a>(boxes: &[(&[u8], &'a [u8])], fourcc: &[u8]) -> &'a [u8] {
+        boxes
+            .iter()
+            .find(|(f, _)| *f == fourcc)
+            .map(|(_, body)| *body)
+            .unwrap_or_else(|| panic!("no {:?} box", std::str::from_utf8(fourcc)))
+    }
+
+    /// Returns the fourccs of the top-level boxes and the video sample count
+    /// of each fragment.
+    fn summarize(data: &[u8]) -> (Vec<&[u8]>, Vec<u32>) {
+        let top = boxes(data);
+        let fourccs = top.iter().map(|(f, _)| *f).collect();
+        let samples = top
+            .iter()
+            .filter(|(f, _)| *f == b"moof")
+            .map(|(_, moof)| {
+                let traf = find(&boxes(moof), b"traf");
+                let trun = find(&boxes(traf), b"trun");
+                u32::from_be_bytes([trun[4], trun[5], trun[6], trun[7]])
+            })
+            .collect();
+        (fourccs, samples)
+    }
+
+    fn video_params() -> retina::codec::VideoParameters {
+        match retina::codec::Depacketizer::new(
+            "video",
+            "h264",
+            90_000,
+            None,
+            Some(
+                "packetization-mode=1;profile-level-id=42C01E;\
+                 sprop-parameter-sets=Z0LAHtkDxWhAAAADAEAAAAwDxYuS,aMuMsg==",
+            ),
+        )
+        .unwrap()
+        .parameters()
+        {
+            Some(retina::codec::Parameters::Video(v)) => v,
+            o => panic!("unexpected parameters {:?}", o),
+        }
+    }
+
+    fn aac_params() -> retina::codec::AudioParameters {
+        match retina::codec::Depacketizer::new(
+            "audio",
+            "mpeg4-generic",
+            12_000,
+            std::num::NonZeroU16::new(2),
+            Some(
+                "profile-level-id=1;mode=AAC-hbr;sizelength=13;indexlength=3;\
+                 indexdeltalength=3;config=1490",
+            ),
+        )
+        .unwrap()
+        .parameters()
+        {
+            Some(retina::codec::Parameters::Audio(a)) => a,
+            o => panic!("unexpected parameters {:?}", o),
+        }
+    }
+
+    fn ts(elapsed: i64, clock_rate: u32) -> retina::Timestamp {
+        retina::Timestamp::new(elapsed, NonZeroU32::new(clock_rate).unwrap(), 0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn audio_video() {
+        audio_video_with(false).await;
+    }
+
+    /// As `audio_video`, but the later-starting audio track's samples arrive
+    /// first, so the origin must move back to the video track's start.
+    #[tokio::test]
+    async fn audio_arrives_first() {
+        audio_video_with(true).await;
+    }
+
+    async fn audio_video_with(audio_first: bool) {
+        let mut out = Vec::new();
+        let mut w = super::Mp4Writer::new(
+            Some(Box::new(video_params())),
+            Some(Box::new(aac_params())),
+            false,
+            false,
+            std::io::Cursor::new(&mut out),
+        )
+        .await
+        .unwrap();
+
+        // Video starts at NPT 0; audio at NPT 0.5.
+        for i in 0..10 {
+            if audio_first {
+                w.audio_sample(
+                    ts(6_000 + i * 1_024, 12_000),
+                    0,
+                    Bytes::from_static(b"\x21"),
+                )
+                .await
+                .unwrap();
+            }
+            w.video_sample(
+                ts(i * 9_000, 90_000),
+                0,
+                i == 0,
+                Bytes::from_static(b"\x00\x00\x00\x01\x65"),
+            )
+            .await
+            .unwrap();
+            if !audio_first {
+                w.audio_sample(
+                    ts(6_000 + i * 1_024, 12_000),
+                    0,
+                    Bytes::from_static(b"\x21"),
+                )
+                .await
+                .unwrap();
+            }
+        }
+        w.finish().await.unwrap();
+
+        let top = boxes(&out);
+        let moov = boxes(find(&top, b"moov"));
+        let traks: Vec<_> = moov.iter().filter(|(f, _)| *f == b"trak").collect();
+        assert_eq!(traks.len(), 2);
+        let handlers: Vec<_> = traks
+            .iter()
+            .map(|(_, trak)| {
+                let mdia = boxes(find(&boxes(trak), b"mdia"));
+                let hdlr = find(&mdia, b"hdlr");
+                hdlr[8..12].to_vec()
+            })
+            .collect();
+        assert_eq!(handlers, [&b"vide"[..], b"soun"]);
+
+        // Only the audio track is delayed, by 0.5 seconds in the movie timescale.
+        assert!(boxes(traks[0].1).iter().all(|(f, _)| *f != b"edts"));
+        let elst = find(&boxes(find(&boxes(traks[1].1), b"edts")), b"elst");
+        assert_eq!(&elst[8..16], &45_000u64.to_be_bytes()[..]);
+        assert_eq!(&elst[16..24], &(-1i64).to_be_bytes()[..]);
+    }
+
+    /// Records from a mock server sending ten frames with `--frames 3`, and
+    /// checks exactly three make it into the `.mp4`.
+    #[tokio::test]
+    async fn frame_limit() {
+        use structopt::StructOpt;
+        let packets = (0..10u16)
+            .map(|i| {
+                let mut p = vec![0x80, 0xe0]; // version 2; marker; payload type 96.
+                p.extend_from_slice(&i.to_be_bytes());
+                p.extend_from_slice(&(u32::from(i) * 3_000).to_be_bytes()); // timestamp
+                p.extend_from_slice(b"\x00\x00\x00\x01"); // ssrc
+                p.extend_from_slice(b"\x65\x88\x84\x00"); // IDR slice
+                p
+            })
+            .collect();
+        let (url, server) =
+            crate::mock::start(crate::mock::reolink_sdp().to_owned(), packets).await;
+        let out =
+            std::env::temp_dir().join(format!("retina-frame-limit-{}.mp4", std::process::id()));
+        let opts = super::Opts::from_iter_safe(vec![
+            "mp4",
+            "--url",
+            url.as_str(),
+            "--no-audio",
+            "--frames",
+            "3",
+            out.to_str().unwrap(),
+        ])
+        .unwrap();
+        super::run(opts).await.unwrap();
+        server.await.unwrap();
+        let data = std::fs::read(&out).unwrap();
+        std::fs::remove_file(&out).unwrap();
+
+        let mut b = find(&boxes(&data), b"moov");
+        for &fourcc in &[&b"trak"[..], b"mdia", b"minf", b"stbl"] {
+            b = find(&boxes(b), fourcc);
+        }
+        let stsz = find(&boxes(b), b"stsz");
+        assert_eq!(
+            u32::from_be_bytes([stsz[8], stsz[9], stsz[10], stsz[11]]),
+            3
+        );
+    }
+
+    #[tokio::test]
+    async fn fragmented() {
+        let params = video_params();
+        let mut out = Vec::new();
+        let mut w = super::Mp4Writer::new(
+            Some(Box::new(params)),
+            None,
+            false,
+            true,
+            std::io::Cursor::new(&mut out),
+        )
+        .await
+        .unwrap();
+
+        // Seven frames, with key frames at 0, 3, and 6.
+        for i in 0..7 {
+            let ts = retina::Timestamp::new(i * 3000, NonZeroU32::new(90_000).unwrap(), 0).unwrap();
+            w.video_sample(
+                ts,
+                0,
+                i % 3 == 0,
+                Bytes::from_static(b"\x00\x00\x00\x01\x65"),
+            )
+            .await
+            .unwrap();
+        }
+
+        // If interrupted now, the file has the first two complete fragments.
+        let (fourccs, samples) = summarize(&w.inner.get_ref()[..]);
+        assert_eq!(
+            fourccs,
+            [&b"ftyp"[..], b"moov", b"moof", b"mdat", b"moof", b"mdat"]
+        );
+        assert_eq!(samples, [3, 3]);
+
+        // Finishing flushes the final key frame as a third fragment.
+        w.finish().await.unwrap();
+        let (fourccs, samples) = summarize(&out);
+        assert_eq!(fourccs.len(), 8);
+        assert_eq!(samples, [3, 3, 1]);
+        let moov = boxes(find(&boxes(&out), b"moov"));
+        assert!(moov.iter().any(|(f, _)| *f == b"mvex"));
+    }
+}
@@ -37,6 +37,8 @@ fuzz_target!(|data: &[u8]| {
             sequence_number,
             loss: u16::from(loss),
             mark,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: data.split_off(usize::from(len)),
         };
         //println!("pkt: {:#?}", pkt);
@@ -0,0 +1,282 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Blocking facade over the async client, for simple tools and FFI consumers
+//! which don't want to manage a tokio runtime.
+//!
+//! Each session owns a private single-threaded runtime which only runs while
+//! a call is blocked. The underlying session's keepalives and UDP reads thus
+//! happen only within [`Demuxed::next_item`]; callers should call it promptly.
+//!
+//! Dropping a [`Session`] or [`Demuxed`] runs the runtime for up to
+//! [`super::TEARDOWN_TIMEOUT`] so that any `TEARDOWN` called for by
+//! [`SessionOptions::teardown`] can complete before the runtime shuts down.
+//!
+//! ```no_run
+//! # fn main() -> Result<(), retina::Error> {
+//! use retina::client::{blocking, PlayOptions, SessionOptions};
+//! let url = url::Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+//! let mut session = blocking::Session::describe(url, SessionOptions::default())?;
+//! session.setup(0)?;
+//! let mut demuxed = session.play(PlayOptions::default())?;
+//! while let Some(item) = demuxed.next_item() {
+//!     println!("{:?}", item?);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use std::sync::Arc;
+
+use futures::StreamExt;
+
+use super::{Described, PlayOptions, SessionGroup, SessionOptions, Stream, TEARDOWN_TIMEOUT};
+use crate::{codec::CodecItem, Error, ErrorInt};
+
+/// A blocking [`super::Session`] in state [`Described`].
+pub struct Session {
+    /// Always `Some` until consumed by [`Session::play`]. Even then, a failed
+    /// `PLAY` may have left a `TEARDOWN` for drop to wait on.
+    inner: Option<super::Session<Described>>,
+
+    /// Shared with the [`Demuxed`] once playing.
+    rt: Arc<tokio::runtime::Runtime>,
+
+    /// The group tracking this session's `TEARDOWN` on drop.
+    group: Arc<SessionGroup>,
+}
+
+/// A blocking [`super::Demuxed`], returned by [`Session::play`].
+pub struct Demuxed {
+    /// Always `Some` except while being dropped.
+    inner: Option<super::Demuxed>,
+    rt: Arc<tokio::runtime::Runtime>,
+    group: Arc<SessionGroup>,
+}
+
+impl Session {
+    /// Creates a runtime and sends a `DESCRIBE`; see [`super::Session::describe`].
+    pub fn describe(url: url::Url, mut options: SessionOptions) -> Result<Self, Error> {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| wrap!(ErrorInt::Internal(Box::new(e))))?;
+        let group = options
+            .session_group
+            .get_or_insert_with(|| Arc::new(SessionGroup::default()))
+            .clone();
+        let inner = rt.block_on(super::Session::describe(url, options))?;
+        Ok(Session {
+            inner: Some(inner),
+            rt: Arc::new(rt),
+            group,
+        })
+    }
+
+    /// Returns the streams described by the `DESCRIBE` response; see
+    /// [`super::Session::streams`].
+    pub fn streams(&self) -> &[Stream] {
+        self.inner.as_ref().unwrap().streams()
+    }
+
+    /// Sends a `SETUP` for the given stream; see [`super::Session::setup`].
+    pub fn setup(&mut self, stream_i: usize) -> Result<(), Error> {
+        let inner = self.inner.as_mut().unwrap();
+        self.rt.block_on(inner.setup(stream_i))
+    }
+
+    /// Sends a `PLAY` and prepares to demux; see [`super::Session::play`] and
+    /// [`super::Session::demuxed`].
+    pub fn play(mut self, options: PlayOptions) -> Result<Demuxed, Error> {
+        let inner = self.inner.take().unwrap();
+        let inner = self.rt.block_on(inner.play(options))?.demuxed()?;
+        Ok(Demuxed {
+            inner: Some(inner),
+            rt: self.rt.clone(),
+            group: self.group.clone(),
+        })
+    }
+}
+
+impl Drop for Session {
+    fn drop(&mut self) {
+        teardown_on_drop(&self.rt, &self.group, self.inner.take());
+    }
+}
+
+impl Demuxed {
+    /// Blocks until the next item is available, returning `None` at the end
+    /// of the session.
+    pub fn next_item(&mut self) -> Option<Result<CodecItem, Error>> {
+        let inner = self.inner.as_mut().unwrap();
+        self.rt.block_on(inner.next())
+    }
+
+    /// Sends a `TEARDOWN` and waits for its response; see [`super::Demuxed::teardown`].
+    pub fn teardown(mut self) -> Result<(), Error> {
+        let inner = self.inner.as_mut().unwrap();
+        self.rt.block_on(inner.teardown())
+    }
+}
+
+impl Drop for Demuxed {
+    fn drop(&mut self) {
+        teardown_on_drop(&self.rt, &self.group, self.inner.take());
+    }
+}
+
+/// Drops `inner` within `rt`, then keeps the runtime going for up to
+/// [`TEARDOWN_TIMEOUT`] while any `TEARDOWN` it started completes.
+///
+/// Otherwise shutting down the runtime would cancel the background
+/// `TEARDOWN` task before it sends anything.
+fn teardown_on_drop<T>(rt: &tokio::runtime::Runtime, group: &SessionGroup, inner: Option<T>) {
+    rt.block_on(async {
+        drop(inner);
+
+        // Let a queued demuxer's producer task notice the consumer is gone
+        // and drop its session.
+        tokio::task::yield_now().await;
+        if let Err(e) = tokio::time::timeout(TEARDOWN_TIMEOUT, group.await_teardown())
+            .await
+            .unwrap_or_else(|_| Err(wrap!(ErrorInt::Timeout)))
+        {
+            log::debug!("TEARDOWN on drop failed: {}", e);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+
+    use bytes::Bytes;
+    use futures::SinkExt;
+
+    use super::*;
+    use crate::client::tests::{reolink_describe, req_response, UNKNOWN_CODEC};
+    use crate::client::TeardownPolicy;
+    use crate::testutil::response;
+
+    /// Spawns a server thread which answers `DESCRIBE`, `SETUP`, and `PLAY`,
+    /// then hands the connection to `rest`.
+    fn spawn_server<F, Fut>(rest: F) -> (url::Url, std::thread::JoinHandle<()>)
+    where
+        F: FnOnce(crate::tokio::Connection) -> Fut + Send + 'static,
+        Fut: Future<Output = ()>,
+    {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .enable_all()
+                .build()
+                .unwrap();
+            rt.block_on(async move {
+                listener.set_nonblocking(true).unwrap();
+                let listener = tokio::net::TcpListener::from_std(listener).unwrap();
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut server = crate::tokio::Connection::from_stream(stream).unwrap();
+
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Describe,
+                    response(&reolink_describe(UNKNOWN_CODEC)),
+                )
+                .await;
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Setup,
+                    response(include_bytes!("testdata/reolink_setup.txt")),
+                )
+                .await;
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Play,
+                    response(include_bytes!("testdata/reolink_play.txt")),
+                )
+                .await;
+                rest(server).await;
+            });
+        });
+        let url = url::Url::parse(&format!("rtsp://{}/h264Preview_01_main", addr)).unwrap();
+        (url, server)
+    }
+
+    fn play(url: url::Url, options: SessionOptions) -> Demuxed {
+        let mut session = Session::describe(url, options.allow_unknown_codecs(true)).unwrap();
+        assert_eq!(session.streams().len(), 2);
+        session.setup(0).unwrap();
+        session.play(PlayOptions::default()).unwrap()
+    }
+
+    #[test]
+    fn record() {
+        let (url, server) = spawn_server(|mut server| async move {
+            for i in 0..3u16 {
+                let mut pkt =
+                    b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world".to_vec();
+                pkt[2..4].copy_from_slice(&(0x41d4 + i).to_be_bytes());
+                server
+                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                        0,
+                        Bytes::from(pkt),
+                    )))
+                    .await
+                    .unwrap();
+            }
+        });
+        let mut demuxed = play(url, SessionOptions::default());
+        for i in 0..3 {
+            match demuxed.next_item() {
+                Some(Ok(CodecItem::RawRtp(p))) => {
+                    assert_eq!(p.sequence_number, 0x41d4 + i);
+                    assert_eq!(&p.payload[..], b"hello world");
+                }
+                o => panic!("unexpected item: {:#?}", o),
+            }
+        }
+        server.join().unwrap();
+
+        // The server has hung up.
+        assert!(!matches!(demuxed.next_item(), Some(Ok(_))));
+    }
+
+    #[test]
+    fn teardown() {
+        let (url, server) = spawn_server(|mut server| async move {
+            let req = req_response(
+                &mut server,
+                rtsp_types::Method::Teardown,
+                response(b"RTSP/1.0 200 OK\r\n\r\n"),
+            )
+            .await;
+            assert_eq!(
+                req.header(&rtsp_types::headers::SESSION).unwrap().as_str(),
+                "F8F8E425"
+            );
+        });
+        play(url, SessionOptions::default()).teardown().unwrap();
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn teardown_on_drop() {
+        let (url, server) = spawn_server(|mut server| async move {
+            req_response(
+                &mut server,
+                rtsp_types::Method::Teardown,
+                response(b"RTSP/1.0 200 OK\r\n\r\n"),
+            )
+            .await;
+        });
+
+        // Without waiting in drop, shutting down the runtime would cancel the
+        // `TEARDOWN`, and the server would see the connection close instead.
+        drop(play(
+            url,
+            SessionOptions::default().teardown(TeardownPolicy::Always),
+        ));
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,214 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Packet capture of received RTP/RTCP; see [`super::SessionOptions::capture_to`].
+//!
+//! Writes the classic [pcap](https://wiki.wireshark.org/Development/LibpcapFileFormat)
+//! format with `LINKTYPE_RAW`, so each record is an IP packet. Each RTP/RTCP
+//! packet is wrapped in synthetic IP and UDP headers derived from its
+//! [`crate::PacketContext`]. Interleaved (TCP) packets use the RTSP
+//! connection's addresses; they're framed as UDP rather than TCP so that each
+//! record holds exactly one RTP/RTCP packet.
+
+use std::convert::TryFrom;
+use std::io::Write;
+use std::net::{IpAddr, SocketAddr};
+
+use crate::{ConnectionContext, PacketContext, PacketContextInner, WallTime};
+
+/// Raw IP, as in <https://www.tcpdump.org/linktypes.html>.
+const LINKTYPE_RAW: u32 = 101;
+
+const IPPROTO_UDP: u8 = 17;
+
+/// How often buffered records are flushed to the file. The remainder is
+/// flushed when the capture is dropped.
+const FLUSH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+pub(super) struct Capture {
+    out: std::io::BufWriter<std::fs::File>,
+    last_flush: std::time::Instant,
+}
+
+impl Capture {
+    /// Creates `path` and writes the pcap global header.
+    pub(super) fn create(path: &std::path::Path) -> Result<Self, std::io::Error> {
+        let mut out = std::io::BufWriter::new(std::fs::File::create(path)?);
+        out.write_all(&0xa1b2_c3d4u32.to_le_bytes())?; // magic (microsecond resolution)
+        out.write_all(&2u16.to_le_bytes())?; // version_major
+        out.write_all(&4u16.to_le_bytes())?; // version_minor
+        out.write_all(&0i32.to_le_bytes())?; // thiszone
+        out.write_all(&0u32.to_le_bytes())?; // sigfigs
+        out.write_all(&u32::from(u16::MAX).to_le_bytes())?; // snaplen
+        out.write_all(&LINKTYPE_RAW.to_le_bytes())?;
+        Ok(Capture {
+            out,
+            last_flush: std::time::Instant::now(),
+        })
+    }
+
+    /// Writes a record for a received RTP or RTCP packet.
+    ///
+    /// Packets too large to wrap in an IP packet are skipped.
+    pub(super) fn write(
+        &mut self,
+        conn_ctx: &ConnectionContext,
+        pkt_ctx: &PacketContext,
+        data: &[u8],
+    ) -> Result<(), std::io::Error> {
+        let (src, dst, received) = match pkt_ctx.0 {
+            PacketContextInner::Udp {
+                local_addr,
+                peer_addr,
+                received_wall,
+                ..
+            } => (peer_addr, local_addr, received_wall),
+            PacketContextInner::Tcp { msg_ctx, .. } => (
+                conn_ctx.peer_addr,
+                conn_ctx.local_addr,
+                msg_ctx.received_wall,
+            ),
+            PacketContextInner::Dummy => (conn_ctx.peer_addr, conn_ctx.local_addr, WallTime::now()),
+        };
+        let pkt = match ip_udp(src, dst, data) {
+            Some(p) => p,
+            None => {
+                log::debug!("Skipping capture of {}-byte packet", data.len());
+                return Ok(());
+            }
+        };
+        let len = u32::try_from(pkt.len()).expect("IP packet len fits in u32");
+        let ts = received.0;
+        self.out.write_all(&(ts.sec as u32).to_le_bytes())?;
+        self.out
+            .write_all(&((ts.nsec / 1_000) as u32).to_le_bytes())?;
+        self.out.write_all(&len.to_le_bytes())?; // incl_len
+        self.out.write_all(&len.to_le_bytes())?; // orig_len
+        self.out.write_all(&pkt)?;
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.out.flush()?;
+            self.last_flush = std::time::Instant::now();
+        }
+        Ok(())
+    }
+}
+
+/// Returns an IP packet with a UDP header from `src` to `dst` and the given payload.
+///
+/// Uses IPv4 if both addresses are IPv4, else IPv6 (with v4-mapped addresses
+/// as needed). The UDP checksum is omitted (zero). Returns `None` if the
+/// payload is too large for the IP or UDP length fields.
+fn ip_udp(src: SocketAddr, dst: SocketAddr, payload: &[u8]) -> Option<Vec<u8>> {
+    let udp_len = u16::try_from(8 + payload.len()).ok()?;
+    let mut pkt = Vec::with_capacity(40 + usize::from(udp_len));
+    match (src.ip(), dst.ip()) {
+        (IpAddr::V4(s), IpAddr::V4(d)) => {
+            let total_len = udp_len.checked_add(20)?;
+            pkt.extend_from_slice(&[0x45, 0]); // version, IHL; DSCP, ECN
+            pkt.extend_from_slice(&total_len.to_be_bytes());
+            pkt.extend_from_slice(&[0, 0, 0x40, 0]); // identification; flags (DF), fragment offset
+            pkt.extend_from_slice(&[64, IPPROTO_UDP, 0, 0]); // TTL, protocol, checksum
+            pkt.extend_from_slice(&s.octets());
+            pkt.extend_from_slice(&d.octets());
+            let checksum = ipv4_checksum(&pkt);
+            pkt[10..12].copy_from_slice(&checksum.to_be_bytes());
+        }
+        (s, d) => {
+            let to_v6 = |a: IpAddr| match a {
+                IpAddr::V4(a) => a.to_ipv6_mapped(),
+                IpAddr::V6(a) => a,
+            };
+            pkt.extend_from_slice(&[0x60, 0, 0, 0]); // version, traffic class, flow label
+            pkt.extend_from_slice(&udp_len.to_be_bytes()); // payload length
+            pkt.extend_from_slice(&[IPPROTO_UDP, 64]); // next header, hop limit
+            pkt.extend_from_slice(&to_v6(s).octets());
+            pkt.extend_from_slice(&to_v6(d).octets());
+        }
+    }
+    pkt.extend_from_slice(&src.port().to_be_bytes());
+    pkt.extend_from_slice(&dst.port().to_be_bytes());
+    pkt.extend_from_slice(&udp_len.to_be_bytes());
+    pkt.extend_from_slice(&[0, 0]); // checksum
+    pkt.extend_from_slice(payload);
+    Some(pkt)
+}
+
+/// Returns the IPv4 header checksum, as in RFC 791.
+fn ipv4_checksum(header: &[u8]) -> u16 {
+    let mut sum: u32 = header
+        .chunks(2)
+        .map(|c| u32::from(u16::from_be_bytes([c[0], c[1]])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use super::*;
+
+    #[test]
+    fn capture() {
+        let path = std::env::temp_dir().join(format!("retina-capture-{}.pcap", std::process::id()));
+        let conn_ctx = ConnectionContext {
+            local_addr: "192.168.5.1:52000".parse().unwrap(),
+            peer_addr: "192.168.5.206:554".parse().unwrap(),
+            ..ConnectionContext::dummy()
+        };
+        let udp_ctx = PacketContext(PacketContextInner::Udp {
+            local_addr: "192.168.5.1:50000".parse().unwrap(),
+            peer_addr: "192.168.5.206:6970".parse().unwrap(),
+            received_wall: WallTime::now(),
+            received: std::time::Instant::now(),
+        });
+        let tcp_ctx = PacketContext(PacketContextInner::Tcp {
+            msg_ctx: crate::RtspMessageContext::dummy(),
+            channel_id: 0,
+        });
+        let mut c = Capture::create(&path).unwrap();
+        c.write(&conn_ctx, &udp_ctx, b"rtp 1").unwrap();
+        c.write(&conn_ctx, &tcp_ctx, b"rtp 2").unwrap();
+        c.write(&conn_ctx, &udp_ctx, b"rtcp").unwrap();
+
+        // An interleaved packet too large for IPv4 is skipped.
+        c.write(&conn_ctx, &tcp_ctx, &[0; 65_510][..]).unwrap();
+        drop(c);
+
+        let f = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let u32_at = |i: usize| u32::from_le_bytes(f[i..i + 4].try_into().unwrap());
+        assert_eq!(u32_at(0), 0xa1b2_c3d4);
+        assert_eq!(&f[4..8], &[2, 0, 4, 0]);
+        assert_eq!(u32_at(20), LINKTYPE_RAW);
+        let mut pos = 24;
+        let mut records = Vec::new();
+        while pos < f.len() {
+            let incl_len = u32_at(pos + 8) as usize;
+            assert_eq!(u32_at(pos + 12) as usize, incl_len);
+            records.push(&f[pos + 16..pos + 16 + incl_len]);
+            pos += 16 + incl_len;
+        }
+        assert_eq!(pos, f.len());
+        assert_eq!(records.len(), 3);
+
+        // IPv4 + UDP, from the server to the client.
+        let r = records[0];
+        assert_eq!(r[0], 0x45);
+        assert_eq!(ipv4_checksum(&r[..20]), 0);
+        assert_eq!(&r[12..16], &[192, 168, 5, 206]);
+        assert_eq!(&r[16..20], &[192, 168, 5, 1]);
+        assert_eq!(u16::from_be_bytes([r[20], r[21]]), 6970);
+        assert_eq!(u16::from_be_bytes([r[22], r[23]]), 50000);
+        assert_eq!(&r[28..], b"rtp 1");
+
+        // Interleaved data uses the RTSP connection's addresses.
+        let r = records[1];
+        assert_eq!(u16::from_be_bytes([r[20], r[21]]), 554);
+        assert_eq!(u16::from_be_bytes([r[22], r[23]]), 52000);
+        assert_eq!(&r[28..], b"rtp 2");
+    }
+}
@@ -90,6 +90,20 @@ impl ChannelMappings {
             },
         })
     }
+
+    /// Returns the RTP channel id assigned to the given stream, if any.
+    pub fn rtp_channel(&self, stream_i: usize) -> Option<u8> {
+        let i = self
+            .0
+            .iter()
+            .position(|c| matches!(c, Some(c) if usize::from(c.get() - 1) == stream_i))?;
+        Some((i as u8) << 1)
+    }
+
+    /// Returns the RTCP channel id assigned to the given stream, if any.
+    pub fn rtcp_channel(&self, stream_i: usize) -> Option<u8> {
+        self.rtp_channel(stream_i).map(|c| c + 1)
+    }
 }
 
 impl std::fmt::Debug for ChannelMappings {
@@ -128,6 +142,9 @@ mod tests {
                 channel_type: ChannelType::Rtcp,
             })
         );
+        assert_eq!(mappings.rtp_channel(42), Some(0));
+        assert_eq!(mappings.rtcp_channel(42), Some(1));
+        assert_eq!(mappings.rtcp_channel(43), None);
         assert_eq!(mappings.next_unassigned().unwrap(), 2);
         mappings.assign(9, 26).unwrap_err();
         mappings.assign(8, 26).unwrap();
@@ -1,6 +1,7 @@
 // Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
 // SPDX-License-Identifier: MIT OR Apache-2.0
 
+use std::convert::TryFrom;
 use std::mem::MaybeUninit;
 use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::num::NonZeroU32;
@@ -11,7 +12,7 @@ use std::{fmt::Debug, num::NonZeroU16, pin::Pin};
 
 use self::channel_mapping::*;
 pub use self::timeline::Timeline;
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 use futures::{ready, Future, SinkExt, StreamExt};
 use log::{debug, trace, warn};
 use pin_project::pin_project;
@@ -24,15 +25,47 @@ use crate::client::parse::SessionHeader;
 use crate::codec::CodecItem;
 use crate::{Error, ErrorInt, RtspMessageContext};
 
+#[cfg(feature = "blocking")]
+pub mod blocking;
+mod capture;
 mod channel_mapping;
 mod parse;
+mod queue;
 pub mod rtp;
+#[cfg(feature = "srtp")]
+mod srtp;
 mod teardown;
 mod timeline;
 
-/// Duration between keepalive RTSP requests during [Playing] state.
+/// Duration between keepalive RTSP requests during [Playing] state when the
+/// server's `Session` header doesn't specify a timeout.
+///
+/// When the server does specify a timeout, keepalives are sent at half that
+/// interval, however short, so the session never expires between them.
 pub const KEEPALIVE_DURATION: std::time::Duration = std::time::Duration::from_secs(30);
 
+/// Minimum duration between keepalive RTSP requests, which applies only when
+/// the server advertises a session timeout below twice this (such as
+/// `timeout=0`).
+pub const MIN_KEEPALIVE_DURATION: std::time::Duration = std::time::Duration::from_millis(500);
+
+/// Maximum time [`Session::teardown`] waits for the `TEARDOWN` response.
+pub const TEARDOWN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Default time to wait for the first RTP packet via UDP before falling back
+/// to TCP with [`TransportPolicy::UdpThenTcp`].
+pub const DEFAULT_UDP_FALLBACK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The `Require` header value requesting the ONVIF audio backchannel; see
+/// [`SessionOptions::onvif_backchannel`].
+const ONVIF_BACKCHANNEL: &str = "www.onvif.org/ver20/backchannel";
+
+/// Default value of [`SessionOptions::user_agent`].
+pub const DEFAULT_USER_AGENT: &str = concat!("retina/", env!("CARGO_PKG_VERSION"));
+
+/// Default value of [`SessionOptions::max_redirects`].
+pub const DEFAULT_MAX_REDIRECTS: u8 = 5;
+
 /// A stale RTP session.
 struct StaleSession {
     seqnum: u64,
@@ -272,6 +305,168 @@ impl std::str::FromStr for TeardownPolicy {
     }
 }
 
+/// How to send a `TEARDOWN` when a [`Session`] is dropped.
+///
+/// This applies only when [`TeardownPolicy`] calls for a `TEARDOWN` and
+/// [`Session::teardown`] wasn't already called.
+///
+/// Specify via [`SessionOptions::teardown_on_drop`].
+#[derive(Copy, Clone, Debug)]
+pub enum TeardownOnDrop {
+    /// Never send a `TEARDOWN` from drop. Drop neither blocks nor spawns a
+    /// task; the server will expire the session on its own timeout.
+    None,
+
+    /// Default: spawn a background task on the runtime the session was
+    /// created from which retries the `TEARDOWN` until the session expires.
+    /// Drop returns immediately. The task is lost if the runtime shuts down
+    /// first; see [`SessionGroup::await_teardown`] for waiting on it.
+    SpawnBestEffort,
+
+    /// As with `SpawnBestEffort`, but also block the dropping thread for up
+    /// to [`TEARDOWN_TIMEOUT`] until the `TEARDOWN` completes.
+    ///
+    /// Within a multi-threaded runtime, the wait uses
+    /// [`tokio::task::block_in_place`] so other tasks on the dropping worker
+    /// thread keep running. Blocking the only thread of a current-thread
+    /// runtime would stall the `TEARDOWN` itself, so when dropped there this
+    /// behaves as `SpawnBestEffort`.
+    BlockingInDrop,
+}
+
+impl Default for TeardownOnDrop {
+    fn default() -> Self {
+        TeardownOnDrop::SpawnBestEffort
+    }
+}
+
+impl std::fmt::Display for TeardownOnDrop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            TeardownOnDrop::None => "none",
+            TeardownOnDrop::SpawnBestEffort => "spawn-best-effort",
+            TeardownOnDrop::BlockingInDrop => "blocking-in-drop",
+        })
+    }
+}
+
+impl std::str::FromStr for TeardownOnDrop {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "none" => TeardownOnDrop::None,
+            "spawn-best-effort" => TeardownOnDrop::SpawnBestEffort,
+            "blocking-in-drop" => TeardownOnDrop::BlockingInDrop,
+            _ => bail!(ErrorInt::InvalidArgument(format!(
+                "bad TeardownOnDrop {}; expected none, spawn-best-effort, or blocking-in-drop",
+                s
+            ))),
+        })
+    }
+}
+
+/// Policy for handling RTP packet loss while playing.
+///
+/// Loss is detected via gaps in sequence numbers; see [`rtp::Packet::loss`].
+///
+/// Specify via [`SessionOptions::loss_policy`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LossPolicy {
+    /// Default: log a warning and continue. The loss is still reported via
+    /// [`rtp::Packet::loss`] (and [`crate::codec::CodecItem::Loss`] when
+    /// demuxing); frames following it may be corrupt until the next key frame.
+    Tolerate,
+
+    /// Fail with an error on the first loss. This suits captures which must
+    /// be complete, at the cost of ending the session on any network hiccup.
+    Abort,
+
+    /// As with `Tolerate`, but also ask the server for a key frame on video
+    /// streams, as via [`Session::request_keyframe`], so the picture
+    /// recovers quickly. The request is sent without waiting; if it can't be
+    /// sent immediately, it's skipped with a warning.
+    RequestKeyframe,
+}
+
+impl Default for LossPolicy {
+    fn default() -> Self {
+        LossPolicy::Tolerate
+    }
+}
+
+impl std::fmt::Display for LossPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            LossPolicy::Tolerate => "tolerate",
+            LossPolicy::Abort => "abort",
+            LossPolicy::RequestKeyframe => "request-keyframe",
+        })
+    }
+}
+
+impl std::str::FromStr for LossPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "tolerate" => LossPolicy::Tolerate,
+            "abort" => LossPolicy::Abort,
+            "request-keyframe" => LossPolicy::RequestKeyframe,
+            _ => bail!(ErrorInt::InvalidArgument(format!(
+                "bad LossPolicy {}; expected tolerate, abort, or request-keyframe",
+                s
+            ))),
+        })
+    }
+}
+
+/// Policy for RTP packets whose payload type doesn't match the stream's
+/// negotiated payload type (or its retransmission payload type).
+///
+/// Such packets may come from a misrouted SSRC or a server bug; feeding them
+/// to the stream's depacketizer would produce garbage.
+///
+/// Specify via [`SessionOptions::payload_type_policy`].
+#[derive(Copy, Clone, Debug)]
+pub enum PayloadTypePolicy {
+    /// Default: skip the packet, logging a warning.
+    Skip,
+
+    /// Fail the session with an error describing the packet.
+    Error,
+}
+
+impl Default for PayloadTypePolicy {
+    fn default() -> Self {
+        PayloadTypePolicy::Skip
+    }
+}
+
+impl std::fmt::Display for PayloadTypePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            PayloadTypePolicy::Skip => "skip",
+            PayloadTypePolicy::Error => "error",
+        })
+    }
+}
+
+impl std::str::FromStr for PayloadTypePolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "skip" => PayloadTypePolicy::Skip,
+            "error" => PayloadTypePolicy::Error,
+            _ => bail!(ErrorInt::InvalidArgument(format!(
+                "bad PayloadTypePolicy {}; expected skip or error",
+                s
+            ))),
+        })
+    }
+}
+
 /// Policy for handling the `rtptime` parameter normally seem in the `RTP-Info` header.
 /// This parameter is used to map each stream's RTP timestamp to NPT ("normal play time"),
 /// allowing multiple streams to be played in sync.
@@ -331,22 +526,216 @@ impl std::str::FromStr for InitialTimestampPolicy {
     }
 }
 
+/// Policy for buffering items between the network and a [`Demuxed`] stream.
+///
+/// Specify via [`SessionOptions::queue_policy`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QueuePolicy {
+    /// No read-ahead: packets are read only as the caller polls for items.
+    ///
+    /// A slow consumer pushes back on the server via TCP flow control, or (with
+    /// UDP) the kernel drops packets once its receive buffer fills. This is the
+    /// default.
+    Direct,
+
+    /// A background task reads ahead into a queue of up to the given number of
+    /// items, pausing when it's full.
+    ///
+    /// This absorbs bursts and consumer hiccups. Like `Direct`, it never drops
+    /// items itself, so it preserves the relative timing of audio and video
+    /// (A/V sync); a consistently slow consumer just falls further behind.
+    Block(usize),
+
+    /// A background task reads ahead into a queue of up to the given number of
+    /// items, dropping the oldest when it's full.
+    ///
+    /// This bounds latency at the expense of completeness. Each dropped item is
+    /// reported as (at least one packet of) `loss` on the next item delivered
+    /// for the same stream. Streams are dropped independently, so this does
+    /// *not* preserve A/V sync, and video is undecodable until the next random
+    /// access point after a drop.
+    DropOldest(usize),
+}
+
+impl Default for QueuePolicy {
+    fn default() -> Self {
+        QueuePolicy::Direct
+    }
+}
+
+impl std::fmt::Display for QueuePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QueuePolicy::Direct => f.pad("direct"),
+            QueuePolicy::Block(n) => f.pad(&format!("block:{}", n)),
+            QueuePolicy::DropOldest(n) => f.pad(&format!("drop-oldest:{}", n)),
+        }
+    }
+}
+
+impl std::str::FromStr for QueuePolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bad = || {
+            wrap!(ErrorInt::InvalidArgument(format!(
+                "bad QueuePolicy {}; expected direct, block:<N>, or drop-oldest:<N>",
+                s
+            )))
+        };
+        if s == "direct" {
+            return Ok(QueuePolicy::Direct);
+        }
+        let (policy, n) = s.split_once(':').ok_or_else(bad)?;
+        let n = n.parse().map_err(|_| bad())?;
+        match policy {
+            "block" => Ok(QueuePolicy::Block(n)),
+            "drop-oldest" => Ok(QueuePolicy::DropOldest(n)),
+            _ => Err(bad()),
+        }
+    }
+}
+
+/// A callback for [`SessionOptions::packet_observer`].
+pub type PacketObserver = Arc<dyn Fn(&rtp::PacketInfo) + Send + Sync>;
+
+/// A callback for [`SessionOptions::transcript`].
+pub type Transcript = Arc<dyn Fn(&RtspMessageContext, &rtsp_types::Message<Bytes>) + Send + Sync>;
+
+/// A callback for [`SessionOptions::resolver`].
+pub type Resolver = Arc<dyn Fn(&str, u16) -> Vec<SocketAddr> + Send + Sync>;
+
 /// Options which must be known right as a session is created.
 ///
 /// Decisions which can be deferred are in [PlayOptions] instead.
-#[derive(Default)]
+#[derive(Clone)]
 pub struct SessionOptions {
     creds: Option<Credentials>,
     user_agent: Option<Box<str>>,
     transport: Transport,
     session_group: Option<Arc<SessionGroup>>,
     teardown: TeardownPolicy,
+    teardown_on_drop: TeardownOnDrop,
+    max_redirects: u8,
+    keepalive_method: KeepaliveMethod,
+    fallback_to_tcp: bool,
+    udp_fallback_timeout: std::time::Duration,
+    setup_streams: Option<Box<[usize]>>,
+    aac_adts: bool,
+    allow_unknown_codecs: bool,
+    max_frame_bytes: usize,
+    response_timeout: Option<std::time::Duration>,
+    data_timeout: Option<std::time::Duration>,
+    queue_policy: QueuePolicy,
+    capture_path: Option<std::path::PathBuf>,
+    reorder_depth: u16,
+    reorder_max_hold: Option<std::time::Duration>,
+    payload_type_policy: PayloadTypePolicy,
+    strict_ssrc: bool,
+    connected_udp: bool,
+    udp_hole_punch: bool,
+    udp_destination: bool,
+    send_rtcp: bool,
+    udp_recv_buffer_bytes: Option<usize>,
+    dscp: Option<u8>,
+    tcp_keepalive: Option<std::time::Duration>,
+    tcp_nodelay: bool,
+    connect_timeout: Option<std::time::Duration>,
+    bind_address: Option<std::net::IpAddr>,
+    onvif_backchannel: bool,
+    packet_observer: Option<PacketObserver>,
+    extra_headers: Vec<(rtsp_types::HeaderName, String)>,
+    accept_dynamic_rate: bool,
+    lenient: bool,
+    transcript: Option<Transcript>,
+    loss_policy: LossPolicy,
+    video_frame_durations: bool,
+    no_depacketization: bool,
+    require_features: Vec<String>,
+    supported_features: Vec<String>,
+    resolver: Option<Resolver>,
+    #[cfg(feature = "tls")]
+    tls_config: Option<Arc<rustls::ClientConfig>>,
+    #[cfg(feature = "tls")]
+    danger_accept_invalid_certs: bool,
+    #[cfg(feature = "tls")]
+    tls_server_name: Option<String>,
+}
+
+impl Default for SessionOptions {
+    fn default() -> Self {
+        Self {
+            creds: None,
+            user_agent: Some(DEFAULT_USER_AGENT.into()),
+            transport: Transport::default(),
+            session_group: None,
+            teardown: TeardownPolicy::default(),
+            teardown_on_drop: TeardownOnDrop::default(),
+            max_redirects: DEFAULT_MAX_REDIRECTS,
+            keepalive_method: KeepaliveMethod::default(),
+            fallback_to_tcp: false,
+            udp_fallback_timeout: DEFAULT_UDP_FALLBACK_TIMEOUT,
+            setup_streams: None,
+            aac_adts: false,
+            allow_unknown_codecs: false,
+            max_frame_bytes: crate::codec::DEFAULT_MAX_FRAME_BYTES,
+            response_timeout: None,
+            data_timeout: None,
+            queue_policy: QueuePolicy::default(),
+            capture_path: None,
+            reorder_depth: 0,
+            reorder_max_hold: None,
+            payload_type_policy: PayloadTypePolicy::default(),
+            strict_ssrc: true,
+            connected_udp: true,
+            udp_hole_punch: true,
+            udp_destination: false,
+            send_rtcp: true,
+            udp_recv_buffer_bytes: None,
+            dscp: None,
+            tcp_keepalive: None,
+            tcp_nodelay: true,
+            connect_timeout: None,
+            bind_address: None,
+            onvif_backchannel: false,
+            packet_observer: None,
+            extra_headers: Vec::new(),
+            accept_dynamic_rate: false,
+            lenient: false,
+            transcript: None,
+            loss_policy: LossPolicy::default(),
+            video_frame_durations: false,
+            no_depacketization: false,
+            require_features: Vec::new(),
+            supported_features: Vec::new(),
+            resolver: None,
+            #[cfg(feature = "tls")]
+            tls_config: None,
+            #[cfg(feature = "tls")]
+            danger_accept_invalid_certs: false,
+            #[cfg(feature = "tls")]
+            tls_server_name: None,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
 pub enum Transport {
     Tcp,
 
+    /// RTSP-over-HTTP tunneling, as described in Apple's [Tunneling RTSP and
+    /// RTP over HTTP](https://web.archive.org/web/20130801175525/https://developer.apple.com/quicktime/icefloe/dispatch028.html).
+    ///
+    /// The server->client direction is carried by the response to an HTTP
+    /// `GET`; the client->server direction is carried base64-encoded within
+    /// the body of an HTTP `POST`. RTP and RTCP packets are interleaved as
+    /// with `Tcp`. This is useful for servers which are only reachable through
+    /// HTTP proxies.
+    ///
+    /// The HTTP connections are made to the URL's port, or 80 if the URL
+    /// doesn't specify one.
+    HttpTunnel,
+
     /// UDP (experimental).
     ///
     /// This support is currently only suitable for a LAN for a couple reasons:
@@ -355,6 +744,17 @@ pub enum Transport {
     ///     servers won't have the correct information to measure packet loss
     ///     and pace packets appropriately.
     Udp,
+
+    /// Multicast UDP (experimental).
+    ///
+    /// The server chooses the multicast group and ports via the `destination`
+    /// and `port` parameters of the `SETUP` response's `Transport` header;
+    /// retina joins the group on each stream's RTP and RTCP ports and leaves
+    /// it on [`Session::teardown`]. The group is joined on the interface of
+    /// [`SessionOptions::bind_address`] if specified, or else the RTSP
+    /// connection's local address. The `ttl` parameter applies to packets sent
+    /// to the group. The same caveats apply as with [`Transport::Udp`].
+    UdpMulticast,
 }
 
 impl Default for Transport {
@@ -367,27 +767,147 @@ impl std::fmt::Display for Transport {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.pad(match self {
             Transport::Tcp => "tcp",
+            Transport::HttpTunnel => "http-tunnel",
             Transport::Udp => "udp",
+            Transport::UdpMulticast => "udp-multicast",
         })
     }
 }
 
+impl Transport {
+    /// Returns true if RTP and RTCP packets are interleaved within the RTSP
+    /// connection.
+    fn is_interleaved(self) -> bool {
+        !matches!(self, Transport::Udp | Transport::UdpMulticast)
+    }
+}
+
 impl std::str::FromStr for Transport {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         Ok(match s {
             "tcp" => Transport::Tcp,
+            "http-tunnel" => Transport::HttpTunnel,
             "udp" => Transport::Udp,
+            "udp-multicast" => Transport::UdpMulticast,
             _ => bail!(ErrorInt::InvalidArgument(format!(
                 "bad Transport {}; \
-                 expected tcp or udp",
+                 expected tcp, http-tunnel, udp, or udp-multicast",
+                s
+            ))),
+        })
+    }
+}
+
+/// Policy for choosing the [Transport] of each stream.
+///
+/// Specify via [`SessionOptions::transport_policy`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TransportPolicy {
+    /// Uses [`Transport::Udp`] only.
+    UdpOnly,
+
+    /// Uses [`Transport::Tcp`] only. This is the default.
+    TcpOnly,
+
+    /// Tries [`Transport::Udp`] first. If no RTP packet arrives within the
+    /// timeout set via [`SessionOptions::udp_fallback_timeout`] after `PLAY`,
+    /// tears down the session and sets it up again via [`Transport::Tcp`].
+    ///
+    /// This is useful when a firewall or NAT may block incoming UDP packets.
+    UdpThenTcp,
+}
+
+impl Default for TransportPolicy {
+    fn default() -> Self {
+        TransportPolicy::TcpOnly
+    }
+}
+
+impl std::fmt::Display for TransportPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            TransportPolicy::UdpOnly => "udp-only",
+            TransportPolicy::TcpOnly => "tcp-only",
+            TransportPolicy::UdpThenTcp => "udp-then-tcp",
+        })
+    }
+}
+
+impl std::str::FromStr for TransportPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "udp-only" => TransportPolicy::UdpOnly,
+            "tcp-only" => TransportPolicy::TcpOnly,
+            "udp-then-tcp" => TransportPolicy::UdpThenTcp,
+            _ => bail!(ErrorInt::InvalidArgument(format!(
+                "bad TransportPolicy {}; \
+                 expected udp-only, tcp-only, or udp-then-tcp",
+                s
+            ))),
+        })
+    }
+}
+
+/// RTSP method to use for keepalive requests during [Playing] state.
+///
+/// Specify via [`SessionOptions::keepalive_method`].
+#[derive(Copy, Clone, Debug)]
+pub enum KeepaliveMethod {
+    Options,
+    GetParameter,
+
+    /// `SET_PARAMETER` with no body, as recommended in the ONVIF Streaming
+    /// Specification version 21.06 section 5.2.2.2. This is the default.
+    SetParameter,
+}
+
+impl Default for KeepaliveMethod {
+    fn default() -> Self {
+        KeepaliveMethod::SetParameter
+    }
+}
+
+impl std::fmt::Display for KeepaliveMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            KeepaliveMethod::Options => "options",
+            KeepaliveMethod::GetParameter => "get-parameter",
+            KeepaliveMethod::SetParameter => "set-parameter",
+        })
+    }
+}
+
+impl std::str::FromStr for KeepaliveMethod {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s {
+            "options" => KeepaliveMethod::Options,
+            "get-parameter" => KeepaliveMethod::GetParameter,
+            "set-parameter" => KeepaliveMethod::SetParameter,
+            _ => bail!(ErrorInt::InvalidArgument(format!(
+                "bad KeepaliveMethod {}; \
+                 expected options, get-parameter, or set-parameter",
                 s
             ))),
         })
     }
 }
 
+impl From<KeepaliveMethod> for Method {
+    fn from(method: KeepaliveMethod) -> Self {
+        match method {
+            KeepaliveMethod::Options => Method::Options,
+            KeepaliveMethod::GetParameter => Method::GetParameter,
+            KeepaliveMethod::SetParameter => Method::SetParameter,
+        }
+    }
+}
+
 impl SessionOptions {
     /// Use the given credentials when/if the server requests digest authentication.
     pub fn creds(mut self, creds: Option<Credentials>) -> Self {
@@ -395,7 +915,11 @@ impl SessionOptions {
         self
     }
 
-    /// Sends the given user agent string with each request.
+    /// Sends the given user agent string with each request, including
+    /// keepalives and `TEARDOWN`.
+    ///
+    /// Defaults to [`DEFAULT_USER_AGENT`]. An empty string sends no
+    /// `User-Agent` header at all.
     pub fn user_agent(mut self, user_agent: String) -> Self {
         self.user_agent = if user_agent.is_empty() {
             None
@@ -406,8 +930,33 @@ impl SessionOptions {
     }
 
     /// Sets the underlying transport to use.
+    ///
+    /// This overrides any earlier [`SessionOptions::transport_policy`].
     pub fn transport(mut self, transport: Transport) -> Self {
         self.transport = transport;
+        self.fallback_to_tcp = false;
+        self
+    }
+
+    /// Sets the policy for choosing between UDP and TCP transports.
+    ///
+    /// This overrides any earlier [`SessionOptions::transport`]. The transport
+    /// ultimately used is available via [`Session::active_transport`].
+    pub fn transport_policy(mut self, policy: TransportPolicy) -> Self {
+        self.transport = match policy {
+            TransportPolicy::UdpOnly | TransportPolicy::UdpThenTcp => Transport::Udp,
+            TransportPolicy::TcpOnly => Transport::Tcp,
+        };
+        self.fallback_to_tcp = matches!(policy, TransportPolicy::UdpThenTcp);
+        self
+    }
+
+    /// Sets how long [`TransportPolicy::UdpThenTcp`] waits for the first RTP
+    /// packet before falling back to TCP.
+    ///
+    /// Defaults to [`DEFAULT_UDP_FALLBACK_TIMEOUT`].
+    pub fn udp_fallback_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.udp_fallback_timeout = timeout;
         self
     }
 
@@ -420,1603 +969,7315 @@ impl SessionOptions {
         self.teardown = teardown;
         self
     }
-}
-
-/// Options which must be decided at `PLAY` time.
-///
-/// These are mostly adjustments for non-compliant server implementations.
-/// See also [SessionOptions] for options which must be decided earlier.
-#[derive(Default)]
-pub struct PlayOptions {
-    initial_timestamp: InitialTimestampPolicy,
-    ignore_zero_seq: bool,
-    enforce_timestamps_with_max_jump_secs: Option<NonZeroU32>,
-}
 
-impl PlayOptions {
-    pub fn initial_timestamp(self, initial_timestamp: InitialTimestampPolicy) -> Self {
-        Self {
-            initial_timestamp,
-            ..self
-        }
+    /// Sets how a `TEARDOWN` is sent when the session is dropped.
+    ///
+    /// See [`TeardownOnDrop`] for the runtime implications of each choice.
+    pub fn teardown_on_drop(mut self, teardown_on_drop: TeardownOnDrop) -> Self {
+        self.teardown_on_drop = teardown_on_drop;
+        self
     }
 
-    /// If the `RTP-Time` specifies `seq=0`, ignore it. Some cameras set this value then start
-    /// the stream with something dramatically different. (Eg the Hikvision DS-2CD2032-I on its
-    /// metadata stream; the other streams are fine.)
-    pub fn ignore_zero_seq(self, ignore_zero_seq: bool) -> Self {
-        Self {
-            ignore_zero_seq,
-            ..self
-        }
+    /// Sets the maximum number of redirects (`3xx` responses with a
+    /// `Location` header) to follow on `DESCRIBE`.
+    ///
+    /// Defaults to [`DEFAULT_MAX_REDIRECTS`]. `0` disables redirect following,
+    /// so that any redirect is returned as an error. Redirects from `rtsps`
+    /// to another scheme are always errors.
+    pub fn max_redirects(mut self, max_redirects: u8) -> Self {
+        self.max_redirects = max_redirects;
+        self
     }
 
-    /// Enforces that timestamps are non-decreasing and jump forward by no more
-    /// than the given number of seconds.
+    /// Sets the RTSP method used for keepalive requests.
     ///
-    /// By default, no enforcement is done, and computed [crate::Timestamp]
-    /// values will go backward if subsequent 32-bit RTP timestamps differ by
-    /// more than `i32::MAX`.
-    pub fn enforce_timestamps_with_max_jump_secs(self, secs: NonZeroU32) -> Self {
-        Self {
-            enforce_timestamps_with_max_jump_secs: Some(secs),
-            ..self
-        }
+    /// Some servers only extend the session on particular methods, and some
+    /// reject methods they don't implement.
+    pub fn keepalive_method(mut self, keepalive_method: KeepaliveMethod) -> Self {
+        self.keepalive_method = keepalive_method;
+        self
     }
-}
 
-#[derive(Debug)]
-pub struct Presentation {
-    pub streams: Vec<Stream>,
-    base_url: Url,
-    pub control: Url,
-    pub accept_dynamic_rate: bool,
+    /// Restricts which streams (by index within [`Session::streams`]) may be
+    /// set up.
+    ///
+    /// [`Session::setup_all`] sets up exactly these streams, and
+    /// [`Session::setup`] rejects any others. This is useful for subscribing
+    /// to only some of the streams of a presentation (for example, only the
+    /// sub stream of an NVR which offers both main and sub streams) to save
+    /// bandwidth. By default, all streams are allowed.
+    ///
+    /// Other streams get no depacketizer, so their [`Stream::parameters`] are
+    /// `None`.
+    pub fn setup_streams(mut self, streams: &[usize]) -> Self {
+        self.setup_streams = Some(streams.into());
+        self
+    }
 
-    /// Session-level `a:tool` from SDP.
-    tool: Option<Box<str>>,
-}
+    /// Prepends a 7-byte ADTS header to each AAC [`crate::codec::AudioFrame`].
+    ///
+    /// By default, AAC frames contain only the raw access unit, to be
+    /// decoded with the out-of-band [`crate::codec::AudioParameters::extra_data`].
+    /// ADTS framing is convenient for tools which expect a self-describing
+    /// stream, such as ffmpeg's ADTS demuxer.
+    pub fn aac_adts(mut self, aac_adts: bool) -> Self {
+        self.aac_adts = aac_adts;
+        self
+    }
 
-/// Information about a stream offered within a presentation.
-/// Currently if multiple formats are offered, this only describes the first.
-#[derive(Debug)]
-pub struct Stream {
-    /// Media type, as specified in the [IANA SDP parameters media
-    /// registry](https://www.iana.org/assignments/sdp-parameters/sdp-parameters.xhtml#sdp-parameters-1).
-    pub media: String,
+    /// Passes through RTP packets on streams with unsupported codecs.
+    ///
+    /// By default, [`Session::demuxed`] fails if any set-up stream uses a
+    /// codec retina can't depacketize. When this is set, such streams instead
+    /// yield each RTP packet unmodified as [`crate::codec::CodecItem::RawRtp`],
+    /// for the caller to feed to their own depacketizer and decoder.
+    /// [`Stream::parameters`] remains `None` for these streams.
+    pub fn allow_unknown_codecs(mut self, allow_unknown_codecs: bool) -> Self {
+        self.allow_unknown_codecs = allow_unknown_codecs;
+        self
+    }
 
-    /// An encoding name, as specified in the [IANA media type
-    /// registry](https://www.iana.org/assignments/media-types/media-types.xhtml), with
-    /// ASCII characters in lowercase.
+    /// Limits the size of a frame reassembled from multiple RTP packets.
     ///
-    /// Commonly used but not specified in that registry: the ONVIF types
-    /// claimed in the
-    /// [ONVIF Streaming Spec](https://www.onvif.org/specs/stream/ONVIF-Streaming-Spec.pdf):
-    /// *   `vnd.onvif.metadata`
-    /// *   `vnd.onvif.metadata.gzip`,
-    /// *   `vnd.onvif.metadata.exi.onvif`
-    /// *   `vnd.onvif.metadata.exi.ext`
-    pub encoding_name: String,
+    /// Without a limit, a buggy or malicious server could exhaust memory by
+    /// sending an endless series of fragments. When the limit is exceeded, the
+    /// partial frame is discarded, the stream returns an error, and the next
+    /// frame (if the caller continues) reports loss. This applies to H.264,
+    /// H.265, JPEG, and ONVIF metadata; other codecs' frames are inherently
+    /// bounded. The default is 16 MiB.
+    pub fn max_frame_bytes(mut self, max_frame_bytes: usize) -> Self {
+        self.max_frame_bytes = max_frame_bytes;
+        self
+    }
 
-    /// RTP payload type.
-    /// See the [registry](https://www.iana.org/assignments/rtp-parameters/rtp-parameters.xhtml#rtp-parameters-1).
-    /// It's common to use one of the dynamically assigned values, 96–127.
-    pub rtp_payload_type: u8,
+    /// Fails a request if its RTSP response doesn't arrive within `timeout`.
+    ///
+    /// This applies to `DESCRIBE`, `SETUP`, and `PLAY`. (`TEARDOWN` always has
+    /// a short timeout, and keepalives must be answered before the next is
+    /// due.) By default, there's no timeout, so a server which silently
+    /// stops responding can stall the request forever.
+    pub fn response_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.response_timeout = Some(timeout);
+        self
+    }
 
-    /// RTP clock rate, in Hz.
-    pub clock_rate: u32,
+    /// Fails a playing session if no RTP or RTCP data arrives for `timeout`.
+    ///
+    /// The interval is measured from the `PLAY` response and then between
+    /// received packets on any stream. When exceeded, the session's stream
+    /// yields an error, which is useful for noticing a camera that has
+    /// silently stopped sending and reconnecting. By default, there's no
+    /// timeout.
+    pub fn data_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.data_timeout = Some(timeout);
+        self
+    }
 
-    /// Number of audio channels, if applicable (`media` is `audio`) and known.
-    pub channels: Option<NonZeroU16>,
+    /// Sets how [`Session::demuxed`] buffers items; see [`QueuePolicy`].
+    ///
+    /// Policies other than [`QueuePolicy::Direct`] spawn a background task,
+    /// so `describe` must be called from within a tokio runtime.
+    pub fn queue_policy(mut self, queue_policy: QueuePolicy) -> Self {
+        self.queue_policy = queue_policy;
+        self
+    }
 
-    depacketizer: Result<crate::codec::Depacketizer, String>,
+    /// Writes a pcap file of all received RTP and RTCP packets to `path`.
+    ///
+    /// Each packet is wrapped in synthetic IP/UDP headers derived from its
+    /// [`crate::PacketContext`], yielding a self-contained artifact for offline
+    /// analysis or bug reports. Off by default. The file is created during
+    /// [`Session::describe`]; write errors are logged and stop the capture.
+    pub fn capture_to(mut self, path: impl Into<std::path::PathBuf>) -> Self {
+        self.capture_path = Some(path.into());
+        self
+    }
 
-    /// The specified control URL.
-    /// This is needed with multiple streams to send `SETUP` requests and
-    /// interpret the `PLAY` response's `RTP-Info` header.
-    /// [RFC 2326 section C.3](https://datatracker.ietf.org/doc/html/rfc2326#appendix-C.3)
-    /// says the server is allowed to omit it when there is only a single stream.
-    pub control: Option<Url>,
+    /// Reorders UDP packets which arrive out of order, holding back up to
+    /// `depth` packets past a gap in sequence numbers.
+    ///
+    /// When a gap isn't filled by the time a packet `depth` sequence numbers
+    /// later arrives, it's reported as loss and the held packets are
+    /// released. By default, the depth is 0: packets are released as they
+    /// arrive and out-of-order packets are skipped. This has no effect with
+    /// TCP, which never reorders packets.
+    ///
+    /// `depth` must be less than 32,768 (half the sequence number space);
+    /// otherwise connecting fails. See also [`SessionOptions::reorder_max_hold`].
+    pub fn reorder_buffer(mut self, depth: u16) -> Self {
+        self.reorder_depth = depth;
+        self
+    }
 
-    /// The sockets for `Transport::Udp`.
-    sockets: Option<UdpSockets>,
+    /// Limits how long a packet is held past a gap in sequence numbers,
+    /// whether by [`SessionOptions::reorder_buffer`] or while waiting for an
+    /// RFC 4588 retransmission.
+    ///
+    /// Once a held packet has waited this long, the gap before it is reported
+    /// as loss and it's released, even if fewer than the buffer's depth of
+    /// packets have arrived since. This bounds the latency reordering adds
+    /// on low-bitrate streams. The limit is checked as packets arrive.
+    ///
+    /// By default, a reorder buffer holds packets without a time limit, and
+    /// retransmissions are awaited for up to 200 ms.
+    pub fn reorder_max_hold(mut self, max_hold: std::time::Duration) -> Self {
+        self.reorder_max_hold = Some(max_hold);
+        self
+    }
 
-    state: StreamState,
-}
+    /// Sets the policy for RTP packets with an unexpected payload type; see
+    /// [`PayloadTypePolicy`].
+    pub fn payload_type_policy(mut self, policy: PayloadTypePolicy) -> Self {
+        self.payload_type_policy = policy;
+        self
+    }
 
-#[derive(Debug)]
-struct UdpSockets {
-    local_ip: IpAddr,
-    local_rtp_port: u16,
-    remote_ip: IpAddr,
-    remote_rtp_port: u16,
-    rtp_socket: UdpSocket,
-    remote_rtcp_port: u16,
-    rtcp_socket: UdpSocket,
-}
+    /// Locks each stream to a single SSRC, as established by the `SETUP` or
+    /// `PLAY` response or the first RTP packet or RTCP sender report.
+    ///
+    /// When enabled (the default), UDP packets from other SSRCs are dropped,
+    /// which prevents cross-talk from stray sources on shared ports. When
+    /// using TCP, they're instead treated as an error; they likely indicate
+    /// data from a stale session. The drops are counted in
+    /// [`rtp::StreamStats::wrong_ssrc`]. When disabled, packets are accepted
+    /// regardless of SSRC.
+    pub fn strict_ssrc(mut self, strict_ssrc: bool) -> Self {
+        self.strict_ssrc = strict_ssrc;
+        self
+    }
 
-impl Stream {
-    /// Returns the parameters for this stream.
+    /// Connects each stream's UDP sockets to the server's ports from the
+    /// `SETUP` response's `Transport` header.
     ///
-    /// Returns `None` on unknown codecs, bad parameters, or if parameters aren't specified
-    /// via SDP. Some codecs allow parameters to be specified in-band instead.
-    pub fn parameters(&self) -> Option<crate::codec::Parameters> {
-        self.depacketizer.as_ref().ok().and_then(|d| d.parameters())
+    /// When enabled (the default), the operating system drops packets from
+    /// any other address or port, mitigating spoofing and cross-talk. Disable
+    /// for servers which send from an unexpected address or port; then
+    /// packets from any peer are accepted, and each packet's
+    /// [`crate::PacketContext`] records its actual source.
+    pub fn connected_udp(mut self, connected_udp: bool) -> Self {
+        self.connected_udp = connected_udp;
+        self
     }
-}
 
-#[derive(Debug)]
-enum StreamState {
-    /// Uninitialized; no `SETUP` has yet been sent.
-    Uninit,
+    /// After each UDP `SETUP`, sends dummy RTP and RTCP packets to the server's
+    /// ports.
+    ///
+    /// These open connection-tracking state in a firewall on the client's
+    /// side, so that the server's packets are let through. Enabled by
+    /// default. This doesn't help with a NAT which doesn't rewrite the
+    /// `Transport` header's `client_port`.
+    pub fn udp_hole_punch(mut self, udp_hole_punch: bool) -> Self {
+        self.udp_hole_punch = udp_hole_punch;
+        self
+    }
 
-    /// `SETUP` response has been received.
-    Init(StreamStateInit),
+    /// Advertises the UDP sockets' address via the `Transport` header's
+    /// `destination` parameter on `SETUP`, when it differs from the RTSP
+    /// connection's local address (see [`SessionOptions::bind_address`]).
+    ///
+    /// Otherwise the server sends RTP to the address the RTSP connection came
+    /// from. Disabled by default: some servers reject `destination` or treat
+    /// it as a request to stream to a third party, and behind a NAT the
+    /// local address is meaningless to the server.
+    pub fn udp_destination(mut self, udp_destination: bool) -> Self {
+        self.udp_destination = udp_destination;
+        self
+    }
 
-    /// `PLAY` response has been received.
-    Playing {
-        timeline: Timeline,
-        rtp_handler: rtp::InorderParser,
-    },
-}
+    /// Sends RTCP packets to the server. Enabled by default.
+    ///
+    /// When disabled, RTCP sockets are still bound and received RTCP is still
+    /// processed, but nothing is transmitted: the UDP hole punch sends only
+    /// RTP, [`Session::request_keyframe`] fails, and
+    /// [`LossPolicy::RequestKeyframe`] has no effect. This saves a little
+    /// bandwidth on constrained links at the cost of giving the server no
+    /// feedback. Beware some servers tear down sessions which send no RTCP.
+    pub fn send_rtcp(mut self, send_rtcp: bool) -> Self {
+        self.send_rtcp = send_rtcp;
+        self
+    }
 
-#[derive(Copy, Clone, Debug, Default)]
-struct StreamStateInit {
-    /// The RTP synchronization source (SSRC), as defined in
-    /// [RFC 3550](https://tools.ietf.org/html/rfc3550). This is normally
-    /// supplied in the `SETUP` response's `Transport` header. Reolink cameras
-    /// instead supply it in the `PLAY` response's `RTP-Info` header.
-    ssrc: Option<u32>,
+    /// Requests a receive buffer (`SO_RCVBUF`) of the given size for each
+    /// stream's UDP sockets.
+    ///
+    /// High-bitrate streams can overflow the OS's default buffer when the
+    /// session isn't polled promptly, causing bursty loss. The OS may grant a
+    /// different size (eg Linux clamps to `net.core.rmem_max`); the granted
+    /// size is logged. Failures are logged rather than returned. By default,
+    /// the OS's default size is used.
+    pub fn udp_recv_buffer_bytes(mut self, bytes: usize) -> Self {
+        self.udp_recv_buffer_bytes = Some(bytes);
+        self
+    }
 
-    /// The initial RTP sequence number, as specified in the `PLAY` response's
-    /// `RTP-Info` header. This field is only used during the `play()` call
-    /// itself; by the time it returns, the stream will be in state `Playing`.
-    initial_seq: Option<u16>,
+    /// Marks outbound RTSP and UDP RTP/RTCP packets with the given DSCP
+    /// (Differentiated Services Code Point), so that QoS-aware networks can
+    /// prioritize them.
+    ///
+    /// `dscp` must fit in 6 bits, eg 46 for Expedited Forwarding or 34 for
+    /// AF41; larger values fail [`Session::describe`] with an error. It's
+    /// placed in the upper bits of the IPv4 ToS or IPv6 traffic class byte.
+    /// By default, packets aren't marked.
+    pub fn dscp(mut self, dscp: u8) -> Self {
+        self.dscp = Some(dscp);
+        self
+    }
 
-    /// The initial RTP timestamp, as specified in the `PLAY` response's
-    /// `RTP-Info` header. This field is only used during the `play()` call
-    /// itself; by the time it returns, the stream will be in state `Playing`.
-    initial_rtptime: Option<u32>,
-}
+    /// Enables TCP keepalives on the RTSP connection, probing after it's been
+    /// idle for the given time.
+    ///
+    /// This keeps stateful firewalls from silently dropping long-lived
+    /// connections, particularly with interleaved (TCP) transport when the
+    /// RTSP-level keepalives are infrequent. By default, TCP keepalives are
+    /// disabled.
+    pub fn tcp_keepalive(mut self, keepalive: Option<std::time::Duration>) -> Self {
+        self.tcp_keepalive = keepalive;
+        self
+    }
 
-#[derive(Clone)]
-pub struct Credentials {
-    pub username: String,
-    pub password: String,
-}
+    /// Sets `TCP_NODELAY` on the RTSP connection, disabling Nagle's algorithm.
+    ///
+    /// Enabled by default, as RTSP requests are small and latency-sensitive.
+    pub fn tcp_nodelay(mut self, nodelay: bool) -> Self {
+        self.tcp_nodelay = nodelay;
+        self
+    }
 
-/// Marker trait for the state of a [Session].
-/// This doesn't closely match [RFC 2326
-/// A.1](https://tools.ietf.org/html/rfc2326#appendix-A.1). In practice, we've
-/// found that cheap IP cameras are more restrictive than RTSP suggests. Eg, a
-/// `DESCRIBE` changes the connection's state such that another one will fail,
-/// before assigning a session id. Thus [Session] represents something more like
-/// an RTSP connection than an RTSP session.
-#[doc(hidden)]
-pub trait State {}
+    /// Sets a timeout for establishing the RTSP connection, including name
+    /// resolution and (with [`Transport::HttpTunnel`]) the tunnel's setup.
+    ///
+    /// If the host has several addresses, connection attempts are staggered
+    /// and raced as in [RFC 8305](https://datatracker.ietf.org/doc/html/rfc8305)
+    /// ("Happy Eyeballs"), so an unreachable IPv6 or IPv4 address doesn't
+    /// delay connecting via the other. By default, there's no timeout beyond
+    /// the operating system's.
+    pub fn connect_timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
 
-/// Initial state after a `DESCRIBE`; use via `Session<Described>`.
-#[doc(hidden)]
-pub struct Described(());
-impl State for Described {}
+    /// Binds the RTSP connection and any RTP/RTCP UDP sockets to the given
+    /// local address, for multi-homed hosts which should reach the server via a
+    /// particular interface.
+    ///
+    /// Only server addresses of the same family are tried. The address must
+    /// belong to this host; otherwise [`Session::describe`] fails. By default,
+    /// the operating system chooses the RTSP connection's local address, and
+    /// the UDP sockets use the same one.
+    pub fn bind_address(mut self, addr: std::net::IpAddr) -> Self {
+        self.bind_address = Some(addr);
+        self
+    }
 
-enum KeepaliveState {
-    Idle,
-    Flushing(u32),
-    Waiting(u32),
-}
+    /// Requests the ONVIF audio backchannel by sending `Require:
+    /// www.onvif.org/ver20/backchannel` with `DESCRIBE` and `SETUP`.
+    ///
+    /// Supporting cameras then describe an additional `a=sendonly` audio
+    /// stream (see [`Stream::is_backchannel`]) which, once set up and playing,
+    /// accepts audio via [`Session::send_audio`]. See the [ONVIF Streaming
+    /// Specification](https://www.onvif.org/specs/stream/ONVIF-Streaming-Spec.pdf)
+    /// section 5.3. Servers which don't support the backchannel may reject
+    /// requests with this header. Disabled by default.
+    pub fn onvif_backchannel(mut self, onvif_backchannel: bool) -> Self {
+        self.onvif_backchannel = onvif_backchannel;
+        self
+    }
 
-/// State after a `PLAY`; use via `Session<Playing>`.
-#[doc(hidden)]
-pub struct Playing(());
-impl State for Playing {}
+    /// Calls `observer` for each received RTP packet, before it's reordered
+    /// or depacketized.
+    ///
+    /// This is intended for telemetry and debugging; the observer sees each
+    /// packet's header fields and [`crate::PacketContext`] but can't alter the
+    /// stream. It's called synchronously while polling the session, so it
+    /// should return quickly.
+    pub fn packet_observer(mut self, observer: PacketObserver) -> Self {
+        self.packet_observer = Some(observer);
+        self
+    }
 
-/// The raw connection, without tracking session state.
-struct RtspConnection {
-    inner: crate::tokio::Connection,
-    channels: ChannelMappings,
+    /// Attaches the given headers to every outgoing RTSP request.
+    ///
+    /// This is an escape hatch for cameras which require proprietary headers,
+    /// such as a session token. Headers Retina sets itself (such as `CSeq`,
+    /// `Session`, `Transport`, or [`crate::X_DYNAMIC_RATE`]) take precedence
+    /// over any of the same name here; use [`SessionOptions::user_agent`] to
+    /// change `User-Agent`.
+    pub fn extra_headers(mut self, extra_headers: Vec<(rtsp_types::HeaderName, String)>) -> Self {
+        self.extra_headers = extra_headers;
+        self
+    }
 
-    /// The next `CSeq` header value to use when sending an RTSP request.
-    next_cseq: u32,
-}
+    /// Advertises support for dynamic rate via `x-Accept-Dynamic-Rate: 1` on
+    /// `SETUP` and `PLAY`.
+    ///
+    /// Some cameras (notably certain Hikvision models) then may change their
+    /// encoding rate dynamically. The server's decision, if it sends one, is
+    /// available via [`Session::dynamic_rate`]. Disabled by default.
+    pub fn accept_dynamic_rate(mut self, accept_dynamic_rate: bool) -> Self {
+        self.accept_dynamic_rate = accept_dynamic_rate;
+        self
+    }
 
-/// Mode to use in `RtspConnection::send` when looking for a response.
-enum ResponseMode {
-    /// Anything but the response to this request is an error.
-    Normal,
+    /// Relaxes specific RTSP checks which some non-compliant cameras fail.
+    ///
+    /// When enabled, Retina tolerates bare LF line endings in message heads,
+    /// truncates status lines longer than 1024 bytes, uses the first value of
+    /// a duplicated `CSeq` header, and treats a response with no `CSeq` at all
+    /// as the response to the oldest outstanding request. Each leniency
+    /// applied is logged at debug level. Disabled by default.
+    pub fn lenient(mut self, lenient: bool) -> Self {
+        self.lenient = lenient;
+        self
+    }
 
-    /// Silently discard data messages on assigned channels.
-    /// This is a workaround for recent Reolink cameras which appear to send
-    /// RTCP sender reports immediately *before* the `PLAY` response when
-    /// using interleaved data. It's simplest to discard them rather than
-    /// attempt to interpret them before having `RTP-Info`.
-    Play,
+    /// Calls `transcript` for every RTSP request and response sent or
+    /// received on the session's connections, for protocol debugging.
+    ///
+    /// For received messages, the context is as described in
+    /// [`crate::RtspMessageContext`]. For sent messages, its position is the
+    /// byte offset within the outbound stream, and its time is when the
+    /// message was queued for sending. `Authorization`,
+    /// `Proxy-Authorization`, `WWW-Authenticate`, and `Proxy-Authenticate`
+    /// header values are redacted. Interleaved data messages aren't included. The callback is
+    /// called synchronously, so it should return quickly.
+    pub fn transcript(mut self, transcript: Transcript) -> Self {
+        self.transcript = Some(transcript);
+        self
+    }
 
-    /// Discard data messages and unrelated responses while awaiting the
-    /// response to this request.
-    Teardown,
-}
+    /// Sets the policy for handling RTP packet loss while playing.
+    pub fn loss_policy(mut self, loss_policy: LossPolicy) -> Self {
+        self.loss_policy = loss_policy;
+        self
+    }
 
-/// An RTSP session.
-///
-/// When a `Session` is dropped, Retina may issue a `TEARDOWN` in the
-/// background, depending on the [`SessionOptions::teardown`] parameter. Clients
-/// may need to wait for the `TEARDOWN` to complete; see
-/// [`SessionOptions::session_group`] and [`SessionGroup::await_teardown`].
-pub struct Session<S: State>(Pin<Box<SessionInner>>, S);
+    /// Fills in [`crate::codec::VideoFrame::duration`] by looking ahead one frame.
+    ///
+    /// A video frame's duration is only known once the next frame's
+    /// timestamp arrives, so when this is set, each video frame is held back
+    /// until the following one is depacketized. This adds one frame of
+    /// latency. When the session ends, the held frame is yielded with a
+    /// `None` duration. Audio frames'
+    /// durations are known from the codec and aren't delayed. The default is
+    /// false.
+    pub fn video_frame_durations(mut self, video_frame_durations: bool) -> Self {
+        self.video_frame_durations = video_frame_durations;
+        self
+    }
 
-#[pin_project(PinnedDrop)]
-struct SessionInner {
-    /// The connection. Currently there's expected to always be a RTSP
-    /// connection, even while playing a RTP/AVP/UDP session. The only
-    /// exception is during drop.
-    conn: Option<RtspConnection>,
+    /// Bypasses depacketization, yielding every RTP packet unmodified.
+    ///
+    /// When set, [`Demuxed`] yields each packet of every stream as
+    /// [`crate::codec::CodecItem::RawRtp`], with its full RTP header and
+    /// payload intact, and no codec-specific logic applies. This is useful to
+    /// forward RTP to another system, eg a WebRTC SFU. [`Stream::parameters`]
+    /// still reports any parameters known from the SDP. Unlike
+    /// [`SessionOptions::allow_unknown_codecs`], this also applies to codecs
+    /// retina could depacketize. The default is false.
+    pub fn no_depacketization(mut self, no_depacketization: bool) -> Self {
+        self.no_depacketization = no_depacketization;
+        self
+    }
 
-    /// A handle to the tokio runtime which created this session. It will
-    /// be used to asynchronously send a `TEARDOWN` on drop.
-    runtime_handle: Option<tokio::runtime::Handle>,
+    /// Sends a `Require` header listing the given feature tags on `DESCRIBE`
+    /// and `SETUP`.
+    ///
+    /// See [RFC 2326 section 12.32](https://datatracker.ietf.org/doc/html/rfc2326#section-12.32).
+    /// A server which doesn't support one of them responds with `551 Option
+    /// not supported`, and the resulting error names the features from its
+    /// `Unsupported` header. These are in addition to the tag sent by
+    /// [`SessionOptions::onvif_backchannel`].
+    pub fn require_features(mut self, require_features: Vec<String>) -> Self {
+        self.require_features = require_features;
+        self
+    }
 
-    options: SessionOptions,
-    requested_auth: Option<digest_access::DigestAccess>,
-    presentation: Presentation,
+    /// Sends a `Supported` header listing the given feature tags on every
+    /// request, advertising client features to the server.
+    pub fn supported_features(mut self, supported_features: Vec<String>) -> Self {
+        self.supported_features = supported_features;
+        self
+    }
 
-    /// This will be set iff one or more `SETUP` calls have been issued.
-    /// This is sometimes true in state `Described` and always true in state
-    /// `Playing`.
-    session: Option<parse::SessionHeader>,
+    /// Resolves RTSP host names via `resolver` rather than the system resolver.
+    ///
+    /// The callback receives the URL's host name and port and returns the
+    /// addresses to try, as for the system resolver. This allows injecting
+    /// mDNS, service discovery, split-horizon lookups, or fixed mappings. It's
+    /// used for each connection (including `DESCRIBE` redirects and
+    /// `TEARDOWN` on a fresh connection) but not for IP literal hosts. The
+    /// callback is called synchronously, so it should return quickly;
+    /// returning no addresses fails the connection.
+    pub fn resolver(mut self, resolver: Resolver) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
 
-    // Keep some information about the DESCRIBE response. If a depacketizer
-    // couldn't be constructed correctly for one or more streams, this will be
-    // used to create a RtspResponseError on `State<Playing>::demuxed()`.
-    // We defer such errors from DESCRIBE time until then because they only
-    // matter if the stream is setup and the caller wants depacketization.
-    describe_ctx: RtspMessageContext,
-    describe_cseq: u32,
-    describe_status: rtsp_types::StatusCode,
+    /// Uses the given TLS configuration for `rtsps` URLs.
+    ///
+    /// This allows custom root certificates, client certificates, and the
+    /// like. By default, server certificates are verified against the Mozilla
+    /// root certificates from the `webpki-roots` crate. `rtsps` URLs with an
+    /// IP address host require [`SessionOptions::tls_server_name`] or
+    /// [`SessionOptions::danger_accept_invalid_certs`]. The default port is
+    /// 322. Interleaved RTP and RTCP data flows over the TLS stream; this
+    /// doesn't encrypt UDP media.
+    #[cfg(feature = "tls")]
+    pub fn tls_config(mut self, tls_config: Arc<rustls::ClientConfig>) -> Self {
+        self.tls_config = Some(tls_config);
+        self
+    }
 
-    /// The state of the keepalive request; only used in state `Playing`.
-    keepalive_state: KeepaliveState,
+    /// Accepts any server certificate for `rtsps` URLs, including
+    /// self-signed and expired ones.
+    ///
+    /// Many cameras have self-signed certificates which can't be verified
+    /// otherwise. This leaves the connection open to man-in-the-middle
+    /// attacks, so prefer adding the camera's certificate as a root via
+    /// [`SessionOptions::tls_config`] when practical.
+    #[cfg(feature = "tls")]
+    pub fn danger_accept_invalid_certs(mut self, danger_accept_invalid_certs: bool) -> Self {
+        self.danger_accept_invalid_certs = danger_accept_invalid_certs;
+        self
+    }
 
-    keepalive_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+    /// Verifies `rtsps` servers' certificates against the given DNS name, and
+    /// sends it as the TLS server name indication, rather than using the
+    /// URL's host.
+    ///
+    /// Certificates can only be verified against DNS names, so this allows
+    /// connecting to a camera by IP address while still verifying the name
+    /// its certificate was issued to.
+    #[cfg(feature = "tls")]
+    pub fn tls_server_name(mut self, tls_server_name: String) -> Self {
+        self.tls_server_name = Some(tls_server_name);
+        self
+    }
 
-    /// Set if the server may be in state Playing: we have sent a `PLAY`
-    /// request, regardless of if the response has been received.
-    maybe_playing: bool,
-    has_live555_tcp_bug: bool,
+    /// Returns the `Require` header value for `DESCRIBE` and `SETUP`, if any.
+    fn require_header(&self) -> Option<String> {
+        let mut features = Vec::with_capacity(self.require_features.len() + 1);
+        if self.onvif_backchannel {
+            features.push(ONVIF_BACKCHANNEL);
+        }
+        features.extend(self.require_features.iter().map(String::as_str));
+        if features.is_empty() {
+            return None;
+        }
+        Some(features.join(", "))
+    }
 
-    /// The index within `presentation.streams` to start the next poll at.
-    /// Round-robining between them rather than always starting at 0 should
-    /// prevent one stream from starving the others.
-    udp_next_poll_i: usize,
+    /// Returns true if the given stream is allowed by [`SessionOptions::setup_streams`].
+    fn is_setup_stream(&self, stream_i: usize) -> bool {
+        self.setup_streams
+            .as_ref()
+            .map(|s| s.contains(&stream_i))
+            .unwrap_or(true)
+    }
 }
 
-impl RtspConnection {
-    async fn connect(url: &Url) -> Result<Self, Error> {
-        let host =
-            RtspConnection::validate_url(url).map_err(|e| wrap!(ErrorInt::InvalidArgument(e)))?;
-        let port = url.port().unwrap_or(554);
-        let inner = crate::tokio::Connection::connect(host, port)
-            .await
-            .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
-        Ok(Self {
-            inner,
-            channels: ChannelMappings::default(),
-            next_cseq: 1,
-        })
+/// A `Range` header value, as described in [RFC 2326 section
+/// 12.29](https://datatracker.ietf.org/doc/html/rfc2326#section-12.29).
+///
+/// Specify one via [`PlayOptions::range`] to seek within VOD-capable sources;
+/// the server's response is available via [`Session::play_range`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Range {
+    /// Normal play time, relative to the beginning of the presentation, as in
+    /// `npt=10.000-` or `npt=10.000-20.000`.
+    Npt {
+        start: std::time::Duration,
+        end: Option<std::time::Duration>,
+    },
+
+    /// Absolute time, in ISO 8601 basic UTC format such as `19961108T142300Z`,
+    /// as in `clock=19961108T142300Z-`.
+    Clock { start: String, end: Option<String> },
+
+    /// Any other range, sent and received verbatim.
+    Other(String),
+}
+
+impl Range {
+    fn fmt_npt(f: &mut std::fmt::Formatter<'_>, t: std::time::Duration) -> std::fmt::Result {
+        write!(f, "{}.{:03}", t.as_secs(), t.subsec_millis())
     }
 
-    fn validate_url(url: &Url) -> Result<url::Host<&str>, String> {
-        if url.scheme() != "rtsp" {
-            return Err(format!(
-                "Bad URL {}; only scheme rtsp supported",
-                url.as_str()
-            ));
+    /// Parses a single npt time, either in seconds or `hh:mm:ss` form.
+    fn parse_npt(s: &str) -> Option<std::time::Duration> {
+        let mut secs = 0.;
+        for part in s.split(':') {
+            secs = secs * 60. + part.parse::<f64>().ok()?;
         }
-        if url.username() != "" || url.password().is_some() {
-            // Url apparently doesn't even have a way to clear the credentials,
-            // so this has to be an error.
-            // TODO: that's not true; revisit this.
-            return Err("URL must not contain credentials".to_owned());
+        // `Duration::from_secs_f64` panics on values it can't represent.
+        if !secs.is_finite() || secs < 0. || secs >= u64::MAX as f64 {
+            return None;
         }
-        url.host()
-            .ok_or_else(|| format!("Must specify host in rtsp url {}", &url))
+        Some(std::time::Duration::from_secs_f64(secs))
     }
+}
 
-    /// Sends a request and expects the next message from the peer to be its response.
-    /// Takes care of authorization and `CSeq`. Returns `Error` if not successful.
-    async fn send(
-        &mut self,
-        mode: ResponseMode,
-        options: &SessionOptions,
-        requested_auth: &mut Option<digest_access::DigestAccess>,
-        req: &mut rtsp_types::Request<Bytes>,
-    ) -> Result<(RtspMessageContext, u32, rtsp_types::Response<Bytes>), Error> {
-        loop {
-            let cseq = self.fill_req(options, requested_auth, req)?;
-            self.inner
-                .send(rtsp_types::Message::Request(req.clone()))
-                .await
-                .map_err(|e| wrap!(e))?;
-            let method: &str = req.method().into();
-            let (resp, msg_ctx) = loop {
-                let msg = self.inner.next().await.unwrap_or_else(|| {
-                    bail!(ErrorInt::RtspReadError {
-                        conn_ctx: *self.inner.ctx(),
-                        msg_ctx: self.inner.eof_ctx(),
-                        source: std::io::Error::new(
-                            std::io::ErrorKind::UnexpectedEof,
-                            format!("EOF while expecting response to {} CSeq {}", method, cseq),
-                        ),
-                    })
-                })?;
-                let msg_ctx = msg.ctx;
-                let description = match msg.msg {
-                    rtsp_types::Message::Response(r) => {
-                        if let Some(response_cseq) = parse::get_cseq(&r) {
-                            if response_cseq == cseq {
-                                break (r, msg_ctx);
-                            }
-                            if matches!(mode, ResponseMode::Teardown) {
-                                debug!("ignoring unrelated response during TEARDOWN");
-                                continue;
-                            }
-                            format!("{} response with CSeq {}", r.reason_phrase(), response_cseq)
-                        } else {
-                            format!("{} response with no/unparseable cseq", r.reason_phrase())
-                        }
-                    }
-                    rtsp_types::Message::Data(d) => {
-                        if matches!(mode, ResponseMode::Teardown { .. }) {
-                            debug!("ignoring RTSP data during TEARDOWN");
-                            continue;
-                        } else if let (ResponseMode::Play, Some(m)) =
-                            (&mode, self.channels.lookup(d.channel_id()))
-                        {
-                            if m.channel_type == ChannelType::Rtcp {
-                                debug!(
-                                    "ignoring interleaved data message on RTCP channel {} while \
-                                     waiting for response to {} CSeq {}",
-                                    d.channel_id(),
-                                    method,
-                                    cseq
-                                );
-                                continue;
-                            }
-                        }
+impl std::fmt::Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Range::Npt { start, end } => {
+                f.write_str("npt=")?;
+                Range::fmt_npt(f, *start)?;
+                f.write_str("-")?;
+                if let Some(end) = end {
+                    Range::fmt_npt(f, *end)?;
+                }
+                Ok(())
+            }
+            Range::Clock { start, end } => {
+                write!(f, "clock={}-{}", start, end.as_deref().unwrap_or(""))
+            }
+            Range::Other(s) => f.write_str(s),
+        }
+    }
+}
 
-                        if let Some(session_group) = options.session_group.as_ref() {
-                            session_group.note_stale_live555_data();
-                        }
+impl std::str::FromStr for Range {
+    type Err = Error;
 
-                        format!(
-                            "{}-byte interleaved data message on channel {}",
-                            d.len(),
-                            d.channel_id()
-                        )
-                    }
-                    rtsp_types::Message::Request(r) => format!("{:?} request", r.method()),
-                };
-                bail!(ErrorInt::RtspFramingError {
-                    conn_ctx: *self.inner.ctx(),
-                    msg_ctx,
-                    description: format!(
-                        "Expected response to {} CSeq {}, got {}",
-                        method, cseq, description,
-                    ),
-                });
+    /// Parses a `Range` value.
+    ///
+    /// Units other than `npt` and `clock` (such as `smpte`) are returned as
+    /// [`Range::Other`]. Malformed `npt` and `clock` ranges are errors.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Ignore any trailing parameters, such as ";time=...".
+        let value = s
+            .split(';')
+            .next()
+            .expect("split returns at least one part")
+            .trim();
+        let bad = || {
+            wrap!(ErrorInt::InvalidArgument(format!(
+                "bad Range {:?}; expected npt=<start>-[<end>] or clock=<start>-[<end>]",
+                s
+            )))
+        };
+        if let Some(npt) = value.strip_prefix("npt=") {
+            let (start, end) = npt.split_once('-').ok_or_else(bad)?;
+            let start = Range::parse_npt(start).ok_or_else(bad)?;
+            let end = match end {
+                "" => None,
+                e => Some(Range::parse_npt(e).ok_or_else(bad)?),
             };
-            if resp.status() == rtsp_types::StatusCode::Unauthorized {
-                if requested_auth.is_some() {
-                    // TODO: the WWW-Authenticate might indicate a new domain or nonce.
-                    // In that case, we should retry rather than returning error.
-                    bail!(ErrorInt::RtspResponseError {
-                        conn_ctx: *self.inner.ctx(),
-                        msg_ctx,
-                        method: req.method().clone(),
-                        cseq,
-                        status: resp.status(),
-                        description: "Received Unauthorized after trying digest auth".into(),
-                    })
-                }
-                let www_authenticate = match resp.header(&rtsp_types::headers::WWW_AUTHENTICATE) {
-                    None => bail!(ErrorInt::RtspResponseError {
-                        conn_ctx: *self.inner.ctx(),
-                        msg_ctx,
-                        method: req.method().clone(),
-                        cseq,
-                        status: resp.status(),
-                        description: "Unauthorized without WWW-Authenticate header".into(),
-                    }),
-                    Some(h) => h,
-                };
-                let www_authenticate = www_authenticate.as_str();
-                if !www_authenticate.starts_with("Digest ") {
-                    // TODO: the header(s) might also indicate both Basic and Digest; we shouldn't
-                    // error or not based on ordering.
-                    bail!(ErrorInt::RtspResponseError {
-                        conn_ctx: *self.inner.ctx(),
-                        msg_ctx,
-                        method: req.method().clone(),
-                        cseq,
-                        status: resp.status(),
-                        description: format!(
-                            "Non-digest authentication requested: {}",
-                            www_authenticate
-                        ),
-                    })
-                }
-                if options.creds.is_none() {
-                    bail!(ErrorInt::RtspResponseError {
-                        conn_ctx: *self.inner.ctx(),
-                        msg_ctx,
-                        method: req.method().clone(),
-                        cseq,
-                        status: resp.status(),
-                        description: "Authentication requested and no credentials supplied"
-                            .to_owned(),
-                    })
-                }
-                let www_authenticate = www_authenticate
-                    .parse::<digest_access::DigestAccess>()
-                    .map_err(|e| {
-                        wrap!(ErrorInt::RtspResponseError {
-                            conn_ctx: *self.inner.ctx(),
-                            msg_ctx,
-                            method: req.method().clone(),
-                            cseq,
-                            status: resp.status(),
-                            description: format!(
-                                "Bad WWW-Authenticate header {:?}: {}",
-                                www_authenticate, e
-                            ),
-                        })
-                    })?;
-                *requested_auth = Some(www_authenticate);
-                continue;
-            } else if !resp.status().is_success() {
-                bail!(ErrorInt::RtspResponseError {
-                    conn_ctx: *self.inner.ctx(),
-                    msg_ctx,
-                    method: req.method().clone(),
-                    cseq,
-                    status: resp.status(),
-                    description: "Unexpected RTSP response status".into(),
-                });
+            return Ok(Range::Npt { start, end });
+        }
+        if let Some(clock) = value.strip_prefix("clock=") {
+            let (start, end) = clock.split_once('-').ok_or_else(bad)?;
+            if start.is_empty() {
+                return Err(bad());
             }
-            return Ok((msg_ctx, cseq, resp));
+            return Ok(Range::Clock {
+                start: start.to_owned(),
+                end: if end.is_empty() {
+                    None
+                } else {
+                    Some(end.to_owned())
+                },
+            });
         }
+        Ok(Range::Other(value.to_owned()))
     }
+}
 
-    /// Fills out `req` with authorization and `CSeq` headers.
-    fn fill_req(
-        &mut self,
-        options: &SessionOptions,
-        requested_auth: &mut Option<digest_access::DigestAccess>,
-        req: &mut rtsp_types::Request<Bytes>,
-    ) -> Result<u32, Error> {
-        let cseq = self.next_cseq;
-        self.next_cseq += 1;
-        if let Some(ref mut auth) = requested_auth {
-            let creds = options
-                .creds
-                .as_ref()
-                .expect("creds were checked when filling request_auth");
+/// An entry within a `PLAY` response's `RTP-Info` header, as described in
+/// [RFC 2326 section 12.33](https://datatracker.ietf.org/doc/html/rfc2326#section-12.33).
+///
+/// Returned by [`Session::rtp_info`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RtpInfoEntry {
+    /// The stream URL, resolved relative to the presentation's base URL.
+    pub url: Url,
+
+    /// The sequence number of the first packet of the stream, if specified.
+    pub seq: Option<u16>,
+
+    /// The RTP timestamp corresponding to the start of the `Range`, if
+    /// specified.
+    pub rtptime: Option<u32>,
+}
 
-            let uri = req.request_uri().map(|u| u.as_str()).unwrap_or("*");
-            let method = req.method().into();
-            auth.set_username(&creds.username);
-            auth.set_password(&creds.password);
+/// Options which must be decided at `SETUP` time.
+///
+/// Specify via [`Session::setup_with_options`]. See also [SessionOptions] for
+/// options which apply to every `SETUP`.
+#[derive(Default)]
+pub struct SetupOptions {
+    transport_header_override: Option<String>,
+    clock_rate_override: Option<u32>,
+}
 
-            if let Some(authorization) = auth.generate_authorization(method, uri, None, None) {
-                req.insert_header(rtsp_types::headers::AUTHORIZATION, authorization);
-            }
+impl SetupOptions {
+    /// Sends the given `Transport` header verbatim on `SETUP`, instead of the
+    /// one retina constructs.
+    ///
+    /// This is an escape hatch for non-compliant firmware which requires a
+    /// particular `mode=`, `ssrc=`, or parameter ordering. Use it with care:
+    /// the response is still interpreted according to
+    /// [`SessionOptions::transport`], and retina doesn't check that the
+    /// header is consistent with it. Eg, with [`Transport::Udp`] the header's
+    /// `client_port` must match the sockets retina binds, which it doesn't
+    /// know in advance; with [`Transport::Tcp`] the `interleaved` channels the
+    /// server chooses must not already be assigned.
+    pub fn transport_header_override(self, header: String) -> Self {
+        Self {
+            transport_header_override: Some(header),
+            ..self
         }
-        req.insert_header(rtsp_types::headers::CSEQ, cseq.to_string());
-        if let Some(ref u) = options.user_agent {
-            req.insert_header(rtsp_types::headers::USER_AGENT, u.to_string());
+    }
+
+    /// Uses the given RTP clock rate for this stream instead of the one in
+    /// the SDP `a=rtpmap` attribute.
+    ///
+    /// This is an escape hatch for firmware which advertises the wrong rate
+    /// (eg claiming 8 kHz for 16 kHz audio), which otherwise corrupts
+    /// [crate::Timestamp] values and normal play time. It affects both
+    /// timestamp construction and the stream's depacketizer, and
+    /// [`Stream::clock_rate`] reflects the override once `SETUP` succeeds.
+    /// The rate must be non-zero.
+    pub fn override_clock_rate(self, clock_rate: u32) -> Self {
+        Self {
+            clock_rate_override: Some(clock_rate),
+            ..self
         }
-        Ok(cseq)
     }
 }
 
-impl Session<Described> {
-    /// Creates a new session from a `DESCRIBE` request on the given URL.
-    ///
-    /// This method is permissive; it will return success even if there are
-    /// errors in the SDP that would prevent one or more streams from being
+/// Options which must be decided at `PLAY` time.
+///
+/// These are mostly adjustments for non-compliant server implementations.
+/// See also [SessionOptions] for options which must be decided earlier.
+#[derive(Debug, Default)]
+pub struct PlayOptions {
+    initial_timestamp: InitialTimestampPolicy,
+    ignore_zero_seq: bool,
+    enforce_timestamps_with_max_jump_secs: Option<NonZeroU32>,
+    timestamp_reset_threshold_secs: Option<NonZeroU32>,
+    range: Option<Range>,
+    scale: Option<f32>,
+}
+
+impl PlayOptions {
+    pub fn initial_timestamp(self, initial_timestamp: InitialTimestampPolicy) -> Self {
+        Self {
+            initial_timestamp,
+            ..self
+        }
+    }
+
+    /// If the `RTP-Time` specifies `seq=0`, ignore it. Some cameras set this value then start
+    /// the stream with something dramatically different. (Eg the Hikvision DS-2CD2032-I on its
+    /// metadata stream; the other streams are fine.)
+    pub fn ignore_zero_seq(self, ignore_zero_seq: bool) -> Self {
+        Self {
+            ignore_zero_seq,
+            ..self
+        }
+    }
+
+    /// Enforces that timestamps are non-decreasing and jump forward by no more
+    /// than the given number of seconds.
+    ///
+    /// By default, no enforcement is done, and computed [crate::Timestamp]
+    /// values will go backward if subsequent 32-bit RTP timestamps differ by
+    /// more than `i32::MAX`.
+    pub fn enforce_timestamps_with_max_jump_secs(self, secs: NonZeroU32) -> Self {
+        Self {
+            enforce_timestamps_with_max_jump_secs: Some(secs),
+            ..self
+        }
+    }
+
+    /// Treats an RTP timestamp jumping backward by more than the given
+    /// number of seconds as a timestamp reset.
+    ///
+    /// Some cameras reset their RTP timestamps mid-stream, eg after a reboot
+    /// or a stream splice. Without this, the following [crate::Timestamp]s
+    /// go far backward (or, with [`PlayOptions::enforce_timestamps_with_max_jump_secs`],
+    /// the stream fails). With it, the stream's timeline is re-anchored at
+    /// the new timestamp and the packet is marked with
+    /// [`crate::client::rtp::Packet::discontinuity`], as is the first
+    /// depacketized frame on the new timeline.
+    pub fn timestamp_reset_threshold_secs(self, secs: NonZeroU32) -> Self {
+        Self {
+            timestamp_reset_threshold_secs: Some(secs),
+            ..self
+        }
+    }
+
+    /// Sends the given `Range` header on `PLAY`.
+    ///
+    /// By default, `npt=0.000-` is sent. Servers may ignore or adjust the
+    /// requested range; see [`Session::play_range`] for the range they
+    /// actually returned. When that is an `npt` range and `RTP-Info` supplies
+    /// a rtptime, timestamps' elapsed time starts at the returned npt start.
+    pub fn range(self, range: Range) -> Self {
+        Self {
+            range: Some(range),
+            ..self
+        }
+    }
+
+    /// Sends the given `Scale` header on `PLAY`, requesting playback at a
+    /// multiple of normal speed.
+    ///
+    /// This is generally only supported by servers of recorded footage. For
+    /// example, `2.0` requests fast-forward at double speed and `-1.0` requests
+    /// reverse playback. Servers may adjust the requested scale or ignore it
+    /// entirely; see [`Session::play_scale`] for the scale they returned.
+    ///
+    /// RTP timestamps continue to track media time, so at a scale of `2.0`
+    /// they advance twice as fast as wall time. Callers computing NPT or
+    /// wall-clock positions from them should account for this.
+    ///
+    /// By default, no `Scale` header is sent.
+    pub fn scale(self, scale: f32) -> Self {
+        Self {
+            scale: Some(scale),
+            ..self
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Presentation {
+    pub streams: Vec<Stream>,
+
+    /// The URL `DESCRIBE` was sent to, after following any redirects.
+    request_url: Url,
+
+    base_url: Url,
+    pub control: Url,
+    pub accept_dynamic_rate: bool,
+
+    /// Session-level `a:tool` from SDP.
+    tool: Option<Box<str>>,
+
+    /// The `DESCRIBE` response body, exactly as received.
+    raw_sdp: Bytes,
+}
+
+/// Information about a stream offered within a presentation.
+/// Currently if multiple formats are offered, this only describes the first.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Stream {
+    /// This stream's index within [`Session::streams`].
+    index: usize,
+
+    /// Media type, as specified in the [IANA SDP parameters media
+    /// registry](https://www.iana.org/assignments/sdp-parameters/sdp-parameters.xhtml#sdp-parameters-1).
+    pub media: String,
+
+    /// An encoding name, as specified in the [IANA media type
+    /// registry](https://www.iana.org/assignments/media-types/media-types.xhtml), with
+    /// ASCII characters in lowercase.
+    ///
+    /// Commonly used but not specified in that registry: the ONVIF types
+    /// claimed in the
+    /// [ONVIF Streaming Spec](https://www.onvif.org/specs/stream/ONVIF-Streaming-Spec.pdf):
+    /// *   `vnd.onvif.metadata`
+    /// *   `vnd.onvif.metadata.gzip`,
+    /// *   `vnd.onvif.metadata.exi.onvif`
+    /// *   `vnd.onvif.metadata.exi.ext`
+    pub encoding_name: String,
+
+    /// RTP payload type.
+    /// See the [registry](https://www.iana.org/assignments/rtp-parameters/rtp-parameters.xhtml#rtp-parameters-1).
+    /// It's common to use one of the dynamically assigned values, 96–127.
+    pub rtp_payload_type: u8,
+
+    /// RTP clock rate, in Hz.
+    pub clock_rate: u32,
+
+    /// Number of audio channels, if applicable (`media` is `audio`) and known.
+    pub channels: Option<NonZeroU16>,
+
+    #[cfg_attr(
+        feature = "serde",
+        serde(rename = "parameters", serialize_with = "serialize_parameters")
+    )]
+    depacketizer: Result<crate::codec::Depacketizer, String>,
+
+    /// The specified control URL.
+    /// This is needed with multiple streams to send `SETUP` requests and
+    /// interpret the `PLAY` response's `RTP-Info` header.
+    /// [RFC 2326 section C.3](https://datatracker.ietf.org/doc/html/rfc2326#appendix-C.3)
+    /// says the server is allowed to omit it when there is only a single stream.
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::option_url")
+    )]
+    pub control: Option<Url>,
+
+    /// The media-level SDP attributes, in order.
+    sdp_attributes: Vec<(String, Option<String>)>,
+
+    /// The sockets for `Transport::Udp`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sockets: Option<UdpSockets>,
+
+    #[cfg_attr(feature = "serde", serde(skip))]
+    state: StreamState,
+
+    /// If set, this stream's timestamps are rebased onto this one when
+    /// playing; see [`reconnect`].
+    #[cfg_attr(feature = "serde", serde(skip))]
+    timeline_base: Option<crate::Timestamp>,
+
+    /// The `a=rtcp-fb` attributes applying to `rtp_payload_type`.
+    rtcp_feedback: Vec<RtcpFeedback>,
+
+    /// The payload type of the associated retransmission stream, if any.
+    rtx_payload_type: Option<u8>,
+
+    /// True if the SDP media description has `a=sendonly`.
+    backchannel: bool,
+
+    /// The sequence number of the next packet sent via [`Session::send_audio`],
+    /// or `None` if none has been sent yet.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    send_seq: Option<u16>,
+
+    /// The SRTP context, if the SDP media description uses a `RTP/SAVP`
+    /// profile, or an error if it has no usable `a=crypto` attribute.
+    #[cfg(feature = "srtp")]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    srtp: Option<Result<srtp::Context, String>>,
+}
+
+/// An RTCP feedback capability advertised for a stream via an SDP `a=rtcp-fb`
+/// attribute; see [RFC 4585 section
+/// 4.2](https://datatracker.ietf.org/doc/html/rfc4585#section-4.2).
+///
+/// Eg `a=rtcp-fb:96 nack pli` is represented with `payload_type: Some(96)`,
+/// `id: "nack"`, and `param: Some("pli")`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct RtcpFeedback {
+    /// The payload type this applies to, or `None` for the wildcard `*`.
+    pub payload_type: Option<u8>,
+
+    /// The feedback type, such as `ack`, `nack`, `ccm`, or `trr-int`.
+    pub id: String,
+
+    /// The remainder of the attribute, such as `pli` or `fir`, if any.
+    pub param: Option<String>,
+}
+
+impl RtcpFeedback {
+    /// Returns true for generic NACK (RFC 4585 section 6.2.1).
+    pub fn is_nack(&self) -> bool {
+        self.id == "nack" && self.param.is_none()
+    }
+
+    /// Returns true for Picture Loss Indication (RFC 4585 section 6.3.1).
+    pub fn is_pli(&self) -> bool {
+        self.id == "nack" && self.param.as_deref() == Some("pli")
+    }
+
+    /// Returns true for Full Intra Request (RFC 5104 section 4.3.1).
+    pub fn is_fir(&self) -> bool {
+        self.id == "ccm" && self.param.as_deref() == Some("fir")
+    }
+}
+
+/// Writes a received RTP/RTCP packet to the capture file, if any.
+///
+/// On failure, logs and stops capturing rather than failing the session.
+fn write_capture(
+    capture: &mut Option<capture::Capture>,
+    conn_ctx: &crate::ConnectionContext,
+    pkt_ctx: &crate::PacketContext,
+    data: &[u8],
+) {
+    if let Some(c) = capture {
+        if let Err(e) = c.write(conn_ctx, pkt_ctx, data) {
+            warn!("Unable to write capture; stopping: {}", e);
+            *capture = None;
+        }
+    }
+}
+
+/// Serializes a stream's depacketizer as its current parameters, if any.
+#[cfg(feature = "serde")]
+fn serialize_parameters<S: serde::Serializer>(
+    depacketizer: &Result<crate::codec::Depacketizer, String>,
+    s: S,
+) -> Result<S::Ok, S::Error> {
+    serde::Serialize::serialize(&depacketizer.as_ref().ok().and_then(|d| d.parameters()), s)
+}
+
+#[derive(Debug)]
+struct UdpSockets {
+    local_ip: IpAddr,
+    local_rtp_port: u16,
+    remote_ip: IpAddr,
+    remote_rtp_port: u16,
+    rtp_socket: UdpSocket,
+    remote_rtcp_port: u16,
+    rtcp_socket: UdpSocket,
+
+    /// True if the sockets are connected to the remote ports; see
+    /// [`SessionOptions::connected_udp`].
+    connected: bool,
+
+    /// The multicast group joined with [`Transport::UdpMulticast`], which is
+    /// also `remote_ip`.
+    multicast_group: Option<IpAddr>,
+}
+
+impl UdpSockets {
+    /// Leaves the multicast group, if any.
+    fn leave_multicast(&mut self) {
+        let group = match self.multicast_group.take() {
+            Some(g) => g,
+            None => return,
+        };
+        for s in [&self.rtp_socket, &self.rtcp_socket] {
+            let r = match (group, self.local_ip) {
+                (IpAddr::V4(g), IpAddr::V4(i)) => s.leave_multicast_v4(g, i),
+                (IpAddr::V4(g), IpAddr::V6(_)) => s.leave_multicast_v4(g, Ipv4Addr::UNSPECIFIED),
+                (IpAddr::V6(g), _) => s.leave_multicast_v6(&g, 0),
+            };
+            if let Err(e) = r {
+                debug!("Unable to leave multicast group {}: {}", group, e);
+            }
+        }
+    }
+
+    fn remote_rtp_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.remote_ip, self.remote_rtp_port)
+    }
+
+    fn remote_rtcp_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.remote_ip, self.remote_rtcp_port)
+    }
+
+    async fn send_rtp(&self, data: &[u8]) -> Result<usize, std::io::Error> {
+        if self.connected {
+            self.rtp_socket.send(data).await
+        } else {
+            self.rtp_socket.send_to(data, self.remote_rtp_addr()).await
+        }
+    }
+
+    async fn send_rtcp(&self, data: &[u8]) -> Result<usize, std::io::Error> {
+        if self.connected {
+            self.rtcp_socket.send(data).await
+        } else {
+            self.rtcp_socket
+                .send_to(data, self.remote_rtcp_addr())
+                .await
+        }
+    }
+
+    /// Sends an RTCP packet if possible without waiting.
+    fn try_send_rtcp(&self, data: &[u8]) -> Result<usize, std::io::Error> {
+        if self.connected {
+            self.rtcp_socket.try_send(data)
+        } else {
+            self.rtcp_socket.try_send_to(data, self.remote_rtcp_addr())
+        }
+    }
+}
+
+impl Stream {
+    /// Returns this stream's index within [`Session::streams`], as used by
+    /// [`Session::setup`] and [`crate::codec::CodecItem`] stream ids.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the media type; see [`Stream::media`](#structfield.media).
+    pub fn media(&self) -> &str {
+        &self.media
+    }
+
+    /// Returns the lowercase encoding name; see
+    /// [`Stream::encoding_name`](#structfield.encoding_name).
+    pub fn encoding_name(&self) -> &str {
+        &self.encoding_name
+    }
+
+    /// Returns the RTP payload type.
+    pub fn rtp_payload_type(&self) -> u8 {
+        self.rtp_payload_type
+    }
+
+    /// Returns the RTP clock rate, in Hz.
+    pub fn clock_rate(&self) -> u32 {
+        self.clock_rate
+    }
+
+    /// Returns the number of audio channels, if applicable and known.
+    pub fn channels(&self) -> Option<NonZeroU16> {
+        self.channels
+    }
+
+    /// Returns the control URL, used to `SETUP` this stream.
+    pub fn control(&self) -> Option<&Url> {
+        self.control.as_ref()
+    }
+
+    /// Returns the parameters for this stream.
+    ///
+    /// Returns `None` on unknown codecs, bad parameters, or if parameters aren't specified
+    /// via SDP. Some codecs allow parameters to be specified in-band instead.
+    pub fn parameters(&self) -> Option<crate::codec::Parameters> {
+        self.depacketizer.as_ref().ok().and_then(|d| d.parameters())
+    }
+
+    /// Returns the raw `a=` attributes of this stream's SDP media
+    /// description, in order.
+    ///
+    /// Each is a name and, if there was a `:`, a value. This includes
+    /// attributes retina interprets itself (such as `rtpmap` and `control`)
+    /// as well as vendor-specific ones (such as `x-dimensions`).
+    pub fn sdp_attributes(&self) -> &[(String, Option<String>)] {
+        &self.sdp_attributes
+    }
+
+    /// Returns the `a=fmtp` parameters for `rtp_payload_type`, if any.
+    fn format_specific_params(&self) -> Option<&str> {
+        let payload_type = self.rtp_payload_type.to_string();
+        self.sdp_attributes
+            .iter()
+            .filter(|(attribute, _)| attribute == "fmtp")
+            .filter_map(|(_, value)| value.as_deref()?.split_once(' '))
+            .find(|&(pt, _)| pt == payload_type)
+            .map(|(_, params)| params)
+    }
+
+    /// Returns the RTCP feedback types the server advertised for this stream
+    /// via `a=rtcp-fb`, including wildcard (`*`) entries.
+    pub fn rtcp_feedback(&self) -> &[RtcpFeedback] {
+        &self.rtcp_feedback
+    }
+
+    /// Returns the payload type of this stream's [RFC
+    /// 4588](https://datatracker.ietf.org/doc/html/rfc4588) retransmission
+    /// stream, if advertised via `a=rtpmap:<pt> rtx/...` and
+    /// `a=fmtp:<pt> apt=<rtp_payload_type>`.
+    ///
+    /// Retransmitted packets are received alongside the original stream and
+    /// used to fill gaps in it.
+    pub fn rtx_payload_type(&self) -> Option<u8> {
+        self.rtx_payload_type
+    }
+
+    /// Returns true if this is a backchannel stream, which the server
+    /// receives rather than sends, as indicated by `a=sendonly`.
+    ///
+    /// See [`SessionOptions::onvif_backchannel`].
+    pub fn is_backchannel(&self) -> bool {
+        self.backchannel
+    }
+
+    /// Returns the server's RTP port, as negotiated via the `server_port`
+    /// parameter of the `SETUP` response's `Transport` header.
+    ///
+    /// Returns `None` unless this stream has been successfully set up with
+    /// [`Transport::Udp`]. (With [`Transport::UdpMulticast`], the server
+    /// doesn't specify its ports.)
+    pub fn server_rtp_port(&self) -> Option<u16> {
+        self.negotiated_sockets().map(|s| s.remote_rtp_port)
+    }
+
+    /// Returns the server's RTCP port; see [`Stream::server_rtp_port`].
+    pub fn server_rtcp_port(&self) -> Option<u16> {
+        self.negotiated_sockets().map(|s| s.remote_rtcp_port)
+    }
+
+    /// Returns the UDP sockets, if their remote ports have been set from a
+    /// successful `SETUP` response.
+    fn negotiated_sockets(&self) -> Option<&UdpSockets> {
+        self.sockets
+            .as_ref()
+            .filter(|s| s.remote_rtp_port != 0 && s.multicast_group.is_none())
+    }
+
+    /// Returns how to request a keyframe, preferring PLI.
+    fn keyframe_request(&self) -> Option<KeyframeRequest> {
+        if self.rtcp_feedback.iter().any(RtcpFeedback::is_pli) {
+            Some(KeyframeRequest::Pli)
+        } else if self.rtcp_feedback.iter().any(RtcpFeedback::is_fir) {
+            Some(KeyframeRequest::Fir)
+        } else {
+            None
+        }
+    }
+}
+
+/// An RTCP feedback message which asks the sender for a keyframe.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum KeyframeRequest {
+    /// Picture Loss Indication; see [RFC 4585 section
+    /// 6.3.1](https://datatracker.ietf.org/doc/html/rfc4585#section-6.3.1).
+    Pli,
+
+    /// Full Intra Request; see [RFC 5104 section
+    /// 4.3.1](https://datatracker.ietf.org/doc/html/rfc5104#section-4.3.1).
+    Fir,
+}
+
+#[derive(Debug)]
+enum StreamState {
+    /// Uninitialized; no `SETUP` has yet been sent.
+    Uninit,
+
+    /// `SETUP` response has been received.
+    Init(StreamStateInit),
+
+    /// `PLAY` response has been received.
+    Playing {
+        timeline: Timeline,
+        rtp_handler: rtp::InorderParser,
+    },
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct StreamStateInit {
+    /// The RTP synchronization source (SSRC), as defined in
+    /// [RFC 3550](https://tools.ietf.org/html/rfc3550). This is normally
+    /// supplied in the `SETUP` response's `Transport` header. Reolink cameras
+    /// instead supply it in the `PLAY` response's `RTP-Info` header.
+    ssrc: Option<u32>,
+
+    /// The initial RTP sequence number, as specified in the `PLAY` response's
+    /// `RTP-Info` header. This field is only used during the `play()` call
+    /// itself; by the time it returns, the stream will be in state `Playing`.
+    initial_seq: Option<u16>,
+
+    /// The initial RTP timestamp, as specified in the `PLAY` response's
+    /// `RTP-Info` header. This field is only used during the `play()` call
+    /// itself; by the time it returns, the stream will be in state `Playing`.
+    initial_rtptime: Option<u32>,
+}
+
+#[derive(Clone)]
+pub struct Credentials {
+    pub username: String,
+    pub password: String,
+}
+
+/// Marker trait for the state of a [Session].
+/// This doesn't closely match [RFC 2326
+/// A.1](https://tools.ietf.org/html/rfc2326#appendix-A.1). In practice, we've
+/// found that cheap IP cameras are more restrictive than RTSP suggests. Eg, a
+/// `DESCRIBE` changes the connection's state such that another one will fail,
+/// before assigning a session id. Thus [Session] represents something more like
+/// an RTSP connection than an RTSP session.
+#[doc(hidden)]
+pub trait State {}
+
+/// Initial state after a `DESCRIBE`; use via `Session<Described>`.
+#[doc(hidden)]
+pub struct Described(());
+impl State for Described {}
+
+enum KeepaliveState {
+    Idle,
+    Flushing(u32),
+    Waiting(u32),
+}
+
+impl KeepaliveState {
+    /// Returns the `CSeq` of the keepalive request awaiting a response, if any.
+    fn outstanding(&self) -> Option<u32> {
+        match *self {
+            KeepaliveState::Idle => None,
+            KeepaliveState::Flushing(cseq) | KeepaliveState::Waiting(cseq) => Some(cseq),
+        }
+    }
+}
+
+/// State after a `PLAY`; use via `Session<Playing>`.
+#[doc(hidden)]
+pub struct Playing(());
+impl State for Playing {}
+
+/// The raw connection, without tracking session state.
+struct RtspConnection {
+    inner: crate::tokio::Connection,
+    channels: ChannelMappings,
+
+    /// The next `CSeq` header value to use when sending an RTSP request.
+    next_cseq: u32,
+
+    /// Interleaved data received while awaiting a [`ResponseMode::Control`]
+    /// response, to be handled by the next poll of a playing session. Holds at
+    /// most [`MAX_DEFERRED_DATA`] messages.
+    deferred_data: std::collections::VecDeque<(RtspMessageContext, rtsp_types::Data<Bytes>)>,
+}
+
+/// The most interleaved data messages to defer while awaiting a
+/// [`ResponseMode::Control`] response. Further messages are dropped, which
+/// shows up as RTP packet loss.
+const MAX_DEFERRED_DATA: usize = 1024;
+
+/// Mode to use in `RtspConnection::send` when looking for a response.
+enum ResponseMode {
+    /// Anything but the response to this request is an error.
+    Normal,
+
+    /// As with `Normal`, but redirect (`3xx`) responses are returned to the
+    /// caller rather than treated as errors.
+    AllowRedirect,
+
+    /// Silently discard data messages on assigned channels.
+    /// This is a workaround for recent Reolink cameras which appear to send
+    /// RTCP sender reports immediately *before* the `PLAY` response when
+    /// using interleaved data. It's simplest to discard them rather than
+    /// attempt to interpret them before having `RTP-Info`.
+    Play,
+
+    /// Discard data messages and unrelated responses while awaiting the
+    /// response to this request.
+    ///
+    /// Holds the `CSeq` of an earlier request still awaiting its response (an
+    /// outstanding keepalive), if any. In lenient mode, a response with no
+    /// `CSeq` is taken to answer that earlier request first.
+    Teardown(Option<u32>),
+
+    /// As with `Teardown`, for a control request such as `GET_PARAMETER`
+    /// which may be sent while playing, except that data messages are
+    /// deferred to be returned from the session's stream rather than
+    /// discarded.
+    Control(Option<u32>),
+}
+
+impl ResponseMode {
+    /// Returns the `CSeq` of an earlier request still awaiting its response.
+    fn earlier_cseq(&self) -> Option<u32> {
+        match *self {
+            ResponseMode::Teardown(c) | ResponseMode::Control(c) => c,
+            _ => None,
+        }
+    }
+}
+
+/// An RTSP session.
+///
+/// When a `Session` is dropped, Retina may issue a `TEARDOWN` in the
+/// background, depending on the [`SessionOptions::teardown`] parameter. Clients
+/// may need to wait for the `TEARDOWN` to complete; see
+/// [`SessionOptions::session_group`] and [`SessionGroup::await_teardown`].
+pub struct Session<S: State>(Pin<Box<SessionInner>>, S);
+
+/// State recovered from a [`Session`] via [`Session::into_parts`].
+///
+/// This remains available after the session has failed, so callers can log
+/// what was negotiated or resume via [`reconnect`].
+pub struct SessionParts {
+    conn_ctx: crate::ConnectionContext,
+    url: Url,
+    creds: Option<Credentials>,
+    streams: Vec<Stream>,
+    play_options: PlayOptions,
+}
+
+impl std::fmt::Debug for SessionParts {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Omit creds.
+        f.debug_struct("SessionParts")
+            .field("conn_ctx", &self.conn_ctx)
+            .field("url", &self.url)
+            .field("streams", &self.streams)
+            .field("play_options", &self.play_options)
+            .finish()
+    }
+}
+
+impl SessionParts {
+    /// Returns the context of the RTSP connection the session used.
+    pub fn conn_ctx(&self) -> &crate::ConnectionContext {
+        &self.conn_ctx
+    }
+
+    /// Returns the URL the session's `DESCRIBE` was sent to, after any redirects.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Returns the stream descriptors, indexed by stream id.
+    pub fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    /// Consumes these parts, returning the stream descriptors.
+    pub fn into_streams(self) -> Vec<Stream> {
+        self.streams
+    }
+
+    /// Returns the SSRC of the given stream, if it was set up and the SSRC is known.
+    pub fn ssrc(&self, stream_id: usize) -> Option<u32> {
+        match self.streams.get(stream_id)?.state {
+            StreamState::Uninit => None,
+            StreamState::Init(init) => init.ssrc,
+            StreamState::Playing {
+                ref rtp_handler, ..
+            } => rtp_handler.ssrc(),
+        }
+    }
+
+    /// Returns the timestamp of the last RTP packet received on the given
+    /// stream, if any.
+    pub fn last_timestamp(&self, stream_id: usize) -> Option<crate::Timestamp> {
+        match self.streams.get(stream_id)?.state {
+            StreamState::Playing {
+                ref rtp_handler, ..
+            } => rtp_handler.last_timestamp(),
+            _ => None,
+        }
+    }
+
+    /// Returns when the last RTP packet on any stream was received, if any.
+    fn last_received(&self) -> Option<std::time::Instant> {
+        self.streams
+            .iter()
+            .filter_map(|s| match s.state {
+                StreamState::Playing {
+                    ref rtp_handler, ..
+                } => rtp_handler.last_received(),
+                _ => None,
+            })
+            .max()
+    }
+}
+
+/// Re-establishes a session after `previous` failed, continuing its timeline.
+///
+/// Sends `DESCRIBE` to the previous session's URL, using its credentials
+/// unless `options` specifies others, `SETUP`s the streams which were
+/// previously set up, and `PLAY`s with the previous session's
+/// [`PlayOptions`].
+///
+/// Each stream which matches the previous stream of the same index in
+/// encoding name and clock rate has its timestamps rebased (see
+/// [`crate::Timestamp::rebase`]) to start at the previous stream's last
+/// timestamp plus the gap, so NPT stays continuous.
+///
+/// Returns the new session and the gap: the time since the previous session
+/// last received a RTP packet.
+pub async fn reconnect(
+    previous: SessionParts,
+    options: SessionOptions,
+) -> Result<(Session<Playing>, std::time::Duration), Error> {
+    let options = match options.creds {
+        Some(_) => options,
+        None => options.creds(previous.creds.clone()),
+    };
+    let conn = RtspConnection::connect(&previous.url, &options).await?;
+    reconnect_with_conn(conn, previous, options).await
+}
+
+async fn reconnect_with_conn(
+    conn: RtspConnection,
+    mut previous: SessionParts,
+    options: SessionOptions,
+) -> Result<(Session<Playing>, std::time::Duration), Error> {
+    let mut session = Session::describe_with_conn(conn, options, previous.url.clone()).await?;
+    for (i, s) in previous.streams.iter().enumerate() {
+        if !matches!(s.state, StreamState::Uninit) && i < session.streams().len() {
+            session.setup(i).await?;
+        }
+    }
+    let gap = previous
+        .last_received()
+        .map(|r| r.elapsed())
+        .unwrap_or_default();
+    for (i, s) in session.0.presentation.streams.iter_mut().enumerate() {
+        let last = match (previous.streams.get(i), previous.last_timestamp(i)) {
+            (Some(p), Some(last))
+                if p.encoding_name == s.encoding_name && p.clock_rate == s.clock_rate =>
+            {
+                last
+            }
+            _ => continue,
+        };
+        let gap_ticks = u32::try_from(gap.as_nanos() * u128::from(s.clock_rate) / 1_000_000_000)
+            .unwrap_or(u32::MAX);
+        s.timeline_base = last.try_add(gap_ticks);
+    }
+    let play_options = std::mem::take(&mut previous.play_options);
+    let session = session.play(play_options).await?;
+    Ok((session, gap))
+}
+
+/// Sends an `OPTIONS` request to `url` and returns the methods listed in the
+/// response's `Public` header, without setting up a session.
+///
+/// This is useful for capability discovery, such as checking if
+/// `GET_PARAMETER` is allowed before choosing a [`KeepaliveMethod`]. Uses
+/// `creds` unless `options` specifies others. Returns an empty list if the
+/// server omits the header.
+pub async fn probe_options(
+    url: &Url,
+    creds: Option<Credentials>,
+    options: SessionOptions,
+) -> Result<Vec<Method>, Error> {
+    let options = match options.creds {
+        Some(_) => options,
+        None => options.creds(creds),
+    };
+    let mut conn = RtspConnection::connect(url, &options).await?;
+    probe_options_with_conn(&mut conn, url, &options).await
+}
+
+async fn probe_options_with_conn(
+    conn: &mut RtspConnection,
+    url: &Url,
+    options: &SessionOptions,
+) -> Result<Vec<Method>, Error> {
+    let mut req = rtsp_types::Request::builder(Method::Options, rtsp_types::Version::V1_0)
+        .request_uri(url.clone())
+        .build(Bytes::new());
+    let (_, _, response) = conn
+        .send(ResponseMode::Normal, options, &mut None, &mut req)
+        .await?;
+    Ok(parse::parse_public(&response))
+}
+
+/// A presentation description retrieved via [`describe`], without a session.
+#[derive(Debug)]
+pub struct Description {
+    url: Url,
+    base_url: Url,
+    streams: Vec<Stream>,
+    raw_sdp: Bytes,
+}
+
+impl Description {
+    /// Returns the URL the `DESCRIBE` was sent to, after any redirects.
+    pub fn url(&self) -> &Url {
+        &self.url
+    }
+
+    /// Returns the base URL for control URLs; see [`Session::base_url`].
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Returns the stream descriptors, indexed by stream id.
+    pub fn streams(&self) -> &[Stream] {
+        &self.streams
+    }
+
+    /// Returns the SDP from the `DESCRIBE` response, exactly as received.
+    pub fn raw_sdp(&self) -> &Bytes {
+        &self.raw_sdp
+    }
+}
+
+/// Connects to `url`, sends `DESCRIBE`, and returns the description, then
+/// closes the connection.
+///
+/// This is a lighter-weight alternative to [`Session::describe`] for callers
+/// which only need the SDP, eg to cache or validate it. No `SETUP` or
+/// `PLAY` is sent, so there's no session to tear down. Redirects and
+/// authentication are handled as in [`Session::describe`]. If `creds` is
+/// supplied, it takes precedence over any [`SessionOptions::creds`];
+/// otherwise those are used.
+pub async fn describe(
+    url: Url,
+    creds: Option<Credentials>,
+    options: SessionOptions,
+) -> Result<Description, Error> {
+    let options = match creds {
+        Some(_) => options.creds(creds),
+        None => options,
+    };
+    let conn = RtspConnection::connect(&url, &options).await?;
+    describe_with_conn(conn, options, url).await
+}
+
+async fn describe_with_conn(
+    conn: RtspConnection,
+    options: SessionOptions,
+    url: Url,
+) -> Result<Description, Error> {
+    let mut session = Session::describe_with_conn(conn, options, url).await?;
+    let presentation = session.0.as_mut().project().presentation;
+    Ok(Description {
+        url: presentation.request_url.clone(),
+        base_url: presentation.base_url.clone(),
+        streams: std::mem::take(&mut presentation.streams),
+        raw_sdp: presentation.raw_sdp.clone(),
+    })
+}
+
+/// Policy for retrying transient failures in [`connect_with_retry`].
+#[derive(Copy, Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: std::time::Duration,
+    backoff_factor: f32,
+    max_delay: std::time::Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            base_delay: std::time::Duration::from_secs(1),
+            backoff_factor: 2.0,
+            max_delay: std::time::Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Sets the total number of attempts, including the first. Defaults to 5.
+    pub fn max_attempts(self, max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..self
+        }
+    }
+
+    /// Sets the delay before the first retry. Defaults to 1 second.
+    pub fn base_delay(self, base_delay: std::time::Duration) -> Self {
+        Self { base_delay, ..self }
+    }
+
+    /// Sets the factor by which the delay grows after each retry. Defaults to 2.
+    ///
+    /// Panics if `backoff_factor` is not a finite number of at least 1.
+    pub fn backoff_factor(self, backoff_factor: f32) -> Self {
+        assert!(
+            backoff_factor.is_finite() && backoff_factor >= 1.,
+            "backoff_factor {} must be finite and at least 1",
+            backoff_factor
+        );
+        Self {
+            backoff_factor,
+            ..self
+        }
+    }
+
+    /// Sets the longest delay between attempts. Defaults to 30 seconds.
+    pub fn max_delay(self, max_delay: std::time::Duration) -> Self {
+        Self { max_delay, ..self }
+    }
+
+    /// Returns the delay to use after `delay`.
+    fn next_delay(&self, delay: std::time::Duration) -> std::time::Duration {
+        // Compare in floating point, as the product may not fit in a `Duration`.
+        let next = delay.as_secs_f64() * f64::from(self.backoff_factor);
+        if next >= self.max_delay.as_secs_f64() {
+            self.max_delay
+        } else {
+            std::time::Duration::from_secs_f64(next)
+        }
+    }
+}
+
+/// Connects to `url` and sends `DESCRIBE`, as in [`Session::describe`],
+/// retrying transient failures according to `policy`.
+///
+/// Cameras commonly refuse connections, drop them, or return `5xx` statuses
+/// (such as `503 Service Unavailable`) while booting or when busy. These are
+/// retried, as are timeouts. Other errors, notably authentication failures,
+/// are returned immediately. If all attempts fail, returns the last error.
+///
+/// Uses `creds` unless `options` specifies others.
+pub async fn connect_with_retry(
+    url: Url,
+    creds: Option<Credentials>,
+    options: SessionOptions,
+    policy: RetryPolicy,
+) -> Result<Session<Described>, Error> {
+    let options = match options.creds {
+        Some(_) => options,
+        None => options.creds(creds),
+    };
+    let mut delay = policy.base_delay.min(policy.max_delay);
+    let mut attempt = 1;
+    loop {
+        let e = match Session::describe(url.clone(), options.clone()).await {
+            Ok(s) => return Ok(s),
+            Err(e) => e,
+        };
+        if attempt >= policy.max_attempts || !is_transient(&e) {
+            return Err(e);
+        }
+        warn!(
+            "Attempt {}/{} to describe {} failed; retrying in {:?}: {}",
+            attempt, policy.max_attempts, &url, delay, e
+        );
+        tokio::time::sleep(delay).await;
+        delay = policy.next_delay(delay);
+        attempt += 1;
+    }
+}
+
+/// Returns true if `e` may succeed on retry; see [`connect_with_retry`].
+fn is_transient(e: &Error) -> bool {
+    match &*e.0 {
+        ErrorInt::ConnectError(_)
+        | ErrorInt::RtspReadError { .. }
+        | ErrorInt::WriteError { .. }
+        | ErrorInt::Timeout => true,
+        ErrorInt::RtspResponseError { status, .. } => (500..600).contains(&u16::from(*status)),
+        _ => false,
+    }
+}
+
+#[pin_project(PinnedDrop)]
+struct SessionInner {
+    /// The connection. Currently there's expected to always be a RTSP
+    /// connection, even while playing a RTP/AVP/UDP session. The only
+    /// exception is during drop.
+    conn: Option<RtspConnection>,
+
+    /// A handle to the tokio runtime which created this session. It will
+    /// be used to asynchronously send a `TEARDOWN` on drop.
+    runtime_handle: Option<tokio::runtime::Handle>,
+
+    options: SessionOptions,
+    requested_auth: Option<digest_access::DigestAccess>,
+    presentation: Presentation,
+
+    /// This will be set iff one or more `SETUP` calls have been issued.
+    /// This is sometimes true in state `Described` and always true in state
+    /// `Playing`.
+    session: Option<parse::SessionHeader>,
+
+    // Keep some information about the DESCRIBE response. If a depacketizer
+    // couldn't be constructed correctly for one or more streams, this will be
+    // used to create a RtspResponseError on `State<Playing>::demuxed()`.
+    // We defer such errors from DESCRIBE time until then because they only
+    // matter if the stream is setup and the caller wants depacketization.
+    describe_ctx: RtspMessageContext,
+    describe_cseq: u32,
+    describe_status: rtsp_types::StatusCode,
+
+    /// The state of the keepalive request; only used in state `Playing`.
+    keepalive_state: KeepaliveState,
+
+    keepalive_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+
+    /// Expires if no RTP/RTCP data arrives within
+    /// [`SessionOptions::data_timeout`]; only used in state `Playing`.
+    data_timer: Option<Pin<Box<tokio::time::Sleep>>>,
+
+    /// The capture file, if [`SessionOptions::capture_to`] was set and no
+    /// write has failed.
+    capture: Option<capture::Capture>,
+
+    /// The SSRC this client uses when sending RTCP feedback.
+    rtcp_ssrc: u32,
+
+    /// The sequence number of the next RTCP FIR; see [`Session::request_keyframe`].
+    fir_seq: u8,
+
+    /// Set once [`LossPolicy::RequestKeyframe`] has been skipped due to
+    /// [`SessionOptions::send_rtcp`], so that's logged only once.
+    logged_keyframe_skip: bool,
+
+    /// Set if the server may be in state Playing: we have sent a `PLAY`
+    /// request, regardless of if the response has been received.
+    maybe_playing: bool,
+    has_live555_tcp_bug: bool,
+
+    /// The index within `presentation.streams` to start the next poll at.
+    /// Round-robining between them rather than always starting at 0 should
+    /// prevent one stream from starving the others.
+    udp_next_poll_i: usize,
+
+    /// The `Range` header of the `PLAY` response, if any.
+    play_range: Option<Range>,
+
+    /// The `Scale` header of the `PLAY` response, if any.
+    play_scale: Option<f32>,
+
+    /// The most recent `x-Dynamic-Rate` header of a `SETUP` or `PLAY` response.
+    dynamic_rate: Option<bool>,
+
+    /// The parsed `RTP-Info` header of the `PLAY` response.
+    rtp_info: Vec<RtpInfoEntry>,
+
+    /// The options of the successful `PLAY`, reused by [`reconnect`].
+    play_options: Option<PlayOptions>,
+}
+
+impl RtspConnection {
+    async fn connect(url: &Url, options: &SessionOptions) -> Result<Self, Error> {
+        if let Some(dscp) = options.dscp.filter(|&d| d >= 64) {
+            bail!(ErrorInt::InvalidArgument(format!(
+                "DSCP {} doesn't fit in 6 bits",
+                dscp
+            )));
+        }
+        if options.reorder_depth >= 0x8000 {
+            bail!(ErrorInt::InvalidArgument(format!(
+                "reorder buffer depth {} must be less than 32768",
+                options.reorder_depth
+            )));
+        }
+        let host =
+            RtspConnection::validate_url(url).map_err(|e| wrap!(ErrorInt::InvalidArgument(e)))?;
+        if let Some(addr) = options.bind_address {
+            if let Err(e) = std::net::UdpSocket::bind((addr, 0)) {
+                bail!(ErrorInt::InvalidArgument(format!(
+                    "can't bind to {}; is it a local address? {}",
+                    addr, e
+                )));
+            }
+        }
+        let connect = async {
+            match options.transport {
+                #[cfg(feature = "tls")]
+                Transport::HttpTunnel if url.scheme() == "rtsps" => Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "HTTP tunneling isn't supported with rtsps URLs",
+                )),
+                #[cfg(feature = "tls")]
+                _ if url.scheme() == "rtsps" => {
+                    crate::tokio::Connection::connect_tls(
+                        host,
+                        port(url, options.transport),
+                        options.bind_address,
+                        options.resolver.as_ref(),
+                        options.tls_config.as_ref(),
+                        options.danger_accept_invalid_certs,
+                        options.tls_server_name.as_deref(),
+                    )
+                    .await
+                }
+                Transport::HttpTunnel => {
+                    crate::tokio::Connection::connect_http_tunnel(
+                        host,
+                        port(url, options.transport),
+                        &url[url::Position::BeforePath..],
+                        options.bind_address,
+                        options.resolver.as_ref(),
+                    )
+                    .await
+                }
+                _ => {
+                    crate::tokio::Connection::connect(
+                        host,
+                        port(url, options.transport),
+                        options.bind_address,
+                        options.resolver.as_ref(),
+                    )
+                    .await
+                }
+            }
+        };
+        let mut inner = match options.connect_timeout {
+            None => connect.await,
+            Some(timeout) => tokio::time::timeout(timeout, connect)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::TimedOut,
+                        format!("connect timed out after {:?}", timeout),
+                    ))
+                }),
+        }
+        .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
+        inner.set_lenient(options.lenient);
+        inner.set_transcript(options.transcript.clone());
+        inner
+            .set_tcp_options(options.tcp_keepalive, options.tcp_nodelay)
+            .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
+        if let Some(dscp) = options.dscp {
+            inner
+                .set_dscp(dscp)
+                .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
+        }
+        Ok(Self {
+            inner,
+            channels: ChannelMappings::default(),
+            next_cseq: 1,
+            deferred_data: std::collections::VecDeque::new(),
+        })
+    }
+
+    fn validate_url(url: &Url) -> Result<url::Host<&str>, String> {
+        match url.scheme() {
+            "rtsp" => {}
+            #[cfg(feature = "tls")]
+            "rtsps" => {}
+            #[cfg(not(feature = "tls"))]
+            "rtsps" => {
+                return Err(format!(
+                    "Bad URL {}; scheme rtsps requires the tls feature",
+                    url.as_str()
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "Bad URL {}; only schemes rtsp and rtsps supported",
+                    url.as_str()
+                ))
+            }
+        }
+        if url.username() != "" || url.password().is_some() {
+            // Url apparently doesn't even have a way to clear the credentials,
+            // so this has to be an error.
+            // TODO: that's not true; revisit this.
+            return Err("URL must not contain credentials".to_owned());
+        }
+        url.host()
+            .ok_or_else(|| format!("Must specify host in rtsp url {}", &url))
+    }
+
+    /// Sends a request and expects the next message from the peer to be its response.
+    /// Takes care of authorization and `CSeq`. Returns `Error` if not successful.
+    async fn send(
+        &mut self,
+        mode: ResponseMode,
+        options: &SessionOptions,
+        requested_auth: &mut Option<digest_access::DigestAccess>,
+        req: &mut rtsp_types::Request<Bytes>,
+    ) -> Result<(RtspMessageContext, u32, rtsp_types::Response<Bytes>), Error> {
+        let mut earlier_cseq = mode.earlier_cseq();
+        let mut dropped_data = false;
+        loop {
+            let cseq = self.fill_req(options, requested_auth, req)?;
+            self.inner
+                .send(rtsp_types::Message::Request(req.clone()))
+                .await
+                .map_err(|e| wrap!(e))?;
+            let method: &str = req.method().into();
+            let (resp, msg_ctx) = loop {
+                let msg = match options.response_timeout {
+                    None => self.inner.next().await,
+                    Some(timeout) => tokio::time::timeout(timeout, self.inner.next())
+                        .await
+                        .map_err(|_| {
+                            wrap!(ErrorInt::RtspReadError {
+                                conn_ctx: *self.inner.ctx(),
+                                msg_ctx: self.inner.eof_ctx(),
+                                source: std::io::Error::new(
+                                    std::io::ErrorKind::TimedOut,
+                                    format!(
+                                        "No response to {} CSeq {} within {:?}",
+                                        method, cseq, timeout
+                                    ),
+                                ),
+                            })
+                        })?,
+                };
+                let msg = msg.unwrap_or_else(|| {
+                    bail!(ErrorInt::RtspReadError {
+                        conn_ctx: *self.inner.ctx(),
+                        msg_ctx: self.inner.eof_ctx(),
+                        source: std::io::Error::new(
+                            std::io::ErrorKind::UnexpectedEof,
+                            format!("EOF while expecting response to {} CSeq {}", method, cseq),
+                        ),
+                    })
+                })?;
+                let msg_ctx = msg.ctx;
+                let description = match msg.msg {
+                    rtsp_types::Message::Response(r) => {
+                        if let Some(response_cseq) = parse::get_cseq(&r, options.lenient) {
+                            if response_cseq == cseq {
+                                break (r, msg_ctx);
+                            }
+                            if matches!(mode, ResponseMode::Teardown(_) | ResponseMode::Control(_))
+                            {
+                                if earlier_cseq == Some(response_cseq) {
+                                    earlier_cseq = None;
+                                }
+                                debug!("ignoring unrelated response during {}", method);
+                                continue;
+                            }
+                            format!("{} response with CSeq {}", r.reason_phrase(), response_cseq)
+                        } else if let (true, Some(earlier)) = (options.lenient, earlier_cseq) {
+                            debug!(
+                                "Lenient parsing: treating response with no/unparseable CSeq \
+                                 as response to earlier CSeq {} during {}",
+                                earlier, method
+                            );
+                            earlier_cseq = None;
+                            continue;
+                        } else if options.lenient {
+                            debug!(
+                                "Lenient parsing: treating response with no/unparseable CSeq \
+                                 as response to {} CSeq {}",
+                                method, cseq
+                            );
+                            break (r, msg_ctx);
+                        } else {
+                            format!("{} response with no/unparseable cseq", r.reason_phrase())
+                        }
+                    }
+                    rtsp_types::Message::Data(d) => {
+                        if matches!(mode, ResponseMode::Control(_)) {
+                            if self.deferred_data.len() < MAX_DEFERRED_DATA {
+                                self.deferred_data.push_back((msg_ctx, d));
+                            } else if !dropped_data {
+                                warn!(
+                                    "Dropping interleaved data beyond {} messages received while \
+                                     awaiting response to {} CSeq {}; expect packet loss",
+                                    MAX_DEFERRED_DATA, method, cseq
+                                );
+                                dropped_data = true;
+                            }
+                            continue;
+                        } else if matches!(mode, ResponseMode::Teardown(_)) {
+                            debug!("ignoring RTSP data during {}", method);
+                            continue;
+                        } else if let (ResponseMode::Play, Some(m)) =
+                            (&mode, self.channels.lookup(d.channel_id()))
+                        {
+                            if m.channel_type == ChannelType::Rtcp {
+                                debug!(
+                                    "ignoring interleaved data message on RTCP channel {} while \
+                                     waiting for response to {} CSeq {}",
+                                    d.channel_id(),
+                                    method,
+                                    cseq
+                                );
+                                continue;
+                            }
+                        }
+
+                        if let Some(session_group) = options.session_group.as_ref() {
+                            session_group.note_stale_live555_data();
+                        }
+
+                        format!(
+                            "{}-byte interleaved data message on channel {}",
+                            d.len(),
+                            d.channel_id()
+                        )
+                    }
+                    rtsp_types::Message::Request(r) => format!("{:?} request", r.method()),
+                };
+                bail!(ErrorInt::RtspFramingError {
+                    conn_ctx: *self.inner.ctx(),
+                    msg_ctx,
+                    description: format!(
+                        "Expected response to {} CSeq {}, got {}",
+                        method, cseq, description,
+                    ),
+                });
+            };
+            if resp.status() == rtsp_types::StatusCode::Unauthorized {
+                if requested_auth.is_some() {
+                    // TODO: the WWW-Authenticate might indicate a new domain or nonce.
+                    // In that case, we should retry rather than returning error.
+                    bail!(ErrorInt::RtspResponseError {
+                        conn_ctx: *self.inner.ctx(),
+                        msg_ctx,
+                        method: req.method().clone(),
+                        cseq,
+                        status: resp.status(),
+                        description: "Received Unauthorized after trying digest auth".into(),
+                    })
+                }
+                let www_authenticate = match resp.header(&rtsp_types::headers::WWW_AUTHENTICATE) {
+                    None => bail!(ErrorInt::RtspResponseError {
+                        conn_ctx: *self.inner.ctx(),
+                        msg_ctx,
+                        method: req.method().clone(),
+                        cseq,
+                        status: resp.status(),
+                        description: "Unauthorized without WWW-Authenticate header".into(),
+                    }),
+                    Some(h) => h,
+                };
+                let www_authenticate = www_authenticate.as_str();
+                if !www_authenticate.starts_with("Digest ") {
+                    // TODO: the header(s) might also indicate both Basic and Digest; we shouldn't
+                    // error or not based on ordering.
+                    bail!(ErrorInt::RtspResponseError {
+                        conn_ctx: *self.inner.ctx(),
+                        msg_ctx,
+                        method: req.method().clone(),
+                        cseq,
+                        status: resp.status(),
+                        description: format!(
+                            "Non-digest authentication requested: {}",
+                            www_authenticate
+                        ),
+                    })
+                }
+                if options.creds.is_none() {
+                    bail!(ErrorInt::RtspResponseError {
+                        conn_ctx: *self.inner.ctx(),
+                        msg_ctx,
+                        method: req.method().clone(),
+                        cseq,
+                        status: resp.status(),
+                        description: "Authentication requested and no credentials supplied"
+                            .to_owned(),
+                    })
+                }
+                let www_authenticate = www_authenticate
+                    .parse::<digest_access::DigestAccess>()
+                    .map_err(|e| {
+                        wrap!(ErrorInt::RtspResponseError {
+                            conn_ctx: *self.inner.ctx(),
+                            msg_ctx,
+                            method: req.method().clone(),
+                            cseq,
+                            status: resp.status(),
+                            description: format!(
+                                "Bad WWW-Authenticate header {:?}: {}",
+                                www_authenticate, e
+                            ),
+                        })
+                    })?;
+                *requested_auth = Some(www_authenticate);
+                continue;
+            } else if !resp.status().is_success()
+                && !(matches!(mode, ResponseMode::AllowRedirect) && is_redirect(resp.status()))
+            {
+                let description = match resp.header(&rtsp_types::headers::UNSUPPORTED) {
+                    Some(unsupported) if u16::from(resp.status()) == 551 => format!(
+                        "Server doesn't support required feature(s): {}",
+                        unsupported.as_str()
+                    ),
+                    _ => "Unexpected RTSP response status".into(),
+                };
+                bail!(ErrorInt::RtspResponseError {
+                    conn_ctx: *self.inner.ctx(),
+                    msg_ctx,
+                    method: req.method().clone(),
+                    cseq,
+                    status: resp.status(),
+                    description,
+                });
+            }
+            return Ok((msg_ctx, cseq, resp));
+        }
+    }
+
+    /// Fills out `req` with authorization and `CSeq` headers.
+    fn fill_req(
+        &mut self,
+        options: &SessionOptions,
+        requested_auth: &mut Option<digest_access::DigestAccess>,
+        req: &mut rtsp_types::Request<Bytes>,
+    ) -> Result<u32, Error> {
+        let cseq = self.next_cseq;
+        self.next_cseq += 1;
+        if let Some(ref mut auth) = requested_auth {
+            let creds = options
+                .creds
+                .as_ref()
+                .expect("creds were checked when filling request_auth");
+
+            let uri = req.request_uri().map(|u| u.as_str()).unwrap_or("*");
+            let method = req.method().into();
+            auth.set_username(&creds.username);
+            auth.set_password(&creds.password);
+
+            if let Some(authorization) = auth.generate_authorization(method, uri, None, None) {
+                req.insert_header(rtsp_types::headers::AUTHORIZATION, authorization);
+            }
+        }
+        for (name, value) in &options.extra_headers {
+            if req.header(name).is_none() {
+                req.insert_header(name.clone(), value.clone());
+            }
+        }
+        req.insert_header(rtsp_types::headers::CSEQ, cseq.to_string());
+        if !options.supported_features.is_empty() {
+            req.insert_header(
+                rtsp_types::headers::SUPPORTED,
+                options.supported_features.join(", "),
+            );
+        }
+        if let Some(ref u) = options.user_agent {
+            req.insert_header(rtsp_types::headers::USER_AGENT, u.to_string());
+        }
+        Ok(cseq)
+    }
+}
+
+impl<S: State> Session<S> {
+    /// Returns the transport in use.
+    ///
+    /// This is the transport chosen via [`SessionOptions::transport`] or
+    /// [`SessionOptions::transport_policy`], except that it changes to
+    /// [`Transport::Tcp`] if the [`TransportPolicy::UdpThenTcp`] fallback
+    /// happens during [`Session::play`].
+    pub fn active_transport(&self) -> Transport {
+        self.0.options.transport
+    }
+
+    /// Returns reception statistics for the given stream.
+    ///
+    /// Returns `None` if the stream doesn't exist or isn't playing.
+    pub fn stream_stats(&self, stream_id: usize) -> Option<rtp::StreamStats> {
+        match self.0.presentation.streams.get(stream_id)?.state {
+            StreamState::Playing {
+                ref rtp_handler, ..
+            } => Some(rtp_handler.stats()),
+            _ => None,
+        }
+    }
+
+    /// Returns the [RFC 3550 interarrival
+    /// jitter](https://datatracker.ietf.org/doc/html/rfc3550#section-6.4.1)
+    /// estimate for the given stream, in clock rate units.
+    ///
+    /// Returns `None` if the stream doesn't exist or isn't playing.
+    pub fn stream_jitter(&self, stream_id: usize) -> Option<u32> {
+        match self.0.presentation.streams.get(stream_id)?.state {
+            StreamState::Playing {
+                ref rtp_handler, ..
+            } => Some(rtp_handler.jitter()),
+            _ => None,
+        }
+    }
+
+    /// Returns the received bitrate of the given stream, in bits per second.
+    ///
+    /// This counts RTP headers as well as payloads, averaged over the trailing
+    /// five seconds, so it ramps up over the first five seconds of playback and
+    /// decays to zero within five seconds of packets stopping.
+    ///
+    /// Returns `None` if the stream doesn't exist or isn't playing.
+    pub fn stream_bitrate(&self, stream_id: usize) -> Option<u32> {
+        match self.0.presentation.streams.get(stream_id)?.state {
+            StreamState::Playing {
+                ref rtp_handler, ..
+            } => Some(rtp_handler.bitrate()),
+            _ => None,
+        }
+    }
+
+    /// Returns the `Range` from the `PLAY` response, if any.
+    ///
+    /// This may differ from the range requested via [`PlayOptions::range`],
+    /// as servers may ignore or adjust it.
+    pub fn play_range(&self) -> Option<&Range> {
+        self.0.play_range.as_ref()
+    }
+
+    /// Returns the `Scale` from the `PLAY` response, if any.
+    ///
+    /// This may differ from the scale requested via [`PlayOptions::scale`],
+    /// as servers may ignore or adjust it.
+    pub fn play_scale(&self) -> Option<f32> {
+        self.0.play_scale
+    }
+
+    /// Returns the server's `x-Dynamic-Rate` decision from the most recent
+    /// `SETUP` or `PLAY` response which included one.
+    ///
+    /// This is typically only sent when enabled via
+    /// [`SessionOptions::accept_dynamic_rate`]. `Some(true)` means the server
+    /// may change its encoding rate dynamically.
+    pub fn dynamic_rate(&self) -> Option<bool> {
+        self.0.dynamic_rate
+    }
+
+    /// Consumes the session, returning its connection context and streams.
+    ///
+    /// This works in any state, including after the session has returned a
+    /// fatal error. The session is then dropped, sending a `TEARDOWN` as
+    /// described in [`SessionOptions::teardown`].
+    pub fn into_parts(mut self) -> SessionParts {
+        let inner = self.0.as_mut().project();
+        SessionParts {
+            conn_ctx: *inner
+                .conn
+                .as_ref()
+                .expect("conn is present until drop")
+                .inner
+                .ctx(),
+            url: inner.presentation.request_url.clone(),
+            creds: inner.options.creds.clone(),
+            streams: std::mem::take(&mut inner.presentation.streams),
+            play_options: inner.play_options.take().unwrap_or_default(),
+        }
+    }
+
+    /// Returns the entries of the `PLAY` response's `RTP-Info` header.
+    ///
+    /// This is empty before `PLAY` or if the server sent no `RTP-Info`.
+    /// Entries are in the server's order and may include streams which
+    /// weren't set up.
+    pub fn rtp_info(&self) -> &[RtpInfoEntry] {
+        &self.0.rtp_info
+    }
+
+    /// Sends a `TEARDOWN` and waits for its response, ending the session.
+    ///
+    /// Gives up with an error if the response doesn't arrive within
+    /// [`TEARDOWN_TIMEOUT`]. If this returns success, the server has
+    /// acknowledged the `TEARDOWN`, and dropping the `Session` won't send
+    /// another one regardless of [`SessionOptions::teardown`].
+    ///
+    /// Fails if no `SETUP` has been issued or the session has already been
+    /// torn down.
+    pub async fn teardown(&mut self) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let session_id = match inner.session.as_ref() {
+            Some(s) => s.id.to_string(),
+            None => bail!(ErrorInt::FailedPrecondition(
+                "no session to tear down".into()
+            )),
+        };
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let mut req = rtsp_types::Request::builder(Method::Teardown, rtsp_types::Version::V1_0)
+            .request_uri(inner.presentation.base_url.clone())
+            .header(rtsp_types::headers::SESSION, session_id)
+            .build(Bytes::new());
+        tokio::time::timeout(
+            TEARDOWN_TIMEOUT,
+            conn.send(
+                ResponseMode::Teardown(inner.keepalive_state.outstanding()),
+                inner.options,
+                inner.requested_auth,
+                &mut req,
+            ),
+        )
+        .await
+        .map_err(|_| wrap!(ErrorInt::Timeout))??;
+        *inner.session = None;
+        *inner.maybe_playing = false;
+        for s in &mut inner.presentation.streams {
+            if let Some(sockets) = &mut s.sockets {
+                sockets.leave_multicast();
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a `GET_PARAMETER` request with the given body on the established
+    /// session, returning the response body.
+    ///
+    /// Cameras commonly expose vendor-specific parameters (such as PTZ
+    /// position) this way. The body is sent with `Content-Type:
+    /// text/parameters`, as in [RFC 2326 section
+    /// 10.8](https://datatracker.ietf.org/doc/html/rfc2326#section-10.8).
+    ///
+    /// While playing via an interleaved transport, RTP and RTCP data which
+    /// arrives before the response is deferred, to be returned by the
+    /// session's stream afterward. The response to any outstanding keepalive
+    /// is discarded; this exchange takes its place.
+    ///
+    /// Fails if no `SETUP` has been issued or the session has been torn down.
+    pub async fn get_parameter(&mut self, body: &[u8]) -> Result<Bytes, Error> {
+        let response = self.parameter_request(Method::GetParameter, body).await?;
+        Ok(response.into_body())
+    }
+
+    /// Sends a `SET_PARAMETER` request with the given body on the established
+    /// session; see [`Session::get_parameter`].
+    pub async fn set_parameter(&mut self, body: &[u8]) -> Result<(), Error> {
+        self.parameter_request(Method::SetParameter, body).await?;
+        Ok(())
+    }
+
+    async fn parameter_request(
+        &mut self,
+        method: Method,
+        body: &[u8],
+    ) -> Result<rtsp_types::Response<Bytes>, Error> {
+        let inner = self.0.as_mut().project();
+        let session_id = match inner.session.as_ref() {
+            Some(s) => s.id.to_string(),
+            None => bail!(ErrorInt::FailedPrecondition("no session".into())),
+        };
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let mut req = rtsp_types::Request::builder(method, rtsp_types::Version::V1_0)
+            .request_uri(inner.presentation.base_url.clone())
+            .header(rtsp_types::headers::SESSION, session_id)
+            .header(
+                rtsp_types::headers::CONTENT_TYPE,
+                "text/parameters".to_owned(),
+            )
+            .header(rtsp_types::headers::CONTENT_LENGTH, body.len().to_string())
+            .build(Bytes::copy_from_slice(body));
+        let (_, _, response) = conn
+            .send(
+                ResponseMode::Control(inner.keepalive_state.outstanding()),
+                inner.options,
+                inner.requested_auth,
+                &mut req,
+            )
+            .await?;
+        *inner.keepalive_state = KeepaliveState::Idle;
+        Ok(response)
+    }
+}
+
+impl Session<Described> {
+    /// Creates a new session from a `DESCRIBE` request on the given URL.
+    ///
+    /// This method is permissive; it will return success even if there are
+    /// errors in the SDP that would prevent one or more streams from being
     /// depacketized correctly. If those streams are setup via
     /// `Session<Described>::setup`, the erorrs in question will be ultimately
     /// returned from `Stream<Playing>::demuxed`.
     ///
-    /// Expects to be called from a tokio runtime.
-    pub async fn describe(url: Url, options: SessionOptions) -> Result<Self, Error> {
-        let conn = RtspConnection::connect(&url).await?;
-        Self::describe_with_conn(conn, options, url).await
+    /// Expects to be called from a tokio runtime.
+    pub async fn describe(url: Url, options: SessionOptions) -> Result<Self, Error> {
+        let conn = RtspConnection::connect(&url, &options).await?;
+        Self::describe_with_conn(conn, options, url).await
+    }
+
+    async fn describe_with_conn(
+        mut conn: RtspConnection,
+        options: SessionOptions,
+        mut url: Url,
+    ) -> Result<Self, Error> {
+        conn.inner.set_transcript(options.transcript.clone());
+        let mut requested_auth = None;
+        let mut visited = Vec::new();
+        let (msg_ctx, cseq, response) = loop {
+            let mut req = rtsp_types::Request::builder(Method::Describe, rtsp_types::Version::V1_0)
+                .header(rtsp_types::headers::ACCEPT, "application/sdp")
+                .request_uri(url.clone());
+            if let Some(require) = options.require_header() {
+                req = req.header(rtsp_types::headers::REQUIRE, require);
+            }
+            let mut req = req.build(Bytes::new());
+            let (msg_ctx, cseq, response) = conn
+                .send(
+                    ResponseMode::AllowRedirect,
+                    &options,
+                    &mut requested_auth,
+                    &mut req,
+                )
+                .await?;
+            if !is_redirect(response.status()) {
+                break (msg_ctx, cseq, response);
+            }
+            let response_error = |description| {
+                wrap!(ErrorInt::RtspResponseError {
+                    conn_ctx: *conn.inner.ctx(),
+                    msg_ctx,
+                    method: rtsp_types::Method::Describe,
+                    cseq,
+                    status: response.status(),
+                    description,
+                })
+            };
+            let location = response
+                .header(&rtsp_types::headers::LOCATION)
+                .ok_or_else(|| response_error("Redirect without Location header".to_owned()))?;
+            let location = url.join(location.as_str()).map_err(|e| {
+                response_error(format!(
+                    "Bad Location header {:?}: {}",
+                    location.as_str(),
+                    e
+                ))
+            })?;
+            if visited.len() >= usize::from(options.max_redirects) {
+                return Err(response_error(format!(
+                    "Redirect to {} exceeds max_redirects={}",
+                    location, options.max_redirects
+                )));
+            }
+            visited.push(url);
+            if visited.contains(&location) {
+                return Err(response_error(format!(
+                    "Redirect loop: {} was already visited",
+                    location
+                )));
+            }
+            let prev = visited.last().expect("visited is non-empty");
+            if prev.scheme() == "rtsps" && location.scheme() != "rtsps" {
+                return Err(response_error(format!(
+                    "Refusing to follow redirect from {} to non-TLS {}",
+                    prev, location
+                )));
+            }
+            debug!("Following DESCRIBE redirect to {}", &location);
+            let same_server = prev.scheme() == location.scheme()
+                && prev.host() == location.host()
+                && port(&prev, options.transport) == port(&location, options.transport);
+            if !same_server {
+                conn = RtspConnection::connect(&location, &options).await?;
+                requested_auth = None;
+            }
+            url = location;
+        };
+        let mut presentation = parse::parse_describe(url, &response).map_err(|description| {
+            wrap!(ErrorInt::RtspResponseError {
+                conn_ctx: *conn.inner.ctx(),
+                msg_ctx,
+                method: rtsp_types::Method::Describe,
+                cseq,
+                status: response.status(),
+                description,
+            })
+        })?;
+        for (i, s) in presentation.streams.iter_mut().enumerate() {
+            if !options.is_setup_stream(i) {
+                s.depacketizer = Err("not selected via SessionOptions::setup_streams".to_owned());
+                continue;
+            }
+            configure_depacketizer(s, &options);
+        }
+        let has_live555_tcp_bug = presentation
+            .tool
+            .as_deref()
+            .map(has_live555_tcp_bug)
+            .unwrap_or(false);
+        let capture = options
+            .capture_path
+            .as_deref()
+            .map(|p| {
+                capture::Capture::create(p).map_err(|e| {
+                    wrap!(ErrorInt::InvalidArgument(format!(
+                        "Unable to create capture file {}: {}",
+                        p.display(),
+                        e
+                    )))
+                })
+            })
+            .transpose()?;
+        Ok(Session(
+            Box::pin(SessionInner {
+                conn: Some(conn),
+                options,
+                runtime_handle: tokio::runtime::Handle::try_current().ok(),
+                requested_auth,
+                presentation,
+                session: None,
+                describe_ctx: msg_ctx,
+                describe_cseq: cseq,
+                describe_status: response.status(),
+                keepalive_state: KeepaliveState::Idle,
+                keepalive_timer: None,
+                data_timer: None,
+                capture,
+                rtcp_ssrc: rand::random(),
+                fir_seq: 0,
+                logged_keyframe_skip: false,
+                maybe_playing: false,
+                has_live555_tcp_bug,
+                udp_next_poll_i: 0,
+                play_range: None,
+                play_scale: None,
+                dynamic_rate: None,
+                rtp_info: Vec::new(),
+                play_options: None,
+            }),
+            Described(()),
+        ))
+    }
+
+    pub fn streams(&self) -> &[Stream] {
+        &self.0.presentation.streams
+    }
+
+    /// Returns the SDP from the `DESCRIBE` response, exactly as received.
+    ///
+    /// This can be useful for logging or for diagnosing differences between
+    /// what the server sent and what retina understood.
+    pub fn raw_sdp(&self) -> &Bytes {
+        &self.0.presentation.raw_sdp
+    }
+
+    /// Returns the base URL against which relative control URLs were resolved.
+    ///
+    /// This is the `DESCRIBE` response's `Content-Base` header if present,
+    /// else its `Content-Location`, else the URL the request was sent to
+    /// (after following any redirects). It's useful for debugging
+    /// [`Stream::control`] resolution.
+    pub fn base_url(&self) -> &Url {
+        &self.0.presentation.base_url
+    }
+
+    /// Sends a `SETUP` request for a stream.
+    ///
+    /// Note these can't reasonably be pipelined because subsequent requests
+    /// are expected to adopt the previous response's `Session`. Likewise,
+    /// the server may override the preferred interleaved channel id and it
+    /// seems like a bad idea to try to assign more interleaved channels without
+    /// inspect that first.
+    ///
+    /// Panics if `stream_i >= self.streams().len()`.
+    pub async fn setup(&mut self, stream_i: usize) -> Result<(), Error> {
+        self.setup_with_options(stream_i, SetupOptions::default())
+            .await
+    }
+
+    /// Sends a `SETUP` request for a stream with the given options.
+    ///
+    /// See [`Session::setup`].
+    pub async fn setup_with_options(
+        &mut self,
+        stream_i: usize,
+        setup_options: SetupOptions,
+    ) -> Result<(), Error> {
+        let inner = &mut self.0.as_mut().project();
+        let presentation = &mut inner.presentation;
+        let options = &inner.options;
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let stream = &mut presentation.streams[stream_i];
+        if !matches!(stream.state, StreamState::Uninit) {
+            bail!(ErrorInt::FailedPrecondition("stream already set up".into()));
+        }
+        if !options.is_setup_stream(stream_i) {
+            bail!(ErrorInt::FailedPrecondition(format!(
+                "stream {} not selected via SessionOptions::setup_streams",
+                stream_i
+            )));
+        }
+        if setup_options.clock_rate_override == Some(0) {
+            bail!(ErrorInt::InvalidArgument(
+                "SetupOptions::override_clock_rate must be non-zero".into()
+            ));
+        }
+        let url = stream
+            .control
+            .as_ref()
+            .unwrap_or(&presentation.control)
+            .clone();
+        let mut req = rtsp_types::Request::builder(Method::Setup, rtsp_types::Version::V1_0)
+            .request_uri(url)
+            .header(crate::X_DYNAMIC_RATE.clone(), "1".to_owned());
+        if options.accept_dynamic_rate {
+            req = req.header(crate::X_ACCEPT_DYNAMIC_RATE.clone(), "1".to_owned());
+        }
+        if let Some(require) = options.require_header() {
+            req = req.header(rtsp_types::headers::REQUIRE, require);
+        }
+        #[cfg(feature = "srtp")]
+        let profile = match &stream.srtp {
+            None => "AVP",
+            Some(Ok(_)) => "SAVP",
+            Some(Err(e)) => bail!(ErrorInt::FailedPrecondition(format!(
+                "can't decrypt SRTP stream {}: {}",
+                stream_i, e
+            ))),
+        };
+        #[cfg(not(feature = "srtp"))]
+        let profile = "AVP";
+        let transport = match options.transport {
+            Transport::Tcp | Transport::HttpTunnel => {
+                let proposed_channel_id = conn.channels.next_unassigned().ok_or_else(|| {
+                    wrap!(ErrorInt::FailedPrecondition(
+                        "no unassigned channels".into()
+                    ))
+                })?;
+                format!(
+                    "RTP/{}/TCP;unicast;interleaved={}-{}",
+                    profile,
+                    proposed_channel_id,
+                    proposed_channel_id + 1
+                )
+            }
+            Transport::Udp => {
+                // Bind an ephemeral UDP port on the requested local address or the
+                // same local address used to connect to the RTSP server.
+                let conn_ip = conn.inner.ctx().local_addr.ip();
+                let ip_addr = options.bind_address.unwrap_or(conn_ip);
+                if ip_addr.is_ipv6() != conn_ip.is_ipv6() {
+                    // The server would have no way to reach this socket.
+                    bail!(ErrorInt::InvalidArgument(format!(
+                        "UDP bind address {} doesn't match the address family of the RTSP \
+                         connection's local address {}",
+                        ip_addr, conn_ip
+                    )));
+                }
+                let pair = crate::tokio::UdpPair::for_ip(ip_addr)
+                    .map_err(|e| wrap!(ErrorInt::Internal(e.into())))?;
+                configure_udp_pair(&pair, ip_addr.is_ipv6(), options)?;
+                stream.sockets = Some(UdpSockets {
+                    local_ip: ip_addr,
+                    local_rtp_port: pair.rtp_port,
+                    remote_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    remote_rtp_port: 0,
+                    rtp_socket: pair.rtp_socket,
+                    remote_rtcp_port: 0,
+                    rtcp_socket: pair.rtcp_socket,
+                    connected: false,
+                    multicast_group: None,
+                });
+                let mut transport = format!(
+                    "RTP/{}/UDP;client_port={}-{}",
+                    profile,
+                    pair.rtp_port,
+                    pair.rtp_port + 1,
+                );
+                if options.udp_destination && ip_addr != conn_ip && !ip_addr.is_unspecified() {
+                    // An unspecified bind address receives on the connection's
+                    // address anyway.
+                    transport.push_str(&format!(";destination={}", ip_addr));
+                }
+                transport
+            }
+            Transport::UdpMulticast => format!("RTP/{};multicast", profile),
+        };
+        req = req.header(
+            rtsp_types::headers::TRANSPORT,
+            setup_options.transport_header_override.unwrap_or(transport),
+        );
+        if let Some(ref s) = inner.session {
+            req = req.header(rtsp_types::headers::SESSION, s.id.to_string());
+        }
+        let (msg_ctx, cseq, response) = conn
+            .send(
+                ResponseMode::Normal,
+                &inner.options,
+                &mut inner.requested_auth,
+                &mut req.build(Bytes::new()),
+            )
+            .await?;
+        debug!("SETUP response: {:#?}", &response);
+        if let Some(dynamic_rate) = parse::parse_dynamic_rate(&response) {
+            *inner.dynamic_rate = Some(dynamic_rate);
+        }
+        let conn_ctx = conn.inner.ctx();
+        let status = response.status();
+        let response = parse::parse_setup(&response).map_err(|description| {
+            wrap!(ErrorInt::RtspResponseError {
+                conn_ctx: *conn_ctx,
+                msg_ctx,
+                method: rtsp_types::Method::Setup,
+                cseq,
+                status,
+                description,
+            })
+        })?;
+        match inner.session.as_ref() {
+            Some(SessionHeader { id, .. }) if id.as_ref() != &*response.session.id => {
+                bail!(ErrorInt::RtspResponseError {
+                    conn_ctx: *conn.inner.ctx(),
+                    msg_ctx,
+                    method: rtsp_types::Method::Setup,
+                    cseq,
+                    status,
+                    description: format!(
+                        "session id changed from {:?} to {:?}",
+                        id, response.session.id,
+                    ),
+                });
+            }
+            Some(_) => {}
+            None => *inner.session = Some(response.session),
+        };
+        let conn_ctx = conn.inner.ctx();
+        match options.transport {
+            Transport::Tcp | Transport::HttpTunnel => {
+                let channel_id = match response.channel_id {
+                    Some(id) => id,
+                    None => bail!(ErrorInt::RtspResponseError {
+                        conn_ctx: *conn.inner.ctx(),
+                        msg_ctx,
+                        method: rtsp_types::Method::Setup,
+                        cseq,
+                        status,
+                        description: "Transport header is missing interleaved parameter".to_owned(),
+                    }),
+                };
+                conn.channels
+                    .assign(channel_id, stream_i)
+                    .map_err(|description| {
+                        wrap!(ErrorInt::RtspResponseError {
+                            conn_ctx: *conn_ctx,
+                            msg_ctx,
+                            method: rtsp_types::Method::Setup,
+                            cseq,
+                            status,
+                            description,
+                        })
+                    })?;
+            }
+            Transport::Udp => {
+                // TODO: RFC 2326 section 12.39 says "If the source address for
+                // the stream is different than can be derived from the RTSP
+                // endpoint address (the server in playback or the client in
+                // recording), the source MAY be specified." Not MUST,
+                // unfortunately. But let's see if we can get away with this
+                // for now.
+                let udp_sockets = stream.sockets.as_mut().unwrap();
+                let peer_ip = conn.inner.ctx().peer_addr.ip();
+                let source = match response.source {
+                    Some(s) if s.is_ipv6() != udp_sockets.local_ip.is_ipv6() => {
+                        log::warn!(
+                            "Ignoring SETUP response source={} which doesn't match the \
+                             address family of local UDP address {}; using {}",
+                            s,
+                            udp_sockets.local_ip,
+                            peer_ip
+                        );
+                        peer_ip
+                    }
+                    Some(s) => s,
+                    None => peer_ip,
+                };
+                let server_port = response.server_port.ok_or_else(|| {
+                    wrap!(ErrorInt::RtspResponseError {
+                        conn_ctx: *conn_ctx,
+                        msg_ctx,
+                        method: rtsp_types::Method::Setup,
+                        cseq,
+                        status,
+                        description: "Transport header is missing server_port parameter".to_owned(),
+                    })
+                })?;
+                udp_sockets.remote_ip = source;
+                udp_sockets.remote_rtp_port = server_port.0;
+                udp_sockets.remote_rtcp_port = server_port.1;
+                if options.connected_udp {
+                    udp_sockets
+                        .rtp_socket
+                        .connect(udp_sockets.remote_rtp_addr())
+                        .await
+                        .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
+                    udp_sockets
+                        .rtcp_socket
+                        .connect(udp_sockets.remote_rtcp_addr())
+                        .await
+                        .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
+                    udp_sockets.connected = true;
+                }
+                if options.udp_hole_punch {
+                    punch_firewall_hole(udp_sockets, options.send_rtcp)
+                        .await
+                        .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
+                }
+            }
+            Transport::UdpMulticast => {
+                let (group, ports) = match (response.destination, response.multicast_port) {
+                    (Some(g), Some(p)) if g.is_multicast() => (g, p),
+                    _ => bail!(ErrorInt::RtspResponseError {
+                        conn_ctx: *conn_ctx,
+                        msg_ctx,
+                        method: rtsp_types::Method::Setup,
+                        cseq,
+                        status,
+                        description: "Transport header is missing multicast destination and port"
+                            .to_owned(),
+                    }),
+                };
+                let interface = options
+                    .bind_address
+                    .unwrap_or_else(|| conn_ctx.local_addr.ip());
+                let pair = crate::tokio::UdpPair::for_multicast(
+                    group,
+                    ports,
+                    Some(interface),
+                    response.ttl,
+                )
+                .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
+                configure_udp_pair(&pair, group.is_ipv6(), options)?;
+                stream.sockets = Some(UdpSockets {
+                    local_ip: interface,
+                    local_rtp_port: ports.0,
+                    remote_ip: group,
+                    remote_rtp_port: ports.0,
+                    rtp_socket: pair.rtp_socket,
+                    remote_rtcp_port: ports.1,
+                    rtcp_socket: pair.rtcp_socket,
+                    connected: false,
+                    multicast_group: Some(group),
+                });
+            }
+        }
+        if let Some(clock_rate) = setup_options.clock_rate_override {
+            log::info!(
+                "Overriding stream {} clock rate from SDP's {} Hz to {} Hz",
+                stream_i,
+                stream.clock_rate,
+                clock_rate
+            );
+            stream.clock_rate = clock_rate;
+            stream.depacketizer = crate::codec::Depacketizer::new(
+                &stream.media,
+                &stream.encoding_name,
+                clock_rate,
+                stream.channels,
+                stream.format_specific_params(),
+            );
+            configure_depacketizer(stream, options);
+        }
+        stream.state = StreamState::Init(StreamStateInit {
+            ssrc: response.ssrc,
+            initial_seq: None,
+            initial_rtptime: None,
+        });
+        Ok(())
+    }
+
+    /// Sends `SETUP` requests for all streams selected via
+    /// [`SessionOptions::setup_streams`], or all streams if unspecified.
+    ///
+    /// Streams which have already been set up are skipped.
+    pub async fn setup_all(&mut self) -> Result<(), Error> {
+        let num_streams = self.0.presentation.streams.len();
+        if let Some(s) = self.0.options.setup_streams.as_ref() {
+            if let Some(&i) = s.iter().find(|&&i| i >= num_streams) {
+                bail!(ErrorInt::InvalidArgument(format!(
+                    "SessionOptions::setup_streams specifies stream {}, but \
+                     the presentation has only {} streams",
+                    i, num_streams
+                )));
+            }
+        }
+        for i in 0..num_streams {
+            if self.0.options.is_setup_stream(i)
+                && matches!(self.0.presentation.streams[i].state, StreamState::Uninit)
+            {
+                self.setup(i).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a `PLAY` request for the entire presentation.
+    ///
+    /// The presentation must support aggregate control, as defined in [RFC 2326
+    /// section 1.3](https://tools.ietf.org/html/rfc2326#section-1.3).
+    ///
+    /// With [`TransportPolicy::UdpThenTcp`], if no RTP packet arrives over UDP
+    /// within the fallback timeout, this tears down the session, sets up the
+    /// same streams again over TCP, and reissues the `PLAY`. See
+    /// [`Session::active_transport`].
+    pub async fn play(mut self, policy: PlayOptions) -> Result<Session<Playing>, Error> {
+        self.play_inner(&policy).await?;
+        let options = &self.0.options;
+        if options.fallback_to_tcp && matches!(options.transport, Transport::Udp) {
+            let timeout = options.udp_fallback_timeout;
+            if !self.await_udp_rtp(timeout).await {
+                warn!(
+                    "No RTP packet received via UDP within {:?}; falling back to TCP",
+                    timeout
+                );
+                self.fall_back_to_tcp().await?;
+                self.play_inner(&policy).await?;
+            }
+        }
+        *self.0.as_mut().project().play_options = Some(policy);
+        Ok(Session(self.0, Playing(())))
+    }
+
+    /// Waits up to `timeout` for an RTP packet on any stream set up via UDP.
+    ///
+    /// The packet is left in the socket for normal processing.
+    async fn await_udp_rtp(&self, timeout: std::time::Duration) -> bool {
+        let readable: Vec<_> = self
+            .0
+            .presentation
+            .streams
+            .iter()
+            .filter_map(|s| s.sockets.as_ref())
+            .map(|s| Box::pin(s.rtp_socket.readable()))
+            .collect();
+        if readable.is_empty() {
+            return true;
+        }
+        matches!(
+            tokio::time::timeout(timeout, futures::future::select_all(readable)).await,
+            Ok((Ok(()), _, _))
+        )
+    }
+
+    /// Tears down the current session and sets up all previously set-up
+    /// streams again via TCP.
+    async fn fall_back_to_tcp(&mut self) -> Result<(), Error> {
+        if let Err(e) = self.teardown().await {
+            // Proceed anyway; the server will expire the old session.
+            warn!("Unable to tear down UDP session before TCP fallback: {}", e);
+        }
+        let inner = self.0.as_mut().project();
+        *inner.session = None;
+        *inner.maybe_playing = false;
+        *inner.keepalive_timer = None;
+        *inner.data_timer = None;
+        inner.options.transport = Transport::Tcp;
+        let mut to_setup = Vec::new();
+        for (i, s) in inner.presentation.streams.iter_mut().enumerate() {
+            if !matches!(s.state, StreamState::Uninit) {
+                s.state = StreamState::Uninit;
+                s.sockets = None;
+                to_setup.push(i);
+            }
+        }
+        for i in to_setup {
+            self.setup(i).await?;
+        }
+        Ok(())
+    }
+
+    /// Sends a `PLAY` request and moves all set-up streams to `Playing` state.
+    async fn play_inner(&mut self, policy: &PlayOptions) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let session = inner.session.as_ref().ok_or_else(|| {
+            wrap!(ErrorInt::FailedPrecondition(
+                "must SETUP before PLAY".into()
+            ))
+        })?;
+        if let Some(tool) = inner.presentation.tool.as_deref() {
+            if inner.options.transport.is_interleaved() && *inner.has_live555_tcp_bug {
+                warn!(
+                    "Connecting via TCP to known-broken RTSP server {:?}. \
+                       See <https://github.com/scottlamb/retina/issues/17>. \
+                       Consider using UDP instead!",
+                    tool
+                );
+            }
+        }
+
+        trace!("PLAY with channel mappings: {:#?}", &conn.channels);
+        *inner.maybe_playing = true;
+        let mut req = rtsp_types::Request::builder(Method::Play, rtsp_types::Version::V1_0)
+            .request_uri(inner.presentation.control.clone())
+            .header(rtsp_types::headers::SESSION, &*session.id)
+            .header(
+                rtsp_types::headers::RANGE,
+                policy
+                    .range
+                    .as_ref()
+                    .map(Range::to_string)
+                    .unwrap_or_else(|| "npt=0.000-".to_owned()),
+            );
+        if let Some(scale) = policy.scale {
+            // `Debug` always includes a decimal point, as in `Scale: 2.0`.
+            req = req.header(rtsp_types::headers::SCALE, format!("{:?}", scale));
+        }
+        if inner.options.accept_dynamic_rate {
+            req = req.header(crate::X_ACCEPT_DYNAMIC_RATE.clone(), "1".to_owned());
+        }
+        let (msg_ctx, cseq, response) = conn
+            .send(
+                ResponseMode::Play,
+                &inner.options,
+                inner.requested_auth,
+                &mut req.build(Bytes::new()),
+            )
+            .await?;
+        let play_response =
+            parse::parse_play(&response, inner.presentation).map_err(|description| {
+                wrap!(ErrorInt::RtspResponseError {
+                    conn_ctx: *conn.inner.ctx(),
+                    msg_ctx,
+                    method: rtsp_types::Method::Play,
+                    cseq,
+                    status: response.status(),
+                    description,
+                })
+            })?;
+        *inner.play_range = play_response.range;
+        *inner.play_scale = play_response.scale;
+        *inner.rtp_info = play_response.rtp_info;
+        if let Some(dynamic_rate) = parse::parse_dynamic_rate(&response) {
+            *inner.dynamic_rate = Some(dynamic_rate);
+        }
+
+        let play_start = match inner.play_range.as_ref() {
+            Some(Range::Npt { start, .. }) => Some(*start),
+            _ => None,
+        };
+        let conn_ctx = *conn.inner.ctx();
+        let status = response.status();
+        start_streams(
+            inner.presentation,
+            inner.options,
+            policy,
+            play_start,
+            |description| {
+                wrap!(ErrorInt::RtspResponseError {
+                    conn_ctx,
+                    msg_ctx,
+                    method: rtsp_types::Method::Play,
+                    cseq,
+                    status,
+                    description,
+                })
+            },
+        )?;
+        *inner.keepalive_timer = Some(Box::pin(tokio::time::sleep(session.keepalive_interval())));
+        *inner.data_timer = inner
+            .options
+            .data_timeout
+            .map(|t| Box::pin(tokio::time::sleep(t)));
+        Ok(())
+    }
+}
+
+/// Moves all streams that have been set up from `Init` to `Playing` state,
+/// checking that required parameters are present while doing so.
+///
+/// `err` builds an error describing the `PLAY` response.
+fn start_streams(
+    presentation: &mut Presentation,
+    options: &SessionOptions,
+    policy: &PlayOptions,
+    play_start: Option<std::time::Duration>,
+    err: impl Fn(String) -> Error,
+) -> Result<(), Error> {
+    // Count how many streams have been setup (not how many are in the presentation).
+    let setup_streams = presentation
+        .streams
+        .iter()
+        .filter(|s| matches!(s.state, StreamState::Init(_)))
+        .count();
+
+    let all_have_time = presentation.streams.iter().all(|s| match s.state {
+        StreamState::Init(StreamStateInit {
+            initial_rtptime, ..
+        }) => initial_rtptime.is_some(),
+        _ => true,
+    });
+
+    for (i, s) in presentation.streams.iter_mut().enumerate() {
+        let (initial_rtptime, initial_seq, ssrc) = match s.state {
+            StreamState::Init(StreamStateInit {
+                initial_rtptime,
+                initial_seq,
+                ssrc,
+                ..
+            }) => (initial_rtptime, initial_seq, ssrc),
+            StreamState::Uninit | StreamState::Playing { .. } => continue,
+        };
+        let initial_rtptime = match policy.initial_timestamp {
+            InitialTimestampPolicy::Require | InitialTimestampPolicy::Default
+                if setup_streams > 1 =>
+            {
+                if initial_rtptime.is_none() {
+                    return Err(err(format!(
+                        "Expected rtptime on PLAY with mode {:?}, missing on \
+                         stream {} ({:?}). Consider setting initial timestamp \
+                         mode permissive.",
+                        policy.initial_timestamp, i, &s.control
+                    )));
+                }
+                initial_rtptime
+            }
+            InitialTimestampPolicy::Permissive if setup_streams > 1 && all_have_time => {
+                initial_rtptime
+            }
+            _ => None,
+        };
+        let initial_seq = match initial_seq {
+            Some(0) if policy.ignore_zero_seq => {
+                log::info!("Ignoring seq=0 on stream {}", i);
+                None
+            }
+            o => o,
+        };
+        let mut timeline = Timeline::new(
+            initial_rtptime,
+            s.clock_rate,
+            policy.enforce_timestamps_with_max_jump_secs,
+        )
+        .map_err(&err)?;
+        // Absent a base from `reconnect`, start elapsed time at
+        // the npt the server says `RTP-Info`'s rtptime refers to.
+        let base = match (s.timeline_base, initial_rtptime, play_start) {
+            (Some(base), _, _) => Some(base),
+            (None, Some(_), Some(start)) => npt_base(start, s.clock_rate),
+            _ => None,
+        };
+        timeline.set_base(base);
+        timeline.set_reset_threshold_secs(policy.timestamp_reset_threshold_secs);
+        let mut rtp_handler = rtp::InorderParser::new(ssrc, initial_seq);
+        rtp_handler.set_payload_type(s.rtp_payload_type);
+        if !options.transport.is_interleaved() {
+            rtp_handler.set_reorder_depth(options.reorder_depth);
+        }
+        if let Some(max_hold) = options.reorder_max_hold {
+            rtp_handler.set_max_hold(max_hold);
+        }
+        if let Some(pt) = s.rtx_payload_type {
+            rtp_handler.set_retransmission(pt);
+        }
+        s.state = StreamState::Playing {
+            timeline,
+            rtp_handler,
+        };
+    }
+    Ok(())
+}
+
+/// Returns a timeline base which aligns the `RTP-Info` rtptime with the
+/// `PLAY` response's npt start, or `None` if the start is zero or too large.
+fn npt_base(start: std::time::Duration, clock_rate: u32) -> Option<crate::Timestamp> {
+    if start.as_nanos() == 0 {
+        return None;
+    }
+    let ticks = i64::try_from(start.as_nanos() * u128::from(clock_rate) / 1_000_000_000).ok()?;
+    crate::Timestamp::new(ticks, NonZeroU32::new(clock_rate)?, 0)
+}
+
+/// Returns the port `url` connects to via `transport`: its explicit port, or
+/// the default for its scheme and transport.
+fn port(url: &Url, transport: Transport) -> u16 {
+    url.port().unwrap_or(if url.scheme() == "rtsps" {
+        322
+    } else if matches!(transport, Transport::HttpTunnel) {
+        80
+    } else {
+        554
+    })
+}
+
+/// Returns true iff `status` is a redirect (`3xx`).
+fn is_redirect(status: rtsp_types::StatusCode) -> bool {
+    (300..400).contains(&u16::from(status))
+}
+
+/// Checks if the `tool` entry refers to a live555 version affected by
+/// [#17](https://github.com/scottlamb/retina/issues/17).
+fn has_live555_tcp_bug(tool: &str) -> bool {
+    const PREFIX: &str = "LIVE555 Streaming Media v";
+    if !tool.starts_with(PREFIX) {
+        return false;
+    }
+    let version = &tool[PREFIX.len()..];
+    version > "0000.00.00" && version < "2017.06.04"
+}
+
+/// Applies the depacketization-related [`SessionOptions`] to a stream's
+/// depacketizer, falling back to raw RTP with
+/// [`SessionOptions::allow_unknown_codecs`] or
+/// [`SessionOptions::no_depacketization`].
+fn configure_depacketizer(s: &mut Stream, options: &SessionOptions) {
+    if options.allow_unknown_codecs || options.no_depacketization {
+        if let Err(description) = &s.depacketizer {
+            debug!(
+                "passing through raw RTP for {}/{}: {}",
+                &s.media, &s.encoding_name, description
+            );
+            s.depacketizer = Ok(crate::codec::Depacketizer::raw());
+        }
+    }
+    if let Ok(d) = &mut s.depacketizer {
+        d.set_aac_adts(options.aac_adts);
+        d.set_max_frame_bytes(options.max_frame_bytes);
+        d.set_video_frame_durations(options.video_frame_durations);
+        d.set_passthrough(options.no_depacketization);
+    }
+}
+
+/// Applies [`SessionOptions::dscp`] and [`SessionOptions::udp_recv_buffer_bytes`]
+/// to a newly bound pair of UDP sockets.
+fn configure_udp_pair(
+    pair: &crate::tokio::UdpPair,
+    is_ipv6: bool,
+    options: &SessionOptions,
+) -> Result<(), Error> {
+    if let Some(dscp) = options.dscp {
+        pair.set_dscp(is_ipv6, dscp)
+            .map_err(|e| wrap!(ErrorInt::Internal(e.into())))?;
+    }
+    if let Some(bytes) = options.udp_recv_buffer_bytes {
+        match pair.set_recv_buffer_size(bytes) {
+            Ok(granted) if granted < bytes => warn!(
+                "Requested {}-byte UDP receive buffer; OS granted only {}",
+                bytes, granted
+            ),
+            Ok(granted) => debug!(
+                "Requested {}-byte UDP receive buffer; OS granted {}",
+                bytes, granted
+            ),
+            Err(e) => warn!("Unable to set {}-byte UDP receive buffer: {}", bytes, e),
+        }
+    }
+    Ok(())
+}
+
+/// Sends dummy RTP and RTCP packets to punch a hole in connection-tracking
+/// firewalls.
+///
+/// This is useful when the client is on the protected side of the firewall and
+/// the server isn't. The server should discard these dummy packets, but they
+/// prompt the firewall to add the appropriate connection tracking state for
+/// server->client packets to make it through.
+///
+/// Note this is insufficient for NAT traversal; the NAT firewall must be
+/// RTSP-aware to rewrite the Transport header's client_ports.
+///
+/// Each packet is sent twice in case one is lost. The RTCP packets are
+/// skipped unless `send_rtcp`.
+async fn punch_firewall_hole(sockets: &UdpSockets, send_rtcp: bool) -> Result<(), std::io::Error> {
+    #[rustfmt::skip]
+    const DUMMY_RTP: [u8; 12] = [
+        2 << 6,     // version=2 + p=0 + x=0 + cc=0
+        0,          // m=0 + pt=0
+        0, 0,       // sequence number=0
+        0, 0, 0, 0, // timestamp=0 0,
+        0, 0, 0, 0, // ssrc=0
+    ];
+    #[rustfmt::skip]
+    const DUMMY_RTCP: [u8; 8] = [
+        2 << 6,     // version=2 + p=0 + rc=0
+        200,        // pt=200 (reception report)
+        0, 1,       // length=1 (in 4-byte words minus 1)
+        0, 0, 0, 0, // ssrc=0 (bogus but we don't know the ssrc reliably yet)
+    ];
+    for _ in 0..2 {
+        sockets.send_rtp(&DUMMY_RTP[..]).await?;
+        if send_rtcp {
+            sockets.send_rtcp(&DUMMY_RTCP[..]).await?;
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug)]
+pub enum PacketItem {
+    RtpPacket(rtp::Packet),
+    SenderReport(rtp::SenderReport),
+}
+
+impl Session<Playing> {
+    /// Returns a wrapper which demuxes/depacketizes into frames.
+    ///
+    /// Fails if a stream that has been setup can't be depacketized.
+    pub fn demuxed(mut self) -> Result<Demuxed, Error> {
+        let inner = self.0.as_mut().project();
+        let conn = inner
+            .conn
+            .as_ref()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        for s in &mut inner.presentation.streams {
+            if matches!(s.state, StreamState::Playing { .. }) {
+                if let Err(ref description) = s.depacketizer {
+                    bail!(ErrorInt::RtspResponseError {
+                        conn_ctx: *conn.inner.ctx(),
+                        msg_ctx: *inner.describe_ctx,
+                        method: rtsp_types::Method::Describe,
+                        cseq: *inner.describe_cseq,
+                        status: *inner.describe_status,
+                        description: description.clone(),
+                    });
+                }
+            }
+        }
+        let queue_policy = inner.options.queue_policy;
+        let handle = inner.runtime_handle.clone();
+        let (capacity, drop_oldest) = match queue_policy {
+            QueuePolicy::Direct => {
+                return Ok(Demuxed(DemuxedInner::Direct(DirectDemuxed {
+                    state: DemuxedState::Waiting,
+                    session: self,
+                })))
+            }
+            QueuePolicy::Block(capacity) => (capacity, false),
+            QueuePolicy::DropOldest(capacity) => (capacity, true),
+        };
+        if capacity == 0 {
+            bail!(ErrorInt::InvalidArgument(
+                "queue policy capacity must be non-zero".into()
+            ));
+        }
+        let handle = handle.ok_or_else(|| {
+            wrap!(ErrorInt::FailedPrecondition(
+                "queue policy requires describe to be called from a tokio runtime".into()
+            ))
+        })?;
+        let direct = DirectDemuxed {
+            state: DemuxedState::Waiting,
+            session: self,
+        };
+        Ok(Demuxed(DemuxedInner::Queued(queue::Queue::spawn(
+            &handle,
+            direct,
+            capacity,
+            drop_oldest,
+        ))))
+    }
+
+    pub fn streams(&self) -> &[Stream] {
+        &self.0.presentation.streams
+    }
+
+    /// Temporarily stops media flow by sending a `PAUSE` request.
+    ///
+    /// The session, its streams' RTP state, and any depacketizer state are
+    /// preserved, so that [`Session::resume`] can restart playback without
+    /// the latency of a new `DESCRIBE` and `SETUP`. Keepalives continue
+    /// only while the session is polled, so callers should keep polling (no
+    /// packets are expected) or resume within the session timeout.
+    ///
+    /// While playing via an interleaved transport, RTP and RTCP data which
+    /// arrives before the `PAUSE` response is deferred, to be returned by the
+    /// session's stream afterward. Many cameras don't support `PAUSE`; their
+    /// `405 Method Not Allowed`, `455 Method Not Valid in This State`, or `501
+    /// Not Implemented` response is returned as an error saying so, and the
+    /// session remains playing.
+    pub async fn pause(&mut self) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let session_id = match inner.session.as_ref() {
+            Some(s) => s.id.to_string(),
+            None => bail!(ErrorInt::FailedPrecondition("no session".into())),
+        };
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let mut req = rtsp_types::Request::builder(Method::Pause, rtsp_types::Version::V1_0)
+            .request_uri(inner.presentation.control.clone())
+            .header(rtsp_types::headers::SESSION, session_id)
+            .build(Bytes::new());
+        conn.send(
+            ResponseMode::Control(inner.keepalive_state.outstanding()),
+            inner.options,
+            inner.requested_auth,
+            &mut req,
+        )
+        .await
+        .map_err(|e| match &*e.0 {
+            ErrorInt::RtspResponseError {
+                conn_ctx,
+                msg_ctx,
+                method,
+                cseq,
+                status,
+                ..
+            } if matches!(u16::from(*status), 405 | 455 | 501) => {
+                wrap!(ErrorInt::RtspResponseError {
+                    conn_ctx: *conn_ctx,
+                    msg_ctx: *msg_ctx,
+                    method: method.clone(),
+                    cseq: *cseq,
+                    status: *status,
+                    description: "Server doesn't support PAUSE".into(),
+                })
+            }
+            _ => e,
+        })?;
+        *inner.keepalive_state = KeepaliveState::Idle;
+        *inner.data_timer = None;
+        Ok(())
+    }
+
+    /// Restarts media flow after [`Session::pause`] by sending another `PLAY`.
+    ///
+    /// If `range` is `None`, no `Range` header is sent, so the server resumes
+    /// from the point of pause. Streams continue with their existing RTP
+    /// state; a sequence number gap across the pause is reported as loss.
+    ///
+    /// If `range` is `Some`, the server seeks, and each stream's sequence
+    /// number tracking and timeline restart from the response's `RTP-Info`
+    /// as on the initial [`Session::play`], using the same [`PlayOptions`].
+    /// Stream statistics restart as well.
+    ///
+    /// The response's `Range` and `Scale` replace those returned by
+    /// [`Session::play_range`] and [`Session::play_scale`].
+    pub async fn resume(&mut self, range: Option<Range>) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let session_id = match inner.session.as_ref() {
+            Some(s) => s.id.to_string(),
+            None => bail!(ErrorInt::FailedPrecondition("no session".into())),
+        };
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let mut req = rtsp_types::Request::builder(Method::Play, rtsp_types::Version::V1_0)
+            .request_uri(inner.presentation.control.clone())
+            .header(rtsp_types::headers::SESSION, session_id);
+        let seeking = range.is_some();
+        if let Some(range) = range {
+            req = req.header(rtsp_types::headers::RANGE, range.to_string());
+        }
+        if inner.options.accept_dynamic_rate {
+            req = req.header(crate::X_ACCEPT_DYNAMIC_RATE.clone(), "1".to_owned());
+        }
+        let (msg_ctx, cseq, response) = conn
+            .send(
+                ResponseMode::Control(inner.keepalive_state.outstanding()),
+                inner.options,
+                inner.requested_auth,
+                &mut req.build(Bytes::new()),
+            )
+            .await?;
+        *inner.keepalive_state = KeepaliveState::Idle;
+        if seeking {
+            // Return to `Init` so `parse_play` records the new `RTP-Info`.
+            for s in &mut inner.presentation.streams {
+                let ssrc = match &s.state {
+                    StreamState::Playing { rtp_handler, .. } => rtp_handler.ssrc(),
+                    _ => continue,
+                };
+                s.state = StreamState::Init(StreamStateInit {
+                    ssrc,
+                    ..StreamStateInit::default()
+                });
+                s.timeline_base = None;
+            }
+        }
+        let conn_ctx = *conn.inner.ctx();
+        let status = response.status();
+        let err = |description| {
+            wrap!(ErrorInt::RtspResponseError {
+                conn_ctx,
+                msg_ctx,
+                method: rtsp_types::Method::Play,
+                cseq,
+                status,
+                description,
+            })
+        };
+        let play_response = parse::parse_play(&response, inner.presentation).map_err(&err)?;
+        *inner.play_range = play_response.range;
+        *inner.play_scale = play_response.scale;
+        *inner.rtp_info = play_response.rtp_info;
+        if let Some(dynamic_rate) = parse::parse_dynamic_rate(&response) {
+            *inner.dynamic_rate = Some(dynamic_rate);
+        }
+        if seeking {
+            let play_start = match inner.play_range.as_ref() {
+                Some(Range::Npt { start, .. }) => Some(*start),
+                _ => None,
+            };
+            let default_options = PlayOptions::default();
+            let policy = inner.play_options.as_ref().unwrap_or(&default_options);
+            start_streams(inner.presentation, inner.options, policy, play_start, err)?;
+        }
+        *inner.data_timer = inner
+            .options
+            .data_timeout
+            .map(|t| Box::pin(tokio::time::sleep(t)));
+        Ok(())
+    }
+
+    /// Waits for the next item, or returns `None` if `cancel` completes first.
+    ///
+    /// This is a convenience for clean shutdown, eg with a future which
+    /// completes on a shutdown signal. Polling the session is
+    /// cancellation-safe: all partially received data is buffered within the
+    /// session rather than the `next` future. So after cancellation, a later
+    /// call (or `futures::StreamExt::next`) picks up exactly where this left
+    /// off. Items already available are returned even if `cancel` is also
+    /// complete.
+    pub async fn next_item_with_cancel<C: Future<Output = ()>>(
+        &mut self,
+        cancel: C,
+    ) -> Option<Result<PacketItem, Error>> {
+        tokio::select! {
+            biased;
+            item = self.next() => item,
+            () = cancel => None,
+        }
+    }
+
+    /// Asks the server for a keyframe on the given stream via RTCP feedback.
+    ///
+    /// This is useful to recover quickly after loss (see
+    /// [`crate::codec::CodecItem::Loss`]). Sends a PLI (RFC 4585) or FIR
+    /// (RFC 5104) on the stream's RTCP channel, according to the stream's
+    /// `a=rtcp-fb` SDP attributes, protected as SRTCP on `RTP/SAVP` streams.
+    /// If the server advertised neither, logs a warning and does nothing.
+    /// Fails if RTCP sending is disabled via [`SessionOptions::send_rtcp`].
+    pub async fn request_keyframe(&mut self, stream_id: usize) -> Result<(), Error> {
+        if !self.0.options.send_rtcp {
+            bail!(ErrorInt::FailedPrecondition(
+                "RTCP sending is disabled via SessionOptions::send_rtcp".into()
+            ));
+        }
+        let pkt = match self.keyframe_request_pkt(stream_id)? {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+        let inner = self.0.as_mut().project();
+        let stream = &inner.presentation.streams[stream_id];
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        match &stream.sockets {
+            Some(sockets) => {
+                sockets.send_rtcp(&pkt[..]).await.map_err(|source| {
+                    wrap!(ErrorInt::WriteError {
+                        conn_ctx: *conn.inner.ctx(),
+                        source,
+                    })
+                })?;
+            }
+            None => {
+                let channel_id = conn.channels.rtcp_channel(stream_id).ok_or_else(|| {
+                    wrap!(ErrorInt::FailedPrecondition(format!(
+                        "no RTCP channel assigned to stream {}",
+                        stream_id
+                    )))
+                })?;
+                conn.inner
+                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                        channel_id, pkt,
+                    )))
+                    .await
+                    .map_err(|e| wrap!(e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds an RTCP keyframe request for [`Session::request_keyframe`], or
+    /// returns `None` (after logging) if the stream supports neither PLI nor FIR.
+    fn keyframe_request_pkt(&mut self, stream_id: usize) -> Result<Option<Bytes>, Error> {
+        let inner = self.0.as_mut().project();
+        let stream = inner
+            .presentation
+            .streams
+            .get_mut(stream_id)
+            .ok_or_else(|| {
+                wrap!(ErrorInt::InvalidArgument(format!(
+                    "no such stream {}",
+                    stream_id
+                )))
+            })?;
+        let media_ssrc = match &stream.state {
+            StreamState::Playing { rtp_handler, .. } => rtp_handler.ssrc(),
+            _ => bail!(ErrorInt::FailedPrecondition(format!(
+                "stream {} isn't playing",
+                stream_id
+            ))),
+        }
+        .ok_or_else(|| {
+            wrap!(ErrorInt::FailedPrecondition(format!(
+                "SSRC of stream {} isn't yet known",
+                stream_id
+            )))
+        })?;
+        let pkt = match stream.keyframe_request() {
+            Some(KeyframeRequest::Pli) => {
+                Bytes::copy_from_slice(&crate::rtcp::pli(*inner.rtcp_ssrc, media_ssrc))
+            }
+            Some(KeyframeRequest::Fir) => {
+                let seq = *inner.fir_seq;
+                *inner.fir_seq = seq.wrapping_add(1);
+                Bytes::copy_from_slice(&crate::rtcp::fir(*inner.rtcp_ssrc, media_ssrc, seq))
+            }
+            None => {
+                warn!(
+                    "Can't request keyframe on stream {}: no PLI or FIR in a=rtcp-fb",
+                    stream_id
+                );
+                return Ok(None);
+            }
+        };
+        #[cfg(feature = "srtp")]
+        let pkt = match &mut stream.srtp {
+            Some(Ok(ctx)) => ctx.protect_rtcp(&pkt[..]),
+            _ => pkt,
+        };
+        Ok(Some(pkt))
+    }
+
+    /// Requests a keyframe without waiting, as for [`LossPolicy::RequestKeyframe`].
+    ///
+    /// Failures are logged rather than returned, as loss is itself tolerated.
+    fn try_request_keyframe(&mut self, cx: &mut std::task::Context<'_>, stream_id: usize) {
+        if !self.0.options.send_rtcp {
+            let inner = self.0.as_mut().project();
+            if !*inner.logged_keyframe_skip {
+                warn!(
+                    "Not requesting keyframes on loss because SessionOptions::send_rtcp is false"
+                );
+                *inner.logged_keyframe_skip = true;
+            }
+            return;
+        }
+        let pkt = match self.keyframe_request_pkt(stream_id) {
+            Ok(Some(p)) => p,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Can't request keyframe on stream {}: {}", stream_id, e);
+                return;
+            }
+        };
+        let inner = self.0.as_mut().project();
+        let conn = match inner.conn.as_mut() {
+            Some(c) => c,
+            None => return,
+        };
+        let result = match &inner.presentation.streams[stream_id].sockets {
+            Some(sockets) => sockets.try_send_rtcp(&pkt[..]).map(|_| ()),
+            None => match conn.channels.rtcp_channel(stream_id) {
+                None => Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    "no RTCP channel assigned",
+                )),
+                Some(channel_id) => match conn.inner.poll_ready_unpin(cx) {
+                    Poll::Ready(Ok(())) => {
+                        conn.inner
+                            .start_send_unpin(rtsp_types::Message::Data(rtsp_types::Data::new(
+                                channel_id, pkt,
+                            )))
+                            .expect("encoding is infallible");
+
+                        // If the flush doesn't complete now, it will along
+                        // with the next keepalive.
+                        match conn.inner.poll_flush_unpin(cx) {
+                            Poll::Ready(Err(e)) => Err(std::io::Error::new(
+                                std::io::ErrorKind::Other,
+                                e.to_string(),
+                            )),
+                            _ => Ok(()),
+                        }
+                    }
+                    Poll::Ready(Err(e)) => Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        e.to_string(),
+                    )),
+                    Poll::Pending => Err(std::io::ErrorKind::WouldBlock.into()),
+                },
+            },
+        };
+        if let Err(e) = result {
+            warn!(
+                "Unable to send keyframe request on stream {}: {}",
+                stream_id, e
+            );
+        }
+    }
+
+    /// Sends audio to the server via a backchannel stream.
+    ///
+    /// `payload` is sent as a single RTP packet with the stream's payload
+    /// type and the given RTP timestamp, so it should be a suitable duration
+    /// of audio in the stream's encoding, eg 20 ms of G.711 (160 bytes at
+    /// 8 kHz). The first packet has the marker bit set, as the start of a
+    /// talkspurt. Sequence numbers are assigned automatically.
+    ///
+    /// Fails if the stream isn't a playing backchannel stream; see
+    /// [`Stream::is_backchannel`].
+    pub async fn send_audio(
+        &mut self,
+        stream_id: usize,
+        payload: &[u8],
+        timestamp: u32,
+    ) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let stream = inner
+            .presentation
+            .streams
+            .get_mut(stream_id)
+            .ok_or_else(|| {
+                wrap!(ErrorInt::InvalidArgument(format!(
+                    "no such stream {}",
+                    stream_id
+                )))
+            })?;
+        if !stream.backchannel {
+            bail!(ErrorInt::InvalidArgument(format!(
+                "stream {} isn't a backchannel",
+                stream_id
+            )));
+        }
+        if !matches!(stream.state, StreamState::Playing { .. }) {
+            bail!(ErrorInt::FailedPrecondition(format!(
+                "stream {} isn't playing",
+                stream_id
+            )));
+        }
+        let (sequence_number, mark) = match stream.send_seq {
+            Some(seq) => (seq, false),
+            None => (rand::random(), true),
+        };
+        stream.send_seq = Some(sequence_number.wrapping_add(1));
+        let mut pkt = BytesMut::with_capacity(12 + payload.len());
+        pkt.put_u8(2 << 6); // version 2, no padding, extension, or CSRCs.
+        pkt.put_u8(if mark { 0x80 } else { 0 } | stream.rtp_payload_type);
+        pkt.put_u16(sequence_number);
+        pkt.put_u32(timestamp);
+        pkt.put_u32(*inner.rtcp_ssrc);
+        pkt.put_slice(payload);
+        let pkt = pkt.freeze();
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        match &stream.sockets {
+            Some(sockets) => {
+                sockets.send_rtp(&pkt[..]).await.map_err(|source| {
+                    wrap!(ErrorInt::WriteError {
+                        conn_ctx: *conn.inner.ctx(),
+                        source,
+                    })
+                })?;
+            }
+            None => {
+                let channel_id = conn.channels.rtp_channel(stream_id).ok_or_else(|| {
+                    wrap!(ErrorInt::FailedPrecondition(format!(
+                        "no RTP channel assigned to stream {}",
+                        stream_id
+                    )))
+                })?;
+                conn.inner
+                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                        channel_id, pkt,
+                    )))
+                    .await
+                    .map_err(|e| wrap!(e))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_keepalive_timer(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let session = match inner.session.as_ref() {
+            Some(s) => s,
+            None => bail!(ErrorInt::FailedPrecondition(
+                "session has been torn down".into()
+            )),
+        };
+        let keepalive_interval = session.keepalive_interval();
+
+        // Expect the previous keepalive request to have finished.
+        match inner.keepalive_state {
+            KeepaliveState::Flushing(cseq) => bail!(ErrorInt::WriteError {
+                conn_ctx: *conn.inner.ctx(),
+                source: std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!(
+                        "Unable to write keepalive {} within {:?}",
+                        cseq, keepalive_interval,
+                    ),
+                ),
+            }),
+            KeepaliveState::Waiting(cseq) => bail!(ErrorInt::RtspReadError {
+                conn_ctx: *conn.inner.ctx(),
+                msg_ctx: conn.inner.eof_ctx(),
+                source: std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!(
+                        "Server failed to respond to keepalive {} within {:?}",
+                        cseq, keepalive_interval,
+                    ),
+                ),
+            }),
+            KeepaliveState::Idle => {}
+        }
+
+        // Currently the only outbound data should be keepalives, and the previous one
+        // has already been flushed, so there's no reason the Sink shouldn't be ready.
+        if matches!(conn.inner.poll_ready_unpin(cx), Poll::Pending) {
+            bail!(ErrorInt::Internal(
+                "Unexpectedly not ready to send keepalive".into()
+            ));
+        }
+
+        // Send a new one and reset the timer.
+        let session_id = &*session.id;
+        let method = Method::from(inner.options.keepalive_method);
+        let mut req = rtsp_types::Request::builder(method, rtsp_types::Version::V1_0)
+            .request_uri(inner.presentation.base_url.clone())
+            .header(rtsp_types::headers::SESSION, session_id.to_string())
+            .build(Bytes::new());
+        let cseq = conn.fill_req(inner.options, inner.requested_auth, &mut req)?;
+        conn.inner
+            .start_send_unpin(rtsp_types::Message::Request(req))
+            .expect("encoding is infallible");
+        *inner.keepalive_state = match conn.inner.poll_flush_unpin(cx) {
+            Poll::Ready(Ok(())) => KeepaliveState::Waiting(cseq),
+            Poll::Ready(Err(e)) => bail!(e),
+            Poll::Pending => KeepaliveState::Flushing(cseq),
+        };
+
+        inner
+            .keepalive_timer
+            .as_mut()
+            .expect("keepalive timer set in state Playing")
+            .as_mut()
+            .reset(tokio::time::Instant::now() + keepalive_interval);
+        Ok(())
+    }
+
+    /// Notes that RTP/RTCP data has arrived, postponing the data timeout.
+    fn reset_data_timer(mut self: Pin<&mut Self>) {
+        let inner = self.0.as_mut().project();
+        if let (Some(t), Some(timeout)) = (inner.data_timer.as_mut(), inner.options.data_timeout) {
+            t.as_mut().reset(tokio::time::Instant::now() + timeout);
+        }
+    }
+
+    fn handle_response(
+        mut self: Pin<&mut Self>,
+        msg_ctx: &crate::RtspMessageContext,
+        response: rtsp_types::Response<Bytes>,
+    ) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let lenient = inner.options.lenient;
+        if matches!(inner.keepalive_state,
+        KeepaliveState::Waiting(cseq) if match parse::get_cseq(&response, lenient) {
+            Some(c) => c == *cseq,
+            None => lenient,
+        }) {
+            // We don't care if the keepalive response succeeds or fails. Just mark complete.
+            *inner.keepalive_state = KeepaliveState::Idle;
+            return Ok(());
+        }
+
+        // The only response we expect in this state is to our keepalive request.
+        bail!(ErrorInt::RtspFramingError {
+            conn_ctx: *inner
+                .conn
+                .as_ref()
+                .expect("have conn when handling response")
+                .inner
+                .ctx(),
+            msg_ctx: *msg_ctx,
+            description: format!("Unexpected RTSP response {:#?}", response),
+        })
+    }
+
+    fn handle_data(
+        mut self: Pin<&mut Self>,
+        msg_ctx: &RtspMessageContext,
+        data: rtsp_types::Data<Bytes>,
+    ) -> Result<Option<PacketItem>, Error> {
+        let inner = self.0.as_mut().project();
+        let conn = inner
+            .conn
+            .as_mut()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
+        let channel_id = data.channel_id();
+        let pkt_ctx = crate::PacketContext(crate::PacketContextInner::Tcp {
+            msg_ctx: *msg_ctx,
+            channel_id,
+        });
+        let data = data.into_body();
+        write_capture(&mut *inner.capture, conn.inner.ctx(), &pkt_ctx, &data);
+        let m = match conn.channels.lookup(channel_id) {
+            Some(m) => m,
+            None => bail!(ErrorInt::RtspUnassignedChannelError {
+                conn_ctx: *conn.inner.ctx(),
+                msg_ctx: *msg_ctx,
+                channel_id,
+            }),
+        };
+        let stream = &mut inner.presentation.streams[m.stream_i];
+        #[cfg(feature = "srtp")]
+        let data = match srtp::unprotect(&mut stream.srtp, m.stream_i, m.channel_type, data) {
+            Some(data) => data,
+            None => return Ok(None),
+        };
+        let (mut timeline, rtp_handler) = match &mut stream.state {
+            StreamState::Playing {
+                timeline,
+                rtp_handler,
+            } => (timeline, rtp_handler),
+            _ => unreachable!(
+                "Session<Playing>'s {}->{:?} not in Playing state",
+                channel_id, m
+            ),
+        };
+        match m.channel_type {
+            ChannelType::Rtp => Ok(rtp_handler.rtp(
+                &inner.options,
+                conn.inner.ctx(),
+                &pkt_ctx,
+                &mut timeline,
+                m.stream_i,
+                data,
+            )?),
+            ChannelType::Rtcp => {
+                match rtp_handler.rtcp(&inner.options, &pkt_ctx, &mut timeline, m.stream_i, data) {
+                    Ok(p) => Ok(p),
+                    Err(description) => Err(wrap!(ErrorInt::PacketError {
+                        conn_ctx: *conn.inner.ctx(),
+                        pkt_ctx: pkt_ctx,
+                        stream_id: m.stream_i,
+                        description,
+                    })),
+                }
+            }
+        }
+    }
+
+    /// Polls a single UDP stream, `inner.presentation.streams[i]`.
+    ///
+    /// Assumes `buf` is cleared and large enough for any UDP packet.
+    /// Only returns `Poll::Pending` after both RTCP and RTP sockets have
+    /// returned `Poll::Pending`.
+    fn poll_udp_stream(
+        &mut self,
+        cx: &mut std::task::Context,
+        buf: &mut tokio::io::ReadBuf,
+        i: usize,
+    ) -> Poll<Option<Result<PacketItem, Error>>> {
+        debug_assert!(buf.filled().is_empty());
+        let inner = self.0.as_mut().project();
+        let conn_ctx = inner
+            .conn
+            .as_ref()
+            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?
+            .inner
+            .ctx();
+        let s = &mut inner.presentation.streams[i];
+        if let Some(sockets) = &mut s.sockets {
+            let (mut timeline, rtp_handler) = match &mut s.state {
+                StreamState::Playing {
+                    timeline,
+                    rtp_handler,
+                } => (timeline, rtp_handler),
+                _ => unreachable!("Session<Playing>'s {}->{:?} not in Playing state", i, s),
+            };
+            // Prioritize RTCP over RTP within a stream.
+            while let Poll::Ready(r) = sockets.rtcp_socket.poll_recv_from(cx, buf) {
+                let pkt_ctx = crate::PacketContext(crate::PacketContextInner::Udp {
+                    local_addr: SocketAddr::new(sockets.local_ip, sockets.local_rtp_port + 1),
+                    peer_addr: *r.as_ref().unwrap_or(&sockets.remote_rtcp_addr()),
+                    received_wall: crate::WallTime::now(),
+                    received: Instant::now(),
+                });
+                match r {
+                    Ok(_) => {
+                        write_capture(&mut *inner.capture, conn_ctx, &pkt_ctx, buf.filled());
+                        let msg = Bytes::copy_from_slice(buf.filled());
+                        #[cfg(feature = "srtp")]
+                        let msg = match srtp::unprotect(&mut s.srtp, i, ChannelType::Rtcp, msg) {
+                            Some(msg) => msg,
+                            None => {
+                                buf.clear();
+                                continue;
+                            }
+                        };
+                        match rtp_handler.rtcp(&inner.options, &pkt_ctx, &mut timeline, i, msg) {
+                            Ok(Some(p)) => return Poll::Ready(Some(Ok(p))),
+                            Ok(None) => buf.clear(),
+                            Err(description) => {
+                                return Poll::Ready(Some(Err(wrap!(ErrorInt::PacketError {
+                                    conn_ctx: *conn_ctx,
+                                    pkt_ctx,
+                                    stream_id: i,
+                                    description,
+                                }))))
+                            }
+                        }
+                    }
+                    Err(source) => {
+                        return Poll::Ready(Some(Err(wrap!(ErrorInt::UdpRecvError {
+                            conn_ctx: *conn_ctx,
+                            pkt_ctx,
+                            source,
+                        }))))
+                    }
+                }
+            }
+            while let Poll::Ready(r) = sockets.rtp_socket.poll_recv_from(cx, buf) {
+                let pkt_ctx = crate::PacketContext(crate::PacketContextInner::Udp {
+                    local_addr: SocketAddr::new(sockets.local_ip, sockets.local_rtp_port),
+                    peer_addr: *r.as_ref().unwrap_or(&sockets.remote_rtp_addr()),
+                    received_wall: crate::WallTime::now(),
+                    received: Instant::now(),
+                });
+                match r {
+                    Ok(_) => {
+                        write_capture(&mut *inner.capture, conn_ctx, &pkt_ctx, buf.filled());
+                        let msg = Bytes::copy_from_slice(buf.filled());
+                        #[cfg(feature = "srtp")]
+                        let msg = match srtp::unprotect(&mut s.srtp, i, ChannelType::Rtp, msg) {
+                            Some(msg) => msg,
+                            None => {
+                                buf.clear();
+                                continue;
+                            }
+                        };
+                        match rtp_handler.rtp(
+                            &inner.options,
+                            &conn_ctx,
+                            &pkt_ctx,
+                            &mut timeline,
+                            i,
+                            msg,
+                        ) {
+                            Ok(Some(p)) => return Poll::Ready(Some(Ok(p))),
+                            Ok(None) => buf.clear(),
+                            Err(e) => return Poll::Ready(Some(Err(e))),
+                        }
+                    }
+                    Err(source) => {
+                        return Poll::Ready(Some(Err(wrap!(ErrorInt::UdpRecvError {
+                            conn_ctx: *conn_ctx,
+                            pkt_ctx,
+                            source,
+                        }))))
+                    }
+                }
+            }
+        }
+        Poll::Pending
+    }
+
+    /// Returns a packet which a stream has released but not yet returned, if any.
+    /// Releases all packets held for reordering, as at the end of the session.
+    fn flush_held(mut self: Pin<&mut Self>) -> Result<(), Error> {
+        let inner = self.0.as_mut().project();
+        let conn_ctx = *inner.conn.as_ref().unwrap().inner.ctx();
+        for (i, s) in inner.presentation.streams.iter_mut().enumerate() {
+            if let StreamState::Playing {
+                timeline,
+                rtp_handler,
+            } = &mut s.state
+            {
+                rtp_handler.flush(&conn_ctx, timeline, i)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn pull_ready(mut self: Pin<&mut Self>) -> Option<PacketItem> {
+        let inner = self.0.as_mut().project();
+        inner
+            .presentation
+            .streams
+            .iter_mut()
+            .find_map(|s| match &mut s.state {
+                StreamState::Playing { rtp_handler, .. } => rtp_handler.pull(),
+                _ => None,
+            })
+    }
+
+    /// Polls all UDP streams, round-robining between them to avoid starvation.
+    fn poll_udp(&mut self, cx: &mut std::task::Context) -> Poll<Option<Result<PacketItem, Error>>> {
+        // For now, create a buffer on the stack large enough for any UDP packet, then
+        // copy into a fresh allocation if it's actually used.
+        // TODO: a ring buffer would be better: see
+        // <https://github.com/scottlamb/retina/issues/6>.
+
+        // SAFETY: this exactly matches an example in the documentation:
+        // <https://doc.rust-lang.org/nightly/core/mem/union.MaybeUninit.html#initializing-an-array-element-by-element>.
+        let mut buf: [MaybeUninit<u8>; 65_536] = unsafe { MaybeUninit::uninit().assume_init() };
+        let mut buf = tokio::io::ReadBuf::uninit(&mut buf);
+
+        // Assume 0 <= inner.udp_next_poll_i < inner.presentation.streams.len().
+        // play() would have failed if there were no (setup) streams.
+        let starting_i = *self.0.as_mut().project().udp_next_poll_i;
+        loop {
+            let inner = self.0.as_mut().project();
+            let i = *inner.udp_next_poll_i;
+            *inner.udp_next_poll_i += 1;
+            if *inner.udp_next_poll_i == inner.presentation.streams.len() {
+                *inner.udp_next_poll_i = 0;
+            }
+
+            if let Poll::Ready(r) = self.poll_udp_stream(cx, &mut buf, i) {
+                return Poll::Ready(r);
+            }
+
+            if *self.0.as_mut().project().udp_next_poll_i == starting_i {
+                break;
+            }
+        }
+        Poll::Pending
+    }
+}
+
+#[pin_project::pinned_drop]
+impl PinnedDrop for SessionInner {
+    fn drop(self: Pin<&mut Self>) {
+        let this = self.project();
+
+        let is_tcp = this.options.transport.is_interleaved();
+        match this.options.teardown {
+            TeardownPolicy::Auto if is_tcp && !*this.has_live555_tcp_bug => return,
+            TeardownPolicy::Auto | TeardownPolicy::Always => {}
+            TeardownPolicy::Never => return,
+        }
+        let teardown_on_drop = this.options.teardown_on_drop;
+        if let TeardownOnDrop::None = teardown_on_drop {
+            return;
+        }
+
+        let session = match this.session.take() {
+            Some(s) => s,
+            None => return,
+        };
+
+        // For now, assume the whole timeout is left.
+        let expires = tokio::time::Instant::now()
+            + std::time::Duration::from_secs(session.timeout_sec.into());
+
+        // Track the session, if there is a group.
+        let (teardown_tx, teardown_rx) = tokio::sync::watch::channel(None);
+        let seqnum = if let Some(session_group) = this.options.session_group.as_ref() {
+            let mut lock = session_group.sessions.lock().unwrap();
+            let seqnum = lock.next_seqnum;
+            lock.next_seqnum += 1;
+            lock.sessions.push(StaleSession {
+                seqnum,
+                expires: expires.clone(),
+                teardown_rx: Some(teardown_rx),
+                is_tcp,
+                maybe_playing: *this.maybe_playing,
+            });
+            Some(seqnum)
+        } else {
+            None
+        };
+
+        let handle = match this.runtime_handle.take() {
+            Some(h) => h,
+            None => {
+                const MSG: &str = "Unable to start async TEARDOWN because describe wasn't called \
+                                   from a tokio runtime";
+                log::warn!("{}", MSG);
+                let _ =
+                    teardown_tx.send(Some(Err(wrap!(ErrorInt::FailedPrecondition(MSG.into())))));
+                return;
+            }
+        };
+
+        let teardown = teardown::background_teardown(
+            seqnum,
+            this.presentation.base_url.clone(),
+            session.id,
+            std::mem::take(this.options),
+            this.requested_auth.take(),
+            this.conn.take(),
+            this.keepalive_state.outstanding(),
+            teardown_tx,
+            expires,
+        );
+        if let TeardownOnDrop::BlockingInDrop = teardown_on_drop {
+            let flavor = tokio::runtime::Handle::try_current()
+                .ok()
+                .map(|h| h.runtime_flavor());
+            if let Some(tokio::runtime::RuntimeFlavor::CurrentThread) = flavor {
+                log::debug!(
+                    "Can't block for TEARDOWN on a current-thread runtime; \
+                     continuing in background"
+                );
+                handle.spawn(teardown);
+                return;
+            }
+            let (done_tx, done_rx) = std::sync::mpsc::sync_channel(1);
+            handle.spawn(async move {
+                teardown.await;
+                let _ = done_tx.send(());
+            });
+            let wait = || done_rx.recv_timeout(TEARDOWN_TIMEOUT);
+            let done = match flavor {
+                None => wait(),
+                Some(_) => tokio::task::block_in_place(wait),
+            };
+            if done.is_err() {
+                log::warn!(
+                    "TEARDOWN didn't complete within {:?}; continuing in background",
+                    TEARDOWN_TIMEOUT
+                );
+            }
+        } else {
+            handle.spawn(teardown);
+        }
+    }
+}
+
+impl futures::Stream for Session<Playing> {
+    type Item = Result<PacketItem, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Self::Item>> {
+        let pkt = match ready!(self.as_mut().poll_packet(cx)) {
+            Some(Ok(PacketItem::RtpPacket(p))) if p.loss > 0 => p,
+            o => return Poll::Ready(o),
+        };
+        let stream_id = pkt.stream_id;
+        match self.0.options.loss_policy {
+            LossPolicy::Abort => {
+                let conn_ctx = *self.0.conn.as_ref().unwrap().inner.ctx();
+                return Poll::Ready(Some(Err(wrap!(ErrorInt::RtpPacketError {
+                    conn_ctx,
+                    pkt_ctx: pkt.ctx,
+                    stream_id,
+                    ssrc: pkt.ssrc,
+                    sequence_number: pkt.sequence_number,
+                    description: format!("Lost {} RTP packets; loss policy is abort", pkt.loss),
+                }))));
+            }
+            LossPolicy::Tolerate => {
+                warn!("Lost {} RTP packets on stream {}", pkt.loss, stream_id);
+            }
+            LossPolicy::RequestKeyframe => {
+                warn!(
+                    "Lost {} RTP packets on stream {}; requesting keyframe",
+                    pkt.loss, stream_id
+                );
+                if self.0.presentation.streams[stream_id].media == "video" {
+                    self.as_mut().get_mut().try_request_keyframe(cx, stream_id);
+                }
+            }
+        }
+        Poll::Ready(Some(Ok(PacketItem::RtpPacket(pkt))))
+    }
+}
+
+impl Session<Playing> {
+    /// Polls for the next packet, before applying [`SessionOptions::loss_policy`].
+    fn poll_packet(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Result<PacketItem, Error>>> {
+        loop {
+            // Return any packets already released by a stream's reordering.
+            if let Some(pkt) = self.as_mut().pull_ready() {
+                return Poll::Ready(Some(Ok(pkt)));
+            }
+
+            // Return any data deferred while awaiting a control response.
+            if let Some((msg_ctx, data)) = self.0.conn.as_mut().unwrap().deferred_data.pop_front() {
+                match self.as_mut().handle_data(&msg_ctx, data) {
+                    Err(e) => return Poll::Ready(Some(Err(e))),
+                    Ok(Some(pkt)) => return Poll::Ready(Some(Ok(pkt))),
+                    Ok(None) => continue,
+                };
+            }
+
+            // First try receiving data on the RTSP connection. Let this starve
+            // sending keepalives; if we can't keep up, the server should
+            // probably drop us.
+            match Pin::new(&mut self.0.conn.as_mut().unwrap().inner).poll_next(cx) {
+                Poll::Ready(Some(Ok(msg))) => match msg.msg {
+                    rtsp_types::Message::Data(data) => {
+                        self.as_mut().reset_data_timer();
+                        match self.as_mut().handle_data(&msg.ctx, data) {
+                            Err(e) => return Poll::Ready(Some(Err(e))),
+                            Ok(Some(pkt)) => return Poll::Ready(Some(Ok(pkt))),
+                            Ok(None) => continue,
+                        };
+                    }
+                    rtsp_types::Message::Response(response) => {
+                        if let Err(e) = self.as_mut().handle_response(&msg.ctx, response) {
+                            return Poll::Ready(Some(Err(e)));
+                        }
+                        continue;
+                    }
+                    rtsp_types::Message::Request(request) => {
+                        warn!("Received RTSP request in Playing state. Responding unimplemented.\n{:#?}",
+                            request);
+                    }
+                },
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => {
+                    // Release any packets still held for reordering first.
+                    if let Err(e) = self.as_mut().flush_held() {
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    return Poll::Ready(self.as_mut().pull_ready().map(Ok));
+                }
+                std::task::Poll::Pending => {}
+            }
+
+            // Next try receiving data on the UDP sockets, if any.
+            if !self.0.options.transport.is_interleaved() {
+                if let Poll::Ready(result) = self.as_mut().poll_udp(cx) {
+                    if matches!(result, Some(Ok(_))) {
+                        self.as_mut().reset_data_timer();
+                    }
+                    return Poll::Ready(result);
+                }
+            }
+
+            // Then check if it's time for a new keepalive.
+            if matches!(
+                self.0.keepalive_timer.as_mut().unwrap().as_mut().poll(cx),
+                Poll::Ready(())
+            ) {
+                log::debug!("time for a keepalive");
+                self.as_mut().handle_keepalive_timer(cx)?;
+            }
+
+            // Then check if data has stopped arriving.
+            if let Some(t) = self.0.data_timer.as_mut() {
+                if t.as_mut().poll(cx).is_ready() {
+                    let conn_ctx = *self.0.conn.as_ref().unwrap().inner.ctx();
+                    let timeout = self
+                        .0
+                        .options
+                        .data_timeout
+                        .expect("data_timer implies timeout");
+                    return Poll::Ready(Some(Err(wrap!(ErrorInt::DataTimeout {
+                        conn_ctx,
+                        timeout
+                    }))));
+                }
+            }
+
+            // Then finish flushing the current keepalive if necessary.
+            if let KeepaliveState::Flushing(cseq) = self.0.keepalive_state {
+                match self.0.conn.as_mut().unwrap().inner.poll_flush_unpin(cx) {
+                    Poll::Ready(Ok(())) => self.0.keepalive_state = KeepaliveState::Waiting(cseq),
+                    Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(Error(Arc::new(e))))),
+                    Poll::Pending => {}
+                }
+            }
+
+            // Nothing to do. The poll calls above have already registered cx as necessary.
+            return Poll::Pending;
+        }
+    }
+}
+
+enum DemuxedState {
+    Waiting,
+
+    /// A [`CodecItem::Loss`] has been returned for this packet, which is to be
+    /// pushed next.
+    Lost(rtp::Packet),
+
+    Pulling(usize),
+
+    /// The session has ended; video frames held for
+    /// [`SessionOptions::video_frame_durations`] are being yielded, starting
+    /// from this stream.
+    Flushing(usize),
+
+    Fused,
+}
+
+/// Wrapper returned by [`Session<Playing>::demuxed`] which demuxes/depacketizes into frames.
+///
+/// This implements `futures::Stream<Item = Result<CodecItem, Error>>`, so
+/// `futures::StreamExt` combinators such as `map`, `take`, and `filter` apply
+/// directly, as do wrappers such as `tokio_stream::StreamExt::timeout`. The
+/// stream ends when the server closes the connection. Errors are yielded as
+/// items; depending on the error, the caller may continue polling or drop the
+/// stream. Dropping it (including via a combinator which stops early) drops
+/// the session, which tears down according to [`SessionOptions::teardown`]
+/// and [`SessionOptions::teardown_on_drop`].
+pub struct Demuxed(DemuxedInner);
+
+enum DemuxedInner {
+    Direct(DirectDemuxed),
+    Queued(queue::Queue),
+}
+
+/// Demuxes as the caller polls, as in [`QueuePolicy::Direct`].
+struct DirectDemuxed {
+    state: DemuxedState,
+    session: Session<Playing>,
+}
+
+impl Demuxed {
+    /// Sends a `TEARDOWN` and waits for its response, ending the session.
+    ///
+    /// See [`Session::teardown`].
+    pub async fn teardown(&mut self) -> Result<(), Error> {
+        match &mut self.0 {
+            DemuxedInner::Direct(d) => d.session.teardown().await,
+            DemuxedInner::Queued(q) => q.teardown().await,
+        }
+    }
+
+    /// Waits for the next item, or returns `None` if `cancel` completes first.
+    ///
+    /// See [`Session::next_item_with_cancel`]; this is similarly
+    /// cancellation-safe with any [`QueuePolicy`].
+    pub async fn next_item_with_cancel<C: Future<Output = ()>>(
+        &mut self,
+        cancel: C,
+    ) -> Option<Result<CodecItem, Error>> {
+        tokio::select! {
+            biased;
+            item = self.next() => item,
+            () = cancel => None,
+        }
+    }
+
+    /// Asks the server for a keyframe; see [`Session::request_keyframe`].
+    ///
+    /// Fails with a [`QueuePolicy`] other than `Direct`, as the session is
+    /// then owned by a background task.
+    pub async fn request_keyframe(&mut self, stream_id: usize) -> Result<(), Error> {
+        match &mut self.0 {
+            DemuxedInner::Direct(d) => d.session.request_keyframe(stream_id).await,
+            DemuxedInner::Queued(_) => bail!(ErrorInt::FailedPrecondition(
+                "request_keyframe is unsupported with a queue policy".into()
+            )),
+        }
+    }
+
+    /// Sends audio via a backchannel stream; see [`Session::send_audio`].
+    ///
+    /// Fails with a [`QueuePolicy`] other than `Direct`, as the session is
+    /// then owned by a background task.
+    pub async fn send_audio(
+        &mut self,
+        stream_id: usize,
+        payload: &[u8],
+        timestamp: u32,
+    ) -> Result<(), Error> {
+        match &mut self.0 {
+            DemuxedInner::Direct(d) => d.session.send_audio(stream_id, payload, timestamp).await,
+            DemuxedInner::Queued(_) => bail!(ErrorInt::FailedPrecondition(
+                "send_audio is unsupported with a queue policy".into()
+            )),
+        }
+    }
+
+    /// Returns reception statistics for the given stream.
+    ///
+    /// See [`Session::stream_stats`]. With a [`QueuePolicy`] other than
+    /// `Direct`, these are as of the most recently queued item.
+    pub fn stream_stats(&self, stream_id: usize) -> Option<rtp::StreamStats> {
+        match &self.0 {
+            DemuxedInner::Direct(d) => d.session.stream_stats(stream_id),
+            DemuxedInner::Queued(q) => q.stream_stats(stream_id),
+        }
+    }
+
+    /// Returns the interarrival jitter estimate for the given stream.
+    ///
+    /// See [`Session::stream_jitter`].
+    pub fn stream_jitter(&self, stream_id: usize) -> Option<u32> {
+        match &self.0 {
+            DemuxedInner::Direct(d) => d.session.stream_jitter(stream_id),
+            DemuxedInner::Queued(q) => q.stream_jitter(stream_id),
+        }
+    }
+
+    /// Returns the received bitrate of the given stream, in bits per second.
+    ///
+    /// See [`Session::stream_bitrate`].
+    pub fn stream_bitrate(&self, stream_id: usize) -> Option<u32> {
+        match &self.0 {
+            DemuxedInner::Direct(d) => d.session.stream_bitrate(stream_id),
+            DemuxedInner::Queued(q) => q.stream_bitrate(stream_id),
+        }
+    }
+}
+
+impl futures::Stream for Demuxed {
+    type Item = Result<CodecItem, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        match &mut self.0 {
+            DemuxedInner::Direct(d) => Pin::new(d).poll_next(cx),
+            DemuxedInner::Queued(q) => q.poll_next(cx),
+        }
+    }
+}
+
+impl futures::Stream for DirectDemuxed {
+    type Item = Result<CodecItem, Error>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        loop {
+            let (stream_id, pkt) = match std::mem::replace(&mut self.state, DemuxedState::Waiting) {
+                DemuxedState::Waiting => match ready!(Pin::new(&mut self.session).poll_next(cx)) {
+                    Some(Ok(PacketItem::RtpPacket(p))) if p.loss > 0 => {
+                        let item = CodecItem::Loss {
+                            stream_id: p.stream_id,
+                            lost_range: p.sequence_number.wrapping_sub(p.loss)..p.sequence_number,
+                            ctx: p.ctx,
+                        };
+                        self.state = DemuxedState::Lost(p);
+                        return Poll::Ready(Some(Ok(item)));
+                    }
+                    Some(Ok(PacketItem::RtpPacket(p))) => (p.stream_id, Some(p)),
+                    Some(Ok(PacketItem::SenderReport(p))) => {
+                        return Poll::Ready(Some(Ok(CodecItem::SenderReport(p))))
+                    }
+                    Some(Err(e)) => return Poll::Ready(Some(Err(e))),
+                    None => {
+                        self.state = DemuxedState::Flushing(0);
+                        continue;
+                    }
+                },
+                DemuxedState::Lost(p) => (p.stream_id, Some(p)),
+                DemuxedState::Pulling(stream_id) => (stream_id, None),
+                DemuxedState::Flushing(first) => {
+                    let inner = self.session.0.as_mut().project();
+                    let flushed = inner.presentation.streams[first..]
+                        .iter_mut()
+                        .enumerate()
+                        .find_map(|(i, s)| match &mut s.depacketizer {
+                            Ok(d) => d.flush().map(|item| (first + i, item)),
+                            Err(_) => None,
+                        });
+                    match flushed {
+                        Some((i, item)) => {
+                            self.state = DemuxedState::Flushing(i + 1);
+                            return Poll::Ready(Some(Ok(item)));
+                        }
+                        None => {
+                            self.state = DemuxedState::Fused;
+                            return Poll::Ready(None);
+                        }
+                    }
+                }
+                DemuxedState::Fused => {
+                    self.state = DemuxedState::Fused;
+                    return Poll::Ready(None);
+                }
+            };
+            let inner = self.session.0.as_mut().project();
+            let depacketizer = match &mut inner.presentation.streams[stream_id].depacketizer {
+                Ok(d) => d,
+                Err(_) => unreachable!("depacketizer was Ok"),
+            };
+            let conn_ctx = inner
+                .conn
+                .as_ref()
+                .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?
+                .inner
+                .ctx();
+            if let Some(p) = pkt {
+                let pkt_ctx = p.ctx;
+                let stream_id = p.stream_id;
+                let ssrc = p.ssrc;
+                let sequence_number = p.sequence_number;
+                depacketizer.push(p).map_err(|description| {
+                    wrap!(ErrorInt::RtpPacketError {
+                        conn_ctx: *conn_ctx,
+                        pkt_ctx,
+                        stream_id,
+                        ssrc,
+                        sequence_number,
+                        description,
+                    })
+                })?;
+            }
+            match depacketizer.pull(conn_ctx) {
+                Ok(Some(item)) => {
+                    self.state = DemuxedState::Pulling(stream_id);
+                    return Poll::Ready(Some(Ok(item)));
+                }
+                Ok(None) => {
+                    self.state = DemuxedState::Waiting;
+                    continue;
+                }
+                Err(e) => {
+                    self.state = DemuxedState::Fused;
+                    return Poll::Ready(Some(Err(e)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testutil::response;
+
+    /// Cross-platform, tokio equivalent of `socketpair(2)`.
+    async fn socketpair(addr: &str) -> (tokio::net::TcpStream, tokio::net::TcpStream) {
+        // Another process on the machine could connect to the server and mess
+        // this up, but that's unlikely enough to ignore in test code.
+        let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = tokio::net::TcpStream::connect(addr);
+        let server = listener.accept();
+        (client.await.unwrap(), server.await.unwrap().0)
+    }
+
+    async fn connect_to_mock() -> (RtspConnection, crate::tokio::Connection) {
+        connect_to_mock_on("127.0.0.1:0").await
+    }
+
+    /// Like `connect_to_mock`, but listens on the given address, eg `[::1]:0`.
+    async fn connect_to_mock_on(addr: &str) -> (RtspConnection, crate::tokio::Connection) {
+        let (client, server) = socketpair(addr).await;
+        let client = crate::tokio::Connection::from_stream(client).unwrap();
+        let server = crate::tokio::Connection::from_stream(server).unwrap();
+        let client = RtspConnection {
+            inner: client,
+            channels: ChannelMappings::default(),
+            next_cseq: 1,
+            deferred_data: std::collections::VecDeque::new(),
+        };
+        (client, server)
+    }
+
+    /// Receives a request and sends a response, filling in the matching `CSeq`.
+    /// Returns the request for further inspection.
+    pub(super) async fn req_response(
+        server: &mut crate::tokio::Connection,
+        expected_method: rtsp_types::Method,
+        mut response: rtsp_types::Response<Bytes>,
+    ) -> rtsp_types::Request<Bytes> {
+        let msg = server.next().await.unwrap().unwrap();
+        let req = match msg.msg {
+            rtsp_types::Message::Request(r) => r,
+            _ => panic!(),
+        };
+        assert_eq!(req.method(), expected_method);
+        let cseq = req.header(&rtsp_types::headers::CSEQ).unwrap();
+        response.insert_header(rtsp_types::headers::CSEQ, cseq.as_str());
+        server
+            .send(rtsp_types::Message::Response(response))
+            .await
+            .unwrap();
+        req
+    }
+
+    /// Replaces the Reolink test presentation's video encoding with one retina
+    /// doesn't support. Same length as "H264", so the `Content-Length` is the
+    /// same either way.
+    pub(super) const UNKNOWN_CODEC: &[(&str, &str)] = &[("H264/90000", "X-FO/90000")];
+
+    /// Advertises PLI support on the Reolink test presentation's video stream.
+    const PLI: &[(&str, &str)] = &[(
+        "a=rtpmap:96 H264/90000\r\n",
+        "a=rtpmap:96 H264/90000\r\na=rtcp-fb:96 nack pli\r\n",
+    )];
+
+    /// Returns the Reolink `DESCRIBE` response with each `(from, to)` of
+    /// `sdp_edits` applied to its SDP, adjusting the `Content-Length` to match.
+    pub(super) fn reolink_describe(sdp_edits: &[(&str, &str)]) -> Vec<u8> {
+        let raw = std::str::from_utf8(include_bytes!("testdata/reolink_describe.txt")).unwrap();
+        let (headers, body) = raw.split_at(raw.find("\r\n\r\n").unwrap() + 4);
+        let mut new_body = body.to_owned();
+        for (from, to) in sdp_edits {
+            new_body = new_body.replace(from, to);
+        }
+        let headers = headers.replace(
+            &format!("Content-Length: {}", body.len()),
+            &format!("Content-Length: {}", new_body.len()),
+        );
+        (headers + &new_body).into_bytes()
+    }
+
+    /// Describes the Reolink test presentation against a mock server.
+    async fn describe_mock(
+        options: SessionOptions,
+    ) -> (Session<Described>, crate::tokio::Connection) {
+        describe_mock_with(options, &reolink_describe(&[])).await
+    }
+
+    /// As [`describe_mock`], with the given `DESCRIBE` response.
+    async fn describe_mock_with(
+        options: SessionOptions,
+        describe: &[u8],
+    ) -> (Session<Described>, crate::tokio::Connection) {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, options, url),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(describe)
+            ),
+        );
+        (session.unwrap(), server)
+    }
+
+    /// Describes the Reolink test presentation and plays its first stream
+    /// against a mock server.
+    async fn play_mock(options: SessionOptions) -> (Session<Playing>, crate::tokio::Connection) {
+        play_mock_with(options, &[], SetupOptions::default()).await
+    }
+
+    /// As [`play_mock`], with `sdp_edits` applied as in [`reolink_describe`]
+    /// and the given options for `SETUP`.
+    async fn play_mock_with(
+        options: SessionOptions,
+        sdp_edits: &[(&str, &str)],
+        setup_options: SetupOptions,
+    ) -> (Session<Playing>, crate::tokio::Connection) {
+        let (mut session, mut server) =
+            describe_mock_with(options, &reolink_describe(sdp_edits)).await;
+        tokio::join!(
+            async {
+                session.setup_with_options(0, setup_options).await.unwrap();
+            },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        (session.unwrap(), server)
+    }
+
+    /// Receives a `DESCRIBE` request for `expected_uri` and sends a response,
+    /// filling in the matching `CSeq`.
+    async fn describe_response(
+        server: &mut crate::tokio::Connection,
+        expected_uri: &str,
+        mut response: rtsp_types::Response<Bytes>,
+    ) {
+        let msg = server.next().await.unwrap().unwrap();
+        let cseq = match msg.msg {
+            rtsp_types::Message::Request(ref r) => {
+                assert_eq!(r.method(), rtsp_types::Method::Describe);
+                assert_eq!(r.request_uri().map(Url::as_str), Some(expected_uri));
+                r.header(&rtsp_types::headers::CSEQ).unwrap()
+            }
+            _ => panic!(),
+        };
+        response.insert_header(rtsp_types::headers::CSEQ, cseq.as_str());
+        server
+            .send(rtsp_types::Message::Response(response))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn follow_redirect() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/old").unwrap();
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, SessionOptions::default(), url),
+            async {
+                describe_response(
+                    &mut server,
+                    "rtsp://192.168.5.206:554/old",
+                    response(
+                        b"RTSP/1.0 302 Moved Temporarily\r\n\
+                          Location: rtsp://192.168.5.206:554/h264Preview_01_main\r\n\r\n",
+                    ),
+                )
+                .await;
+                describe_response(
+                    &mut server,
+                    "rtsp://192.168.5.206:554/h264Preview_01_main",
+                    response(include_bytes!("testdata/reolink_describe.txt")),
+                )
+                .await;
+            },
+        );
+        let session = session.unwrap();
+        assert_eq!(session.streams().len(), 2);
     }
 
-    async fn describe_with_conn(
-        mut conn: RtspConnection,
-        options: SessionOptions,
-        url: Url,
-    ) -> Result<Self, Error> {
-        let mut req = rtsp_types::Request::builder(Method::Describe, rtsp_types::Version::V1_0)
-            .header(rtsp_types::headers::ACCEPT, "application/sdp")
-            .request_uri(url.clone())
-            .build(Bytes::new());
-        let mut requested_auth = None;
-        let (msg_ctx, cseq, response) = conn
-            .send(
-                ResponseMode::Normal,
-                &options,
-                &mut requested_auth,
-                &mut req,
+    /// A redirect which spells out the transport's default port stays on the
+    /// same connection.
+    #[tokio::test]
+    async fn follow_redirect_default_port() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206/old").unwrap();
+        let options = SessionOptions::default().transport(Transport::HttpTunnel);
+        let (session, _) = tokio::join!(Session::describe_with_conn(conn, options, url), async {
+            describe_response(
+                &mut server,
+                "rtsp://192.168.5.206/old",
+                response(
+                    b"RTSP/1.0 302 Moved Temporarily\r\n\
+                      Location: rtsp://192.168.5.206:80/h264Preview_01_main\r\n\r\n",
+                ),
             )
-            .await?;
-        let presentation = parse::parse_describe(url, &response).map_err(|description| {
-            wrap!(ErrorInt::RtspResponseError {
-                conn_ctx: *conn.inner.ctx(),
-                msg_ctx,
-                method: rtsp_types::Method::Describe,
-                cseq,
-                status: response.status(),
-                description,
-            })
-        })?;
-        let has_live555_tcp_bug = presentation
-            .tool
-            .as_deref()
-            .map(has_live555_tcp_bug)
-            .unwrap_or(false);
-        Ok(Session(
-            Box::pin(SessionInner {
-                conn: Some(conn),
-                options,
-                runtime_handle: tokio::runtime::Handle::try_current().ok(),
-                requested_auth,
-                presentation,
-                session: None,
-                describe_ctx: msg_ctx,
-                describe_cseq: cseq,
-                describe_status: response.status(),
-                keepalive_state: KeepaliveState::Idle,
-                keepalive_timer: None,
-                maybe_playing: false,
-                has_live555_tcp_bug,
-                udp_next_poll_i: 0,
-            }),
-            Described(()),
-        ))
+            .await;
+            describe_response(
+                &mut server,
+                "rtsp://192.168.5.206:80/h264Preview_01_main",
+                response(include_bytes!("testdata/reolink_describe.txt")),
+            )
+            .await;
+        });
+        session.unwrap();
+    }
+
+    #[tokio::test]
+    async fn redirect_loop() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/a").unwrap();
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, SessionOptions::default(), url),
+            async {
+                describe_response(
+                    &mut server,
+                    "rtsp://192.168.5.206:554/a",
+                    response(b"RTSP/1.0 302 Moved Temporarily\r\nLocation: /b\r\n\r\n"),
+                )
+                .await;
+                describe_response(
+                    &mut server,
+                    "rtsp://192.168.5.206:554/b",
+                    response(b"RTSP/1.0 302 Moved Temporarily\r\nLocation: /a\r\n\r\n"),
+                )
+                .await;
+            },
+        );
+        let e = session.err().unwrap();
+        assert!(e.to_string().contains("Redirect loop"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn redirect_tls_downgrade() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsps://192.168.5.206:322/a").unwrap();
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, SessionOptions::default(), url),
+            describe_response(
+                &mut server,
+                "rtsps://192.168.5.206:322/a",
+                response(
+                    b"RTSP/1.0 302 Moved Temporarily\r\n\
+                      Location: rtsp://192.168.5.206:554/a\r\n\r\n"
+                ),
+            ),
+        );
+        let e = session.err().unwrap();
+        assert!(e.to_string().contains("non-TLS"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn too_many_redirects() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/a").unwrap();
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, SessionOptions::default().max_redirects(1), url),
+            async {
+                describe_response(
+                    &mut server,
+                    "rtsp://192.168.5.206:554/a",
+                    response(b"RTSP/1.0 302 Moved Temporarily\r\nLocation: /b\r\n\r\n"),
+                )
+                .await;
+                describe_response(
+                    &mut server,
+                    "rtsp://192.168.5.206:554/b",
+                    response(b"RTSP/1.0 302 Moved Temporarily\r\nLocation: /c\r\n\r\n"),
+                )
+                .await;
+            },
+        );
+        let e = session.err().unwrap();
+        assert!(e.to_string().contains("exceeds max_redirects=1"), "{}", e);
+    }
+
+    /// Tests the happy path of session initialization.
+    #[tokio::test]
+    async fn simple() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+
+        // DESCRIBE.
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, SessionOptions::default(), url),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(include_bytes!("testdata/reolink_describe.txt"))
+            ),
+        );
+        let mut session = session.unwrap();
+        assert_eq!(session.streams().len(), 2);
+
+        // SETUP.
+        tokio::join!(
+            async {
+                session.setup(0).await.unwrap();
+            },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+
+        // PLAY.
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        let session = session.unwrap();
+        tokio::pin!(session);
+
+        // Packet.
+        tokio::join!(
+            async {
+                match session.next().await {
+                    Some(Ok(PacketItem::RtpPacket(p))) => {
+                        assert_eq!(p.ssrc, 0xdcc4a0d8);
+                        assert_eq!(p.sequence_number, 0x41d4);
+                        assert_eq!(&p.payload[..], b"hello world");
+                    }
+                    o => panic!("unexpected item: {:#?}", o),
+                }
+            },
+            async {
+                let pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+                server
+                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                        0,
+                        Bytes::from_static(pkt),
+                    )))
+                    .await
+                    .unwrap();
+            },
+        );
+
+        // End of stream.
+        tokio::join!(
+            async {
+                assert!(session.next().await.is_none());
+            },
+            async {
+                server.close().await.unwrap();
+            },
+        );
+    }
+
+    #[tokio::test]
+    async fn unknown_codec() {
+        let (session, _server) = play_mock_with(
+            SessionOptions::default(),
+            UNKNOWN_CODEC,
+            SetupOptions::default(),
+        )
+        .await;
+        assert!(session.streams()[0].parameters().is_none());
+        let e = session.demuxed().err().unwrap();
+        assert!(e.to_string().contains("no depacketizer"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn allow_unknown_codecs() {
+        let (session, mut server) = play_mock_with(
+            SessionOptions::default().allow_unknown_codecs(true),
+            UNKNOWN_CODEC,
+            SetupOptions::default(),
+        )
+        .await;
+        let session = session.demuxed().unwrap();
+        tokio::pin!(session);
+        tokio::join!(
+            async {
+                match session.next().await {
+                    Some(Ok(CodecItem::RawRtp(p))) => {
+                        assert_eq!(p.stream_id, 0);
+                        assert_eq!(p.sequence_number, 0x41d4);
+                        assert_eq!(&p.payload[..], b"hello world");
+                    }
+                    o => panic!("unexpected item: {:#?}", o),
+                }
+            },
+            async {
+                let pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+                server
+                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                        0,
+                        Bytes::from_static(pkt),
+                    )))
+                    .await
+                    .unwrap();
+            },
+        );
+    }
+
+    /// Sends `n` consecutive RTP packets, starting with sequence number 0x41d4,
+    /// then reads the items a slow consumer sees under `policy`.
+    async fn queue_policy_items(policy: QueuePolicy, n: u16) -> Vec<rtp::Packet> {
+        tokio::time::pause();
+        let (session, mut server) = play_mock_with(
+            SessionOptions::default()
+                .allow_unknown_codecs(true)
+                .queue_policy(policy),
+            UNKNOWN_CODEC,
+            SetupOptions::default(),
+        )
+        .await;
+        let mut session = session.demuxed().unwrap();
+        for i in 0..n {
+            let mut pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world".to_vec();
+            pkt[2..4].copy_from_slice(&(0x41d4 + i).to_be_bytes());
+            server
+                .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from(pkt),
+                )))
+                .await
+                .unwrap();
+        }
+
+        // Give the producer time to read everything before consuming. With
+        // time paused, this returns once the runtime is otherwise idle.
+        tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+        let mut pkts = Vec::new();
+        let mut expected = 0;
+        while expected < n {
+            match session.next().await {
+                Some(Ok(CodecItem::RawRtp(p))) => {
+                    expected = p.sequence_number - 0x41d4 + 1;
+                    pkts.push(p);
+                }
+                o => panic!("unexpected item: {:#?}", o),
+            }
+        }
+        pkts
+    }
+
+    #[tokio::test]
+    async fn queue_policy_block() {
+        let pkts = queue_policy_items(QueuePolicy::Block(2), 5).await;
+        let seqs: Vec<u16> = pkts.iter().map(|p| p.sequence_number).collect();
+        assert_eq!(seqs, &[0x41d4, 0x41d5, 0x41d6, 0x41d7, 0x41d8]);
+        assert!(pkts.iter().all(|p| p.loss == 0));
+    }
+
+    #[tokio::test]
+    async fn queue_policy_drop_oldest() {
+        let pkts = queue_policy_items(QueuePolicy::DropOldest(2), 5).await;
+        let seqs: Vec<u16> = pkts.iter().map(|p| p.sequence_number).collect();
+        assert_eq!(seqs, &[0x41d7, 0x41d8]);
+        assert_eq!(pkts[0].loss, 3);
+        assert_eq!(pkts[1].loss, 0);
+    }
+
+    #[test]
+    fn queue_policy_parse() {
+        for &(s, p) in &[
+            ("direct", QueuePolicy::Direct),
+            ("block:8", QueuePolicy::Block(8)),
+            ("drop-oldest:16", QueuePolicy::DropOldest(16)),
+        ] {
+            assert_eq!(s.parse::<QueuePolicy>().unwrap(), p);
+            assert_eq!(p.to_string(), s);
+        }
+        "block".parse::<QueuePolicy>().unwrap_err();
+        "drop:3".parse::<QueuePolicy>().unwrap_err();
+    }
+
+    #[tokio::test]
+    async fn into_parts_after_error() {
+        let (mut session, mut server) = play_mock(SessionOptions::default()).await;
+        let mut first_timestamp = None;
+        tokio::join!(
+            async {
+                match session.next().await {
+                    Some(Ok(PacketItem::RtpPacket(p))) => first_timestamp = Some(p.timestamp),
+                    o => panic!("unexpected item: {:#?}", o),
+                }
+                match session.next().await {
+                    Some(Err(e)) => assert!(e.to_string().contains("Wrong ssrc"), "{}", e),
+                    o => panic!("unexpected item: {:#?}", o),
+                }
+            },
+            async {
+                for &pkt in &[
+                    &b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world"[..],
+                    &b"\x80\x60\x41\xd5\x00\x00\x00\x00\x01\x02\x03\x04hello world"[..],
+                ] {
+                    server
+                        .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                            0,
+                            Bytes::from_static(pkt),
+                        )))
+                        .await
+                        .unwrap();
+                }
+            },
+        );
+        let parts = session.into_parts();
+        assert_eq!(parts.streams().len(), 2);
+        assert_eq!(parts.streams()[0].encoding_name, "h264");
+        assert_eq!(parts.ssrc(0), Some(0xdcc4a0d8));
+        assert_eq!(parts.last_timestamp(0), first_timestamp);
+        assert!(first_timestamp.is_some());
+        assert_eq!(parts.last_timestamp(1), None);
+    }
+
+    #[tokio::test]
+    async fn reconnect() {
+        const PKT: &[u8] = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+
+        // One second of the old timeline elapses before the connection drops.
+        const LATER_PKT: &[u8] = b"\x80\x60\x41\xd5\x00\x01\x5f\x90\xdc\xc4\xa0\xd8hello world";
+        let (mut session, mut server) = describe_mock(SessionOptions::default()).await;
+        tokio::join!(
+            async { session.setup(0).await.unwrap() },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default().scale(2.0)),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        let mut session = session.unwrap();
+        let mut old = None;
+        for &pkt in &[PKT, LATER_PKT] {
+            let (item, _) = tokio::join!(
+                session.next(),
+                server.send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from_static(pkt),
+                ))),
+            );
+            old = match item {
+                Some(Ok(PacketItem::RtpPacket(p))) => Some(p.timestamp),
+                o => panic!("unexpected item: {:#?}", o),
+            };
+        }
+        let old = old.unwrap();
+        assert_eq!(old.elapsed(), 90_000);
+
+        // The connection drops; reconnect to a fresh server.
+        drop(server);
+        let parts = session.into_parts();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        let (conn, mut server) = connect_to_mock().await;
+        let (session, play_req) = tokio::join!(
+            super::reconnect_with_conn(conn, parts, SessionOptions::default()),
+            async {
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Describe,
+                    response(include_bytes!("testdata/reolink_describe.txt")),
+                )
+                .await;
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Setup,
+                    response(include_bytes!("testdata/reolink_setup.txt")),
+                )
+                .await;
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Play,
+                    response(include_bytes!("testdata/reolink_play.txt")),
+                )
+                .await
+            },
+        );
+        let (mut session, gap) = session.unwrap();
+
+        // The original play options are reused.
+        assert_eq!(
+            play_req
+                .header(&rtsp_types::headers::SCALE)
+                .map(|v| v.as_str()),
+            Some("2.0")
+        );
+        assert!(gap >= std::time::Duration::from_millis(50), "{:?}", gap);
+
+        // The same RTP timestamp now lands after the old one plus the gap.
+        let (item, _) = tokio::join!(
+            session.next(),
+            server.send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                0,
+                Bytes::from_static(PKT),
+            ))),
+        );
+        let new = match item {
+            Some(Ok(PacketItem::RtpPacket(p))) => p.timestamp,
+            o => panic!("unexpected item: {:#?}", o),
+        };
+        assert_eq!(new.start(), old.start());
+        let gap_ticks = (gap.as_secs_f64() * 90_000.0) as i64;
+        assert!(
+            (new.elapsed() - old.elapsed() - gap_ticks).abs() <= 90,
+            "old={} new={}",
+            old,
+            new
+        );
     }
 
-    pub fn streams(&self) -> &[Stream] {
-        &self.0.presentation.streams
-    }
+    #[tokio::test]
+    async fn loss_event() {
+        let (session, mut server) = play_mock_with(
+            SessionOptions::default().allow_unknown_codecs(true),
+            UNKNOWN_CODEC,
+            SetupOptions::default(),
+        )
+        .await;
+        let mut session = session.demuxed().unwrap();
+        for &seq in &[0x41d4u16, 0x41d8] {
+            let mut pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world".to_vec();
+            pkt[2..4].copy_from_slice(&seq.to_be_bytes());
+            server
+                .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from(pkt),
+                )))
+                .await
+                .unwrap();
+        }
+        match session.next().await {
+            Some(Ok(CodecItem::RawRtp(p))) => assert_eq!(p.sequence_number, 0x41d4),
+            o => panic!("unexpected item: {:#?}", o),
+        }
+        match session.next().await {
+            Some(Ok(CodecItem::Loss {
+                stream_id,
+                lost_range,
+                ..
+            })) => {
+                assert_eq!(stream_id, 0);
+                assert_eq!(lost_range, 0x41d5..0x41d8);
+            }
+            o => panic!("unexpected item: {:#?}", o),
+        }
 
-    /// Sends a `SETUP` request for a stream.
-    ///
-    /// Note these can't reasonably be pipelined because subsequent requests
-    /// are expected to adopt the previous response's `Session`. Likewise,
-    /// the server may override the preferred interleaved channel id and it
-    /// seems like a bad idea to try to assign more interleaved channels without
-    /// inspect that first.
-    ///
-    /// Panics if `stream_i >= self.streams().len()`.
-    pub async fn setup(&mut self, stream_i: usize) -> Result<(), Error> {
-        let inner = &mut self.0.as_mut().project();
-        let presentation = &mut inner.presentation;
-        let options = &inner.options;
-        let conn = inner
-            .conn
-            .as_mut()
-            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
-        let stream = &mut presentation.streams[stream_i];
-        if !matches!(stream.state, StreamState::Uninit) {
-            bail!(ErrorInt::FailedPrecondition("stream already set up".into()));
+        // The next item still reports the loss.
+        match session.next().await {
+            Some(Ok(CodecItem::RawRtp(p))) => {
+                assert_eq!(p.sequence_number, 0x41d8);
+                assert_eq!(p.loss, 3);
+            }
+            o => panic!("unexpected item: {:#?}", o),
         }
-        let url = stream
-            .control
-            .as_ref()
-            .unwrap_or(&presentation.control)
-            .clone();
-        let mut req = rtsp_types::Request::builder(Method::Setup, rtsp_types::Version::V1_0)
-            .request_uri(url)
-            .header(crate::X_DYNAMIC_RATE.clone(), "1".to_owned());
-        match options.transport {
-            Transport::Tcp => {
-                let proposed_channel_id = conn.channels.next_unassigned().ok_or_else(|| {
-                    wrap!(ErrorInt::FailedPrecondition(
-                        "no unassigned channels".into()
-                    ))
-                })?;
-                req = req.header(
-                    rtsp_types::headers::TRANSPORT,
-                    format!(
-                        "RTP/AVP/TCP;unicast;interleaved={}-{}",
-                        proposed_channel_id,
-                        proposed_channel_id + 1
-                    ),
-                );
+    }
+
+    #[tokio::test]
+    async fn no_depacketization() {
+        let (session, mut server) =
+            play_mock(SessionOptions::default().no_depacketization(true)).await;
+        assert!(session.streams()[0].parameters().is_some());
+        let mut session = session.demuxed().unwrap();
+        let pkt: &[u8] = b"\x80\xe0\x41\xd4\x3f\xd5\x3a\x05\xdc\xc4\xa0\xd8hello world";
+        server
+            .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                0,
+                Bytes::from_static(pkt),
+            )))
+            .await
+            .unwrap();
+        match session.next().await {
+            Some(Ok(CodecItem::RawRtp(p))) => {
+                assert_eq!(&p.header[..], &pkt[..12]);
+                assert_eq!(&p.payload[..], b"hello world");
+                assert!(p.mark);
             }
-            Transport::Udp => {
-                // Bind an ephemeral UDP port on the same local address used to connect
-                // to the RTSP server.
-                let ip_addr = conn.inner.ctx().local_addr.ip();
-                let pair = crate::tokio::UdpPair::for_ip(ip_addr)
-                    .map_err(|e| wrap!(ErrorInt::Internal(e.into())))?;
-                stream.sockets = Some(UdpSockets {
-                    local_ip: ip_addr,
-                    local_rtp_port: pair.rtp_port,
-                    remote_ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
-                    remote_rtp_port: 0,
-                    rtp_socket: pair.rtp_socket,
-                    remote_rtcp_port: 0,
-                    rtcp_socket: pair.rtcp_socket,
-                });
-                req = req.header(
-                    rtsp_types::headers::TRANSPORT,
-                    format!(
-                        "RTP/AVP/UDP;client_port={}-{}",
-                        pair.rtp_port,
-                        pair.rtp_port + 1,
-                    ),
-                );
+            o => panic!("unexpected item: {:#?}", o),
+        }
+    }
+
+    #[tokio::test]
+    async fn next_item_with_cancel() {
+        tokio::time::pause();
+        let (mut session, mut server) = play_mock(SessionOptions::default()).await;
+
+        // Nothing is available, so cancellation wins.
+        assert!(session
+            .next_item_with_cancel(tokio::time::sleep(std::time::Duration::from_millis(10)))
+            .await
+            .is_none());
+
+        // The session is intact afterward.
+        send_seqs(&mut server, &[0x41d4, 0x41d5]).await;
+        match session.next_item_with_cancel(std::future::pending()).await {
+            Some(Ok(PacketItem::RtpPacket(p))) => assert_eq!(p.sequence_number, 0x41d4),
+            o => panic!("unexpected item: {:#?}", o),
+        }
+        match session.next().await {
+            Some(Ok(PacketItem::RtpPacket(p))) => {
+                assert_eq!(p.sequence_number, 0x41d5);
+                assert_eq!(p.loss, 0);
             }
+            o => panic!("unexpected item: {:#?}", o),
         }
-        if let Some(ref s) = inner.session {
-            req = req.header(rtsp_types::headers::SESSION, s.id.to_string());
+    }
+
+    #[tokio::test]
+    async fn stream_combinators() {
+        let (session, mut server) = play_mock_with(
+            SessionOptions::default().allow_unknown_codecs(true),
+            UNKNOWN_CODEC,
+            SetupOptions::default(),
+        )
+        .await;
+        for seq in 0x41d4u16..0x41d9 {
+            let mut pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world".to_vec();
+            pkt[2..4].copy_from_slice(&seq.to_be_bytes());
+            server
+                .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from(pkt),
+                )))
+                .await
+                .unwrap();
         }
-        let (msg_ctx, cseq, response) = conn
-            .send(
-                ResponseMode::Normal,
-                &inner.options,
-                &mut inner.requested_auth,
-                &mut req.build(Bytes::new()),
-            )
-            .await?;
-        debug!("SETUP response: {:#?}", &response);
-        let conn_ctx = conn.inner.ctx();
-        let status = response.status();
-        let response = parse::parse_setup(&response).map_err(|description| {
-            wrap!(ErrorInt::RtspResponseError {
-                conn_ctx: *conn_ctx,
-                msg_ctx,
-                method: rtsp_types::Method::Setup,
-                cseq,
-                status,
-                description,
+        let seqs: Vec<u16> = session
+            .demuxed()
+            .unwrap()
+            .take(3)
+            .map(|item| match item {
+                Ok(CodecItem::RawRtp(p)) => p.sequence_number,
+                o => panic!("unexpected item: {:#?}", o),
             })
-        })?;
-        match inner.session.as_ref() {
-            Some(SessionHeader { id, .. }) if id.as_ref() != &*response.session.id => {
-                bail!(ErrorInt::RtspResponseError {
-                    conn_ctx: *conn.inner.ctx(),
-                    msg_ctx,
-                    method: rtsp_types::Method::Setup,
-                    cseq,
-                    status,
-                    description: format!(
-                        "session id changed from {:?} to {:?}",
-                        id, response.session.id,
-                    ),
-                });
-            }
-            Some(_) => {}
-            None => *inner.session = Some(response.session),
+            .collect()
+            .await;
+        assert_eq!(seqs, [0x41d4, 0x41d5, 0x41d6]);
+    }
+
+    #[tokio::test]
+    async fn request_keyframe() {
+        let (mut session, mut server) =
+            play_mock_with(SessionOptions::default(), PLI, SetupOptions::default()).await;
+        let rtcp_ssrc = session.0.rtcp_ssrc;
+        session.request_keyframe(0).await.unwrap();
+        let data = match server.next().await.unwrap().unwrap().msg {
+            rtsp_types::Message::Data(d) => d,
+            o => panic!("unexpected message: {:#?}", o),
         };
-        let conn_ctx = conn.inner.ctx();
-        match options.transport {
-            Transport::Tcp => {
-                let channel_id = match response.channel_id {
-                    Some(id) => id,
-                    None => bail!(ErrorInt::RtspResponseError {
-                        conn_ctx: *conn.inner.ctx(),
-                        msg_ctx,
-                        method: rtsp_types::Method::Setup,
-                        cseq,
-                        status,
-                        description: "Transport header is missing interleaved parameter".to_owned(),
-                    }),
-                };
-                conn.channels
-                    .assign(channel_id, stream_i)
-                    .map_err(|description| {
-                        wrap!(ErrorInt::RtspResponseError {
-                            conn_ctx: *conn_ctx,
-                            msg_ctx,
-                            method: rtsp_types::Method::Setup,
-                            cseq,
-                            status,
-                            description,
-                        })
-                    })?;
-            }
-            Transport::Udp => {
-                // TODO: RFC 2326 section 12.39 says "If the source address for
-                // the stream is different than can be derived from the RTSP
-                // endpoint address (the server in playback or the client in
-                // recording), the source MAY be specified." Not MUST,
-                // unfortunately. But let's see if we can get away with this
-                // for now.
-                let source = match response.source {
-                    Some(s) => s,
-                    None => conn.inner.ctx().peer_addr.ip(),
-                };
-                let server_port = response.server_port.ok_or_else(|| {
-                    wrap!(ErrorInt::RtspResponseError {
-                        conn_ctx: *conn_ctx,
-                        msg_ctx,
-                        method: rtsp_types::Method::Setup,
-                        cseq,
-                        status,
-                        description: "Transport header is missing server_port parameter".to_owned(),
-                    })
-                })?;
-                let udp_sockets = stream.sockets.as_mut().unwrap();
-                udp_sockets.remote_ip = source;
-                udp_sockets.remote_rtp_port = server_port.0;
-                udp_sockets.remote_rtcp_port = server_port.1;
-                udp_sockets
-                    .rtp_socket
-                    .connect(SocketAddr::new(source, udp_sockets.remote_rtp_port))
-                    .await
-                    .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
-                udp_sockets
-                    .rtcp_socket
-                    .connect(SocketAddr::new(source, udp_sockets.remote_rtcp_port))
-                    .await
-                    .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
-                punch_firewall_hole(&udp_sockets.rtp_socket, &udp_sockets.rtcp_socket)
-                    .await
-                    .map_err(|e| wrap!(ErrorInt::ConnectError(e)))?;
-            }
+        assert_eq!(data.channel_id(), 1);
+        let pkt = data.into_body();
+        assert_eq!(&pkt[..4], b"\x81\xce\x00\x02"); // fmt=1 (PLI), pt=206, len=2
+        assert_eq!(&pkt[4..8], &rtcp_ssrc.to_be_bytes());
+        assert_eq!(&pkt[8..], b"\xdc\xc4\xa0\xd8");
+
+        // Without a=rtcp-fb, it's a no-op.
+        let (mut session, _server) = play_mock(SessionOptions::default()).await;
+        session.request_keyframe(0).await.unwrap();
+    }
+
+    /// The Reolink video stream as `RTP/SAVP` with feedback, keyed with zeros.
+    #[cfg(feature = "srtp")]
+    const SAVP_PLI: &[(&str, &str)] = &[
+        ("m=video 0 RTP/AVP 96", "m=video 0 RTP/SAVP 96"),
+        (
+            "a=rtpmap:96 H264/90000\r\n",
+            "a=rtpmap:96 H264/90000\r\n\
+             a=rtcp-fb:96 nack pli\r\n\
+             a=crypto:1 AES_CM_128_HMAC_SHA1_80 \
+             inline:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA\r\n",
+        ),
+    ];
+
+    #[cfg(feature = "srtp")]
+    #[tokio::test]
+    async fn srtp() {
+        let (mut session, mut server) =
+            describe_mock_with(SessionOptions::default(), &reolink_describe(SAVP_PLI)).await;
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        r.unwrap();
+        let transport = req.header(&rtsp_types::headers::TRANSPORT).unwrap();
+        assert!(
+            transport.as_str().starts_with("RTP/SAVP/TCP;"),
+            "{}",
+            transport.as_str()
+        );
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        let mut session = session.unwrap();
+
+        // The PLI is sent as SRTCP.
+        let rtcp_ssrc = session.0.rtcp_ssrc;
+        session.request_keyframe(0).await.unwrap();
+        let data = match server.next().await.unwrap().unwrap().msg {
+            rtsp_types::Message::Data(d) => d,
+            o => panic!("unexpected message: {:#?}", o),
+        };
+        let mut ctx = super::srtp::parse_crypto(
+            "1 AES_CM_128_HMAC_SHA1_80 inline:AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA",
+        )
+        .unwrap();
+        let pkt = ctx.unprotect_rtcp(data.into_body()).unwrap();
+        assert_eq!(&pkt[..4], b"\x81\xce\x00\x02");
+        assert_eq!(&pkt[4..8], &rtcp_ssrc.to_be_bytes());
+        assert_eq!(&pkt[8..], b"\xdc\xc4\xa0\xd8");
+
+        // Without a usable a=crypto, SETUP fails before sending anything.
+        let (mut session, _server) = describe_mock_with(
+            SessionOptions::default(),
+            &reolink_describe(&[("m=video 0 RTP/AVP 96", "m=video 0 RTP/SAVP 96")]),
+        )
+        .await;
+        let e = session.setup(0).await.unwrap_err().to_string();
+        assert!(e.contains("no a=crypto attribute"), "{}", e);
+    }
+
+    /// Sends RTP packets with the given sequence numbers on channel 0.
+    async fn send_seqs(server: &mut crate::tokio::Connection, seqs: &[u16]) {
+        for &seq in seqs {
+            let mut pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world".to_vec();
+            pkt[2..4].copy_from_slice(&seq.to_be_bytes());
+            server
+                .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from(pkt),
+                )))
+                .await
+                .unwrap();
         }
-        stream.state = StreamState::Init(StreamStateInit {
-            ssrc: response.ssrc,
-            initial_seq: None,
-            initial_rtptime: None,
-        });
-        Ok(())
     }
 
-    /// Sends a `PLAY` request for the entire presentation.
-    ///
-    /// The presentation must support aggregate control, as defined in [RFC 2326
-    /// section 1.3](https://tools.ietf.org/html/rfc2326#section-1.3).
-    pub async fn play(mut self, policy: PlayOptions) -> Result<Session<Playing>, Error> {
-        let inner = self.0.as_mut().project();
-        let conn = inner
-            .conn
-            .as_mut()
-            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
-        let session = inner.session.as_ref().ok_or_else(|| {
-            wrap!(ErrorInt::FailedPrecondition(
-                "must SETUP before PLAY".into()
-            ))
-        })?;
-        if let Some(tool) = inner.presentation.tool.as_deref() {
-            if matches!(inner.options.transport, Transport::Tcp) && *inner.has_live555_tcp_bug {
-                warn!(
-                    "Connecting via TCP to known-broken RTSP server {:?}. \
-                       See <https://github.com/scottlamb/retina/issues/17>. \
-                       Consider using UDP instead!",
-                    tool
-                );
+    #[tokio::test]
+    async fn loss_policy() {
+        // Abort fails on the packet after the gap.
+        let (mut session, mut server) =
+            play_mock(SessionOptions::default().loss_policy(LossPolicy::Abort)).await;
+        send_seqs(&mut server, &[0x41d4, 0x41d8]).await;
+        match session.next().await {
+            Some(Ok(PacketItem::RtpPacket(p))) => assert_eq!(p.sequence_number, 0x41d4),
+            o => panic!("unexpected item: {:#?}", o),
+        }
+        let e = session.next().await.unwrap().unwrap_err();
+        assert!(e.to_string().contains("Lost 3 RTP packets"), "{}", e);
+
+        // RequestKeyframe passes the packet through and sends a PLI.
+        let (mut session, mut server) = play_mock_with(
+            SessionOptions::default().loss_policy(LossPolicy::RequestKeyframe),
+            PLI,
+            SetupOptions::default(),
+        )
+        .await;
+        send_seqs(&mut server, &[0x41d4, 0x41d8]).await;
+        session.next().await.unwrap().unwrap();
+        match session.next().await {
+            Some(Ok(PacketItem::RtpPacket(p))) => assert_eq!(p.loss, 3),
+            o => panic!("unexpected item: {:#?}", o),
+        }
+        let data = match server.next().await.unwrap().unwrap().msg {
+            rtsp_types::Message::Data(d) => d,
+            o => panic!("unexpected message: {:#?}", o),
+        };
+        assert_eq!(data.channel_id(), 1);
+        assert_eq!(&data.into_body()[..4], b"\x81\xce\x00\x02"); // PLI
+
+        assert_eq!(
+            "request-keyframe".parse::<LossPolicy>().unwrap(),
+            LossPolicy::RequestKeyframe
+        );
+        assert_eq!(LossPolicy::default().to_string(), "tolerate");
+    }
+
+    #[tokio::test]
+    async fn video_frame_durations_flush() {
+        let (session, mut server) =
+            play_mock(SessionOptions::default().video_frame_durations(true)).await;
+        let mut session = session.demuxed().unwrap();
+        for pkt in [
+            &b"\x80\xe0\x41\xd4\x3f\xd5\x3a\x05\xdc\xc4\xa0\xd8\x65slice"[..],
+            &b"\x80\xe0\x41\xd5\x3f\xd5\x45\xbd\xdc\xc4\xa0\xd8\x65slice"[..],
+        ] {
+            server
+                .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from_static(pkt),
+                )))
+                .await
+                .unwrap();
+        }
+        drop(server);
+
+        // The last frame is held until the session ends, then yielded with
+        // no duration.
+        for expected in [Some(3000), None] {
+            match session.next().await {
+                Some(Ok(CodecItem::VideoFrame(f))) => assert_eq!(f.duration(), expected),
+                o => panic!("unexpected item: {:#?}", o),
             }
         }
+        assert!(session.next().await.is_none());
+    }
 
-        trace!("PLAY with channel mappings: {:#?}", &conn.channels);
-        *inner.maybe_playing = true;
-        let (msg_ctx, cseq, response) = conn
-            .send(
-                ResponseMode::Play,
-                &inner.options,
-                inner.requested_auth,
-                &mut rtsp_types::Request::builder(Method::Play, rtsp_types::Version::V1_0)
-                    .request_uri(inner.presentation.control.clone())
-                    .header(rtsp_types::headers::SESSION, &*session.id)
-                    .header(rtsp_types::headers::RANGE, "npt=0.000-".to_owned())
-                    .build(Bytes::new()),
-            )
-            .await?;
-        parse::parse_play(&response, inner.presentation).map_err(|description| {
-            wrap!(ErrorInt::RtspResponseError {
-                conn_ctx: *conn.inner.ctx(),
-                msg_ctx,
-                method: rtsp_types::Method::Play,
-                cseq,
-                status: response.status(),
-                description,
-            })
-        })?;
+    #[tokio::test]
+    async fn override_clock_rate() {
+        // Sends a packet one second (at the SDP's 90 kHz) after the PLAY
+        // response's rtptime, and returns its elapsed seconds.
+        async fn elapsed_secs(setup_options: SetupOptions) -> f64 {
+            let (mut session, mut server) =
+                play_mock_with(SessionOptions::default(), &[], setup_options).await;
+            let mut pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world".to_vec();
+            pkt[4..8].copy_from_slice(&(1070938629u32 + 90_000).to_be_bytes());
+            server
+                .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from(pkt),
+                )))
+                .await
+                .unwrap();
+            match session.next().await {
+                Some(Ok(PacketItem::RtpPacket(p))) => p.timestamp.elapsed_secs(),
+                o => panic!("unexpected item: {:#?}", o),
+            }
+        }
+        assert_eq!(elapsed_secs(SetupOptions::default()).await, 1.0);
+        assert_eq!(
+            elapsed_secs(SetupOptions::default().override_clock_rate(45_000)).await,
+            2.0
+        );
 
-        // Count how many streams have been setup (not how many are in the presentation).
-        let setup_streams = inner
-            .presentation
-            .streams
-            .iter()
-            .filter(|s| matches!(s.state, StreamState::Init(_)))
-            .count();
+        let (mut session, _server) = describe_mock(SessionOptions::default()).await;
+        let e = session
+            .setup_with_options(0, SetupOptions::default().override_clock_rate(0))
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("non-zero"), "{}", e);
+    }
 
-        let all_have_time = inner.presentation.streams.iter().all(|s| match s.state {
-            StreamState::Init(StreamStateInit {
-                initial_rtptime, ..
-            }) => initial_rtptime.is_some(),
-            _ => true,
+    #[tokio::test]
+    async fn response_timeout() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+        let options =
+            SessionOptions::default().response_timeout(std::time::Duration::from_millis(10));
+        let (session, _) = tokio::join!(Session::describe_with_conn(conn, options, url), async {
+            // Read the request but never respond.
+            server.next().await.unwrap().unwrap();
         });
+        let e = session.err().unwrap();
+        assert!(
+            e.to_string()
+                .contains("No response to DESCRIBE CSeq 1 within"),
+            "{}",
+            e
+        );
+    }
 
-        // Move all streams that have been set up from Init to Playing state. Check that required
-        // parameters are present while doing so.
-        for (i, s) in inner.presentation.streams.iter_mut().enumerate() {
-            match s.state {
-                StreamState::Init(StreamStateInit {
-                    initial_rtptime,
-                    initial_seq,
-                    ssrc,
-                    ..
-                }) => {
-                    let initial_rtptime = match policy.initial_timestamp {
-                        InitialTimestampPolicy::Require | InitialTimestampPolicy::Default
-                            if setup_streams > 1 =>
-                        {
-                            if initial_rtptime.is_none() {
-                                bail!(ErrorInt::RtspResponseError {
-                                    conn_ctx: *conn.inner.ctx(),
-                                    msg_ctx,
-                                    method: rtsp_types::Method::Play,
-                                    cseq,
-                                    status: response.status(),
-                                    description: format!(
-                                        "Expected rtptime on PLAY with mode {:?}, missing on \
-                                             stream {} ({:?}). Consider setting initial timestamp \
-                                             mode permissive.",
-                                        policy.initial_timestamp, i, &s.control
-                                    ),
-                                });
-                            }
-                            initial_rtptime
-                        }
-                        InitialTimestampPolicy::Permissive
-                            if setup_streams > 1 && all_have_time =>
-                        {
-                            initial_rtptime
-                        }
-                        _ => None,
-                    };
-                    let initial_seq = match initial_seq {
-                        Some(0) if policy.ignore_zero_seq => {
-                            log::info!("Ignoring seq=0 on stream {}", i);
-                            None
-                        }
-                        o => o,
-                    };
-                    let conn_ctx = conn.inner.ctx();
-                    s.state = StreamState::Playing {
-                        timeline: Timeline::new(
-                            initial_rtptime,
-                            s.clock_rate,
-                            policy.enforce_timestamps_with_max_jump_secs,
-                        )
-                        .map_err(|description| {
-                            wrap!(ErrorInt::RtspResponseError {
-                                conn_ctx: *conn_ctx,
-                                msg_ctx,
-                                method: rtsp_types::Method::Play,
-                                cseq,
-                                status: response.status(),
-                                description,
-                            })
-                        })?,
-                        rtp_handler: rtp::InorderParser::new(ssrc, initial_seq),
-                    };
+    #[tokio::test]
+    async fn data_timeout() {
+        tokio::time::pause();
+        let (session, mut server) = play_mock(
+            SessionOptions::default().data_timeout(std::time::Duration::from_millis(100)),
+        )
+        .await;
+        tokio::pin!(session);
+
+        // Data postpones the timeout.
+        tokio::join!(
+            async {
+                match session.next().await {
+                    Some(Ok(PacketItem::RtpPacket(_))) => {}
+                    o => panic!("unexpected item: {:#?}", o),
                 }
-                StreamState::Uninit => {}
-                StreamState::Playing { .. } => unreachable!(),
-            };
+            },
+            async {
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                let pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+                server
+                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                        0,
+                        Bytes::from_static(pkt),
+                    )))
+                    .await
+                    .unwrap();
+            },
+        );
+
+        // Then the server stalls.
+        match session.next().await {
+            Some(Err(e)) => assert!(
+                e.to_string().contains("No RTP/RTCP data received within"),
+                "{}",
+                e
+            ),
+            o => panic!("unexpected item: {:#?}", o),
         }
-        *inner.keepalive_timer = Some(Box::pin(tokio::time::sleep(KEEPALIVE_DURATION)));
-        Ok(Session(self.0, Playing(())))
     }
-}
 
-/// Checks if the `tool` entry refers to a live555 version affected by
-/// [#17](https://github.com/scottlamb/retina/issues/17).
-fn has_live555_tcp_bug(tool: &str) -> bool {
-    const PREFIX: &str = "LIVE555 Streaming Media v";
-    if !tool.starts_with(PREFIX) {
-        return false;
-    }
-    let version = &tool[PREFIX.len()..];
-    version > "0000.00.00" && version < "2017.06.04"
-}
+    /// Tests ignoring a bogus RTCP message while waiting for PLAY response.
+    #[tokio::test]
+    async fn ignore_early_rtcp() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+        let bogus_pkt = rtsp_types::Message::Data(rtsp_types::Data::new(
+            1,                                // RTCP channel
+            Bytes::from_static(b"bogus pkt"), // the real packet parses but this is fine.
+        ));
 
-/// Sends dummy RTP and RTCP packets to punch a hole in connection-tracking
-/// firewalls.
-///
-/// This is useful when the client is on the protected side of the firewall and
-/// the server isn't. The server should discard these dummy packets, but they
-/// prompt the firewall to add the appropriate connection tracking state for
-/// server->client packets to make it through.
-///
-/// Note this is insufficient for NAT traversal; the NAT firewall must be
-/// RTSP-aware to rewrite the Transport header's client_ports.
-async fn punch_firewall_hole(
-    rtp_socket: &UdpSocket,
-    rtcp_socket: &UdpSocket,
-) -> Result<(), std::io::Error> {
-    #[rustfmt::skip]
-    const DUMMY_RTP: [u8; 12] = [
-        2 << 6,     // version=2 + p=0 + x=0 + cc=0
-        0,          // m=0 + pt=0
-        0, 0,       // sequence number=0
-        0, 0, 0, 0, // timestamp=0 0,
-        0, 0, 0, 0, // ssrc=0
-    ];
-    #[rustfmt::skip]
-    const DUMMY_RTCP: [u8; 8] = [
-        2 << 6,     // version=2 + p=0 + rc=0
-        200,        // pt=200 (reception report)
-        0, 1,       // length=1 (in 4-byte words minus 1)
-        0, 0, 0, 0, // ssrc=0 (bogus but we don't know the ssrc reliably yet)
-    ];
-    rtp_socket.send(&DUMMY_RTP[..]).await?;
-    rtcp_socket.send(&DUMMY_RTCP[..]).await?;
-    Ok(())
-}
+        // DESCRIBE.
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, SessionOptions::default(), url),
+            async {
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Describe,
+                    response(include_bytes!("testdata/reolink_describe.txt")),
+                )
+                .await;
+            },
+        );
+        let mut session = session.unwrap();
+        assert_eq!(session.streams().len(), 2);
 
-#[derive(Debug)]
-pub enum PacketItem {
-    RtpPacket(rtp::Packet),
-    SenderReport(rtp::SenderReport),
-}
+        // SETUP.
+        tokio::join!(
+            async {
+                session.setup(0).await.unwrap();
+            },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
 
-impl Session<Playing> {
-    /// Returns a wrapper which demuxes/depacketizes into frames.
-    ///
-    /// Fails if a stream that has been setup can't be depacketized.
-    pub fn demuxed(mut self) -> Result<Demuxed, Error> {
-        let inner = self.0.as_mut().project();
-        let conn = inner
-            .conn
-            .as_ref()
-            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
-        for s in &mut inner.presentation.streams {
-            if matches!(s.state, StreamState::Playing { .. }) {
-                if let Err(ref description) = s.depacketizer {
-                    bail!(ErrorInt::RtspResponseError {
-                        conn_ctx: *conn.inner.ctx(),
-                        msg_ctx: *inner.describe_ctx,
-                        method: rtsp_types::Method::Describe,
-                        cseq: *inner.describe_cseq,
-                        status: *inner.describe_status,
-                        description: description.clone(),
-                    });
-                }
-            }
-        }
-        Ok(Demuxed {
-            state: DemuxedState::Waiting,
-            session: self,
-        })
+        // PLAY.
+        let (session, _) = tokio::join!(session.play(PlayOptions::default()), async move {
+            server.send(bogus_pkt).await.unwrap();
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt")),
+            )
+            .await
+        });
+        let _session = session.unwrap();
     }
 
-    /// Sends a `TEARDOWN`, ending the session.
-    #[deprecated(since = "0.3.1", note = "Use SessionGroup::await_teardown instead")]
-    pub async fn teardown(mut self) -> Result<(), Error> {
-        let inner = self.0.as_mut().project();
-        let mut req = rtsp_types::Request::builder(Method::Teardown, rtsp_types::Version::V1_0)
-            .request_uri(inner.presentation.base_url.clone())
-            .header(
-                rtsp_types::headers::SESSION,
-                inner.session.as_ref().unwrap().id.to_string(),
-            )
-            .build(Bytes::new());
-        inner
-            .conn
-            .as_mut()
-            .unwrap()
-            .send(
-                ResponseMode::Teardown,
-                inner.options,
-                inner.requested_auth,
-                &mut req,
-            )
-            .await?;
-        *inner.session = None;
-        *inner.maybe_playing = false;
-        Ok(())
+    #[tokio::test]
+    async fn explicit_teardown() {
+        let (mut session, mut server) =
+            play_mock(SessionOptions::default().teardown(TeardownPolicy::Always)).await;
+        let (r, req) = tokio::join!(
+            session.teardown(),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Teardown,
+                response(b"RTSP/1.0 200 OK\r\n\r\n"),
+            ),
+        );
+        r.unwrap();
+        assert_eq!(
+            req.header(&rtsp_types::headers::SESSION).unwrap().as_str(),
+            "F8F8E425"
+        );
+
+        // Dropping the session shouldn't send another TEARDOWN; the server
+        // should simply see the connection close.
+        drop(session);
+        assert!(server.next().await.is_none());
     }
 
-    pub fn streams(&self) -> &[Stream] {
-        &self.0.presentation.streams
+    #[tokio::test]
+    async fn teardown_on_drop() {
+        let (session, mut server) = play_mock(
+            SessionOptions::default()
+                .teardown(TeardownPolicy::Always)
+                .teardown_on_drop(TeardownOnDrop::SpawnBestEffort),
+        )
+        .await;
+        drop(session);
+        let req = req_response(
+            &mut server,
+            rtsp_types::Method::Teardown,
+            response(b"RTSP/1.0 200 OK\r\n\r\n"),
+        )
+        .await;
+        assert_eq!(
+            req.header(&rtsp_types::headers::SESSION).unwrap().as_str(),
+            "F8F8E425"
+        );
+        assert!(server.next().await.is_none());
+
+        let (session, mut server) = play_mock(
+            SessionOptions::default()
+                .teardown(TeardownPolicy::Always)
+                .teardown_on_drop(TeardownOnDrop::None),
+        )
+        .await;
+        drop(session);
+        assert!(server.next().await.is_none());
     }
 
-    fn handle_keepalive_timer(
-        mut self: Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> Result<(), Error> {
-        let inner = self.0.as_mut().project();
-        let conn = inner
-            .conn
-            .as_mut()
-            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
-        // Expect the previous keepalive request to have finished.
-        match inner.keepalive_state {
-            KeepaliveState::Flushing(cseq) => bail!(ErrorInt::WriteError {
-                conn_ctx: *conn.inner.ctx(),
-                source: std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    format!(
-                        "Unable to write keepalive {} within {:?}",
-                        cseq, KEEPALIVE_DURATION,
-                    ),
-                ),
-            }),
-            KeepaliveState::Waiting(cseq) => bail!(ErrorInt::RtspReadError {
-                conn_ctx: *conn.inner.ctx(),
-                msg_ctx: conn.inner.eof_ctx(),
-                source: std::io::Error::new(
-                    std::io::ErrorKind::TimedOut,
-                    format!(
-                        "Server failed to respond to keepalive {} within {:?}",
-                        cseq, KEEPALIVE_DURATION,
-                    ),
-                ),
-            }),
-            KeepaliveState::Idle => {}
+    #[tokio::test(flavor = "multi_thread")]
+    async fn teardown_blocking_in_drop() {
+        let (session, mut server) = play_mock(
+            SessionOptions::default()
+                .teardown(TeardownPolicy::Always)
+                .teardown_on_drop(TeardownOnDrop::BlockingInDrop),
+        )
+        .await;
+        let received = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let received_clone = received.clone();
+        let server = tokio::spawn(async move {
+            let msg = server.next().await.unwrap().unwrap();
+            let req = match msg.msg {
+                rtsp_types::Message::Request(r) => r,
+                o => panic!("unexpected message: {:#?}", o),
+            };
+            assert_eq!(req.method(), rtsp_types::Method::Teardown);
+            received_clone.store(true, std::sync::atomic::Ordering::SeqCst);
+            let mut response = response(b"RTSP/1.0 200 OK\r\n\r\n");
+            response.insert_header(
+                rtsp_types::headers::CSEQ,
+                req.header(&rtsp_types::headers::CSEQ).unwrap().as_str(),
+            );
+            server
+                .send(rtsp_types::Message::Response(response))
+                .await
+                .unwrap();
+        });
+
+        // The TEARDOWN has been received by the time drop returns.
+        drop(session);
+        assert!(received.load(std::sync::atomic::Ordering::SeqCst));
+        server.await.unwrap();
+    }
+
+    /// On a current-thread runtime, drop can't wait for the `TEARDOWN`, so it
+    /// returns promptly and sends it in the background.
+    #[tokio::test]
+    async fn teardown_blocking_in_drop_current_thread() {
+        let (session, mut server) = play_mock(
+            SessionOptions::default()
+                .teardown(TeardownPolicy::Always)
+                .teardown_on_drop(TeardownOnDrop::BlockingInDrop),
+        )
+        .await;
+        let start = Instant::now();
+        drop(session);
+        assert!(start.elapsed() < TEARDOWN_TIMEOUT);
+        req_response(
+            &mut server,
+            rtsp_types::Method::Teardown,
+            response(b"RTSP/1.0 200 OK\r\n\r\n"),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn keepalive_method() {
+        for (keepalive_method, expected) in vec![
+            (KeepaliveMethod::Options, rtsp_types::Method::Options),
+            (
+                KeepaliveMethod::GetParameter,
+                rtsp_types::Method::GetParameter,
+            ),
+            (
+                KeepaliveMethod::SetParameter,
+                rtsp_types::Method::SetParameter,
+            ),
+        ] {
+            let (session, mut server) =
+                play_mock(SessionOptions::default().keepalive_method(keepalive_method)).await;
+            tokio::pin!(session);
+            futures::future::poll_fn(|cx| Poll::Ready(session.as_mut().handle_keepalive_timer(cx)))
+                .await
+                .unwrap();
+            match server.next().await.unwrap().unwrap().msg {
+                rtsp_types::Message::Request(r) => {
+                    assert_eq!(r.method(), expected);
+                    assert_eq!(
+                        r.header(&rtsp_types::headers::SESSION).unwrap().as_str(),
+                        "F8F8E425"
+                    );
+                    assert_eq!(
+                        r.header(&rtsp_types::headers::USER_AGENT).unwrap().as_str(),
+                        DEFAULT_USER_AGENT
+                    );
+                }
+                o => panic!("unexpected message: {:#?}", o),
+            }
         }
+    }
 
-        // Currently the only outbound data should be keepalives, and the previous one
-        // has already been flushed, so there's no reason the Sink shouldn't be ready.
-        if matches!(conn.inner.poll_ready_unpin(cx), Poll::Pending) {
-            bail!(ErrorInt::Internal(
-                "Unexpectedly not ready to send keepalive".into()
-            ));
+    #[tokio::test]
+    async fn user_agent() {
+        for (options, expected) in vec![
+            (SessionOptions::default(), Some(DEFAULT_USER_AGENT)),
+            (
+                SessionOptions::default().user_agent("my agent/1.0".to_owned()),
+                Some("my agent/1.0"),
+            ),
+            (SessionOptions::default().user_agent(String::new()), None),
+        ] {
+            let (conn, mut server) = connect_to_mock().await;
+            let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+            let (session, req) = tokio::join!(
+                Session::describe_with_conn(conn, options, url),
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Describe,
+                    response(include_bytes!("testdata/reolink_describe.txt"))
+                ),
+            );
+            session.unwrap();
+            assert_eq!(
+                req.header(&rtsp_types::headers::USER_AGENT)
+                    .map(|h| h.as_str()),
+                expected
+            );
         }
+    }
 
-        // Send a new one and reset the timer.
-        // Use a SET_PARAMETER with no body for keepalives, as recommended in the
-        // ONVIF Streaming Specification version version 21.06 section 5.2.2.2.
-        let session_id = &*inner.session.as_ref().unwrap().id;
-        let mut req = rtsp_types::Request::builder(Method::SetParameter, rtsp_types::Version::V1_0)
-            .request_uri(inner.presentation.base_url.clone())
-            .header(rtsp_types::headers::SESSION, session_id.to_string())
-            .build(Bytes::new());
-        let cseq = conn.fill_req(inner.options, inner.requested_auth, &mut req)?;
-        conn.inner
-            .start_send_unpin(rtsp_types::Message::Request(req))
-            .expect("encoding is infallible");
-        *inner.keepalive_state = match conn.inner.poll_flush_unpin(cx) {
-            Poll::Ready(Ok(())) => KeepaliveState::Waiting(cseq),
-            Poll::Ready(Err(e)) => bail!(e),
-            Poll::Pending => KeepaliveState::Flushing(cseq),
+    #[tokio::test]
+    async fn extra_headers() {
+        let token = rtsp_types::HeaderName::from_static_str("x-Session-Token").unwrap();
+        let options = SessionOptions::default().extra_headers(vec![
+            (token.clone(), "secret".to_owned()),
+            (rtsp_types::headers::ACCEPT, "text/plain".to_owned()),
+            (rtsp_types::headers::CSEQ, "999".to_owned()),
+            (crate::X_DYNAMIC_RATE.clone(), "0".to_owned()),
+        ]);
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+        let (session, req) = tokio::join!(
+            Session::describe_with_conn(conn, options, url),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(include_bytes!("testdata/reolink_describe.txt"))
+            ),
+        );
+        let mut session = session.unwrap();
+        let header = |req: &rtsp_types::Request<Bytes>, name: &rtsp_types::HeaderName| {
+            req.header(name).map(|h| h.as_str().to_owned())
         };
+        assert_eq!(header(&req, &token).as_deref(), Some("secret"));
+        assert_eq!(
+            header(&req, &rtsp_types::headers::ACCEPT).as_deref(),
+            Some("application/sdp")
+        );
+        assert_ne!(
+            header(&req, &rtsp_types::headers::CSEQ).as_deref(),
+            Some("999")
+        );
 
-        inner
-            .keepalive_timer
-            .as_mut()
-            .expect("keepalive timer set in state Playing")
-            .as_mut()
-            .reset(tokio::time::Instant::now() + KEEPALIVE_DURATION);
-        Ok(())
+        let (_, req) = tokio::join!(
+            async {
+                session.setup(0).await.unwrap();
+            },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        assert_eq!(header(&req, &token).as_deref(), Some("secret"));
+        assert_eq!(header(&req, &*crate::X_DYNAMIC_RATE).as_deref(), Some("1"));
     }
 
-    fn handle_response(
-        mut self: Pin<&mut Self>,
-        msg_ctx: &crate::RtspMessageContext,
-        response: rtsp_types::Response<Bytes>,
-    ) -> Result<(), Error> {
-        let inner = self.0.as_mut().project();
-        if matches!(inner.keepalive_state,
-                    KeepaliveState::Waiting(cseq) if parse::get_cseq(&response) == Some(*cseq))
-        {
-            // We don't care if the keepalive response succeeds or fails. Just mark complete.
-            *inner.keepalive_state = KeepaliveState::Idle;
-            return Ok(());
-        }
+    #[tokio::test]
+    async fn accept_dynamic_rate() {
+        let (mut session, mut server) =
+            describe_mock(SessionOptions::default().accept_dynamic_rate(true)).await;
+        let (_, req) = tokio::join!(
+            async {
+                session.setup(0).await.unwrap();
+            },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      CSeq: 4\r\n\
+                      Transport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n\
+                      Session: F8F8E425\r\n\
+                      x-Dynamic-Rate: 1\r\n\
+                      \r\n"
+                )
+            ),
+        );
+        assert_eq!(
+            req.header(&crate::X_ACCEPT_DYNAMIC_RATE)
+                .map(|h| h.as_str()),
+            Some("1")
+        );
+        assert_eq!(session.dynamic_rate(), Some(true));
+        let (session, req) = tokio::join!(
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        assert_eq!(
+            req.header(&crate::X_ACCEPT_DYNAMIC_RATE)
+                .map(|h| h.as_str()),
+            Some("1")
+        );
 
-        // The only response we expect in this state is to our keepalive request.
-        bail!(ErrorInt::RtspFramingError {
-            conn_ctx: *inner
-                .conn
-                .as_ref()
-                .expect("have conn when handling response")
-                .inner
-                .ctx(),
-            msg_ctx: *msg_ctx,
-            description: format!("Unexpected RTSP response {:#?}", response),
-        })
+        // The PLAY response doesn't repeat the header.
+        assert_eq!(session.unwrap().dynamic_rate(), Some(true));
+
+        // By default, the header isn't sent.
+        let (mut session, mut server) = describe_mock(SessionOptions::default()).await;
+        let (_, req) = tokio::join!(
+            async {
+                session.setup(0).await.unwrap();
+            },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        assert!(req.header(&crate::X_ACCEPT_DYNAMIC_RATE).is_none());
+        assert_eq!(session.dynamic_rate(), None);
     }
 
-    fn handle_data(
-        mut self: Pin<&mut Self>,
-        msg_ctx: &RtspMessageContext,
-        data: rtsp_types::Data<Bytes>,
-    ) -> Result<Option<PacketItem>, Error> {
-        let inner = self.0.as_mut().project();
-        let conn = inner
-            .conn
-            .as_mut()
-            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?;
-        let channel_id = data.channel_id();
-        let pkt_ctx = crate::PacketContext(crate::PacketContextInner::Tcp {
-            msg_ctx: *msg_ctx,
-            channel_id,
-        });
-        let m = match conn.channels.lookup(channel_id) {
-            Some(m) => m,
-            None => bail!(ErrorInt::RtspUnassignedChannelError {
-                conn_ctx: *conn.inner.ctx(),
-                msg_ctx: *msg_ctx,
-                channel_id,
-            }),
-        };
-        let stream = &mut inner.presentation.streams[m.stream_i];
-        let (mut timeline, rtp_handler) = match &mut stream.state {
-            StreamState::Playing {
-                timeline,
-                rtp_handler,
-            } => (timeline, rtp_handler),
-            _ => unreachable!(
-                "Session<Playing>'s {}->{:?} not in Playing state",
-                channel_id, m
+    /// Returns a `SETUP` response for UDP with the given server ports.
+    fn udp_setup_response(server_rtp_port: u16) -> rtsp_types::Response<Bytes> {
+        rtsp_types::Response::builder(rtsp_types::Version::V1_0, rtsp_types::StatusCode::Ok)
+            .header(rtsp_types::headers::SESSION, "F8F8E425".to_owned())
+            .header(
+                rtsp_types::headers::TRANSPORT,
+                format!(
+                    "RTP/AVP/UDP;unicast;client_port=1-2;server_port={}-{}",
+                    server_rtp_port,
+                    server_rtp_port + 1
+                ),
+            )
+            .build(Bytes::new())
+    }
+
+    fn transport_header(req: &rtsp_types::Request<Bytes>) -> String {
+        req.header(&rtsp_types::headers::TRANSPORT)
+            .unwrap()
+            .as_str()
+            .to_owned()
+    }
+
+    #[tokio::test]
+    async fn transport_policy_tcp_only() {
+        let (mut session, mut server) =
+            describe_mock(SessionOptions::default().transport_policy(TransportPolicy::TcpOnly))
+                .await;
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
             ),
-        };
-        match m.channel_type {
-            ChannelType::Rtp => Ok(rtp_handler.rtp(
-                &inner.options,
-                conn.inner.ctx(),
-                &pkt_ctx,
-                &mut timeline,
-                m.stream_i,
-                data.into_body(),
-            )?),
-            ChannelType::Rtcp => {
-                match rtp_handler.rtcp(
-                    &inner.options,
-                    &pkt_ctx,
-                    &mut timeline,
-                    m.stream_i,
-                    data.into_body(),
-                ) {
-                    Ok(p) => Ok(p),
-                    Err(description) => Err(wrap!(ErrorInt::PacketError {
-                        conn_ctx: *conn.inner.ctx(),
-                        pkt_ctx: pkt_ctx,
-                        stream_id: m.stream_i,
-                        description,
-                    })),
-                }
-            }
-        }
+        );
+        r.unwrap();
+        assert!(transport_header(&req).starts_with("RTP/AVP/TCP;"));
+        assert!(matches!(session.active_transport(), Transport::Tcp));
     }
 
-    /// Polls a single UDP stream, `inner.presentation.streams[i]`.
-    ///
-    /// Assumes `buf` is cleared and large enough for any UDP packet.
-    /// Only returns `Poll::Pending` after both RTCP and RTP sockets have
-    /// returned `Poll::Pending`.
-    fn poll_udp_stream(
-        &mut self,
-        cx: &mut std::task::Context,
-        buf: &mut tokio::io::ReadBuf,
-        i: usize,
-    ) -> Poll<Option<Result<PacketItem, Error>>> {
-        debug_assert!(buf.filled().is_empty());
-        let inner = self.0.as_mut().project();
-        let conn_ctx = inner
-            .conn
-            .as_ref()
-            .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?
-            .inner
-            .ctx();
-        let s = &mut inner.presentation.streams[i];
-        if let Some(sockets) = &mut s.sockets {
-            let (mut timeline, rtp_handler) = match &mut s.state {
-                StreamState::Playing {
-                    timeline,
-                    rtp_handler,
-                } => (timeline, rtp_handler),
-                _ => unreachable!("Session<Playing>'s {}->{:?} not in Playing state", i, s),
-            };
-            // Prioritize RTCP over RTP within a stream.
-            while let Poll::Ready(r) = sockets.rtcp_socket.poll_recv(cx, buf) {
-                let pkt_ctx = crate::PacketContext(crate::PacketContextInner::Udp {
-                    local_addr: SocketAddr::new(sockets.local_ip, sockets.local_rtp_port + 1),
-                    peer_addr: SocketAddr::new(sockets.remote_ip, sockets.remote_rtcp_port),
-                    received_wall: crate::WallTime::now(),
-                    received: Instant::now(),
-                });
-                match r {
-                    Ok(()) => {
-                        let msg = Bytes::copy_from_slice(buf.filled());
-                        match rtp_handler.rtcp(&inner.options, &pkt_ctx, &mut timeline, i, msg) {
-                            Ok(Some(p)) => return Poll::Ready(Some(Ok(p))),
-                            Ok(None) => buf.clear(),
-                            Err(description) => {
-                                return Poll::Ready(Some(Err(wrap!(ErrorInt::PacketError {
-                                    conn_ctx: *conn_ctx,
-                                    pkt_ctx,
-                                    stream_id: i,
-                                    description,
-                                }))))
-                            }
-                        }
-                    }
-                    Err(source) => {
-                        return Poll::Ready(Some(Err(wrap!(ErrorInt::UdpRecvError {
-                            conn_ctx: *conn_ctx,
-                            pkt_ctx,
-                            source,
-                        }))))
-                    }
+    /// Plays via UDP against `server_pair`, returning the client's RTP port.
+    async fn play_udp_mock(
+        options: SessionOptions,
+        server_pair: &crate::UdpPair,
+    ) -> (Session<Playing>, crate::tokio::Connection, u16) {
+        play_udp_mock_with(options, &[], server_pair).await
+    }
+
+    /// As [`play_udp_mock`], with `sdp_edits` applied as in [`reolink_describe`].
+    async fn play_udp_mock_with(
+        options: SessionOptions,
+        sdp_edits: &[(&str, &str)],
+        server_pair: &crate::UdpPair,
+    ) -> (Session<Playing>, crate::tokio::Connection, u16) {
+        let (mut session, mut server) = describe_mock_with(
+            options.transport(Transport::Udp),
+            &reolink_describe(sdp_edits),
+        )
+        .await;
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                udp_setup_response(server_pair.rtp_port),
+            ),
+        );
+        r.unwrap();
+        let transport = transport_header(&req);
+        let client_port = transport
+            .split(';')
+            .find_map(|p| p.strip_prefix("client_port="))
+            .and_then(|p| p.split('-').next())
+            .unwrap()
+            .parse()
+            .unwrap();
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        (session.unwrap(), server, client_port)
+    }
+
+    #[tokio::test]
+    async fn resolver() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let lookups = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let lookups_clone = lookups.clone();
+        let options = SessionOptions::default().resolver(Arc::new(move |host, port| {
+            lookups_clone.lock().unwrap().push((host.to_owned(), port));
+            vec![addr]
+        }));
+        let url = Url::parse("rtsp://camera.invalid/").unwrap();
+        let (conn, server) =
+            tokio::join!(RtspConnection::connect(&url, &options), listener.accept());
+        let conn = conn.unwrap();
+        assert_eq!(conn.inner.ctx().peer_addr, addr);
+        assert_eq!(conn.inner.ctx().local_addr, server.unwrap().1);
+        assert_eq!(
+            &lookups.lock().unwrap()[..],
+            &[("camera.invalid".to_owned(), 554)]
+        );
+
+        // A resolver which returns nothing fails the connection.
+        let options = SessionOptions::default().resolver(Arc::new(|_, _| Vec::new()));
+        RtspConnection::connect(&url, &options)
+            .await
+            .map(|_| ())
+            .unwrap_err();
+    }
+
+    #[cfg(feature = "tls")]
+    #[tokio::test]
+    async fn tls() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut server_config = rustls::ServerConfig::new(rustls::NoClientAuth::new());
+        let certs = rustls::internal::pemfile::certs(
+            &mut &include_bytes!("testdata/tls_localhost_cert.pem")[..],
+        )
+        .unwrap();
+        let mut keys = rustls::internal::pemfile::pkcs8_private_keys(
+            &mut &include_bytes!("testdata/tls_localhost_key.pem")[..],
+        )
+        .unwrap();
+        server_config
+            .set_single_cert(certs, keys.remove(0))
+            .unwrap();
+        let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(server_config));
+        let mut client_config = rustls::ClientConfig::new();
+        client_config
+            .root_store
+            .add_pem_file(&mut &include_bytes!("testdata/tls_ca.pem")[..])
+            .unwrap();
+        let client_config = Arc::new(client_config);
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let url = Url::parse(&format!("rtsps://localhost:{}/", addr.port())).unwrap();
+        let serve = |url: Url, expect_handshake: bool| {
+            let acceptor = acceptor.clone();
+            let listener = &listener;
+            async move {
+                let (tcp, _) = listener.accept().await.unwrap();
+                let handshake = acceptor.accept(tcp).await;
+                if !expect_handshake {
+                    return;
                 }
-            }
-            while let Poll::Ready(r) = sockets.rtp_socket.poll_recv(cx, buf) {
-                let pkt_ctx = crate::PacketContext(crate::PacketContextInner::Udp {
-                    local_addr: SocketAddr::new(sockets.local_ip, sockets.local_rtp_port),
-                    peer_addr: SocketAddr::new(sockets.remote_ip, sockets.remote_rtp_port),
-                    received_wall: crate::WallTime::now(),
-                    received: Instant::now(),
-                });
-                match r {
-                    Ok(()) => {
-                        let msg = Bytes::copy_from_slice(buf.filled());
-                        match rtp_handler.rtp(
-                            &inner.options,
-                            &conn_ctx,
-                            &pkt_ctx,
-                            &mut timeline,
-                            i,
-                            msg,
-                        ) {
-                            Ok(Some(p)) => return Poll::Ready(Some(Ok(p))),
-                            Ok(None) => buf.clear(),
-                            Err(e) => return Poll::Ready(Some(Err(e))),
-                        }
-                    }
-                    Err(source) => {
-                        return Poll::Ready(Some(Err(wrap!(ErrorInt::UdpRecvError {
-                            conn_ctx: *conn_ctx,
-                            pkt_ctx,
-                            source,
-                        }))))
-                    }
+                let mut tls = handshake.unwrap();
+                let mut req = Vec::new();
+                while !req.ends_with(b"\r\n\r\n") {
+                    let mut buf = [0u8; 1024];
+                    let n = tls.read(&mut buf).await.unwrap();
+                    assert_ne!(n, 0);
+                    req.extend_from_slice(&buf[..n]);
                 }
+                let req = String::from_utf8(req).unwrap();
+                assert!(
+                    req.starts_with(&format!("OPTIONS {} RTSP/1.0\r\n", url)),
+                    "{}",
+                    req
+                );
+                tls.write_all(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\nPublic: OPTIONS, DESCRIBE\r\n\r\n")
+                    .await
+                    .unwrap();
+                tls.flush().await.unwrap();
             }
+        };
+        let options = SessionOptions::default()
+            .resolver(Arc::new(move |_, _| vec![addr]))
+            .tls_config(client_config);
+
+        // The certificate verifies against the configured root.
+        let (methods, _) = tokio::join!(
+            probe_options(&url, None, options.clone()),
+            serve(url.clone(), true)
+        );
+        assert_eq!(methods.unwrap(), [Method::Options, Method::Describe]);
+
+        // An IP address host can't be verified...
+        let ip_url = Url::parse(&format!("rtsps://{}/", addr)).unwrap();
+        let e = probe_options(&ip_url, None, options.clone())
+            .await
+            .unwrap_err();
+        assert!(e.to_string().contains("server name"), "{}", e);
+
+        // ...unless the certificate's name is supplied.
+        let (methods, _) = tokio::join!(
+            probe_options(
+                &ip_url,
+                None,
+                options.clone().tls_server_name("localhost".to_owned())
+            ),
+            serve(ip_url.clone(), true)
+        );
+        assert_eq!(methods.unwrap(), [Method::Options, Method::Describe]);
+
+        // It doesn't verify against the default roots...
+        let options = SessionOptions::default().resolver(Arc::new(move |_, _| vec![addr]));
+        let (methods, _) = tokio::join!(
+            probe_options(&url, None, options.clone()),
+            serve(url.clone(), false)
+        );
+        methods.unwrap_err();
+
+        // ...unless verification is disabled, which also allows IP addresses.
+        for url in [&url, &ip_url] {
+            let (methods, _) = tokio::join!(
+                probe_options(url, None, options.clone().danger_accept_invalid_certs(true)),
+                serve(url.clone(), true)
+            );
+            assert_eq!(methods.unwrap(), [Method::Options, Method::Describe]);
         }
-        Poll::Pending
     }
 
-    /// Polls all UDP streams, round-robining between them to avoid starvation.
-    fn poll_udp(&mut self, cx: &mut std::task::Context) -> Poll<Option<Result<PacketItem, Error>>> {
-        // For now, create a buffer on the stack large enough for any UDP packet, then
-        // copy into a fresh allocation if it's actually used.
-        // TODO: a ring buffer would be better: see
-        // <https://github.com/scottlamb/retina/issues/6>.
+    #[tokio::test]
+    async fn nonlocal_bind_address() {
+        let url = Url::parse("rtsp://127.0.0.1:1/").unwrap();
+        let options = SessionOptions::default().bind_address("192.0.2.1".parse().unwrap());
+        let e = RtspConnection::connect(&url, &options)
+            .await
+            .map(|_| ())
+            .unwrap_err();
+        assert!(e.to_string().contains("local address"), "{}", e);
+    }
 
-        // SAFETY: this exactly matches an example in the documentation:
-        // <https://doc.rust-lang.org/nightly/core/mem/union.MaybeUninit.html#initializing-an-array-element-by-element>.
-        let mut buf: [MaybeUninit<u8>; 65_536] = unsafe { MaybeUninit::uninit().assume_init() };
-        let mut buf = tokio::io::ReadBuf::uninit(&mut buf);
+    #[tokio::test]
+    async fn udp_ipv6() {
+        use std::net::Ipv6Addr;
+        if let Err(e) = std::net::TcpListener::bind("[::1]:0") {
+            // IPv6 may be unavailable in the test environment.
+            log::info!("skipping IPv6 test: {}", e);
+            return;
+        }
+        let (conn, mut server) = connect_to_mock_on("[::1]:0").await;
+        let url = Url::parse("rtsp://[::1]:554/h264Preview_01_main").unwrap();
+        let options = SessionOptions::default().transport(Transport::Udp);
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, options, url),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(include_bytes!("testdata/reolink_describe.txt"))
+            ),
+        );
+        let mut session = session.unwrap();
+        let server_pair = crate::UdpPair::for_ip(IpAddr::V6(Ipv6Addr::LOCALHOST)).unwrap();
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                udp_setup_response(server_pair.rtp_port),
+            ),
+        );
+        r.unwrap();
+        let sockets = session.0.presentation.streams[0].sockets.as_ref().unwrap();
+        assert_eq!(sockets.local_ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(sockets.remote_ip, IpAddr::V6(Ipv6Addr::LOCALHOST));
+        assert_eq!(
+            transport_header(&req),
+            format!(
+                "RTP/AVP/UDP;client_port={}-{}",
+                sockets.local_rtp_port,
+                sockets.local_rtp_port + 1
+            )
+        );
 
-        // Assume 0 <= inner.udp_next_poll_i < inner.presentation.streams.len().
-        // play() would have failed if there were no (setup) streams.
-        let starting_i = *self.0.as_mut().project().udp_next_poll_i;
-        loop {
-            let inner = self.0.as_mut().project();
-            let i = *inner.udp_next_poll_i;
-            *inner.udp_next_poll_i += 1;
-            if *inner.udp_next_poll_i == inner.presentation.streams.len() {
-                *inner.udp_next_poll_i = 0;
+        // An IPv4 bind address can't be used on an IPv6 RTSP connection.
+        let (conn, mut server) = connect_to_mock_on("[::1]:0").await;
+        let url = Url::parse("rtsp://[::1]:554/h264Preview_01_main").unwrap();
+        let options = SessionOptions::default()
+            .transport(Transport::Udp)
+            .bind_address(IpAddr::V4(Ipv4Addr::LOCALHOST));
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, options, url),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(include_bytes!("testdata/reolink_describe.txt"))
+            ),
+        );
+        let e = session.unwrap().setup(0).await.unwrap_err();
+        assert!(e.to_string().contains("address family"), "{}", e);
+    }
+
+    #[tokio::test]
+    async fn udp_destination() {
+        // Only a different address is advertised as the destination, and only
+        // when requested. The connection is from 127.0.0.1; on Linux, all of
+        // 127.0.0.0/8 is local.
+        let other = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 2));
+        for &(bind_address, udp_destination, expected) in &[
+            (IpAddr::V4(Ipv4Addr::LOCALHOST), true, None),
+            (IpAddr::V4(Ipv4Addr::UNSPECIFIED), true, None),
+            (other, false, None),
+            (other, true, Some("127.0.0.2")),
+        ] {
+            if bind_address == other && std::net::UdpSocket::bind((other, 0)).is_err() {
+                log::info!("skipping udp_destination case: can't bind to {}", other);
+                continue;
             }
+            let (mut session, mut server) = describe_mock(
+                SessionOptions::default()
+                    .transport(Transport::Udp)
+                    .bind_address(bind_address)
+                    .udp_destination(udp_destination),
+            )
+            .await;
+            let server_pair = crate::UdpPair::for_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+            let (r, req) = tokio::join!(
+                session.setup(0),
+                req_response(
+                    &mut server,
+                    rtsp_types::Method::Setup,
+                    udp_setup_response(server_pair.rtp_port),
+                ),
+            );
+            r.unwrap();
+            let transport = transport_header(&req);
+            assert_eq!(
+                transport
+                    .split(';')
+                    .find_map(|p| p.strip_prefix("destination=")),
+                expected,
+                "{}",
+                transport
+            );
+        }
+    }
 
-            if let Poll::Ready(r) = self.poll_udp_stream(cx, &mut buf, i) {
-                return Poll::Ready(r);
+    #[tokio::test]
+    async fn server_ports() {
+        let server_pair = crate::UdpPair::for_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let (session, _server, _) = play_udp_mock(SessionOptions::default(), &server_pair).await;
+        let stream = &session.streams()[0];
+        assert_eq!(stream.server_rtp_port(), Some(server_pair.rtp_port));
+        assert_eq!(stream.server_rtcp_port(), Some(server_pair.rtp_port + 1));
+    }
+
+    #[tokio::test]
+    async fn udp_multicast() {
+        let group = Ipv4Addr::new(239, 255, 42, 43);
+        let port = std::net::UdpSocket::bind("0.0.0.0:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let (mut session, mut server) =
+            describe_mock(SessionOptions::default().transport(Transport::UdpMulticast)).await;
+        let setup_response =
+            rtsp_types::Response::builder(rtsp_types::Version::V1_0, rtsp_types::StatusCode::Ok)
+                .header(rtsp_types::headers::SESSION, "F8F8E425".to_owned())
+                .header(
+                    rtsp_types::headers::TRANSPORT,
+                    format!(
+                        "RTP/AVP;multicast;destination={};port={}-{};ttl=1",
+                        group,
+                        port,
+                        port + 1
+                    ),
+                )
+                .build(Bytes::new());
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(&mut server, rtsp_types::Method::Setup, setup_response),
+        );
+        assert_eq!(transport_header(&req), "RTP/AVP;multicast");
+        match r {
+            Err(e) if matches!(&*e.0, ErrorInt::ConnectError(_)) => {
+                // Binding or joining the group may be unavailable in the test
+                // environment.
+                log::info!("skipping multicast test: {}", e);
+                return;
             }
+            r => r.unwrap(),
+        }
+        assert_eq!(session.streams()[0].server_rtp_port(), None);
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        let mut session = session.unwrap();
+        let sender =
+            socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None).unwrap();
+        sender.set_multicast_if_v4(&Ipv4Addr::LOCALHOST).unwrap();
+        sender.set_multicast_loop_v4(true).unwrap();
+        sender
+            .send_to(
+                b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8multicast",
+                &SocketAddr::new(IpAddr::V4(group), port).into(),
+            )
+            .unwrap();
+        match tokio::time::timeout(std::time::Duration::from_secs(1), session.next()).await {
+            Ok(Some(Ok(PacketItem::RtpPacket(p)))) => assert_eq!(&p.payload[..], b"multicast"),
+            o => panic!("unexpected item: {:#?}", o),
+        }
+    }
 
-            if *self.0.as_mut().project().udp_next_poll_i == starting_i {
-                break;
+    /// Checks that connected UDP sockets reject packets from other peers.
+    #[tokio::test]
+    async fn connected_udp() {
+        for connected_udp in [true, false] {
+            let server_pair = crate::UdpPair::for_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+            let (mut session, _server, client_port) = play_udp_mock(
+                SessionOptions::default().connected_udp(connected_udp),
+                &server_pair,
+            )
+            .await;
+            let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), client_port);
+            let stranger = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+            stranger
+                .send_to(
+                    b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8stranger",
+                    client_addr,
+                )
+                .unwrap();
+            server_pair
+                .rtp_socket
+                .send_to(
+                    b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8server",
+                    client_addr,
+                )
+                .unwrap();
+            match session.next().await {
+                Some(Ok(PacketItem::RtpPacket(p))) => {
+                    let expected: &[u8] = if connected_udp {
+                        b"server"
+                    } else {
+                        b"stranger"
+                    };
+                    assert_eq!(&p.payload[..], expected);
+                    let peer_port = if connected_udp {
+                        server_pair.rtp_port
+                    } else {
+                        stranger.local_addr().unwrap().port()
+                    };
+                    match p.ctx.0 {
+                        crate::PacketContextInner::Udp { peer_addr, .. } => {
+                            assert_eq!(peer_addr.port(), peer_port)
+                        }
+                        o => panic!("unexpected ctx: {:#?}", o),
+                    }
+                }
+                o => panic!("unexpected item: {:#?}", o),
             }
         }
-        Poll::Pending
     }
-}
 
-#[pin_project::pinned_drop]
-impl PinnedDrop for SessionInner {
-    fn drop(self: Pin<&mut Self>) {
-        let this = self.project();
+    #[tokio::test]
+    async fn send_rtcp_disabled() {
+        let server_pair = crate::UdpPair::for_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let (mut session, _server, client_port) = play_udp_mock_with(
+            SessionOptions::default()
+                .send_rtcp(false)
+                .loss_policy(LossPolicy::RequestKeyframe),
+            PLI,
+            &server_pair,
+        )
+        .await;
+        session.request_keyframe(0).await.unwrap_err();
+
+        // A loss which would otherwise send a PLI.
+        let client_addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), client_port);
+        for &seq in &[0x41d4u16, 0x41d8] {
+            let mut pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world".to_vec();
+            pkt[2..4].copy_from_slice(&seq.to_be_bytes());
+            server_pair.rtp_socket.send_to(&pkt, client_addr).unwrap();
+        }
+        session.next().await.unwrap().unwrap();
+        match session.next().await {
+            Some(Ok(PacketItem::RtpPacket(p))) => assert_eq!(p.loss, 3),
+            o => panic!("unexpected item: {:#?}", o),
+        }
+        assert!(session.0.logged_keyframe_skip);
+
+        // The hole punch still sends RTP, but no RTCP arrives in the window.
+        let timeout = Some(std::time::Duration::from_millis(100));
+        server_pair.rtp_socket.set_read_timeout(timeout).unwrap();
+        server_pair.rtcp_socket.set_read_timeout(timeout).unwrap();
+        let mut buf = [0u8; 32];
+        let (_, from) = server_pair.rtp_socket.recv_from(&mut buf).unwrap();
+        assert_eq!(from.port(), client_port);
+        server_pair.rtcp_socket.recv_from(&mut buf).unwrap_err();
+    }
 
-        let is_tcp = matches!(this.options.transport, Transport::Tcp);
-        match this.options.teardown {
-            TeardownPolicy::Auto if is_tcp && !*this.has_live555_tcp_bug => return,
-            TeardownPolicy::Auto | TeardownPolicy::Always => {}
-            TeardownPolicy::Never => return,
+    #[tokio::test]
+    async fn udp_hole_punch() {
+        for udp_hole_punch in [true, false] {
+            let server_pair = crate::UdpPair::for_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+            let (_session, _server, client_port) = play_udp_mock(
+                SessionOptions::default().udp_hole_punch(udp_hole_punch),
+                &server_pair,
+            )
+            .await;
+            let timeout = Some(std::time::Duration::from_millis(100));
+            server_pair.rtp_socket.set_read_timeout(timeout).unwrap();
+            server_pair.rtcp_socket.set_read_timeout(timeout).unwrap();
+            let mut buf = [0u8; 16];
+            for (socket, len, port) in [
+                (&server_pair.rtp_socket, 12, client_port),
+                (&server_pair.rtcp_socket, 8, client_port + 1),
+            ] {
+                if udp_hole_punch {
+                    for _ in 0..2 {
+                        let (n, from) = socket.recv_from(&mut buf).unwrap();
+                        assert_eq!(n, len);
+                        assert_eq!(from.port(), port);
+                    }
+                } else {
+                    socket.recv_from(&mut buf).unwrap_err();
+                }
+            }
         }
+    }
 
-        let session = match this.session.take() {
-            Some(s) => s,
-            None => return,
-        };
+    #[tokio::test]
+    async fn transport_policy_udp_only() {
+        // Bind real server-side sockets so the client's packets aren't rejected.
+        let server_pair = crate::UdpPair::for_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let (mut session, mut server) =
+            describe_mock(SessionOptions::default().transport_policy(TransportPolicy::UdpOnly))
+                .await;
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                udp_setup_response(server_pair.rtp_port),
+            ),
+        );
+        r.unwrap();
+        assert!(transport_header(&req).starts_with("RTP/AVP/UDP;"));
 
-        // For now, assume the whole timeout is left.
-        let expires = tokio::time::Instant::now()
-            + std::time::Duration::from_secs(session.timeout_sec.into());
+        // With no RTP packets, play should still succeed via UDP immediately.
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        assert!(matches!(
+            session.unwrap().active_transport(),
+            Transport::Udp
+        ));
+    }
 
-        // Track the session, if there is a group.
-        let (teardown_tx, teardown_rx) = tokio::sync::watch::channel(None);
-        let seqnum = if let Some(session_group) = this.options.session_group.as_ref() {
-            let mut lock = session_group.sessions.lock().unwrap();
-            let seqnum = lock.next_seqnum;
-            lock.next_seqnum += 1;
-            lock.sessions.push(StaleSession {
-                seqnum,
-                expires: expires.clone(),
-                teardown_rx: Some(teardown_rx),
-                is_tcp,
-                maybe_playing: *this.maybe_playing,
-            });
-            Some(seqnum)
-        } else {
-            None
-        };
+    #[tokio::test]
+    async fn transport_policy_udp_then_tcp() {
+        let server_pair = crate::UdpPair::for_ip(IpAddr::V4(Ipv4Addr::LOCALHOST)).unwrap();
+        let (mut session, mut server) = describe_mock(
+            SessionOptions::default()
+                .transport_policy(TransportPolicy::UdpThenTcp)
+                .udp_fallback_timeout(std::time::Duration::from_millis(100)),
+        )
+        .await;
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                udp_setup_response(server_pair.rtp_port),
+            ),
+        );
+        r.unwrap();
+        assert!(transport_header(&req).starts_with("RTP/AVP/UDP;"));
+
+        // No RTP packet ever arrives via UDP, so the client should tear down
+        // and set up again via TCP.
+        let (session, _) = tokio::join!(session.play(PlayOptions::default()), async {
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt")),
+            )
+            .await;
+            req_response(
+                &mut server,
+                rtsp_types::Method::Teardown,
+                response(b"RTSP/1.0 200 OK\r\n\r\n"),
+            )
+            .await;
+            let req = req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt")),
+            )
+            .await;
+            assert!(transport_header(&req).starts_with("RTP/AVP/TCP;"));
+            assert!(req.header(&rtsp_types::headers::SESSION).is_none());
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt")),
+            )
+            .await;
+        });
+        assert!(matches!(
+            session.unwrap().active_transport(),
+            Transport::Tcp
+        ));
+    }
+
+    #[tokio::test]
+    async fn transport_header_override() {
+        let (mut session, mut server) = describe_mock(SessionOptions::default()).await;
+        const TRANSPORT: &str = "RTP/AVP/TCP;interleaved=0-1;unicast;mode=\"PLAY\"";
+        let (r, req) = tokio::join!(
+            session.setup_with_options(
+                0,
+                SetupOptions::default().transport_header_override(TRANSPORT.to_owned())
+            ),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        r.unwrap();
+        assert_eq!(transport_header(&req), TRANSPORT);
+        assert!(matches!(session.streams()[0].state, StreamState::Init(_)));
+    }
 
-        let handle = match this.runtime_handle.take() {
-            Some(h) => h,
-            None => {
-                const MSG: &str = "Unable to start async TEARDOWN because describe wasn't called \
-                                   from a tokio runtime";
-                log::warn!("{}", MSG);
-                let _ =
-                    teardown_tx.send(Some(Err(wrap!(ErrorInt::FailedPrecondition(MSG.into())))));
-                return;
+    #[tokio::test]
+    async fn probe_options() {
+        let (mut conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/").unwrap();
+        let (methods, req) = tokio::join!(
+            probe_options_with_conn(&mut conn, &url, &SessionOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Options,
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      CSeq: 1\r\n\
+                      Public: OPTIONS, DESCRIBE, SETUP, PLAY, TEARDOWN\r\n\r\n"
+                )
+            ),
+        );
+        assert_eq!(req.request_uri(), Some(&url));
+        assert_eq!(
+            methods.unwrap(),
+            [
+                Method::Options,
+                Method::Describe,
+                Method::Setup,
+                Method::Play,
+                Method::Teardown
+            ]
+        );
+    }
+
+    /// Serves `DESCRIBE` on a local listener, responding to each connection
+    /// with the next of `responses`. Returns the URL and the number of
+    /// connections accepted.
+    async fn describe_server(responses: Vec<Vec<u8>>) -> (Url, tokio::task::JoinHandle<usize>) {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "rtsp://{}/h264Preview_01_main",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let server = tokio::spawn(async move {
+            let mut accepted = 0;
+            for r in responses {
+                let (tcp, _) = listener.accept().await.unwrap();
+                accepted += 1;
+                let mut server = crate::tokio::Connection::from_stream(tcp).unwrap();
+                req_response(&mut server, rtsp_types::Method::Describe, response(&r)).await;
             }
-        };
+            accepted
+        });
+        (url, server)
+    }
 
-        handle.spawn(teardown::background_teardown(
-            seqnum,
-            this.presentation.base_url.clone(),
-            session.id,
-            std::mem::take(this.options),
-            this.requested_auth.take(),
-            this.conn.take(),
-            teardown_tx,
-            expires,
-        ));
+    #[tokio::test]
+    async fn connect_with_retry() {
+        const UNAVAILABLE: &[u8] = b"RTSP/1.0 503 Service Unavailable\r\nCSeq: 1\r\n\r\n";
+        let policy = RetryPolicy::default().base_delay(std::time::Duration::from_millis(10));
+
+        // Fails twice, then succeeds.
+        let (url, server) = describe_server(vec![
+            UNAVAILABLE.to_vec(),
+            UNAVAILABLE.to_vec(),
+            reolink_describe(&[]),
+        ])
+        .await;
+        let session = super::connect_with_retry(url, None, SessionOptions::default(), policy)
+            .await
+            .unwrap();
+        assert_eq!(session.streams().len(), 2);
+        assert_eq!(server.await.unwrap(), 3);
+
+        // Gives up after the configured number of attempts.
+        let (url, server) = describe_server(vec![UNAVAILABLE.to_vec(), UNAVAILABLE.to_vec()]).await;
+        let e =
+            super::connect_with_retry(url, None, SessionOptions::default(), policy.max_attempts(2))
+                .await
+                .unwrap_err();
+        assert!(e.to_string().contains("503"), "{}", e);
+        assert_eq!(server.await.unwrap(), 2);
+
+        // Doesn't retry authentication failures.
+        let (url, server) = describe_server(vec![
+            b"RTSP/1.0 401 Unauthorized\r\nCSeq: 1\r\n\r\n".to_vec()
+        ])
+        .await;
+        super::connect_with_retry(url, None, SessionOptions::default(), policy)
+            .await
+            .unwrap_err();
+        assert_eq!(server.await.unwrap(), 1);
     }
-}
 
-impl futures::Stream for Session<Playing> {
-    type Item = Result<PacketItem, Error>;
+    #[test]
+    fn retry_policy_delays() {
+        let secs = std::time::Duration::from_secs;
+        assert_eq!(RetryPolicy::default().next_delay(secs(1)), secs(2));
+
+        // Growth saturates at the maximum rather than overflowing.
+        let policy = RetryPolicy::default()
+            .backoff_factor(1e30)
+            .max_delay(secs(60));
+        assert_eq!(policy.next_delay(secs(1)), secs(60));
+        let policy = policy.max_delay(std::time::Duration::new(u64::MAX, 999_999_999));
+        assert_eq!(policy.next_delay(secs(u64::MAX / 2)), policy.max_delay);
+
+        for &bad in &[0.5, f32::NAN, f32::INFINITY] {
+            assert!(
+                std::panic::catch_unwind(|| RetryPolicy::default().backoff_factor(bad)).is_err()
+            );
+        }
+    }
 
-    fn poll_next(
-        mut self: Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Option<Self::Item>> {
-        loop {
-            // First try receiving data on the RTSP connection. Let this starve
-            // sending keepalives; if we can't keep up, the server should
-            // probably drop us.
-            match Pin::new(&mut self.0.conn.as_mut().unwrap().inner).poll_next(cx) {
-                Poll::Ready(Some(Ok(msg))) => match msg.msg {
-                    rtsp_types::Message::Data(data) => {
-                        match self.as_mut().handle_data(&msg.ctx, data) {
-                            Err(e) => return Poll::Ready(Some(Err(e))),
-                            Ok(Some(pkt)) => return Poll::Ready(Some(Ok(pkt))),
-                            Ok(None) => continue,
-                        };
-                    }
-                    rtsp_types::Message::Response(response) => {
-                        if let Err(e) = self.as_mut().handle_response(&msg.ctx, response) {
-                            return Poll::Ready(Some(Err(e)));
-                        }
-                        continue;
-                    }
-                    rtsp_types::Message::Request(request) => {
-                        warn!("Received RTSP request in Playing state. Responding unimplemented.\n{:#?}",
-                            request);
-                    }
-                },
-                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
-                Poll::Ready(None) => return Poll::Ready(None),
-                std::task::Poll::Pending => {}
+    #[tokio::test]
+    async fn lenient_missing_cseq() {
+        const NO_CSEQ: &[u8] = b"RTSP/1.0 200 OK\r\nPublic: OPTIONS, DESCRIBE\r\n\r\n";
+        let url = Url::parse("rtsp://192.168.5.206:554/").unwrap();
+        for &lenient in &[false, true] {
+            let (mut conn, mut server) = connect_to_mock().await;
+            let options = SessionOptions::default().lenient(lenient);
+            let (methods, _) =
+                tokio::join!(probe_options_with_conn(&mut conn, &url, &options), async {
+                    server.next().await.unwrap().unwrap();
+                    server
+                        .send(rtsp_types::Message::Response(response(NO_CSEQ)))
+                        .await
+                        .unwrap();
+                },);
+            if lenient {
+                assert_eq!(methods.unwrap(), [Method::Options, Method::Describe]);
+            } else {
+                methods.unwrap_err();
             }
+        }
+    }
 
-            // Next try receiving data on the UDP sockets, if any.
-            if matches!(self.0.options.transport, Transport::Udp) {
-                if let Poll::Ready(result) = self.as_mut().poll_udp(cx) {
-                    return Poll::Ready(result);
+    /// Checks that in lenient mode, responses without `CSeq` are matched to
+    /// the oldest outstanding request: here a keepalive sent before a
+    /// `GET_PARAMETER`.
+    #[tokio::test]
+    async fn lenient_missing_cseq_outstanding_keepalive() {
+        let (mut session, mut server) = play_mock(SessionOptions::default().lenient(true)).await;
+        futures::future::poll_fn(|cx| {
+            Poll::Ready(Pin::new(&mut session).handle_keepalive_timer(cx))
+        })
+        .await
+        .unwrap();
+        server.next().await.unwrap().unwrap();
+        let (body, _) = tokio::join!(session.get_parameter(b"position\r\n"), async {
+            match server.next().await.unwrap().unwrap().msg {
+                rtsp_types::Message::Request(r) => {
+                    assert_eq!(r.method(), rtsp_types::Method::GetParameter)
                 }
+                o => panic!("unexpected message: {:#?}", o),
             }
+            server
+                .send(rtsp_types::Message::Response(response(
+                    b"RTSP/1.0 200 OK\r\n\r\n",
+                )))
+                .await
+                .unwrap();
+            server
+                .send(rtsp_types::Message::Response(response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      Content-Type: text/parameters\r\n\
+                      Content-Length: 17\r\n\r\n\
+                      position: 10,20\r\n",
+                )))
+                .await
+                .unwrap();
+        });
+        assert_eq!(&body.unwrap()[..], b"position: 10,20\r\n");
+    }
 
-            // Then check if it's time for a new keepalive.
-            if matches!(
-                self.0.keepalive_timer.as_mut().unwrap().as_mut().poll(cx),
-                Poll::Ready(())
-            ) {
-                log::debug!("time for a keepalive");
-                self.as_mut().handle_keepalive_timer(cx)?;
-            }
+    #[tokio::test]
+    async fn transcript() {
+        let transcript = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let transcript_clone = transcript.clone();
+        let options = SessionOptions::default()
+            .creds(Some(Credentials {
+                username: "admin".to_owned(),
+                password: "hunter2".to_owned(),
+            }))
+            .transcript(Arc::new(
+                move |_ctx: &RtspMessageContext, msg: &rtsp_types::Message<Bytes>| {
+                    let summary = match msg {
+                        rtsp_types::Message::Request(r) => format!(
+                            "{:?} {:?}",
+                            r.method(),
+                            r.header(&rtsp_types::headers::AUTHORIZATION)
+                                .map(|v| v.as_str().to_owned())
+                        ),
+                        rtsp_types::Message::Response(r) => format!(
+                            "{} {:?}",
+                            u16::from(r.status()),
+                            r.header(&rtsp_types::headers::WWW_AUTHENTICATE)
+                                .map(|v| v.as_str().to_owned())
+                        ),
+                        rtsp_types::Message::Data(_) => "data".to_owned(),
+                    };
+                    transcript_clone.lock().unwrap().push(summary);
+                },
+            ));
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+        let (session, _) = tokio::join!(Session::describe_with_conn(conn, options, url), async {
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(
+                    b"RTSP/1.0 401 Unauthorized\r\n\
+                          CSeq: 1\r\n\
+                          WWW-Authenticate: Digest realm=\"test\", nonce=\"abc\"\r\n\r\n",
+                ),
+            )
+            .await;
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(include_bytes!("testdata/reolink_describe.txt")),
+            )
+            .await;
+        });
+        session.unwrap();
+        let transcript = transcript.lock().unwrap();
+        assert_eq!(
+            &transcript[..],
+            &[
+                "Describe None".to_owned(),
+                "401 Some(\"<redacted>\")".to_owned(),
+                "Describe Some(\"<redacted>\")".to_owned(),
+                "200 None".to_owned(),
+            ]
+        );
+    }
 
-            // Then finish flushing the current keepalive if necessary.
-            if let KeepaliveState::Flushing(cseq) = self.0.keepalive_state {
-                match self.0.conn.as_mut().unwrap().inner.poll_flush_unpin(cx) {
-                    Poll::Ready(Ok(())) => self.0.keepalive_state = KeepaliveState::Waiting(cseq),
-                    Poll::Ready(Err(e)) => return Poll::Ready(Some(Err(Error(Arc::new(e))))),
-                    Poll::Pending => {}
-                }
-            }
+    #[tokio::test]
+    async fn get_parameter() {
+        let (mut session, mut server) = play_mock(SessionOptions::default()).await;
+        let (body, req) = tokio::join!(
+            session.get_parameter(b"position\r\n"),
+            req_response(
+                &mut server,
+                rtsp_types::Method::GetParameter,
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      CSeq: 1\r\n\
+                      Content-Type: text/parameters\r\n\
+                      Content-Length: 17\r\n\r\n\
+                      position: 10,20\r\n"
+                )
+            ),
+        );
+        assert_eq!(&body.unwrap()[..], b"position: 10,20\r\n");
+        assert_eq!(&req.body()[..], b"position\r\n");
+        assert_eq!(
+            req.header(&rtsp_types::headers::CONTENT_TYPE)
+                .map(|h| h.as_str()),
+            Some("text/parameters")
+        );
+        assert_eq!(
+            req.header(&rtsp_types::headers::SESSION)
+                .map(|h| h.as_str()),
+            Some("F8F8E425")
+        );
+    }
 
-            // Nothing to do. The poll calls above have already registered cx as necessary.
-            return Poll::Pending;
+    /// Checks that data arriving while awaiting a control response is
+    /// returned afterward rather than discarded.
+    #[tokio::test]
+    async fn control_defers_data() {
+        const PKT: &[u8] = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+        let (mut session, mut server) = play_mock(SessionOptions::default()).await;
+        let (body, _) = tokio::join!(session.get_parameter(b"position\r\n"), async {
+            server
+                .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                    0,
+                    Bytes::from_static(PKT),
+                )))
+                .await
+                .unwrap();
+            req_response(
+                &mut server,
+                rtsp_types::Method::GetParameter,
+                response(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n"),
+            )
+            .await
+        });
+        body.unwrap();
+        match session.next().await {
+            Some(Ok(PacketItem::RtpPacket(p))) => assert_eq!(&p.payload[..], b"hello world"),
+            o => panic!("unexpected item: {:#?}", o),
         }
     }
-}
 
-enum DemuxedState {
-    Waiting,
-    Pulling(usize),
-    Fused,
-}
+    /// Checks that data deferred while awaiting a control response is bounded.
+    #[tokio::test]
+    async fn control_defers_bounded_data() {
+        const PKT: &[u8] = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+        let (mut session, mut server) = play_mock(SessionOptions::default()).await;
+        let (body, _) = tokio::join!(session.get_parameter(b"position\r\n"), async {
+            for _ in 0..=MAX_DEFERRED_DATA {
+                server
+                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                        0,
+                        Bytes::from_static(PKT),
+                    )))
+                    .await
+                    .unwrap();
+            }
+            req_response(
+                &mut server,
+                rtsp_types::Method::GetParameter,
+                response(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n"),
+            )
+            .await
+        });
+        body.unwrap();
+        assert_eq!(
+            session.0.conn.as_ref().unwrap().deferred_data.len(),
+            MAX_DEFERRED_DATA
+        );
+    }
 
-/// Wrapper returned by [`Session<Playing>::demuxed`] which demuxes/depacketizes into frames.
-pub struct Demuxed {
-    state: DemuxedState,
-    session: Session<Playing>,
-}
+    #[tokio::test]
+    async fn pause_resume() {
+        const PKT: &[u8] = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+        let (mut session, mut server) = play_mock(SessionOptions::default()).await;
 
-impl Demuxed {
-    #[deprecated(since = "0.3.1", note = "Use SessionGroup::await_teardown instead")]
-    pub async fn teardown(self) -> Result<(), Error> {
-        #[allow(deprecated)]
-        self.session.teardown().await
+        // A camera which doesn't support PAUSE.
+        let (r, _) = tokio::join!(
+            session.pause(),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Pause,
+                response(b"RTSP/1.0 501 Not Implemented\r\nCSeq: 1\r\n\r\n")
+            ),
+        );
+        let e = r.unwrap_err().to_string();
+        assert!(e.contains("doesn't support PAUSE"), "{}", e);
+
+        let (r, req) = tokio::join!(
+            session.pause(),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Pause,
+                response(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\nSession: F8F8E425\r\n\r\n")
+            ),
+        );
+        r.unwrap();
+        assert_eq!(
+            req.header(&rtsp_types::headers::SESSION)
+                .map(|h| h.as_str()),
+            Some("F8F8E425")
+        );
+        let (r, req) = tokio::join!(
+            session.resume(None),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        r.unwrap();
+        assert!(req.header(&rtsp_types::headers::RANGE).is_none());
+
+        let (item, _) = tokio::join!(
+            session.next(),
+            server.send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                0,
+                Bytes::from_static(PKT),
+            ))),
+        );
+        match item {
+            Some(Ok(PacketItem::RtpPacket(p))) => {
+                assert_eq!(p.sequence_number, 0x41d4);
+                assert_eq!(p.loss, 0);
+            }
+            o => panic!("unexpected item: {:#?}", o),
+        }
     }
-}
 
-impl futures::Stream for Demuxed {
-    type Item = Result<CodecItem, Error>;
+    /// Resuming with a range restarts the streams from the new `RTP-Info`.
+    #[tokio::test]
+    async fn pause_resume_seek() {
+        const PKT: &[u8] = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
+
+        // seq=1000, rtptime=5000, as in the seek's RTP-Info below.
+        const SEEK_PKT: &[u8] = b"\x80\x60\x03\xe8\x00\x00\x13\x88\xdc\xc4\xa0\xd8hello world";
+        let (mut session, mut server) = play_mock(SessionOptions::default()).await;
+        let (item, _) = tokio::join!(
+            session.next(),
+            server.send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                0,
+                Bytes::from_static(PKT),
+            ))),
+        );
+        match item {
+            Some(Ok(PacketItem::RtpPacket(p))) => assert_eq!(p.sequence_number, 0x41d4),
+            o => panic!("unexpected item: {:#?}", o),
+        }
 
-    fn poll_next(
-        mut self: Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> Poll<Option<Self::Item>> {
-        loop {
-            let (stream_id, pkt) = match self.state {
-                DemuxedState::Waiting => match ready!(Pin::new(&mut self.session).poll_next(cx)) {
-                    Some(Ok(PacketItem::RtpPacket(p))) => (p.stream_id, Some(p)),
-                    Some(Ok(PacketItem::SenderReport(p))) => {
-                        return Poll::Ready(Some(Ok(CodecItem::SenderReport(p))))
-                    }
-                    Some(Err(e)) => return Poll::Ready(Some(Err(e))),
-                    None => return Poll::Ready(None),
-                },
-                DemuxedState::Pulling(stream_id) => (stream_id, None),
-                DemuxedState::Fused => return Poll::Ready(None),
-            };
-            let inner = self.session.0.as_mut().project();
-            let depacketizer = match &mut inner.presentation.streams[stream_id].depacketizer {
-                Ok(d) => d,
-                Err(_) => unreachable!("depacketizer was Ok"),
-            };
-            let conn_ctx = inner
-                .conn
-                .as_ref()
-                .ok_or_else(|| wrap!(ErrorInt::FailedPrecondition("no connection".into())))?
-                .inner
-                .ctx();
-            if let Some(p) = pkt {
-                let pkt_ctx = p.ctx;
-                let stream_id = p.stream_id;
-                let ssrc = p.ssrc;
-                let sequence_number = p.sequence_number;
-                depacketizer.push(p).map_err(|description| {
-                    wrap!(ErrorInt::RtpPacketError {
-                        conn_ctx: *conn_ctx,
-                        pkt_ctx,
-                        stream_id,
-                        ssrc,
-                        sequence_number,
-                        description,
-                    })
-                })?;
-            }
-            match depacketizer.pull(conn_ctx) {
-                Ok(Some(item)) => {
-                    self.state = DemuxedState::Pulling(stream_id);
-                    return Poll::Ready(Some(Ok(item)));
-                }
-                Ok(None) => {
-                    self.state = DemuxedState::Waiting;
-                    continue;
-                }
-                Err(e) => {
-                    self.state = DemuxedState::Fused;
-                    return Poll::Ready(Some(Err(e)));
-                }
+        let (r, _) = tokio::join!(
+            session.pause(),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Pause,
+                response(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\nSession: F8F8E425\r\n\r\n")
+            ),
+        );
+        r.unwrap();
+        let (r, req) = tokio::join!(
+            session.resume(Some(Range::Npt {
+                start: std::time::Duration::from_secs(30),
+                end: None,
+            })),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      CSeq: 1\r\n\
+                      Session: F8F8E425\r\n\
+                      Range: npt=30.000-\r\n\
+                      RTP-Info: url=trackID=1;seq=1000;rtptime=5000;ssrc=dcc4a0d8\r\n\
+                      \r\n"
+                )
+            ),
+        );
+        r.unwrap();
+        assert_eq!(
+            req.header(&rtsp_types::headers::RANGE).map(|h| h.as_str()),
+            Some("npt=30.000-")
+        );
+
+        // Without a restart, this would look like a packet from long ago.
+        let (item, _) = tokio::join!(
+            session.next(),
+            server.send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                0,
+                Bytes::from_static(SEEK_PKT),
+            ))),
+        );
+        match item {
+            Some(Ok(PacketItem::RtpPacket(p))) => {
+                assert_eq!(p.sequence_number, 1000);
+                assert_eq!(p.loss, 0);
+                assert_eq!(p.timestamp.start(), 5000);
+                assert_eq!(p.timestamp.elapsed(), 0);
             }
+            o => panic!("unexpected item: {:#?}", o),
         }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::testutil::response;
+    #[tokio::test]
+    async fn base_url() {
+        // Content-Base takes precedence over the request URL.
+        let (session, _server) = describe_mock(SessionOptions::default()).await;
+        assert_eq!(
+            session.base_url().as_str(),
+            "rtsp://192.168.5.206/h264Preview_01_main/"
+        );
+        assert_eq!(
+            session.streams()[0].control().unwrap().as_str(),
+            "rtsp://192.168.5.206/h264Preview_01_main/trackID=1"
+        );
 
-    /// Cross-platform, tokio equivalent of `socketpair(2)`.
-    async fn socketpair() -> (tokio::net::TcpStream, tokio::net::TcpStream) {
-        // Another process on the machine could connect to the server and mess
-        // this up, but that's unlikely enough to ignore in test code.
-        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
-        let addr = listener.local_addr().unwrap();
-        let client = tokio::net::TcpStream::connect(addr);
-        let server = listener.accept();
-        (client.await.unwrap(), server.await.unwrap().0)
+        // Without it, the request URL is the base.
+        let raw = String::from_utf8(reolink_describe(&[])).unwrap().replace(
+            "Content-Base: rtsp://192.168.5.206/h264Preview_01_main/\r\n",
+            "",
+        );
+        let (session, _server) =
+            describe_mock_with(SessionOptions::default(), raw.as_bytes()).await;
+        assert_eq!(
+            session.base_url().as_str(),
+            "rtsp://192.168.5.206:554/h264Preview_01_main"
+        );
     }
 
-    async fn connect_to_mock() -> (RtspConnection, crate::tokio::Connection) {
-        let (client, server) = socketpair().await;
-        let client = crate::tokio::Connection::from_stream(client).unwrap();
-        let server = crate::tokio::Connection::from_stream(server).unwrap();
-        let client = RtspConnection {
-            inner: client,
-            channels: ChannelMappings::default(),
-            next_cseq: 1,
-        };
-        (client, server)
+    #[tokio::test]
+    async fn describe_only() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+        let (description, _) = tokio::join!(
+            super::describe_with_conn(conn, SessionOptions::default(), url),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(include_bytes!("testdata/reolink_describe.txt"))
+            ),
+        );
+        let description = description.unwrap();
+        assert_eq!(description.streams().len(), 2);
+        assert_eq!(description.streams()[0].encoding_name(), "h264");
+        assert!(description.raw_sdp().starts_with(b"v=0\r\n"));
+        assert_eq!(
+            description.base_url().as_str(),
+            "rtsp://192.168.5.206/h264Preview_01_main/"
+        );
+
+        // The connection is closed without a SETUP (or anything else).
+        assert!(server.next().await.is_none());
     }
 
-    /// Receives a request and sends a response, filling in the matching `CSeq`.
-    async fn req_response(
-        server: &mut crate::tokio::Connection,
-        expected_method: rtsp_types::Method,
-        mut response: rtsp_types::Response<Bytes>,
-    ) {
-        let msg = server.next().await.unwrap().unwrap();
-        let cseq = match msg.msg {
-            rtsp_types::Message::Request(ref r) => {
-                assert_eq!(r.method(), expected_method);
-                r.header(&rtsp_types::headers::CSEQ).unwrap()
-            }
-            _ => panic!(),
-        };
-        response.insert_header(rtsp_types::headers::CSEQ, cseq.as_str());
-        server
-            .send(rtsp_types::Message::Response(response))
+    #[tokio::test]
+    async fn describe_creds_argument_wins() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!(
+            "rtsp://{}/h264Preview_01_main",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+        let options = SessionOptions::default().creds(Some(Credentials {
+            username: "options".to_owned(),
+            password: "wrong".to_owned(),
+        }));
+        let creds = Some(Credentials {
+            username: "argument".to_owned(),
+            password: "right".to_owned(),
+        });
+        let (description, req) = tokio::join!(super::describe(url, creds, options), async {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut server = crate::tokio::Connection::from_stream(tcp).unwrap();
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(
+                    b"RTSP/1.0 401 Unauthorized\r\n\
+                      CSeq: 1\r\n\
+                      WWW-Authenticate: Basic realm=\"test\"\r\n\r\n",
+                ),
+            )
+            .await;
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(include_bytes!("testdata/reolink_describe.txt")),
+            )
             .await
-            .unwrap();
+        });
+        description.unwrap();
+        assert_eq!(
+            req.header(&rtsp_types::headers::AUTHORIZATION)
+                .map(|v| v.as_str()),
+            Some(&*format!("Basic {}", base64::encode("argument:right"))),
+        );
     }
 
-    /// Tests the happy path of session initialization.
     #[tokio::test]
-    async fn simple() {
+    async fn require_features() {
         let (conn, mut server) = connect_to_mock().await;
         let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
+        let options = SessionOptions::default()
+            .require_features(vec!["play.basic".to_owned(), "x-frobnicate".to_owned()])
+            .supported_features(vec!["play.scale".to_owned()]);
+        let (session, req) = tokio::join!(
+            Session::describe_with_conn(conn, options, url),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(
+                    b"RTSP/1.0 551 Option not supported\r\n\
+                      Unsupported: x-frobnicate\r\n\
+                      Content-Length: 0\r\n\r\n"
+                )
+            ),
+        );
+        assert_eq!(
+            req.header(&rtsp_types::headers::REQUIRE)
+                .map(|h| h.as_str()),
+            Some("play.basic, x-frobnicate")
+        );
+        assert_eq!(
+            req.header(&rtsp_types::headers::SUPPORTED)
+                .map(|h| h.as_str()),
+            Some("play.scale")
+        );
+        let e = session.err().unwrap();
+        assert!(e.to_string().contains("x-frobnicate"), "{}", e);
+    }
 
-        // DESCRIBE.
+    #[tokio::test]
+    async fn onvif_backchannel() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/back").unwrap();
+        let (session, req) = tokio::join!(
+            Session::describe_with_conn(
+                conn,
+                SessionOptions::default().onvif_backchannel(true),
+                url
+            ),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Describe,
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      Content-Base: rtsp://192.168.5.206:554/back/\r\n\
+                      Content-Type: application/sdp\r\n\
+                      Content-Length: 132\r\n\r\n\
+                      v=0\r\n\
+                      o=- 0 0 IN IP4 127.0.0.1\r\n\
+                      s=-\r\n\
+                      t=0 0\r\n\
+                      a=control:*\r\n\
+                      m=audio 0 RTP/AVP 0\r\n\
+                      a=control:trackID=1\r\n\
+                      a=rtpmap:0 PCMU/8000\r\n\
+                      a=sendonly\r\n"
+                )
+            ),
+        );
+        let require = |req: &rtsp_types::Request<Bytes>| {
+            req.header(&rtsp_types::headers::REQUIRE)
+                .map(|h| h.as_str().to_owned())
+        };
+        assert_eq!(require(&req).as_deref(), Some(ONVIF_BACKCHANNEL));
+        let mut session = session.unwrap();
+        assert!(session.streams()[0].is_backchannel());
+        let (r, req) = tokio::join!(
+            session.setup(0),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        r.unwrap();
+        assert_eq!(require(&req).as_deref(), Some(ONVIF_BACKCHANNEL));
         let (session, _) = tokio::join!(
-            Session::describe_with_conn(conn, SessionOptions::default(), url),
+            session.play(PlayOptions::default()),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(b"RTSP/1.0 200 OK\r\nCSeq: 3\r\nSession: F8F8E425\r\n\r\n")
+            ),
+        );
+        let mut session = session.unwrap();
+        session.send_audio(0, &[0xff; 160], 1234).await.unwrap();
+        session.send_audio(0, &[0xff; 160], 1394).await.unwrap();
+        let rtcp_ssrc = session.0.rtcp_ssrc;
+        let mut seqs = Vec::new();
+        for (mark, timestamp) in [(true, 1234u32), (false, 1394)] {
+            let d = match server.next().await.unwrap().unwrap().msg {
+                rtsp_types::Message::Data(d) => d,
+                o => panic!("unexpected message: {:#?}", o),
+            };
+            assert_eq!(d.channel_id(), 0);
+            let pkt = d.into_body();
+            assert_eq!(pkt.len(), 12 + 160);
+            assert_eq!(pkt[0], 0x80);
+            assert_eq!(pkt[1], if mark { 0x80 } else { 0 }); // payload type 0 (PCMU).
+            seqs.push(u16::from_be_bytes([pkt[2], pkt[3]]));
+            assert_eq!(
+                u32::from_be_bytes([pkt[4], pkt[5], pkt[6], pkt[7]]),
+                timestamp
+            );
+            assert_eq!(
+                u32::from_be_bytes([pkt[8], pkt[9], pkt[10], pkt[11]]),
+                rtcp_ssrc
+            );
+        }
+        assert_eq!(seqs[1], seqs[0].wrapping_add(1));
+    }
+
+    /// Checks setting up only the second of two video streams.
+    #[tokio::test]
+    async fn setup_streams() {
+        let (conn, mut server) = connect_to_mock().await;
+        let url = Url::parse("rtsp://192.168.5.206:554/two").unwrap();
+        let (session, _) = tokio::join!(
+            Session::describe_with_conn(conn, SessionOptions::default().setup_streams(&[1]), url),
             req_response(
                 &mut server,
                 rtsp_types::Method::Describe,
-                response(include_bytes!("testdata/reolink_describe.txt"))
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      Content-Base: rtsp://192.168.5.206:554/two/\r\n\
+                      Content-Type: application/sdp\r\n\
+                      Content-Length: 190\r\n\r\n\
+                      v=0\r\n\
+                      o=- 0 0 IN IP4 127.0.0.1\r\n\
+                      s=-\r\n\
+                      t=0 0\r\n\
+                      a=control:*\r\n\
+                      m=video 0 RTP/AVP 96\r\n\
+                      a=rtpmap:96 H264/90000\r\n\
+                      a=control:trackID=1\r\n\
+                      m=video 0 RTP/AVP 97\r\n\
+                      a=rtpmap:97 H264/90000\r\n\
+                      a=control:trackID=2\r\n"
+                )
             ),
         );
         let mut session = session.unwrap();
         assert_eq!(session.streams().len(), 2);
-
-        // SETUP.
-        tokio::join!(
-            async {
-                session.setup(0).await.unwrap();
-            },
+        assert!(session.streams()[0].depacketizer.is_err());
+        assert!(session.streams()[1].depacketizer.is_ok());
+        let (r, req) = tokio::join!(
+            session.setup_all(),
             req_response(
                 &mut server,
                 rtsp_types::Method::Setup,
                 response(include_bytes!("testdata/reolink_setup.txt"))
             ),
         );
+        r.unwrap();
+        assert_eq!(
+            req.request_uri().map(Url::as_str),
+            Some("rtsp://192.168.5.206:554/two/trackID=2")
+        );
+        session.setup(0).await.unwrap_err();
 
-        // PLAY.
         let (session, _) = tokio::join!(
             session.play(PlayOptions::default()),
             req_response(
@@ -2026,69 +8287,131 @@ mod tests {
             ),
         );
         let session = session.unwrap();
-        tokio::pin!(session);
+        assert!(session.stream_stats(0).is_none());
+        assert!(session.stream_stats(1).is_some());
+    }
 
-        // Packet.
-        tokio::join!(
-            async {
-                match session.next().await {
-                    Some(Ok(PacketItem::RtpPacket(p))) => {
-                        assert_eq!(p.ssrc, 0xdcc4a0d8);
-                        assert_eq!(p.sequence_number, 0x41d4);
-                        assert_eq!(&p.payload[..], b"hello world");
-                    }
-                    o => panic!("unexpected item: {:#?}", o),
-                }
-            },
-            async {
-                let pkt = b"\x80\x60\x41\xd4\x00\x00\x00\x00\xdc\xc4\xa0\xd8hello world";
-                server
-                    .send(rtsp_types::Message::Data(rtsp_types::Data::new(
-                        0,
-                        Bytes::from_static(pkt),
-                    )))
-                    .await
-                    .unwrap();
-            },
+    #[test]
+    fn range() {
+        let r = Range::Npt {
+            start: std::time::Duration::from_millis(10_500),
+            end: None,
+        };
+        assert_eq!(r.to_string(), "npt=10.500-");
+        assert_eq!("npt=10.5-".parse::<Range>().unwrap(), r);
+        assert_eq!(
+            "npt=00:01:02.5-00:02:00".parse::<Range>().unwrap(),
+            Range::Npt {
+                start: std::time::Duration::from_millis(62_500),
+                end: Some(std::time::Duration::from_secs(120)),
+            }
         );
+        let r = "clock=19961108T142300Z-19961108T143520Z"
+            .parse::<Range>()
+            .unwrap();
+        assert_eq!(
+            r,
+            Range::Clock {
+                start: "19961108T142300Z".to_owned(),
+                end: Some("19961108T143520Z".to_owned()),
+            }
+        );
+        assert_eq!(r.to_string(), "clock=19961108T142300Z-19961108T143520Z");
+        assert_eq!(
+            "smpte=10:07:00-".parse::<Range>().unwrap(),
+            Range::Other("smpte=10:07:00-".to_owned())
+        );
+        assert!("npt=bogus-".parse::<Range>().is_err());
+        assert!("npt=1e30-".parse::<Range>().is_err());
+    }
 
-        // End of stream.
+    #[tokio::test]
+    async fn play_range() {
+        let (mut session, mut server) = describe_mock(SessionOptions::default()).await;
         tokio::join!(
             async {
-                assert!(session.next().await.is_none());
-            },
-            async {
-                server.close().await.unwrap();
+                session.setup(0).await.unwrap();
             },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        let (session, req) = tokio::join!(
+            session.play(PlayOptions::default().range(Range::Npt {
+                start: std::time::Duration::from_secs(10),
+                end: None,
+            })),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(include_bytes!("testdata/reolink_play.txt"))
+            ),
+        );
+        assert_eq!(
+            req.header(&rtsp_types::headers::RANGE).unwrap().as_str(),
+            "npt=10.000-"
+        );
+
+        // This server ignores the requested range.
+        assert_eq!(
+            session.unwrap().play_range(),
+            Some(&Range::Npt {
+                start: std::time::Duration::from_secs(0),
+                end: None,
+            })
         );
     }
 
-    /// Tests ignoring a bogus RTCP message while waiting for PLAY response.
     #[tokio::test]
-    async fn ignore_early_rtcp() {
-        let (conn, mut server) = connect_to_mock().await;
-        let url = Url::parse("rtsp://192.168.5.206:554/h264Preview_01_main").unwrap();
-        let bogus_pkt = rtsp_types::Message::Data(rtsp_types::Data::new(
-            1,                                // RTCP channel
-            Bytes::from_static(b"bogus pkt"), // the real packet parses but this is fine.
-        ));
-
-        // DESCRIBE.
-        let (session, _) = tokio::join!(
-            Session::describe_with_conn(conn, SessionOptions::default(), url),
+    async fn play_range_aligns_timestamps() {
+        let (mut session, mut server) = describe_mock(SessionOptions::default()).await;
+        tokio::join!(
             async {
-                req_response(
-                    &mut server,
-                    rtsp_types::Method::Describe,
-                    response(include_bytes!("testdata/reolink_describe.txt")),
-                )
-                .await;
+                session.setup(0).await.unwrap();
             },
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        let (session, _) = tokio::join!(
+            session.play(PlayOptions::default().range(Range::Npt {
+                start: std::time::Duration::from_secs(10),
+                end: None,
+            })),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Play,
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      CSeq: 6\r\n\
+                      Range: npt=10.000-\r\n\
+                      Session: F8F8E425\r\n\
+                      RTP-Info: url=trackID=1;seq=16852;rtptime=1070938629\r\n\
+                      \r\n"
+                )
+            ),
         );
         let mut session = session.unwrap();
-        assert_eq!(session.streams().len(), 2);
+        server
+            .send(rtsp_types::Message::Data(rtsp_types::Data::new(
+                0,
+                Bytes::from_static(b"\x80\x60\x41\xd4\x3f\xd5\x3a\x05\xdc\xc4\xa0\xd8hello world"),
+            )))
+            .await
+            .unwrap();
+        match session.next().await {
+            Some(Ok(PacketItem::RtpPacket(p))) => assert_eq!(p.timestamp.elapsed_secs(), 10.0),
+            o => panic!("unexpected item: {:#?}", o),
+        }
+    }
 
-        // SETUP.
+    #[tokio::test]
+    async fn play_scale() {
+        let (mut session, mut server) = describe_mock(SessionOptions::default()).await;
         tokio::join!(
             async {
                 session.setup(0).await.unwrap();
@@ -2099,18 +8422,41 @@ mod tests {
                 response(include_bytes!("testdata/reolink_setup.txt"))
             ),
         );
-
-        // PLAY.
-        let (session, _) = tokio::join!(session.play(PlayOptions::default()), async move {
-            server.send(bogus_pkt).await.unwrap();
+        let (session, req) = tokio::join!(
+            session.play(PlayOptions::default().scale(2.0)),
             req_response(
                 &mut server,
                 rtsp_types::Method::Play,
-                response(include_bytes!("testdata/reolink_play.txt")),
-            )
-            .await
-        },);
-        let _session = session.unwrap();
+                response(
+                    b"RTSP/1.0 200 OK\r\n\
+                      CSeq: 6\r\n\
+                      Range: npt=0.000-\r\n\
+                      Scale: 1.5\r\n\
+                      Session: F8F8E425\r\n\
+                      RTP-Info: url=trackID=1;seq=16852;rtptime=1070938629\r\n\
+                      \r\n"
+                )
+            ),
+        );
+        assert_eq!(
+            req.header(&rtsp_types::headers::SCALE).unwrap().as_str(),
+            "2.0"
+        );
+
+        // This server adjusts the requested scale.
+        assert_eq!(session.unwrap().play_scale(), Some(1.5));
+    }
+
+    #[test]
+    fn transport_policy_round_trip() {
+        for p in [
+            TransportPolicy::UdpOnly,
+            TransportPolicy::TcpOnly,
+            TransportPolicy::UdpThenTcp,
+        ] {
+            assert_eq!(p.to_string().parse::<TransportPolicy>().unwrap(), p);
+        }
+        assert!("bogus".parse::<TransportPolicy>().is_err());
     }
 
     // See with: cargo test -- --nocapture client::tests::print_sizes
@@ -2146,4 +8492,37 @@ mod tests {
         assert!(!has_live555_tcp_bug("LIVE555 Streaming Media v2017.06.04"));
         assert!(!has_live555_tcp_bug("LIVE555 Streaming Media v2020.01.01"));
     }
+
+    #[tokio::test]
+    async fn stream_descriptors() {
+        let (mut session, mut server) = describe_mock(SessionOptions::default()).await;
+        let summary: Vec<_> = session
+            .streams()
+            .iter()
+            .map(|s| (s.index(), s.media(), s.encoding_name(), s.clock_rate()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (0, "video", "h264", 90_000),
+                (1, "audio", "mpeg4-generic", 16_000)
+            ]
+        );
+        assert_eq!(session.streams()[0].rtp_payload_type(), 96);
+        assert_eq!(session.streams()[1].channels(), None); // unspecified in rtpmap
+
+        // SETUP of stream 1 uses stream 1's control URL.
+        let control = session.streams()[1].control().unwrap().clone();
+        assert!(control.as_str().ends_with("trackID=2"), "{}", control);
+        let (r, req) = tokio::join!(
+            session.setup(1),
+            req_response(
+                &mut server,
+                rtsp_types::Method::Setup,
+                response(include_bytes!("testdata/reolink_setup.txt"))
+            ),
+        );
+        r.unwrap();
+        assert_eq!(req.request_uri(), Some(&control));
+    }
 }
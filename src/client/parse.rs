@@ -8,7 +8,7 @@ use sdp_types::Media;
 use std::{net::IpAddr, num::NonZeroU16};
 use url::Url;
 
-use super::{Presentation, Stream};
+use super::{Presentation, Range, RtcpFeedback, RtpInfoEntry, Stream};
 
 /// A static payload type in the [RTP parameters
 /// registry](https://www.iana.org/assignments/rtp-parameters/rtp-parameters.xhtml#rtp-parameters-1).
@@ -187,6 +187,9 @@ static STATIC_PAYLOAD_TYPES: [Option<StaticPayloadType>; 35] = [
 /// Joins a control URL to a base URL in a non-RFC-compliant but common way.
 /// This matches what live555 and ffmpeg do.
 ///
+/// An absolute control URL is used as-is; `*` refers to the base URL itself;
+/// anything else is appended to the base URL, inserting a `/` if necessary.
+///
 /// See discussion at [#9](https://github.com/scottlamb/retina/issues/9).
 fn join_control(base_url: &Url, control: &str) -> Result<Url, String> {
     if control == "*" {
@@ -215,16 +218,31 @@ fn join_control(base_url: &Url, control: &str) -> Result<Url, String> {
 }
 
 /// Returns the `CSeq` from an RTSP response as a `u32`, or `None` if missing/unparseable.
-pub(crate) fn get_cseq(response: &rtsp_types::Response<Bytes>) -> Option<u32> {
-    response
-        .header(&rtsp_types::headers::CSEQ)
-        .and_then(|cseq| u32::from_str_radix(cseq.as_str(), 10).ok())
+pub(crate) fn get_cseq(response: &rtsp_types::Response<Bytes>, lenient: bool) -> Option<u32> {
+    let cseq = response.header(&rtsp_types::headers::CSEQ)?.as_str();
+    if let Ok(cseq) = u32::from_str_radix(cseq, 10) {
+        return Some(cseq);
+    }
+    if !lenient {
+        return None;
+    }
+
+    // Duplicated headers may be joined, as in `CSeq: 2, 2`. Use the first.
+    let first = cseq
+        .split(',')
+        .next()
+        .expect("split returns at least one part");
+    let parsed = u32::from_str_radix(first.trim(), 10).ok();
+    if parsed.is_some() {
+        debug!("Lenient parsing: using first of CSeq values {:?}", cseq);
+    }
+    parsed
 }
 
 /// Parses a [MediaDescription] to a [Stream].
 /// On failure, returns an error which is expected to be supplemented with
 /// the [MediaDescription] debug string and packed into a `RtspResponseError`.
-fn parse_media(base_url: &Url, media_description: &Media) -> Result<Stream, String> {
+fn parse_media(index: usize, base_url: &Url, media_description: &Media) -> Result<Stream, String> {
     let media = media_description.media.clone();
 
     // https://tools.ietf.org/html/rfc8866#section-5.14 says "If the <proto>
@@ -262,6 +280,11 @@ fn parse_media(base_url: &Url, media_description: &Media) -> Result<Stream, Stri
     let mut rtpmap = None;
     let mut fmtp = None;
     let mut control = None;
+    let mut rtcp_feedback = Vec::new();
+    let mut rtx_rtpmaps = Vec::new();
+    let mut rtx_fmtps = Vec::new();
+    let mut backchannel = false;
+    let apt = format!("apt={}", rtp_payload_type_str);
     for a in &media_description.attributes {
         if a.attribute == "rtpmap" {
             let v = a
@@ -281,6 +304,8 @@ fn parse_media(base_url: &Url, media_description: &Media) -> Result<Stream, Stri
                 .ok_or_else(|| "invalid rtmap attribute".to_string())?;
             if rtpmap_payload_type == rtp_payload_type_str {
                 rtpmap = Some(v);
+            } else if v.len() > 4 && v[..4].eq_ignore_ascii_case("rtx/") {
+                rtx_rtpmaps.push(rtpmap_payload_type);
             }
         } else if a.attribute == "fmtp" {
             // Similarly starts with payload-type SP.
@@ -293,6 +318,8 @@ fn parse_media(base_url: &Url, media_description: &Media) -> Result<Stream, Stri
                 .ok_or_else(|| "invalid fmtp attribute".to_string())?;
             if fmtp_payload_type == rtp_payload_type_str {
                 fmtp = Some(v);
+            } else if v.split(';').any(|p| p.trim() == apt) {
+                rtx_fmtps.push(fmtp_payload_type);
             }
         } else if a.attribute == "control" {
             control = a
@@ -300,9 +327,26 @@ fn parse_media(base_url: &Url, media_description: &Media) -> Result<Stream, Stri
                 .as_deref()
                 .map(|c| join_control(base_url, c))
                 .transpose()?;
+        } else if a.attribute == "sendonly" {
+            backchannel = true;
+        } else if a.attribute == "rtcp-fb" {
+            if let Some(fb) = a.value.as_deref().and_then(parse_rtcp_fb) {
+                if matches!(fb.payload_type, Some(pt) if pt != rtp_payload_type) {
+                    continue;
+                }
+                rtcp_feedback.push(fb);
+            }
         }
     }
 
+    // RFC 4588 section 8.6: a retransmission payload type has a "rtx"
+    // rtpmap and an "apt" (associated payload type) format parameter.
+    let rtx_payload_type = rtx_rtpmaps
+        .iter()
+        .find(|pt| rtx_fmtps.contains(pt))
+        .and_then(|pt| u8::from_str_radix(pt, 10).ok())
+        .filter(|pt| (pt & 0x80) == 0);
+
     let encoding_name;
     let clock_rate;
     let channels;
@@ -349,20 +393,76 @@ fn parse_media(base_url: &Url, media_description: &Media) -> Result<Stream, Stri
         }
     }
 
+    // RTP/SAVP streams are keyed by the first usable a=crypto attribute.
+    #[cfg(feature = "srtp")]
+    let srtp = if media_description.proto.contains("/SAVP") {
+        let mut srtp = Err("no a=crypto attribute".to_owned());
+        for a in media_description
+            .attributes
+            .iter()
+            .filter(|a| a.attribute == "crypto")
+        {
+            srtp = super::srtp::parse_crypto(a.value.as_deref().unwrap_or(""));
+            if srtp.is_ok() {
+                break;
+            }
+        }
+        Some(srtp)
+    } else {
+        None
+    };
+
     let encoding_name = encoding_name.to_ascii_lowercase();
     let depacketizer =
         crate::codec::Depacketizer::new(&media, &encoding_name, clock_rate, channels, fmtp);
 
     Ok(Stream {
+        index,
         media,
         encoding_name,
         clock_rate,
         rtp_payload_type,
         depacketizer,
         control,
+        sdp_attributes: media_description
+            .attributes
+            .iter()
+            .map(|a| (a.attribute.clone(), a.value.clone()))
+            .collect(),
         sockets: None,
         channels,
         state: super::StreamState::Uninit,
+        timeline_base: None,
+        rtcp_feedback,
+        rtx_payload_type,
+        backchannel,
+        send_seq: None,
+        #[cfg(feature = "srtp")]
+        srtp,
+    })
+}
+
+/// Parses the value of an `a=rtcp-fb` attribute, returning `None` if it's malformed.
+///
+/// [RFC 4585 section 4.2](https://datatracker.ietf.org/doc/html/rfc4585#section-4.2):
+/// ```text
+/// rtcp-fb-syntax = "a=rtcp-fb:" rtcp-fb-pt SP rtcp-fb-val CRLF
+/// rtcp-fb-pt     = "*" / fmt
+/// rtcp-fb-val    = "ack" rtcp-fb-ack-param / "nack" rtcp-fb-nack-param
+///                / "trr-int" SP 1*DIGIT / rtcp-fb-id rtcp-fb-param
+/// ```
+fn parse_rtcp_fb(value: &str) -> Option<RtcpFeedback> {
+    let mut parts = value.split_ascii_whitespace();
+    let payload_type = match parts.next()? {
+        "*" => None,
+        pt => Some(pt.parse().ok()?),
+    };
+    let id = parts.next()?.to_owned();
+    let param = parts.collect::<Vec<_>>().join(" ");
+    Some(RtcpFeedback {
+        payload_type,
+        id,
+        param: if param.is_empty() { None } else { Some(param) },
     })
 }
 
@@ -389,6 +489,8 @@ pub(crate) fn parse_describe(
     })?;
 
     // https://tools.ietf.org/html/rfc2326#appendix-C.1.1
+    // The base URL is the Content-Base, else the Content-Location, else the
+    // request URL. Either header may itself be relative to the request URL.
     let base_url = response
         .header(&rtsp_types::headers::CONTENT_BASE)
         .map(|v| (rtsp_types::headers::CONTENT_BASE, v))
@@ -397,8 +499,12 @@ pub(crate) fn parse_describe(
                 .header(&rtsp_types::headers::CONTENT_LOCATION)
                 .map(|v| (rtsp_types::headers::CONTENT_LOCATION, v))
         })
-        .map(|(h, v)| Url::parse(v.as_str()).map_err(|e| format!("bad {} {:?}: {}", h, v, e)))
-        .unwrap_or(Ok(request_url.clone()))?;
+        .map(|(h, v)| {
+            request_url
+                .join(v.as_str())
+                .map_err(|e| format!("bad {} {:?}: {}", h, v, e))
+        })
+        .unwrap_or_else(|| Ok(request_url.clone()))?;
 
     let mut control = None;
     let mut tool = None;
@@ -414,14 +520,14 @@ pub(crate) fn parse_describe(
             tool = a.value.as_deref().map(Into::into);
         }
     }
-    let control = control.unwrap_or(request_url);
+    let control = control.unwrap_or_else(|| request_url.clone());
 
     let streams = sdp
         .medias
         .iter()
         .enumerate()
         .map(|(i, m)| {
-            parse_media(&base_url, &m)
+            parse_media(i, &base_url, &m)
                 .map_err(|e| format!("Unable to parse stream {}: {}\n\n{:#?}", i, &e, &m))
         })
         .collect::<Result<Vec<Stream>, String>>()?;
@@ -431,25 +537,54 @@ pub(crate) fn parse_describe(
 
     Ok(Presentation {
         streams,
+        request_url,
         base_url,
         control,
         accept_dynamic_rate,
         tool,
+        raw_sdp: response.body().clone(),
     })
 }
 
+/// Default session timeout, as specified in [RFC 2326 section
+/// 12.37](https://datatracker.ietf.org/doc/html/rfc2326#section-12.37).
+const DEFAULT_TIMEOUT_SEC: u32 = 60;
+
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct SessionHeader {
     pub(crate) id: Box<str>,
     pub(crate) timeout_sec: u32,
 }
 
+impl SessionHeader {
+    /// Returns the interval at which to send keepalives: half the session
+    /// timeout, so that one keepalive may be lost or delayed without the
+    /// session expiring. `MIN_KEEPALIVE_DURATION` bounds this only for
+    /// sub-second timeouts. With the default timeout, this matches
+    /// `KEEPALIVE_DURATION`.
+    pub(crate) fn keepalive_interval(&self) -> std::time::Duration {
+        std::cmp::max(
+            std::time::Duration::from_secs(u64::from(self.timeout_sec)) / 2,
+            super::MIN_KEEPALIVE_DURATION,
+        )
+    }
+}
+
 pub(crate) struct SetupResponse {
     pub(crate) session: SessionHeader,
     pub(crate) ssrc: Option<u32>,
     pub(crate) channel_id: Option<u8>,
     pub(crate) source: Option<IpAddr>,
     pub(crate) server_port: Option<(u16, u16)>,
+
+    /// The multicast group, from the `destination` parameter.
+    pub(crate) destination: Option<IpAddr>,
+
+    /// The multicast RTP and RTCP ports, from the `port` parameter.
+    pub(crate) multicast_port: Option<(u16, u16)>,
+
+    /// The multicast time-to-live, from the `ttl` parameter.
+    pub(crate) ttl: Option<u8>,
 }
 
 /// Parses a `SETUP` response.
@@ -464,7 +599,7 @@ pub(crate) fn parse_setup(response: &rtsp_types::Response<Bytes>) -> Result<Setu
     let session = match session.as_str().split_once(';') {
         None => SessionHeader {
             id: session.as_str().into(),
-            timeout_sec: 60, // default
+            timeout_sec: DEFAULT_TIMEOUT_SEC,
         },
         Some((id, timeout_str)) => {
             if let Some(v) = timeout_str.trim().strip_prefix("timeout=") {
@@ -486,11 +621,13 @@ pub(crate) fn parse_setup(response: &rtsp_types::Response<Bytes>) -> Result<Setu
     let mut ssrc = None;
     let mut source = None;
     let mut server_port = None;
+    let mut destination = None;
+    let mut multicast_port = None;
+    let mut ttl = None;
     for part in transport.as_str().split(';') {
         if let Some(v) = part.strip_prefix("ssrc=") {
             let v = u32::from_str_radix(v, 16).map_err(|_| format!("Unparseable ssrc {}", v))?;
             ssrc = Some(v);
-            break;
         } else if let Some(interleaved) = part.strip_prefix("interleaved=") {
             let mut channels = interleaved.splitn(2, '-');
             let n = channels.next().expect("splitn returns at least one part");
@@ -509,19 +646,19 @@ pub(crate) fn parse_setup(response: &rtsp_types::Response<Bytes>) -> Result<Setu
                     .map_err(|_| format!("Transport header has unparseable source {:?}", s))?,
             );
         } else if let Some(s) = part.strip_prefix("server_port=") {
-            let mut ports = s.splitn(2, '-');
-            let n = ports.next().expect("splitn returns at least one part");
-            let n = u16::from_str_radix(n, 10)
-                .map_err(|_| format!("bad port in Transport: {}", transport.as_str()))?;
-            if let Some(m) = ports.next() {
-                let m = u16::from_str_radix(m, 10).map_err(|_| format!("bad second port {}", m))?;
-                server_port = Some((n, m))
-            } else {
-                // TODO: this is allowed by RFC 2326's grammar, but I'm not sure
-                // what it means. Does it use the same port for both RTP and
-                // RTCP, or is it implied the second is one more than the first?
-                return Err("Transport header specifies a single server_port".to_owned());
-            }
+            server_port = Some(parse_port_pair("server_port", s, transport.as_str())?);
+        } else if let Some(s) = part.strip_prefix("port=") {
+            multicast_port = Some(parse_port_pair("port", s, transport.as_str())?);
+        } else if let Some(s) = part.strip_prefix("destination=") {
+            destination =
+                Some(s.parse().map_err(|_| {
+                    format!("Transport header has unparseable destination {:?}", s)
+                })?);
+        } else if let Some(s) = part.strip_prefix("ttl=") {
+            ttl = Some(
+                s.parse()
+                    .map_err(|_| format!("Transport header has unparseable ttl {:?}", s))?,
+            );
         }
     }
     Ok(SetupResponse {
@@ -530,18 +667,98 @@ pub(crate) fn parse_setup(response: &rtsp_types::Response<Bytes>) -> Result<Setu
         channel_id,
         source,
         server_port,
+        destination,
+        multicast_port,
+        ttl,
     })
 }
 
+/// Parses a `Transport` header port range such as `6000-6001`.
+fn parse_port_pair(param: &str, s: &str, transport: &str) -> Result<(u16, u16), String> {
+    let mut ports = s.splitn(2, '-');
+    let n = ports.next().expect("splitn returns at least one part");
+    let n =
+        u16::from_str_radix(n, 10).map_err(|_| format!("bad port in Transport: {}", transport))?;
+    if let Some(m) = ports.next() {
+        let m = u16::from_str_radix(m, 10).map_err(|_| format!("bad second port {}", m))?;
+        Ok((n, m))
+    } else {
+        // TODO: this is allowed by RFC 2326's grammar, but I'm not sure
+        // what it means. Does it use the same port for both RTP and
+        // RTCP, or is it implied the second is one more than the first?
+        Err(format!("Transport header specifies a single {}", param))
+    }
+}
+
+/// Parses the `Public` header of an `OPTIONS` response into the methods it
+/// lists, as described in
+/// [RFC 2326 section 12.28](https://datatracker.ietf.org/doc/html/rfc2326#section-12.28).
+pub(crate) fn parse_public(response: &rtsp_types::Response<Bytes>) -> Vec<rtsp_types::Method> {
+    response
+        .header(&rtsp_types::headers::PUBLIC)
+        .map(|h| {
+            h.as_str()
+                .split(',')
+                .map(str::trim)
+                .filter(|m| !m.is_empty())
+                .map(rtsp_types::Method::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Parses the `x-Dynamic-Rate` header of a `SETUP` or `PLAY` response, if any.
+pub(crate) fn parse_dynamic_rate(response: &rtsp_types::Response<Bytes>) -> Option<bool> {
+    let h = response.header(&crate::X_DYNAMIC_RATE)?;
+    match h.as_str().trim() {
+        "1" => Some(true),
+        "0" => Some(false),
+        o => {
+            debug!("Unparseable x-Dynamic-Rate {:?}", o);
+            None
+        }
+    }
+}
+
+pub(crate) struct PlayResponse {
+    pub(crate) range: Option<Range>,
+    pub(crate) scale: Option<f32>,
+    pub(crate) rtp_info: Vec<RtpInfoEntry>,
+}
+
 /// Parses a `PLAY` response. The error should always be packed into a `RtspProtocolError`.
 pub(crate) fn parse_play(
     response: &rtsp_types::Response<Bytes>,
     presentation: &mut Presentation,
-) -> Result<(), String> {
+) -> Result<PlayResponse, String> {
+    // https://datatracker.ietf.org/doc/html/rfc2326#section-12.29
+    let range = response.header(&rtsp_types::headers::RANGE).map(|r| {
+        r.as_str().parse().unwrap_or_else(|e| {
+            debug!("Unparseable PLAY Range: {}", e);
+            Range::Other(r.as_str().to_owned())
+        })
+    });
+
+    // https://datatracker.ietf.org/doc/html/rfc2326#section-12.34
+    let scale = response.header(&rtsp_types::headers::SCALE).and_then(|s| {
+        match s.as_str().trim().parse() {
+            Ok(s) => Some(s),
+            Err(e) => {
+                debug!("Unparseable PLAY Scale {:?}: {}", s.as_str(), e);
+                None
+            }
+        }
+    });
+    let mut play_response = PlayResponse {
+        range,
+        scale,
+        rtp_info: Vec::new(),
+    };
+
     // https://tools.ietf.org/html/rfc2326#section-12.33
     let rtp_info = match response.header(&rtsp_types::headers::RTP_INFO) {
         Some(rtsp_info) => rtsp_info,
-        None => return Ok(()),
+        None => return Ok(play_response),
     };
     for s in rtp_info.as_str().split(',') {
         let s = s.trim();
@@ -552,6 +769,42 @@ pub(crate) fn parse_play(
             .strip_prefix("url=")
             .ok_or_else(|| "RTP-Info missing stream URL".to_string())?;
         let url = join_control(&presentation.base_url, url)?;
+        let mut entry = RtpInfoEntry {
+            url,
+            seq: None,
+            rtptime: None,
+        };
+        let mut ssrc = None;
+
+        // The first unparseable value, which is an error only if it describes
+        // a stream that has been set up.
+        let mut bad_value = None;
+        for part in parts {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| "RTP-Info param has no =".to_string())?;
+            match key {
+                "seq" => match u16::from_str_radix(value, 10) {
+                    Ok(seq) => entry.seq = Some(seq),
+                    Err(_) => {
+                        bad_value.get_or_insert_with(|| format!("bad seq {:?}", value));
+                    }
+                },
+                "rtptime" => match u32::from_str_radix(value, 10) {
+                    Ok(rtptime) => entry.rtptime = Some(rtptime),
+                    Err(_) => {
+                        bad_value.get_or_insert_with(|| format!("bad rtptime {:?}", value));
+                    }
+                },
+                "ssrc" => match u32::from_str_radix(value, 16) {
+                    Ok(s) => ssrc = Some(s),
+                    Err(_) => {
+                        bad_value.get_or_insert_with(|| format!("Unparseable ssrc {}", value));
+                    }
+                },
+                _ => {}
+            }
+        }
         let stream;
         if presentation.streams.len() == 1 {
             // The server is allowed to not specify a stream control URL for
@@ -567,16 +820,30 @@ pub(crate) fn parse_play(
             stream = presentation
                 .streams
                 .iter_mut()
-                .find(|s| matches!(&s.control, Some(u) if u == &url));
+                .find(|s| matches!(&s.control, Some(u) if u == &entry.url));
         }
         let stream = match stream {
             Some(s) => s,
             None => {
-                log::warn!("RTP-Info contains unknown stream {}", url);
+                log::warn!("RTP-Info contains unknown stream {}", &entry.url);
+                if let Some(e) = bad_value {
+                    log::warn!("Ignoring {} in RTP-Info for unknown stream", e);
+                }
+                play_response.rtp_info.push(entry);
                 continue;
             }
         };
-        let state = match &mut stream.state {
+        if let Some(e) = bad_value {
+            if !matches!(stream.state, super::StreamState::Uninit) {
+                return Err(e);
+            }
+            log::warn!(
+                "Ignoring {} in RTP-Info for stream {}, which wasn't set up",
+                e,
+                &entry.url
+            );
+        }
+        match &mut stream.state {
             super::StreamState::Uninit => {
                 // This appears to happen for Reolink devices when we did not send a SETUP request
                 // for all streams. It also happens in some of other the tests
@@ -585,36 +852,28 @@ pub(crate) fn parse_play(
                     "PLAY response described stream {} in Uninit state",
                     stream.control.as_ref().unwrap_or(&presentation.control)
                 );
-                continue;
             }
-            super::StreamState::Init(init) => init,
-            super::StreamState::Playing { .. } => unreachable!(),
-        };
-        for part in parts {
-            let (key, value) = part
-                .split_once('=')
-                .ok_or_else(|| "RTP-Info param has no =".to_string())?;
-            match key {
-                "seq" => {
-                    let seq = u16::from_str_radix(value, 10)
-                        .map_err(|_| format!("bad seq {:?}", value))?;
-                    state.initial_seq = Some(seq);
+            super::StreamState::Init(state) => {
+                if entry.seq.is_some() {
+                    state.initial_seq = entry.seq;
                 }
-                "rtptime" => {
-                    let rtptime = u32::from_str_radix(value, 10)
-                        .map_err(|_| format!("bad rtptime {:?}", value))?;
-                    state.initial_rtptime = Some(rtptime);
+                if entry.rtptime.is_some() {
+                    state.initial_rtptime = entry.rtptime;
                 }
-                "ssrc" => {
-                    let ssrc = u32::from_str_radix(value, 16)
-                        .map_err(|_| format!("Unparseable ssrc {}", value))?;
-                    state.ssrc = Some(ssrc);
+                if ssrc.is_some() {
+                    state.ssrc = ssrc;
                 }
-                _ => {}
             }
-        }
+            super::StreamState::Playing { .. } => {
+                // A `PLAY` to resume after `PAUSE` without seeking. Keep the
+                // existing RTP state, so the stream continues where it left
+                // off. (When seeking, `Session::resume` first moves the
+                // stream back to `Init`.)
+            }
+        };
+        play_response.rtp_info.push(entry);
     }
-    Ok(())
+    Ok(play_response)
 }
 
 #[cfg(test)]
@@ -626,10 +885,19 @@ mod tests {
 
     use crate::{client::StreamStateInit, codec::Parameters};
 
-    use super::super::StreamState;
+    use super::super::{Range, RtcpFeedback, RtpInfoEntry, StreamState};
     use super::SessionHeader;
     use crate::testutil::response;
 
+    #[test]
+    fn lenient_cseq() {
+        let r = response(b"RTSP/1.0 200 OK\r\nCSeq: 2, 2\r\n\r\n");
+        assert_eq!(super::get_cseq(&r, false), None);
+        assert_eq!(super::get_cseq(&r, true), Some(2));
+        let r = response(b"RTSP/1.0 200 OK\r\nCSeq: 3\r\n\r\n");
+        assert_eq!(super::get_cseq(&r, false), Some(3));
+    }
+
     fn parse_describe(
         raw_url: &str,
         raw_response: &'static [u8],
@@ -638,6 +906,287 @@ mod tests {
         super::parse_describe(url, &response(raw_response))
     }
 
+    #[test]
+    fn sdp_attributes() {
+        let p = parse_describe(
+            "rtsp://127.0.0.1/",
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Content-Type: application/sdp\r\n\
+              Content-Length: 138\r\n\r\n\
+              v=0\r\n\
+              o=- 0 0 IN IP4 127.0.0.1\r\n\
+              s=-\r\n\
+              t=0 0\r\n\
+              m=video 0 RTP/AVP 96\r\n\
+              a=rtpmap:96 H264/90000\r\n\
+              a=framerate:30\r\n\
+              a=recvonly\r\n\
+              a=control:trackID=1\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            p.streams[0].sdp_attributes(),
+            &[
+                ("rtpmap".to_owned(), Some("96 H264/90000".to_owned())),
+                ("framerate".to_owned(), Some("30".to_owned())),
+                ("recvonly".to_owned(), None),
+                ("control".to_owned(), Some("trackID=1".to_owned())),
+            ]
+        );
+    }
+
+    #[test]
+    fn rtcp_feedback() {
+        let p = parse_describe(
+            "rtsp://127.0.0.1/",
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Content-Type: application/sdp\r\n\
+              Content-Length: 199\r\n\r\n\
+              v=0\r\n\
+              o=- 0 0 IN IP4 127.0.0.1\r\n\
+              s=-\r\n\
+              t=0 0\r\n\
+              m=video 0 RTP/AVP 96\r\n\
+              a=rtpmap:96 H264/90000\r\n\
+              a=rtcp-fb:96 nack pli\r\n\
+              a=rtcp-fb:* ccm fir\r\n\
+              a=rtcp-fb:97 nack\r\n\
+              a=rtcp-fb:96 trr-int 100\r\n\
+              a=control:trackID=1\r\n",
+        )
+        .unwrap();
+        let fb = p.streams[0].rtcp_feedback();
+        assert_eq!(
+            fb,
+            &[
+                RtcpFeedback {
+                    payload_type: Some(96),
+                    id: "nack".to_owned(),
+                    param: Some("pli".to_owned()),
+                },
+                RtcpFeedback {
+                    payload_type: None,
+                    id: "ccm".to_owned(),
+                    param: Some("fir".to_owned()),
+                },
+                RtcpFeedback {
+                    payload_type: Some(96),
+                    id: "trr-int".to_owned(),
+                    param: Some("100".to_owned()),
+                },
+            ]
+        );
+        assert!(fb[0].is_pli());
+        assert!(fb[1].is_fir());
+        assert!(!fb.iter().any(RtcpFeedback::is_nack));
+    }
+
+    #[test]
+    fn rtx_payload_type() {
+        let p = parse_describe(
+            "rtsp://127.0.0.1/",
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Content-Type: application/sdp\r\n\
+              Content-Length: 168\r\n\r\n\
+              v=0\r\n\
+              o=- 0 0 IN IP4 127.0.0.1\r\n\
+              s=-\r\n\
+              t=0 0\r\n\
+              m=video 0 RTP/AVP 96 97\r\n\
+              a=rtpmap:96 H264/90000\r\n\
+              a=rtpmap:97 rtx/90000\r\n\
+              a=fmtp:97 apt=96;rtx-time=3000\r\n\
+              a=control:trackID=1\r\n",
+        )
+        .unwrap();
+        assert_eq!(p.streams[0].rtp_payload_type, 96);
+        assert_eq!(p.streams[0].rtx_payload_type(), Some(97));
+    }
+
+    #[test]
+    fn join_control() {
+        let base = Url::parse("rtsp://192.168.1.2:554/live/ch00_0?token=abc").unwrap();
+        assert_eq!(
+            super::join_control(&base, "rtsp://192.168.1.3/other/trackID=0")
+                .unwrap()
+                .as_str(),
+            "rtsp://192.168.1.3/other/trackID=0"
+        );
+        assert_eq!(
+            super::join_control(&base, "*").unwrap().as_str(),
+            "rtsp://192.168.1.2:554/live/ch00_0?token=abc"
+        );
+        let base = Url::parse("rtsp://192.168.1.2:554/live/ch00_0").unwrap();
+        assert_eq!(
+            super::join_control(&base, "trackID=1").unwrap().as_str(),
+            "rtsp://192.168.1.2:554/live/ch00_0/trackID=1"
+        );
+        let base = Url::parse("rtsp://192.168.1.2:554/live/ch00_0/").unwrap();
+        assert_eq!(
+            super::join_control(&base, "trackID=1").unwrap().as_str(),
+            "rtsp://192.168.1.2:554/live/ch00_0/trackID=1"
+        );
+    }
+
+    /// Checks control URL resolution with a relative `Content-Base`, an
+    /// aggregate `*` session control, and absolute and relative stream
+    /// controls.
+    #[test]
+    fn control_precedence() {
+        let p = parse_describe(
+            "rtsp://192.168.1.2:554/request/path",
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Content-Base: /live/ch00_0/\r\n\
+              Content-Type: application/sdp\r\n\
+              Content-Length: 203\r\n\r\n\
+              v=0\r\n\
+              o=- 0 0 IN IP4 127.0.0.1\r\n\
+              s=-\r\n\
+              t=0 0\r\n\
+              a=control:*\r\n\
+              m=video 0 RTP/AVP 96\r\n\
+              a=rtpmap:96 H264/90000\r\n\
+              a=control:trackID=1\r\n\
+              m=video 0 RTP/AVP 97\r\n\
+              a=rtpmap:97 H264/90000\r\n\
+              a=control:rtsp://192.168.1.3/sub\r\n",
+        )
+        .unwrap();
+        assert_eq!(p.control.as_str(), "rtsp://192.168.1.2:554/live/ch00_0/");
+        assert_eq!(
+            p.streams[0].control.as_ref().unwrap().as_str(),
+            "rtsp://192.168.1.2:554/live/ch00_0/trackID=1"
+        );
+        assert_eq!(
+            p.streams[1].control.as_ref().unwrap().as_str(),
+            "rtsp://192.168.1.3/sub"
+        );
+
+        // Without Content-Base or Content-Location, use the request URL.
+        let p = parse_describe(
+            "rtsp://192.168.1.2:554/request/path",
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Content-Type: application/sdp\r\n\
+              Content-Length: 102\r\n\r\n\
+              v=0\r\n\
+              o=- 0 0 IN IP4 127.0.0.1\r\n\
+              s=-\r\n\
+              t=0 0\r\n\
+              a=control:*\r\n\
+              m=video 0 RTP/AVP 96\r\n\
+              a=rtpmap:96 H264/90000\r\n",
+        )
+        .unwrap();
+        assert_eq!(p.control.as_str(), "rtsp://192.168.1.2:554/request/path");
+        assert!(p.streams[0].control.is_none());
+    }
+
+    /// Checks that the raw SDP matches the `DESCRIBE` body byte-for-byte.
+    #[test]
+    fn raw_sdp() {
+        let raw = include_bytes!("testdata/reolink_describe.txt");
+        let p = parse_describe("rtsp://192.168.5.206:554/h264Preview_01_main", raw).unwrap();
+        let body_start = raw.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        assert_eq!(&p.raw_sdp[..], &raw[body_start..]);
+        assert!(p.raw_sdp.ends_with(b"\r\n"));
+    }
+
+    #[test]
+    fn session_timeout() {
+        let setup_response = response(
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Session: 12345;timeout=30\r\n\
+              Transport: RTP/AVP/TCP;unicast;interleaved=0-1\r\n\r\n",
+        );
+        let setup_response = super::parse_setup(&setup_response).unwrap();
+        assert_eq!(
+            setup_response.session,
+            SessionHeader {
+                id: "12345".into(),
+                timeout_sec: 30,
+            }
+        );
+        assert_eq!(
+            setup_response.session.keepalive_interval(),
+            std::time::Duration::from_secs(15)
+        );
+
+        // Short timeouts still get keepalives within them.
+        let short = SessionHeader {
+            id: "12345".into(),
+            timeout_sec: 2,
+        };
+        assert_eq!(
+            short.keepalive_interval(),
+            std::time::Duration::from_secs(1)
+        );
+
+        // A zero timeout is clamped rather than sending keepalives constantly.
+        let zero = SessionHeader {
+            id: "12345".into(),
+            timeout_sec: 0,
+        };
+        assert_eq!(
+            zero.keepalive_interval(),
+            crate::client::MIN_KEEPALIVE_DURATION
+        );
+
+        // Without a specified timeout, the keepalive interval is unchanged.
+        let setup_response = response(include_bytes!("testdata/reolink_setup.txt"));
+        let setup_response = super::parse_setup(&setup_response).unwrap();
+        assert_eq!(
+            setup_response.session.keepalive_interval(),
+            crate::client::KEEPALIVE_DURATION
+        );
+    }
+
+    #[test]
+    fn server_port() {
+        let setup_response = response(
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Session: 12345\r\n\
+              Transport: RTP/AVP;unicast;server_port=6000-6001\r\n\r\n",
+        );
+        let setup_response = super::parse_setup(&setup_response).unwrap();
+        assert_eq!(setup_response.server_port, Some((6000, 6001)));
+
+        // Parameters after ssrc are also honored.
+        let setup_response = response(
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Session: 12345\r\n\
+              Transport: RTP/AVP;unicast;ssrc=30a98ee7;server_port=6000-6001\r\n\r\n",
+        );
+        let setup_response = super::parse_setup(&setup_response).unwrap();
+        assert_eq!(setup_response.ssrc, Some(0x30a98ee7));
+        assert_eq!(setup_response.server_port, Some((6000, 6001)));
+    }
+
+    #[test]
+    fn multicast_setup() {
+        let setup_response = response(
+            b"RTSP/1.0 200 OK\r\n\
+              CSeq: 2\r\n\
+              Session: 12345\r\n\
+              Transport: RTP/AVP;multicast;destination=239.1.2.3;port=5000-5001;ttl=16\r\n\r\n",
+        );
+        let setup_response = super::parse_setup(&setup_response).unwrap();
+        assert_eq!(
+            setup_response.destination,
+            Some(std::net::IpAddr::V4(std::net::Ipv4Addr::new(239, 1, 2, 3)))
+        );
+        assert_eq!(setup_response.multicast_port, Some((5000, 5001)));
+        assert_eq!(setup_response.ttl, Some(16));
+        assert_eq!(setup_response.server_port, None);
+    }
+
     #[test]
     fn anvpiz_sdp() {
         let url = Url::parse("rtsp://127.0.0.1/").unwrap();
@@ -767,7 +1316,15 @@ mod tests {
         assert_eq!(p.streams[0].media, "video");
         assert_eq!(p.streams[0].encoding_name, "h265");
         assert_eq!(p.streams[0].rtp_payload_type, 98);
-        assert!(p.streams[0].parameters().is_none());
+        match p.streams[0].parameters().unwrap() {
+            Parameters::Video(v) => {
+                assert_eq!(v.rfc6381_codec(), "hvc1.1.6.L90.B0");
+                assert_eq!(v.pixel_dimensions(), (704, 480));
+                assert_eq!(v.pixel_aspect_ratio(), None);
+                assert_eq!(v.frame_rate(), None);
+            }
+            _ => panic!(),
+        };
         assert_eq!(p.streams[1].media, "audio");
         assert_eq!(p.streams[1].encoding_name, "pcma");
         assert_eq!(p.streams[1].rtp_payload_type, 8);
@@ -928,11 +1485,33 @@ mod tests {
         p.streams[1].state = StreamState::Init(StreamStateInit::default());
 
         // PLAY.
-        super::parse_play(
+        let play_response = super::parse_play(
             &response(include_bytes!("testdata/reolink_play.txt")),
             &mut p,
         )
         .unwrap();
+        assert_eq!(
+            play_response.range,
+            Some(Range::Npt {
+                start: std::time::Duration::from_secs(0),
+                end: None
+            })
+        );
+        assert_eq!(
+            play_response.rtp_info,
+            vec![
+                RtpInfoEntry {
+                    url: Url::parse(&(base.to_string() + "trackID=1")).unwrap(),
+                    seq: Some(16852),
+                    rtptime: Some(1070938629),
+                },
+                RtpInfoEntry {
+                    url: Url::parse(&(base.to_string() + "trackID=2")).unwrap(),
+                    seq: Some(39409),
+                    rtptime: Some(3075976528),
+                },
+            ]
+        );
         match p.streams[0].state {
             StreamState::Init(state) => {
                 assert_eq!(state.initial_seq, Some(16852));
@@ -1291,4 +1870,47 @@ mod tests {
             _ => panic!(),
         };
     }
+
+    /// Unparseable `RTP-Info` values are ignored for streams which weren't set
+    /// up but fail `PLAY` for streams which were.
+    #[test]
+    fn rtp_info_bad_value() {
+        let mut p = parse_describe(
+            "rtsp://192.168.5.206:554/h264Preview_01_main",
+            include_bytes!("testdata/reolink_describe.txt"),
+        )
+        .unwrap();
+        p.streams[0].state = StreamState::Init(StreamStateInit::default());
+        let play_response = super::parse_play(
+            &response(
+                b"RTSP/1.0 200 OK\r\n\
+                  CSeq: 6\r\n\
+                  Session: F8F8E425\r\n\
+                  RTP-Info: url=trackID=1;seq=16852;rtptime=1070938629;ssrc=dcc4a0d8,\
+                  url=trackID=2;seq=bogus;rtptime=-1;ssrc=zz\r\n\
+                  \r\n",
+            ),
+            &mut p,
+        )
+        .unwrap();
+        assert_eq!(play_response.rtp_info[1].seq, None);
+        assert_eq!(play_response.rtp_info[1].rtptime, None);
+        match &p.streams[0].state {
+            StreamState::Init(s) => assert_eq!(s.initial_seq, Some(16852)),
+            _ => panic!(),
+        };
+
+        let e = super::parse_play(
+            &response(
+                b"RTSP/1.0 200 OK\r\n\
+                  CSeq: 6\r\n\
+                  Session: F8F8E425\r\n\
+                  RTP-Info: url=trackID=1;seq=bogus;rtptime=1070938629;ssrc=dcc4a0d8\r\n\
+                  \r\n",
+            ),
+            &mut p,
+        )
+        .unwrap_err();
+        assert_eq!(e, "bad seq \"bogus\"");
+    }
 }
@@ -0,0 +1,279 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Bounded read-ahead queue for [`super::Demuxed`]; see [`super::QueuePolicy`].
+//!
+//! A background task owns the underlying session and depacketizes into a
+//! queue shared with the consumer. The task ends when the session does, after
+//! the first error, on `TEARDOWN`, or when the consumer is dropped (in which
+//! case the session is dropped, tearing down according to its
+//! [`super::TeardownPolicy`]).
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use futures::StreamExt;
+use tokio::sync::Notify;
+
+use super::{rtp::StreamStats, DirectDemuxed};
+use crate::{codec::CodecItem, Error, ErrorInt};
+
+/// The consumer side of the queue.
+pub(super) struct Queue {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    state: Mutex<State>,
+
+    /// Wakes the producer: the queue has space, or the consumer has requested
+    /// `TEARDOWN` or been dropped.
+    producer: Notify,
+
+    /// Wakes [`Queue::teardown`] when the producer has finished tearing down.
+    teardown_done: Notify,
+}
+
+struct State {
+    items: VecDeque<Result<CodecItem, Error>>,
+    capacity: usize,
+    drop_oldest: bool,
+
+    /// For each stream, the loss due to dropped items which hasn't yet been
+    /// reported via a later item.
+    dropped: Vec<u16>,
+
+    /// For each stream, the most recent `(stream_stats, stream_jitter,
+    /// stream_bitrate)`.
+    stats: Vec<(Option<StreamStats>, Option<u32>, Option<u32>)>,
+
+    consumer_waker: Option<Waker>,
+    producer_done: bool,
+    consumer_closed: bool,
+    teardown: Teardown,
+}
+
+enum Teardown {
+    NotRequested,
+    Requested,
+    Done(Result<(), Error>),
+}
+
+/// What the producer should do next.
+enum Action {
+    Stop,
+    Teardown,
+    WaitForSpace,
+    Read,
+}
+
+impl Queue {
+    /// Starts a producer task reading from `demuxed` via `handle`.
+    pub(super) fn spawn(
+        handle: &tokio::runtime::Handle,
+        demuxed: DirectDemuxed,
+        capacity: usize,
+        drop_oldest: bool,
+    ) -> Self {
+        let streams = demuxed.session.streams().len();
+        let shared = Arc::new(Shared {
+            state: Mutex::new(State {
+                items: VecDeque::with_capacity(capacity),
+                capacity,
+                drop_oldest,
+                dropped: vec![0; streams],
+                stats: stats(&demuxed),
+                consumer_waker: None,
+                producer_done: false,
+                consumer_closed: false,
+                teardown: Teardown::NotRequested,
+            }),
+            producer: Notify::new(),
+            teardown_done: Notify::new(),
+        });
+        handle.spawn(produce(demuxed, shared.clone()));
+        Queue { shared }
+    }
+
+    pub(super) fn poll_next(
+        &mut self,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<CodecItem, Error>>> {
+        let mut l = self.shared.state.lock().unwrap();
+        let s = &mut *l;
+        if let Some(mut item) = s.items.pop_front() {
+            if let Ok(i) = &mut item {
+                if let Some((stream_id, loss)) = loss_mut(i) {
+                    *loss = loss.saturating_add(std::mem::take(&mut s.dropped[stream_id]));
+                }
+            }
+            if !s.drop_oldest && s.items.len() + 1 == s.capacity {
+                self.shared.producer.notify_one();
+            }
+            return Poll::Ready(Some(item));
+        }
+        if s.producer_done {
+            return Poll::Ready(None);
+        }
+        s.consumer_waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+
+    pub(super) fn stream_stats(&self, stream_id: usize) -> Option<StreamStats> {
+        let l = self.shared.state.lock().unwrap();
+        l.stats.get(stream_id).and_then(|s| s.0)
+    }
+
+    pub(super) fn stream_jitter(&self, stream_id: usize) -> Option<u32> {
+        let l = self.shared.state.lock().unwrap();
+        l.stats.get(stream_id).and_then(|s| s.1)
+    }
+
+    pub(super) fn stream_bitrate(&self, stream_id: usize) -> Option<u32> {
+        let l = self.shared.state.lock().unwrap();
+        l.stats.get(stream_id).and_then(|s| s.2)
+    }
+
+    /// Asks the producer to send a `TEARDOWN` and waits for its result.
+    pub(super) async fn teardown(&mut self) -> Result<(), Error> {
+        loop {
+            if let Some(r) = self.check_teardown() {
+                return r;
+            }
+            self.shared.teardown_done.notified().await;
+        }
+    }
+
+    /// Requests `TEARDOWN` if necessary, returning its result if finished.
+    fn check_teardown(&self) -> Option<Result<(), Error>> {
+        let mut l = self.shared.state.lock().unwrap();
+        if let Teardown::Done(r) = &l.teardown {
+            return Some(r.clone());
+        }
+        if l.producer_done {
+            return Some(Err(wrap!(ErrorInt::FailedPrecondition(
+                "session has already ended".into()
+            ))));
+        }
+        if matches!(l.teardown, Teardown::NotRequested) {
+            l.teardown = Teardown::Requested;
+            self.shared.producer.notify_one();
+        }
+        None
+    }
+}
+
+impl Drop for Queue {
+    fn drop(&mut self) {
+        self.shared.state.lock().unwrap().consumer_closed = true;
+        self.shared.producer.notify_one();
+    }
+}
+
+impl Shared {
+    fn next_action(&self) -> Action {
+        let l = self.state.lock().unwrap();
+        if l.consumer_closed {
+            Action::Stop
+        } else if matches!(l.teardown, Teardown::Requested) {
+            Action::Teardown
+        } else if !l.drop_oldest && l.items.len() >= l.capacity {
+            Action::WaitForSpace
+        } else {
+            Action::Read
+        }
+    }
+
+    /// Enqueues an item (or end of stream, if `None`) from the producer.
+    /// Returns false if the producer should stop.
+    fn push(&self, item: Option<Result<CodecItem, Error>>, demuxed: &DirectDemuxed) -> bool {
+        let mut l = self.state.lock().unwrap();
+        let s = &mut *l;
+        let more = match item {
+            None => false,
+            Some(item) => {
+                let more = item.is_ok();
+                if s.items.len() >= s.capacity {
+                    // Only reachable with drop_oldest; errors are always last.
+                    if let Some(Ok(mut old)) = s.items.pop_front() {
+                        if let Some((stream_id, loss)) = loss_mut(&mut old) {
+                            let dropped = &mut s.dropped[stream_id];
+                            *dropped = dropped.saturating_add(loss.saturating_add(1));
+                        }
+                    }
+                }
+                s.items.push_back(item);
+                s.stats = stats(demuxed);
+                more
+            }
+        };
+        if !more {
+            s.producer_done = true;
+        }
+        if let Some(w) = s.consumer_waker.take() {
+            w.wake();
+        }
+        more
+    }
+
+    fn finish_teardown(&self, result: Result<(), Error>) {
+        let mut l = self.state.lock().unwrap();
+        l.teardown = Teardown::Done(result);
+        l.producer_done = true;
+        if let Some(w) = l.consumer_waker.take() {
+            w.wake();
+        }
+        self.teardown_done.notify_one();
+    }
+}
+
+async fn produce(mut demuxed: DirectDemuxed, shared: Arc<Shared>) {
+    loop {
+        match shared.next_action() {
+            Action::Stop => return,
+            Action::Teardown => {
+                let r = demuxed.session.teardown().await;
+                shared.finish_teardown(r);
+                return;
+            }
+            Action::WaitForSpace => shared.producer.notified().await,
+            Action::Read => {
+                // Also wake on a notification, to promptly handle a teardown
+                // request or closed consumer while no data is arriving.
+                let item = tokio::select! {
+                    item = demuxed.next() => Some(item),
+                    _ = shared.producer.notified() => None,
+                };
+                if let Some(item) = item {
+                    if !shared.push(item, &demuxed) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn stats(demuxed: &DirectDemuxed) -> Vec<(Option<StreamStats>, Option<u32>, Option<u32>)> {
+    (0..demuxed.session.streams().len())
+        .map(|i| {
+            (
+                demuxed.session.stream_stats(i),
+                demuxed.session.stream_jitter(i),
+                demuxed.session.stream_bitrate(i),
+            )
+        })
+        .collect()
+}
+
+/// Returns the stream id and loss counter of an item, if it has them.
+fn loss_mut(item: &mut CodecItem) -> Option<(usize, &mut u16)> {
+    match item {
+        CodecItem::VideoFrame(f) => Some((f.stream_id, &mut f.loss)),
+        CodecItem::AudioFrame(f) => Some((f.stream_id, &mut f.loss)),
+        CodecItem::MessageFrame(f) => Some((f.stream_id, &mut f.loss)),
+        CodecItem::RawRtp(p) => Some((p.stream_id, &mut p.loss)),
+        CodecItem::SenderReport(_) | CodecItem::Loss { .. } => None,
+    }
+}
@@ -3,6 +3,9 @@
 
 //! RTP and RTCP handling; see [RFC 3550](https://datatracker.ietf.org/doc/html/rfc3550).
 
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+
 use bytes::{Buf, Bytes};
 use log::{debug, trace};
 use pretty_hex::PrettyHex;
@@ -10,7 +13,7 @@ use pretty_hex::PrettyHex;
 use crate::client::PacketItem;
 use crate::{ConnectionContext, Error, ErrorInt, PacketContext};
 
-use super::{SessionOptions, Timeline};
+use super::{PayloadTypePolicy, SessionOptions, Timeline};
 
 /// A received RTP packet.
 pub struct Packet {
@@ -30,10 +33,132 @@ pub struct Packet {
 
     pub mark: bool,
 
+    /// True if the RTP timestamp was reset just before this packet.
+    ///
+    /// This happens when a camera reboots or a stream is spliced. When
+    /// [`crate::client::PlayOptions::timestamp_reset_threshold_secs`] is
+    /// set and the timestamp jumps backward by more than the threshold, the
+    /// stream's timeline is re-anchored at this packet, so `timestamp` has a
+    /// new `start` and its elapsed time restarts at zero.
+    pub discontinuity: bool,
+
+    /// The raw RTP header, including any CSRC list and header extension.
+    ///
+    /// Empty for packets which weren't received from the network.
+    pub header: Bytes,
+
     /// Guaranteed to be less than u16::MAX bytes.
     pub payload: Bytes,
 }
 
+impl Packet {
+    /// Returns the contributing sources (CSRCs), as set by an RTP mixer.
+    ///
+    /// See [RFC 3550 section
+    /// 5.1](https://datatracker.ietf.org/doc/html/rfc3550#section-5.1). This is
+    /// empty for the usual unmixed stream.
+    pub fn contributing_sources(&self) -> impl ExactSizeIterator<Item = u32> + '_ {
+        let h = &self.header[..];
+        let csrcs = match h.first() {
+            Some(&b) => h.get(12..12 + 4 * usize::from(b & 0x0f)).unwrap_or(&[]),
+            None => &[],
+        };
+        csrcs
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes([c[0], c[1], c[2], c[3]]))
+    }
+
+    /// Returns the raw header extension as `(profile, data)`, if present.
+    ///
+    /// See [RFC 3550 section
+    /// 5.3.1](https://datatracker.ietf.org/doc/html/rfc3550#section-5.3.1).
+    /// `data` excludes the 4-byte extension header.
+    pub fn header_extension(&self) -> Option<(u16, &[u8])> {
+        let h = &self.header[..];
+        if h.len() < 12 || (h[0] & 0x10) == 0 {
+            return None;
+        }
+        let start = 12 + 4 * usize::from(h[0] & 0x0f);
+        let ext = h.get(start..start + 4)?;
+        let profile = u16::from_be_bytes([ext[0], ext[1]]);
+        let len = 4 * usize::from(u16::from_be_bytes([ext[2], ext[3]]));
+        Some((profile, h.get(start + 4..start + 4 + len)?))
+    }
+
+    /// Returns the elements of a one-byte or two-byte header extension, as
+    /// described in [RFC 8285](https://datatracker.ietf.org/doc/html/rfc8285).
+    ///
+    /// Yields nothing if there's no header extension or it uses another
+    /// profile; see [`Packet::header_extension`] for those. Stops at a
+    /// truncated element. Elements' meaning is negotiated via SDP `a=extmap`
+    /// attributes (see [`crate::client::Stream::sdp_attributes`]); this
+    /// doesn't interpret them.
+    pub fn header_extensions(&self) -> HeaderExtensions<'_> {
+        match self.header_extension() {
+            Some((0xbede, data)) => HeaderExtensions {
+                data,
+                two_byte: false,
+            },
+            Some((profile, data)) if (profile & 0xfff0) == 0x1000 => HeaderExtensions {
+                data,
+                two_byte: true,
+            },
+            _ => HeaderExtensions {
+                data: &[],
+                two_byte: false,
+            },
+        }
+    }
+}
+
+/// An element of an [RFC 8285](https://datatracker.ietf.org/doc/html/rfc8285)
+/// RTP header extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct HeaderExtensionElement<'a> {
+    /// The local identifier, as mapped to a URI by an `a=extmap` attribute.
+    pub id: u8,
+    pub data: &'a [u8],
+}
+
+/// Iterator returned by [`Packet::header_extensions`].
+#[derive(Clone, Debug)]
+pub struct HeaderExtensions<'a> {
+    data: &'a [u8],
+    two_byte: bool,
+}
+
+impl<'a> Iterator for HeaderExtensions<'a> {
+    type Item = HeaderExtensionElement<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (&first, rest) = self.data.split_first()?;
+            if first == 0 {
+                // Padding.
+                self.data = rest;
+                continue;
+            }
+            let (id, len, rest) = if self.two_byte {
+                let (&len, rest) = rest.split_first()?;
+                (first, usize::from(len), rest)
+            } else if first >> 4 == 15 {
+                // RFC 8285 section 4.2: id 15 is reserved; stop processing.
+                self.data = &[];
+                return None;
+            } else {
+                (first >> 4, usize::from(first & 0x0f) + 1, rest)
+            };
+            if rest.len() < len {
+                self.data = &[];
+                return None;
+            }
+            let (data, rest) = rest.split_at(len);
+            self.data = rest;
+            return Some(HeaderExtensionElement { id, data });
+        }
+    }
+}
+
 impl std::fmt::Debug for Packet {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("Packet")
@@ -44,11 +169,33 @@ impl std::fmt::Debug for Packet {
             .field("sequence_number", &self.sequence_number)
             .field("loss", &self.loss)
             .field("mark", &self.mark)
+            .field("discontinuity", &self.discontinuity)
+            .field("header", &self.header.hex_dump())
             .field("payload", &self.payload.hex_dump())
             .finish()
     }
 }
 
+/// A received RTP packet, as passed to [`SessionOptions::packet_observer`].
+///
+/// This describes the packet as it arrived, before any reordering, skipping,
+/// or depacketization.
+#[derive(Copy, Clone, Debug)]
+pub struct PacketInfo<'a> {
+    pub ctx: &'a PacketContext,
+    pub stream_id: usize,
+    pub ssrc: u32,
+    pub sequence_number: u16,
+
+    /// The 32-bit RTP timestamp, as in the packet header.
+    pub timestamp: u32,
+    pub mark: bool,
+    pub payload_type: u8,
+
+    /// The packet's size in bytes, including the RTP header.
+    pub len: usize,
+}
+
 /// An RTCP sender report.
 #[derive(Debug)]
 pub struct SenderReport {
@@ -58,11 +205,129 @@ pub struct SenderReport {
     pub ntp_timestamp: crate::NtpTimestamp,
 }
 
+/// Running reception statistics for a stream.
+///
+/// Returned by [`crate::client::Session::stream_stats`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StreamStats {
+    /// Number of RTP packets received with the expected SSRC, including
+    /// reordered and duplicate packets.
+    pub packets_received: u64,
+
+    /// Number of sequence numbers skipped between accepted packets.
+    ///
+    /// A packet which arrives late is counted here as well as in `reordered`.
+    pub packets_lost: u64,
+
+    /// Number of packets which arrived after a later sequence number was
+    /// released and so were skipped; see
+    /// [`crate::client::SessionOptions::reorder_buffer`].
+    pub reordered: u64,
+
+    /// Number of packets which repeated the highest sequence number seen.
+    pub duplicates: u64,
+
+    /// The highest sequence number accepted so far, if any.
+    pub highest_seq: Option<u16>,
+
+    /// Number of packets recovered from the [RFC
+    /// 4588](https://datatracker.ietf.org/doc/html/rfc4588) retransmission
+    /// stream. These aren't included in `packets_received`.
+    pub retransmitted: u64,
+
+    /// Number of UDP RTP packets and RTCP sender reports dropped because they
+    /// didn't match the stream's SSRC; see
+    /// [`crate::client::SessionOptions::strict_ssrc`]. These aren't included
+    /// in `packets_received`.
+    pub wrong_ssrc: u64,
+}
+
+/// Interarrival jitter estimator, as described in [RFC 3550 section
+/// 6.4.1](https://datatracker.ietf.org/doc/html/rfc3550#section-6.4.1).
+#[derive(Debug, Default)]
+struct JitterEstimator {
+    /// The arrival time and timestamp of the previous packet.
+    prev: Option<(std::time::Instant, i64)>,
+
+    /// The jitter scaled by 16, in clock rate units, as in RFC 3550 appendix A.8.
+    scaled_jitter: u64,
+}
+
+impl JitterEstimator {
+    fn update(&mut self, arrival: std::time::Instant, timestamp: crate::Timestamp) {
+        if let Some((prev_arrival, prev_timestamp)) = self.prev {
+            let clock_rate = u128::from(timestamp.clock_rate().get());
+            let elapsed = arrival.saturating_duration_since(prev_arrival).as_nanos();
+            let arrival_delta =
+                i64::try_from(elapsed * clock_rate / 1_000_000_000).unwrap_or(i64::MAX);
+            let d = arrival_delta
+                .saturating_sub(timestamp.timestamp() - prev_timestamp)
+                .unsigned_abs();
+
+            // J(i) = J(i-1) + (|D(i-1,i)| - J(i-1))/16, in fixed point.
+            self.scaled_jitter = self
+                .scaled_jitter
+                .saturating_add(d)
+                .saturating_sub((self.scaled_jitter + 8) >> 4);
+        }
+        self.prev = Some((arrival, timestamp.timestamp()));
+    }
+
+    /// Returns the current jitter estimate in clock rate units.
+    fn jitter(&self) -> u32 {
+        u32::try_from(self.scaled_jitter >> 4).unwrap_or(u32::MAX)
+    }
+}
+
+/// The window over which [`BitrateEstimator`] averages.
+const BITRATE_WINDOW: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// The granularity with which [`BitrateEstimator`] records arrivals.
+const BITRATE_BUCKET: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Estimates the received bitrate over the trailing [`BITRATE_WINDOW`].
+///
+/// Arrivals are coalesced into buckets of [`BITRATE_BUCKET`] to bound memory
+/// regardless of the packet rate.
+#[derive(Debug, Default)]
+struct BitrateEstimator {
+    /// `(bucket start, bytes)`, oldest first.
+    buckets: VecDeque<(std::time::Instant, u64)>,
+}
+
+impl BitrateEstimator {
+    fn update(&mut self, arrival: std::time::Instant, len: usize) {
+        while matches!(self.buckets.front(), Some(&(start, _)) if arrival.saturating_duration_since(start) >= BITRATE_WINDOW)
+        {
+            self.buckets.pop_front();
+        }
+        match self.buckets.back_mut() {
+            Some((start, bytes)) if arrival.saturating_duration_since(*start) < BITRATE_BUCKET => {
+                *bytes += len as u64;
+            }
+            _ => self.buckets.push_back((arrival, len as u64)),
+        }
+    }
+
+    /// Returns the bitrate in bits per second as of `now`.
+    fn bitrate(&self, now: std::time::Instant) -> u32 {
+        let bytes: u64 = self
+            .buckets
+            .iter()
+            .filter(|&&(start, _)| now.saturating_duration_since(start) < BITRATE_WINDOW)
+            .map(|&(_, bytes)| bytes)
+            .sum();
+        let bits = u128::from(bytes) * 8 * 1_000;
+        u32::try_from(bits / BITRATE_WINDOW.as_millis()).unwrap_or(u32::MAX)
+    }
+}
+
 /// RTP/RTCP demarshaller which ensures packets have the correct SSRC and
 /// monotonically increasing SEQ. Unstable; exposed for benchmark.
 ///
-/// When using UDP, skips and logs out-of-order packets. When using TCP,
-/// fails on them.
+/// Reorders packets within the depth set by [`InorderParser::set_reorder_depth`].
+/// When using UDP, skips and logs out-of-order packets beyond that. When using
+/// TCP, fails on them.
 ///
 /// This reports packet loss (via [Packet::loss]) but doesn't prohibit it
 /// of more than `i16::MAX` which would be indistinguishable from non-monotonic sequence numbers.
@@ -81,13 +346,164 @@ pub struct SenderReport {
 pub struct InorderParser {
     ssrc: Option<u32>,
     next_seq: Option<u16>,
+    stats: StreamStats,
+    jitter: JitterEstimator,
+    bitrate: BitrateEstimator,
+
+    /// The timestamp of the most recently accepted RTP packet.
+    last_timestamp: Option<crate::Timestamp>,
+
+    /// The negotiated payload type, if set via [`InorderParser::set_payload_type`].
+    payload_type: Option<u8>,
+
+    /// The payload type of the retransmission stream, if enabled via
+    /// [`InorderParser::set_retransmission`].
+    rtx_payload_type: Option<u8>,
+
+    /// The SSRC of the retransmission stream, once known.
+    rtx_ssrc: Option<u32>,
+
+    /// The number of sequence numbers past a gap for which to hold packets,
+    /// waiting for the gap to be filled. If 0, packets are released immediately.
+    /// Always less than `0x8000`.
+    reorder_depth: u16,
+
+    /// The longest a packet may be held waiting for a gap to be filled, if limited.
+    max_hold: Option<std::time::Duration>,
+
+    /// `held[i]` is the packet with sequence number `next_seq + i`, if received.
+    /// `held[0]`, if present, is always `None`.
+    held: VecDeque<Option<Pending>>,
+
+    /// Sequence numbers skipped since the last released packet.
+    pending_loss: u16,
+
+    /// Packets which have been released in order but not yet returned; see
+    /// [`InorderParser::pull`].
+    ready: VecDeque<Packet>,
 }
 
+/// A validated RTP packet which hasn't yet been released in order.
+#[derive(Debug)]
+struct Pending {
+    ctx: PacketContext,
+    ssrc: u32,
+    sequence_number: u16,
+    timestamp: u32,
+    mark: bool,
+    header: Bytes,
+    payload: Bytes,
+}
+
+/// Returns how far `seq` is ahead of `base`, or `None` if it's behind.
+///
+/// Sequence numbers are compared as 16-bit serial numbers as in [RFC
+/// 1982](https://datatracker.ietf.org/doc/html/rfc1982), so this handles
+/// wraparound: `0x0001` is 2 ahead of `0xffff`. A distance of exactly half the
+/// sequence space is undefined and treated as behind.
+fn seq_ahead(seq: u16, base: u16) -> Option<u16> {
+    let d = seq.wrapping_sub(base);
+    if d < 0x80_00 {
+        Some(d)
+    } else {
+        None
+    }
+}
+
+/// The number of sequence numbers past a gap to wait for a retransmission.
+const RTX_REORDER_DEPTH: u16 = 32;
+
+/// The longest to wait for a retransmission before giving up on a gap.
+const RTX_MAX_HOLD: std::time::Duration = std::time::Duration::from_millis(200);
+
 impl InorderParser {
     pub fn new(ssrc: Option<u32>, next_seq: Option<u16>) -> Self {
-        Self { ssrc, next_seq }
+        Self {
+            ssrc,
+            next_seq,
+            stats: StreamStats::default(),
+            jitter: JitterEstimator::default(),
+            bitrate: BitrateEstimator::default(),
+            last_timestamp: None,
+            payload_type: None,
+            rtx_payload_type: None,
+            rtx_ssrc: None,
+            reorder_depth: 0,
+            max_hold: None,
+            held: VecDeque::new(),
+            pending_loss: 0,
+            ready: VecDeque::new(),
+        }
+    }
+
+    /// Checks that RTP packets have the given payload type (or, if enabled,
+    /// the retransmission payload type), handling others according to
+    /// [`SessionOptions::payload_type_policy`].
+    pub fn set_payload_type(&mut self, payload_type: u8) {
+        self.payload_type = Some(payload_type);
+    }
+
+    /// Holds back up to `depth` packets past a gap in sequence numbers, so
+    /// that packets which arrive out of order are released in order.
+    ///
+    /// Depths of `0x8000` or more are indistinguishable from backward jumps in
+    /// sequence number, so they're reduced to `0x7fff`.
+    pub fn set_reorder_depth(&mut self, depth: u16) {
+        self.reorder_depth = std::cmp::min(depth, 0x7fff);
+    }
+
+    /// Releases packets held past a gap once they've waited `max_hold`,
+    /// even if fewer than the reorder depth of packets have arrived since.
+    pub fn set_max_hold(&mut self, max_hold: std::time::Duration) {
+        self.max_hold = Some(max_hold);
+    }
+
+    /// Enables reception of an [RFC 4588](https://datatracker.ietf.org/doc/html/rfc4588)
+    /// retransmission stream, sent SSRC-multiplexed with the given payload type.
+    ///
+    /// Packets following a gap are held (for at least a fixed number of
+    /// sequence numbers, but by default no longer than a fixed time; see
+    /// [`InorderParser::set_max_hold`]) so that a retransmission can fill it.
+    pub fn set_retransmission(&mut self, payload_type: u8) {
+        self.rtx_payload_type = Some(payload_type);
+        self.reorder_depth = std::cmp::max(self.reorder_depth, RTX_REORDER_DEPTH);
+        self.max_hold.get_or_insert(RTX_MAX_HOLD);
+    }
+
+    /// Returns the SSRC, if known from the `SETUP`/`PLAY` response or a packet.
+    pub fn ssrc(&self) -> Option<u32> {
+        self.ssrc
+    }
+
+    /// Returns the timestamp of the most recently accepted RTP packet, if any.
+    pub fn last_timestamp(&self) -> Option<crate::Timestamp> {
+        self.last_timestamp
+    }
+
+    /// Returns when the most recently accepted RTP packet was received, if known.
+    pub fn last_received(&self) -> Option<std::time::Instant> {
+        self.jitter.prev.map(|(received, _)| received)
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        self.stats
+    }
+
+    /// Returns the interarrival jitter estimate in clock rate units.
+    pub fn jitter(&self) -> u32 {
+        self.jitter.jitter()
+    }
+
+    /// Returns the bitrate in bits per second over the trailing five seconds,
+    /// including RTP headers.
+    pub fn bitrate(&self) -> u32 {
+        self.bitrate.bitrate(std::time::Instant::now())
     }
 
+    /// Handles a received RTP packet, returning the next packet to be released
+    /// in order, if any.
+    ///
+    /// If this returns a packet, more may be available via [`InorderParser::pull`].
     pub fn rtp(
         &mut self,
         session_options: &SessionOptions,
@@ -110,6 +526,21 @@ impl InorderParser {
                 ),
             })
         })?;
+        if let Some(observer) = session_options.packet_observer.as_ref() {
+            observer(&PacketInfo {
+                ctx: pkt_ctx,
+                stream_id,
+                ssrc: reader.ssrc(),
+                sequence_number: u16::from_be_bytes([data[2], data[3]]),
+                timestamp: reader.timestamp(),
+                mark: reader.mark(),
+                payload_type: reader.payload_type(),
+                len: data.len(),
+            });
+        }
+        if let Some(received) = pkt_ctx.received() {
+            self.bitrate.update(received, data.len());
+        }
 
         // Skip pt=50 packets, sent by at least Geovision cameras. I'm not sure
         // what purpose these serve, but they have the same sequence number as
@@ -122,28 +553,55 @@ impl InorderParser {
             return Ok(None);
         }
 
-        let sequence_number = u16::from_be_bytes([data[2], data[3]]); // I don't like rtsp_rs::Seq.
-        let ssrc = reader.ssrc();
-        let loss = sequence_number.wrapping_sub(self.next_seq.unwrap_or(sequence_number));
-        let is_tcp = matches!(session_options.transport, super::Transport::Tcp);
-        if matches!(self.ssrc, Some(s) if s != ssrc) {
-            if let (Some(session_group), true) = (session_options.session_group.as_ref(), is_tcp) {
-                session_group.note_stale_live555_data();
+        let mut sequence_number = u16::from_be_bytes([data[2], data[3]]); // I don't like rtsp_rs::Seq.
+        let mut ssrc = reader.ssrc();
+        let is_rtx = self.rtx_payload_type == Some(reader.payload_type());
+        let is_tcp = session_options.transport.is_interleaved();
+        if !is_rtx && matches!(self.payload_type, Some(pt) if pt != reader.payload_type()) {
+            match session_options.payload_type_policy {
+                PayloadTypePolicy::Skip => {
+                    log::warn!(
+                        "Skipping pkt with unexpected payload type {} (expecting {:?}) \
+                         ssrc={:08x} seq={:04x}",
+                        reader.payload_type(),
+                        self.payload_type,
+                        ssrc,
+                        sequence_number
+                    );
+                    return Ok(None);
+                }
+                PayloadTypePolicy::Error => bail!(ErrorInt::RtpPacketError {
+                    conn_ctx: *conn_ctx,
+                    pkt_ctx: *pkt_ctx,
+                    stream_id,
+                    ssrc,
+                    sequence_number,
+                    description: format!(
+                        "Unexpected payload type {}; expecting {:?} (and retransmission {:?})",
+                        reader.payload_type(),
+                        self.payload_type,
+                        self.rtx_payload_type
+                    ),
+                }),
             }
-            bail!(ErrorInt::RtpPacketError {
+        }
+        let mark = reader.mark();
+        let timestamp = reader.timestamp();
+        let payload_range = crate::as_range(&data, reader.payload()).ok_or_else(|| {
+            wrap!(ErrorInt::RtpPacketError {
                 conn_ctx: *conn_ctx,
                 pkt_ctx: *pkt_ctx,
                 stream_id,
                 ssrc,
                 sequence_number,
-                description: format!(
-                    "Wrong ssrc; expecting ssrc={:08x?} seq={:04x?}",
-                    self.ssrc, self.next_seq
-                ),
-            });
-        }
-        if loss > 0x80_00 {
-            if matches!(session_options.transport, super::Transport::Tcp) {
+                description: "empty payload".into(),
+            })
+        })?;
+        let header = data.slice(..payload_range.start);
+        data.truncate(payload_range.end);
+        data.advance(payload_range.start);
+        if is_rtx {
+            if matches!(self.rtx_ssrc, Some(s) if s != ssrc) {
                 bail!(ErrorInt::RtpPacketError {
                     conn_ctx: *conn_ctx,
                     pkt_ctx: *pkt_ctx,
@@ -151,56 +609,261 @@ impl InorderParser {
                     ssrc,
                     sequence_number,
                     description: format!(
-                        "Out-of-order packet or large loss; expecting ssrc={:08x?} seq={:04x?}",
-                        self.ssrc, self.next_seq
+                        "Wrong retransmission ssrc; expecting ssrc={:08x?}",
+                        self.rtx_ssrc
                     ),
                 });
-            } else {
+            }
+            let primary_ssrc = match self.ssrc {
+                Some(s) if data.len() > 2 => s,
+                _ => {
+                    debug!(
+                        "skipping {}-byte retransmission pkt ssrc={:08x} seq={:04x}",
+                        data.len(),
+                        ssrc,
+                        sequence_number
+                    );
+                    return Ok(None);
+                }
+            };
+            self.rtx_ssrc = Some(ssrc);
+
+            // RFC 4588 section 4: the payload starts with the original
+            // sequence number; the remainder is the original payload.
+            sequence_number = data.get_u16();
+            ssrc = primary_ssrc;
+        } else if session_options.strict_ssrc && matches!(self.ssrc, Some(s) if s != ssrc) {
+            if !is_tcp {
+                // Likely a stray packet on a shared port; drop it.
+                self.stats.wrong_ssrc += 1;
                 log::info!(
-                    "Skipping out-of-order seq={:04x} when expecting ssrc={:08x?} seq={:04x?}",
+                    "Skipping pkt with ssrc={:08x} seq={:04x} when expecting ssrc={:08x?}",
+                    ssrc,
                     sequence_number,
-                    self.ssrc,
-                    self.next_seq
+                    self.ssrc
                 );
                 return Ok(None);
             }
-        }
-        let timestamp = match timeline.advance_to(reader.timestamp()) {
-            Ok(ts) => ts,
-            Err(description) => bail!(ErrorInt::RtpPacketError {
+            if let Some(session_group) = session_options.session_group.as_ref() {
+                session_group.note_stale_live555_data();
+            }
+            bail!(ErrorInt::RtpPacketError {
                 conn_ctx: *conn_ctx,
                 pkt_ctx: *pkt_ctx,
                 stream_id,
                 ssrc,
                 sequence_number,
-                description,
-            }),
+                description: format!(
+                    "Wrong ssrc; expecting ssrc={:08x?} seq={:04x?}",
+                    self.ssrc, self.next_seq
+                ),
+            });
+        } else {
+            self.stats.packets_received += 1;
+        }
+        let next_seq = *self.next_seq.get_or_insert(sequence_number);
+        let offset = match seq_ahead(sequence_number, next_seq) {
+            Some(offset) => offset,
+            None => {
+                if is_rtx {
+                    debug!(
+                        "skipping late retransmission of seq={:04x} when expecting seq={:04x}",
+                        sequence_number, next_seq
+                    );
+                    return Ok(None);
+                }
+                if self.stats.highest_seq == Some(sequence_number) {
+                    self.stats.duplicates += 1;
+                } else {
+                    self.stats.reordered += 1;
+                }
+                if is_tcp {
+                    bail!(ErrorInt::RtpPacketError {
+                        conn_ctx: *conn_ctx,
+                        pkt_ctx: *pkt_ctx,
+                        stream_id,
+                        ssrc,
+                        sequence_number,
+                        description: format!(
+                            "Out-of-order packet or large loss; expecting ssrc={:08x?} seq={:04x?}",
+                            self.ssrc, self.next_seq
+                        ),
+                    });
+                } else {
+                    log::info!(
+                        "Skipping out-of-order seq={:04x} when expecting ssrc={:08x?} seq={:04x?}",
+                        sequence_number,
+                        self.ssrc,
+                        self.next_seq
+                    );
+                    return Ok(None);
+                }
+            }
         };
         self.ssrc = Some(ssrc);
-        let mark = reader.mark();
-        let payload_range = crate::as_range(&data, reader.payload()).ok_or_else(|| {
-            wrap!(ErrorInt::RtpPacketError {
-                conn_ctx: *conn_ctx,
-                pkt_ctx: *pkt_ctx,
-                stream_id,
-                ssrc,
-                sequence_number,
-                description: "empty payload".into(),
-            })
-        })?;
-        data.truncate(payload_range.end);
-        data.advance(payload_range.start);
-        self.next_seq = Some(sequence_number.wrapping_add(1));
-        Ok(Some(PacketItem::RtpPacket(Packet {
+        let pending = Pending {
             ctx: *pkt_ctx,
-            stream_id,
-            timestamp,
             ssrc,
             sequence_number,
-            loss,
+            timestamp,
             mark,
+            header,
             payload: data,
-        })))
+        };
+        if usize::from(offset) < self.held.len() {
+            let slot = &mut self.held[usize::from(offset)];
+            if slot.is_some() {
+                self.stats.duplicates += 1;
+                return Ok(None);
+            }
+            *slot = Some(pending);
+        } else {
+            // Make room by advancing past the oldest held sequence numbers.
+            let skip = offset.saturating_sub(self.reorder_depth);
+            self.release(skip, conn_ctx, timeline, stream_id)?;
+            let offset = sequence_number.wrapping_sub(self.next_seq.expect("next_seq is set"));
+            self.held.resize_with(usize::from(offset), || None);
+            self.held.push_back(Some(pending));
+        }
+        if is_rtx {
+            self.stats.retransmitted += 1;
+        }
+        self.release_expired(pkt_ctx.received(), conn_ctx, timeline, stream_id)?;
+        self.release(0, conn_ctx, timeline, stream_id)?;
+        Ok(self.pull())
+    }
+
+    /// Releases all held packets, reporting any remaining gaps as loss.
+    ///
+    /// This should be called when no more packets will arrive, such as at
+    /// the end of the session. Released packets are available via
+    /// [`InorderParser::pull`].
+    pub fn flush(
+        &mut self,
+        conn_ctx: &ConnectionContext,
+        timeline: &mut Timeline,
+        stream_id: usize,
+    ) -> Result<(), Error> {
+        let len = self.held.len();
+        if len == 0 {
+            return Ok(());
+        }
+
+        // `held` is never longer than `reorder_depth + 1`, which fits.
+        let len = u16::try_from(len).expect("held packets fit in u16");
+        self.release(len, conn_ctx, timeline, stream_id)
+    }
+
+    /// Gives up on gaps before any packet held longer than `max_hold` as of `now`.
+    fn release_expired(
+        &mut self,
+        now: Option<std::time::Instant>,
+        conn_ctx: &ConnectionContext,
+        timeline: &mut Timeline,
+        stream_id: usize,
+    ) -> Result<(), Error> {
+        let (max_hold, now) = match (self.max_hold, now) {
+            (Some(m), Some(n)) => (m, n),
+            _ => return Ok(()),
+        };
+        let expired = self.held.iter().rposition(|p| {
+            matches!(p.as_ref().and_then(|p| p.ctx.received()),
+                     Some(r) if now.saturating_duration_since(r) >= max_hold)
+        });
+        if let Some(i) = expired {
+            let i = u16::try_from(i).expect("held packets fit in u16");
+            debug!(
+                "stream {}: giving up on gap after holding seq={:04x} for {:?}",
+                stream_id,
+                self.next_seq.expect("next_seq is set").wrapping_add(i),
+                max_hold
+            );
+            self.release(i, conn_ctx, timeline, stream_id)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the next packet which has been released in order, if any.
+    pub fn pull(&mut self) -> Option<PacketItem> {
+        self.ready.pop_front().map(PacketItem::RtpPacket)
+    }
+
+    /// Releases held packets in order: first advancing past `skip` sequence
+    /// numbers regardless, then through any contiguous received packets.
+    fn release(
+        &mut self,
+        mut skip: u16,
+        conn_ctx: &ConnectionContext,
+        timeline: &mut Timeline,
+        stream_id: usize,
+    ) -> Result<(), Error> {
+        loop {
+            let next_seq = self.next_seq.expect("next_seq is set before release");
+            if self.held.is_empty() && skip > 0 {
+                self.pending_loss = self.pending_loss.saturating_add(skip);
+                self.next_seq = Some(next_seq.wrapping_add(skip));
+                return Ok(());
+            }
+            if skip == 0 && !matches!(self.held.front(), Some(Some(_))) {
+                return Ok(());
+            }
+            skip = skip.saturating_sub(1);
+            self.next_seq = Some(next_seq.wrapping_add(1));
+            match self.held.pop_front().flatten() {
+                None => self.pending_loss = self.pending_loss.saturating_add(1),
+                Some(p) => self.emit(p, conn_ctx, timeline, stream_id)?,
+            }
+        }
+    }
+
+    /// Places a released packet on the timeline and adds it to `ready`.
+    fn emit(
+        &mut self,
+        p: Pending,
+        conn_ctx: &ConnectionContext,
+        timeline: &mut Timeline,
+        stream_id: usize,
+    ) -> Result<(), Error> {
+        let timestamp = match timeline.advance_to(p.timestamp) {
+            Ok(ts) => ts,
+            Err(description) => bail!(ErrorInt::RtpPacketError {
+                conn_ctx: *conn_ctx,
+                pkt_ctx: p.ctx,
+                stream_id,
+                ssrc: p.ssrc,
+                sequence_number: p.sequence_number,
+                description,
+            }),
+        };
+        let discontinuity = timeline.take_reset();
+        if discontinuity {
+            log::warn!(
+                "Stream {} RTP timestamp reset to {} at seq {:04x}; re-anchored timeline",
+                stream_id,
+                p.timestamp,
+                p.sequence_number
+            );
+        }
+        let loss = std::mem::take(&mut self.pending_loss);
+        self.stats.packets_lost += u64::from(loss);
+        self.stats.highest_seq = Some(p.sequence_number);
+        self.last_timestamp = Some(timestamp);
+        if let Some(received) = p.ctx.received() {
+            self.jitter.update(received, timestamp);
+        }
+        self.ready.push_back(Packet {
+            ctx: p.ctx,
+            stream_id,
+            timestamp,
+            ssrc: p.ssrc,
+            sequence_number: p.sequence_number,
+            loss,
+            mark: p.mark,
+            discontinuity,
+            header: p.header,
+            payload: p.payload,
+        });
+        Ok(())
     }
 
     pub fn rtcp(
@@ -231,12 +894,19 @@ impl InorderParser {
                             })?;
 
                     let ssrc = pkt.ssrc();
-                    if matches!(self.ssrc, Some(s) if s != ssrc) {
-                        use super::Transport;
-                        if let (Some(session_group), Transport::Tcp) = (
-                            session_options.session_group.as_ref(),
-                            session_options.transport,
-                        ) {
+                    let is_tcp = session_options.transport.is_interleaved();
+                    if session_options.strict_ssrc && matches!(self.ssrc, Some(s) if s != ssrc) {
+                        if !is_tcp {
+                            self.stats.wrong_ssrc += 1;
+                            log::info!(
+                                "Skipping RTCP SR with ssrc={:08x} when expecting ssrc={:08x?}",
+                                ssrc,
+                                self.ssrc
+                            );
+                            i += 1;
+                            continue;
+                        }
+                        if let Some(session_group) = session_options.session_group.as_ref() {
                             session_group.note_stale_live555_data();
                         }
                         return Err(format!(
@@ -263,6 +933,8 @@ impl InorderParser {
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroU32;
+
     use super::*;
 
     /// Checks dropping and logging Geovision's extra payload type 50 packets.
@@ -319,6 +991,94 @@ mod tests {
         }
     }
 
+    #[test]
+    fn strict_ssrc() {
+        let pkt = |ssrc: u32, seq: u16| -> Bytes {
+            rtp_rs::RtpPacketBuilder::new()
+                .payload_type(96)
+                .ssrc(ssrc)
+                .sequence(seq.into())
+                .timestamp(u32::from(seq))
+                .marked(true)
+                .payload(b"pkt")
+                .build()
+                .unwrap()
+                .into()
+        };
+        for strict in [true, false] {
+            let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+            let mut parser = InorderParser::new(None, None);
+            let session_options = SessionOptions::default()
+                .transport(crate::client::Transport::Udp)
+                .strict_ssrc(strict);
+            let mut accepted = Vec::new();
+            for (ssrc, seq) in [(0xd25614e, 1), (0x1234, 2), (0xd25614e, 3)] {
+                if let Some(PacketItem::RtpPacket(p)) = parser
+                    .rtp(
+                        &session_options,
+                        &ConnectionContext::dummy(),
+                        &PacketContext::dummy(),
+                        &mut timeline,
+                        0,
+                        pkt(ssrc, seq),
+                    )
+                    .unwrap()
+                {
+                    accepted.push((p.ssrc, p.sequence_number));
+                }
+            }
+            if strict {
+                // The first packet locks the SSRC.
+                assert_eq!(accepted, [(0xd25614e, 1), (0xd25614e, 3)]);
+                assert_eq!(parser.stats().wrong_ssrc, 1);
+            } else {
+                assert_eq!(accepted, [(0xd25614e, 1), (0x1234, 2), (0xd25614e, 3)]);
+                assert_eq!(parser.stats().wrong_ssrc, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn unexpected_payload_type() {
+        let pkt = |pt: u8| -> Bytes {
+            rtp_rs::RtpPacketBuilder::new()
+                .payload_type(pt)
+                .ssrc(0xd25614e)
+                .sequence(1.into())
+                .timestamp(1)
+                .marked(true)
+                .payload(b"pkt")
+                .build()
+                .unwrap()
+                .into()
+        };
+        for policy in [PayloadTypePolicy::Skip, PayloadTypePolicy::Error] {
+            let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+            let mut parser = InorderParser::new(Some(0xd25614e), None);
+            parser.set_payload_type(96);
+            let session_options = SessionOptions::default().payload_type_policy(policy);
+            let r = parser.rtp(
+                &session_options,
+                &ConnectionContext::dummy(),
+                &PacketContext::dummy(),
+                &mut timeline,
+                0,
+                pkt(97),
+            );
+            match (policy, r) {
+                (PayloadTypePolicy::Skip, Ok(None)) => {}
+                (PayloadTypePolicy::Error, Err(e)) => {
+                    let e = e.to_string();
+                    assert!(e.contains("Unexpected payload type 97"), "{}", e);
+                }
+                (p, r) => panic!("unexpected {} result: {:#?}", p, r),
+            }
+            assert_eq!(parser.stats().packets_received, 0);
+        }
+        assert!(matches!("error".parse(), Ok(PayloadTypePolicy::Error)));
+        "bogus".parse::<PayloadTypePolicy>().unwrap_err();
+    }
+
     #[test]
     fn out_of_order() {
         let mut timeline = Timeline::new(None, 90_000, None).unwrap();
@@ -393,4 +1153,514 @@ mod tests {
             o => panic!("unexpected packet 2 result: {:#?}", o),
         }
     }
+
+    #[test]
+    fn stats() {
+        let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+        let mut parser = InorderParser::new(Some(0xd25614e), None);
+        let session_options = SessionOptions::default().transport(crate::client::Transport::Udp);
+        for (seq, accepted) in [(1u16, true), (3, true), (2, false), (3, false), (5, true)] {
+            let r = parser.rtp(
+                &session_options,
+                &ConnectionContext::dummy(),
+                &PacketContext::dummy(),
+                &mut timeline,
+                0,
+                rtp_rs::RtpPacketBuilder::new()
+                    .payload_type(96)
+                    .ssrc(0xd25614e)
+                    .sequence(seq.into())
+                    .timestamp(u32::from(seq))
+                    .marked(true)
+                    .payload(b"pkt")
+                    .build()
+                    .unwrap()
+                    .into(),
+            );
+            match r {
+                Ok(Some(PacketItem::RtpPacket(_))) if accepted => {}
+                Ok(None) if !accepted => {}
+                o => panic!("unexpected packet {} result: {:#?}", seq, o),
+            }
+        }
+        assert_eq!(
+            parser.stats(),
+            StreamStats {
+                packets_received: 5,
+                packets_lost: 2,
+                reordered: 1,
+                duplicates: 1,
+                highest_seq: Some(5),
+                retransmitted: 0,
+                wrong_ssrc: 0,
+            }
+        );
+    }
+
+    fn parse(session_options: &SessionOptions, data: &'static [u8]) -> Packet {
+        let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+        let mut parser = InorderParser::new(None, None);
+        match parser.rtp(
+            session_options,
+            &ConnectionContext::dummy(),
+            &PacketContext::dummy(),
+            &mut timeline,
+            0,
+            Bytes::from_static(data),
+        ) {
+            Ok(Some(PacketItem::RtpPacket(p))) => p,
+            o => panic!("unexpected result: {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn packet_observer() {
+        let seen = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen_clone = seen.clone();
+        let session_options = SessionOptions::default().packet_observer(std::sync::Arc::new(
+            move |p: &PacketInfo| {
+                seen_clone.lock().unwrap().push((
+                    p.stream_id,
+                    p.ssrc,
+                    p.sequence_number,
+                    p.timestamp,
+                    p.mark,
+                    p.payload_type,
+                    p.len,
+                ))
+            },
+        ));
+        let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+        let mut parser = InorderParser::new(None, None);
+        for (stream_id, pkt) in [
+            (1, &b"\x80\x60\x12\x34\x00\x00\x00\x01\xdc\xc4\xa0\xd8a"[..]),
+            (
+                1,
+                &b"\x80\xe0\x12\x35\x00\x00\x00\x02\xdc\xc4\xa0\xd8bc"[..],
+            ),
+        ] {
+            parser
+                .rtp(
+                    &session_options,
+                    &ConnectionContext::dummy(),
+                    &PacketContext::dummy(),
+                    &mut timeline,
+                    stream_id,
+                    Bytes::from_static(pkt),
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            &seen.lock().unwrap()[..],
+            &[
+                (1, 0xdcc4_a0d8, 0x1234, 1, false, 96, 13),
+                (1, 0xdcc4_a0d8, 0x1235, 2, true, 96, 14),
+            ]
+        );
+    }
+
+    #[test]
+    fn contributing_sources() {
+        let p = parse(
+            &SessionOptions::default(),
+            b"\x82\x60\x00\x01\x00\x00\x00\x00\xdc\xc4\xa0\xd8\
+              \x00\x00\x00\x01\x12\x34\x56\x78\
+              hello",
+        );
+        assert_eq!(
+            p.contributing_sources().collect::<Vec<_>>(),
+            [0x0000_0001, 0x1234_5678]
+        );
+        assert_eq!(p.ssrc, 0xdcc4_a0d8);
+        assert_eq!(&p.payload[..], b"hello");
+
+        // The header extension follows the CSRC list.
+        let p = parse(
+            &SessionOptions::default(),
+            b"\x91\x60\x00\x01\x00\x00\x00\x00\xdc\xc4\xa0\xd8\
+              \x00\x00\x00\x01\
+              \xbe\xde\x00\x01\x10\xaa\x00\x00\
+              hello",
+        );
+        assert_eq!(p.contributing_sources().len(), 1);
+        assert_eq!(
+            p.header_extensions().collect::<Vec<_>>(),
+            [HeaderExtensionElement {
+                id: 1,
+                data: b"\xaa"
+            }]
+        );
+        assert_eq!(&p.payload[..], b"hello");
+    }
+
+    #[test]
+    fn one_byte_header_extension() {
+        let p = parse(
+            &SessionOptions::default(),
+            b"\x90\x60\x00\x01\x00\x00\x00\x00\xdc\xc4\xa0\xd8\
+              \xbe\xde\x00\x02\
+              \x10\xaa\x00\x22\x01\x02\x03\x00\
+              hello",
+        );
+        assert_eq!(p.header_extension().unwrap().0, 0xbede);
+        assert_eq!(
+            p.header_extensions().collect::<Vec<_>>(),
+            [
+                HeaderExtensionElement {
+                    id: 1,
+                    data: b"\xaa"
+                },
+                HeaderExtensionElement {
+                    id: 2,
+                    data: b"\x01\x02\x03"
+                },
+            ]
+        );
+        assert_eq!(&p.payload[..], b"hello");
+    }
+
+    #[test]
+    fn two_byte_header_extension() {
+        let p = parse(
+            &SessionOptions::default(),
+            b"\x90\x60\x00\x01\x00\x00\x00\x00\xdc\xc4\xa0\xd8\
+              \x10\x00\x00\x02\
+              \x01\x00\x20\x03\x01\x02\x03\x00\
+              hello",
+        );
+        assert_eq!(
+            p.header_extensions().collect::<Vec<_>>(),
+            [
+                HeaderExtensionElement { id: 1, data: b"" },
+                HeaderExtensionElement {
+                    id: 0x20,
+                    data: b"\x01\x02\x03"
+                },
+            ]
+        );
+        assert_eq!(&p.payload[..], b"hello");
+
+        // Other profiles are available only in raw form.
+        let p = parse(
+            &SessionOptions::default(),
+            b"\x90\x60\x00\x01\x00\x00\x00\x00\xdc\xc4\xa0\xd8\
+              \xab\xcd\x00\x01\x01\x02\x03\x04\
+              hello",
+        );
+        assert_eq!(
+            p.header_extension(),
+            Some((0xabcd, &b"\x01\x02\x03\x04"[..]))
+        );
+        assert_eq!(p.header_extensions().count(), 0);
+    }
+
+    /// Checks loss counting across the sequence number (and timestamp) wrap.
+    #[test]
+    fn seq_wraparound() {
+        assert_eq!(seq_ahead(0x0001, 0xffff), Some(2));
+        assert_eq!(seq_ahead(0xffff, 0x0001), None);
+        assert_eq!(seq_ahead(0x7fff, 0x0000), Some(0x7fff));
+        assert_eq!(seq_ahead(0x8000, 0x0000), None);
+
+        let mut timeline = Timeline::new(None, 90_000, NonZeroU32::new(10)).unwrap();
+        let mut parser = InorderParser::new(Some(0xd25614e), Some(0xfffd));
+        let session_options = SessionOptions::default().transport(crate::client::Transport::Udp);
+        let mut losses = Vec::new();
+        for seq in [0xfffdu16, 0xfffe, 0xffff, 0x0000, 0x0001, 0x0004, 0x0005] {
+            match parser.rtp(
+                &session_options,
+                &ConnectionContext::dummy(),
+                &PacketContext::dummy(),
+                &mut timeline,
+                0,
+                rtp_rs::RtpPacketBuilder::new()
+                    .payload_type(96)
+                    .ssrc(0xd25614e)
+                    .sequence(seq.into())
+                    .timestamp(
+                        (u32::MAX - 3_000)
+                            .wrapping_add(1_000 * u32::from(seq.wrapping_sub(0xfffd))),
+                    )
+                    .marked(true)
+                    .payload(b"pkt")
+                    .build()
+                    .unwrap()
+                    .into(),
+            ) {
+                Ok(Some(PacketItem::RtpPacket(p))) => {
+                    assert_eq!(p.sequence_number, seq);
+                    losses.push(p.loss);
+                }
+                o => panic!("unexpected packet {:04x} result: {:#?}", seq, o),
+            }
+        }
+        assert_eq!(losses, [0, 0, 0, 0, 0, 2, 0]);
+        assert_eq!(parser.stats().packets_lost, 2);
+        assert_eq!(parser.stats().highest_seq, Some(0x0005));
+    }
+
+    #[test]
+    fn reorder() {
+        let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+        let mut parser = InorderParser::new(Some(0xd25614e), None);
+        parser.set_reorder_depth(2);
+        let session_options = SessionOptions::default().transport(crate::client::Transport::Udp);
+        let mut released = Vec::new();
+        for seq in [0xfffeu16, 0x0000, 0xffff, 0x0001, 0x0003, 0x0004, 0x0005] {
+            let mut r = parser
+                .rtp(
+                    &session_options,
+                    &ConnectionContext::dummy(),
+                    &PacketContext::dummy(),
+                    &mut timeline,
+                    0,
+                    rtp_rs::RtpPacketBuilder::new()
+                        .payload_type(96)
+                        .ssrc(0xd25614e)
+                        .sequence(seq.into())
+                        .timestamp(u32::from(seq.wrapping_add(2)))
+                        .marked(true)
+                        .payload(b"pkt")
+                        .build()
+                        .unwrap()
+                        .into(),
+                )
+                .unwrap();
+            while let Some(PacketItem::RtpPacket(p)) = r {
+                released.push((seq, p.sequence_number, p.loss));
+                r = parser.pull();
+            }
+        }
+
+        // (pushed seq, released seq, loss). 0x0002 is never received; it's
+        // reported as loss once 0x0005 exceeds the depth.
+        assert_eq!(
+            released,
+            [
+                (0xfffe, 0xfffe, 0),
+                (0xffff, 0xffff, 0),
+                (0xffff, 0x0000, 0),
+                (0x0001, 0x0001, 0),
+                (0x0005, 0x0003, 1),
+                (0x0005, 0x0004, 0),
+                (0x0005, 0x0005, 0),
+            ]
+        );
+        let stats = parser.stats();
+        assert_eq!(stats.packets_received, 7);
+        assert_eq!(stats.packets_lost, 1);
+        assert_eq!(stats.reordered, 0);
+    }
+
+    /// Checks that a packet recovered from the retransmission stream fills a gap.
+    #[test]
+    fn retransmission() {
+        let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+        let mut parser = InorderParser::new(Some(0xd25614e), None);
+        parser.set_retransmission(97);
+        let session_options = SessionOptions::default().transport(crate::client::Transport::Udp);
+        let mut push = |pt: u8, ssrc: u32, seq: u16, payload: &[u8]| {
+            parser
+                .rtp(
+                    &session_options,
+                    &ConnectionContext::dummy(),
+                    &PacketContext::dummy(),
+                    &mut timeline,
+                    0,
+                    rtp_rs::RtpPacketBuilder::new()
+                        .payload_type(pt)
+                        .ssrc(ssrc)
+                        .sequence(seq.into())
+                        .timestamp(u32::from(seq))
+                        .marked(true)
+                        .payload(payload)
+                        .build()
+                        .unwrap()
+                        .into(),
+                )
+                .unwrap()
+        };
+        assert!(matches!(
+            push(96, 0xd25614e, 1, b"pkt 1"),
+            Some(PacketItem::RtpPacket(_))
+        ));
+
+        // seq 2 is missing, so seq 3 is held.
+        assert!(push(96, 0xd25614e, 3, b"pkt 3").is_none());
+
+        // The retransmission has its own SSRC and sequence number, and its
+        // payload starts with the original sequence number.
+        match push(97, 0x1234, 0x5000, b"\x00\x02pkt 2") {
+            Some(PacketItem::RtpPacket(p)) => {
+                assert_eq!(p.sequence_number, 2);
+                assert_eq!(p.ssrc, 0xd25614e);
+                assert_eq!(p.loss, 0);
+                assert_eq!(&p.payload[..], b"pkt 2");
+            }
+            o => panic!("unexpected retransmission result: {:#?}", o),
+        }
+        match parser.pull() {
+            Some(PacketItem::RtpPacket(p)) => {
+                assert_eq!(p.sequence_number, 3);
+                assert_eq!(p.loss, 0);
+                assert_eq!(p.timestamp.elapsed(), 2);
+            }
+            o => panic!("unexpected pull result: {:#?}", o),
+        }
+        assert!(parser.pull().is_none());
+        let stats = parser.stats();
+        assert_eq!(stats.packets_lost, 0);
+        assert_eq!(stats.retransmitted, 1);
+    }
+
+    /// Pushes a packet received via UDP at `received`, returning the
+    /// `(sequence number, loss)` of each packet released.
+    fn push_at(
+        parser: &mut InorderParser,
+        timeline: &mut Timeline,
+        seq: u16,
+        received: std::time::Instant,
+    ) -> Vec<(u16, u16)> {
+        let pkt_ctx = PacketContext(crate::PacketContextInner::Udp {
+            local_addr: "192.168.5.1:50000".parse().unwrap(),
+            peer_addr: "192.168.5.206:6970".parse().unwrap(),
+            received_wall: crate::WallTime::now(),
+            received,
+        });
+        let mut r = parser
+            .rtp(
+                &SessionOptions::default().transport(crate::client::Transport::Udp),
+                &ConnectionContext::dummy(),
+                &pkt_ctx,
+                timeline,
+                0,
+                rtp_rs::RtpPacketBuilder::new()
+                    .payload_type(96)
+                    .ssrc(0xd25614e)
+                    .sequence(seq.into())
+                    .timestamp(u32::from(seq))
+                    .payload(b"pkt")
+                    .build()
+                    .unwrap()
+                    .into(),
+            )
+            .unwrap();
+        let mut released = Vec::new();
+        while let Some(PacketItem::RtpPacket(p)) = r {
+            released.push((p.sequence_number, p.loss));
+            r = parser.pull();
+        }
+        released
+    }
+
+    /// Checks that a gap awaiting retransmission is given up on once a packet
+    /// has been held too long, and that held packets are released on flush.
+    #[test]
+    fn max_hold() {
+        let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+        let mut parser = InorderParser::new(Some(0xd25614e), None);
+        parser.set_retransmission(97);
+        let start = std::time::Instant::now();
+        let t = &mut timeline;
+        assert_eq!(push_at(&mut parser, t, 1, start), [(1, 0)]);
+        assert!(push_at(&mut parser, t, 3, start).is_empty());
+        let later = start + std::time::Duration::from_millis(100);
+        assert!(push_at(&mut parser, t, 4, later).is_empty());
+        let expired = start + RTX_MAX_HOLD;
+        assert_eq!(
+            push_at(&mut parser, t, 5, expired),
+            [(3, 1), (4, 0), (5, 0)]
+        );
+
+        // Another gap, still within the hold time when the session ends.
+        assert!(push_at(&mut parser, t, 7, expired).is_empty());
+        parser.flush(&ConnectionContext::dummy(), t, 0).unwrap();
+        match parser.pull() {
+            Some(PacketItem::RtpPacket(p)) => {
+                assert_eq!(p.sequence_number, 7);
+                assert_eq!(p.loss, 1);
+            }
+            o => panic!("unexpected pull result: {:#?}", o),
+        }
+        assert!(parser.pull().is_none());
+        assert_eq!(parser.stats().packets_lost, 2);
+
+        // Excessive depths are limited to half the sequence space.
+        parser.set_reorder_depth(u16::MAX);
+        assert_eq!(parser.reorder_depth, 0x7fff);
+    }
+
+    /// Checks that a reorder buffer releases held packets after the max hold
+    /// time, even before its depth is exceeded.
+    #[test]
+    fn reorder_max_hold() {
+        let mut timeline = Timeline::new(None, 90_000, None).unwrap();
+        let mut parser = InorderParser::new(Some(0xd25614e), None);
+        parser.set_reorder_depth(100);
+        parser.set_max_hold(std::time::Duration::from_millis(50));
+        let start = std::time::Instant::now();
+        let t = &mut timeline;
+        assert_eq!(push_at(&mut parser, t, 1, start), [(1, 0)]);
+        assert!(push_at(&mut parser, t, 3, start).is_empty());
+        let later = start + std::time::Duration::from_millis(49);
+        assert!(push_at(&mut parser, t, 4, later).is_empty());
+        let expired = start + std::time::Duration::from_millis(50);
+        assert_eq!(
+            push_at(&mut parser, t, 5, expired),
+            [(3, 1), (4, 0), (5, 0)]
+        );
+
+        // A configured hold time also applies to retransmissions.
+        parser.set_retransmission(97);
+        assert_eq!(parser.max_hold, Some(std::time::Duration::from_millis(50)));
+    }
+
+    /// Checks that the jitter estimate converges when packets are sent every
+    /// 20 ms but arrive alternately 10 ms and 30 ms apart.
+    #[test]
+    fn jitter() {
+        let clock_rate = std::num::NonZeroU32::new(90_000).unwrap();
+        let mut estimator = JitterEstimator::default();
+        let start = std::time::Instant::now();
+        let mut arrival = start;
+        for i in 0..500 {
+            let timestamp = crate::Timestamp::new(i * 1_800, clock_rate, 0).unwrap();
+            estimator.update(arrival, timestamp);
+            arrival += std::time::Duration::from_millis(if i % 2 == 0 { 10 } else { 30 });
+        }
+
+        // Each packet's transit time differs from the previous by 10 ms, or
+        // 900 clock units.
+        let jitter = estimator.jitter();
+        assert!((899..=900).contains(&jitter), "jitter={}", jitter);
+    }
+
+    /// Checks the bitrate of 1,250-byte packets every 10 ms, then that it
+    /// decays once packets stop.
+    #[test]
+    fn bitrate() {
+        let mut estimator = BitrateEstimator::default();
+        let start = std::time::Instant::now();
+        let mut arrival = start;
+        for _ in 0..1_000 {
+            estimator.update(arrival, 1_250);
+            arrival += std::time::Duration::from_millis(10);
+        }
+
+        // 125,000 bytes/sec is 1 Mbps.
+        let bitrate = estimator.bitrate(arrival);
+        assert!(
+            (950_000..=1_000_000).contains(&bitrate),
+            "bitrate={}",
+            bitrate
+        );
+        assert!(estimator.buckets.len() <= 51, "{}", estimator.buckets.len());
+        let bitrate = estimator.bitrate(arrival + std::time::Duration::from_millis(2_500));
+        assert!(
+            (450_000..=500_000).contains(&bitrate),
+            "bitrate={}",
+            bitrate
+        );
+        assert_eq!(estimator.bitrate(arrival + BITRATE_WINDOW), 0);
+    }
 }
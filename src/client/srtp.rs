@@ -0,0 +1,544 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! SRTP and SRTCP decryption, as described in
+//! [RFC 3711](https://datatracker.ietf.org/doc/html/rfc3711), plus SRTCP
+//! encryption of outgoing RTCP feedback.
+//!
+//! Only the `AES_CM_128_HMAC_SHA1_80` crypto suite is supported, keyed via an
+//! SDP `a=crypto` attribute as in
+//! [RFC 4568](https://datatracker.ietf.org/doc/html/rfc4568). DTLS-SRTP
+//! keying, master key identifiers (MKIs), and non-zero key derivation rates
+//! aren't supported. Replayed packets are rejected via a 64-packet window as
+//! in [RFC 3711 section 3.3.2](https://datatracker.ietf.org/doc/html/rfc3711#section-3.3.2).
+
+use aes::cipher::generic_array::GenericArray;
+use bytes::Bytes;
+use ctr::cipher::{NewCipher, StreamCipher};
+use hmac::{Hmac, Mac, NewMac};
+use sha1::Sha1;
+
+use super::channel_mapping::ChannelType;
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+/// The only supported crypto suite.
+const SUITE: &str = "AES_CM_128_HMAC_SHA1_80";
+
+const MASTER_KEY_LEN: usize = 16;
+const MASTER_SALT_LEN: usize = 14;
+const AUTH_KEY_LEN: usize = 20;
+const AUTH_TAG_LEN: usize = 10;
+
+/// The length of the SRTCP `E` flag and index which precede the tag.
+const SRTCP_INDEX_LEN: usize = 4;
+
+/// The number of packets before the highest index received which can still
+/// be accepted, as recommended in
+/// [RFC 3711 section 3.3.2](https://datatracker.ietf.org/doc/html/rfc3711#section-3.3.2).
+const REPLAY_WINDOW_LEN: u64 = 64;
+
+/// Unprotects `data` received on a stream's RTP or RTCP channel, if the
+/// stream uses SRTP. Returns `None` if `data` fails authentication and so
+/// should be discarded, as in
+/// [RFC 3711 section 3.3](https://datatracker.ietf.org/doc/html/rfc3711#section-3.3).
+pub(super) fn unprotect(
+    srtp: &mut Option<Result<Context, String>>,
+    stream_id: usize,
+    channel_type: ChannelType,
+    data: Bytes,
+) -> Option<Bytes> {
+    let ctx = match srtp {
+        Some(Ok(ctx)) => ctx,
+        _ => return Some(data),
+    };
+    let result = match channel_type {
+        ChannelType::Rtp => ctx.unprotect_rtp(data),
+        ChannelType::Rtcp => ctx.unprotect_rtcp(data),
+    };
+    match result {
+        Ok(data) => Some(data),
+        Err(e) => {
+            log::warn!("Discarding stream {} packet: {}", stream_id, e);
+            None
+        }
+    }
+}
+
+/// Parses the value of an SDP `a=crypto` attribute, as in
+/// [RFC 4568 section 9.1](https://datatracker.ietf.org/doc/html/rfc4568#section-9.1).
+///
+/// Eg `1 AES_CM_128_HMAC_SHA1_80 inline:WVNfX19zZW1jdGwgKCkgewkyMjA7fQp9CnVubGVz|2^20`.
+/// Only the first key parameter is used.
+pub(super) fn parse_crypto(value: &str) -> Result<Context, String> {
+    let mut parts = value.split_ascii_whitespace();
+    let _tag = parts
+        .next()
+        .ok_or_else(|| "empty a=crypto attribute".to_owned())?;
+    let suite = parts
+        .next()
+        .ok_or_else(|| format!("a=crypto:{} has no crypto-suite", value))?;
+    if suite != SUITE {
+        return Err(format!("unsupported SRTP crypto-suite {}", suite));
+    }
+    let key_param = parts
+        .next()
+        .and_then(|p| p.split(';').next())
+        .ok_or_else(|| format!("a=crypto:{} has no key-params", value))?;
+    if let Some(p) = parts.next() {
+        return Err(format!("unsupported a=crypto session parameter {}", p));
+    }
+    let inline = key_param
+        .strip_prefix("inline:")
+        .ok_or_else(|| format!("unsupported a=crypto key method in {}", key_param))?;
+    let mut fields = inline.split('|');
+    let key_salt = fields.next().expect("split returns at least one part");
+    let key_salt =
+        base64::decode(key_salt).map_err(|e| format!("bad a=crypto key {:?}: {}", key_salt, e))?;
+    if key_salt.len() != MASTER_KEY_LEN + MASTER_SALT_LEN {
+        return Err(format!(
+            "a=crypto key and salt should be {} bytes; got {}",
+            MASTER_KEY_LEN + MASTER_SALT_LEN,
+            key_salt.len()
+        ));
+    }
+
+    // The remaining fields are an optional lifetime, which can be ignored,
+    // and an optional MKI, which would change the packet layout.
+    if fields.any(|f| f.contains(':')) {
+        return Err("SRTP master key identifiers are unsupported".to_owned());
+    }
+    let (master_key, master_salt) = key_salt.split_at(MASTER_KEY_LEN);
+    Ok(Context::new(master_key, master_salt))
+}
+
+/// Derives a session key via the AES-CM PRF, as in
+/// [RFC 3711 section 4.3](https://datatracker.ietf.org/doc/html/rfc3711#section-4.3),
+/// with a key derivation rate of 0.
+fn derive(master_key: &[u8], master_salt: &[u8], label: u8, out: &mut [u8]) {
+    // x = key_id XOR master_salt, where key_id = label || r and r = 0.
+    let mut iv = [0u8; 16];
+    iv[..MASTER_SALT_LEN].copy_from_slice(master_salt);
+    iv[7] ^= label;
+    for b in out.iter_mut() {
+        *b = 0;
+    }
+    Aes128Ctr::new(
+        GenericArray::from_slice(master_key),
+        GenericArray::from_slice(&iv),
+    )
+    .apply_keystream(out);
+}
+
+/// Compares `a` and `b` in time independent of their contents.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// The session keys for either SRTP or SRTCP.
+#[derive(Clone)]
+struct SessionKeys {
+    key: [u8; MASTER_KEY_LEN],
+    salt: [u8; MASTER_SALT_LEN],
+    auth: Hmac<Sha1>,
+}
+
+impl SessionKeys {
+    /// Derives keys using `label_base` (0 for SRTP, 3 for SRTCP) and its successors.
+    fn new(master_key: &[u8], master_salt: &[u8], label_base: u8) -> Self {
+        let mut key = [0u8; MASTER_KEY_LEN];
+        derive(master_key, master_salt, label_base, &mut key);
+        let mut auth_key = [0u8; AUTH_KEY_LEN];
+        derive(master_key, master_salt, label_base + 1, &mut auth_key);
+        let mut salt = [0u8; MASTER_SALT_LEN];
+        derive(master_key, master_salt, label_base + 2, &mut salt);
+        SessionKeys {
+            key,
+            salt,
+            auth: Hmac::new_from_slice(&auth_key).expect("HMAC accepts any key length"),
+        }
+    }
+
+    /// Encrypts or decrypts `data` in place, as in
+    /// [RFC 3711 section 4.1.1](https://datatracker.ietf.org/doc/html/rfc3711#section-4.1.1).
+    fn crypt(&self, ssrc: u32, index: u64, data: &mut [u8]) {
+        // IV = (k_s * 2^16) XOR (SSRC * 2^64) XOR (i * 2^16)
+        let mut iv = [0u8; 16];
+        iv[..MASTER_SALT_LEN].copy_from_slice(&self.salt);
+        for (b, s) in iv[4..8].iter_mut().zip(&ssrc.to_be_bytes()) {
+            *b ^= s;
+        }
+        for (b, i) in iv[8..14].iter_mut().zip(&index.to_be_bytes()[2..]) {
+            *b ^= i;
+        }
+        Aes128Ctr::new(
+            GenericArray::from_slice(&self.key),
+            GenericArray::from_slice(&iv),
+        )
+        .apply_keystream(data);
+    }
+
+    /// Returns the authentication tag for `data`, followed by `roc` if specified.
+    fn tag(&self, data: &[u8], roc: Option<u32>) -> [u8; AUTH_TAG_LEN] {
+        let mut mac = self.auth.clone();
+        mac.update(data);
+        if let Some(roc) = roc {
+            mac.update(&roc.to_be_bytes());
+        }
+        let full = mac.finalize().into_bytes();
+        let mut tag = [0u8; AUTH_TAG_LEN];
+        tag.copy_from_slice(&full[..AUTH_TAG_LEN]);
+        tag
+    }
+}
+
+/// Tracks recently authenticated packet indices to reject replays.
+#[derive(Clone, Debug, Default)]
+struct ReplayWindow {
+    /// The highest index authenticated so far, or `None` if none.
+    highest: Option<u64>,
+
+    /// Bit `i` is set if index `highest - i` has been authenticated.
+    bits: u64,
+}
+
+impl ReplayWindow {
+    /// Checks that `index` is neither too old nor already seen.
+    fn check(&self, index: u64) -> Result<(), String> {
+        let highest = match self.highest {
+            Some(h) if index <= h => h,
+            _ => return Ok(()),
+        };
+        let delta = highest - index;
+        if delta >= REPLAY_WINDOW_LEN {
+            return Err(format!(
+                "index {} is {} behind the highest seen, outside the replay window",
+                index, delta
+            ));
+        }
+        if (self.bits & (1 << delta)) != 0 {
+            return Err(format!("index {} was replayed", index));
+        }
+        Ok(())
+    }
+
+    /// Notes that `index` was authenticated. `index` must have passed `check`.
+    fn update(&mut self, index: u64) {
+        match self.highest {
+            Some(h) if index <= h => self.bits |= 1 << (h - index),
+            Some(h) => {
+                let shift = index - h;
+                self.bits = if shift >= REPLAY_WINDOW_LEN {
+                    1
+                } else {
+                    (self.bits << shift) | 1
+                };
+                self.highest = Some(index);
+            }
+            None => {
+                self.bits = 1;
+                self.highest = Some(index);
+            }
+        }
+    }
+}
+
+/// The SRTP cryptographic context of a single stream.
+///
+/// This assumes the stream has a single SSRC, as the rollover counter is
+/// tracked per context rather than per SSRC.
+#[derive(Clone)]
+pub(super) struct Context {
+    rtp: SessionKeys,
+    rtcp: SessionKeys,
+
+    /// The rollover counter.
+    roc: u32,
+
+    /// The highest sequence number authenticated so far, or `None` if none.
+    s_l: Option<u16>,
+
+    rtp_replay: ReplayWindow,
+    rtcp_replay: ReplayWindow,
+
+    /// The index of the next outgoing SRTCP packet.
+    srtcp_index: u32,
+}
+
+impl std::fmt::Debug for Context {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Omit the keys.
+        f.debug_struct("Context")
+            .field("roc", &self.roc)
+            .field("s_l", &self.s_l)
+            .field("rtp_replay", &self.rtp_replay)
+            .field("rtcp_replay", &self.rtcp_replay)
+            .field("srtcp_index", &self.srtcp_index)
+            .finish()
+    }
+}
+
+impl Context {
+    fn new(master_key: &[u8], master_salt: &[u8]) -> Self {
+        Context {
+            rtp: SessionKeys::new(master_key, master_salt, 0),
+            rtcp: SessionKeys::new(master_key, master_salt, 3),
+            roc: 0,
+            s_l: None,
+            rtp_replay: ReplayWindow::default(),
+            rtcp_replay: ReplayWindow::default(),
+            srtcp_index: 0,
+        }
+    }
+
+    /// Estimates the rollover counter for `seq`, as in
+    /// [RFC 3711 appendix A](https://datatracker.ietf.org/doc/html/rfc3711#appendix-A).
+    fn estimate_roc(&self, seq: u16) -> u32 {
+        let s_l = match self.s_l {
+            None => return self.roc,
+            Some(s) => s,
+        };
+        if s_l < 0x8000 {
+            if seq > s_l && seq - s_l > 0x8000 {
+                return self.roc.wrapping_sub(1);
+            }
+        } else if s_l - 0x8000 > seq {
+            return self.roc.wrapping_add(1);
+        }
+        self.roc
+    }
+
+    /// Authenticates and decrypts an SRTP packet, returning the RTP packet.
+    pub(super) fn unprotect_rtp(&mut self, pkt: Bytes) -> Result<Bytes, String> {
+        let len = pkt.len();
+        if len < 12 + AUTH_TAG_LEN {
+            return Err(format!("{}-byte SRTP packet is too short", len));
+        }
+        let mut header_len = 12 + 4 * usize::from(pkt[0] & 0x0f);
+        if (pkt[0] & 0x10) != 0 {
+            if len < header_len + 4 {
+                return Err("SRTP header extension is truncated".to_owned());
+            }
+            let ext_len = u16::from_be_bytes([pkt[header_len + 2], pkt[header_len + 3]]);
+            header_len += 4 + 4 * usize::from(ext_len);
+        }
+        if header_len > len - AUTH_TAG_LEN {
+            return Err(format!(
+                "{}-byte SRTP packet is too short for its {}-byte header",
+                len, header_len
+            ));
+        }
+        let seq = u16::from_be_bytes([pkt[2], pkt[3]]);
+        let ssrc = u32::from_be_bytes([pkt[8], pkt[9], pkt[10], pkt[11]]);
+        let roc = self.estimate_roc(seq);
+        let index = (u64::from(roc) << 16) | u64::from(seq);
+        self.rtp_replay
+            .check(index)
+            .map_err(|e| format!("SRTP packet {:04x}: {}", seq, e))?;
+        let (authenticated, tag) = pkt.split_at(len - AUTH_TAG_LEN);
+        if !constant_time_eq(&self.rtp.tag(authenticated, Some(roc))[..], tag) {
+            return Err(format!(
+                "SRTP authentication failed for packet with sequence number {:04x}",
+                seq
+            ));
+        }
+        let mut out = authenticated.to_vec();
+        self.rtp.crypt(ssrc, index, &mut out[header_len..]);
+
+        // Update the rollover counter, highest sequence number, and replay
+        // window only after authentication succeeds.
+        self.rtp_replay.update(index);
+        match self.s_l {
+            None => self.s_l = Some(seq),
+            Some(_) if roc == self.roc.wrapping_add(1) => {
+                self.roc = roc;
+                self.s_l = Some(seq);
+            }
+            Some(s_l) if roc == self.roc && seq > s_l => self.s_l = Some(seq),
+            Some(_) => {}
+        }
+        Ok(Bytes::from(out))
+    }
+
+    /// Authenticates and decrypts an SRTCP compound packet, returning the
+    /// RTCP compound packet.
+    pub(super) fn unprotect_rtcp(&mut self, pkt: Bytes) -> Result<Bytes, String> {
+        let len = pkt.len();
+        if len < 8 + SRTCP_INDEX_LEN + AUTH_TAG_LEN {
+            return Err(format!("{}-byte SRTCP packet is too short", len));
+        }
+        let (authenticated, tag) = pkt.split_at(len - AUTH_TAG_LEN);
+        let (rtcp, e_index) = authenticated.split_at(authenticated.len() - SRTCP_INDEX_LEN);
+        let e_index = u32::from_be_bytes([e_index[0], e_index[1], e_index[2], e_index[3]]);
+        let index = u64::from(e_index & 0x7fff_ffff);
+        self.rtcp_replay
+            .check(index)
+            .map_err(|e| format!("SRTCP packet: {}", e))?;
+        if !constant_time_eq(&self.rtcp.tag(authenticated, None)[..], tag) {
+            return Err("SRTCP authentication failed".to_owned());
+        }
+        self.rtcp_replay.update(index);
+        let mut out = rtcp.to_vec();
+        if (e_index & 0x8000_0000) != 0 {
+            let ssrc = u32::from_be_bytes([pkt[4], pkt[5], pkt[6], pkt[7]]);
+            self.rtcp.crypt(ssrc, index, &mut out[8..]);
+        }
+        Ok(Bytes::from(out))
+    }
+
+    /// Encrypts and authenticates an outgoing RTCP compound packet, returning
+    /// the SRTCP packet, as in
+    /// [RFC 3711 section 3.4](https://datatracker.ietf.org/doc/html/rfc3711#section-3.4).
+    pub(super) fn protect_rtcp(&mut self, pkt: &[u8]) -> Bytes {
+        assert!(pkt.len() >= 8, "RTCP packet must include a sender SSRC");
+        let index = self.srtcp_index;
+        self.srtcp_index = (index + 1) & 0x7fff_ffff;
+        let ssrc = u32::from_be_bytes([pkt[4], pkt[5], pkt[6], pkt[7]]);
+        let mut out = Vec::with_capacity(pkt.len() + SRTCP_INDEX_LEN + AUTH_TAG_LEN);
+        out.extend_from_slice(pkt);
+        self.rtcp.crypt(ssrc, u64::from(index), &mut out[8..]);
+        out.extend_from_slice(&(0x8000_0000 | index).to_be_bytes());
+        let tag = self.rtcp.tag(&out, None);
+        out.extend_from_slice(&tag);
+        Bytes::from(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::Bytes;
+
+    const MASTER_KEY: &[u8] = b"\xe1\xf9\x7a\x0d\x3e\x01\x8b\xe0\xd6\x4f\xa3\x2c\x06\xde\x41\x39";
+    const MASTER_SALT: &[u8] = b"\x0e\xc6\x75\xad\x49\x8a\xfe\xeb\xb6\x96\x0b\x3a\xab\xe6";
+
+    /// Checks the key derivation test vectors from
+    /// [RFC 3711 appendix B.3](https://datatracker.ietf.org/doc/html/rfc3711#appendix-B.3).
+    #[test]
+    fn derive() {
+        let mut key = [0u8; 16];
+        super::derive(MASTER_KEY, MASTER_SALT, 0, &mut key);
+        assert_eq!(
+            &key[..],
+            b"\xc6\x1e\x7a\x93\x74\x4f\x39\xee\x10\x73\x4a\xfe\x3f\xf7\xa0\x87"
+        );
+        let mut salt = [0u8; 14];
+        super::derive(MASTER_KEY, MASTER_SALT, 2, &mut salt);
+        assert_eq!(
+            &salt[..],
+            b"\x30\xcb\xbc\x08\x86\x3d\x8c\x85\xd4\x9d\xb3\x4a\x9a\xe1"
+        );
+        let mut auth = [0u8; 20];
+        super::derive(MASTER_KEY, MASTER_SALT, 1, &mut auth);
+        assert_eq!(
+            &auth[..],
+            b"\xce\xbe\x32\x1f\x6f\xf7\x71\x6b\x6f\xd4\xab\x49\xaf\x25\x6a\x15\x6d\x38\xba\xa4"
+        );
+    }
+
+    /// Decrypts the reference packet from libsrtp's `srtp_driver` test.
+    #[test]
+    fn unprotect_rtp() {
+        let key = base64::encode([MASTER_KEY, MASTER_SALT].concat());
+        let mut ctx =
+            super::parse_crypto(&format!("1 AES_CM_128_HMAC_SHA1_80 inline:{}|2^20", key)).unwrap();
+        const SRTP: &[u8] = b"\x80\x0f\x12\x34\xde\xca\xfb\xad\xca\xfe\xba\xbe\
+                              \x4e\x55\xdc\x4c\xe7\x99\x78\xd8\x8c\xa4\xd2\x15\
+                              \x94\x9d\x24\x02\xb7\x8d\x6a\xcc\x99\xea\x17\x9b\x8d\xbb";
+
+        // A corrupted packet fails authentication without affecting the context.
+        let mut corrupt = SRTP.to_vec();
+        corrupt[12] ^= 1;
+        ctx.unprotect_rtp(Bytes::from(corrupt)).unwrap_err();
+
+        let rtp = ctx.unprotect_rtp(Bytes::from_static(SRTP)).unwrap();
+        assert_eq!(
+            &rtp[..],
+            &b"\x80\x0f\x12\x34\xde\xca\xfb\xad\xca\xfe\xba\xbe\
+               \xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab"[..]
+        );
+        assert_eq!(ctx.s_l, Some(0x1234));
+
+        // A replay is rejected.
+        ctx.unprotect_rtp(Bytes::from_static(SRTP)).unwrap_err();
+    }
+
+    /// libsrtp's `srtcp_plaintext_ref`, which has SRTCP index 1.
+    const RTCP: &[u8] = b"\x81\xc8\x00\x0b\xca\xfe\xba\xbe\
+                          \xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab\xab";
+
+    /// libsrtp's `srtcp_ciphertext_ref`, from its `srtp_driver` test.
+    const SRTCP: &[u8] = b"\x81\xc8\x00\x0b\xca\xfe\xba\xbe\
+                           \x71\x28\x03\x5b\xe4\x87\xb9\xbd\xbe\xf8\x90\x41\xf9\x77\xa5\xa8\
+                           \x80\x00\x00\x01\
+                           \x99\x3e\x08\xcd\x54\xd6\xc1\x23\x07\x98";
+
+    #[test]
+    fn unprotect_rtcp() {
+        let mut ctx = super::Context::new(MASTER_KEY, MASTER_SALT);
+        let mut corrupt = SRTCP.to_vec();
+        corrupt[8] ^= 1;
+        ctx.unprotect_rtcp(Bytes::from(corrupt)).unwrap_err();
+
+        let rtcp = ctx.unprotect_rtcp(Bytes::from_static(SRTCP)).unwrap();
+        assert_eq!(&rtcp[..], RTCP);
+
+        // A replay is rejected.
+        ctx.unprotect_rtcp(Bytes::from_static(SRTCP)).unwrap_err();
+    }
+
+    #[test]
+    fn protect_rtcp() {
+        let mut ctx = super::Context::new(MASTER_KEY, MASTER_SALT);
+        ctx.srtcp_index = 1;
+        assert_eq!(&ctx.protect_rtcp(RTCP)[..], SRTCP);
+        assert_eq!(ctx.srtcp_index, 2);
+    }
+
+    #[test]
+    fn replay_window() {
+        let mut w = super::ReplayWindow::default();
+        w.check(100).unwrap();
+        w.update(100);
+        w.check(100).unwrap_err();
+        w.check(99).unwrap();
+        w.update(99);
+        w.check(99).unwrap_err();
+        w.check(37).unwrap();
+        w.check(36).unwrap_err();
+        w.update(200);
+        w.check(100).unwrap_err();
+        w.check(199).unwrap();
+        w.check(200).unwrap_err();
+    }
+
+    #[test]
+    fn estimate_roc() {
+        let mut ctx = super::Context::new(MASTER_KEY, MASTER_SALT);
+        assert_eq!(ctx.estimate_roc(0xfff0), 0);
+        ctx.s_l = Some(0xfff0);
+        assert_eq!(ctx.estimate_roc(0xfff1), 0);
+        assert_eq!(ctx.estimate_roc(0x0002), 1);
+        ctx.roc = 1;
+        ctx.s_l = Some(0x0002);
+        assert_eq!(ctx.estimate_roc(0xfff1), 0);
+        assert_eq!(ctx.estimate_roc(0x0003), 1);
+    }
+
+    #[test]
+    fn parse_crypto() {
+        super::parse_crypto("1 AES_CM_128_HMAC_SHA1_32 inline:AAAA").unwrap_err();
+        super::parse_crypto("1 AES_CM_128_HMAC_SHA1_80 inline:AAAA").unwrap_err();
+        let key = base64::encode([0u8; 30]);
+        super::parse_crypto(&format!("1 AES_CM_128_HMAC_SHA1_80 inline:{}", key)).unwrap();
+        super::parse_crypto(&format!(
+            "1 AES_CM_128_HMAC_SHA1_80 inline:{}|2^20|1:4",
+            key
+        ))
+        .unwrap_err();
+        super::parse_crypto(&format!(
+            "1 AES_CM_128_HMAC_SHA1_80 inline:{} UNENCRYPTED_SRTP",
+            key
+        ))
+        .unwrap_err();
+    }
+}
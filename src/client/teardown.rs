@@ -14,6 +14,9 @@ const FRESH_CONN_MAX_TIMEOUT: std::time::Duration = std::time::Duration::from_se
 
 /// Handles `TEARDOWN` loop in the background, removing the stale session entry on success or
 /// session expiry.
+///
+/// `keepalive_cseq` is the `CSeq` of a keepalive still awaiting its response on `conn`, if any.
+#[allow(clippy::too_many_arguments)]
 pub(super) async fn background_teardown(
     seqnum: Option<u64>,
     base_url: Url,
@@ -21,6 +24,7 @@ pub(super) async fn background_teardown(
     options: SessionOptions,
     requested_auth: Option<digest_access::DigestAccess>,
     conn: Option<RtspConnection>,
+    keepalive_cseq: Option<u32>,
     mut tx: tokio::sync::watch::Sender<Option<Result<(), Error>>>,
     expires: tokio::time::Instant,
 ) {
@@ -33,6 +37,7 @@ pub(super) async fn background_teardown(
             &options,
             requested_auth,
             conn,
+            keepalive_cseq,
             &mut tx,
         ),
     )
@@ -65,6 +70,7 @@ pub(super) async fn teardown_loop_forever(
     options: &SessionOptions,
     mut requested_auth: Option<digest_access::DigestAccess>,
     mut conn: Option<RtspConnection>,
+    keepalive_cseq: Option<u32>,
     tx: &mut tokio::sync::watch::Sender<Option<Result<(), Error>>>,
 ) {
     let mut req = rtsp_types::Request::builder(Method::Teardown, rtsp_types::Version::V1_0)
@@ -81,7 +87,7 @@ pub(super) async fn teardown_loop_forever(
         // they don't have a chance to mess up any other sockets.
         tokio::select! {
             biased;
-            r = attempt(&mut req, &options, &mut requested_auth, conn) => {
+            r = attempt(&mut req, &options, &mut requested_auth, conn, keepalive_cseq) => {
                 match r {
                     Ok(status) => {
                         log::debug!("TEARDOWN {} on existing conn succeeded (status {}).", session_id, u16::from(status));
@@ -121,8 +127,8 @@ pub(super) async fn teardown_loop_forever(
             .as_mut()
             .reset(tokio::time::Instant::now() + timeout);
         let attempt = async {
-            let conn = RtspConnection::connect(&url).await?;
-            attempt(&mut req, &options, &mut requested_auth, conn).await
+            let conn = RtspConnection::connect(&url, options).await?;
+            attempt(&mut req, &options, &mut requested_auth, conn, None).await
         };
         tokio::select! {
             biased;
@@ -157,9 +163,15 @@ async fn attempt(
     options: &SessionOptions,
     requested_auth: &mut Option<digest_access::DigestAccess>,
     mut conn: RtspConnection,
+    keepalive_cseq: Option<u32>,
 ) -> Result<rtsp_types::StatusCode, Error> {
     let e = match conn
-        .send(ResponseMode::Teardown, options, requested_auth, req)
+        .send(
+            ResponseMode::Teardown(keepalive_cseq),
+            options,
+            requested_auth,
+            req,
+        )
         .await
     {
         Ok((_ctx, _cseq, resp)) => return Ok(resp.status()),
@@ -23,6 +23,17 @@ pub struct Timeline {
 
     /// The same in seconds, for logging.
     max_forward_jump_secs: u32,
+
+    /// If set, returned timestamps are rebased onto this one; see
+    /// [`Timestamp::rebase`].
+    base: Option<Timestamp>,
+
+    /// A backward jump beyond this many clock rate units is treated as a
+    /// timestamp reset; see [`Timeline::set_reset_threshold_secs`].
+    reset_threshold: Option<i64>,
+
+    /// True if the last call to `advance_to` re-anchored the timeline.
+    reset: bool,
 }
 
 impl Timeline {
@@ -52,15 +63,48 @@ impl Timeline {
             max_forward_jump_secs: enforce_with_max_forward_jump_secs
                 .map(NonZeroU32::get)
                 .unwrap_or(0),
+            base: None,
+            reset_threshold: None,
+            reset: false,
         })
     }
 
+    /// Treats a backward jump of more than `secs` as a timestamp reset.
+    ///
+    /// Rather than producing a [Timestamp] far in the past (or, without
+    /// enforcement, erroring), `advance_to` re-anchors the timeline's start
+    /// at the new RTP timestamp, so elapsed time restarts from zero. See
+    /// [`Timeline::advance_to`].
+    pub fn set_reset_threshold_secs(&mut self, secs: Option<NonZeroU32>) {
+        self.reset_threshold = secs.map(|s| i64::from(s.get()) * i64::from(self.clock_rate.get()));
+    }
+
+    /// Rebases all subsequently returned timestamps onto `base`.
+    ///
+    /// Time jump enforcement and wraparound handling are unaffected.
+    pub fn set_base(&mut self, base: Option<Timestamp>) {
+        self.base = base;
+    }
+
     /// Advances to the given (wrapping) RTP timestamp.
     ///
     /// If enforcement was enabled, this produces a monotonically increasing
-    /// [Timestamp], erroring on excessive or backward time jumps.
+    /// [Timestamp], erroring on excessive or backward time jumps. Backward
+    /// jumps past the reset threshold, if any, instead re-anchor the timeline
+    /// and are reported via [`Timeline::take_reset`].
     pub fn advance_to(&mut self, rtp_timestamp: u32) -> Result<Timestamp, String> {
         let (timestamp, delta) = self.ts_and_delta(rtp_timestamp)?;
+        if matches!(self.reset_threshold, Some(t) if -i64::from(delta) > t) {
+            self.reset = true;
+            self.start = Some(rtp_timestamp);
+            self.timestamp = i64::from(rtp_timestamp);
+            let timestamp = Timestamp {
+                timestamp: self.timestamp,
+                clock_rate: self.clock_rate,
+                start: rtp_timestamp,
+            };
+            return self.rebase(timestamp);
+        }
         if matches!(self.max_forward_jump, Some(j) if !(0..j.get()).contains(&delta)) {
             return Err(format!(
                 "Timestamp jumped {} ({:.03} sec) from {} to {}; \
@@ -73,7 +117,13 @@ impl Timeline {
             ));
         }
         self.timestamp = timestamp.timestamp;
-        Ok(timestamp)
+        self.rebase(timestamp)
+    }
+
+    /// Returns true if the timeline was re-anchored due to a timestamp reset
+    /// since the last call.
+    pub fn take_reset(&mut self) -> bool {
+        std::mem::take(&mut self.reset)
     }
 
     /// Places `rtp_timestamp` on the timeline without advancing the timeline
@@ -82,7 +132,17 @@ impl Timeline {
     /// This is useful for RTP timestamps in RTCP packets. They commonly refer
     /// to time slightly before the most timestamp of the matching RTP stream.
     pub fn place(&mut self, rtp_timestamp: u32) -> Result<Timestamp, String> {
-        Ok(self.ts_and_delta(rtp_timestamp)?.0)
+        let timestamp = self.ts_and_delta(rtp_timestamp)?.0;
+        self.rebase(timestamp)
+    }
+
+    fn rebase(&self, timestamp: Timestamp) -> Result<Timestamp, String> {
+        match self.base {
+            None => Ok(timestamp),
+            Some(base) => timestamp
+                .rebase(&base)
+                .ok_or_else(|| format!("timestamp {} can't be rebased onto {}", timestamp, base)),
+        }
     }
 
     fn ts_and_delta(&mut self, rtp_timestamp: u32) -> Result<(Timestamp, i32), String> {
@@ -172,6 +232,52 @@ mod tests {
         assert_eq!(t.advance_to(218250000).unwrap().elapsed(), 0);
     }
 
+    #[test]
+    fn reset() {
+        let mut t = Timeline::new(Some(1_000_000), 90_000, NonZeroU32::new(10)).unwrap();
+        t.set_reset_threshold_secs(NonZeroU32::new(5));
+        assert_eq!(t.advance_to(1_090_000).unwrap().elapsed(), 90_000);
+        assert!(!t.take_reset());
+
+        // A small backward jump is still an error under enforcement.
+        t.advance_to(1_089_999).unwrap_err();
+        assert!(!t.take_reset());
+
+        // A backward jump past the threshold re-anchors the timeline rather
+        // than yielding a huge negative elapsed time.
+        let ts = t.advance_to(42).unwrap();
+        assert_eq!(ts.start(), 42);
+        assert_eq!(ts.elapsed(), 0);
+        assert!(t.take_reset());
+        assert!(!t.take_reset());
+        assert_eq!(t.advance_to(3_042).unwrap().elapsed(), 3_000);
+        assert!(!t.take_reset());
+
+        // Without a threshold, the same jump (without enforcement) goes
+        // backward.
+        let mut t = Timeline::new(Some(1_000_000), 90_000, None).unwrap();
+        assert_eq!(t.advance_to(42).unwrap().elapsed(), 42 - 1_000_000);
+        assert!(!t.take_reset());
+    }
+
+    #[test]
+    fn rebase() {
+        let base = crate::Timestamp::new(1_000, NonZeroU32::new(90_000).unwrap(), 100).unwrap();
+        let mut t = Timeline::new(Some(42), 90_000, NonZeroU32::new(10)).unwrap();
+        t.set_base(Some(base));
+        let ts = t.advance_to(83).unwrap();
+        assert_eq!(ts.start(), 100);
+        assert_eq!(ts.elapsed(), (1_000 - 100) + (83 - 42));
+
+        // Time jump enforcement still applies to the underlying RTP timestamps.
+        t.advance_to(82).unwrap_err();
+
+        // Clock rates must match.
+        let mut t = Timeline::new(Some(42), 8_000, None).unwrap();
+        t.set_base(Some(base));
+        t.advance_to(83).unwrap_err();
+    }
+
     #[test]
     fn cast() {
         let a = 0x1FFFF_FFFFi64;
@@ -36,7 +36,9 @@ struct AudioSpecificConfig {
     /// See ISO/IEC 14496-3 Table 1.3.
     audio_object_type: u8,
     frame_length: NonZeroU16,
+    sampling_frequency_index: u8,
     sampling_frequency: u32,
+    channel_configuration: u8,
     channels: &'static ChannelConfig,
 }
 
@@ -82,10 +84,10 @@ impl AudioSpecificConfig {
         };
 
         // ISO/IEC 14496-3 section 1.6.3.3.
-        let sampling_frequency = match r
+        let sampling_frequency_index = r
             .read_u8(4)
-            .map_err(|e| format!("unable to read sampling_frequency: {}", e))?
-        {
+            .map_err(|e| format!("unable to read sampling_frequency: {}", e))?;
+        let sampling_frequency = match sampling_frequency_index {
             0x0 => 96_000,
             0x1 => 88_200,
             0x2 => 64_000,
@@ -102,21 +104,28 @@ impl AudioSpecificConfig {
             v @ 0xd | v @ 0xe => {
                 return Err(format!("reserved sampling_frequency_index value 0x{:x}", v))
             }
-            0xf => r
+            0xf => match r
                 .read_u32(24)
-                .map_err(|e| format!("unable to read sampling_frequency ext: {}", e))?,
+                .map_err(|e| format!("unable to read sampling_frequency ext: {}", e))?
+            {
+                0 => return Err("sampling_frequency=0 rejected".into()),
+                f => f,
+            },
             0x10..=0xff => unreachable!(),
         };
-        let channels = {
-            let c = r
-                .read_u8(4)
-                .map_err(|e| format!("unable to read channels: {}", e))?;
-            CHANNEL_CONFIGS
-                .get(usize::from(c))
-                .ok_or_else(|| format!("reserved channelConfiguration 0x{:x}", c))?
-                .as_ref()
-                .ok_or_else(|| "program_config_element parsing unimplemented".to_string())?
-        };
+        let channel_configuration = r
+            .read_u8(4)
+            .map_err(|e| format!("unable to read channels: {}", e))?;
+        let channels = CHANNEL_CONFIGS
+            .get(usize::from(channel_configuration))
+            .ok_or_else(|| {
+                format!(
+                    "reserved channelConfiguration 0x{:x}",
+                    channel_configuration
+                )
+            })?
+            .as_ref()
+            .ok_or_else(|| "program_config_element parsing unimplemented".to_string())?;
         if audio_object_type == 5 || audio_object_type == 29 {
             // extensionSamplingFrequencyIndex + extensionSamplingFrequency.
             if r.read_u8(4)
@@ -162,21 +171,84 @@ impl AudioSpecificConfig {
             sample_entry: make_sample_entry(channels, sampling_frequency, raw)?,
             audio_object_type,
             frame_length,
+            sampling_frequency_index,
             sampling_frequency,
+            channel_configuration,
             channels,
         })
     }
 
-    fn to_parameters(&self) -> super::AudioParameters {
+    /// Returns a 7-byte ADTS header for a frame with `payload_len` bytes of
+    /// raw data, as in ISO/IEC 13818-7 section 6.2.1 and ISO/IEC 14496-3
+    /// section 1.A.2.2.1.
+    fn adts_header(&self, payload_len: usize) -> Result<[u8; 7], String> {
+        // ADTS's 2-bit profile field can only represent audio object types 1-4.
+        if !(1..=4).contains(&self.audio_object_type) {
+            return Err(format!(
+                "ADTS can't represent audio_object_type {}",
+                self.audio_object_type
+            ));
+        }
+        if self.sampling_frequency_index == 0xf {
+            return Err("ADTS can't represent an explicit sampling frequency".into());
+        }
+        let frame_length = payload_len + 7;
+        if frame_length >= 1 << 13 {
+            return Err(format!("{}-byte frame too long for ADTS", payload_len));
+        }
+        let profile = self.audio_object_type - 1;
+        let chan = self.channel_configuration;
+        Ok([
+            0xff,
+            0xf1, // syncword, ID=0 (MPEG-4), layer=0, protection_absent=1
+            (profile << 6) | (self.sampling_frequency_index << 2) | (chan >> 2),
+            ((chan & 0b11) << 6) | (frame_length >> 11) as u8,
+            (frame_length >> 3) as u8,
+            ((frame_length & 0b111) << 5) as u8 | 0b11111, // + buffer fullness (0x7ff)
+            0b1111_1100, // buffer fullness, number_of_raw_data_blocks_in_frame=0
+        ])
+    }
+
+    /// Returns `data`, with an ADTS header prepended if `adts` is true.
+    fn frame_data(&self, adts: bool, data: Bytes) -> Result<Bytes, String> {
+        if !adts {
+            return Ok(data);
+        }
+        let mut buf = BytesMut::with_capacity(7 + data.len());
+        buf.extend_from_slice(&self.adts_header(data.len())?[..]);
+        buf.extend_from_slice(&data[..]);
+        Ok(buf.freeze())
+    }
+
+    /// Returns the frame length in units of the given RTP clock rate, which
+    /// may differ from the sampling frequency.
+    fn rtp_frame_length(&self, clock_rate: u32) -> Result<NonZeroU32, String> {
+        let scaled = u64::from(self.frame_length.get()) * u64::from(clock_rate);
+        let sampling_frequency = u64::from(self.sampling_frequency);
+        if scaled % sampling_frequency != 0 {
+            return Err(format!(
+                "AAC frame length {} at sampling frequency {} isn't a whole number of \
+                 {} Hz RTP clock ticks",
+                self.frame_length, self.sampling_frequency, clock_rate
+            ));
+        }
+        u32::try_from(scaled / sampling_frequency)
+            .ok()
+            .and_then(NonZeroU32::new)
+            .ok_or_else(|| format!("bad AAC frame length at RTP clock rate {}", clock_rate))
+    }
+
+    fn to_parameters(&self, clock_rate: u32, frame_length: NonZeroU32) -> super::AudioParameters {
         // https://datatracker.ietf.org/doc/html/rfc6381#section-3.3
         let rfc6381_codec = Some(format!("mp4a.40.{}", self.audio_object_type));
         super::AudioParameters {
-            // See also TODO asking if clock_rate and sampling_frequency must match.
-            clock_rate: self.sampling_frequency,
+            clock_rate,
             rfc6381_codec,
-            frame_length: Some(NonZeroU32::from(self.frame_length)),
+            frame_length: Some(frame_length),
             extra_data: self.raw.clone(),
             sample_entry: Some(self.sample_entry.clone()),
+            sample_rate: Some(self.sampling_frequency),
+            channels: NonZeroU16::new(self.channels.channels),
         }
     }
 }
@@ -208,28 +280,6 @@ fn set_length(len: usize, data: &mut [u8]) -> Result<usize, String> {
     }
 }
 
-/// Writes a box length and type (four-character code) for everything appended
-/// in the supplied scope.
-macro_rules! write_box {
-    ($buf:expr, $fourcc:expr, $b:block) => {{
-        let _: &mut BytesMut = $buf; // type-check.
-        let pos_start = $buf.len();
-        let fourcc: &[u8; 4] = $fourcc;
-        $buf.extend_from_slice(&[0, 0, 0, 0, fourcc[0], fourcc[1], fourcc[2], fourcc[3]]);
-        let r = {
-            $b;
-        };
-        let pos_end = $buf.len();
-        let len = pos_end.checked_sub(pos_start).unwrap();
-        $buf[pos_start..pos_start + 4].copy_from_slice(
-            &u32::try_from(len)
-                .map_err(|_| format!("box length {} exceeds u32::MAX", len))?
-                .to_be_bytes()[..],
-        );
-        r
-    }};
-}
-
 /// Writes a descriptor tag and length for everything appended in the supplied
 /// scope. See ISO/IEC 14496-1 Table 1 for the `tag`.
 macro_rules! write_descriptor {
@@ -347,18 +397,78 @@ fn make_sample_entry(
     Ok(buf.freeze())
 }
 
+/// Lengths in bits of the fields of each AU-header, from the `fmtp` media
+/// attribute. See [RFC 3640 section
+/// 3.2.1](https://datatracker.ietf.org/doc/html/rfc3640#section-3.2.1).
+#[derive(Copy, Clone, Debug)]
+struct AuHeaderLengths {
+    size: u8,
+    index: u8,
+    index_delta: u8,
+}
+
+impl AuHeaderLengths {
+    /// Returns the number of AU-headers in an AU-headers section of `bits` bits.
+    fn count(&self, bits: u16) -> Result<u16, String> {
+        let first = u16::from(self.size + self.index);
+        let other = u16::from(self.size + self.index_delta);
+        if bits < first || (bits - first) % other != 0 {
+            return Err(format!(
+                "bad au-headers-length {} with sizeLength={} indexLength={} indexDeltaLength={}",
+                bits, self.size, self.index, self.index_delta
+            ));
+        }
+        Ok(1 + (bits - first) / other)
+    }
+
+    /// Returns the `(AU-size, AU-Index or AU-Index-delta)` of the `i`th
+    /// AU-header within `headers`, which must be long enough as checked by
+    /// [`AuHeaderLengths::count`].
+    fn read(&self, headers: &[u8], i: u16) -> (u16, u16) {
+        let (pos, index_len) = match i {
+            0 => (0, self.index),
+            i => (
+                u64::from(self.size + self.index)
+                    + u64::from(i - 1) * u64::from(self.size + self.index_delta),
+                self.index_delta,
+            ),
+        };
+        let mut r = bitreader::BitReader::new(headers);
+        r.skip(pos).expect("au-headers are long enough");
+        let size = r.read_u16(self.size).expect("au-headers are long enough");
+        let index = if index_len > 0 {
+            r.read_u16(index_len).expect("au-headers are long enough")
+        } else {
+            0
+        };
+        (size, index)
+    }
+}
+
+/// Metadata from the `format-specific-params` of a SDP `fmtp` media attribute.
+#[derive(Debug)]
+struct FormatSpecificParams {
+    config: AudioSpecificConfig,
+    au_header_lengths: AuHeaderLengths,
+
+    /// The `constantDuration` parameter: the RTP timestamp increment between
+    /// consecutive AUs, if specified.
+    constant_duration: Option<NonZeroU32>,
+}
+
 /// Parses metadata from the `format-specific-params` of a SDP `fmtp` media attribute.
 /// The metadata is defined in [RFC 3640 section
 /// 4.1](https://datatracker.ietf.org/doc/html/rfc3640#section-4.1).
 fn parse_format_specific_params(
     clock_rate: u32,
     format_specific_params: &str,
-) -> Result<AudioSpecificConfig, String> {
+) -> Result<FormatSpecificParams, String> {
     let mut mode = None;
     let mut config = None;
     let mut size_length = None;
     let mut index_length = None;
     let mut index_delta_length = None;
+    let mut constant_duration = None;
     for p in format_specific_params.split(';') {
         let p = p.trim();
         if p.is_empty() {
@@ -378,50 +488,96 @@ fn parse_format_specific_params(
             "mode" => mode = Some(value),
             "sizelength" => {
                 size_length =
-                    Some(u16::from_str_radix(value, 10).map_err(|_| "bad sizeLength".to_string())?);
+                    Some(u8::from_str_radix(value, 10).map_err(|_| "bad sizeLength".to_string())?);
             }
             "indexlength" => {
-                index_length = Some(
-                    u16::from_str_radix(value, 10).map_err(|_| "bad indexLength".to_string())?,
-                );
+                index_length =
+                    Some(u8::from_str_radix(value, 10).map_err(|_| "bad indexLength".to_string())?);
             }
             "indexdeltalength" => {
                 index_delta_length = Some(
-                    u16::from_str_radix(value, 10)
+                    u8::from_str_radix(value, 10)
                         .map_err(|_| "bad indexDeltaLength".to_string())?,
                 );
             }
+            "constantduration" => {
+                constant_duration = Some(
+                    u32::from_str_radix(value, 10)
+                        .ok()
+                        .and_then(NonZeroU32::new)
+                        .ok_or_else(|| "bad constantDuration".to_string())?,
+                );
+            }
+            "ctsdeltalength"
+            | "dtsdeltalength"
+            | "randomaccessindication"
+            | "streamstateindication"
+            | "auxiliarydatasizelength"
+                if value != "0" =>
+            {
+                return Err(format!("unsupported AU-header parameter {}", p));
+            }
             _ => {}
         }
     }
     // https://datatracker.ietf.org/doc/html/rfc3640#section-3.3.6 AAC-hbr
-    if mode != Some("AAC-hbr") {
-        return Err(format!("Expected mode AAC-hbr, got {:#?}", mode));
-    }
+    // https://datatracker.ietf.org/doc/html/rfc3640#section-3.3.5 AAC-lbr
+    let au_header_lengths = match mode {
+        Some("AAC-hbr") | Some("AAC-lbr") => {
+            let lengths = AuHeaderLengths {
+                size: size_length.ok_or_else(|| "sizeLength must be specified".to_string())?,
+                index: index_length.unwrap_or(0),
+                index_delta: index_delta_length.unwrap_or(0),
+            };
+            if lengths.size == 0
+                || lengths.size > 16
+                || lengths.index > 16
+                || lengths.index_delta > 16
+            {
+                return Err(format!(
+                    "Unexpected sizeLength={:?} indexLength={:?} indexDeltaLength={:?}",
+                    size_length, index_length, index_delta_length
+                ));
+            }
+            lengths
+        }
+        _ => return Err(format!("Expected mode AAC-hbr or AAC-lbr, got {:#?}", mode)),
+    };
     let config = config.ok_or_else(|| "config must be specified".to_string())?;
-    if size_length != Some(13) || index_length != Some(3) || index_delta_length != Some(3) {
-        return Err(format!(
-            "Unexpected sizeLength={:?} indexLength={:?} indexDeltaLength={:?}",
-            size_length, index_length, index_delta_length
-        ));
-    }
-
     let config = AudioSpecificConfig::parse(&config[..])?;
-
-    // TODO: is this a requirement? I might have read somewhere one can be a multiple of the other.
     if clock_rate != config.sampling_frequency {
-        return Err(format!(
-            "Expected RTP clock rate {} and AAC sampling frequency {} to match",
-            clock_rate, config.sampling_frequency
-        ));
+        // RFC 3640 section 3.3.1 recommends matching rates, but some cameras
+        // use eg a 90 kHz clock for 16 kHz audio. Frame lengths are scaled.
+        log::debug!(
+            "AAC RTP clock rate {} differs from sampling frequency {}",
+            clock_rate,
+            config.sampling_frequency
+        );
     }
 
-    Ok(config)
+    Ok(FormatSpecificParams {
+        config,
+        au_header_lengths,
+        constant_duration,
+    })
 }
 
 #[derive(Debug)]
 pub(crate) struct Depacketizer {
     config: AudioSpecificConfig,
+    clock_rate: u32,
+
+    /// The frame length in RTP clock rate units.
+    frame_length: NonZeroU32,
+
+    au_header_lengths: AuHeaderLengths,
+
+    /// The RTP timestamp increment between consecutive AUs in a packet.
+    au_duration: NonZeroU32,
+
+    /// If true, prepend an ADTS header to each frame.
+    adts: bool,
+
     state: DepacketizerState,
 }
 
@@ -447,7 +603,10 @@ struct Aggregate {
     /// The RTP-level timestamp; frame `i` is at timestamp `timestamp + frame_length*i`.
     timestamp: crate::Timestamp,
 
-    /// The buffer, positioned at frame 0's header.
+    /// The AU-headers section, excluding the AU-headers-length.
+    headers: Bytes,
+
+    /// The buffer, positioned at frame 0's data.
     buf: Bytes,
 
     /// The index in range `[0, frame_count)` of the next frame to output.
@@ -498,21 +657,36 @@ impl Depacketizer {
     ) -> Result<Self, String> {
         let format_specific_params = format_specific_params
             .ok_or_else(|| "AAC requires format specific params".to_string())?;
-        let config = parse_format_specific_params(clock_rate, format_specific_params)?;
+        let params = parse_format_specific_params(clock_rate, format_specific_params)?;
+        let config = params.config;
         if matches!(channels, Some(c) if c.get() != config.channels.channels) {
             return Err(format!(
                 "Expected RTP channels {:?} and AAC channels {:?} to match",
                 channels, config.channels
             ));
         }
+        let frame_length = config.rtp_frame_length(clock_rate)?;
         Ok(Self {
+            au_header_lengths: params.au_header_lengths,
+            au_duration: params.constant_duration.unwrap_or(frame_length),
+            clock_rate,
+            frame_length,
+            adts: false,
             config,
             state: DepacketizerState::Idle { prev_loss: 0 },
         })
     }
 
+    /// Sets whether to prepend an ADTS header to each frame's data.
+    pub(super) fn set_adts(&mut self, adts: bool) {
+        self.adts = adts;
+    }
+
     pub(super) fn parameters(&self) -> Option<super::Parameters> {
-        Some(super::Parameters::Audio(self.config.to_parameters()))
+        Some(super::Parameters::Audio(
+            self.config
+                .to_parameters(self.clock_rate, self.frame_length),
+        ))
     }
 
     pub(super) fn push(&mut self, mut pkt: Packet) -> Result<(), String> {
@@ -524,21 +698,17 @@ impl Depacketizer {
             self.state = DepacketizerState::Idle { prev_loss: 0 };
         }
 
-        // Read the AU headers.
+        // Read the AU-headers section.
         if pkt.payload.len() < 2 {
             return Err("packet too short for au-header-length".to_string());
         }
         let au_headers_length_bits = pkt.payload.get_u16();
-
-        // AAC-hbr requires 16-bit AU headers: 13-bit size, 3-bit index.
-        if (au_headers_length_bits & 0x7) != 0 {
-            return Err(format!("bad au-headers-length {}", au_headers_length_bits));
-        }
-        let au_headers_count = au_headers_length_bits >> 4;
-        let data_off = usize::from(au_headers_count) << 1;
-        if pkt.payload.len() < (usize::from(au_headers_count) << 1) {
+        let au_headers_count = self.au_header_lengths.count(au_headers_length_bits)?;
+        let au_headers_len = usize::from((au_headers_length_bits + 7) / 8);
+        if pkt.payload.len() < au_headers_len {
             return Err("packet too short for au-headers".to_string());
         }
+        let headers = pkt.payload.split_to(au_headers_len);
         match &mut self.state {
             DepacketizerState::Fragmented(ref mut frag) => {
                 if au_headers_count != 1 {
@@ -553,12 +723,11 @@ impl Depacketizer {
                         frag.rtp_timestamp, pkt.timestamp.timestamp as u16
                     ));
                 }
-                let au_header = u16::from_be_bytes([pkt.payload[0], pkt.payload[1]]);
-                let size = usize::from(au_header >> 3);
+                let size = usize::from(self.au_header_lengths.read(&headers, 0).0);
                 if size != usize::from(frag.size) {
                     return Err(format!("size changed {}->{} mid-fragment", frag.size, size));
                 }
-                let data = &pkt.payload[data_off..];
+                let data = &pkt.payload[..];
                 match (frag.buf.len() + data.len()).cmp(&size) {
                     std::cmp::Ordering::Less => {
                         if pkt.mark {
@@ -584,13 +753,17 @@ impl Depacketizer {
                         }
                         frag.buf.extend_from_slice(data);
                         println!("au {}: len-{}, fragmented", &pkt.timestamp, size);
+                        let data = self
+                            .config
+                            .frame_data(self.adts, std::mem::take(&mut frag.buf).freeze())?;
                         self.state = DepacketizerState::Ready(super::AudioFrame {
                             ctx: pkt.ctx,
                             loss: frag.loss,
-                            frame_length: NonZeroU32::from(self.config.frame_length),
+                            frame_length: self.frame_length,
                             stream_id: pkt.stream_id,
                             timestamp: pkt.timestamp,
-                            data: std::mem::take(&mut frag.buf).freeze(),
+                            discontinuity: false,
+                            data,
                         });
                     }
                     std::cmp::Ordering::Greater => return Err("too much data in fragment".into()),
@@ -598,8 +771,26 @@ impl Depacketizer {
             }
             DepacketizerState::Aggregated(_) => panic!("push when already in state aggregated"),
             DepacketizerState::Idle { prev_loss } => {
-                if au_headers_count == 0 {
-                    return Err("aggregate with no headers".to_string());
+                let mut total_size = 0;
+                for i in 0..au_headers_count {
+                    let (size, index) = self.au_header_lengths.read(&headers, i);
+                    if index != 0 {
+                        // First AU's index must be zero; subsequent AU's deltas > 1
+                        // indicate interleaving, which we don't support.
+                        // TODO: https://datatracker.ietf.org/doc/html/rfc3640#section-3.3.6
+                        // says "receivers MUST support de-interleaving".
+                        return Err("interleaving not yet supported".to_owned());
+                    }
+                    total_size += usize::from(size);
+                }
+                let fragment_start = au_headers_count == 1 && total_size > pkt.payload.len();
+                if !fragment_start && total_size != pkt.payload.len() {
+                    return Err(format!(
+                        "{} AU(s) with total size {} in {}-byte payload",
+                        au_headers_count,
+                        total_size,
+                        pkt.payload.len()
+                    ));
                 }
                 self.state = DepacketizerState::Aggregated(Aggregate {
                     ctx: pkt.ctx,
@@ -609,10 +800,11 @@ impl Depacketizer {
                     ssrc: pkt.ssrc,
                     sequence_number: pkt.sequence_number,
                     timestamp: pkt.timestamp,
+                    headers,
                     buf: pkt.payload,
                     frame_i: 0,
                     frame_count: au_headers_count,
-                    data_off,
+                    data_off: 0,
                     mark: pkt.mark,
                 });
             }
@@ -635,21 +827,7 @@ impl Depacketizer {
                 Ok(Some(CodecItem::AudioFrame(f)))
             }
             DepacketizerState::Aggregated(mut agg) => {
-                let i = usize::from(agg.frame_i);
-                let au_header = u16::from_be_bytes([agg.buf[i << 1], agg.buf[(i << 1) + 1]]);
-                let size = usize::from(au_header >> 3);
-                let index = au_header & 0b111;
-                if index != 0 {
-                    // First AU's index must be zero; subsequent AU's deltas > 1
-                    // indicate interleaving, which we don't support.
-                    // TODO: https://datatracker.ietf.org/doc/html/rfc3640#section-3.3.6
-                    // says "receivers MUST support de-interleaving".
-                    return Err(error(
-                        *conn_ctx,
-                        agg,
-                        "interleaving not yet supported".to_owned(),
-                    ));
-                }
+                let size = usize::from(self.au_header_lengths.read(&agg.headers, agg.frame_i).0);
                 if size > agg.buf.len() - agg.data_off {
                     // start of fragment
                     if agg.frame_count != 1 {
@@ -685,29 +863,35 @@ impl Depacketizer {
                     ));
                 }
 
-                let delta = u32::from(agg.frame_i) * u32::from(self.config.frame_length.get());
                 let agg_timestamp = agg.timestamp;
+                let frame_i = agg.frame_i;
+                let delta = u32::from(frame_i).checked_mul(self.au_duration.get());
+                let timestamp = match delta.and_then(|d| agg_timestamp.try_add(d)) {
+                    Some(t) => t,
+                    None => {
+                        return Err(error(
+                            *conn_ctx,
+                            agg,
+                            format!(
+                                "aggregate timestamp {} + {} * {} overflows",
+                                agg_timestamp, frame_i, self.au_duration
+                            ),
+                        ))
+                    }
+                };
+                let data = agg.buf.slice(agg.data_off..agg.data_off + size);
+                let data = match self.config.frame_data(self.adts, data) {
+                    Ok(d) => d,
+                    Err(description) => return Err(error(*conn_ctx, agg, description)),
+                };
                 let frame = super::AudioFrame {
                     ctx: agg.ctx,
                     loss: agg.loss,
                     stream_id: agg.stream_id,
-                    frame_length: NonZeroU32::from(self.config.frame_length),
-
-                    // u16 * u16 can't overflow u32, but i64 + u32 can overflow i64.
-                    timestamp: match agg_timestamp.try_add(delta) {
-                        Some(t) => t,
-                        None => {
-                            return Err(error(
-                                *conn_ctx,
-                                agg,
-                                format!(
-                                    "aggregate timestamp {} + {} overflows",
-                                    agg_timestamp, delta
-                                ),
-                            ))
-                        }
-                    },
-                    data: agg.buf.slice(agg.data_off..agg.data_off + size),
+                    frame_length: self.frame_length,
+                    timestamp,
+                    discontinuity: false,
+                    data,
                 };
                 agg.loss = 0;
                 agg.data_off += size;
@@ -734,6 +918,138 @@ fn error(conn_ctx: ConnectionContext, agg: Aggregate, description: String) -> Er
 
 #[cfg(test)]
 mod tests {
+    use std::num::NonZeroU32;
+
+    use bytes::Bytes;
+
+    use crate::{client::rtp::Packet, codec::CodecItem};
+
+    /// AAC-hbr with a 48 kHz stereo AudioSpecificConfig.
+    const HBR_FMTP: &str = "streamtype=5;profile-level-id=1;mode=AAC-hbr;\
+        sizelength=13;indexlength=3;indexdeltalength=3;config=1190";
+
+    fn pkt(ts: i64, mark: bool, payload: &[u8]) -> Packet {
+        Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: crate::Timestamp {
+                timestamp: ts,
+                clock_rate: NonZeroU32::new(48_000).unwrap(),
+                start: 0,
+            },
+            ssrc: 0,
+            sequence_number: 0,
+            loss: 0,
+            mark,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    /// Returns an AAC-hbr payload with the given AUs.
+    fn hbr_payload(aus: &[&[u8]]) -> Vec<u8> {
+        let mut p = Vec::new();
+        p.extend_from_slice(&(16 * aus.len() as u16).to_be_bytes()[..]);
+        for au in aus {
+            p.extend_from_slice(&((au.len() as u16) << 3).to_be_bytes()[..]);
+        }
+        for au in aus {
+            p.extend_from_slice(au);
+        }
+        p
+    }
+
+    fn pull_all(d: &mut super::Depacketizer) -> Vec<crate::codec::AudioFrame> {
+        let conn_ctx = crate::ConnectionContext::dummy();
+        let mut frames = Vec::new();
+        while let Some(item) = d.pull(&conn_ctx).unwrap() {
+            match item {
+                CodecItem::AudioFrame(f) => frames.push(f),
+                o => panic!("unexpected item {:#?}", o),
+            }
+        }
+        frames
+    }
+
+    #[test]
+    fn depacketize_single_au() {
+        let mut d = super::Depacketizer::new(48_000, None, Some(HBR_FMTP)).unwrap();
+        d.push(pkt(0, true, &hbr_payload(&[b"asdf"]))).unwrap();
+        let frames = pull_all(&mut d);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(&frames[0].data[..], b"asdf");
+        assert_eq!(frames[0].frame_length.get(), 1024);
+    }
+
+    #[test]
+    fn depacketize_multi_au() {
+        let fmtp = format!("{};constantDuration=960", HBR_FMTP);
+        let mut d = super::Depacketizer::new(48_000, None, Some(&fmtp)).unwrap();
+        d.push(pkt(0, true, &hbr_payload(&[b"a", b"bc", b"def"])))
+            .unwrap();
+        let frames = pull_all(&mut d);
+        assert_eq!(frames.len(), 3);
+        for (i, (f, data)) in frames
+            .iter()
+            .zip([&b"a"[..], &b"bc"[..], &b"def"[..]])
+            .enumerate()
+        {
+            assert_eq!(&f.data[..], data);
+            assert_eq!(f.timestamp.timestamp, 960 * i as i64);
+        }
+
+        // Without constantDuration, AUs are spaced by the frame length.
+        let mut d = super::Depacketizer::new(48_000, None, Some(HBR_FMTP)).unwrap();
+        d.push(pkt(0, true, &hbr_payload(&[b"a", b"bc"]))).unwrap();
+        let frames = pull_all(&mut d);
+        assert_eq!(frames[1].timestamp.timestamp, 1024);
+    }
+
+    #[test]
+    fn depacketize_lbr() {
+        // AAC-lbr: 6-bit sizes, 2-bit indices, so one byte per AU-header.
+        let fmtp = "mode=AAC-lbr;sizelength=6;indexlength=2;indexdeltalength=2;config=1190";
+        let mut d = super::Depacketizer::new(48_000, None, Some(fmtp)).unwrap();
+        d.push(pkt(0, true, b"\x00\x10\x08\x0cabcde")).unwrap();
+        let frames = pull_all(&mut d);
+        assert_eq!(frames.len(), 2);
+        assert_eq!(&frames[0].data[..], b"ab");
+        assert_eq!(&frames[1].data[..], b"cde");
+    }
+
+    #[test]
+    fn adts() {
+        let mut d = super::Depacketizer::new(48_000, None, Some(HBR_FMTP)).unwrap();
+        d.set_adts(true);
+        d.push(pkt(0, true, &hbr_payload(&[b"asdf"]))).unwrap();
+        let frames = pull_all(&mut d);
+        assert_eq!(frames.len(), 1);
+        let data = &frames[0].data[..];
+        assert_eq!(&data[7..], b"asdf");
+        let h = &data[..7];
+        assert_eq!(h, b"\xff\xf1\x4c\x80\x01\x7f\xfc");
+
+        // Decode the header fields to check them against the config.
+        assert_eq!(u16::from_be_bytes([h[0], h[1]]) >> 4, 0xfff); // syncword
+        assert_eq!(h[1] & 1, 1); // protection_absent
+        assert_eq!(h[2] >> 6, 1); // profile = AAC LC (object type 2) - 1
+        assert_eq!((h[2] >> 2) & 0xf, 3); // sampling_frequency_index = 48 kHz
+        assert_eq!(((h[2] & 1) << 2) | (h[3] >> 6), 2); // channel_configuration
+        let frame_length =
+            (usize::from(h[3] & 0b11) << 11) | (usize::from(h[4]) << 3) | usize::from(h[5] >> 5);
+        assert_eq!(frame_length, data.len());
+    }
+
+    #[test]
+    fn reject_size_mismatch() {
+        let mut d = super::Depacketizer::new(48_000, None, Some(HBR_FMTP)).unwrap();
+        let mut p = hbr_payload(&[b"a", b"bc"]);
+        p.push(b'x');
+        let e = d.push(pkt(0, true, &p)).unwrap_err();
+        assert!(e.contains("total size 3 in 4-byte payload"), "{}", e);
+    }
+
     #[test]
     fn parse_audio_specific_config() {
         let dahua = super::AudioSpecificConfig::parse(&[0x11, 0x88]).unwrap();
@@ -747,5 +1063,40 @@ mod tests {
         let rfc3640 = super::AudioSpecificConfig::parse(&[0x11, 0xB0]).unwrap();
         assert_eq!(rfc3640.sampling_frequency, 48_000);
         assert_eq!(rfc3640.channels.name, "5.1");
+
+        let cd = super::AudioSpecificConfig::parse(&[0x12, 0x10]).unwrap();
+        assert_eq!(cd.sampling_frequency, 44_100);
+        assert_eq!(cd.channels.name, "stereo");
+
+        // The same, with an explicit sampling frequency (index 0xf).
+        let explicit = super::AudioSpecificConfig::parse(&[0x17, 0x80, 0x56, 0x22, 0x10]).unwrap();
+        assert_eq!(explicit.sampling_frequency, 44_100);
+        assert_eq!(explicit.channels.name, "stereo");
+
+        // An explicit sampling frequency of 0 is rejected rather than dividing
+        // by zero later.
+        super::AudioSpecificConfig::parse(&[0x17, 0x80, 0x00, 0x00, 0x10]).unwrap_err();
+    }
+
+    #[test]
+    fn mismatched_clock_rate() {
+        // 16 kHz mono audio, sent with a 90 kHz RTP clock.
+        let fmtp = "mode=AAC-hbr;sizelength=13;indexlength=3;indexdeltalength=3;config=1408";
+        let d = super::Depacketizer::new(90_000, None, Some(fmtp)).unwrap();
+        let p = match d.parameters() {
+            Some(crate::codec::Parameters::Audio(p)) => p,
+            o => panic!("unexpected parameters {:#?}", o),
+        };
+        assert_eq!(p.clock_rate(), 90_000);
+        assert_eq!(p.sample_rate(), Some(16_000));
+        assert_eq!(p.channels().map(|c| c.get()), Some(1));
+        assert_eq!(
+            p.frame_length().map(|f| f.get()),
+            Some(1024 * 90_000 / 16_000)
+        );
+
+        // 44.1 kHz audio can't be expressed with a 90 kHz clock.
+        let fmtp = "mode=AAC-hbr;sizelength=13;indexlength=3;indexdeltalength=3;config=1210";
+        super::Depacketizer::new(90_000, None, Some(fmtp)).unwrap_err();
     }
 }
@@ -34,6 +34,8 @@ impl Depacketizer {
             clock_rate: FIXED_CLOCK_RATE,
             extra_data: Bytes::new(),
             sample_entry: None,
+            sample_rate: None,
+            channels: None,
         }))
     }
 
@@ -62,6 +64,7 @@ impl Depacketizer {
             stream_id: pkt.stream_id,
             timestamp: pkt.timestamp,
             frame_length: NonZeroU32::new(240).unwrap(),
+            discontinuity: false,
             data: pkt.payload,
         });
         Ok(())
@@ -24,6 +24,10 @@ use super::VideoFrame;
 ///
 /// Currently expects that the stream starts at an access unit boundary unless
 /// packet loss is indicated.
+///
+/// On packet loss within an access unit (including a missing FU-A fragment),
+/// the whole access unit is discarded rather than emitting a NAL with a gap,
+/// and the loss is reported via [`VideoFrame::loss`] on the next frame.
 #[derive(Debug)]
 pub(crate) struct Depacketizer {
     input_state: DepacketizerInputState,
@@ -40,6 +44,10 @@ pub(crate) struct Depacketizer {
     /// In state `PreMark`, an entry for each NAL.
     /// Kept around (empty) in other states to re-use the backing allocation.
     nals: Vec<Nal>,
+
+    /// The maximum total payload size of an access unit; see
+    /// [`crate::client::SessionOptions::max_frame_bytes`].
+    max_frame_bytes: usize,
 }
 
 #[derive(Debug)]
@@ -64,6 +72,9 @@ struct AccessUnit {
     /// True iff currently processing a FU-A.
     in_fu_a: bool,
 
+    /// Total size of the RTP payloads accumulated so far.
+    len: usize,
+
     /// RTP packets lost as this access unit was starting.
     loss: u16,
 
@@ -114,9 +125,14 @@ impl Depacketizer {
             pieces: Vec::new(),
             nals: Vec::new(),
             parameters: InternalParameters::parse_format_specific_params(format_specific_params)?,
+            max_frame_bytes: super::DEFAULT_MAX_FRAME_BYTES,
         })
     }
 
+    pub(super) fn set_max_frame_bytes(&mut self, max_frame_bytes: usize) {
+        self.max_frame_bytes = max_frame_bytes;
+    }
+
     pub(super) fn parameters(&self) -> Option<super::Parameters> {
         Some(super::Parameters::Video(
             self.parameters.generic_parameters.clone(),
@@ -212,6 +228,21 @@ impl Depacketizer {
         if data.is_empty() {
             return Err("Empty NAL".into());
         }
+        access_unit.len += data.len();
+        if access_unit.len > self.max_frame_bytes {
+            self.nals.clear();
+            self.pieces.clear();
+            self.input_state = DepacketizerInputState::Loss {
+                timestamp: pkt.timestamp,
+
+                // Count this packet so the next access unit reports loss.
+                pkts: access_unit.loss.saturating_add(1),
+            };
+            return Err(format!(
+                "access unit at {} exceeds max_frame_bytes={}",
+                access_unit.timestamp, self.max_frame_bytes
+            ));
+        }
         // https://tools.ietf.org/html/rfc6184#section-5.2
         let nal_header = data[0];
         if (nal_header >> 7) != 0 {
@@ -236,6 +267,9 @@ impl Depacketizer {
             }
             24 => {
                 // STAP-A. https://tools.ietf.org/html/rfc6184#section-5.7.1
+                if access_unit.in_fu_a {
+                    return Err("STAP-A while fragment in progress".into());
+                }
                 loop {
                     if data.remaining() < 3 {
                         return Err(format!(
@@ -441,6 +475,13 @@ impl Depacketizer {
                     }
                 }
                 UnitType::SliceLayerWithoutPartitioningIdr => is_random_access_point = true,
+                UnitType::SEI => {
+                    let sei = to_bytes(nal.hdr, nal.len, nal_pieces);
+                    let rbsp = h264_reader::rbsp::decode_nal(&sei[1..]);
+                    if has_immediate_recovery_point(&rbsp[..]) {
+                        is_random_access_point = true;
+                    }
+                }
                 _ => {}
             }
             if nal.hdr.nal_ref_idc() != 0 {
@@ -477,7 +518,20 @@ impl Depacketizer {
             let sps_nal = new_sps.as_deref().unwrap_or(&self.parameters.sps_nal);
             let pps_nal = new_pps.as_deref().unwrap_or(&self.parameters.pps_nal);
             // TODO: could map this to a RtpPacketError more accurately.
-            self.parameters = InternalParameters::parse_sps_and_pps(sps_nal, pps_nal)?;
+            let parameters = InternalParameters::parse_sps_and_pps(sps_nal, pps_nal)?;
+            let old_dims = self.parameters.generic_parameters.pixel_dimensions;
+            let new_dims = parameters.generic_parameters.pixel_dimensions;
+            if old_dims != new_dims {
+                log::info!(
+                    "H.264 resolution changed from {}x{} to {}x{} at ts {}",
+                    old_dims.0,
+                    old_dims.1,
+                    new_dims.0,
+                    new_dims.1,
+                    au.timestamp
+                );
+            }
+            self.parameters = parameters;
             Some(Box::new(self.parameters.generic_parameters.clone()))
         } else {
             None
@@ -491,6 +545,8 @@ impl Depacketizer {
             stream_id: au.stream_id,
             is_random_access_point,
             is_disposable,
+            duration: None,
+            discontinuity: false,
             data,
         })
     }
@@ -510,6 +566,45 @@ fn can_end_au(nal_unit_type: UnitType) -> bool {
     nal_unit_type != UnitType::SeqParameterSet && nal_unit_type != UnitType::PicParameterSet
 }
 
+/// Returns true if the given SEI RBSP contains a recovery point message
+/// (payload type 6) whose recovery count is zero, meaning pictures are
+/// correct in content starting from the associated picture.
+///
+/// The recovery count is the first syntax element of the message:
+/// `recovery_frame_cnt` in H.264 section D.1.8, `recovery_poc_cnt` in H.265
+/// section D.2.8. Either way, a value of zero is coded as a single `1` bit.
+pub(super) fn has_immediate_recovery_point(mut sei: &[u8]) -> bool {
+    // Each message is a payload type and size, each coded as a run of 0xFF
+    // bytes plus a final byte, then the payload. See H.264 section 7.3.2.3.1.
+    fn read_value(sei: &mut &[u8]) -> Option<usize> {
+        let mut v = 0usize;
+        loop {
+            let (&b, rest) = sei.split_first()?;
+            *sei = rest;
+            v += usize::from(b);
+            if b != 0xFF {
+                return Some(v);
+            }
+        }
+    }
+    // Stop at the rbsp_trailing_bits.
+    while !sei.is_empty() && sei[..] != [0x80] {
+        let (payload_type, payload_size) = match (read_value(&mut sei), read_value(&mut sei)) {
+            (Some(t), Some(s)) => (t, s),
+            _ => return false,
+        };
+        if payload_size > sei.len() {
+            return false;
+        }
+        let (payload, rest) = sei.split_at(payload_size);
+        if payload_type == 6 {
+            return matches!(payload.first(), Some(b) if (b & 0x80) != 0);
+        }
+        sei = rest;
+    }
+    false
+}
+
 impl AccessUnit {
     fn start(
         pkt: &crate::client::rtp::Packet,
@@ -522,6 +617,7 @@ impl AccessUnit {
             timestamp: pkt.timestamp,
             stream_id: pkt.stream_id,
             in_fu_a: false,
+            len: 0,
 
             // TODO: overflow?
             loss: pkt.loss + additional_loss,
@@ -717,6 +813,12 @@ impl InternalParameters {
         let avc_decoder_config = avc_decoder_config.freeze();
         let sps_nal = avc_decoder_config.slice(sps_nal_start..sps_nal_end);
         let pps_nal = avc_decoder_config.slice(pps_nal_start..pps_nal_end);
+        let mut avcc_box = BytesMut::with_capacity(8 + avc_decoder_config.len());
+        write_box!(&mut avcc_box, b"avcC", {
+            avcc_box.extend_from_slice(&avc_decoder_config[..]);
+        });
+        let sample_entry =
+            super::make_visual_sample_entry(b"avc1", pixel_dimensions, &avcc_box[..])?;
         Ok(InternalParameters {
             generic_parameters: super::VideoParameters {
                 rfc6381_codec,
@@ -724,6 +826,8 @@ impl InternalParameters {
                 pixel_aspect_ratio,
                 frame_rate,
                 extra_data: avc_decoder_config,
+                parameter_sets: vec![sps_nal.clone(), pps_nal.clone()],
+                sample_entry: Some(sample_entry),
             },
             sps_nal,
             pps_nal,
@@ -853,6 +957,8 @@ impl Packetizer {
                         sequence_number,
                         loss: 0,
                         mark: false,
+                        discontinuity: false,
+                        header: Bytes::new(),
                         payload: Bytes::from(payload),
                     }));
                 }
@@ -876,6 +982,8 @@ impl Packetizer {
                     sequence_number,
                     loss: 0,
                     mark,
+                    discontinuity: false,
+                    header: Bytes::new(),
                     payload: data,
                 }))
             }
@@ -928,6 +1036,8 @@ impl Packetizer {
                     sequence_number,
                     loss: 0,
                     mark,
+                    discontinuity: false,
+                    header: Bytes::new(),
                     payload: Bytes::from(payload),
                 }))
             }
@@ -1017,6 +1127,64 @@ mod tests {
     }
      */
 
+    #[test]
+    fn max_frame_bytes() {
+        let mut d = super::Depacketizer::new(90_000, Some("packetization-mode=1;profile-level-id=64001E;sprop-parameter-sets=Z2QAHqwsaoLA9puCgIKgAAADACAAAAMD0IAA,aO4xshsA")).unwrap();
+        d.set_max_frame_bytes(100);
+        let pkt = |ts: i64, sequence_number: u16, mark: bool, payload: &'static [u8]| Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: crate::Timestamp {
+                timestamp: ts,
+                clock_rate: NonZeroU32::new(90_000).unwrap(),
+                start: 0,
+            },
+            ssrc: 0,
+            sequence_number,
+            loss: 0,
+            mark,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(payload),
+        };
+
+        // An endless FU-A fails rather than buffering without bound.
+        d.push(pkt(
+            0,
+            0,
+            false,
+            b"\x7c\x85 40 bytes of IDR slice data...........",
+        ))
+        .unwrap();
+        d.push(pkt(
+            0,
+            1,
+            false,
+            b"\x7c\x05 40 bytes of IDR slice data...........",
+        ))
+        .unwrap();
+        let e = d
+            .push(pkt(
+                0,
+                2,
+                false,
+                b"\x7c\x05 40 bytes of IDR slice data...........",
+            ))
+            .unwrap_err();
+        assert!(e.contains("max_frame_bytes=100"), "{}", e);
+        assert!(d.pull().is_none());
+
+        // The remainder of that access unit is discarded, and the next one
+        // reports loss.
+        d.push(pkt(0, 3, true, b"\x7c\x45end")).unwrap();
+        assert!(d.pull().is_none());
+        d.push(pkt(3000, 4, true, b"\x01slice")).unwrap();
+        match d.pull() {
+            Some(CodecItem::VideoFrame(f)) => assert_eq!(f.loss, 1),
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
     #[test]
     fn depacketize() {
         let mut d = super::Depacketizer::new(90_000, Some("packetization-mode=1;profile-level-id=64001E;sprop-parameter-sets=Z2QAHqwsaoLA9puCgIKgAAADACAAAAMD0IAA,aO4xshsA")).unwrap();
@@ -1034,6 +1202,8 @@ mod tests {
             sequence_number: 0,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x06plain"),
         })
         .unwrap();
@@ -1047,6 +1217,8 @@ mod tests {
             sequence_number: 1,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x18\x00\x09\x06stap-a 1\x00\x09\x06stap-a 2"),
         })
         .unwrap();
@@ -1060,6 +1232,8 @@ mod tests {
             sequence_number: 2,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x7c\x86fu-a start, "),
         })
         .unwrap();
@@ -1073,6 +1247,8 @@ mod tests {
             sequence_number: 3,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x7c\x06fu-a middle, "),
         })
         .unwrap();
@@ -1086,6 +1262,8 @@ mod tests {
             sequence_number: 4,
             loss: 0,
             mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x7c\x46fu-a end"),
         })
         .unwrap();
@@ -1127,6 +1305,8 @@ mod tests {
             sequence_number: 0,
             loss: 0,
             mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x67\x64\x00\x33\xac\x15\x14\xa0\xa0\x2f\xf9\x50"),
         })
         .unwrap();
@@ -1140,6 +1320,8 @@ mod tests {
             sequence_number: 1,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x68\xee\x3c\xb0"),
         })
         .unwrap();
@@ -1157,6 +1339,8 @@ mod tests {
             sequence_number: 2,
             loss: 0,
             mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x65slice"),
         })
         .unwrap();
@@ -1199,6 +1383,8 @@ mod tests {
             sequence_number: 0,
             loss: 0,
             mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x01slice"),
         })
         .unwrap();
@@ -1217,6 +1403,8 @@ mod tests {
             sequence_number: 1,
             loss: 0,
             mark: false, // correctly has no mark, unlike first SPS in stream.
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x67\x64\x00\x33\xac\x15\x14\xa0\xa0\x2f\xf9\x50"),
         })
         .unwrap();
@@ -1230,6 +1418,8 @@ mod tests {
             sequence_number: 2,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x68\xee\x3c\xb0"),
         })
         .unwrap();
@@ -1243,6 +1433,8 @@ mod tests {
             sequence_number: 3,
             loss: 0,
             mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x65slice"),
         })
         .unwrap();
@@ -1281,6 +1473,8 @@ mod tests {
             sequence_number: 0,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x67\x4d\x40\x1e\x9a\x64\x05\x01\xef\xf3\x50\x10\x10\x14\x00\x00\x0f\xa0\x00\x01\x38\x80\x10"),
         }).unwrap();
         assert!(d.pull().is_none());
@@ -1293,6 +1487,8 @@ mod tests {
             sequence_number: 1,
             loss: 0,
             mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x68\xee\x3c\x80"),
         })
         .unwrap();
@@ -1306,6 +1502,8 @@ mod tests {
             sequence_number: 2,
             loss: 0,
             mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
             payload: Bytes::from_static(b"\x65slice"),
         })
         .unwrap();
@@ -1316,6 +1514,363 @@ mod tests {
         assert!(frame.new_parameters.is_some());
         let p = frame.new_parameters.unwrap();
         assert_eq!(p.pixel_dimensions(), (640, 480));
+        assert_eq!(
+            p.parameter_sets(),
+            &[
+                Bytes::from_static(b"\x67\x4d\x40\x1e\x9a\x64\x05\x01\xef\xf3\x50\x10\x10\x14\x00\x00\x0f\xa0\x00\x01\x38\x80\x10"),
+                Bytes::from_static(b"\x68\xee\x3c\x80"),
+            ]
+        );
+    }
+
+    /// Pushes one packet per NAL of an access unit with the given timestamp and
+    /// returns the resulting frame.
+    fn push_au(
+        d: &mut super::Depacketizer,
+        sequence_number: &mut u16,
+        ts: i64,
+        nals: &[&'static [u8]],
+    ) -> crate::codec::VideoFrame {
+        let timestamp = crate::Timestamp {
+            timestamp: ts,
+            clock_rate: NonZeroU32::new(90_000).unwrap(),
+            start: 0,
+        };
+        for (i, nal) in nals.iter().enumerate() {
+            d.push(Packet {
+                ctx: crate::PacketContext::dummy(),
+                stream_id: 0,
+                timestamp,
+                ssrc: 0,
+                sequence_number: *sequence_number,
+                loss: 0,
+                mark: i == nals.len() - 1,
+                discontinuity: false,
+                header: Bytes::new(),
+                payload: Bytes::from_static(nal),
+            })
+            .unwrap();
+            *sequence_number = sequence_number.wrapping_add(1);
+        }
+        match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn has_new_parameters() {
+        let mut d = super::Depacketizer::new(90_000, Some("a=fmtp:96 profile-level-id=420029; packetization-mode=1; sprop-parameter-sets=Z01AHppkBYHv/lBgYGQAAA+gAAE4gBA=,aO48gA==")).unwrap();
+        let mut sequence_number = 0;
+        let mut push_au = |ts, nals: &[&'static [u8]]| {
+            push_au(&mut d, &mut sequence_number, ts, nals).has_new_parameters()
+        };
+        assert!(!push_au(0, &[b"\x65slice"]));
+
+        // Repeating the SDP's parameters doesn't count as a change.
+        assert!(!push_au(
+            3000,
+            &[
+                b"\x67\x4d\x40\x1e\x9a\x64\x05\x81\xef\xfe\x50\x60\x60\x64\x00\x00\x0f\xa0\x00\x01\x38\x80\x10",
+                b"\x68\xee\x3c\x80",
+                b"\x65slice",
+            ]
+        ));
+
+        // A new SPS sets the flag on its frame only.
+        assert!(push_au(
+            6000,
+            &[
+                b"\x67\x4d\x40\x1e\x9a\x64\x05\x01\xef\xf3\x50\x10\x10\x14\x00\x00\x0f\xa0\x00\x01\x38\x80\x10",
+                b"\x68\xee\x3c\x80",
+                b"\x65slice",
+            ]
+        ));
+        assert!(!push_au(9000, &[b"\x41slice"]));
+        assert!(!push_au(
+            12000,
+            &[
+                b"\x67\x4d\x40\x1e\x9a\x64\x05\x01\xef\xf3\x50\x10\x10\x14\x00\x00\x0f\xa0\x00\x01\x38\x80\x10",
+                b"\x68\xee\x3c\x80",
+                b"\x65slice",
+            ]
+        ));
+    }
+
+    #[test]
+    fn random_access_point() {
+        let mut d = super::Depacketizer::new(90_000, Some("packetization-mode=1;profile-level-id=640033;sprop-parameter-sets=Z2QAM6wVFKAoAPGQ,aO48sA==")).unwrap();
+        let mut sequence_number = 0;
+        let mut push_au = |ts, nals: &[&'static [u8]]| {
+            push_au(&mut d, &mut sequence_number, ts, nals).is_random_access_point()
+        };
+        assert!(push_au(0, &[b"\x65idr slice"]));
+        assert!(!push_au(3000, &[b"\x41non-idr slice"]));
+
+        // A recovery point SEI with recovery_frame_cnt=0 makes a non-IDR
+        // picture a random access point; a non-zero count doesn't.
+        assert!(push_au(
+            6000,
+            &[b"\x06\x06\x01\xc0\x80", b"\x41non-idr slice"]
+        ));
+        assert!(!push_au(
+            9000,
+            &[b"\x06\x06\x01\x40\x80", b"\x41non-idr slice"]
+        ));
+
+        // Other SEI messages don't either.
+        assert!(!push_au(
+            12000,
+            &[b"\x06\x05\x01\xc0\x80", b"\x41non-idr slice"]
+        ));
+    }
+
+    /// Checks extracting a SPS and PPS from a STAP-A.
+    #[test]
+    fn depacketize_stap_a() {
+        const SPS_640X480: &[u8] = b"\x67\x4d\x40\x1e\x9a\x64\x05\x01\xef\xf3\x50\x10\x10\x14\x00\x00\x0f\xa0\x00\x01\x38\x80\x10";
+        const PPS: &[u8] = b"\x68\xee\x3c\x80";
+        let mut d = super::Depacketizer::new(90_000, Some("a=fmtp:96 profile-level-id=420029; packetization-mode=1; sprop-parameter-sets=Z01AHppkBYHv/lBgYGQAAA+gAAE4gBA=,aO48gA==")).unwrap();
+        let timestamp = crate::Timestamp {
+            timestamp: 0,
+            clock_rate: NonZeroU32::new(90_000).unwrap(),
+            start: 0,
+        };
+        let mut stap_a = vec![0x18]; // STAP-A NAL header.
+        for nal in [SPS_640X480, PPS] {
+            stap_a.extend_from_slice(&(nal.len() as u16).to_be_bytes()[..]);
+            stap_a.extend_from_slice(nal);
+        }
+        d.push(Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp,
+            ssrc: 0,
+            sequence_number: 0,
+            loss: 0,
+            mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: stap_a.into(),
+        })
+        .unwrap();
+        assert!(d.pull().is_none());
+        d.push(Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp,
+            ssrc: 0,
+            sequence_number: 1,
+            loss: 0,
+            mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"\x65slice"),
+        })
+        .unwrap();
+        let frame = match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        };
+        let p = frame.new_parameters.as_ref().unwrap();
+        assert_eq!(p.pixel_dimensions(), (640, 480));
+        assert_eq!(
+            p.parameter_sets(),
+            &[Bytes::from_static(SPS_640X480), Bytes::from_static(PPS)]
+        );
+
+        // The frame data holds all three NALs, each with a 4-byte length prefix.
+        let mut expected = Vec::new();
+        for nal in [SPS_640X480, PPS, &b"\x65slice"[..]] {
+            expected.extend_from_slice(&(nal.len() as u32).to_be_bytes()[..]);
+            expected.extend_from_slice(nal);
+        }
+        assert_eq!(&frame.data()[..], &expected[..]);
+    }
+
+    /// Checks that STAP-A lengths which exceed the payload are rejected.
+    #[test]
+    fn depacketize_stap_a_too_short() {
+        let mut d = super::Depacketizer::new(90_000, Some("a=fmtp:96 profile-level-id=420029; packetization-mode=1; sprop-parameter-sets=Z01AHppkBYHv/lBgYGQAAA+gAAE4gBA=,aO48gA==")).unwrap();
+        let e = d
+            .push(Packet {
+                ctx: crate::PacketContext::dummy(),
+                stream_id: 0,
+                timestamp: crate::Timestamp {
+                    timestamp: 0,
+                    clock_rate: NonZeroU32::new(90_000).unwrap(),
+                    start: 0,
+                },
+                ssrc: 0,
+                sequence_number: 0,
+                loss: 0,
+                mark: true,
+                discontinuity: false,
+                // STAP-A claiming a 5-byte NAL, with only 4 bytes present.
+                header: Bytes::new(),
+                payload: Bytes::from_static(b"\x18\x00\x05\x68\xee\x3c\x80"),
+            })
+            .unwrap_err();
+        assert!(e.contains("STAP-A too short"), "{}", e);
+    }
+
+    /// Checks that a FU-A with a lost middle fragment is discarded rather than
+    /// emitted as a corrupt NAL, and that the loss is recorded on the next frame.
+    #[test]
+    fn depacketize_fu_a_gap() {
+        let mut d = super::Depacketizer::new(90_000, Some("packetization-mode=1;profile-level-id=640033;sprop-parameter-sets=Z2QAM6zoAoALWQ==,aO48MA==")).unwrap();
+        let ts = |timestamp| crate::Timestamp {
+            timestamp,
+            clock_rate: NonZeroU32::new(90_000).unwrap(),
+            start: 0,
+        };
+        d.push(Packet {
+            // FU-A start.
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: ts(0),
+            ssrc: 0,
+            sequence_number: 0,
+            loss: 0,
+            mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"\x7c\x85idr slice start"),
+        })
+        .unwrap();
+        assert!(d.pull().is_none());
+
+        // The middle fragment (sequence number 1) is lost.
+        d.push(Packet {
+            // FU-A end.
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: ts(0),
+            ssrc: 0,
+            sequence_number: 2,
+            loss: 1,
+            mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"\x7c\x45idr slice end"),
+        })
+        .unwrap();
+        assert!(d.pull().is_none());
+
+        d.push(Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: ts(3000),
+            ssrc: 0,
+            sequence_number: 3,
+            loss: 0,
+            mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"\x41slice"),
+        })
+        .unwrap();
+        let frame = match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        };
+        assert_eq!(frame.loss, 1);
+        assert_eq!(frame.timestamp.timestamp, 3000);
+        assert_eq!(&frame.data()[..], b"\x00\x00\x00\x06\x41slice");
+    }
+
+    /// Checks that a FU-A whose end fragment is lost is discarded when the
+    /// next access unit starts.
+    #[test]
+    fn depacketize_fu_a_lost_end() {
+        let mut d = super::Depacketizer::new(90_000, Some("packetization-mode=1;profile-level-id=640033;sprop-parameter-sets=Z2QAM6zoAoALWQ==,aO48MA==")).unwrap();
+        let ts = |timestamp| crate::Timestamp {
+            timestamp,
+            clock_rate: NonZeroU32::new(90_000).unwrap(),
+            start: 0,
+        };
+        d.push(Packet {
+            // FU-A start.
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: ts(0),
+            ssrc: 0,
+            sequence_number: 0,
+            loss: 0,
+            mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"\x7c\x85idr slice start"),
+        })
+        .unwrap();
+        assert!(d.pull().is_none());
+
+        // The end fragment (sequence number 1) is lost.
+        d.push(Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: ts(3000),
+            ssrc: 0,
+            sequence_number: 2,
+            loss: 1,
+            mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"\x41slice"),
+        })
+        .unwrap();
+        let frame = match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        };
+        assert_eq!(frame.loss, 1);
+        assert_eq!(&frame.data()[..], b"\x00\x00\x00\x06\x41slice");
+    }
+
+    /// Checks that each mid-stream resolution change is signaled exactly once.
+    #[test]
+    fn depacketize_resolution_changes() {
+        const SPS_704X480: &[u8] = b"\x67\x4d\x40\x1e\x9a\x64\x05\x81\xef\xfe\x50\x60\x60\x64\x00\x00\x0f\xa0\x00\x01\x38\x80\x10";
+        const SPS_640X480: &[u8] = b"\x67\x4d\x40\x1e\x9a\x64\x05\x01\xef\xf3\x50\x10\x10\x14\x00\x00\x0f\xa0\x00\x01\x38\x80\x10";
+        let mut d = super::Depacketizer::new(90_000, Some("a=fmtp:96 profile-level-id=420029; packetization-mode=1; sprop-parameter-sets=Z01AHppkBYHv/lBgYGQAAA+gAAE4gBA=,aO48gA==")).unwrap();
+        let mut seq = 0;
+        let mut push_au = |d: &mut super::Depacketizer, ts: i64, nals: &[&'static [u8]]| {
+            let timestamp = crate::Timestamp {
+                timestamp: ts,
+                clock_rate: NonZeroU32::new(90_000).unwrap(),
+                start: 0,
+            };
+            for (i, nal) in nals.iter().enumerate() {
+                d.push(Packet {
+                    ctx: crate::PacketContext::dummy(),
+                    stream_id: 0,
+                    timestamp,
+                    ssrc: 0,
+                    sequence_number: seq,
+                    loss: 0,
+                    mark: i == nals.len() - 1,
+                    discontinuity: false,
+                    header: Bytes::new(),
+                    payload: Bytes::from_static(nal),
+                })
+                .unwrap();
+                seq += 1;
+            }
+            match d.pull() {
+                Some(CodecItem::VideoFrame(frame)) => frame,
+                o => panic!("unexpected pull result {:#?}", o),
+            }
+        };
+
+        let frame = push_au(&mut d, 0, &[SPS_640X480, b"\x68\xee\x3c\x80", b"\x65slice"]);
+        assert_eq!(frame.new_parameters.unwrap().pixel_dimensions(), (640, 480));
+
+        // Repeating the same SPS isn't a change.
+        let frame = push_au(&mut d, 3000, &[SPS_640X480, b"\x65slice"]);
+        assert!(frame.new_parameters.is_none());
+
+        let frame = push_au(&mut d, 6000, &[SPS_704X480, b"\x65slice"]);
+        assert_eq!(frame.new_parameters.unwrap().pixel_dimensions(), (704, 480));
     }
 
     /// Tests parsing parameters from GW Security camera, which erroneously puts
@@ -1334,4 +1889,21 @@ mod tests {
         );
         assert_eq!(&params.pps_nal[..], b"\x68\xee\x3c\x80");
     }
+
+    #[test]
+    fn parameter_sets() {
+        let params = super::InternalParameters::parse_format_specific_params(
+            "packetization-mode=1;\
+             profile-level-id=640033;\
+             sprop-parameter-sets=Z2QAM6zoAoALWQ==,aO48MA==",
+        )
+        .unwrap();
+        assert_eq!(
+            params.generic_parameters.parameter_sets(),
+            &[
+                Bytes::from_static(b"\x67\x64\x00\x33\xac\xe8\x02\x80\x0b\x59"),
+                Bytes::from_static(b"\x68\xee\x3c\x30"),
+            ]
+        );
+    }
 }
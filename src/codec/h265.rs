@@ -0,0 +1,1062 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! [H.265](https://www.itu.int/rec/T-REC-H.265)-encoded video.
+
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use log::{debug, log_enabled, trace};
+
+use crate::client::rtp::Packet;
+
+use super::VideoFrame;
+
+/// NAL unit types of interest, from H.265 table 7-1 and RFC 7798 section 4.4.
+mod nal_type {
+    pub(super) const VPS: u8 = 32;
+    pub(super) const SPS: u8 = 33;
+    pub(super) const PPS: u8 = 34;
+    pub(super) const PREFIX_SEI: u8 = 39;
+    pub(super) const AP: u8 = 48;
+    pub(super) const FU: u8 = 49;
+    pub(super) const PACI: u8 = 50;
+}
+
+/// Returns the NAL unit type from the first byte of a NAL header.
+fn nal_unit_type(hdr0: u8) -> u8 {
+    (hdr0 >> 1) & 0b111111
+}
+
+/// A [super::Depacketizer] implementation which finds access unit boundaries
+/// and produces unfragmented NAL units as specified in [RFC
+/// 7798](https://datatracker.ietf.org/doc/html/rfc7798).
+///
+/// Like the H.264 depacketizer, this doesn't inspect the contents of the NAL
+/// units (beyond parameter sets), and on packet loss within an access unit,
+/// discards the whole access unit and reports the loss on the next frame.
+///
+/// Only supports `sprop-max-don-diff=0`, so there are no decoding order number
+/// (DONL/DOND) fields. PACI packets are unsupported.
+#[derive(Debug)]
+pub(crate) struct Depacketizer {
+    input_state: DepacketizerInputState,
+
+    /// A complete video frame ready for pull.
+    pending: Option<VideoFrame>,
+
+    parameters: InternalParameters,
+
+    /// In state `PreMark`, pieces of NALs, excluding their two-byte headers.
+    /// Kept around (empty) in other states to re-use the backing allocation.
+    pieces: Vec<Bytes>,
+
+    /// In state `PreMark`, an entry for each NAL.
+    /// Kept around (empty) in other states to re-use the backing allocation.
+    nals: Vec<Nal>,
+
+    /// The maximum total payload size of an access unit; see
+    /// [`crate::client::SessionOptions::max_frame_bytes`].
+    max_frame_bytes: usize,
+}
+
+#[derive(Debug)]
+struct Nal {
+    hdr: [u8; 2],
+
+    /// The length of `Depacketizer::pieces` as this NAL finishes.
+    next_piece_idx: u32,
+
+    /// The total length of this NAL, including the header bytes.
+    len: u32,
+}
+
+/// An access unit that is currently being accumulated during `PreMark` state.
+#[derive(Debug)]
+struct AccessUnit {
+    start_ctx: crate::PacketContext,
+    end_ctx: crate::PacketContext,
+    timestamp: crate::Timestamp,
+    stream_id: usize,
+
+    /// True iff currently processing a FU.
+    in_fu: bool,
+
+    /// Total size of the RTP payloads accumulated so far.
+    len: usize,
+
+    /// RTP packets lost as this access unit was starting.
+    loss: u16,
+
+    same_ts_as_prev: bool,
+}
+
+#[derive(Debug)]
+#[allow(clippy::large_enum_variant)]
+enum DepacketizerInputState {
+    /// Not yet processing an access unit.
+    New,
+
+    /// Ignoring the remainder of an access unit because of interior packet loss.
+    Loss {
+        timestamp: crate::Timestamp,
+        pkts: u16,
+    },
+
+    /// Currently processing an access unit.
+    /// This will be flushed after a marked packet or when receiving a later timestamp.
+    PreMark(AccessUnit),
+
+    /// Finished processing the given packet. It's an error to receive the same timestamp again.
+    PostMark {
+        timestamp: crate::Timestamp,
+        loss: u16,
+    },
+}
+
+impl Depacketizer {
+    pub(super) fn new(
+        clock_rate: u32,
+        format_specific_params: Option<&str>,
+    ) -> Result<Self, String> {
+        if clock_rate != 90_000 {
+            return Err(format!(
+                "invalid H.265 clock rate {}; must always be 90000",
+                clock_rate
+            ));
+        }
+        let format_specific_params = format_specific_params
+            .ok_or_else(|| "H.265 depacketizer expects out-of-band parameters".to_owned())?;
+        Ok(Depacketizer {
+            input_state: DepacketizerInputState::New,
+            pending: None,
+            pieces: Vec::new(),
+            nals: Vec::new(),
+            parameters: InternalParameters::parse_format_specific_params(format_specific_params)?,
+            max_frame_bytes: super::DEFAULT_MAX_FRAME_BYTES,
+        })
+    }
+
+    pub(super) fn set_max_frame_bytes(&mut self, max_frame_bytes: usize) {
+        self.max_frame_bytes = max_frame_bytes;
+    }
+
+    pub(super) fn parameters(&self) -> Option<super::Parameters> {
+        Some(super::Parameters::Video(
+            self.parameters.generic_parameters.clone(),
+        ))
+    }
+
+    pub(super) fn push(&mut self, pkt: Packet) -> Result<(), String> {
+        // Push shouldn't be called until pull is exhausted.
+        if let Some(p) = self.pending.as_ref() {
+            panic!("push with data already pending: {:?}", p);
+        }
+
+        let mut access_unit =
+            match std::mem::replace(&mut self.input_state, DepacketizerInputState::New) {
+                DepacketizerInputState::New => {
+                    // There may be leftovers if the previous push failed.
+                    self.pieces.clear();
+                    self.nals.clear();
+                    AccessUnit::start(&pkt, 0, false)
+                }
+                DepacketizerInputState::PreMark(mut access_unit) => {
+                    if pkt.loss > 0 {
+                        self.pieces.clear();
+                        self.nals.clear();
+                        if access_unit.timestamp.timestamp == pkt.timestamp.timestamp {
+                            // Loss within this access unit. Ignore until mark or new timestamp.
+                            self.input_state = if pkt.mark {
+                                DepacketizerInputState::PostMark {
+                                    timestamp: pkt.timestamp,
+                                    loss: pkt.loss,
+                                }
+                            } else {
+                                DepacketizerInputState::Loss {
+                                    timestamp: pkt.timestamp,
+                                    pkts: pkt.loss,
+                                }
+                            };
+                            return Ok(());
+                        }
+                        // A suffix of a previous access unit was lost; discard it.
+                        // A prefix of the new one may have been lost; try parsing.
+                        AccessUnit::start(&pkt, 0, false)
+                    } else if access_unit.timestamp.timestamp != pkt.timestamp.timestamp {
+                        if access_unit.in_fu {
+                            return Err(format!(
+                                "Timestamp changed from {} to {} in the middle of a fragmented NAL",
+                                access_unit.timestamp, pkt.timestamp
+                            ));
+                        }
+                        access_unit.end_ctx = pkt.ctx;
+                        self.pending = Some(self.finalize_access_unit(access_unit, "ts change")?);
+                        AccessUnit::start(&pkt, 0, false)
+                    } else {
+                        access_unit
+                    }
+                }
+                DepacketizerInputState::PostMark {
+                    timestamp: state_ts,
+                    loss,
+                } => {
+                    debug_assert!(self.pieces.is_empty());
+                    AccessUnit::start(&pkt, loss, state_ts.timestamp == pkt.timestamp.timestamp)
+                }
+                DepacketizerInputState::Loss {
+                    timestamp,
+                    mut pkts,
+                } => {
+                    debug_assert!(self.pieces.is_empty());
+                    if pkt.timestamp.timestamp == timestamp.timestamp {
+                        pkts += pkt.loss;
+                        self.input_state = DepacketizerInputState::Loss { timestamp, pkts };
+                        return Ok(());
+                    }
+                    AccessUnit::start(&pkt, pkts, false)
+                }
+            };
+
+        let mut data = pkt.payload;
+        if data.len() < 3 {
+            return Err(format!(
+                "H.265 RTP payload of {} bytes is too short",
+                data.len()
+            ));
+        }
+        access_unit.len += data.len();
+        if access_unit.len > self.max_frame_bytes {
+            self.pieces.clear();
+            self.nals.clear();
+            self.input_state = DepacketizerInputState::Loss {
+                timestamp: pkt.timestamp,
+
+                // Count this packet so the next access unit reports loss.
+                pkts: access_unit.loss.saturating_add(1),
+            };
+            return Err(format!(
+                "access unit at {} exceeds max_frame_bytes={}",
+                access_unit.timestamp, self.max_frame_bytes
+            ));
+        }
+        // https://datatracker.ietf.org/doc/html/rfc7798#section-1.1.4
+        let hdr = [data[0], data[1]];
+        if (hdr[0] >> 7) != 0 {
+            return Err(format!("NAL header {:02x?} has F bit set", hdr));
+        }
+        match nal_unit_type(hdr[0]) {
+            0..=47 => {
+                // Single NAL unit packet. https://datatracker.ietf.org/doc/html/rfc7798#section-4.4.1
+                if access_unit.in_fu {
+                    return Err(format!(
+                        "Non-fragmented NAL {:02x?} while fragment in progress",
+                        hdr
+                    ));
+                }
+                data.advance(2);
+                let len = u32::try_from(data.len()).expect("data len < u16::MAX") + 2;
+                let next_piece_idx = self.add_piece(data)?;
+                self.nals.push(Nal {
+                    hdr,
+                    next_piece_idx,
+                    len,
+                });
+            }
+            nal_type::AP => {
+                // Aggregation packet. https://datatracker.ietf.org/doc/html/rfc7798#section-4.4.2
+                if access_unit.in_fu {
+                    return Err("AP while fragment in progress".into());
+                }
+                data.advance(2);
+                while data.has_remaining() {
+                    if data.remaining() < 2 {
+                        return Err(format!(
+                            "AP has {} remaining bytes; expecting 2-byte length",
+                            data.remaining()
+                        ));
+                    }
+                    let len = usize::from(data.get_u16());
+                    if len < 2 {
+                        return Err(format!("AP has NAL of length {}", len));
+                    }
+                    if data.remaining() < len {
+                        return Err(format!(
+                            "AP too short: {} bytes remaining, expecting {}-byte NAL",
+                            data.remaining(),
+                            len
+                        ));
+                    }
+                    let mut nal = data.split_to(len);
+                    if (nal[0] >> 7) != 0 {
+                        return Err(format!("NAL header {:02x?} in AP has F bit set", &nal[..2]));
+                    }
+                    let hdr = [nal[0], nal[1]];
+                    nal.advance(2);
+                    let next_piece_idx = self.add_piece(nal)?;
+                    self.nals.push(Nal {
+                        hdr,
+                        next_piece_idx,
+                        len: u32::try_from(len).expect("len is a u16"),
+                    });
+                }
+            }
+            nal_type::FU => {
+                // Fragmentation unit. https://datatracker.ietf.org/doc/html/rfc7798#section-4.4.3
+                let fu_header = data[2];
+                let start = (fu_header & 0b10000000) != 0;
+                let end = (fu_header & 0b01000000) != 0;
+                let fu_type = fu_header & 0b00111111;
+                let nal_hdr = [(hdr[0] & 0b10000001) | (fu_type << 1), hdr[1]];
+                data.advance(3);
+                if start && end {
+                    return Err(format!("Invalid FU header {:02x}", fu_header));
+                }
+                if !end && pkt.mark {
+                    return Err("FU pkt with MARK && !END".into());
+                }
+                let u32_len = u32::try_from(data.len()).expect("RTP packet len must be < u16::MAX");
+                match (start, access_unit.in_fu) {
+                    (true, true) => return Err("FU with start bit while frag in progress".into()),
+                    (true, false) => {
+                        self.add_piece(data)?;
+                        self.nals.push(Nal {
+                            hdr: nal_hdr,
+                            next_piece_idx: u32::MAX, // should be overwritten later.
+                            len: 2 + u32_len,
+                        });
+                        access_unit.in_fu = true;
+                    }
+                    (false, true) => {
+                        let pieces = self.add_piece(data)?;
+                        let nal = self.nals.last_mut().expect("nals non-empty while in fu");
+                        if nal.hdr != nal_hdr {
+                            return Err(format!(
+                                "FU has inconsistent NAL header: {:02x?} then {:02x?}",
+                                nal.hdr, nal_hdr,
+                            ));
+                        }
+                        nal.len += u32_len;
+                        if end {
+                            nal.next_piece_idx = pieces;
+                            access_unit.in_fu = false;
+                        }
+                    }
+                    (false, false) => {
+                        if pkt.loss > 0 {
+                            self.pieces.clear();
+                            self.nals.clear();
+                            self.input_state = DepacketizerInputState::Loss {
+                                timestamp: pkt.timestamp,
+                                pkts: pkt.loss,
+                            };
+                            return Ok(());
+                        }
+                        return Err("FU has start bit unset while no frag in progress".into());
+                    }
+                }
+            }
+            nal_type::PACI => return Err("unimplemented PACI packet".into()),
+            t => return Err(format!("bad NAL type {} in header {:02x?}", t, hdr)),
+        }
+        self.input_state = if pkt.mark {
+            let last_nal_type = nal_unit_type(self.nals.last().unwrap().hdr[0]);
+            if can_end_au(last_nal_type) {
+                access_unit.end_ctx = pkt.ctx;
+                self.pending = Some(self.finalize_access_unit(access_unit, "mark")?);
+                DepacketizerInputState::PostMark {
+                    timestamp: pkt.timestamp,
+                    loss: 0,
+                }
+            } else {
+                log::debug!(
+                    "Bogus mid-access unit mark after NAL type {}",
+                    last_nal_type
+                );
+                DepacketizerInputState::PreMark(access_unit)
+            }
+        } else {
+            DepacketizerInputState::PreMark(access_unit)
+        };
+        Ok(())
+    }
+
+    pub(super) fn pull(&mut self) -> Option<super::CodecItem> {
+        self.pending.take().map(super::CodecItem::VideoFrame)
+    }
+
+    /// Adds a piece to `self.pieces`, erroring if it becomes absurdly large.
+    fn add_piece(&mut self, piece: Bytes) -> Result<u32, String> {
+        self.pieces.push(piece);
+        u32::try_from(self.pieces.len()).map_err(|_| "more than u32::MAX pieces!".to_string())
+    }
+
+    fn finalize_access_unit(&mut self, au: AccessUnit, reason: &str) -> Result<VideoFrame, String> {
+        let mut retained_len = 0usize;
+        let mut is_random_access_point = false;
+        let mut is_disposable = true;
+        let mut new_vps = None;
+        let mut new_sps = None;
+        let mut new_pps = None;
+
+        if log_enabled!(log::Level::Trace) {
+            let mut nals = String::new();
+            for (i, nal) in self.nals.iter().enumerate() {
+                let _ = write!(&mut nals, "\n  {}: type {}", i, nal_unit_type(nal.hdr[0]));
+            }
+            trace!(
+                "access unit (ended by {}) at ts {}; NALS are:{}",
+                reason,
+                au.timestamp,
+                nals
+            );
+        }
+        if au.same_ts_as_prev {
+            debug!(
+                "access unit (ended by {}) at ts {} has same timestamp as previous",
+                reason, au.timestamp
+            );
+        }
+        let mut piece_idx = 0;
+        for nal in &self.nals {
+            let next_piece_idx = usize::try_from(nal.next_piece_idx).expect("u32 fits in usize");
+            let nal_pieces = &self.pieces[piece_idx..next_piece_idx];
+            match nal_unit_type(nal.hdr[0]) {
+                nal_type::VPS if !matches(&self.parameters.vps_nal[..], nal.hdr, nal_pieces) => {
+                    new_vps = Some(to_bytes(nal.hdr, nal.len, nal_pieces))
+                }
+                nal_type::SPS if !matches(&self.parameters.sps_nal[..], nal.hdr, nal_pieces) => {
+                    new_sps = Some(to_bytes(nal.hdr, nal.len, nal_pieces))
+                }
+                nal_type::PPS if !matches(&self.parameters.pps_nal[..], nal.hdr, nal_pieces) => {
+                    new_pps = Some(to_bytes(nal.hdr, nal.len, nal_pieces))
+                }
+                nal_type::PREFIX_SEI if nal.len > 2 => {
+                    let sei = to_bytes(nal.hdr, nal.len, nal_pieces);
+                    let rbsp = h264_reader::rbsp::decode_nal(&sei[2..]);
+                    if super::h264::has_immediate_recovery_point(&rbsp) {
+                        is_random_access_point = true;
+                    }
+                }
+
+                // IRAP pictures (BLA_W_LP through RSV_IRAP_VCL23) are always
+                // referenced.
+                16..=23 => {
+                    is_random_access_point = true;
+                    is_disposable = false;
+                }
+
+                // Other VCL NAL units. Sub-layer non-reference pictures
+                // (TRAIL_N, TSA_N, ..., RSV_VCL_N14) have even types <= 14.
+                t @ 0..=31 => is_disposable &= t % 2 == 0 && t <= 14,
+                _ => {}
+            }
+            retained_len += 4usize + usize::try_from(nal.len).expect("u32 fits in usize");
+            piece_idx = next_piece_idx;
+        }
+        let mut data = Vec::with_capacity(retained_len);
+        piece_idx = 0;
+        for nal in &self.nals {
+            let next_piece_idx = usize::try_from(nal.next_piece_idx).expect("u32 fits in usize");
+            let nal_pieces = &self.pieces[piece_idx..next_piece_idx];
+            data.extend_from_slice(&nal.len.to_be_bytes()[..]);
+            data.extend_from_slice(&nal.hdr[..]);
+            let mut actual_len = 2;
+            for piece in nal_pieces {
+                data.extend_from_slice(&piece[..]);
+                actual_len += piece.len();
+            }
+            debug_assert_eq!(
+                usize::try_from(nal.len).expect("u32 fits in usize"),
+                actual_len
+            );
+            piece_idx = next_piece_idx;
+        }
+        debug_assert_eq!(retained_len, data.len());
+        self.nals.clear();
+        self.pieces.clear();
+
+        let new_parameters = if new_vps.is_some() || new_sps.is_some() || new_pps.is_some() {
+            let vps_nal = new_vps.as_deref().unwrap_or(&self.parameters.vps_nal);
+            let sps_nal = new_sps.as_deref().unwrap_or(&self.parameters.sps_nal);
+            let pps_nal = new_pps.as_deref().unwrap_or(&self.parameters.pps_nal);
+            self.parameters = InternalParameters::parse_vps_sps_pps(vps_nal, sps_nal, pps_nal)?;
+            Some(Box::new(self.parameters.generic_parameters.clone()))
+        } else {
+            None
+        };
+        Ok(VideoFrame {
+            new_parameters,
+            loss: au.loss,
+            start_ctx: au.start_ctx,
+            end_ctx: au.end_ctx,
+            timestamp: au.timestamp,
+            stream_id: au.stream_id,
+            is_random_access_point,
+            is_disposable,
+            duration: None,
+            discontinuity: false,
+            data: data.into(),
+        })
+    }
+}
+
+/// Returns true iff the bytes of `nal` equal the bytes of `[hdr, ..data]`.
+fn matches(nal: &[u8], hdr: [u8; 2], pieces: &[Bytes]) -> bool {
+    if nal.len() < 2 || nal[..2] != hdr[..] {
+        return false;
+    }
+    let mut nal_pos = 2;
+    for piece in pieces {
+        let new_pos = nal_pos + piece.len();
+        if nal.len() < new_pos {
+            return false;
+        }
+        if piece[..] != nal[nal_pos..new_pos] {
+            return false;
+        }
+        nal_pos = new_pos;
+    }
+    nal_pos == nal.len()
+}
+
+/// Saves the given NAL to a contiguous Bytes.
+fn to_bytes(hdr: [u8; 2], len: u32, pieces: &[Bytes]) -> Bytes {
+    let len = usize::try_from(len).expect("u32 fits in usize");
+    let mut out = Vec::with_capacity(len);
+    out.extend_from_slice(&hdr[..]);
+    for piece in pieces {
+        out.extend_from_slice(&piece[..]);
+    }
+    debug_assert_eq!(len, out.len());
+    out.into()
+}
+
+/// Returns true if we allow the given NAL unit type to end an access unit.
+///
+/// As with H.264, parameter sets can't follow the last VCL NAL unit of an
+/// access unit (H.265 section 7.4.2.4.4), so a mark after them is bogus.
+fn can_end_au(nal_unit_type: u8) -> bool {
+    !matches!(nal_unit_type, nal_type::VPS | nal_type::SPS | nal_type::PPS)
+}
+
+impl AccessUnit {
+    fn start(pkt: &Packet, additional_loss: u16, same_ts_as_prev: bool) -> Self {
+        AccessUnit {
+            start_ctx: pkt.ctx,
+            end_ctx: pkt.ctx,
+            timestamp: pkt.timestamp,
+            stream_id: pkt.stream_id,
+            in_fu: false,
+            len: 0,
+
+            // TODO: overflow?
+            loss: pkt.loss.saturating_add(additional_loss),
+            same_ts_as_prev,
+        }
+    }
+}
+
+/// Reads an unsigned Exp-Golomb-coded value, as in H.265 section 9.2.
+fn read_ue(r: &mut bitreader::BitReader, name: &str) -> Result<u32, String> {
+    let mut leading_zeros = 0u8;
+    while !r
+        .read_bool()
+        .map_err(|e| format!("unable to read {}: {}", name, e))?
+    {
+        leading_zeros += 1;
+        if leading_zeros > 31 {
+            return Err(format!("{} exceeds 32 bits", name));
+        }
+    }
+    if leading_zeros == 0 {
+        return Ok(0);
+    }
+    let v = r
+        .read_u32(leading_zeros)
+        .map_err(|e| format!("unable to read {}: {}", name, e))?;
+    Ok((1u32 << leading_zeros) - 1 + v)
+}
+
+/// The fields of a SPS needed for [super::VideoParameters].
+#[derive(Debug, PartialEq, Eq)]
+struct Sps {
+    max_sub_layers_minus1: u8,
+    temporal_id_nesting: bool,
+
+    /// The first 12 bytes of the `profile_tier_level` structure:
+    /// profile space/tier/idc, compatibility flags, constraint indicator
+    /// flags, and level.
+    general_profile_tier_level: [u8; 12],
+
+    chroma_format_idc: u8,
+    pixel_dimensions: (u32, u32),
+    bit_depth_luma_minus8: u8,
+    bit_depth_chroma_minus8: u8,
+}
+
+impl Sps {
+    /// Parses the beginning of a SPS NAL, including its two-byte header.
+    /// See H.265 section 7.3.2.2.1.
+    fn parse(nal: &[u8]) -> Result<Self, String> {
+        if nal.len() < 3 || nal_unit_type(nal[0]) != nal_type::SPS {
+            return Err("bad SPS NAL".into());
+        }
+        let rbsp = h264_reader::rbsp::decode_nal(&nal[2..]);
+        let mut r = bitreader::BitReader::new(&rbsp[..]);
+        let e = |name| move |e| format!("unable to read SPS {}: {}", name, e);
+        r.skip(4).map_err(e("sps_video_parameter_set_id"))?;
+        let max_sub_layers_minus1 = r.read_u8(3).map_err(e("sps_max_sub_layers_minus1"))?;
+        let temporal_id_nesting = r.read_bool().map_err(e("sps_temporal_id_nesting_flag"))?;
+
+        // profile_tier_level(1, sps_max_sub_layers_minus1), section 7.3.3.
+        let mut general_profile_tier_level = [0u8; 12];
+        for b in &mut general_profile_tier_level {
+            *b = r.read_u8(8).map_err(e("profile_tier_level"))?;
+        }
+        let mut sub_layer_present = [(false, false); 8];
+        for p in &mut sub_layer_present[..usize::from(max_sub_layers_minus1)] {
+            p.0 = r.read_bool().map_err(e("sub_layer_profile_present_flag"))?;
+            p.1 = r.read_bool().map_err(e("sub_layer_level_present_flag"))?;
+        }
+        if max_sub_layers_minus1 > 0 {
+            r.skip(2 * (8 - u64::from(max_sub_layers_minus1)))
+                .map_err(e("reserved_zero_2bits"))?;
+        }
+        for &(profile_present, level_present) in
+            &sub_layer_present[..usize::from(max_sub_layers_minus1)]
+        {
+            if profile_present {
+                r.skip(88).map_err(e("sub_layer profile"))?;
+            }
+            if level_present {
+                r.skip(8).map_err(e("sub_layer_level_idc"))?;
+            }
+        }
+
+        read_ue(&mut r, "sps_seq_parameter_set_id")?;
+        let chroma_format_idc = read_ue(&mut r, "chroma_format_idc")?;
+        let chroma_format_idc = u8::try_from(chroma_format_idc)
+            .ok()
+            .filter(|&c| c <= 3)
+            .ok_or_else(|| format!("bad chroma_format_idc {}", chroma_format_idc))?;
+        let separate_colour_plane = if chroma_format_idc == 3 {
+            r.read_bool().map_err(e("separate_colour_plane_flag"))?
+        } else {
+            false
+        };
+        let width = read_ue(&mut r, "pic_width_in_luma_samples")?;
+        let height = read_ue(&mut r, "pic_height_in_luma_samples")?;
+        let (mut crop_width, mut crop_height) = (0, 0);
+        if r.read_bool().map_err(e("conformance_window_flag"))? {
+            // Offsets are in chroma sample units; see (7-1) and (7-2) and table 6-1.
+            let (sub_width_c, sub_height_c) = match (chroma_format_idc, separate_colour_plane) {
+                (1, _) => (2, 2),
+                (2, _) => (2, 1),
+                _ => (1, 1),
+            };
+            let left = read_ue(&mut r, "conf_win_left_offset")?;
+            let right = read_ue(&mut r, "conf_win_right_offset")?;
+            let top = read_ue(&mut r, "conf_win_top_offset")?;
+            let bottom = read_ue(&mut r, "conf_win_bottom_offset")?;
+            crop_width = u64::from(sub_width_c) * (u64::from(left) + u64::from(right));
+            crop_height = u64::from(sub_height_c) * (u64::from(top) + u64::from(bottom));
+        }
+        let pixel_dimensions = (
+            u64::from(width)
+                .checked_sub(crop_width)
+                .and_then(|w| u32::try_from(w).ok()),
+            u64::from(height)
+                .checked_sub(crop_height)
+                .and_then(|h| u32::try_from(h).ok()),
+        );
+        let pixel_dimensions = match pixel_dimensions {
+            (Some(w), Some(h)) => (w, h),
+            _ => return Err("SPS conformance window exceeds picture size".into()),
+        };
+        let bit_depth_luma_minus8 = read_ue(&mut r, "bit_depth_luma_minus8")?;
+        let bit_depth_chroma_minus8 = read_ue(&mut r, "bit_depth_chroma_minus8")?;
+        if bit_depth_luma_minus8 > 8 || bit_depth_chroma_minus8 > 8 {
+            return Err("SPS bit depth exceeds 16".into());
+        }
+        Ok(Sps {
+            max_sub_layers_minus1,
+            temporal_id_nesting,
+            general_profile_tier_level,
+            chroma_format_idc,
+            pixel_dimensions,
+            bit_depth_luma_minus8: bit_depth_luma_minus8 as u8,
+            bit_depth_chroma_minus8: bit_depth_chroma_minus8 as u8,
+        })
+    }
+
+    /// Returns a codec description in
+    /// [RFC-6381](https://tools.ietf.org/html/rfc6381) form, as specified in
+    /// ISO/IEC 14496-15 annex E, eg `hvc1.1.6.L93.B0`.
+    fn rfc6381_codec(&self) -> String {
+        let ptl = &self.general_profile_tier_level;
+        let profile_space = ptl[0] >> 6;
+        let tier = (ptl[0] >> 5) & 1;
+        let profile_idc = ptl[0] & 0b11111;
+        let compat = u32::from_be_bytes([ptl[1], ptl[2], ptl[3], ptl[4]]);
+        let level_idc = ptl[11];
+        let mut codec = format!(
+            "hvc1.{}{}.{:X}.{}{}",
+            ["", "A", "B", "C"][usize::from(profile_space)],
+            profile_idc,
+            compat.reverse_bits(),
+            if tier == 1 { 'H' } else { 'L' },
+            level_idc
+        );
+        let constraints = &ptl[5..11];
+        let n = constraints
+            .iter()
+            .rposition(|&b| b != 0)
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        for b in &constraints[..n] {
+            let _ = write!(&mut codec, ".{:X}", b);
+        }
+        codec
+    }
+}
+
+#[derive(Clone, Debug)]
+struct InternalParameters {
+    generic_parameters: super::VideoParameters,
+
+    /// The (single) VPS NAL.
+    vps_nal: Bytes,
+
+    /// The (single) SPS NAL.
+    sps_nal: Bytes,
+
+    /// The (single) PPS NAL.
+    pps_nal: Bytes,
+}
+
+impl InternalParameters {
+    /// Parses metadata from the `format-specific-params` of a SDP `fmtp` media attribute.
+    /// See [RFC 7798 section 7.1](https://datatracker.ietf.org/doc/html/rfc7798#section-7.1).
+    fn parse_format_specific_params(format_specific_params: &str) -> Result<Self, String> {
+        let mut vps_nal = None;
+        let mut sps_nal = None;
+        let mut pps_nal = None;
+        for p in format_specific_params.split(';') {
+            let p = p.trim();
+            if p.is_empty() {
+                continue;
+            }
+            let (key, value) = p
+                .split_once('=')
+                .ok_or_else(|| format!("bad format-specific-param {:?}", p))?;
+            let (nal, expected_type) = match key {
+                "sprop-vps" => (&mut vps_nal, nal_type::VPS),
+                "sprop-sps" => (&mut sps_nal, nal_type::SPS),
+                "sprop-pps" => (&mut pps_nal, nal_type::PPS),
+                "sprop-max-don-diff" if value != "0" => {
+                    return Err("sprop-max-don-diff > 0 is unsupported".into())
+                }
+                _ => continue,
+            };
+            if value.contains(',') {
+                return Err(format!("multiple NALs in {} are unsupported", key));
+            }
+            let decoded = base64::decode(value)
+                .map_err(|_| format!("{} has invalid base64 encoding", key))?;
+            if decoded.len() < 3 || nal_unit_type(decoded[0]) != expected_type {
+                return Err(format!("{} is not a valid NAL", key));
+            }
+            *nal = Some(decoded);
+        }
+        let vps_nal = vps_nal.ok_or_else(|| "no sprop-vps".to_string())?;
+        let sps_nal = sps_nal.ok_or_else(|| "no sprop-sps".to_string())?;
+        let pps_nal = pps_nal.ok_or_else(|| "no sprop-pps".to_string())?;
+        Self::parse_vps_sps_pps(&vps_nal, &sps_nal, &pps_nal)
+    }
+
+    fn parse_vps_sps_pps(
+        vps_nal: &[u8],
+        sps_nal: &[u8],
+        pps_nal: &[u8],
+    ) -> Result<InternalParameters, String> {
+        let sps = Sps::parse(sps_nal)?;
+        debug!("sps: {:#?}", &sps);
+        let rfc6381_codec = sps.rfc6381_codec();
+        let hvcc = hvcc_box(vps_nal, sps_nal, pps_nal)?;
+        let sample_entry =
+            super::make_visual_sample_entry(b"hvc1", sps.pixel_dimensions, &hvcc[..])?;
+        let vps_nal = Bytes::copy_from_slice(vps_nal);
+        let sps_nal = Bytes::copy_from_slice(sps_nal);
+        let pps_nal = Bytes::copy_from_slice(pps_nal);
+        Ok(InternalParameters {
+            generic_parameters: super::VideoParameters {
+                rfc6381_codec,
+                pixel_dimensions: sps.pixel_dimensions,
+                pixel_aspect_ratio: None,
+                frame_rate: None,
+                extra_data: hvcc.slice(8..),
+                parameter_sets: vec![vps_nal.clone(), sps_nal.clone(), pps_nal.clone()],
+                sample_entry: Some(sample_entry),
+            },
+            vps_nal,
+            sps_nal,
+            pps_nal,
+        })
+    }
+}
+
+/// Returns an `hvcC` box containing an HEVCDecoderConfigurationRecord, as in
+/// ISO/IEC 14496-15 section 8.3.3.1, with a single-NAL array for each of the
+/// given VPS, SPS, and PPS.
+///
+/// The general profile, tier, and level are taken from the SPS.
+pub(crate) fn hvcc_box(vps_nal: &[u8], sps_nal: &[u8], pps_nal: &[u8]) -> Result<Bytes, String> {
+    let sps = Sps::parse(sps_nal)?;
+    let mut buf =
+        BytesMut::with_capacity(8 + 23 + 3 * 5 + vps_nal.len() + sps_nal.len() + pps_nal.len());
+    write_box!(&mut buf, b"hvcC", {
+        buf.put_u8(1); // configurationVersion
+        buf.extend_from_slice(&sps.general_profile_tier_level[..]);
+        buf.put_u16(0xf000); // reserved + min_spatial_segmentation_idc
+        buf.put_u8(0xfc); // reserved + parallelismType
+        buf.put_u8(0xfc | sps.chroma_format_idc);
+        buf.put_u8(0xf8 | sps.bit_depth_luma_minus8);
+        buf.put_u8(0xf8 | sps.bit_depth_chroma_minus8);
+        buf.put_u16(0); // avgFrameRate
+
+        // constantFrameRate=0 + numTemporalLayers + temporalIdNested +
+        // lengthSizeMinusOne=3, matching the 4-byte lengths of VideoFrame data.
+        buf.put_u8(
+            ((sps.max_sub_layers_minus1 + 1) << 3) | (u8::from(sps.temporal_id_nesting) << 2) | 3,
+        );
+        buf.put_u8(3); // numOfArrays
+        for nal in [vps_nal, sps_nal, pps_nal].iter() {
+            if nal.len() < 2 {
+                return Err("empty NAL in hvcC".into());
+            }
+            // array_completeness=1 + reserved=0 + NAL_unit_type.
+            buf.put_u8(0x80 | nal_unit_type(nal[0]));
+            buf.put_u16(1); // numNalus
+            buf.put_u16(
+                u16::try_from(nal.len())
+                    .map_err(|_| format!("NAL is {} bytes long; must fit in u16", nal.len()))?,
+            );
+            buf.extend_from_slice(nal);
+        }
+    });
+    Ok(buf.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::num::NonZeroU32;
+
+    use bytes::Bytes;
+
+    use crate::{client::rtp::Packet, codec::CodecItem};
+
+    /// Parameters from a Dahua camera's sub stream.
+    const DAHUA_FMTP: &str = "profile-id=1;\
+        sprop-sps=QgEBAWAAAAMAsAAAAwAAAwBaoAWCAeFja5JFL83BQYFBAAADAAEAAAMADKE=;\
+        sprop-pps=RAHA8saNA7NA;\
+        sprop-vps=QAEMAf//AWAAAAMAsAAAAwAAAwBarAwAAAMABAAAAwAyqA==";
+
+    fn pkt(ts: i64, sequence_number: u16, mark: bool, payload: &[u8]) -> Packet {
+        Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: crate::Timestamp {
+                timestamp: ts,
+                clock_rate: NonZeroU32::new(90_000).unwrap(),
+                start: 0,
+            },
+            ssrc: 0,
+            sequence_number,
+            loss: 0,
+            mark,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    fn pull_frame(d: &mut super::Depacketizer) -> crate::codec::VideoFrame {
+        match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn parse_params() {
+        let d = super::Depacketizer::new(90_000, Some(DAHUA_FMTP)).unwrap();
+        let p = match d.parameters() {
+            Some(crate::codec::Parameters::Video(v)) => v,
+            o => panic!("unexpected parameters {:#?}", o),
+        };
+        assert_eq!(p.pixel_dimensions(), (704, 480));
+        assert_eq!(p.rfc6381_codec(), "hvc1.1.6.L90.B0");
+        let sets = p.parameter_sets();
+        assert_eq!(sets.len(), 3);
+        assert_eq!(super::nal_unit_type(sets[0][0]), super::nal_type::VPS);
+        assert_eq!(super::nal_unit_type(sets[1][0]), super::nal_type::SPS);
+        assert_eq!(super::nal_unit_type(sets[2][0]), super::nal_type::PPS);
+        assert_eq!(&sets[2][..], b"\x44\x01\xc0\xf2\xc6\x8d\x03\xb3\x40");
+
+        // HEVCDecoderConfigurationRecord: 23-byte fixed part, plus 5 bytes of
+        // array header per NAL.
+        let extra_data = p.extra_data();
+        assert_eq!(
+            extra_data.len(),
+            23 + 3 * 5 + sets.iter().map(|s| s.len()).sum::<usize>()
+        );
+        assert_eq!(extra_data[0], 1);
+        assert_eq!(extra_data[1], 0x01); // general_profile_idc=1 (Main).
+        assert_eq!(extra_data[12], 90); // general_level_idc.
+        assert_eq!(extra_data[16], 0xfc | 1); // chroma_format_idc=1.
+        assert_eq!(extra_data[22], 3); // numOfArrays.
+    }
+
+    /// Checks single NAL unit packets.
+    #[test]
+    fn depacketize_single_nals() {
+        let mut d = super::Depacketizer::new(90_000, Some(DAHUA_FMTP)).unwrap();
+        d.push(pkt(0, 0, false, b"\x40\x01vps")).unwrap(); // new VPS.
+        assert!(d.pull().is_none());
+        d.push(pkt(0, 1, true, b"\x26\x01idr slice")).unwrap(); // IDR_W_RADL.
+        let frame = pull_frame(&mut d);
+        assert!(frame.is_random_access_point);
+        assert!(!frame.is_disposable);
+        assert_eq!(
+            &frame.data()[..],
+            b"\x00\x00\x00\x05\x40\x01vps\x00\x00\x00\x0b\x26\x01idr slice"
+        );
+
+        // The changed VPS produces new parameters.
+        let p = frame.new_parameters.unwrap();
+        assert_eq!(&p.parameter_sets()[0][..], b"\x40\x01vps");
+
+        d.push(pkt(3000, 2, true, b"\x00\x01trail_n slice"))
+            .unwrap(); // TRAIL_N.
+        let frame = pull_frame(&mut d);
+        assert!(!frame.is_random_access_point);
+        assert!(frame.is_disposable);
+        assert!(frame.new_parameters.is_none());
+    }
+
+    /// Checks an aggregation packet and a following timestamp change.
+    #[test]
+    fn depacketize_ap() {
+        let mut d = super::Depacketizer::new(90_000, Some(DAHUA_FMTP)).unwrap();
+        let pps = b"\x44\x01\xc0\xf2\xc6\x8d\x03\xb3\x40";
+        let mut ap = vec![0x60, 0x01]; // AP header.
+        for nal in [&b"\x4e\x01sei"[..], &pps[..]] {
+            ap.extend_from_slice(&(nal.len() as u16).to_be_bytes()[..]);
+            ap.extend_from_slice(nal);
+        }
+        d.push(pkt(0, 0, false, &ap)).unwrap();
+        assert!(d.pull().is_none());
+        d.push(pkt(0, 1, false, b"\x02\x01trail_r slice")).unwrap(); // TRAIL_R.
+        assert!(d.pull().is_none());
+
+        // The timestamp change ends the access unit despite the lack of a mark.
+        d.push(pkt(3000, 2, false, b"\x02\x01trail_r slice"))
+            .unwrap();
+        let frame = pull_frame(&mut d);
+        assert_eq!(frame.timestamp.timestamp, 0);
+        assert!(!frame.is_disposable);
+        assert!(frame.new_parameters.is_none()); // same PPS as in the SDP.
+        assert_eq!(
+            &frame.data()[..],
+            &b"\x00\x00\x00\x05\x4e\x01sei\x00\x00\x00\x09\x44\x01\xc0\xf2\xc6\x8d\x03\xb3\x40\x00\x00\x00\x0f\x02\x01trail_r slice"[..]
+        );
+        d.push(pkt(3000, 3, true, b"\x02\x01trail_r slice"))
+            .unwrap();
+        let frame = pull_frame(&mut d);
+        assert_eq!(frame.timestamp.timestamp, 3000);
+
+        // An AP with a length exceeding the payload is rejected.
+        let e = d
+            .push(pkt(6000, 4, true, b"\x60\x01\x00\x10\x02\x01"))
+            .unwrap_err();
+        assert!(e.contains("AP too short"), "{}", e);
+    }
+
+    /// Checks a NAL fragmented into three FUs, and the loss of a FU.
+    #[test]
+    fn depacketize_fu() {
+        let mut d = super::Depacketizer::new(90_000, Some(DAHUA_FMTP)).unwrap();
+        // FU payload header type 49; FU header type 19 (IDR_W_RADL).
+        d.push(pkt(0, 0, false, b"\x62\x01\x93idr ")).unwrap();
+        d.push(pkt(0, 1, false, b"\x62\x01\x13slice ")).unwrap();
+        d.push(pkt(0, 2, true, b"\x62\x01\x53end")).unwrap();
+        let frame = pull_frame(&mut d);
+        assert!(frame.is_random_access_point);
+        assert_eq!(&frame.data()[..], b"\x00\x00\x00\x0f\x26\x01idr slice end");
+
+        // Lose the middle fragment of the next NAL: the access unit should be
+        // discarded and the loss reported on the following frame.
+        d.push(pkt(3000, 3, false, b"\x62\x01\x81trail ")).unwrap();
+        let mut end = pkt(3000, 5, true, b"\x62\x01\x41end");
+        end.loss = 1;
+        d.push(end).unwrap();
+        assert!(d.pull().is_none());
+        d.push(pkt(6000, 6, true, b"\x02\x01trail_r slice"))
+            .unwrap();
+        let frame = pull_frame(&mut d);
+        assert_eq!(frame.loss, 1);
+        assert_eq!(frame.timestamp.timestamp, 6000);
+
+        // Invalid: start and end bits both set.
+        d.push(pkt(9000, 7, true, b"\x62\x01\xd3x")).unwrap_err();
+    }
+
+    #[test]
+    fn hvcc_box() {
+        let vps = base64::decode("QAEMAf//AWAAAAMAsAAAAwAAAwBarAwAAAMABAAAAwAyqA==").unwrap();
+        let sps =
+            base64::decode("QgEBAWAAAAMAsAAAAwAAAwBaoAWCAeFja5JFL83BQYFBAAADAAEAAAMADKE=").unwrap();
+        let pps = base64::decode("RAHA8saNA7NA").unwrap();
+        let b = super::hvcc_box(&vps, &sps, &pps).unwrap();
+        let expected_len = 8 + 23 + 3 * 5 + vps.len() + sps.len() + pps.len();
+        assert_eq!(b.len(), expected_len);
+        assert_eq!(
+            &b[..4],
+            &u32::try_from(expected_len).unwrap().to_be_bytes()[..]
+        );
+        assert_eq!(&b[4..8], b"hvcC");
+
+        // Walk the NAL arrays: one each of VPS, SPS, and PPS.
+        let mut arrays = &b[8 + 22..];
+        assert_eq!(arrays[0], 3); // numOfArrays
+        arrays = &arrays[1..];
+        for (nal_type, nal) in [(32, &vps), (33, &sps), (34, &pps)] {
+            assert_eq!(arrays[0], 0x80 | nal_type);
+            assert_eq!(u16::from_be_bytes([arrays[1], arrays[2]]), 1); // numNalus
+            let len = usize::from(u16::from_be_bytes([arrays[3], arrays[4]]));
+            assert_eq!(&arrays[5..5 + len], &nal[..]);
+            arrays = &arrays[5 + len..];
+        }
+        assert!(arrays.is_empty());
+
+        // The sample entry wraps the same box.
+        let d = super::Depacketizer::new(90_000, Some(DAHUA_FMTP)).unwrap();
+        let p = match d.parameters() {
+            Some(crate::codec::Parameters::Video(v)) => v,
+            o => panic!("unexpected parameters {:#?}", o),
+        };
+        let sample_entry = p.sample_entry().unwrap();
+        assert_eq!(&sample_entry[4..8], b"hvc1");
+        assert!(sample_entry.ends_with(&b[..]));
+        assert_eq!(&b[8..], &p.extra_data()[..]);
+    }
+
+    #[test]
+    fn reject_don() {
+        let fmtp = format!("{};sprop-max-don-diff=1", DAHUA_FMTP);
+        super::Depacketizer::new(90_000, Some(&fmtp)).unwrap_err();
+    }
+}
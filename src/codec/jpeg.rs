@@ -0,0 +1,733 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Motion JPEG depacketization as in [RFC 2435](https://datatracker.ietf.org/doc/html/rfc2435).
+//!
+//! RTP/JPEG packets carry only the entropy-coded scan data plus a few header
+//! fields (type, Q, and dimensions). The JPEG headers (quantization tables,
+//! frame header, Huffman tables, and scan header) are reconstructed here as in
+//! the RFC's appendices, producing complete JFIF-compatible images.
+
+use bytes::{Buf, Bytes};
+
+use crate::client::rtp::Packet;
+
+use super::VideoFrame;
+
+/// The JPEG Annex K.1 luminance quantization table, in natural order.
+#[rustfmt::skip]
+const LUMA_QUANTIZER: [u8; 64] = [
+    16, 11, 10, 16, 24, 40, 51, 61,
+    12, 12, 14, 19, 26, 58, 60, 55,
+    14, 13, 16, 24, 40, 57, 69, 56,
+    14, 17, 22, 29, 51, 87, 80, 62,
+    18, 22, 37, 56, 68, 109, 103, 77,
+    24, 35, 55, 64, 81, 104, 113, 92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103, 99,
+];
+
+/// The JPEG Annex K.1 chrominance quantization table, in natural order.
+#[rustfmt::skip]
+const CHROMA_QUANTIZER: [u8; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// Maps zigzag index to natural index, as `DQT` segments are in zigzag order.
+#[rustfmt::skip]
+const ZIGZAG: [u8; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10,
+    17, 24, 32, 25, 18, 11, 4, 5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13, 6, 7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// The JPEG Annex K.3 Huffman tables, as in RFC 2435 appendix B.
+const LUMA_DC_CODELENS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const LUMA_DC_SYMBOLS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const LUMA_AC_CODELENS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7d];
+#[rustfmt::skip]
+const LUMA_AC_SYMBOLS: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12,
+    0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xa1, 0x08,
+    0x23, 0x42, 0xb1, 0xc1, 0x15, 0x52, 0xd1, 0xf0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0a, 0x16,
+    0x17, 0x18, 0x19, 0x1a, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2a, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39,
+    0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59,
+    0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79,
+    0x7a, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8a, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98,
+    0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7,
+    0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4, 0xb5, 0xb6,
+    0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3, 0xc4, 0xc5,
+    0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2, 0xd3, 0xd4,
+    0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda, 0xe1, 0xe2,
+    0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9, 0xea,
+    0xf1, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+const CHROMA_DC_CODELENS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const CHROMA_DC_SYMBOLS: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+const CHROMA_AC_CODELENS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+#[rustfmt::skip]
+const CHROMA_AC_SYMBOLS: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21,
+    0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91,
+    0xa1, 0xb1, 0xc1, 0x09, 0x23, 0x33, 0x52, 0xf0,
+    0x15, 0x62, 0x72, 0xd1, 0x0a, 0x16, 0x24, 0x34,
+    0xe1, 0x25, 0xf1, 0x17, 0x18, 0x19, 0x1a, 0x26,
+    0x27, 0x28, 0x29, 0x2a, 0x35, 0x36, 0x37, 0x38,
+    0x39, 0x3a, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4a, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58,
+    0x59, 0x5a, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6a, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78,
+    0x79, 0x7a, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8a, 0x92, 0x93, 0x94, 0x95, 0x96,
+    0x97, 0x98, 0x99, 0x9a, 0xa2, 0xa3, 0xa4, 0xa5,
+    0xa6, 0xa7, 0xa8, 0xa9, 0xaa, 0xb2, 0xb3, 0xb4,
+    0xb5, 0xb6, 0xb7, 0xb8, 0xb9, 0xba, 0xc2, 0xc3,
+    0xc4, 0xc5, 0xc6, 0xc7, 0xc8, 0xc9, 0xca, 0xd2,
+    0xd3, 0xd4, 0xd5, 0xd6, 0xd7, 0xd8, 0xd9, 0xda,
+    0xe2, 0xe3, 0xe4, 0xe5, 0xe6, 0xe7, 0xe8, 0xe9,
+    0xea, 0xf2, 0xf3, 0xf4, 0xf5, 0xf6, 0xf7, 0xf8,
+    0xf9, 0xfa,
+];
+
+/// Quantization tables for one frame, as in the RFC 2435 section 3.1.8
+/// quantization table header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct QuantizationTables {
+    /// Bit `i` is set iff table `i` has 16-bit rather than 8-bit entries.
+    precision: u8,
+
+    /// The tables' values in zigzag order, concatenated.
+    data: Vec<u8>,
+}
+
+impl QuantizationTables {
+    /// Derives tables from a `Q` value in `[1, 99]` as in RFC 2435 appendix A.
+    fn from_q(q: u8) -> Self {
+        debug_assert!((1..100).contains(&q));
+        let factor = u32::from(q);
+        let scale = if factor < 50 {
+            5000 / factor
+        } else {
+            200 - factor * 2
+        };
+        let mut data = Vec::with_capacity(128);
+        for table in [&LUMA_QUANTIZER, &CHROMA_QUANTIZER] {
+            for &i in &ZIGZAG {
+                let v = (u32::from(table[usize::from(i)]) * scale + 50) / 100;
+                data.push(v.clamp(1, 255) as u8);
+            }
+        }
+        QuantizationTables { precision: 0, data }
+    }
+
+    /// Returns the length in bytes of table `i`.
+    fn table_len(&self, i: usize) -> usize {
+        if (self.precision >> i) & 1 != 0 {
+            128
+        } else {
+            64
+        }
+    }
+}
+
+/// A frame currently being accumulated.
+#[derive(Debug)]
+struct InProgress {
+    start_ctx: crate::PacketContext,
+    timestamp: crate::Timestamp,
+    stream_id: usize,
+    pixel_dimensions: (u32, u32),
+
+    /// RTP packets lost before this frame started.
+    loss: u16,
+
+    /// The reconstructed headers, followed by the scan data received so far.
+    data: Vec<u8>,
+
+    /// The length of the reconstructed headers within `data`.
+    headers_len: usize,
+}
+
+/// A [super::Depacketizer] implementation for Motion JPEG.
+///
+/// Supports the baseline types 0 and 1 (YUV 4:2:2 and 4:2:0) and their
+/// restart marker variants 64 and 65. On packet loss, the partial frame is
+/// discarded and the loss reported on the next complete frame.
+#[derive(Debug)]
+pub(crate) struct Depacketizer {
+    /// A complete video frame ready for pull.
+    pending: Option<VideoFrame>,
+
+    /// Parameters from the most recent frame, as they're only known once
+    /// packets arrive.
+    parameters: Option<super::VideoParameters>,
+
+    in_progress: Option<InProgress>,
+
+    /// Packets lost since the last complete frame, which will be reported on
+    /// the next one.
+    loss: u16,
+
+    /// Dynamic tables from the most recent frame with `Q >= 128`, which the
+    /// server may omit from later frames with the same `Q`.
+    dynamic_tables: Option<(u8, QuantizationTables)>,
+
+    /// The maximum size of a frame; see
+    /// [`crate::client::SessionOptions::max_frame_bytes`].
+    max_frame_bytes: usize,
+}
+
+impl Depacketizer {
+    pub(super) fn new(clock_rate: u32) -> Result<Self, String> {
+        if clock_rate != 90_000 {
+            return Err(format!(
+                "invalid JPEG clock rate {}; must always be 90000",
+                clock_rate
+            ));
+        }
+        Ok(Depacketizer {
+            pending: None,
+            parameters: None,
+            in_progress: None,
+            loss: 0,
+            dynamic_tables: None,
+            max_frame_bytes: super::DEFAULT_MAX_FRAME_BYTES,
+        })
+    }
+
+    pub(super) fn set_max_frame_bytes(&mut self, max_frame_bytes: usize) {
+        self.max_frame_bytes = max_frame_bytes;
+    }
+
+    pub(super) fn parameters(&self) -> Option<super::Parameters> {
+        self.parameters.clone().map(super::Parameters::Video)
+    }
+
+    pub(super) fn push(&mut self, pkt: Packet) -> Result<(), String> {
+        // Push shouldn't be called until pull is exhausted.
+        if let Some(p) = self.pending.as_ref() {
+            panic!("push with data already pending: {:?}", p);
+        }
+
+        self.loss = self.loss.saturating_add(pkt.loss);
+        if let Some(p) = self.in_progress.as_ref() {
+            if pkt.loss > 0 || p.timestamp.timestamp != pkt.timestamp.timestamp {
+                log::debug!(
+                    "discarding incomplete JPEG frame at ts {} after {}",
+                    p.timestamp,
+                    if pkt.loss > 0 {
+                        "packet loss"
+                    } else {
+                        "timestamp change"
+                    }
+                );
+                self.in_progress = None;
+                if pkt.loss == 0 {
+                    // The frame's final (marked) packet must have been lost.
+                    self.loss = self.loss.saturating_add(1);
+                }
+            }
+        }
+
+        // Main JPEG header, RFC 2435 section 3.1.
+        let mut data = pkt.payload;
+        if data.len() < 8 {
+            return Err(format!(
+                "JPEG RTP payload of {} bytes is too short",
+                data.len()
+            ));
+        }
+        data.advance(1); // type-specific
+        let fragment_offset = data.get_uint(3) as usize;
+        let type_ = data.get_u8();
+        let q = data.get_u8();
+        let width = u32::from(data.get_u8()) * 8;
+        let height = u32::from(data.get_u8()) * 8;
+        if width == 0 || height == 0 {
+            return Err(format!("invalid JPEG dimensions {}x{}", width, height));
+        }
+        if !matches!(type_, 0 | 1 | 64 | 65) {
+            return Err(format!("unsupported JPEG type {}", type_));
+        }
+
+        // Restart marker header, RFC 2435 section 3.1.7.
+        let restart_interval = if type_ >= 64 {
+            if data.len() < 4 {
+                return Err("JPEG RTP payload too short for restart marker header".into());
+            }
+            let restart_interval = data.get_u16();
+            data.advance(2); // F, L, restart count.
+            restart_interval
+        } else {
+            0
+        };
+
+        if fragment_offset == 0 {
+            let tables = if q >= 128 {
+                // Quantization table header, RFC 2435 section 3.1.8.
+                if data.len() < 4 {
+                    return Err("JPEG RTP payload too short for quantization table header".into());
+                }
+                data.advance(1); // MBZ
+                let precision = data.get_u8();
+                let len = usize::from(data.get_u16());
+                if len == 0 {
+                    match &self.dynamic_tables {
+                        Some((prev_q, t)) if *prev_q == q => t.clone(),
+                        _ => return Err(format!("JPEG Q={} frame with no tables", q)),
+                    }
+                } else {
+                    if data.len() < len {
+                        return Err(format!(
+                            "JPEG quantization tables of {} bytes exceed {}-byte payload",
+                            len,
+                            data.len()
+                        ));
+                    }
+                    let t = QuantizationTables {
+                        precision,
+                        data: data.split_to(len).to_vec(),
+                    };
+                    if t.table_len(0) + t.table_len(1) != len {
+                        return Err(format!(
+                            "JPEG quantization tables have length {}; expected {}",
+                            len,
+                            t.table_len(0) + t.table_len(1)
+                        ));
+                    }
+                    self.dynamic_tables = Some((q, t.clone()));
+                    t
+                }
+            } else if q == 0 || q >= 100 {
+                // Q=0 and Q=100..=127 are reserved.
+                return Err(format!("invalid JPEG Q={}", q));
+            } else {
+                QuantizationTables::from_q(q)
+            };
+            let mut frame = Vec::new();
+            write_headers(&mut frame, type_, width, height, &tables, restart_interval);
+            let headers_len = frame.len();
+            self.in_progress = Some(InProgress {
+                start_ctx: pkt.ctx,
+                timestamp: pkt.timestamp,
+                stream_id: pkt.stream_id,
+                pixel_dimensions: (width, height),
+                loss: std::mem::take(&mut self.loss),
+                data: frame,
+                headers_len,
+            });
+        }
+
+        let p = match self.in_progress.as_mut() {
+            None => {
+                // The start of this frame was lost, or preceded the start of
+                // the stream; wait for the next.
+                log::debug!(
+                    "discarding JPEG fragment at offset {} of unstarted frame",
+                    fragment_offset
+                );
+                return Ok(());
+            }
+            Some(p) => p,
+        };
+        let received = p.data.len() - p.headers_len;
+        if fragment_offset != received {
+            return Err(format!(
+                "JPEG fragment offset {} doesn't match {} bytes received",
+                fragment_offset, received
+            ));
+        }
+        if p.data.len() + data.len() > self.max_frame_bytes {
+            let timestamp = p.timestamp;
+            self.in_progress = None;
+            self.loss = self.loss.saturating_add(1);
+            return Err(format!(
+                "JPEG frame at {} exceeds max_frame_bytes={}",
+                timestamp, self.max_frame_bytes
+            ));
+        }
+        p.data.extend_from_slice(&data[..]);
+
+        if pkt.mark {
+            let mut p = self.in_progress.take().expect("in_progress is some");
+            if !p.data.ends_with(&[0xff, 0xd9]) {
+                p.data.extend_from_slice(&[0xff, 0xd9]); // EOI
+            }
+            let new_parameters = match &self.parameters {
+                Some(params) if params.pixel_dimensions == p.pixel_dimensions => None,
+                _ => {
+                    let params = super::VideoParameters {
+                        pixel_dimensions: p.pixel_dimensions,
+                        // ISO/IEC 14496-1 objectTypeIndication 0x6C: JPEG.
+                        rfc6381_codec: "mp4v.6C".to_owned(),
+                        pixel_aspect_ratio: None,
+                        frame_rate: None,
+                        extra_data: Bytes::new(),
+                        parameter_sets: Vec::new(),
+                        sample_entry: None,
+                    };
+                    self.parameters = Some(params.clone());
+                    Some(Box::new(params))
+                }
+            };
+            self.pending = Some(VideoFrame {
+                new_parameters,
+                loss: p.loss,
+                start_ctx: p.start_ctx,
+                end_ctx: pkt.ctx,
+                timestamp: p.timestamp,
+                stream_id: p.stream_id,
+                is_random_access_point: true,
+                is_disposable: true,
+                duration: None,
+                discontinuity: false,
+                data: p.data.into(),
+            });
+        }
+        Ok(())
+    }
+
+    pub(super) fn pull(&mut self) -> Option<super::CodecItem> {
+        self.pending.take().map(super::CodecItem::VideoFrame)
+    }
+}
+
+/// Appends a marker segment with the given marker and contents.
+fn write_segment(buf: &mut Vec<u8>, marker: u8, contents: &[&[u8]]) {
+    let len = 2 + contents.iter().map(|c| c.len()).sum::<usize>();
+    buf.extend_from_slice(&[0xff, marker]);
+    buf.extend_from_slice(&(len as u16).to_be_bytes()[..]);
+    for c in contents {
+        buf.extend_from_slice(c);
+    }
+}
+
+/// Writes JPEG headers from `SOI` through `SOS`, as in RFC 2435 appendix A.
+fn write_headers(
+    buf: &mut Vec<u8>,
+    type_: u8,
+    width: u32,
+    height: u32,
+    tables: &QuantizationTables,
+    restart_interval: u16,
+) {
+    buf.extend_from_slice(&[0xff, 0xd8]); // SOI
+
+    // DQT, one per table.
+    let mut table_data = &tables.data[..];
+    for i in 0..2 {
+        let (table, rest) = table_data.split_at(tables.table_len(i));
+        table_data = rest;
+        let pq_tq = (((tables.precision >> i) & 1) << 4) | i as u8;
+        write_segment(buf, 0xdb, &[&[pq_tq][..], table]);
+    }
+
+    if restart_interval != 0 {
+        write_segment(buf, 0xdd, &[&restart_interval.to_be_bytes()[..]]); // DRI
+    }
+
+    // SOF0 (baseline DCT), or SOF1 (extended sequential DCT) if there are
+    // 16-bit tables, which baseline doesn't allow. The luma sampling factors
+    // distinguish 4:2:2 (type 0) from 4:2:0 (type 1); both chroma components
+    // use table 1.
+    let sof = if tables.precision & 0b11 != 0 {
+        0xc1
+    } else {
+        0xc0
+    };
+    let luma_sampling = if type_ & 0x3f == 0 { 0x21 } else { 0x22 };
+    let [h1, h0] = (height as u16).to_be_bytes();
+    let [w1, w0] = (width as u16).to_be_bytes();
+    write_segment(
+        buf,
+        sof,
+        &[&[
+            8, // precision
+            h1,
+            h0,
+            w1,
+            w0,
+            3, // components
+            0,
+            luma_sampling,
+            0,
+            1,
+            0x11,
+            1,
+            2,
+            0x11,
+            1,
+        ][..]],
+    );
+
+    // DHT: luma DC, luma AC, chroma DC, chroma AC.
+    write_segment(
+        buf,
+        0xc4,
+        &[&[0x00][..], &LUMA_DC_CODELENS[..], &LUMA_DC_SYMBOLS[..]],
+    );
+    write_segment(
+        buf,
+        0xc4,
+        &[&[0x10][..], &LUMA_AC_CODELENS[..], &LUMA_AC_SYMBOLS[..]],
+    );
+    write_segment(
+        buf,
+        0xc4,
+        &[&[0x01][..], &CHROMA_DC_CODELENS[..], &CHROMA_DC_SYMBOLS[..]],
+    );
+    write_segment(
+        buf,
+        0xc4,
+        &[&[0x11][..], &CHROMA_AC_CODELENS[..], &CHROMA_AC_SYMBOLS[..]],
+    );
+
+    // SOS.
+    write_segment(
+        buf,
+        0xda,
+        &[&[
+            3, // components
+            0, 0x00, // luma: DC table 0, AC table 0
+            1, 0x11, // Cb: DC table 1, AC table 1
+            2, 0x11, // Cr: DC table 1, AC table 1
+            0, 63, 0, // Ss, Se, Ah/Al
+        ][..]],
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use bytes::Bytes;
+
+    use crate::{client::rtp::Packet, codec::CodecItem};
+
+    /// Entropy-coded data for a 16x16 4:2:0 flat gray image: one MCU whose six
+    /// blocks each have a zero DC difference (`00`) and immediate EOB
+    /// (luma `1010`, chroma `00`).
+    const GRAY_SCAN: &[u8] = b"\x28\xa2\x8a\x00";
+
+    fn pkt(ts: i64, mark: bool, payload: &[u8]) -> Packet {
+        Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: crate::Timestamp {
+                timestamp: ts,
+                clock_rate: NonZeroU32::new(90_000).unwrap(),
+                start: 0,
+            },
+            ssrc: 0,
+            sequence_number: 0,
+            loss: 0,
+            mark,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::copy_from_slice(payload),
+        }
+    }
+
+    fn jpeg_payload(offset: u32, type_: u8, q: u8, rest: &[u8]) -> Vec<u8> {
+        let mut p = vec![0];
+        p.extend_from_slice(&offset.to_be_bytes()[1..]);
+        p.extend_from_slice(&[type_, q, 16 / 8, 16 / 8]);
+        p.extend_from_slice(rest);
+        p
+    }
+
+    /// Returns the markers of each segment in `jpeg`, checking overall structure.
+    fn markers(jpeg: &[u8]) -> Vec<u8> {
+        assert_eq!(&jpeg[..2], b"\xff\xd8");
+        assert_eq!(&jpeg[jpeg.len() - 2..], b"\xff\xd9");
+        let mut markers = Vec::new();
+        let mut pos = 2;
+        loop {
+            assert_eq!(jpeg[pos], 0xff);
+            let marker = jpeg[pos + 1];
+            markers.push(marker);
+            let len = usize::from(u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]));
+            pos += 2 + len;
+            if marker == 0xda {
+                break;
+            }
+        }
+        markers
+    }
+
+    fn segment(jpeg: &[u8], want: u8) -> &[u8] {
+        let mut pos = 2;
+        loop {
+            let marker = jpeg[pos + 1];
+            let len = usize::from(u16::from_be_bytes([jpeg[pos + 2], jpeg[pos + 3]]));
+            if marker == want {
+                return &jpeg[pos + 4..pos + 2 + len];
+            }
+            pos += 2 + len;
+        }
+    }
+
+    #[test]
+    fn quantization_tables() {
+        // Q=50 yields the Annex K tables unscaled.
+        let t = super::QuantizationTables::from_q(50);
+        assert_eq!(t.data.len(), 128);
+        assert_eq!(&t.data[..4], &[16, 11, 12, 14]);
+        assert_eq!(&t.data[64..68], &[17, 18, 18, 24]);
+
+        // Lower Q values scale up, clamped to 255.
+        let t = super::QuantizationTables::from_q(1);
+        assert_eq!(t.data[0], 255);
+    }
+
+    #[test]
+    fn depacketize_single() {
+        let mut d = super::Depacketizer::new(90_000).unwrap();
+        assert!(d.parameters().is_none());
+        d.push(pkt(0, true, &jpeg_payload(0, 1, 50, GRAY_SCAN)))
+            .unwrap();
+        let mut frame = match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        };
+        assert!(frame.is_random_access_point);
+        assert_eq!(
+            frame.new_parameters.take().unwrap().pixel_dimensions(),
+            (16, 16)
+        );
+        let jpeg = &frame.data()[..];
+        assert_eq!(
+            markers(jpeg),
+            &[0xdb, 0xdb, 0xc0, 0xc4, 0xc4, 0xc4, 0xc4, 0xda]
+        );
+        assert_eq!(
+            segment(jpeg, 0xc0),
+            b"\x08\x00\x10\x00\x10\x03\x00\x22\x00\x01\x11\x01\x02\x11\x01"
+        );
+        assert!(jpeg.ends_with(b"\x00\x3f\x00\x28\xa2\x8a\x00\xff\xd9"));
+
+        // A second frame of the same size has no new parameters.
+        d.push(pkt(3000, true, &jpeg_payload(0, 1, 50, GRAY_SCAN)))
+            .unwrap();
+        match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => assert!(frame.new_parameters.is_none()),
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn depacketize_fragmented() {
+        let mut d = super::Depacketizer::new(90_000).unwrap();
+        d.push(pkt(0, false, &jpeg_payload(0, 1, 50, &GRAY_SCAN[..2])))
+            .unwrap();
+        assert!(d.pull().is_none());
+        d.push(pkt(0, true, &jpeg_payload(2, 1, 50, &GRAY_SCAN[2..])))
+            .unwrap();
+        let frame = match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        };
+        assert!(frame.data().ends_with(b"\x28\xa2\x8a\x00\xff\xd9"));
+
+        // A fragment at an unexpected offset is an error.
+        d.push(pkt(3000, false, &jpeg_payload(0, 1, 50, &GRAY_SCAN[..2])))
+            .unwrap();
+        d.push(pkt(3000, true, &jpeg_payload(3, 1, 50, &GRAY_SCAN[3..])))
+            .unwrap_err();
+
+        // Loss discards the partial frame and is reported on the next.
+        let mut d = super::Depacketizer::new(90_000).unwrap();
+        d.push(pkt(0, false, &jpeg_payload(0, 1, 50, &GRAY_SCAN[..2])))
+            .unwrap();
+        let mut next = pkt(3000, true, &jpeg_payload(0, 1, 50, GRAY_SCAN));
+        next.loss = 1;
+        d.push(next).unwrap();
+        match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => {
+                assert_eq!(frame.loss, 1);
+                assert_eq!(frame.timestamp.timestamp, 3000);
+            }
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn depacketize_inline_tables_and_restart() {
+        let mut d = super::Depacketizer::new(90_000).unwrap();
+        let mut rest = Vec::new();
+        rest.extend_from_slice(b"\x00\x01\xff\xff"); // restart interval 1, F=L=1
+        rest.extend_from_slice(b"\x00\x00\x00\x80"); // MBZ, precision, length 128
+        rest.extend((0..128).map(|i| i as u8 + 1));
+        rest.extend_from_slice(GRAY_SCAN);
+        d.push(pkt(0, true, &jpeg_payload(0, 65, 255, &rest)))
+            .unwrap();
+        let frame = match d.pull() {
+            Some(CodecItem::VideoFrame(frame)) => frame,
+            o => panic!("unexpected pull result {:#?}", o),
+        };
+        let jpeg = &frame.data()[..];
+        assert_eq!(
+            markers(jpeg),
+            &[0xdb, 0xdb, 0xdd, 0xc0, 0xc4, 0xc4, 0xc4, 0xc4, 0xda]
+        );
+        let dqt = segment(jpeg, 0xdb);
+        assert_eq!(dqt.len(), 65);
+        assert_eq!(&dqt[..3], &[0, 1, 2]);
+        assert_eq!(segment(jpeg, 0xdd), b"\x00\x01");
+
+        // A later frame with the same Q may omit the tables.
+        let mut rest = Vec::new();
+        rest.extend_from_slice(b"\x00\x01\xff\xff\x00\x00\x00\x00");
+        rest.extend_from_slice(GRAY_SCAN);
+        d.push(pkt(3000, true, &jpeg_payload(0, 65, 255, &rest)))
+            .unwrap();
+        match d.pull() {
+            Some(CodecItem::VideoFrame(f)) => assert_eq!(segment(&f.data()[..], 0xdb), dqt),
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+
+        // 16-bit tables require SOF1 rather than baseline SOF0.
+        let mut rest = Vec::new();
+        rest.extend_from_slice(b"\x00\x01\x00\xc0"); // MBZ, precision, length 192
+        rest.extend((0..192).map(|i| i as u8 + 1));
+        rest.extend_from_slice(GRAY_SCAN);
+        d.push(pkt(6000, true, &jpeg_payload(0, 1, 254, &rest)))
+            .unwrap();
+        match d.pull() {
+            Some(CodecItem::VideoFrame(f)) => assert_eq!(
+                markers(&f.data()[..]),
+                &[0xdb, 0xdb, 0xc1, 0xc4, 0xc4, 0xc4, 0xc4, 0xda]
+            ),
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn reserved_q() {
+        let mut d = super::Depacketizer::new(90_000).unwrap();
+        d.push(pkt(0, true, &jpeg_payload(0, 1, 100, GRAY_SCAN)))
+            .unwrap_err();
+        d.push(pkt(3000, true, &jpeg_payload(0, 1, 127, GRAY_SCAN)))
+            .unwrap_err();
+    }
+}
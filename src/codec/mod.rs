@@ -7,21 +7,56 @@
 //! codec, as needed for a client during `PLAY` and a server during `RECORD`.
 //! Packetization (needed for the reverse) may be added in the future.
 
+use std::convert::TryFrom;
 use std::num::{NonZeroU16, NonZeroU32};
 
 use crate::client::rtp;
 use crate::ConnectionContext;
 use crate::Error;
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
 use pretty_hex::PrettyHex;
 
+/// Writes a box length and type (four-character code) for everything appended
+/// in the supplied scope.
+macro_rules! write_box {
+    ($buf:expr, $fourcc:expr, $b:block) => {{
+        let _: &mut BytesMut = $buf; // type-check.
+        let pos_start = $buf.len();
+        let fourcc: &[u8; 4] = $fourcc;
+        $buf.extend_from_slice(&[0, 0, 0, 0, fourcc[0], fourcc[1], fourcc[2], fourcc[3]]);
+        let r = {
+            $b;
+        };
+        let pos_end = $buf.len();
+        let len = pos_end.checked_sub(pos_start).unwrap();
+        $buf[pos_start..pos_start + 4].copy_from_slice(
+            &u32::try_from(len)
+                .map_err(|_| format!("box length {} exceeds u32::MAX", len))?
+                .to_be_bytes()[..],
+        );
+        r
+    }};
+}
+
+/// The default for [`crate::client::SessionOptions::max_frame_bytes`].
+pub(crate) const DEFAULT_MAX_FRAME_BYTES: usize = 16 << 20;
+
 pub(crate) mod aac;
 pub(crate) mod g723;
 
 #[doc(hidden)]
 pub mod h264;
 
+pub(crate) mod h265;
+
+pub(crate) mod jpeg;
 pub(crate) mod onvif;
+
+#[cfg(feature = "onvif-events")]
+pub mod onvif_events;
+
+pub(crate) mod opus;
+pub(crate) mod raw;
 pub(crate) mod simple_audio;
 
 #[derive(Debug)]
@@ -30,9 +65,75 @@ pub enum CodecItem {
     AudioFrame(AudioFrame),
     MessageFrame(MessageFrame),
     SenderReport(crate::client::rtp::SenderReport),
+
+    /// An RTP packet on a stream without a depacketizer, passed through
+    /// unmodified. Only produced when
+    /// [`crate::client::SessionOptions::allow_unknown_codecs`] is set.
+    RawRtp(crate::client::rtp::Packet),
+
+    /// A gap in RTP sequence numbers, reported when detected, just before the
+    /// item which includes the packet following the gap.
+    ///
+    /// That item's `loss` also counts these packets, so callers which don't
+    /// need the detail can ignore this event. Those which do may use it to,
+    /// for example, request a new keyframe promptly.
+    Loss {
+        stream_id: usize,
+
+        /// The lost RTP sequence numbers. This may wrap, so `lost_range.start`
+        /// can be greater than `lost_range.end`; the count is
+        /// `lost_range.end.wrapping_sub(lost_range.start)`.
+        lost_range: std::ops::Range<u16>,
+
+        /// The context of the packet following the gap.
+        ctx: crate::PacketContext,
+    },
+}
+
+impl CodecItem {
+    /// Returns the media timestamp, or `None` for items without one (`Loss`).
+    pub fn timestamp(&self) -> Option<crate::Timestamp> {
+        match self {
+            CodecItem::VideoFrame(f) => Some(f.timestamp),
+            CodecItem::AudioFrame(f) => Some(f.timestamp),
+            CodecItem::MessageFrame(f) => Some(f.timestamp),
+            CodecItem::SenderReport(r) => Some(r.timestamp),
+            CodecItem::RawRtp(p) => Some(p.timestamp),
+            CodecItem::Loss { .. } => None,
+        }
+    }
+
+    /// Returns the stream this item belongs to, as an index into
+    /// [`crate::client::Session::streams`].
+    pub fn stream_id(&self) -> usize {
+        match self {
+            CodecItem::VideoFrame(f) => f.stream_id,
+            CodecItem::AudioFrame(f) => f.stream_id,
+            CodecItem::MessageFrame(f) => f.stream_id,
+            CodecItem::SenderReport(r) => r.stream_id,
+            CodecItem::RawRtp(p) => p.stream_id,
+            CodecItem::Loss { stream_id, .. } => *stream_id,
+        }
+    }
+
+    /// Returns the context of the packet which produced this item.
+    ///
+    /// For a video frame spanning several packets, this is the first; see
+    /// [`VideoFrame::start_ctx`].
+    pub fn ctx(&self) -> &crate::PacketContext {
+        match self {
+            CodecItem::VideoFrame(f) => &f.start_ctx,
+            CodecItem::AudioFrame(f) => &f.ctx,
+            CodecItem::MessageFrame(f) => &f.ctx,
+            CodecItem::SenderReport(r) => &r.ctx,
+            CodecItem::RawRtp(p) => &p.ctx,
+            CodecItem::Loss { ctx, .. } => ctx,
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum Parameters {
     Video(VideoParameters),
     Audio(AudioParameters),
@@ -40,12 +141,24 @@ pub enum Parameters {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct VideoParameters {
     pixel_dimensions: (u32, u32),
     rfc6381_codec: String,
     pixel_aspect_ratio: Option<(u32, u32)>,
     frame_rate: Option<(u32, u32)>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::base64"))]
     extra_data: Bytes,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::vec_base64")
+    )]
+    parameter_sets: Vec<Bytes>,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::option_base64")
+    )]
+    sample_entry: Option<Bytes>,
 }
 
 impl VideoParameters {
@@ -90,9 +203,70 @@ impl VideoParameters {
 
     /// The codec-specific "extra data" to feed to eg ffmpeg to decode the video frames.
     /// *   H.264: an AvcDecoderConfig.
+    /// *   H.265: an HEVCDecoderConfigurationRecord.
+    /// *   JPEG: empty; each frame is a complete JFIF image.
     pub fn extra_data(&self) -> &Bytes {
         &self.extra_data
     }
+
+    /// The codec's current parameter set NAL units, each including its NAL
+    /// header but without any Annex B start code or length prefix.
+    /// *   H.264: the SPS followed by the PPS.
+    /// *   H.265: the VPS, SPS, and PPS.
+    ///
+    /// These reflect the SDP's `sprop-parameter-sets` until in-band parameter
+    /// sets arrive, at which point a new `VideoParameters` is produced via
+    /// [`VideoFrame::new_parameters`].
+    pub fn parameter_sets(&self) -> &[Bytes] {
+        &self.parameter_sets
+    }
+
+    /// An `.mp4` `VisualSampleEntry` box (as defined in ISO/IEC 14496-12), if possible.
+    /// *   H.264: an `avc1` box containing an `avcC` box.
+    /// *   H.265: an `hvc1` box containing an `hvcC` box.
+    /// *   JPEG: none.
+    pub fn sample_entry(&self) -> Option<&Bytes> {
+        self.sample_entry.as_ref()
+    }
+}
+
+/// Returns a `VisualSampleEntry` box with the given four-character code, as in
+/// ISO/IEC 14496-12 section 12.1.3, followed by the given codec-specific
+/// configuration box (eg `avcC`).
+fn make_visual_sample_entry(
+    fourcc: &[u8; 4],
+    pixel_dimensions: (u32, u32),
+    config_box: &[u8],
+) -> Result<Bytes, String> {
+    let (width, height) = pixel_dimensions;
+    let width = u16::try_from(width).map_err(|_| format!("width {} exceeds u16::MAX", width))?;
+    let height =
+        u16::try_from(height).map_err(|_| format!("height {} exceeds u16::MAX", height))?;
+    let mut buf = BytesMut::new();
+    write_box!(&mut buf, fourcc, {
+        buf.put_u32(0); // SampleEntry.reserved
+        buf.put_u32(1); // SampleEntry.reserved, SampleEntry.data_reference_index (1)
+        buf.extend_from_slice(&[0; 16]); // pre_defined, reserved, pre_defined
+        buf.put_u16(width);
+        buf.put_u16(height);
+        buf.extend_from_slice(&[
+            0x00, 0x48, 0x00, 0x00, // horizresolution
+            0x00, 0x48, 0x00, 0x00, // vertresolution
+            0x00, 0x00, 0x00, 0x00, // reserved
+            0x00, 0x01, // frame count
+            0x00, 0x00, 0x00, 0x00, // compressorname
+            0x00, 0x00, 0x00, 0x00, //
+            0x00, 0x00, 0x00, 0x00, //
+            0x00, 0x00, 0x00, 0x00, //
+            0x00, 0x00, 0x00, 0x00, //
+            0x00, 0x00, 0x00, 0x00, //
+            0x00, 0x00, 0x00, 0x00, //
+            0x00, 0x00, 0x00, 0x00, //
+            0x00, 0x18, 0xff, 0xff, // depth + pre_defined
+        ]);
+        buf.extend_from_slice(config_box);
+    });
+    Ok(buf.freeze())
 }
 
 impl std::fmt::Debug for VideoParameters {
@@ -108,12 +282,20 @@ impl std::fmt::Debug for VideoParameters {
 }
 
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct AudioParameters {
     rfc6381_codec: Option<String>,
     frame_length: Option<NonZeroU32>,
     clock_rate: u32,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::base64"))]
     extra_data: Bytes,
+    #[cfg_attr(
+        feature = "serde",
+        serde(serialize_with = "crate::serialize::option_base64")
+    )]
     sample_entry: Option<Bytes>,
+    sample_rate: Option<u32>,
+    channels: Option<NonZeroU16>,
 }
 
 impl std::fmt::Debug for AudioParameters {
@@ -121,6 +303,9 @@ impl std::fmt::Debug for AudioParameters {
         f.debug_struct("AudioParameters")
             .field("rfc6381_codec", &self.rfc6381_codec)
             .field("frame_length", &self.frame_length)
+            .field("clock_rate", &self.clock_rate)
+            .field("sample_rate", &self.sample_rate)
+            .field("channels", &self.channels)
             .field("extra_data", &self.extra_data.hex_dump())
             .finish()
     }
@@ -136,10 +321,24 @@ impl AudioParameters {
         self.frame_length
     }
 
+    /// The RTP clock rate, in which timestamps and frame lengths are expressed.
     pub fn clock_rate(&self) -> u32 {
         self.clock_rate
     }
 
+    /// The codec's sampling frequency in Hz, if known.
+    ///
+    /// This may differ from [`AudioParameters::clock_rate`]; eg some cameras
+    /// send 16 kHz AAC with a 90 kHz RTP clock.
+    pub fn sample_rate(&self) -> Option<u32> {
+        self.sample_rate
+    }
+
+    /// The number of audio channels, if known.
+    pub fn channels(&self) -> Option<NonZeroU16> {
+        self.channels
+    }
+
     /// The codec-specific "extra data" to feed to eg ffmpeg to decode the audio.
     /// *   AAC: a serialized `AudioSpecificConfig`.
     pub fn extra_data(&self) -> &Bytes {
@@ -167,10 +366,38 @@ pub struct AudioFrame {
     /// of data may be skipped.
     pub loss: u16,
 
-    // TODO: expose bytes or Buf (for zero-copy)?
+    /// True if this is the first frame after an RTP timestamp reset. See
+    /// [crate::client::rtp::Packet::discontinuity].
+    pub discontinuity: bool,
+
+    /// The encoded audio frame, as a reference-counted buffer which
+    /// typically shares the allocation of the RTP packet(s) it came from.
     pub data: Bytes,
 }
 
+impl AudioFrame {
+    /// Returns the duration of this frame, in the stream's clock rate units.
+    ///
+    /// Unlike [`VideoFrame::duration`], this is always known from the codec
+    /// and requires no look-ahead; it's the same as `frame_length`.
+    #[inline]
+    pub fn duration(&self) -> u32 {
+        self.frame_length.get()
+    }
+
+    /// Returns the encoded audio frame; see [`AudioFrame::data`](#structfield.data).
+    #[inline]
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Returns the data without copying.
+    #[inline]
+    pub fn into_data(self) -> Bytes {
+        self.data
+    }
+}
+
 impl std::fmt::Debug for AudioFrame {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AudioFrame")
@@ -179,6 +406,7 @@ impl std::fmt::Debug for AudioFrame {
             .field("loss", &self.loss)
             .field("timestamp", &self.timestamp)
             .field("frame_length", &self.frame_length)
+            .field("discontinuity", &self.discontinuity)
             .field("data", &self.data.hex_dump())
             .finish()
     }
@@ -199,10 +427,25 @@ impl Buf for AudioFrame {
 }
 
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct MessageParameters(onvif::CompressionType);
 
+/// A single ONVIF metadata message, reassembled from one or more RTP packets.
+///
+/// With the `onvif-events` feature, uncompressed messages can be parsed via
+/// `onvif_events::MetadataStream::parse`.
 pub struct MessageFrame {
     pub ctx: crate::PacketContext,
+
+    /// The RTP timestamp shared by all packets of this message.
+    ///
+    /// Metadata streams use the same time base as the presentation's other
+    /// streams, so [`crate::Timestamp::elapsed_secs`] gives the normal play
+    /// time (NPT) at which the described event occurred, for correlation with
+    /// video frames. To get an absolute wallclock time, pair it with the most
+    /// recent [`CodecItem::SenderReport`] on the same stream: add
+    /// `(timestamp.timestamp() - sr.timestamp.timestamp()) / clock_rate`
+    /// seconds to `sr.ntp_timestamp`. The `metadata` example does this.
     pub timestamp: crate::Timestamp,
     pub stream_id: usize,
 
@@ -210,10 +453,26 @@ pub struct MessageFrame {
     /// If this is non-zero, a prefix of the message may be missing.
     pub loss: u16,
 
-    // TODO: expose bytes or Buf (for zero-copy)?
+    /// The message body, as a reference-counted buffer which typically
+    /// shares the allocation of the RTP packet(s) it came from. Its format
+    /// depends on the stream's encoding, eg ONVIF metadata is XML.
     pub data: Bytes,
 }
 
+impl MessageFrame {
+    /// Returns the message body; see [`MessageFrame::data`](#structfield.data).
+    #[inline]
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Returns the data without copying.
+    #[inline]
+    pub fn into_data(self) -> Bytes {
+        self.data
+    }
+}
+
 impl std::fmt::Debug for MessageFrame {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("AudioFrame")
@@ -232,7 +491,11 @@ impl std::fmt::Debug for MessageFrame {
 /// specified here; they can be calculated from the timestamp of a following
 /// picture, or approximated via the frame rate.
 pub struct VideoFrame {
-    // New video parameters. Rarely populated and large, so boxed to reduce bloat.
+    /// New video parameters, set on the first frame after in-band parameter
+    /// sets change, including mid-stream resolution changes. Decoders should
+    /// be reconfigured before decoding this frame.
+    ///
+    /// Rarely populated and large, so boxed to reduce bloat.
     pub new_parameters: Option<Box<VideoParameters>>,
 
     /// Number of lost RTP packets before this video frame. See [crate::client::rtp::Packet::loss].
@@ -260,6 +523,14 @@ pub struct VideoFrame {
     /// In H.264 terms, this is a frame with `nal_ref_idc == 0`.
     pub is_disposable: bool,
 
+    /// True if this is the first frame after an RTP timestamp reset. See
+    /// [crate::client::rtp::Packet::discontinuity]. Decoders and muxers
+    /// should not assume this frame's timestamp follows the previous one's.
+    pub discontinuity: bool,
+
+    /// See [`VideoFrame::duration`].
+    duration: Option<u32>,
+
     data: bytes::Bytes,
 }
 
@@ -274,11 +545,45 @@ impl VideoFrame {
         self.end_ctx
     }
 
+    /// Returns the duration of this frame, in the stream's clock rate units.
+    ///
+    /// This is the timestamp delta to the next frame, so it's only known when
+    /// [`crate::client::SessionOptions::video_frame_durations`] is enabled,
+    /// at the cost of one frame of latency. It's `None` otherwise or if the
+    /// next frame's timestamp doesn't follow this one's.
+    #[inline]
+    pub fn duration(&self) -> Option<u32> {
+        self.duration
+    }
+
+    /// Returns true if in-band parameter sets changed as of this frame.
+    ///
+    /// This is a shorthand for `new_parameters.is_some()`, which muxers can
+    /// check cheaply on every frame instead of comparing parameters; see
+    /// [`VideoFrame::new_parameters`](#structfield.new_parameters). Only the
+    /// first frame after a change has this set.
+    #[inline]
+    pub fn has_new_parameters(&self) -> bool {
+        self.new_parameters.is_some()
+    }
+
+    /// Returns true if this picture is a random access point (keyframe).
+    ///
+    /// This is true for H.264 IDR pictures and H.265 IRAP pictures, as well
+    /// as pictures preceded by a recovery point SEI message which says
+    /// decoding is correct starting from this picture. See
+    /// [`VideoFrame::is_random_access_point`](#structfield.is_random_access_point).
+    #[inline]
+    pub fn is_random_access_point(&self) -> bool {
+        self.is_random_access_point
+    }
+
     #[inline]
     pub fn data(&self) -> &Bytes {
         &self.data
     }
 
+    /// Returns the data without copying.
     #[inline]
     pub fn into_data(self) -> Bytes {
         self.data
@@ -296,6 +601,8 @@ impl std::fmt::Debug for VideoFrame {
             .field("new_parameters", &self.new_parameters)
             .field("is_random_access_point", &self.is_random_access_point)
             .field("is_disposable", &self.is_disposable)
+            .field("duration", &self.duration)
+            .field("discontinuity", &self.discontinuity)
             .field("data_len", &self.data.len())
             //.field("data", &self.data.hex_dump())
             .finish()
@@ -306,7 +613,23 @@ impl std::fmt::Debug for VideoFrame {
 /// This interface unstable and for internal use; it's exposed for direct fuzzing and benchmarking.
 #[doc(hidden)]
 #[derive(Debug)]
-pub struct Depacketizer(DepacketizerInner);
+pub struct Depacketizer {
+    inner: DepacketizerInner,
+
+    /// If [`Depacketizer::set_video_frame_durations`] is enabled.
+    video_frame_durations: bool,
+
+    /// With `video_frame_durations`, the video frame awaiting its successor.
+    held_video_frame: Option<VideoFrame>,
+
+    /// The timestamp of the last pushed packet with
+    /// [`rtp::Packet::discontinuity`] set, until a frame on its new timeline
+    /// is pulled.
+    discontinuity: Option<crate::Timestamp>,
+
+    /// If set, packets bypass `inner`; see [`Depacketizer::set_passthrough`].
+    passthrough: Option<Box<raw::Depacketizer>>,
+}
 
 #[derive(Debug)]
 enum DepacketizerInner {
@@ -314,7 +637,11 @@ enum DepacketizerInner {
     SimpleAudio(Box<simple_audio::Depacketizer>),
     G723(Box<g723::Depacketizer>),
     H264(Box<h264::Depacketizer>),
+    H265(Box<h265::Depacketizer>),
+    Jpeg(Box<jpeg::Depacketizer>),
     Onvif(Box<onvif::Depacketizer>),
+    Opus(Box<opus::Depacketizer>),
+    Raw(Box<raw::Depacketizer>),
 }
 
 impl Depacketizer {
@@ -329,16 +656,27 @@ impl Depacketizer {
 
         // RTP Payload Format Media Types
         // https://www.iana.org/assignments/rtp-parameters/rtp-parameters.xhtml#rtp-parameters-2
-        Ok(Depacketizer(match (media, encoding_name) {
+        Ok(Depacketizer::with_inner(match (media, encoding_name) {
             ("video", "h264") => DepacketizerInner::H264(Box::new(h264::Depacketizer::new(
                 clock_rate,
                 format_specific_params,
             )?)),
+            ("video", "h265") => DepacketizerInner::H265(Box::new(h265::Depacketizer::new(
+                clock_rate,
+                format_specific_params,
+            )?)),
+            ("video", "jpeg") => {
+                DepacketizerInner::Jpeg(Box::new(jpeg::Depacketizer::new(clock_rate)?))
+            }
             ("audio", "mpeg4-generic") => DepacketizerInner::Aac(Box::new(aac::Depacketizer::new(
                 clock_rate,
                 channels,
                 format_specific_params,
             )?)),
+            ("audio", "opus") => DepacketizerInner::Opus(Box::new(opus::Depacketizer::new(
+                clock_rate,
+                format_specific_params,
+            )?)),
             ("audio", "g726-16") => DepacketizerInner::SimpleAudio(Box::new(
                 simple_audio::Depacketizer::new(clock_rate, 2),
             )),
@@ -351,11 +689,15 @@ impl Depacketizer {
             ("audio", "g726-40") => DepacketizerInner::SimpleAudio(Box::new(
                 simple_audio::Depacketizer::new(clock_rate, 5),
             )),
-            ("audio", "pcma") | ("audio", "pcmu") | ("audio", "u8") | ("audio", "g722") => {
-                DepacketizerInner::SimpleAudio(Box::new(simple_audio::Depacketizer::new(
-                    clock_rate, 8,
-                )))
-            }
+            ("audio", "pcma") => DepacketizerInner::SimpleAudio(Box::new(
+                simple_audio::Depacketizer::g711(clock_rate, channels, b"alaw")?,
+            )),
+            ("audio", "pcmu") => DepacketizerInner::SimpleAudio(Box::new(
+                simple_audio::Depacketizer::g711(clock_rate, channels, b"ulaw")?,
+            )),
+            ("audio", "u8") | ("audio", "g722") => DepacketizerInner::SimpleAudio(Box::new(
+                simple_audio::Depacketizer::new(clock_rate, 8),
+            )),
             ("audio", "l16") => DepacketizerInner::SimpleAudio(Box::new(
                 simple_audio::Depacketizer::new(clock_rate, 16),
             )),
@@ -391,32 +733,150 @@ impl Depacketizer {
         }))
     }
 
+    fn with_inner(inner: DepacketizerInner) -> Self {
+        Depacketizer {
+            inner,
+            video_frame_durations: false,
+            held_video_frame: None,
+            discontinuity: None,
+            passthrough: None,
+        }
+    }
+
+    /// Returns a depacketizer which passes RTP packets through unmodified as
+    /// [`CodecItem::RawRtp`].
+    pub(crate) fn raw() -> Self {
+        Depacketizer::with_inner(DepacketizerInner::Raw(Box::new(
+            raw::Depacketizer::default(),
+        )))
+    }
+
+    /// Sets the maximum size of a frame which may be reassembled from
+    /// multiple RTP packets. Has no effect on codecs whose frames are
+    /// inherently bounded in size.
+    pub(crate) fn set_max_frame_bytes(&mut self, max_frame_bytes: usize) {
+        match &mut self.inner {
+            DepacketizerInner::H264(d) => d.set_max_frame_bytes(max_frame_bytes),
+            DepacketizerInner::H265(d) => d.set_max_frame_bytes(max_frame_bytes),
+            DepacketizerInner::Jpeg(d) => d.set_max_frame_bytes(max_frame_bytes),
+            DepacketizerInner::Onvif(d) => d.set_max_frame_bytes(max_frame_bytes),
+            _ => {}
+        }
+    }
+
+    /// Sets whether to fill in [`VideoFrame::duration`] by holding each video
+    /// frame until the next one arrives. See
+    /// [`crate::client::SessionOptions::video_frame_durations`].
+    pub(crate) fn set_video_frame_durations(&mut self, video_frame_durations: bool) {
+        self.video_frame_durations = video_frame_durations;
+    }
+
+    /// Sets whether to pass RTP packets through unmodified as
+    /// [`CodecItem::RawRtp`], bypassing codec-specific logic. `parameters`
+    /// still reports those known from the SDP. See
+    /// [`crate::client::SessionOptions::no_depacketization`].
+    pub(crate) fn set_passthrough(&mut self, passthrough: bool) {
+        self.passthrough = match passthrough {
+            true => Some(Box::new(raw::Depacketizer::default())),
+            false => None,
+        };
+    }
+
+    /// Sets whether AAC frames should be prefixed with an ADTS header.
+    /// Has no effect on other codecs.
+    pub(crate) fn set_aac_adts(&mut self, adts: bool) {
+        if let DepacketizerInner::Aac(d) = &mut self.inner {
+            d.set_adts(adts);
+        }
+    }
+
     pub fn parameters(&self) -> Option<Parameters> {
-        match &self.0 {
+        match &self.inner {
             DepacketizerInner::Aac(d) => d.parameters(),
             DepacketizerInner::G723(d) => d.parameters(),
             DepacketizerInner::H264(d) => d.parameters(),
+            DepacketizerInner::H265(d) => d.parameters(),
+            DepacketizerInner::Jpeg(d) => d.parameters(),
             DepacketizerInner::Onvif(d) => d.parameters(),
+            DepacketizerInner::Opus(d) => d.parameters(),
+            DepacketizerInner::Raw(d) => d.parameters(),
             DepacketizerInner::SimpleAudio(d) => d.parameters(),
         }
     }
 
     pub fn push(&mut self, input: rtp::Packet) -> Result<(), String> {
-        match &mut self.0 {
+        if input.discontinuity {
+            self.discontinuity = Some(input.timestamp);
+        }
+        if let Some(d) = &mut self.passthrough {
+            return d.push(input);
+        }
+        match &mut self.inner {
             DepacketizerInner::Aac(d) => d.push(input),
             DepacketizerInner::G723(d) => d.push(input),
             DepacketizerInner::H264(d) => d.push(input),
+            DepacketizerInner::H265(d) => d.push(input),
+            DepacketizerInner::Jpeg(d) => d.push(input),
             DepacketizerInner::Onvif(d) => d.push(input),
+            DepacketizerInner::Opus(d) => d.push(input),
+            DepacketizerInner::Raw(d) => d.push(input),
             DepacketizerInner::SimpleAudio(d) => d.push(input),
         }
     }
 
     pub fn pull(&mut self, conn_ctx: &ConnectionContext) -> Result<Option<CodecItem>, Error> {
-        match &mut self.0 {
+        let mut item = self.pull_inner(conn_ctx)?;
+        if let Some(ts) = self.discontinuity {
+            // Frames completed by the discontinuous packet may still be on
+            // the old timeline; mark the first one which isn't.
+            let discontinuity = match &mut item {
+                Some(CodecItem::VideoFrame(f)) if f.timestamp.start == ts.start => {
+                    Some(&mut f.discontinuity)
+                }
+                Some(CodecItem::AudioFrame(f)) if f.timestamp.start == ts.start => {
+                    Some(&mut f.discontinuity)
+                }
+                _ => None,
+            };
+            if let Some(d) = discontinuity {
+                *d = true;
+                self.discontinuity = None;
+            }
+        }
+        if !self.video_frame_durations {
+            return Ok(item);
+        }
+        match item {
+            Some(CodecItem::VideoFrame(next)) => {
+                let next_timestamp = next.timestamp.timestamp;
+                Ok(self.held_video_frame.replace(next).map(|mut f| {
+                    f.duration = u32::try_from(next_timestamp - f.timestamp.timestamp).ok();
+                    CodecItem::VideoFrame(f)
+                }))
+            }
+            o => Ok(o),
+        }
+    }
+
+    /// Returns the video frame held back for [`VideoFrame::duration`], if
+    /// any, for when the stream has ended. Its duration is unknown.
+    pub(crate) fn flush(&mut self) -> Option<CodecItem> {
+        self.held_video_frame.take().map(CodecItem::VideoFrame)
+    }
+
+    fn pull_inner(&mut self, conn_ctx: &ConnectionContext) -> Result<Option<CodecItem>, Error> {
+        if let Some(d) = &mut self.passthrough {
+            return Ok(d.pull());
+        }
+        match &mut self.inner {
             DepacketizerInner::Aac(d) => d.pull(conn_ctx),
             DepacketizerInner::G723(d) => Ok(d.pull()),
             DepacketizerInner::H264(d) => Ok(d.pull()),
+            DepacketizerInner::H265(d) => Ok(d.pull()),
+            DepacketizerInner::Jpeg(d) => Ok(d.pull()),
             DepacketizerInner::Onvif(d) => Ok(d.pull()),
+            DepacketizerInner::Opus(d) => Ok(d.pull()),
+            DepacketizerInner::Raw(d) => Ok(d.pull()),
             DepacketizerInner::SimpleAudio(d) => Ok(d.pull()),
         }
     }
@@ -426,6 +886,223 @@ impl Depacketizer {
 mod tests {
     use super::*;
 
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serialize_video_parameters() {
+        let p = Parameters::Video(VideoParameters {
+            pixel_dimensions: (1920, 1080),
+            rfc6381_codec: "avc1.4D401E".to_owned(),
+            pixel_aspect_ratio: Some((1, 1)),
+            frame_rate: None,
+            extra_data: Bytes::from_static(b"\x01\x4d\x40\x1e"),
+            parameter_sets: vec![
+                Bytes::from_static(b"\x67\x4d"),
+                Bytes::from_static(b"\x68\xee"),
+            ],
+            sample_entry: None,
+        });
+        assert_eq!(
+            serde_json::to_value(&p).unwrap(),
+            serde_json::json!({
+                "Video": {
+                    "pixel_dimensions": [1920, 1080],
+                    "rfc6381_codec": "avc1.4D401E",
+                    "pixel_aspect_ratio": [1, 1],
+                    "frame_rate": null,
+                    "extra_data": "AU1AHg==",
+                    "parameter_sets": ["Z00=", "aO4="],
+                    "sample_entry": null,
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn into_data_is_zero_copy() {
+        let timestamp = crate::Timestamp::new(0, NonZeroU32::new(90_000).unwrap(), 0).unwrap();
+        let original = vec![0u8; 64];
+        let ptr = original.as_ptr();
+        let data = Bytes::from(original);
+        let video = VideoFrame {
+            new_parameters: None,
+            loss: 0,
+            start_ctx: crate::PacketContext::dummy(),
+            end_ctx: crate::PacketContext::dummy(),
+            timestamp,
+            stream_id: 0,
+            is_random_access_point: true,
+            is_disposable: false,
+            duration: None,
+            discontinuity: false,
+            data: data.clone(),
+        };
+        assert_eq!(video.data().as_ptr(), ptr);
+        assert_eq!(video.into_data().as_ptr(), ptr);
+        let audio = AudioFrame {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp,
+            frame_length: NonZeroU32::new(1024).unwrap(),
+            loss: 0,
+            discontinuity: false,
+            data: data.clone(),
+        };
+        assert_eq!(audio.data().as_ptr(), ptr);
+        assert_eq!(audio.into_data().as_ptr(), ptr);
+        let message = MessageFrame {
+            ctx: crate::PacketContext::dummy(),
+            timestamp,
+            stream_id: 0,
+            loss: 0,
+            data,
+        };
+        assert_eq!(message.data().as_ptr(), ptr);
+        assert_eq!(message.into_data().as_ptr(), ptr);
+    }
+
+    #[test]
+    fn codec_item_accessors() {
+        let timestamp = crate::Timestamp::new(90_000, NonZeroU32::new(90_000).unwrap(), 0).unwrap();
+        let items = vec![
+            CodecItem::AudioFrame(AudioFrame {
+                ctx: crate::PacketContext::dummy(),
+                stream_id: 1,
+                timestamp,
+                frame_length: NonZeroU32::new(1024).unwrap(),
+                loss: 0,
+                discontinuity: false,
+                data: Bytes::new(),
+            }),
+            CodecItem::SenderReport(crate::client::rtp::SenderReport {
+                stream_id: 0,
+                ctx: crate::PacketContext::dummy(),
+                timestamp,
+                ntp_timestamp: crate::NtpTimestamp(0),
+            }),
+            CodecItem::Loss {
+                stream_id: 2,
+                lost_range: 5..7,
+                ctx: crate::PacketContext::dummy(),
+            },
+        ];
+        let summary: Vec<_> = items
+            .iter()
+            .map(|i| (i.stream_id(), i.timestamp()))
+            .collect();
+        assert_eq!(
+            summary,
+            [(1, Some(timestamp)), (0, Some(timestamp)), (2, None)]
+        );
+        for i in &items {
+            i.ctx();
+        }
+    }
+
+    #[test]
+    fn discontinuity() {
+        let mut d = Depacketizer::new(
+            "video",
+            "h264",
+            90_000,
+            None,
+            Some("packetization-mode=1;profile-level-id=640033;sprop-parameter-sets=Z2QAM6wVFKAoAPGQ,aO48sA=="),
+        )
+        .unwrap();
+        let conn_ctx = ConnectionContext::dummy();
+        let mut frames = Vec::new();
+
+        // (timestamp, start, mark, discontinuity). The reset packet completes
+        // the previous, unmarked frame, which stays unflagged; the frame it
+        // starts is flagged.
+        for (i, &(ts, start, mark, discontinuity)) in [
+            (0, 0, true, false),
+            (3000, 0, false, false),
+            (100, 100, false, true),
+            (100, 100, true, false),
+            (3100, 100, true, false),
+        ]
+        .iter()
+        .enumerate()
+        {
+            d.push(rtp::Packet {
+                ctx: crate::PacketContext::dummy(),
+                stream_id: 0,
+                timestamp: crate::Timestamp::new(ts, NonZeroU32::new(90_000).unwrap(), start)
+                    .unwrap(),
+                ssrc: 0,
+                sequence_number: i as u16,
+                loss: 0,
+                mark,
+                discontinuity,
+                header: Bytes::new(),
+                payload: Bytes::from_static(b"\x65slice"),
+            })
+            .unwrap();
+            match d.pull(&conn_ctx).unwrap() {
+                None => {}
+                Some(CodecItem::VideoFrame(f)) => {
+                    frames.push((f.timestamp.timestamp, f.discontinuity))
+                }
+                Some(o) => panic!("unexpected item {:#?}", o),
+            }
+        }
+        assert_eq!(
+            frames,
+            [(0, false), (3000, false), (100, true), (3100, false)]
+        );
+    }
+
+    #[test]
+    fn video_frame_durations() {
+        let mut d = Depacketizer::new(
+            "video",
+            "h264",
+            90_000,
+            None,
+            Some("packetization-mode=1;profile-level-id=640033;sprop-parameter-sets=Z2QAM6wVFKAoAPGQ,aO48sA=="),
+        )
+        .unwrap();
+        d.set_video_frame_durations(true);
+        let conn_ctx = ConnectionContext::dummy();
+        let mut durations = Vec::new();
+        for (i, &ts) in [0, 3000, 6003, 9003].iter().enumerate() {
+            d.push(rtp::Packet {
+                ctx: crate::PacketContext::dummy(),
+                stream_id: 0,
+                timestamp: crate::Timestamp::new(ts, NonZeroU32::new(90_000).unwrap(), 0).unwrap(),
+                ssrc: 0,
+                sequence_number: i as u16,
+                loss: 0,
+                mark: true,
+                discontinuity: false,
+                header: Bytes::new(),
+                payload: Bytes::from_static(b"\x65slice"),
+            })
+            .unwrap();
+            match d.pull(&conn_ctx).unwrap() {
+                None => durations.push(None),
+                Some(CodecItem::VideoFrame(f)) => durations.push(Some(f.duration())),
+                Some(o) => panic!("unexpected item {:#?}", o),
+            }
+        }
+
+        // Each frame is held until the next, so the first pull yields nothing.
+        assert_eq!(
+            durations,
+            [None, Some(Some(3000)), Some(Some(3003)), Some(Some(3000))]
+        );
+
+        // The last frame is flushed at the end with no known duration.
+        match d.flush() {
+            Some(CodecItem::VideoFrame(f)) => {
+                assert_eq!(f.timestamp.timestamp, 9003);
+                assert_eq!(f.duration(), None);
+            }
+            o => panic!("unexpected flush result {:#?}", o),
+        }
+        assert!(d.flush().is_none());
+    }
+
     // See with: cargo test -- --nocapture codec::tests::print_sizes
     #[test]
     fn print_sizes() {
@@ -443,10 +1120,26 @@ mod tests {
                 "h264::Depacketizer",
                 std::mem::size_of::<h264::Depacketizer>(),
             ),
+            (
+                "h265::Depacketizer",
+                std::mem::size_of::<h265::Depacketizer>(),
+            ),
+            (
+                "jpeg::Depacketizer",
+                std::mem::size_of::<jpeg::Depacketizer>(),
+            ),
             (
                 "onvif::Depacketizer",
                 std::mem::size_of::<onvif::Depacketizer>(),
             ),
+            (
+                "opus::Depacketizer",
+                std::mem::size_of::<opus::Depacketizer>(),
+            ),
+            (
+                "raw::Depacketizer",
+                std::mem::size_of::<raw::Depacketizer>(),
+            ),
             (
                 "simple_audio::Depacketizer",
                 std::mem::size_of::<simple_audio::Depacketizer>(),
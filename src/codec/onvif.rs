@@ -7,12 +7,17 @@
 //! [ONVIF Streaming Specification](https://www.onvif.org/specs/stream/ONVIF-Streaming-Spec.pdf)
 //! version 19.12 section 5.2.1.1. The RTP layer muxing is simple: RTP packets with the MARK
 //! bit set end messages.
+//!
+//! Each message is a complete XML document (possibly compressed), so a
+//! document spanning several RTP packets is buffered until the MARK bit and
+//! yielded as a single [`super::MessageFrame`].
 
 use bytes::{Buf, BufMut, BytesMut};
 
 use super::CodecItem;
 
 #[derive(Copy, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum CompressionType {
     Uncompressed,
     GzipCompressed,
@@ -25,6 +30,14 @@ pub(crate) struct Depacketizer {
     compression_type: CompressionType,
     state: State,
     high_water_size: usize,
+
+    /// The maximum size of a message; see
+    /// [`crate::client::SessionOptions::max_frame_bytes`].
+    max_frame_bytes: usize,
+
+    /// Packets discarded because their message exceeded `max_frame_bytes`,
+    /// to be reported on the next message.
+    loss: u16,
 }
 
 #[derive(Debug)]
@@ -48,9 +61,15 @@ impl Depacketizer {
             compression_type,
             state: State::Idle,
             high_water_size: 0,
+            max_frame_bytes: super::DEFAULT_MAX_FRAME_BYTES,
+            loss: 0,
         }
     }
 
+    pub(super) fn set_max_frame_bytes(&mut self, max_frame_bytes: usize) {
+        self.max_frame_bytes = max_frame_bytes;
+    }
+
     pub(super) fn parameters(&self) -> Option<super::Parameters> {
         Some(super::Parameters::Message(super::MessageParameters(
             self.compression_type,
@@ -80,11 +99,13 @@ impl Depacketizer {
             }
             State::Ready(..) => panic!("push while in state ready"),
             State::Idle => {
+                let loss = pkt.loss.saturating_add(std::mem::take(&mut self.loss));
                 if pkt.mark {
                     // fast-path: avoid copy.
+                    self.validate(&pkt.payload[..])?;
                     self.state = State::Ready(super::MessageFrame {
                         stream_id: pkt.stream_id,
-                        loss: pkt.loss,
+                        loss,
                         ctx: pkt.ctx,
                         timestamp: pkt.timestamp,
                         data: pkt.payload,
@@ -92,15 +113,24 @@ impl Depacketizer {
                     return Ok(());
                 }
                 InProgress {
-                    loss: pkt.loss,
+                    loss,
                     ctx: pkt.ctx,
                     timestamp: pkt.timestamp,
                     data: BytesMut::with_capacity(self.high_water_size),
                 }
             }
         };
+        if in_progress.data.len() + pkt.payload.len() > self.max_frame_bytes {
+            // Leave the state Idle, discarding the prefix.
+            self.loss = in_progress.loss.saturating_add(1);
+            return Err(format!(
+                "ONVIF metadata message at {} exceeds max_frame_bytes={}",
+                &in_progress.timestamp, self.max_frame_bytes
+            ));
+        }
         in_progress.data.put(pkt.payload);
         if pkt.mark {
+            self.validate(&in_progress.data[..])?;
             self.high_water_size =
                 std::cmp::max(self.high_water_size, in_progress.data.remaining());
             self.state = State::Ready(super::MessageFrame {
@@ -116,6 +146,26 @@ impl Depacketizer {
         Ok(())
     }
 
+    /// Checks a complete message before it's yielded.
+    ///
+    /// Compressed messages are opaque here, but an uncompressed message must
+    /// be a non-empty UTF-8 XML document.
+    fn validate(&self, data: &[u8]) -> Result<(), String> {
+        if data.is_empty() {
+            return Err("empty ONVIF metadata message".to_owned());
+        }
+        if let CompressionType::Uncompressed = self.compression_type {
+            if let Err(e) = std::str::from_utf8(data) {
+                return Err(format!(
+                    "ONVIF metadata message of {} bytes is not valid UTF-8: {}",
+                    data.len(),
+                    e
+                ));
+            }
+        }
+        Ok(())
+    }
+
     pub(super) fn pull(&mut self) -> Option<CodecItem> {
         match std::mem::replace(&mut self.state, State::Idle) {
             State::Ready(message) => Some(CodecItem::MessageFrame(message)),
@@ -126,3 +176,136 @@ impl Depacketizer {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use bytes::Bytes;
+
+    use super::CompressionType;
+    use crate::{client::rtp::Packet, codec::CodecItem};
+
+    const DOC: &[u8] = b"<?xml version=\"1.0\" encoding=\"UTF-8\"?>\
+        <tt:MetadataStream xmlns:tt=\"http://www.onvif.org/ver10/schema\">\
+        <tt:Event><wsnt:NotificationMessage/></tt:Event>\
+        </tt:MetadataStream>";
+
+    fn packet(sequence_number: u16, mark: bool, payload: &'static [u8]) -> Packet {
+        Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: crate::Timestamp {
+                timestamp: 0,
+                clock_rate: NonZeroU32::new(90_000).unwrap(),
+                start: 0,
+            },
+            ssrc: 0,
+            sequence_number,
+            loss: 0,
+            mark,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(payload),
+        }
+    }
+
+    #[test]
+    fn reassemble() {
+        let mut d = super::Depacketizer::new(CompressionType::Uncompressed);
+        let (a, rest) = DOC.split_at(40);
+        let (b, c) = rest.split_at(50);
+        d.push(packet(0, false, a)).unwrap();
+        assert!(d.pull().is_none());
+        d.push(packet(1, false, b)).unwrap();
+        assert!(d.pull().is_none());
+        d.push(packet(2, true, c)).unwrap();
+        match d.pull() {
+            Some(CodecItem::MessageFrame(m)) => assert_eq!(&m.data[..], DOC),
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+        assert!(d.pull().is_none());
+
+        // A single-packet message takes the fast path.
+        d.push(packet(3, true, DOC)).unwrap();
+        match d.pull() {
+            Some(CodecItem::MessageFrame(m)) => assert_eq!(&m.data[..], DOC),
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn timestamp() {
+        let ts = crate::Timestamp {
+            timestamp: 0x1_0000_1234,
+            clock_rate: NonZeroU32::new(90_000).unwrap(),
+            start: 0x1000,
+        };
+        let mut d = super::Depacketizer::new(CompressionType::Uncompressed);
+        let (a, b) = DOC.split_at(40);
+        let mut p = packet(0, false, a);
+        p.timestamp = ts;
+        d.push(p).unwrap();
+        let mut p = packet(1, true, b);
+        p.timestamp = ts;
+        d.push(p).unwrap();
+        match d.pull() {
+            Some(CodecItem::MessageFrame(m)) => {
+                assert_eq!(m.timestamp, ts);
+                assert_eq!(m.timestamp.elapsed(), 0x1_0000_1234 - 0x1000);
+            }
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn discard_after_loss() {
+        let mut d = super::Depacketizer::new(CompressionType::Uncompressed);
+        let (a, rest) = DOC.split_at(40);
+        let (_, c) = rest.split_at(50);
+        d.push(packet(0, false, a)).unwrap();
+        let mut p = packet(2, true, c);
+        p.loss = 1;
+        d.push(p).unwrap();
+        match d.pull() {
+            Some(CodecItem::MessageFrame(m)) => {
+                assert_eq!(m.loss, 1);
+                assert_eq!(&m.data[..], c);
+            }
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn max_frame_bytes() {
+        let mut d = super::Depacketizer::new(CompressionType::Uncompressed);
+        d.set_max_frame_bytes(64);
+        let (a, rest) = DOC.split_at(40);
+        let (b, c) = rest.split_at(50);
+        d.push(packet(0, false, a)).unwrap();
+        let e = d.push(packet(1, false, b)).unwrap_err();
+        assert!(e.contains("max_frame_bytes=64"), "{}", e);
+
+        // The next message reports loss, as its prefix may have been discarded.
+        d.push(packet(2, true, c)).unwrap();
+        match d.pull() {
+            Some(CodecItem::MessageFrame(m)) => assert_eq!(m.loss, 1),
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+    }
+
+    #[test]
+    fn reject_invalid() {
+        let mut d = super::Depacketizer::new(CompressionType::Uncompressed);
+        d.push(packet(0, true, b"")).unwrap_err();
+
+        let mut d = super::Depacketizer::new(CompressionType::Uncompressed);
+        d.push(packet(0, false, b"<tt:Metadata\xff")).unwrap();
+        d.push(packet(1, true, b"Stream/>")).unwrap_err();
+
+        // Compressed messages aren't expected to be UTF-8.
+        let mut d = super::Depacketizer::new(CompressionType::GzipCompressed);
+        d.push(packet(0, true, b"\x1f\x8b\x08\x00")).unwrap();
+        assert!(d.pull().is_some());
+    }
+}
@@ -0,0 +1,378 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Parsing of common fields from uncompressed ONVIF `tt:MetadataStream`
+//! documents, as carried in [`super::MessageFrame::data`].
+//!
+//! This covers the video analytics objects (with their bounding boxes) and
+//! events (such as motion detection) described in the
+//! [ONVIF Analytics Service Specification](https://www.onvif.org/specs/srv/analytics/ONVIF-Analytics-Service-Spec.pdf).
+//! It's lenient: unknown elements and attributes are ignored, as are
+//! malformed numbers. The raw XML remains available in the `MessageFrame`
+//! for anything not extracted here.
+//!
+//! Requires the `onvif-events` feature.
+
+use quick_xml::events::{BytesStart, Event as XmlEvent};
+
+use crate::{Error, ErrorInt};
+
+/// The extracted contents of a `tt:MetadataStream` document.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetadataStream {
+    /// Video analytics frames, from `tt:VideoAnalytics/tt:Frame` elements.
+    pub frames: Vec<Frame>,
+
+    /// Events, from `tt:Event/wsnt:NotificationMessage` elements.
+    pub events: Vec<Event>,
+}
+
+/// A `tt:Frame`: the objects detected at a particular time.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Frame {
+    /// The `UtcTime` attribute, unparsed, eg `2021-10-01T12:34:56.789Z`.
+    pub utc_time: Option<String>,
+    pub objects: Vec<Object>,
+}
+
+/// A `tt:Object` within a [`Frame`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Object {
+    /// The `ObjectId` attribute.
+    pub object_id: Option<String>,
+
+    /// The `tt:Appearance/tt:Shape/tt:BoundingBox`, if present and valid.
+    pub bounding_box: Option<BoundingBox>,
+
+    /// The text of any `tt:Type` elements within `tt:Appearance/tt:Class`,
+    /// such as `Human` or `Vehicle`.
+    pub types: Vec<String>,
+}
+
+/// A rectangle in ONVIF's normalized coordinate system, in which `(-1, -1)`
+/// is the bottom left and `(1, 1)` is the top right of the frame unless a
+/// `tt:Transformation` says otherwise.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct BoundingBox {
+    pub left: f32,
+    pub top: f32,
+    pub right: f32,
+    pub bottom: f32,
+}
+
+/// A `wsnt:NotificationMessage`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Event {
+    /// The `wsnt:Topic` text, eg `tns1:RuleEngine/CellMotionDetector/Motion`.
+    pub topic: Option<String>,
+
+    /// The `UtcTime` attribute of the `tt:Message`, unparsed.
+    pub utc_time: Option<String>,
+
+    /// The `PropertyOperation` attribute of the `tt:Message`, eg
+    /// `Initialized`, `Changed`, or `Deleted`.
+    pub property_operation: Option<String>,
+
+    /// `tt:SimpleItem`s within `tt:Source`.
+    pub source: Vec<SimpleItem>,
+
+    /// `tt:SimpleItem`s within `tt:Key`.
+    pub key: Vec<SimpleItem>,
+
+    /// `tt:SimpleItem`s within `tt:Data`.
+    pub data: Vec<SimpleItem>,
+}
+
+impl Event {
+    /// Returns whether motion is detected, for motion events.
+    ///
+    /// This recognizes the `IsMotion` data item of cell motion detection
+    /// (`tns1:RuleEngine/CellMotionDetector/Motion`) and video source
+    /// (`tns1:VideoSource/MotionAlarm`) events, and the `State` data item of
+    /// motion region detection (`tns1:RuleEngine/MotionRegionDetector/Motion`).
+    pub fn is_motion(&self) -> Option<bool> {
+        if !self.topic.as_deref().unwrap_or("").contains("Motion") {
+            return None;
+        }
+        self.data
+            .iter()
+            .find(|i| i.name == "IsMotion" || i.name == "State")
+            .and_then(|i| match i.value.as_str() {
+                "true" | "1" => Some(true),
+                "false" | "0" => Some(false),
+                _ => None,
+            })
+    }
+}
+
+/// A `tt:SimpleItem` name/value pair.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SimpleItem {
+    pub name: String,
+    pub value: String,
+}
+
+/// Where a `tt:SimpleItem` belongs within its event.
+#[derive(Copy, Clone)]
+enum ItemList {
+    Source,
+    Key,
+    Data,
+}
+
+impl MetadataStream {
+    /// Parses an uncompressed `tt:MetadataStream` document.
+    ///
+    /// Fails only if the document isn't well-formed XML.
+    pub fn parse(xml: &[u8]) -> Result<Self, Error> {
+        let mut reader = quick_xml::Reader::from_reader(xml);
+        reader.trim_text(true);
+        let mut parser = Parser::default();
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf) {
+                Ok(XmlEvent::Start(e)) => {
+                    parser.element(&e);
+                    parser.stack.push(e.local_name().to_vec());
+                }
+                Ok(XmlEvent::Empty(e)) => parser.element(&e),
+                Ok(XmlEvent::End(_)) => {
+                    parser.stack.pop();
+                }
+                Ok(XmlEvent::Text(t)) => {
+                    if let Ok(t) = t.unescaped() {
+                        parser.text(String::from_utf8_lossy(&t).into_owned());
+                    }
+                }
+                Ok(XmlEvent::Eof) => break,
+                Ok(_) => {}
+                Err(e) => bail!(ErrorInt::InvalidArgument(format!(
+                    "bad ONVIF metadata at position {}: {}",
+                    reader.buffer_position(),
+                    e
+                ))),
+            }
+            buf.clear();
+        }
+        Ok(parser.out)
+    }
+}
+
+#[derive(Default)]
+struct Parser {
+    out: MetadataStream,
+
+    /// Local names of the currently open elements, outermost first.
+    stack: Vec<Vec<u8>>,
+}
+
+impl Parser {
+    fn parent(&self) -> &[u8] {
+        self.stack.last().map(Vec::as_slice).unwrap_or(b"")
+    }
+
+    fn within(&self, name: &[u8]) -> bool {
+        self.stack.iter().any(|n| n == name)
+    }
+
+    /// Handles the start of an element (whether or not it's empty).
+    fn element(&mut self, e: &BytesStart) {
+        match e.local_name() {
+            b"Frame" if self.parent() == b"VideoAnalytics" => {
+                self.out.frames.push(Frame {
+                    utc_time: attr(e, b"UtcTime"),
+                    objects: Vec::new(),
+                });
+            }
+            b"Object" if self.parent() == b"Frame" => {
+                if let Some(f) = self.out.frames.last_mut() {
+                    f.objects.push(Object {
+                        object_id: attr(e, b"ObjectId"),
+                        ..Default::default()
+                    });
+                }
+            }
+            b"BoundingBox" if self.within(b"Object") => {
+                let coord = |name: &[u8]| attr(e, name).and_then(|v| v.parse::<f32>().ok());
+                let bounding_box = match (
+                    coord(b"left"),
+                    coord(b"top"),
+                    coord(b"right"),
+                    coord(b"bottom"),
+                ) {
+                    (Some(left), Some(top), Some(right), Some(bottom)) => Some(BoundingBox {
+                        left,
+                        top,
+                        right,
+                        bottom,
+                    }),
+                    _ => None,
+                };
+                if let Some(o) = self.current_object() {
+                    o.bounding_box = bounding_box;
+                }
+            }
+            b"NotificationMessage" if self.within(b"Event") => {
+                self.out.events.push(Event::default());
+            }
+            b"Message" if self.within(b"NotificationMessage") => {
+                if let Some(ev) = self.out.events.last_mut() {
+                    if let Some(t) = attr(e, b"UtcTime") {
+                        ev.utc_time = Some(t);
+                    }
+                    if let Some(o) = attr(e, b"PropertyOperation") {
+                        ev.property_operation = Some(o);
+                    }
+                }
+            }
+            b"SimpleItem" if self.within(b"NotificationMessage") => {
+                let list = match self.parent() {
+                    b"Source" => ItemList::Source,
+                    b"Key" => ItemList::Key,
+                    b"Data" => ItemList::Data,
+                    _ => return,
+                };
+                let item = match (attr(e, b"Name"), attr(e, b"Value")) {
+                    (Some(name), Some(value)) => SimpleItem { name, value },
+                    _ => return,
+                };
+                if let Some(ev) = self.out.events.last_mut() {
+                    match list {
+                        ItemList::Source => ev.source.push(item),
+                        ItemList::Key => ev.key.push(item),
+                        ItemList::Data => ev.data.push(item),
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Handles text within the innermost open element.
+    fn text(&mut self, text: String) {
+        match self.parent() {
+            b"Topic" if self.within(b"NotificationMessage") => {
+                if let Some(ev) = self.out.events.last_mut() {
+                    ev.topic = Some(text);
+                }
+            }
+            b"Type" if self.within(b"Object") && self.within(b"Class") => {
+                if let Some(o) = self.current_object() {
+                    o.types.push(text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn current_object(&mut self) -> Option<&mut Object> {
+        self.out
+            .frames
+            .last_mut()
+            .and_then(|f| f.objects.last_mut())
+    }
+}
+
+/// Returns the unescaped value of the given (unprefixed) attribute, if present.
+fn attr(e: &BytesStart, name: &[u8]) -> Option<String> {
+    e.attributes()
+        .filter_map(Result::ok)
+        .find(|a| a.key == name)
+        .and_then(|a| {
+            a.unescaped_value()
+                .ok()
+                .map(|v| String::from_utf8_lossy(&v).into_owned())
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ANALYTICS: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<tt:MetadataStream xmlns:tt="http://www.onvif.org/ver10/schema" xmlns:wsnt="http://docs.oasis-open.org/wsn/b-2" xmlns:tns1="http://www.onvif.org/ver10/topics">
+  <tt:VideoAnalytics>
+    <tt:Frame UtcTime="2021-10-01T12:34:56.789Z">
+      <tt:Transformation><tt:Translate x="-1.0" y="-1.0"/></tt:Transformation>
+      <tt:Object ObjectId="12">
+        <tt:Appearance>
+          <tt:Shape>
+            <tt:BoundingBox left="-0.5" top="0.75" right="0.25" bottom="-0.125"/>
+            <tt:CenterOfGravity x="-0.125" y="0.3125"/>
+          </tt:Shape>
+          <tt:Class>
+            <tt:ClassCandidate><tt:Type>Human</tt:Type><tt:Likelihood>0.9</tt:Likelihood></tt:ClassCandidate>
+          </tt:Class>
+        </tt:Appearance>
+      </tt:Object>
+      <tt:Object ObjectId="13">
+        <tt:Appearance>
+          <tt:Shape><tt:BoundingBox left="0.0" top="1.0" right="bogus" bottom="0.5"/></tt:Shape>
+          <tt:VendorExtension foo="bar"/>
+        </tt:Appearance>
+      </tt:Object>
+    </tt:Frame>
+  </tt:VideoAnalytics>
+  <tt:Event>
+    <wsnt:NotificationMessage>
+      <wsnt:Topic Dialect="http://www.onvif.org/ver10/tev/topicExpression/ConcreteSet">tns1:RuleEngine/CellMotionDetector/Motion</wsnt:Topic>
+      <wsnt:Message>
+        <tt:Message UtcTime="2021-10-01T12:34:56Z" PropertyOperation="Changed">
+          <tt:Source>
+            <tt:SimpleItem Name="VideoSourceConfigurationToken" Value="VideoSourceToken"/>
+            <tt:SimpleItem Name="Rule" Value="MyMotionDetectorRule"/>
+          </tt:Source>
+          <tt:Data><tt:SimpleItem Name="IsMotion" Value="true"/></tt:Data>
+        </tt:Message>
+      </wsnt:Message>
+    </wsnt:NotificationMessage>
+  </tt:Event>
+</tt:MetadataStream>"#;
+
+    #[test]
+    fn analytics() {
+        let m = MetadataStream::parse(ANALYTICS).unwrap();
+        assert_eq!(m.frames.len(), 1);
+        let f = &m.frames[0];
+        assert_eq!(f.utc_time.as_deref(), Some("2021-10-01T12:34:56.789Z"));
+        assert_eq!(
+            f.objects,
+            vec![
+                Object {
+                    object_id: Some("12".to_owned()),
+                    bounding_box: Some(BoundingBox {
+                        left: -0.5,
+                        top: 0.75,
+                        right: 0.25,
+                        bottom: -0.125,
+                    }),
+                    types: vec!["Human".to_owned()],
+                },
+                Object {
+                    object_id: Some("13".to_owned()),
+                    bounding_box: None,
+                    types: Vec::new(),
+                },
+            ]
+        );
+
+        assert_eq!(m.events.len(), 1);
+        let e = &m.events[0];
+        assert_eq!(
+            e.topic.as_deref(),
+            Some("tns1:RuleEngine/CellMotionDetector/Motion")
+        );
+        assert_eq!(e.utc_time.as_deref(), Some("2021-10-01T12:34:56Z"));
+        assert_eq!(e.property_operation.as_deref(), Some("Changed"));
+        assert_eq!(e.source.len(), 2);
+        assert_eq!(e.source[1].name, "Rule");
+        assert_eq!(e.source[1].value, "MyMotionDetectorRule");
+        assert!(e.key.is_empty());
+        assert_eq!(e.is_motion(), Some(true));
+    }
+
+    #[test]
+    fn malformed() {
+        MetadataStream::parse(b"<tt:MetadataStream><tt:Event></tt:MetadataStream>").unwrap_err();
+    }
+}
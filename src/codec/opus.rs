@@ -0,0 +1,204 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! [Opus](https://datatracker.ietf.org/doc/html/rfc6716) audio, as packetized
+//! in [RFC 7587](https://datatracker.ietf.org/doc/html/rfc7587).
+
+use std::num::{NonZeroU16, NonZeroU32};
+
+use bytes::Bytes;
+
+use super::CodecItem;
+
+/// The Opus RTP clock rate, which is fixed regardless of the actual sampling
+/// rate. See [RFC 7587 section 4.1](https://datatracker.ietf.org/doc/html/rfc7587#section-4.1).
+const CLOCK_RATE: u32 = 48_000;
+
+/// The maximum duration of an Opus packet, 120 ms, in 48 kHz samples.
+const MAX_PACKET_SAMPLES: u32 = 5_760;
+
+#[derive(Debug)]
+pub(crate) struct Depacketizer {
+    pending: Option<super::AudioFrame>,
+    channels: NonZeroU16,
+}
+
+impl Depacketizer {
+    /// Creates a new Depacketizer.
+    pub(super) fn new(
+        clock_rate: u32,
+        format_specific_params: Option<&str>,
+    ) -> Result<Self, String> {
+        if clock_rate != CLOCK_RATE {
+            return Err(format!(
+                "invalid Opus clock rate {}; must always be {}",
+                clock_rate, CLOCK_RATE
+            ));
+        }
+
+        // The rtpmap's channel count is always 2; `sprop-stereo` says whether
+        // the sender is likely to actually send stereo.
+        let mut stereo = false;
+        for p in format_specific_params.unwrap_or("").split(';') {
+            if let Some((key, value)) = p.trim().split_once('=') {
+                if key == "sprop-stereo" {
+                    stereo = value == "1";
+                }
+            }
+        }
+        Ok(Self {
+            pending: None,
+            channels: NonZeroU16::new(if stereo { 2 } else { 1 }).expect("non-zero"),
+        })
+    }
+
+    pub(super) fn parameters(&self) -> Option<super::Parameters> {
+        Some(super::Parameters::Audio(super::AudioParameters {
+            rfc6381_codec: Some("opus".to_owned()),
+            frame_length: None, // variable
+            clock_rate: CLOCK_RATE,
+            extra_data: Bytes::new(),
+            sample_entry: None,
+            sample_rate: Some(CLOCK_RATE),
+            channels: Some(self.channels),
+        }))
+    }
+
+    pub(super) fn push(&mut self, pkt: crate::client::rtp::Packet) -> Result<(), String> {
+        assert!(self.pending.is_none());
+        let frame_length = packet_samples(&pkt.payload[..])?;
+        self.pending = Some(super::AudioFrame {
+            loss: pkt.loss,
+            ctx: pkt.ctx,
+            stream_id: pkt.stream_id,
+            timestamp: pkt.timestamp,
+            frame_length,
+            discontinuity: false,
+            data: pkt.payload,
+        });
+        Ok(())
+    }
+
+    pub(super) fn pull(&mut self) -> Option<super::CodecItem> {
+        self.pending.take().map(CodecItem::AudioFrame)
+    }
+}
+
+/// Returns the duration of an Opus packet in 48 kHz samples, as determined by
+/// its TOC byte and (for code 3) frame count byte.
+/// See [RFC 6716 section 3.1](https://datatracker.ietf.org/doc/html/rfc6716#section-3.1).
+fn packet_samples(packet: &[u8]) -> Result<NonZeroU32, String> {
+    let toc = *packet
+        .first()
+        .ok_or_else(|| "empty Opus packet".to_string())?;
+    let config = toc >> 3;
+    let frame_samples = match config {
+        // SILK-only: 10, 20, 40, or 60 ms.
+        0..=11 => [480, 960, 1_920, 2_880][usize::from(config & 0b11)],
+
+        // Hybrid: 10 or 20 ms.
+        12..=15 => [480, 960][usize::from(config & 0b1)],
+
+        // CELT-only: 2.5, 5, 10, or 20 ms.
+        _ => [120, 240, 480, 960][usize::from(config & 0b11)],
+    };
+    let frames = match toc & 0b11 {
+        0 => 1,
+        1 | 2 => 2,
+        _ => {
+            let count = packet
+                .get(1)
+                .ok_or_else(|| "code 3 Opus packet missing frame count byte".to_string())?;
+            u32::from(count & 0b11_1111)
+        }
+    };
+    let samples = frames * frame_samples;
+    if samples > MAX_PACKET_SAMPLES {
+        return Err(format!(
+            "Opus packet with TOC {:02x} has duration {} samples, exceeding 120 ms",
+            toc, samples
+        ));
+    }
+    NonZeroU32::new(samples).ok_or_else(|| "code 3 Opus packet with 0 frames".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use bytes::Bytes;
+
+    use crate::{client::rtp::Packet, codec::CodecItem};
+
+    #[test]
+    fn packet_samples() {
+        for &(packet, samples) in &[
+            (&b"\x00"[..], 480),         // config 0: SILK NB 10 ms, code 0
+            (&b"\x18"[..], 2_880),       // config 3: SILK NB 60 ms
+            (&b"\x09"[..], 2 * 960),     // config 1: SILK NB 20 ms, code 1
+            (&b"\x78"[..], 960),         // config 15: hybrid FB 20 ms
+            (&b"\x80"[..], 120),         // config 16: CELT NB 2.5 ms
+            (&b"\xfc"[..], 960),         // config 31: CELT FB 20 ms
+            (&b"\xfe\x00"[..], 2 * 960), // config 31, code 2
+            (&b"\xf3\x03"[..], 3 * 480), // config 30: CELT FB 10 ms, code 3 with 3 frames
+        ] {
+            assert_eq!(
+                super::packet_samples(packet).unwrap().get(),
+                samples,
+                "packet {:02x?}",
+                packet
+            );
+        }
+
+        super::packet_samples(b"").unwrap_err();
+        super::packet_samples(b"\x03").unwrap_err(); // code 3 missing frame count.
+        super::packet_samples(b"\x03\x00").unwrap_err(); // code 3 with 0 frames.
+        super::packet_samples(b"\x1b\x03").unwrap_err(); // 3 * 60 ms exceeds 120 ms.
+    }
+
+    #[test]
+    fn depacketize() {
+        let mut d = super::Depacketizer::new(48_000, Some("minptime=10;useinbandfec=1")).unwrap();
+        match d.parameters() {
+            Some(crate::codec::Parameters::Audio(p)) => {
+                assert_eq!(p.rfc6381_codec(), Some("opus"));
+                assert_eq!(p.clock_rate(), 48_000);
+                assert_eq!(p.channels().map(|c| c.get()), Some(1));
+            }
+            o => panic!("unexpected parameters {:#?}", o),
+        }
+        d.push(Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 0,
+            timestamp: crate::Timestamp {
+                timestamp: 0,
+                clock_rate: NonZeroU32::new(48_000).unwrap(),
+                start: 0,
+            },
+            ssrc: 0,
+            sequence_number: 0,
+            loss: 0,
+            mark: false,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"\xfc\x01\x02"),
+        })
+        .unwrap();
+        match d.pull() {
+            Some(CodecItem::AudioFrame(f)) => {
+                assert_eq!(f.frame_length.get(), 960);
+                assert_eq!(&f.data[..], b"\xfc\x01\x02");
+            }
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+
+        let d = super::Depacketizer::new(48_000, Some("sprop-stereo=1")).unwrap();
+        match d.parameters() {
+            Some(crate::codec::Parameters::Audio(p)) => {
+                assert_eq!(p.channels().map(|c| c.get()), Some(2))
+            }
+            o => panic!("unexpected parameters {:#?}", o),
+        }
+        super::Depacketizer::new(8_000, None).unwrap_err();
+    }
+}
@@ -0,0 +1,77 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Passthrough of RTP packets for codecs without a depacketizer.
+//!
+//! Used only when [`crate::client::SessionOptions::allow_unknown_codecs`] or
+//! [`crate::client::SessionOptions::no_depacketization`] is set. Each RTP
+//! packet is yielded as-is via [`CodecItem::RawRtp`] so the caller can
+//! depacketize or forward it themselves.
+
+use super::CodecItem;
+
+#[derive(Debug, Default)]
+pub(crate) struct Depacketizer {
+    pending: Option<crate::client::rtp::Packet>,
+}
+
+impl Depacketizer {
+    pub(super) fn parameters(&self) -> Option<super::Parameters> {
+        None
+    }
+
+    pub(super) fn push(&mut self, pkt: crate::client::rtp::Packet) -> Result<(), String> {
+        assert!(self.pending.is_none());
+        self.pending = Some(pkt);
+        Ok(())
+    }
+
+    pub(super) fn pull(&mut self) -> Option<CodecItem> {
+        self.pending.take().map(CodecItem::RawRtp)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroU32;
+
+    use bytes::Bytes;
+
+    use crate::{client::rtp::Packet, codec::CodecItem};
+
+    #[test]
+    fn passthrough() {
+        let mut d = super::Depacketizer::default();
+        assert!(d.parameters().is_none());
+        assert!(d.pull().is_none());
+        d.push(Packet {
+            ctx: crate::PacketContext::dummy(),
+            stream_id: 1,
+            timestamp: crate::Timestamp {
+                timestamp: 42,
+                clock_rate: NonZeroU32::new(90_000).unwrap(),
+                start: 0,
+            },
+            ssrc: 0x1234,
+            sequence_number: 7,
+            loss: 2,
+            mark: true,
+            discontinuity: false,
+            header: Bytes::new(),
+            payload: Bytes::from_static(b"opaque"),
+        })
+        .unwrap();
+        match d.pull() {
+            Some(CodecItem::RawRtp(p)) => {
+                assert_eq!(p.stream_id, 1);
+                assert_eq!(p.timestamp.timestamp, 42);
+                assert_eq!(p.sequence_number, 7);
+                assert_eq!(p.loss, 2);
+                assert!(p.mark);
+                assert_eq!(&p.payload[..], b"opaque");
+            }
+            o => panic!("unexpected pull result {:#?}", o),
+        }
+        assert!(d.pull().is_none());
+    }
+}
@@ -4,9 +4,10 @@
 //! Fixed-size audio sample codecs as defined in
 //! [RFC 3551 section 4.5](https://datatracker.ietf.org/doc/html/rfc3551#section-4.5).
 
-use std::num::NonZeroU32;
+use std::convert::TryFrom;
+use std::num::{NonZeroU16, NonZeroU32};
 
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 
 use super::CodecItem;
 
@@ -15,6 +16,10 @@ pub(crate) struct Depacketizer {
     clock_rate: u32,
     pending: Option<super::AudioFrame>,
     bits_per_sample: u32,
+    channels: Option<NonZeroU16>,
+
+    /// An `.mp4` sample entry, for the codecs which have one.
+    sample_entry: Option<Bytes>,
 }
 
 impl Depacketizer {
@@ -24,16 +29,38 @@ impl Depacketizer {
             clock_rate,
             bits_per_sample,
             pending: None,
+            channels: None,
+            sample_entry: None,
         }
     }
 
+    /// Creates a new Depacketizer for G.711 (`PCMA` or `PCMU`), which can be
+    /// written to `.mp4` files with the QuickTime `alaw` or `ulaw` sample
+    /// entries respectively.
+    pub(super) fn g711(
+        clock_rate: u32,
+        channels: Option<NonZeroU16>,
+        fourcc: &[u8; 4],
+    ) -> Result<Self, String> {
+        let mut d = Self::new(clock_rate, 8);
+        d.channels = channels;
+        d.sample_entry = Some(make_sample_entry(
+            fourcc,
+            channels.map(NonZeroU16::get).unwrap_or(1),
+            clock_rate,
+        )?);
+        Ok(d)
+    }
+
     pub(super) fn parameters(&self) -> Option<super::Parameters> {
         Some(super::Parameters::Audio(super::AudioParameters {
             rfc6381_codec: None,
             frame_length: None, // variable
             clock_rate: self.clock_rate,
             extra_data: Bytes::new(),
-            sample_entry: None,
+            sample_entry: self.sample_entry.clone(),
+            sample_rate: None,
+            channels: self.channels,
         }))
     }
 
@@ -63,6 +90,7 @@ impl Depacketizer {
             stream_id: pkt.stream_id,
             timestamp: pkt.timestamp,
             frame_length,
+            discontinuity: false,
             data: pkt.payload,
         });
         Ok(())
@@ -72,3 +100,45 @@ impl Depacketizer {
         self.pending.take().map(CodecItem::AudioFrame)
     }
 }
+
+/// Returns an `AudioSampleEntry` box with the given four-character code, as in
+/// ISO/IEC 14496-12 section 12.2.3.2, with no codec-specific configuration.
+fn make_sample_entry(fourcc: &[u8; 4], channels: u16, sample_rate: u32) -> Result<Bytes, String> {
+    let sample_rate = u16::try_from(sample_rate)
+        .map_err(|_| format!("sample rate {} exceeds u16::MAX", sample_rate))?;
+    let mut buf = BytesMut::new();
+    write_box!(&mut buf, fourcc, {
+        buf.put_u32(0); // SampleEntry.reserved
+        buf.put_u32(1); // SampleEntry.reserved, SampleEntry.data_reference_index (1)
+        buf.put_u64(0); // AudioSampleEntry.reserved
+        buf.put_u16(channels);
+        buf.put_u16(16); // AudioSampleEntry.samplesize
+        buf.put_u32(0); // AudioSampleEntry.pre_defined, AudioSampleEntry.reserved
+        buf.put_u32(u32::from(sample_rate) << 16);
+    });
+    Ok(buf.freeze())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn g711_sample_entry() {
+        let d = super::Depacketizer::g711(8_000, None, b"ulaw").unwrap();
+        let p = match d.parameters() {
+            Some(crate::codec::Parameters::Audio(p)) => p,
+            o => panic!("unexpected parameters {:?}", o),
+        };
+        let e = p.sample_entry().unwrap();
+        assert_eq!(e.len(), 36);
+        assert_eq!(&e[4..8], b"ulaw");
+        assert_eq!(&e[24..26], &[0, 1]); // channels
+        assert_eq!(&e[32..34], &8_000u16.to_be_bytes()[..]);
+
+        // 8-bit codecs other than G.711 have no sample entry.
+        let d = super::Depacketizer::new(8_000, 8);
+        match d.parameters() {
+            Some(crate::codec::Parameters::Audio(p)) => assert!(p.sample_entry().is_none()),
+            o => panic!("unexpected parameters {:?}", o),
+        }
+    }
+}
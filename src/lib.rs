@@ -14,11 +14,13 @@ use std::ops::Range;
 
 mod error;
 mod rtcp;
+#[cfg(feature = "serde")]
+mod serialize;
 
 #[cfg(test)]
 mod testutil;
 
-pub use error::Error;
+pub use error::{Error, ErrorKind};
 
 /// Wraps the supplied `ErrorInt` and returns it as an `Err`.
 macro_rules! bail {
@@ -60,6 +62,7 @@ struct ReceivedMessage {
 /// *   the full timestamp, with top bits accumulated as RTP packet timestamps wrap around.
 /// *   a conversion to RTSP "normal play time" (NPT): zero-based and normalized to seconds.
 #[derive(Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Timestamp {
     /// A timestamp which must be compared to `start`. The top bits are inferred
     /// from wraparounds of 32-bit RTP timestamps. The `i64` itself is not
@@ -116,6 +119,20 @@ impl Timestamp {
         (self.elapsed() as f64) / (self.clock_rate.get() as f64)
     }
 
+    /// Moves this timestamp onto `base`'s timeline, returning a timestamp with
+    /// `base`'s start and an elapsed time of `base.elapsed() + self.elapsed()`.
+    ///
+    /// This keeps NPT continuous when a stream is resumed in a new session.
+    /// Returns `None` if the clock rates differ or the result would overflow.
+    pub fn rebase(&self, base: &Timestamp) -> Option<Self> {
+        if self.clock_rate != base.clock_rate {
+            return None;
+        }
+        base.timestamp
+            .checked_add(self.elapsed())
+            .and_then(|timestamp| Timestamp::new(timestamp, self.clock_rate, base.start))
+    }
+
     /// Returns `self + delta` unless it would overflow.
     pub fn try_add(&self, delta: u32) -> Option<Self> {
         // Check for `timestamp` overflow only. We don't need to check for
@@ -154,6 +171,7 @@ pub const UNIX_EPOCH: NtpTimestamp = NtpTimestamp((2_208_988_800) << 32);
 /// This isn't necessarily gathered from a real NTP server. Reported NTP
 /// timestamps are allowed to jump backwards and/or be complete nonsense.
 #[derive(Copy, Clone, PartialEq, PartialOrd, Eq, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct NtpTimestamp(pub u64);
 
 impl std::fmt::Display for NtpTimestamp {
@@ -299,6 +317,15 @@ impl PacketContext {
     pub fn dummy() -> PacketContext {
         Self(PacketContextInner::Dummy)
     }
+
+    /// Returns the time the packet was received, if known.
+    pub(crate) fn received(&self) -> Option<std::time::Instant> {
+        match self.0 {
+            PacketContextInner::Tcp { msg_ctx, .. } => Some(msg_ctx.received),
+            PacketContextInner::Udp { received, .. } => Some(received),
+            PacketContextInner::Dummy => None,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -180,10 +180,58 @@ impl<'a> GenericPacket<'a> {
     }
 }
 
+/// Payload-specific feedback, as defined in
+/// [RFC 4585 section 6.1](https://datatracker.ietf.org/doc/html/rfc4585#section-6.1).
+const PT_PSFB: u8 = 206;
+
+/// Returns a Picture Loss Indication packet, as defined in
+/// [RFC 4585 section 6.3.1](https://datatracker.ietf.org/doc/html/rfc4585#section-6.3.1).
+pub(crate) fn pli(sender_ssrc: u32, media_ssrc: u32) -> [u8; 12] {
+    let mut pkt = [0; 12];
+    pkt[0] = (2 << 6) | 1; // version=2 + p=0 + fmt=1
+    pkt[1] = PT_PSFB;
+    pkt[2..4].copy_from_slice(&2u16.to_be_bytes()); // length in 4-byte words minus 1
+    pkt[4..8].copy_from_slice(&sender_ssrc.to_be_bytes());
+    pkt[8..12].copy_from_slice(&media_ssrc.to_be_bytes());
+    pkt
+}
+
+/// Returns a Full Intra Request packet, as defined in
+/// [RFC 5104 section 4.3.1](https://datatracker.ietf.org/doc/html/rfc5104#section-4.3.1).
+///
+/// `seq` should be incremented for each new request.
+pub(crate) fn fir(sender_ssrc: u32, media_ssrc: u32, seq: u8) -> [u8; 20] {
+    let mut pkt = [0; 20];
+    pkt[0] = (2 << 6) | 4; // version=2 + p=0 + fmt=4
+    pkt[1] = PT_PSFB;
+    pkt[2..4].copy_from_slice(&4u16.to_be_bytes()); // length in 4-byte words minus 1
+    pkt[4..8].copy_from_slice(&sender_ssrc.to_be_bytes());
+    // Media source SSRC is unused and must be 0; the FCI identifies the target.
+    pkt[12..16].copy_from_slice(&media_ssrc.to_be_bytes());
+    pkt[16] = seq;
+    pkt
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn feedback() {
+        let (pkt, rest) = GenericPacket::parse(&pli(1, 0xdcc4a0d8)[..]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!((pkt.payload_type(), pkt.count()), (206, 1));
+
+        let fir = fir(1, 0xdcc4a0d8, 7);
+        let (pkt, rest) = GenericPacket::parse(&fir[..]).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!((pkt.payload_type(), pkt.count()), (206, 4));
+        assert_eq!(
+            &fir[8..],
+            b"\x00\x00\x00\x00\xdc\xc4\xa0\xd8\x07\x00\x00\x00"
+        );
+    }
+
     #[test]
     fn dahua() {
         // Sender report and source description from a Dahua camera.
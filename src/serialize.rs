@@ -0,0 +1,35 @@
+// Copyright (C) 2021 Scott Lamb <slamb@slamb.org>
+// SPDX-License-Identifier: MIT OR Apache-2.0
+
+//! Helpers for `serde::Serialize` implementations, with the `serde` feature.
+//!
+//! Byte blobs are encoded as base64 strings, and URLs as strings.
+
+use bytes::Bytes;
+use serde::ser::{SerializeSeq, Serializer};
+
+pub(crate) fn base64<S: Serializer>(b: &Bytes, s: S) -> Result<S::Ok, S::Error> {
+    s.serialize_str(&::base64::encode(b))
+}
+
+pub(crate) fn option_base64<S: Serializer>(b: &Option<Bytes>, s: S) -> Result<S::Ok, S::Error> {
+    match b {
+        Some(b) => s.serialize_some(&::base64::encode(b)),
+        None => s.serialize_none(),
+    }
+}
+
+pub(crate) fn vec_base64<S: Serializer>(v: &[Bytes], s: S) -> Result<S::Ok, S::Error> {
+    let mut seq = s.serialize_seq(Some(v.len()))?;
+    for b in v {
+        seq.serialize_element(&::base64::encode(b))?;
+    }
+    seq.end()
+}
+
+pub(crate) fn option_url<S: Serializer>(u: &Option<url::Url>, s: S) -> Result<S::Ok, S::Error> {
+    match u {
+        Some(u) => s.serialize_some(u.as_str()),
+        None => s.serialize_none(),
+    }
+}
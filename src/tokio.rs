@@ -6,11 +6,17 @@
 //! In theory there could be a similar async-std-based implementation.
 
 use bytes::{Buf, BufMut, Bytes, BytesMut};
-use futures::{Sink, SinkExt, Stream, StreamExt};
+use futures::{ready, Sink, SinkExt, Stream, StreamExt};
 use pretty_hex::PrettyHex;
+use rand::Rng;
 use rtsp_types::{Data, Message};
+use std::collections::VecDeque;
 use std::convert::TryFrom;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::pin::Pin;
+use std::task::{Context, Poll};
 use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::net::{TcpStream, UdpSocket};
 use tokio_util::codec::Framed;
 use url::Host;
@@ -20,24 +26,306 @@ use crate::{Error, ErrorInt, RtspMessageContext};
 use super::{ConnectionContext, ReceivedMessage, WallTime};
 
 /// A RTSP connection which implements `Stream`, `Sink`, and `Unpin`.
-pub(crate) struct Connection(Framed<TcpStream, Codec>);
+pub(crate) struct Connection(Framed<ConnectionStream, Codec>);
+
+/// The delay before starting a connection attempt to the next address, as in
+/// [RFC 8305 section 5](https://datatracker.ietf.org/doc/html/rfc8305#section-5).
+const CONNECTION_ATTEMPT_DELAY: std::time::Duration = std::time::Duration::from_millis(250);
+
+async fn connect_tcp(
+    host: &Host<&str>,
+    port: u16,
+    bind: Option<IpAddr>,
+    resolver: Option<&crate::client::Resolver>,
+) -> Result<TcpStream, std::io::Error> {
+    let mut addrs: Vec<SocketAddr> = match (host, resolver) {
+        (&Host::Domain(h), Some(resolver)) => {
+            let addrs = resolver(h, port);
+            if addrs.is_empty() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("resolver returned no addresses for {}", h),
+                ));
+            }
+            addrs
+        }
+        (&Host::Domain(h), None) => tokio::net::lookup_host((h, port)).await?.collect(),
+        (&Host::Ipv4(h), _) => vec![SocketAddr::new(h.into(), port)],
+        (&Host::Ipv6(h), _) => vec![SocketAddr::new(h.into(), port)],
+    };
+    if let Some(bind) = bind {
+        // Only addresses of the bound address's family are reachable.
+        addrs.retain(|a| a.is_ipv6() == bind.is_ipv6());
+    }
+    connect_happy_eyeballs(interleave_families(addrs), bind, CONNECTION_ATTEMPT_DELAY).await
+}
+
+/// Connects to `addr`, first binding to an ephemeral port on `bind` if specified.
+async fn connect_one(addr: SocketAddr, bind: Option<IpAddr>) -> Result<TcpStream, std::io::Error> {
+    let bind = match bind {
+        None => return TcpStream::connect(addr).await,
+        Some(b) => b,
+    };
+    let socket = match bind {
+        IpAddr::V4(_) => tokio::net::TcpSocket::new_v4()?,
+        IpAddr::V6(_) => tokio::net::TcpSocket::new_v6()?,
+    };
+    socket.bind(SocketAddr::new(bind, 0))?;
+    socket.connect(addr).await
+}
+
+/// Reorders `addrs` to alternate between address families, starting with the
+/// family of the first, as in
+/// [RFC 8305 section 4](https://datatracker.ietf.org/doc/html/rfc8305#section-4).
+fn interleave_families(addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let first_is_ipv6 = addrs.first().map(SocketAddr::is_ipv6).unwrap_or(false);
+    let (mut first, mut second): (VecDeque<_>, VecDeque<_>) = addrs
+        .into_iter()
+        .partition(|a| a.is_ipv6() == first_is_ipv6);
+    let mut out = Vec::with_capacity(first.len() + second.len());
+    while !first.is_empty() || !second.is_empty() {
+        out.extend(first.pop_front());
+        out.extend(second.pop_front());
+    }
+    out
+}
+
+/// Connects to the first of `addrs` to accept, starting a new attempt each
+/// `delay` (or as soon as the previous attempt fails) while earlier ones are
+/// still in progress. The losing attempts are cancelled.
+async fn connect_happy_eyeballs(
+    addrs: Vec<SocketAddr>,
+    bind: Option<IpAddr>,
+    delay: std::time::Duration,
+) -> Result<TcpStream, std::io::Error> {
+    let mut addrs = addrs.into_iter();
+    let mut attempts = futures::stream::FuturesUnordered::new();
+    let mut last_err = None;
+    loop {
+        if attempts.is_empty() {
+            match addrs.next() {
+                Some(a) => attempts.push(connect_one(a, bind)),
+                None => {
+                    return Err(last_err.unwrap_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::NotFound, "host has no addresses")
+                    }))
+                }
+            }
+        }
+        tokio::select! {
+            r = attempts.next() => match r.expect("attempts is non-empty") {
+                Ok(stream) => return Ok(stream),
+                Err(e) => last_err = Some(e),
+            },
+            _ = tokio::time::sleep(delay), if !addrs.as_slice().is_empty() => {
+                attempts.push(connect_one(addrs.next().expect("addrs is non-empty"), bind));
+            }
+        }
+    }
+}
 
 impl Connection {
-    pub(crate) async fn connect(host: Host<&str>, port: u16) -> Result<Self, std::io::Error> {
-        let stream = match host {
-            Host::Domain(h) => TcpStream::connect((h, port)).await,
-            Host::Ipv4(h) => TcpStream::connect((h, port)).await,
-            Host::Ipv6(h) => TcpStream::connect((h, port)).await,
-        }?;
+    /// Connects via TCP, binding to `bind` (with an ephemeral port) if specified.
+    pub(crate) async fn connect(
+        host: Host<&str>,
+        port: u16,
+        bind: Option<IpAddr>,
+        resolver: Option<&crate::client::Resolver>,
+    ) -> Result<Self, std::io::Error> {
+        let stream = connect_tcp(&host, port, bind, resolver).await?;
         Self::from_stream(stream)
     }
 
+    /// Connects via TLS, for `rtsps` URLs.
+    ///
+    /// Server certificates are verified against `server_name` if supplied, or
+    /// else the host. Only DNS names can be verified, so an IP address host
+    /// requires one of `server_name` or `accept_invalid_certs`. See
+    /// [`tls_client_config`] for `custom` and `accept_invalid_certs`.
+    #[cfg(feature = "tls")]
+    pub(crate) async fn connect_tls(
+        host: Host<&str>,
+        port: u16,
+        bind: Option<IpAddr>,
+        resolver: Option<&crate::client::Resolver>,
+        custom: Option<&std::sync::Arc<rustls::ClientConfig>>,
+        accept_invalid_certs: bool,
+        server_name: Option<&str>,
+    ) -> Result<Self, std::io::Error> {
+        let (name, sni) = match (server_name, &host) {
+            (Some(n), _) | (None, &Host::Domain(n)) => (n, true),
+
+            // The certificate isn't verified, so the name is a placeholder.
+            // Server name indication can't carry an IP address; omit it.
+            (None, _) if accept_invalid_certs => ("invalid", false),
+            (None, _) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "TLS to an IP address requires a server name to verify or \
+                     disabling certificate verification",
+                ))
+            }
+        };
+        let name = webpki::DNSNameRef::try_from_ascii_str(name).map_err(|_| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{:?} isn't a valid TLS server name", name),
+            )
+        })?;
+        let config = tls_client_config(custom, accept_invalid_certs, sni);
+        let stream = connect_tcp(&host, port, bind, resolver).await?;
+        let local_addr = stream.local_addr()?;
+        let peer_addr = stream.peer_addr()?;
+        let stream = tokio_rustls::TlsConnector::from(config)
+            .connect(name, stream)
+            .await?;
+        Ok(Self::new(
+            ConnectionStream::Tls(Box::new(stream)),
+            local_addr,
+            peer_addr,
+        ))
+    }
+
+    /// Connects via an RTSP-over-HTTP tunnel, using `path` (including any
+    /// query string) as the HTTP request target.
+    pub(crate) async fn connect_http_tunnel(
+        host: Host<&str>,
+        port: u16,
+        path: &str,
+        bind: Option<IpAddr>,
+        resolver: Option<&crate::client::Resolver>,
+    ) -> Result<Self, std::io::Error> {
+        let session_cookie: String = rand::thread_rng()
+            .sample_iter(&rand::distributions::Alphanumeric)
+            .take(22)
+            .map(char::from)
+            .collect();
+
+        // Proxies and virtual-hosted servers route by the Host header.
+        let host_header = match port {
+            80 => host.to_string(),
+            _ => format!("{}:{}", host, port),
+        };
+
+        let get = connect_tcp(&host, port, bind, resolver).await?;
+        write_all(
+            &get,
+            format!(
+                "GET {} HTTP/1.0\r\n\
+                 Host: {}\r\n\
+                 x-sessioncookie: {}\r\n\
+                 Accept: application/x-rtsp-tunnelled\r\n\
+                 Pragma: no-cache\r\n\
+                 Cache-Control: no-cache\r\n\
+                 \r\n",
+                path, host_header, session_cookie
+            )
+            .as_bytes(),
+        )
+        .await?;
+        let (status_line, get_leftover) = read_http_response_head(&get).await?;
+        if !matches!(status_line.split(' ').nth(1), Some("200")) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Other,
+                format!("HTTP tunnel GET failed: {:?}", status_line),
+            ));
+        }
+
+        // Connect to the same address as the GET rather than resolving again,
+        // so both halves reach the same server.
+        let local_addr = get.local_addr()?;
+        let peer_addr = get.peer_addr()?;
+        let post_head = Bytes::from(format!(
+            "POST {} HTTP/1.0\r\n\
+             Host: {}\r\n\
+             x-sessioncookie: {}\r\n\
+             Content-Type: application/x-rtsp-tunnelled\r\n\
+             Pragma: no-cache\r\n\
+             Cache-Control: no-cache\r\n\
+             Content-Length: {}\r\n\
+             Expires: Sun, 9 Jan 1972 00:00:00 GMT\r\n\
+             \r\n",
+            path, host_header, session_cookie, POST_CONTENT_LENGTH
+        ));
+        let post = open_post(peer_addr, bind, post_head.clone()).await?;
+
+        Ok(Self::new(
+            ConnectionStream::HttpTunnel(HttpTunnel {
+                get,
+                get_leftover,
+                post,
+                post_head,
+                peer_addr,
+                bind,
+                post_budget: POST_BODY_BUDGET,
+                renewing_post: None,
+                post_unencoded: Vec::new(),
+                post_pending: Bytes::new(),
+            }),
+            local_addr,
+            peer_addr,
+        ))
+    }
+
     pub(crate) fn from_stream(stream: TcpStream) -> Result<Self, std::io::Error> {
-        let established_wall = WallTime::now();
-        let established = Instant::now();
         let local_addr = stream.local_addr()?;
         let peer_addr = stream.peer_addr()?;
-        Ok(Self(Framed::new(
+        Ok(Self::new(
+            ConnectionStream::Tcp(stream),
+            local_addr,
+            peer_addr,
+        ))
+    }
+
+    /// Sets TCP keepalive and `TCP_NODELAY` on the underlying TCP stream(s).
+    pub(crate) fn set_tcp_options(
+        &self,
+        keepalive: Option<std::time::Duration>,
+        nodelay: bool,
+    ) -> Result<(), std::io::Error> {
+        let set = |s: &TcpStream| {
+            s.set_nodelay(nodelay)?;
+            let s = socket2::SockRef::from(s);
+            match keepalive {
+                Some(time) => s.set_tcp_keepalive(&socket2::TcpKeepalive::new().with_time(time)),
+                None => s.set_keepalive(false),
+            }
+        };
+        match self.0.get_ref() {
+            ConnectionStream::Tcp(s) => set(s),
+            #[cfg(feature = "tls")]
+            ConnectionStream::Tls(s) => set(s.get_ref().0),
+            ConnectionStream::HttpTunnel(t) => {
+                set(&t.get)?;
+                set(&t.post)
+            }
+        }
+    }
+
+    /// Sets the DSCP on the underlying TCP stream(s); see [`set_dscp`].
+    pub(crate) fn set_dscp(&self, dscp: u8) -> Result<(), std::io::Error> {
+        let is_ipv6 = self.0.codec().ctx.local_addr.is_ipv6();
+        match self.0.get_ref() {
+            ConnectionStream::Tcp(s) => set_dscp(socket2::SockRef::from(s), is_ipv6, dscp),
+            #[cfg(feature = "tls")]
+            ConnectionStream::Tls(s) => {
+                set_dscp(socket2::SockRef::from(s.get_ref().0), is_ipv6, dscp)
+            }
+            ConnectionStream::HttpTunnel(t) => {
+                set_dscp(socket2::SockRef::from(&t.get), is_ipv6, dscp)?;
+                set_dscp(socket2::SockRef::from(&t.post), is_ipv6, dscp)
+            }
+        }
+    }
+
+    fn new(
+        stream: ConnectionStream,
+        local_addr: std::net::SocketAddr,
+        peer_addr: std::net::SocketAddr,
+    ) -> Self {
+        let established_wall = WallTime::now();
+        let established = Instant::now();
+        Self(Framed::new(
             stream,
             Codec {
                 ctx: ConnectionContext {
@@ -47,8 +335,25 @@ impl Connection {
                     established,
                 },
                 read_pos: 0,
+                lenient: false,
+                head_scan: HeadScan::default(),
+                inserted_crs: 0,
+                truncated: 0,
+                write_pos: 0,
+                transcript: None,
             },
-        )))
+        ))
+    }
+
+    /// Sets the callback for [`crate::client::SessionOptions::transcript`].
+    pub(crate) fn set_transcript(&mut self, transcript: Option<crate::client::Transcript>) {
+        self.0.codec_mut().transcript = transcript;
+    }
+
+    /// Tolerates bare LF line endings in RTSP message heads; see
+    /// [`crate::client::SessionOptions::lenient`].
+    pub(crate) fn set_lenient(&mut self, lenient: bool) {
+        self.0.codec_mut().lenient = lenient;
     }
 
     pub(crate) fn ctx(&self) -> &ConnectionContext {
@@ -82,14 +387,22 @@ impl Stream for Connection {
         mut self: std::pin::Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<Option<Self::Item>> {
-        self.0.poll_next_unpin(cx).map_err(|e| {
+        let r = self.0.poll_next_unpin(cx);
+        if let Poll::Ready(Some(Ok(m))) = &r {
+            self.0.codec().transcribe(&m.ctx, &m.msg);
+        }
+        r.map_err(|e| {
             wrap!(match e {
                 CodecError::IoError(error) => ErrorInt::RtspReadError {
                     conn_ctx: *self.ctx(),
                     msg_ctx: self.eof_ctx(),
                     source: error,
                 },
-                CodecError::ParseError { description, pos } => ErrorInt::RtspFramingError {
+                CodecError::ParseError {
+                    description,
+                    pos,
+                    raw,
+                } => ErrorInt::RtspParseError {
                     conn_ctx: *self.ctx(),
                     msg_ctx: RtspMessageContext {
                         pos,
@@ -97,6 +410,7 @@ impl Stream for Connection {
                         received: Instant::now(),
                     },
                     description,
+                    raw,
                 },
             })
         })
@@ -119,6 +433,15 @@ impl Sink<Message<Bytes>> for Connection {
         mut self: std::pin::Pin<&mut Self>,
         item: Message<Bytes>,
     ) -> Result<(), Self::Error> {
+        let codec = self.0.codec();
+        if codec.transcript.is_some() {
+            let ctx = RtspMessageContext {
+                pos: codec.write_pos,
+                received_wall: WallTime::now(),
+                received: Instant::now(),
+            };
+            codec.transcribe(&ctx, &item);
+        }
         self.0
             .start_send_unpin(item)
             .map_err(|e| self.wrap_write_err(e))
@@ -143,12 +466,330 @@ impl Sink<Message<Bytes>> for Connection {
     }
 }
 
+/// The byte stream underlying a [`Connection`].
+enum ConnectionStream {
+    Tcp(TcpStream),
+    #[cfg(feature = "tls")]
+    Tls(Box<tokio_rustls::client::TlsStream<TcpStream>>),
+    HttpTunnel(HttpTunnel),
+}
+
+impl AsyncRead for ConnectionStream {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(s) => Pin::new(s).poll_read(cx, buf),
+            #[cfg(feature = "tls")]
+            ConnectionStream::Tls(s) => Pin::new(s).poll_read(cx, buf),
+            ConnectionStream::HttpTunnel(t) => t.poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for ConnectionStream {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(s) => Pin::new(s).poll_write(cx, buf),
+            #[cfg(feature = "tls")]
+            ConnectionStream::Tls(s) => Pin::new(s).poll_write(cx, buf),
+            ConnectionStream::HttpTunnel(t) => t.poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(s) => Pin::new(s).poll_flush(cx),
+            #[cfg(feature = "tls")]
+            ConnectionStream::Tls(s) => Pin::new(s).poll_flush(cx),
+            ConnectionStream::HttpTunnel(t) => t.poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            ConnectionStream::Tcp(s) => Pin::new(s).poll_shutdown(cx),
+            #[cfg(feature = "tls")]
+            ConnectionStream::Tls(s) => Pin::new(s).poll_shutdown(cx),
+            ConnectionStream::HttpTunnel(t) => t.poll_shutdown(cx),
+        }
+    }
+}
+
+/// Returns the TLS client configuration to use: `custom` if specified, or
+/// else one trusting the Mozilla root certificates. If `accept_invalid_certs`,
+/// the server's certificate isn't verified at all. If not `sni`, no server
+/// name indication is sent.
+#[cfg(feature = "tls")]
+fn tls_client_config(
+    custom: Option<&std::sync::Arc<rustls::ClientConfig>>,
+    accept_invalid_certs: bool,
+    sni: bool,
+) -> std::sync::Arc<rustls::ClientConfig> {
+    if !accept_invalid_certs && sni {
+        if let Some(c) = custom {
+            return c.clone();
+        }
+    }
+    let mut config = match custom {
+        Some(c) => (**c).clone(),
+        None => {
+            let mut c = rustls::ClientConfig::new();
+            c.root_store
+                .add_server_trust_anchors(&webpki_roots::TLS_SERVER_ROOTS);
+            c
+        }
+    };
+    if accept_invalid_certs {
+        config
+            .dangerous()
+            .set_certificate_verifier(std::sync::Arc::new(NoCertificateVerification));
+    }
+    if !sni {
+        config.enable_sni = false;
+    }
+    std::sync::Arc::new(config)
+}
+
+/// A verifier which accepts any server certificate; see
+/// [`crate::client::SessionOptions::danger_accept_invalid_certs`].
+#[cfg(feature = "tls")]
+struct NoCertificateVerification;
+
+#[cfg(feature = "tls")]
+impl rustls::ServerCertVerifier for NoCertificateVerification {
+    fn verify_server_cert(
+        &self,
+        _roots: &rustls::RootCertStore,
+        _presented_certs: &[rustls::Certificate],
+        _dns_name: webpki::DNSNameRef<'_>,
+        _ocsp_response: &[u8],
+    ) -> Result<rustls::ServerCertVerified, rustls::TLSError> {
+        Ok(rustls::ServerCertVerified::assertion())
+    }
+}
+
+/// The `Content-Length` of each tunnel `POST`, which is effectively unbounded
+/// but must be specified for HTTP/1.0 proxies.
+const POST_CONTENT_LENGTH: usize = 32767;
+
+/// The number of base64 characters to send on each `POST` before replacing
+/// it with another, the largest whole number of 4-character groups within
+/// [`POST_CONTENT_LENGTH`].
+const POST_BODY_BUDGET: usize = POST_CONTENT_LENGTH / 4 * 4;
+
+/// Opens a connection to `addr` and sends the tunnel `POST` head on it.
+async fn open_post(
+    addr: SocketAddr,
+    bind: Option<IpAddr>,
+    head: Bytes,
+) -> Result<TcpStream, std::io::Error> {
+    let post = connect_one(addr, bind).await?;
+    write_all(&post, &head[..]).await?;
+    Ok(post)
+}
+
+/// An established RTSP-over-HTTP tunnel.
+struct HttpTunnel {
+    /// The connection on which the `GET` was sent. Its response body carries
+    /// server->client data verbatim.
+    get: TcpStream,
+
+    /// Data read along with the `GET` response head, to be returned before
+    /// reading more from `get`.
+    get_leftover: Bytes,
+
+    /// The connection on which the `POST` was sent. Its body carries
+    /// client->server data, base64-encoded.
+    post: TcpStream,
+
+    /// The `POST` request head, to send again on renewal.
+    post_head: Bytes,
+    peer_addr: SocketAddr,
+    bind: Option<IpAddr>,
+
+    /// The number of base64 characters `post` may still carry within its
+    /// `Content-Length`.
+    post_budget: usize,
+
+    /// A replacement for `post`, while it's being opened.
+    renewing_post:
+        Option<Pin<Box<dyn std::future::Future<Output = std::io::Result<TcpStream>> + Send>>>,
+
+    /// Data accepted by `poll_write` but not yet encoded: fewer than 3 bytes,
+    /// held so that base64 padding appears only where the data is flushed.
+    post_unencoded: Vec<u8>,
+
+    /// Encoded data which has been accepted by `poll_write` but not yet
+    /// written to `post`.
+    post_pending: Bytes,
+}
+
+impl HttpTunnel {
+    fn poll_read(
+        &mut self,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if !self.get_leftover.is_empty() {
+            let n = std::cmp::min(self.get_leftover.len(), buf.remaining());
+            buf.put_slice(&self.get_leftover[..n]);
+            self.get_leftover.advance(n);
+            return Poll::Ready(Ok(()));
+        }
+        Pin::new(&mut self.get).poll_read(cx, buf)
+    }
+
+    /// Writes `post_pending`, replacing `post` with a fresh `POST` whenever
+    /// its `Content-Length` is used up.
+    fn poll_write_pending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        while !self.post_pending.is_empty() {
+            if let Some(renewing) = self.renewing_post.as_mut() {
+                let post = ready!(std::future::Future::poll(renewing.as_mut(), cx));
+                self.renewing_post = None;
+                self.post = post?;
+                self.post_budget = POST_BODY_BUDGET;
+            }
+            if self.post_budget == 0 {
+                self.renewing_post = Some(Box::pin(open_post(
+                    self.peer_addr,
+                    self.bind,
+                    self.post_head.clone(),
+                )));
+                continue;
+            }
+            let len = std::cmp::min(self.post_budget, self.post_pending.len());
+            let n = ready!(Pin::new(&mut self.post).poll_write(cx, &self.post_pending[..len]))?;
+            if n == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+            self.post_pending.advance(n);
+            self.post_budget -= n;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        ready!(self.poll_write_pending(cx))?;
+
+        // Encode only whole 3-byte groups, so a message split across writes
+        // doesn't have `=` padding in its middle.
+        let mut data = std::mem::take(&mut self.post_unencoded);
+        data.extend_from_slice(buf);
+        let whole = data.len() / 3 * 3;
+        self.post_pending = Bytes::from(base64::encode(&data[..whole]));
+        data.drain(..whole);
+        self.post_unencoded = data;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    /// Encodes the final partial group, if any, and writes everything.
+    ///
+    /// The server can't act on a message until it has decoded all of it, so
+    /// this pads at a flush rather than holding back the message's last
+    /// bytes. Flushes happen at message boundaries, where servers such as
+    /// live555 expect any padding.
+    fn poll_write_all(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_write_pending(cx))?;
+        if !self.post_unencoded.is_empty() {
+            let data = std::mem::take(&mut self.post_unencoded);
+            self.post_pending = Bytes::from(base64::encode(&data));
+            ready!(self.poll_write_pending(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_write_all(cx))?;
+        Pin::new(&mut self.post).poll_flush(cx)
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        ready!(self.poll_write_all(cx))?;
+        Pin::new(&mut self.post).poll_shutdown(cx)
+    }
+}
+
+async fn write_all(stream: &TcpStream, mut buf: &[u8]) -> Result<(), std::io::Error> {
+    while !buf.is_empty() {
+        stream.writable().await?;
+        match stream.try_write(buf) {
+            Ok(n) => buf = &buf[n..],
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the head of an HTTP response, returning the status line and any
+/// bytes read beyond the head.
+async fn read_http_response_head(stream: &TcpStream) -> Result<(String, Bytes), std::io::Error> {
+    const MAX_HEAD_LEN: usize = 4096;
+    let mut buf = BytesMut::new();
+    let mut chunk = [0u8; 1024];
+    loop {
+        if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let head = buf.split_to(i + 4);
+            let status_line = String::from_utf8_lossy(&head)
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_owned();
+            return Ok((status_line, buf.freeze()));
+        }
+        if buf.len() > MAX_HEAD_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("HTTP tunnel response head exceeds {} bytes", MAX_HEAD_LEN),
+            ));
+        }
+        stream.readable().await?;
+        match stream.try_read(&mut chunk) {
+            Ok(0) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "EOF while reading HTTP tunnel response",
+                ))
+            }
+            Ok(n) => buf.extend_from_slice(&chunk[..n]),
+            Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 /// Encodes and decodes RTSP messages.
 struct Codec {
     ctx: ConnectionContext,
 
     /// Number of bytes read and processed (drained from the input buffer).
     read_pos: u64,
+
+    /// If true, rewrite bare LF line endings as CRLF and truncate oversized
+    /// status lines before parsing.
+    lenient: bool,
+
+    /// Progress of lenient parsing through the buffered message's head.
+    head_scan: HeadScan,
+
+    /// Number of CRs inserted into the buffered message by lenient parsing,
+    /// so that `read_pos` continues to match the bytes actually received.
+    inserted_crs: usize,
+
+    /// Number of bytes removed from the buffered message by lenient parsing.
+    truncated: usize,
+
+    /// Number of bytes encoded for sending.
+    write_pos: u64,
+
+    transcript: Option<crate::client::Transcript>,
 }
 
 /// An intermediate error type that exists because [`Framed`] expects the
@@ -156,7 +797,11 @@ struct Codec {
 /// takes additional context.
 enum CodecError {
     IoError(std::io::Error),
-    ParseError { description: String, pos: u64 },
+    ParseError {
+        description: String,
+        pos: u64,
+        raw: crate::error::RawMessage,
+    },
 }
 
 impl std::convert::From<std::io::Error> for CodecError {
@@ -165,8 +810,156 @@ impl std::convert::From<std::io::Error> for CodecError {
     }
 }
 
+/// The longest status line, including its line ending, kept by
+/// [`Codec::lenient`] parsing, which truncates longer ones to this length.
+const MAX_STATUS_LINE_LEN: usize = 1024;
+
+/// Progress of [`normalize_head`] through a partially received message head,
+/// so that each byte is scanned once rather than on every call.
+#[derive(Default)]
+struct HeadScan {
+    /// Number of bytes scanned without finding the end of the head.
+    pos: usize,
+
+    /// True if a bare LF was found within the scanned bytes.
+    bare_lf: bool,
+}
+
+/// Rewrites bare LF line endings within the head of the RTSP message at the
+/// start of `src` as CRLF.
+///
+/// Returns the number of bytes inserted, or `None` if the head is incomplete
+/// and contains a bare LF, so parsing should wait for more data. `scan` must
+/// be reset when `src`'s message is consumed.
+fn normalize_head(src: &mut BytesMut, scan: &mut HeadScan) -> Option<usize> {
+    let is_bare_lf = |buf: &[u8], i: usize| buf[i] == b'\n' && (i == 0 || buf[i - 1] != b'\r');
+    let mut end = None;
+
+    // Back up to recheck line endings whose lookahead was incomplete.
+    for i in scan.pos.saturating_sub(2)..src.len() {
+        if src[i] != b'\n' {
+            continue;
+        }
+        scan.bare_lf |= is_bare_lf(&src[..], i);
+        match src.get(i + 1) {
+            Some(b'\n') => end = Some(i + 2),
+            Some(b'\r') if src.get(i + 2) == Some(&b'\n') => end = Some(i + 3),
+            _ => continue,
+        }
+        break;
+    }
+    let end = match end {
+        None => {
+            scan.pos = src.len();
+            return if scan.bare_lf { None } else { Some(0) };
+        }
+        Some(e) => e,
+    };
+    let bare_lf = std::mem::take(scan).bare_lf || is_bare_lf(&src[..], end - 1);
+    if !bare_lf {
+        return Some(0);
+    }
+    let bare = (0..end).filter(|&i| is_bare_lf(&src[..], i)).count();
+    let mut normalized = BytesMut::with_capacity(src.len() + bare);
+    for i in 0..end {
+        if is_bare_lf(&src[..], i) {
+            normalized.put_u8(b'\r');
+        }
+        normalized.put_u8(src[i]);
+    }
+    normalized.extend_from_slice(&src[end..]);
+    *src = normalized;
+    log::debug!(
+        "Lenient parsing: rewrote {} bare LF line endings as CRLF",
+        bare
+    );
+    Some(bare)
+}
+
+/// Truncates the oversized status line at the start of `src` to at most
+/// [`MAX_STATUS_LINE_LEN`] bytes, keeping its line ending.
+///
+/// If the line is incomplete, discards all but its first bytes, so that a
+/// peer can't make us buffer without limit while waiting for its end.
+/// Returns the number of bytes removed.
+fn truncate_status_line(src: &mut BytesMut) -> usize {
+    let eol = match src.iter().position(|&b| b == b'\n') {
+        Some(lf) if src[lf - 1] == b'\r' => lf - 1,
+        Some(lf) => lf,
+        None => src.len(),
+    };
+
+    // Leave room for CRLF, and don't split a UTF-8 sequence within the
+    // reason phrase.
+    let mut cut = MAX_STATUS_LINE_LEN - 2;
+    while cut > 0 && src[cut] & 0b1100_0000 == 0b1000_0000 {
+        cut -= 1;
+    }
+    let mut truncated = BytesMut::with_capacity(src.len() - (eol - cut));
+    truncated.extend_from_slice(&src[..cut]);
+    truncated.extend_from_slice(&src[eol..]);
+    *src = truncated;
+    log::debug!("Lenient parsing: truncated {}-byte status line", eol);
+    eol - cut
+}
+
+/// Request headers carrying credentials, which [`Codec::transcribe`] redacts.
+const REDACTED_REQUEST_HEADERS: &[rtsp_types::HeaderName] = &[
+    rtsp_types::headers::AUTHORIZATION,
+    rtsp_types::headers::PROXY_AUTHORIZATION,
+];
+
+/// Response headers carrying authentication challenges (including nonces),
+/// which [`Codec::transcribe`] redacts.
+const REDACTED_RESPONSE_HEADERS: &[rtsp_types::HeaderName] = &[
+    rtsp_types::headers::WWW_AUTHENTICATE,
+    rtsp_types::headers::PROXY_AUTHENTICATE,
+];
+
 impl Codec {
-    fn parse_msg(&self, src: &mut BytesMut) -> Result<Option<(usize, Message<Bytes>)>, CodecError> {
+    /// Passes a sent or received request or response to the transcript
+    /// callback, if any, with credentials redacted.
+    fn transcribe(&self, ctx: &RtspMessageContext, msg: &Message<Bytes>) {
+        let transcript = match self.transcript.as_ref() {
+            Some(t) => t,
+            None => return,
+        };
+        match msg {
+            Message::Request(r)
+                if REDACTED_REQUEST_HEADERS
+                    .iter()
+                    .any(|h| r.header(h).is_some()) =>
+            {
+                let mut r = r.clone();
+                for h in REDACTED_REQUEST_HEADERS {
+                    if r.header(h).is_some() {
+                        r.insert_header(h.clone(), "<redacted>".to_owned());
+                    }
+                }
+                transcript(ctx, &Message::Request(r));
+            }
+            Message::Response(r)
+                if REDACTED_RESPONSE_HEADERS
+                    .iter()
+                    .any(|h| r.header(h).is_some()) =>
+            {
+                let mut r = r.clone();
+                for h in REDACTED_RESPONSE_HEADERS {
+                    if r.header(h).is_some() {
+                        r.insert_header(h.clone(), "<redacted>".to_owned());
+                    }
+                }
+                transcript(ctx, &Message::Response(r));
+            }
+            Message::Request(_) | Message::Response(_) => transcript(ctx, msg),
+            Message::Data(_) => {}
+        }
+    }
+
+    fn parse_msg(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<(usize, Message<Bytes>)>, CodecError> {
         if !src.is_empty() && src[0] == b'$' {
             // Fast path for interleaved data, avoiding MessageRef -> Message<&[u8]> ->
             // Message<Bytes> conversion. This speeds things up quite a bit in practice,
@@ -188,6 +981,22 @@ impl Codec {
             )));
         }
 
+        if self.lenient {
+            if src.starts_with(b"RTSP/")
+                && src.len() > MAX_STATUS_LINE_LEN
+                && !src[..MAX_STATUS_LINE_LEN].contains(&b'\n')
+            {
+                self.truncated += truncate_status_line(src);
+
+                // Positions within the head have shifted; scan it afresh.
+                self.head_scan = HeadScan::default();
+            }
+            match normalize_head(src, &mut self.head_scan) {
+                None => return Ok(None),
+                Some(n) => self.inserted_crs += n,
+            }
+        }
+
         let (msg, len): (Message<&[u8]>, _) = match Message::parse(src) {
             Ok((m, l)) => (m, l),
             Err(rtsp_types::ParseError::Error) => {
@@ -196,24 +1005,28 @@ impl Codec {
                     title: false,
                     ..Default::default()
                 };
+                let raw = crate::error::RawMessage::new(src);
                 if src.len() > MAX_DUMP_BYTES {
                     return Err(CodecError::ParseError {
                         description: format!(
                             "Invalid RTSP message; next {} of {} buffered bytes are:\n{:#?}",
                             MAX_DUMP_BYTES,
                             src.len(),
-                            (&src[0..MAX_DUMP_BYTES]).hex_conf(conf)
+                            (&raw.as_ref()[..std::cmp::min(MAX_DUMP_BYTES, raw.as_ref().len())])
+                                .hex_conf(conf)
                         ),
                         pos: self.read_pos,
+                        raw,
                     });
                 }
                 return Err(CodecError::ParseError {
                     description: format!(
                         "Invalid RTSP message; next {} buffered bytes are:\n{:#?}",
                         MAX_DUMP_BYTES,
-                        src.hex_conf(conf)
+                        raw.as_ref().hex_conf(conf)
                     ),
                     pos: self.read_pos,
+                    raw,
                 });
             }
             Err(rtsp_types::ParseError::Incomplete) => return Ok(None),
@@ -266,7 +1079,12 @@ impl tokio_util::codec::Decoder for Codec {
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
         let (len, msg) = match self.parse_msg(src) {
-            Err(e) => return Err(e),
+            Err(e) => {
+                self.head_scan = HeadScan::default();
+                self.inserted_crs = 0;
+                self.truncated = 0;
+                return Err(e);
+            }
             Ok(None) => return Ok(None),
             Ok(Some((len, msg))) => (len, msg),
         };
@@ -278,7 +1096,9 @@ impl tokio_util::codec::Decoder for Codec {
                 received: Instant::now(),
             },
         };
-        self.read_pos += u64::try_from(len).expect("usize fits in u64");
+        let received_len =
+            len + std::mem::take(&mut self.truncated) - std::mem::take(&mut self.inserted_crs);
+        self.read_pos += u64::try_from(received_len).expect("usize fits in u64");
         Ok(Some(msg))
     }
 }
@@ -291,10 +1111,53 @@ impl tokio_util::codec::Encoder<rtsp_types::Message<Bytes>> for Codec {
         item: rtsp_types::Message<Bytes>,
         mut dst: &mut BytesMut,
     ) -> Result<(), Self::Error> {
+        let before = dst.len();
         item.write(&mut (&mut dst).writer())
             .expect("BufMut Writer is infallible");
+        self.write_pos += u64::try_from(dst.len() - before).expect("usize fits in u64");
+        Ok(())
+    }
+}
+
+/// Sets the DSCP (Differentiated Services Code Point) of outbound packets.
+///
+/// `dscp` is the upper 6 bits of the IPv4 ToS / IPv6 traffic class byte; the
+/// remaining (ECN) bits are left as zero.
+pub(crate) fn set_dscp(
+    socket: socket2::SockRef<'_>,
+    is_ipv6: bool,
+    dscp: u8,
+) -> Result<(), std::io::Error> {
+    debug_assert!(dscp < 64);
+    let tos = u32::from(dscp) << 2;
+    if !is_ipv6 {
+        return socket.set_tos(tos);
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+        let tclass = tos as libc::c_int;
+
+        // SAFETY: passes a valid fd and a pointer to an int of the given size.
+        let r = unsafe {
+            libc::setsockopt(
+                socket.as_raw_fd(),
+                libc::IPPROTO_IPV6,
+                libc::IPV6_TCLASS,
+                &tclass as *const libc::c_int as *const libc::c_void,
+                std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+            )
+        };
+        if r != 0 {
+            return Err(std::io::Error::last_os_error());
+        }
         Ok(())
     }
+    #[cfg(not(unix))]
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Other,
+        "setting IPV6_TCLASS is unsupported on this platform",
+    ))
 }
 
 /// tokio-specific version of [`crate::UdpPair`].
@@ -315,4 +1178,538 @@ impl UdpPair {
             rtcp_socket: UdpSocket::from_std(inner.rtcp_socket)?,
         })
     }
+
+    /// Binds sockets to the given RTP and RTCP ports on all local addresses
+    /// and joins the multicast `group` on each.
+    ///
+    /// `interface` selects the IPv4 interface by address; the OS chooses if
+    /// it's absent or IPv6. `ttl`, if specified, is used for any packets sent
+    /// to the group.
+    pub(crate) fn for_multicast(
+        group: IpAddr,
+        ports: (u16, u16),
+        interface: Option<IpAddr>,
+        ttl: Option<u8>,
+    ) -> Result<Self, std::io::Error> {
+        let bind = |port| -> Result<UdpSocket, std::io::Error> {
+            let (domain, unspecified) = match group {
+                IpAddr::V4(_) => (socket2::Domain::IPV4, IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+                IpAddr::V6(_) => (socket2::Domain::IPV6, IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+            };
+            let s =
+                socket2::Socket::new(domain, socket2::Type::DGRAM, Some(socket2::Protocol::UDP))?;
+
+            // Other receivers on this host may be in the same group.
+            s.set_reuse_address(true)?;
+            s.bind(&SocketAddr::new(unspecified, port).into())?;
+            match group {
+                IpAddr::V4(g) => {
+                    let interface = match interface {
+                        Some(IpAddr::V4(i)) => i,
+                        _ => Ipv4Addr::UNSPECIFIED,
+                    };
+                    s.join_multicast_v4(&g, &interface)?;
+                    if let Some(ttl) = ttl {
+                        s.set_multicast_ttl_v4(ttl.into())?;
+                    }
+                }
+                IpAddr::V6(g) => {
+                    s.join_multicast_v6(&g, 0)?;
+                    if let Some(ttl) = ttl {
+                        s.set_multicast_hops_v6(ttl.into())?;
+                    }
+                }
+            }
+            s.set_nonblocking(true)?;
+            UdpSocket::from_std(s.into())
+        };
+        Ok(Self {
+            rtp_port: ports.0,
+            rtp_socket: bind(ports.0)?,
+            rtcp_socket: bind(ports.1)?,
+        })
+    }
+
+    /// Sets `SO_RCVBUF` on both sockets, returning the size the OS granted.
+    ///
+    /// The OS may adjust the size; eg Linux doubles it (to allow for
+    /// bookkeeping overhead) and clamps it to `net.core.rmem_max`.
+    pub(crate) fn set_recv_buffer_size(&self, bytes: usize) -> Result<usize, std::io::Error> {
+        for s in [&self.rtp_socket, &self.rtcp_socket] {
+            socket2::SockRef::from(s).set_recv_buffer_size(bytes)?;
+        }
+        socket2::SockRef::from(&self.rtp_socket).recv_buffer_size()
+    }
+
+    /// Sets the DSCP on both sockets; see [`set_dscp`].
+    pub(crate) fn set_dscp(&self, is_ipv6: bool, dscp: u8) -> Result<(), std::io::Error> {
+        for s in [&self.rtp_socket, &self.rtcp_socket] {
+            set_dscp(socket2::SockRef::from(s), is_ipv6, dscp)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Reads an HTTP request head, returning it and any bytes read beyond it.
+    async fn read_head(stream: &mut TcpStream) -> (String, Vec<u8>) {
+        let mut buf = Vec::new();
+        loop {
+            if let Some(i) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+                let rest = buf.split_off(i + 4);
+                return (String::from_utf8(buf).unwrap(), rest);
+            }
+            let mut chunk = [0u8; 1024];
+            let n = stream.read(&mut chunk).await.unwrap();
+            assert!(n > 0, "unexpected EOF");
+            buf.extend_from_slice(&chunk[..n]);
+        }
+    }
+
+    #[test]
+    fn interleave_families() {
+        let addrs: Vec<SocketAddr> = ["[::1]:554", "[::2]:554", "[::3]:554", "127.0.0.1:554"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+        let interleaved = super::interleave_families(addrs.clone());
+        assert_eq!(interleaved, [addrs[0], addrs[3], addrs[1], addrs[2]]);
+    }
+
+    #[tokio::test]
+    async fn happy_eyeballs() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let reachable = listener.local_addr().unwrap();
+
+        // TEST-NET-1 (RFC 5737): the attempt either fails quickly or hangs
+        // until it's cancelled.
+        let unreachable: SocketAddr = "192.0.2.1:554".parse().unwrap();
+        let start = Instant::now();
+        let (client, _server) = tokio::join!(
+            connect_happy_eyeballs(
+                vec![unreachable, reachable],
+                None,
+                std::time::Duration::from_millis(50)
+            ),
+            listener.accept()
+        );
+        assert_eq!(client.unwrap().peer_addr().unwrap(), reachable);
+        let elapsed = start.elapsed();
+        assert!(elapsed < std::time::Duration::from_secs(1), "{:?}", elapsed);
+    }
+
+    #[tokio::test]
+    async fn bind() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let localhost = IpAddr::V4(std::net::Ipv4Addr::LOCALHOST);
+        let (conn, server) = tokio::join!(
+            Connection::connect(
+                Host::Ipv4(std::net::Ipv4Addr::LOCALHOST),
+                port,
+                Some(localhost),
+                None,
+            ),
+            listener.accept()
+        );
+        let conn = conn.unwrap();
+        assert_eq!(conn.ctx().local_addr.ip(), localhost);
+        assert_eq!(conn.ctx().local_addr, server.unwrap().1);
+    }
+
+    /// Checks that an unparseable message's error points at its start.
+    #[tokio::test]
+    async fn parse_error_position() {
+        const GOOD: &[u8] = b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n";
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        let mut conn = Connection::from_stream(client.unwrap()).unwrap();
+        let mut server = server.unwrap().0;
+        server.write_all(GOOD).await.unwrap();
+        server
+            .write_all(b"RTSP/1.0 bogus OK\r\nCSeq: 2\r\n\r\n")
+            .await
+            .unwrap();
+        let msg = conn.next().await.unwrap().unwrap();
+        assert_eq!(msg.ctx.pos(), 0);
+        let e = conn.next().await.unwrap().unwrap_err();
+        assert_eq!(e.message_context().unwrap().pos(), GOOD.len() as u64);
+        assert_eq!(
+            e.raw_message().unwrap(),
+            b"RTSP/1.0 bogus OK\r\nCSeq: 2\r\n\r\n"
+        );
+    }
+
+    #[test]
+    fn normalize_head() {
+        let mut scan = super::HeadScan::default();
+        let mut buf = BytesMut::from(&b"RTSP/1.0 200 OK\nCSeq: 1\r\nContent-Length: 2\n\na\nb"[..]);
+        assert_eq!(super::normalize_head(&mut buf, &mut scan), Some(3));
+        assert_eq!(
+            &buf[..],
+            b"RTSP/1.0 200 OK\r\nCSeq: 1\r\nContent-Length: 2\r\n\r\na\nb"
+        );
+
+        // Already normalized.
+        assert_eq!(super::normalize_head(&mut buf, &mut scan), Some(0));
+
+        // Incomplete.
+        let mut buf = BytesMut::from(&b"RTSP/1.0 200 OK\nCSeq: 1\n"[..]);
+        assert_eq!(super::normalize_head(&mut buf, &mut scan), None);
+        assert_eq!(scan.pos, buf.len());
+
+        // ...then completed, resuming the scan. The end spans the two reads.
+        buf.extend_from_slice(b"\nbody");
+        assert_eq!(super::normalize_head(&mut buf, &mut scan), Some(3));
+        assert_eq!(&buf[..], b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\nbody");
+        assert_eq!(scan.pos, 0);
+
+        let mut buf = BytesMut::from(&b"RTSP/1.0 200 OK\r\n"[..]);
+        assert_eq!(super::normalize_head(&mut buf, &mut scan), Some(0));
+    }
+
+    /// Checks that oversized status lines are accepted as-is by default and
+    /// truncated in lenient mode, keeping positions in terms of bytes received.
+    #[tokio::test]
+    async fn oversized_status_line() {
+        let mut long = b"RTSP/1.0 200 ".to_vec();
+        long.resize(2 * super::MAX_STATUS_LINE_LEN, b'K');
+        long.extend_from_slice(b"\r\nCSeq: 1\r\n\r\n");
+        for &lenient in &[false, true] {
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+            let mut conn = Connection::from_stream(client.unwrap()).unwrap();
+            conn.set_lenient(lenient);
+            let mut server = server.unwrap().0;
+            server.write_all(&long).await.unwrap();
+            server
+                .write_all(b"RTSP/1.0 200 OK\r\nCSeq: 2\r\n\r\n")
+                .await
+                .unwrap();
+            match conn.next().await.unwrap().unwrap().msg {
+                Message::Response(r) => {
+                    assert_eq!(r.status(), rtsp_types::StatusCode::Ok);
+                    assert_eq!(r.header(&rtsp_types::headers::CSEQ).unwrap().as_str(), "1");
+                    let max_reason = super::MAX_STATUS_LINE_LEN - b"RTSP/1.0 200 \r\n".len();
+                    assert_eq!(
+                        r.reason_phrase().len() <= max_reason,
+                        lenient,
+                        "{}",
+                        r.reason_phrase().len()
+                    );
+                }
+                o => panic!("unexpected message {:#?}", o),
+            }
+            let msg = conn.next().await.unwrap().unwrap();
+            assert_eq!(msg.ctx.pos(), long.len() as u64);
+        }
+    }
+
+    /// Checks that lenient mode bounds the buffer while awaiting the end of an
+    /// oversized status line.
+    #[test]
+    fn lenient_incomplete_status_line() {
+        use tokio_util::codec::Decoder;
+        let mut codec = super::Codec {
+            ctx: ConnectionContext::dummy(),
+            read_pos: 0,
+            lenient: true,
+            head_scan: super::HeadScan::default(),
+            inserted_crs: 0,
+            truncated: 0,
+            write_pos: 0,
+            transcript: None,
+        };
+        let mut src = BytesMut::from(&b"RTSP/1.0 200 "[..]);
+        let mut received = src.len();
+        for _ in 0..20 {
+            src.extend_from_slice(&[b'K'; 512]);
+            received += 512;
+            assert!(codec.decode(&mut src).ok().unwrap().is_none());
+            assert!(src.len() <= super::MAX_STATUS_LINE_LEN, "{}", src.len());
+        }
+        const REST: &[u8] = b"\r\nCSeq: 1\r\n\r\n";
+        src.extend_from_slice(REST);
+        received += REST.len();
+        match codec.decode(&mut src).ok().unwrap().unwrap().msg {
+            Message::Response(r) => {
+                assert_eq!(r.header(&rtsp_types::headers::CSEQ).unwrap().as_str(), "1")
+            }
+            o => panic!("unexpected message {:#?}", o),
+        }
+        assert_eq!(codec.read_pos, received as u64);
+    }
+
+    /// Checks that lenient mode parses bare LF line endings while keeping
+    /// positions in terms of bytes received.
+    #[tokio::test]
+    async fn lenient_bare_lf() {
+        const BARE: &[u8] = b"RTSP/1.0 200 OK\nCSeq: 1\nContent-Length: 2\n\nhi";
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        let mut conn = Connection::from_stream(client.unwrap()).unwrap();
+        conn.set_lenient(true);
+        let mut server = server.unwrap().0;
+        server.write_all(BARE).await.unwrap();
+        server
+            .write_all(b"RTSP/1.0 200 OK\r\nCSeq: 2\r\n\r\n")
+            .await
+            .unwrap();
+        match conn.next().await.unwrap().unwrap().msg {
+            Message::Response(r) => {
+                assert_eq!(r.header(&rtsp_types::headers::CSEQ).unwrap().as_str(), "1");
+                assert_eq!(&r.body()[..], b"hi");
+            }
+            o => panic!("unexpected message {:#?}", o),
+        }
+        let msg = conn.next().await.unwrap().unwrap();
+        assert_eq!(msg.ctx.pos(), BARE.len() as u64);
+    }
+
+    #[tokio::test]
+    async fn multicast() {
+        let group = IpAddr::V4(Ipv4Addr::new(239, 255, 42, 42));
+        let localhost = IpAddr::V4(Ipv4Addr::LOCALHOST);
+        let port = std::net::UdpSocket::bind("0.0.0.0:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let pair = match UdpPair::for_multicast(group, (port, 0), Some(localhost), Some(1)) {
+            Ok(p) => p,
+            Err(e) => {
+                // Multicast may be unavailable in the test environment.
+                log::info!("skipping multicast test: {}", e);
+                return;
+            }
+        };
+        let sender =
+            socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None).unwrap();
+        sender.set_multicast_if_v4(&Ipv4Addr::LOCALHOST).unwrap();
+        sender.set_multicast_loop_v4(true).unwrap();
+        sender
+            .send_to(b"rtp", &SocketAddr::new(group, port).into())
+            .unwrap();
+        let mut buf = [0u8; 16];
+        let (len, _) = tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            pair.rtp_socket.recv_from(&mut buf),
+        )
+        .await
+        .unwrap()
+        .unwrap();
+        assert_eq!(&buf[..len], b"rtp");
+    }
+
+    #[tokio::test]
+    async fn udp_recv_buffer_size() {
+        let pair = UdpPair::for_ip(std::net::IpAddr::V4(std::net::Ipv4Addr::LOCALHOST)).unwrap();
+        let default = socket2::SockRef::from(&pair.rtp_socket)
+            .recv_buffer_size()
+            .unwrap();
+        let granted = pair.set_recv_buffer_size(8192).unwrap();
+        assert!(granted >= 8192, "granted={}", granted);
+        assert_ne!(granted, default);
+        assert_eq!(
+            socket2::SockRef::from(&pair.rtcp_socket)
+                .recv_buffer_size()
+                .unwrap(),
+            granted
+        );
+    }
+
+    #[tokio::test]
+    async fn tcp_options() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (client, _server) = tokio::join!(TcpStream::connect(addr), listener.accept());
+        let conn = Connection::from_stream(client.unwrap()).unwrap();
+        let check = |keepalive: bool, nodelay: bool| match conn.0.get_ref() {
+            ConnectionStream::Tcp(s) => {
+                assert_eq!(socket2::SockRef::from(s).keepalive().unwrap(), keepalive);
+                assert_eq!(s.nodelay().unwrap(), nodelay);
+            }
+            _ => unreachable!(),
+        };
+        conn.set_tcp_options(Some(std::time::Duration::from_secs(30)), true)
+            .unwrap();
+        check(true, true);
+        conn.set_tcp_options(None, false).unwrap();
+        check(false, false);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dscp() {
+        let socket = std::net::UdpSocket::bind("127.0.0.1:0").unwrap();
+        set_dscp(socket2::SockRef::from(&socket), false, 46).unwrap();
+        assert_eq!(socket2::SockRef::from(&socket).tos().unwrap(), 46 << 2);
+
+        // IPv6 may be unavailable in the test environment.
+        if let Ok(socket) = std::net::UdpSocket::bind("[::1]:0") {
+            set_dscp(socket2::SockRef::from(&socket), true, 46).unwrap();
+        }
+    }
+
+    /// Tests an `OPTIONS` exchange through a minimal RTSP-over-HTTP tunnel server.
+    #[tokio::test]
+    async fn http_tunnel() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let lookups = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let lookups_clone = lookups.clone();
+        let resolver: crate::client::Resolver = std::sync::Arc::new(move |_, port| {
+            lookups_clone.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            vec![SocketAddr::new(Ipv4Addr::LOCALHOST.into(), port)]
+        });
+        let client = async {
+            let mut conn = Connection::connect_http_tunnel(
+                Host::Domain("camera.example"),
+                port,
+                "/foo?bar",
+                None,
+                Some(&resolver),
+            )
+            .await
+            .unwrap();
+
+            // Both halves of the tunnel use the one lookup.
+            assert_eq!(lookups.load(std::sync::atomic::Ordering::SeqCst), 1);
+            let req = rtsp_types::Request::builder(
+                rtsp_types::Method::Options,
+                rtsp_types::Version::V1_0,
+            )
+            .request_uri(url::Url::parse("rtsp://127.0.0.1/foo?bar").unwrap())
+            .header(rtsp_types::headers::CSEQ, "1")
+            .build(Bytes::new());
+            conn.send(Message::Request(req)).await.unwrap();
+            match conn.next().await.unwrap().unwrap().msg {
+                Message::Response(r) => assert_eq!(r.status(), rtsp_types::StatusCode::Ok),
+                o => panic!("unexpected message: {:#?}", o),
+            }
+        };
+        let server = async {
+            let (mut get, _) = listener.accept().await.unwrap();
+            let (head, _) = read_head(&mut get).await;
+            assert!(head.starts_with("GET /foo?bar HTTP/1.0\r\n"), "{}", head);
+            let host_header = format!("Host: camera.example:{}\r\n", port);
+            assert!(head.contains(&host_header), "{}", head);
+            let cookie = head
+                .lines()
+                .find_map(|l| l.strip_prefix("x-sessioncookie: "))
+                .unwrap()
+                .to_owned();
+            get.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: application/x-rtsp-tunnelled\r\n\r\n")
+                .await
+                .unwrap();
+
+            let (mut post, mut body) = {
+                let (mut post, _) = listener.accept().await.unwrap();
+                let (head, body) = read_head(&mut post).await;
+                assert!(head.starts_with("POST /foo?bar HTTP/1.0\r\n"), "{}", head);
+                assert!(head.contains(&format!("x-sessioncookie: {}\r\n", cookie)));
+                assert!(head.contains(&host_header), "{}", head);
+                (post, body)
+            };
+            let decoded = loop {
+                if let Ok(d) = base64::decode(&body) {
+                    if matches!(
+                        Message::<&[u8]>::parse(&d[..]),
+                        Ok((Message::Request(_), _))
+                    ) {
+                        break d;
+                    }
+                }
+                let mut chunk = [0u8; 1024];
+                let n = post.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "unexpected EOF");
+                body.extend_from_slice(&chunk[..n]);
+            };
+            match Message::<&[u8]>::parse(&decoded[..]).unwrap().0 {
+                Message::Request(r) => assert_eq!(r.method(), rtsp_types::Method::Options),
+                _ => unreachable!(),
+            }
+            get.write_all(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n")
+                .await
+                .unwrap();
+        };
+        tokio::join!(client, server);
+    }
+
+    /// Sends a request too large for one `POST`'s `Content-Length`.
+    #[tokio::test]
+    async fn http_tunnel_renews_post() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let body = Bytes::from(vec![b'x'; 30_001]);
+        let client = async {
+            let mut conn = Connection::connect_http_tunnel(
+                Host::Ipv4(Ipv4Addr::LOCALHOST),
+                port,
+                "/foo",
+                None,
+                None,
+            )
+            .await
+            .unwrap();
+            let req = rtsp_types::Request::builder(
+                rtsp_types::Method::SetParameter,
+                rtsp_types::Version::V1_0,
+            )
+            .request_uri(url::Url::parse("rtsp://127.0.0.1/foo").unwrap())
+            .header(rtsp_types::headers::CSEQ, "1")
+            .header(rtsp_types::headers::CONTENT_LENGTH, body.len().to_string())
+            .build(body.clone());
+            conn.send(Message::Request(req)).await.unwrap();
+            match conn.next().await.unwrap().unwrap().msg {
+                Message::Response(r) => assert_eq!(r.status(), rtsp_types::StatusCode::Ok),
+                o => panic!("unexpected message: {:#?}", o),
+            }
+        };
+        let server = async {
+            let (mut get, _) = listener.accept().await.unwrap();
+            read_head(&mut get).await;
+            get.write_all(b"HTTP/1.0 200 OK\r\nContent-Type: application/x-rtsp-tunnelled\r\n\r\n")
+                .await
+                .unwrap();
+
+            // The first POST carries exactly its budget, then is closed.
+            let (mut post, _) = listener.accept().await.unwrap();
+            let (head, rest) = read_head(&mut post).await;
+            assert!(head.contains("Content-Length: 32767\r\n"), "{}", head);
+            let mut encoded = rest;
+            post.read_to_end(&mut encoded).await.unwrap();
+            assert_eq!(encoded.len(), super::POST_BODY_BUDGET);
+
+            let (mut post, _) = listener.accept().await.unwrap();
+            let (head, rest) = read_head(&mut post).await;
+            assert!(head.starts_with("POST /foo HTTP/1.0\r\n"), "{}", head);
+            encoded.extend_from_slice(&rest);
+            let decoded = loop {
+                if let Ok(d) = base64::decode(&encoded) {
+                    if let Ok((Message::Request(r), _)) = Message::<&[u8]>::parse(&d[..]) {
+                        assert_eq!(*r.body(), &body[..]);
+                        break d;
+                    }
+                }
+                let mut chunk = [0u8; 1024];
+                let n = post.read(&mut chunk).await.unwrap();
+                assert!(n > 0, "unexpected EOF");
+                encoded.extend_from_slice(&chunk[..n]);
+            };
+            let unpadded = encoded.len() - (3 - decoded.len() % 3) % 3;
+            assert!(!encoded[..unpadded].contains(&b'='));
+            get.write_all(b"RTSP/1.0 200 OK\r\nCSeq: 1\r\n\r\n")
+                .await
+                .unwrap();
+        };
+        tokio::join!(client, server);
+    }
 }